@@ -0,0 +1,104 @@
+//! Hints for new users
+//!
+//! Commands give advice, like how to change a default they just used, as
+//! lines starting with `hint: ` on stderr. Each kind of [`Advice`] can be
+//! turned off by setting its `advice.<key>` to false, in the configuration
+//! of the repository or the user's global one, and `--quiet` turns all of
+//! them off.
+//!
+//! New advice is added as a variant of [`Advice`], and given with
+//! [`advise`] where it applies.
+
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+use crate::core::{config, global_config_files};
+use crate::utils::configparser::ConfigParser;
+use crate::utils::trace::{self, Verbosity};
+
+/// The advice commands give.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Advice {
+    /// Given by `init` when the initial branch is named by default, on how
+    /// to name it.
+    DefaultBranchName,
+}
+
+impl Advice {
+    /// The key of the advice in the `[advice]` section of the
+    /// configuration.
+    #[must_use]
+    pub fn key(self) -> &'static str {
+        match self {
+            Self::DefaultBranchName => "defaultBranchName",
+        }
+    }
+}
+
+/// Whether `advice` is given, which it is unless `advice.<key>` is false in
+/// `config`, or if not set there in the user's global configuration, or the
+/// run is `--quiet`.
+#[must_use]
+pub fn enabled(advice: Advice, config: &ConfigParser) -> bool {
+    if trace::verbosity() == Verbosity::Quiet {
+        return false;
+    }
+    config
+        .get("advice")
+        .and_then(|section| section.get_bool(advice.key()))
+        .or_else(|| {
+            global_config_files().iter().rev().find_map(|file| {
+                config::load(file, None)
+                    .ok()?
+                    .get("advice")?
+                    .get_bool(advice.key())
+            })
+        })
+        .unwrap_or(true)
+}
+
+/// Formats `message` as advice, with every line starting with `hint: `,
+/// followed by how to turn the advice off.
+///
+/// # Examples
+///
+/// ```
+/// use mini_git::core::advice::{format, Advice};
+///
+/// let hint = format(Advice::DefaultBranchName, "Name the branch.");
+/// assert_eq!(
+///     hint,
+///     "hint: Name the branch.\n\
+///      hint: Disable this message by setting advice.defaultBranchName to \
+///      false\n"
+/// );
+/// ```
+#[must_use]
+pub fn format(advice: Advice, message: &str) -> String {
+    let mut hint = String::new();
+    for line in message.lines() {
+        if line.is_empty() {
+            hint.push_str("hint:\n");
+        } else {
+            let _ = writeln!(hint, "hint: {line}");
+        }
+    }
+    let _ = writeln!(
+        hint,
+        "hint: Disable this message by setting advice.{} to false",
+        advice.key()
+    );
+    hint
+}
+
+/// Gives `advice` with `message` on stderr, unless it is turned off in
+/// `config`, see [`enabled`].
+pub fn advise(advice: Advice, config: &ConfigParser, message: &str) {
+    if enabled(advice, config) {
+        // Advice is not worth failing an operation over
+        let _ = io::stderr()
+            .lock()
+            .write_all(format(advice, message).as_bytes());
+    }
+}
@@ -1,5 +1,6 @@
 use crate::core::objects::{find_object, read_object};
 use crate::core::repository::{resolve_repository_context, RepositoryContext};
+use crate::core::Error;
 use crate::utils::argparse::{ArgumentParser, ArgumentType, Namespace};
 
 /// Provide content of repository objects
@@ -12,9 +13,9 @@ use crate::utils::argparse::{ArgumentParser, ArgumentType, Namespace};
 /// # Errors
 ///
 /// If file system operations fail, or if input paths are not valid.
-/// A [`String`] message describing the error is returned.
+/// An [`Error`] describing the failure is returned.
 #[allow(clippy::module_name_repetitions)]
-pub fn cat_file(args: &Namespace) -> Result<String, String> {
+pub fn cat_file(args: &Namespace) -> Result<String, Error> {
     let RepositoryContext { repo, .. } = resolve_repository_context()?;
 
     let obj_type = &args["type"];
@@ -23,7 +24,7 @@ pub fn cat_file(args: &Namespace) -> Result<String, String> {
     let object = find_object(&repo, name, Some(obj_type), true)?;
     let object = read_object(&repo, &object)?;
     let Ok(s) = String::from_utf8(object.serialize()) else {
-        return Err("Failed to serialize object!".to_owned());
+        return Err("Failed to serialize object!".into());
     };
     Ok(s)
 }
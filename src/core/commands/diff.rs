@@ -1,23 +1,35 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::Write as _;
+use std::hash::Hash;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
-use std::thread;
 
+use crate::core::commands::log::commit_tree;
 use crate::core::commands::resolve_cla_files;
-use crate::core::objects::{self, get_files, FileSource};
-use crate::core::objects::{blob, tree};
+use crate::core::index::GitIndex;
+use crate::core::objects::tree::{self, Delta, TreeEntry};
+use crate::core::objects::{self, get_files, FileSource, GitObject};
+use crate::core::objects::{blob, oid::Oid, worktree};
 use crate::core::{
-    resolve_repository_context, GitRepository, RepositoryContext,
+    resolve_repository_context, Error, GitRepository, ObjectFormat,
+    RepositoryContext,
 };
 use crate::utils::argparse::{ArgumentParser, ArgumentType, Namespace};
+use crate::utils::color::{ColorMode, Palette};
+use crate::utils::gitattributes::AttrValue;
+use crate::utils::json::Json;
+use crate::utils::threadpool::ThreadPool;
+use crate::utils::trace;
 
-const RESET: &str = "\x1b[0m";
-const RED: &str = "\x1b[31m";
-const GREEN: &str = "\x1b[32m";
-const CYAN: &str = "\x1b[36m";
 const STAT_WIDTH: usize = 80;
-const MAX_THREADS: usize = 8;
+/// The share of all changes, in permille, a directory needs to be listed by
+/// `--dirstat`
+const DIRSTAT_THRESHOLD: usize = 30;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy)]
 #[cfg_attr(test, derive(PartialEq))]
 enum Change {
     Same,
@@ -26,42 +38,286 @@ enum Change {
     Replace,
 }
 
-// Data structures for diff computation
-#[derive(Debug)]
-struct Hunk {
-    old_start: usize,
-    old_count: usize,
-    new_start: usize,
-    new_count: usize,
-    content: String,
+/// The differences in the contents of a file, as data.
+///
+/// Diffs are made with [`diff_blobs`], or for whole trees with
+/// [`GitRepository::diff_tree_to_tree`], and rendered as a patch with
+/// [`format_file_diff`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileDiff {
+    /// How the file changed
+    pub status: Delta,
+    /// The path of the file on the old side, if it is there
+    pub old_path: Option<String>,
+    /// The path of the file on the new side, if it is there
+    pub new_path: Option<String>,
+    /// Whether either side is binary, in which case there are no hunks
+    pub binary: bool,
+    /// The changed regions of the file, in order
+    pub hunks: Vec<Hunk>,
 }
 
-#[allow(clippy::struct_excessive_bools)]
-struct DiffOpts {
-    files: Vec<String>,
-    name_only: bool,
-    name_status: bool,
-    stat: bool,
-    diff_filter: Option<String>,
-    hunk_context_lines: usize,
-    src_prefix: String,
-    dst_prefix: String,
+/// A changed region of a file, with the unchanged lines around it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    /// The first line of the region in the old file, from 1, or 0 if the
+    /// region is empty there
+    pub old_start: usize,
+    /// The number of lines of the region in the old file
+    pub old_count: usize,
+    /// The first line of the region in the new file, from 1, or 0 if the
+    /// region is empty there
+    pub new_start: usize,
+    /// The number of lines of the region in the new file
+    pub new_count: usize,
+    /// The lines of the region, where removed lines come before the lines
+    /// that replace them
+    pub lines: Vec<Line>,
+}
+
+/// A line of a [`Hunk`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    /// Which side the line is on
+    pub origin: LineOrigin,
+    /// The text of the line, without its line ending
+    pub content: String,
+}
+
+/// Which side of a diff a [`Line`] is on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineOrigin {
+    /// The line is on both sides
+    Context,
+    /// The line is only in the old file
+    Deletion,
+    /// The line is only in the new file
+    Addition,
+}
+
+impl Line {
+    fn new(origin: LineOrigin, content: &str) -> Self {
+        Self {
+            origin,
+            content: content.to_owned(),
+        }
+    }
+
+    /// The character a patch starts the line with.
+    #[must_use]
+    pub fn prefix(&self) -> char {
+        match self.origin {
+            LineOrigin::Context => ' ',
+            LineOrigin::Deletion => '-',
+            LineOrigin::Addition => '+',
+        }
+    }
+}
+
+/// How a patch is rendered.
+#[derive(Clone, Copy)]
+struct PatchStyle<'a> {
+    src_prefix: &'a str,
+    dst_prefix: &'a str,
     no_prefix: bool,
+    word_diff: Option<WordDiff>,
+    palette: Palette,
+}
+
+impl<'a> PatchStyle<'a> {
+    fn new(opts: &'a DiffOpts) -> Self {
+        Self {
+            src_prefix: &opts.src_prefix,
+            dst_prefix: &opts.dst_prefix,
+            no_prefix: opts.no_prefix,
+            word_diff: opts.word_diff,
+            palette: opts.palette,
+        }
+    }
+}
+
+#[allow(clippy::struct_excessive_bools)]
+pub(super) struct DiffOpts {
+    pub(super) files: Vec<String>,
+    pub(super) name_only: bool,
+    pub(super) name_status: bool,
+    /// Whether names and statuses are printed as JSON
+    pub(super) json: bool,
+    pub(super) stat: bool,
+    pub(super) numstat: bool,
+    pub(super) shortstat: bool,
+    pub(super) dirstat: bool,
+    pub(super) check: bool,
+    pub(super) diff_filter: Option<String>,
+    pub(super) hunk_context_lines: usize,
+    pub(super) src_prefix: String,
+    pub(super) dst_prefix: String,
+    pub(super) no_prefix: bool,
+    pub(super) word_diff: Option<WordDiff>,
+    pub(super) algorithm: DiffAlgorithm,
+    /// The diff drivers of files with a `diff` attribute, by path
+    pub(super) drivers: HashMap<String, DiffDriver>,
+    /// The hash algorithm the files are named with for external diffs
+    pub(super) object_format: ObjectFormat,
+    pub(super) palette: Palette,
+    /// The workers that files are diffed on
+    pub(super) pool: ThreadPool,
+}
+
+/// The algorithm used to match up lines between two files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum DiffAlgorithm {
+    /// A shortest edit script, found with Myers' O(ND) algorithm
+    Myers,
+    /// Match up lines that are unique on both sides first
+    Patience,
+    /// Match up the least frequent lines first
+    Histogram,
+}
+
+/// How changed words are marked when diffing word by word.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum WordDiff {
+    /// Wrap removed words in `[-...-]` and added words in `{+...+}`
+    Plain,
+    /// Show removed words in red and added words in green
+    Color,
+}
+
+/// How a file is diffed, according to its `diff` attribute.
+#[derive(Debug, Clone, Default)]
+pub(super) struct DiffDriver {
+    /// Set by `-diff` or `binary`, to show the file as binary
+    binary: bool,
+    /// A command that converts the contents to text before diffing
+    textconv: Option<String>,
+    /// A command that produces the whole patch for the file
+    command: Option<String>,
+}
+
+/// The commits a changed submodule is at, on either side of a diff. A side
+/// is `None` if the path is not a submodule there.
+#[derive(Debug, Clone, Copy)]
+struct SubmoduleChange<'a> {
+    old: Option<&'a str>,
+    new: Option<&'a str>,
+}
+
+/// The output for a single changed file.
+struct FileOutput {
+    path: String,
+    text: String,
+    /// Added and deleted lines, if requested and the file is not binary
+    line_counts: Option<(usize, usize)>,
+    /// How much of the file changed, counted for `--dirstat`
+    damage: usize,
+}
+
+/// A file whose contents differ between two trees.
+pub(super) struct FileChange {
+    pub(super) old: Option<Vec<u8>>,
+    pub(super) new: Option<Vec<u8>>,
+}
+
+impl Default for DiffOpts {
+    fn default() -> Self {
+        Self {
+            files: vec![],
+            name_only: false,
+            name_status: false,
+            json: false,
+            stat: false,
+            numstat: false,
+            shortstat: false,
+            dirstat: false,
+            check: false,
+            diff_filter: None,
+            hunk_context_lines: 3,
+            src_prefix: "a/".to_owned(),
+            dst_prefix: "b/".to_owned(),
+            no_prefix: false,
+            word_diff: None,
+            algorithm: DiffAlgorithm::Myers,
+            drivers: HashMap::new(),
+            object_format: ObjectFormat::Sha1,
+            palette: Palette::COLOR,
+            pool: ThreadPool::default(),
+        }
+    }
 }
 
 /// List differences
 /// This handles the subcommand
 ///
 /// ```bash
-/// mini_git diff [options] [ --tree1 TREE1 ] [ --tree2 TREE2 ] [ --files FILE1,FILE2,... ]
+/// mini_git diff [options] [--cached] [ --tree1 TREE1 ] [ --tree2 TREE2 ] [ --files FILE... ]
+/// mini_git diff [options] --cc [ COMMIT ]
 /// ```
 ///
+/// Without any trees, the working tree is compared against the index, or
+/// against `HEAD` if there is no index. With `--cached`, the index is compared
+/// against `TREE1`, or `HEAD` if not given. With one tree, the working tree is
+/// compared against it, and with two trees, they are compared to each other.
+/// With `--cc`, a merge commit (`HEAD` by default) is compared against all of
+/// its parents at once, as a combined diff.
+///
+/// Files whose `diff` attribute is unset (`-diff`) are shown as binary. With
+/// `diff=<driver>`, the `textconv` command of the `[diff "<driver>"]` config
+/// section converts the contents to text before diffing, and its `command`
+/// produces the whole patch instead.
+///
+/// Files are diffed on `--jobs` threads, or `diff.jobs` if not given, and one
+/// thread per CPU if neither is set.
+///
 /// # Errors
 ///
 /// If file system operations fail, or if input paths are not valid.
-/// A [`String`] message describing the error is returned.
+/// An [`Error`] describing the failure is returned.
 #[allow(clippy::module_name_repetitions)]
-pub fn diff(args: &Namespace) -> Result<String, String> {
+pub fn diff(args: &Namespace) -> Result<String, Error> {
+    diff_with_status(args).map(|(output, _)| output)
+}
+
+/// List differences, along with the exit status of the command.
+///
+/// With `--exit-code`, the exit status is 1 if there are differences, and 0
+/// otherwise. `--quiet` implies `--exit-code`, and suppresses all output.
+/// With `--check`, the exit status is 2 if any whitespace errors are found.
+/// Otherwise, the exit status is always 0.
+///
+/// # Errors
+///
+/// Same as [`diff`].
+pub fn diff_with_status(args: &Namespace) -> Result<(String, i32), Error> {
+    let mut output = vec![];
+    let status = run_diff(args, &mut output, false)?;
+    Ok((String::from_utf8_lossy(&output).into_owned(), status))
+}
+
+/// List differences, writing the output for each file to `out` as soon as
+/// it is ready, and return the exit status of the command.
+///
+/// Files are still written in order of their paths, and the output ends
+/// with a newline, as it is printed. See [`diff_with_status`] for the exit
+/// status.
+///
+/// # Errors
+///
+/// Same as [`diff`]. Output is silently dropped once `out` is closed by its
+/// reader, such as when piping into `head`.
+pub fn write_diff(args: &Namespace, out: &mut dyn Write) -> Result<i32, Error> {
+    run_diff(args, out, true)
+}
+
+#[allow(clippy::too_many_lines)]
+fn run_diff(
+    args: &Namespace,
+    out: &mut dyn Write,
+    terminate: bool,
+) -> Result<i32, Error> {
+    let quiet = args.get("quiet").is_some();
+    let exit_code = quiet || args.get("exit-code").is_some();
+
     let RepositoryContext {
         repo,
         cwd,
@@ -69,64 +325,732 @@ pub fn diff(args: &Namespace) -> Result<String, String> {
     } = resolve_repository_context()?;
 
     // Parse arguments
-    let name_only = args.get("name-only").is_some();
+    // Only whether anything changed matters when quiet, so keep it cheap
+    let name_only = quiet || args.get("name-only").is_some();
     let name_status = args.get("name-status").is_some();
+    let json = args.get("json").is_some();
+    if json && !name_status {
+        return Err(Error::Usage(
+            "--json is only supported with --name-status".to_owned(),
+        ));
+    }
     let stat = args.get("stat").is_some();
+    let numstat = args.get("numstat").is_some();
+    let shortstat = args.get("shortstat").is_some();
+    let dirstat = args.get("dirstat").is_some();
+    let check = args.get("check").is_some();
     let diff_filter = args.get("diff-filter").map(String::as_str);
     let hunk_context_lines = &args["n-context-lines"];
     let src_prefix = &args["src-prefix"];
     let dst_prefix = &args["dst-prefix"];
     let no_prefix = args.get("no-prefix").is_some();
+    let word_diff = match args.get("word-diff").map(String::as_str) {
+        Some("plain") => Some(WordDiff::Plain),
+        Some("color") => Some(WordDiff::Color),
+        _ => None,
+    };
+    // Like git, --word-diff color implies --color always
+    let color = match word_diff {
+        Some(WordDiff::Color) => ColorMode::Always,
+        _ => match args.get("color") {
+            Some(color) => color.parse()?,
+            None => ColorMode::from_config(repo.config(), "diff")?,
+        },
+    };
+    let algorithm = match args.get("diff-algorithm").map(String::as_str) {
+        Some("patience") => DiffAlgorithm::Patience,
+        Some("histogram") => DiffAlgorithm::Histogram,
+        _ => DiffAlgorithm::Myers,
+    };
 
     let Ok(hunk_context_lines) = hunk_context_lines.parse::<usize>() else {
         unreachable!()
     };
+    let jobs = match args.get("jobs") {
+        Some(jobs) => jobs.parse::<usize>().map_err(|_| {
+            Error::Usage(format!("Invalid number of jobs: {jobs}"))
+        })?,
+        None => config_jobs(&repo)?,
+    };
 
-    // Resolve the file paths to be relative to the repository root
-    let all_files = repo_path.to_str().map_or_else(
-        || Err("Failed to determined files to diff".to_owned()),
-        |x| Ok(String::from(x)),
-    );
-    let files = args.get("files").map_or(all_files.as_ref(), Ok)?;
-    let resolved_files: Vec<String> = resolve_cla_files(&repo, &cwd, files)?;
+    // Resolve the file paths to be relative to the repository root. No files
+    // means every file on either side is compared
+    let resolved_files = args
+        .get_all("files")
+        .map(|files| resolve_cla_files(&repo, &cwd, files))
+        .transpose()?
+        .unwrap_or_default();
 
     let opts = DiffOpts {
         files: resolved_files,
         name_only,
         name_status,
+        json,
         stat,
+        numstat,
+        shortstat,
+        dirstat,
+        check,
         diff_filter: diff_filter.map(String::from),
         hunk_context_lines,
         src_prefix: src_prefix.to_owned(),
         dst_prefix: dst_prefix.to_owned(),
         no_prefix,
+        word_diff,
+        algorithm,
+        drivers: HashMap::new(),
+        object_format: ObjectFormat::Sha1,
+        palette: Palette::from_config(color, repo.config())?,
+        pool: ThreadPool::new(jobs),
     };
 
     // Parse tree1 and tree2
     let tree1 = args.get("tree1").filter(|s| *s != "*").map(String::as_str);
     let tree2 = args.get("tree2").filter(|s| *s != "*").map(String::as_str);
+    let cached = args.get("cached").is_some();
+    if cached && tree2.is_some() {
+        return Err(Error::Usage(
+            "--cached compares the index with at most one tree".to_owned(),
+        ));
+    }
+    let cc = args.get("cc").is_some();
+    if cc && (cached || tree2.is_some()) {
+        return Err(Error::Usage(
+            "--cc compares a single commit with its parents".to_owned(),
+        ));
+    }
+    // Only comparisons of trees and the index work without a working tree
+    if !cc && !cached && tree2.is_none() {
+        repo.require_worktree()?;
+    }
 
     // Finally, switch to the repo root dir to use the resolved paths correctly
     std::env::set_current_dir(&repo_path).map_err(|_| {
         "Could not switch to repository root directory".to_owned()
     })?;
 
-    _diff(repo, tree1, tree2, opts)
+    let mut quiet_out = io::sink();
+    let mut sink =
+        DiffSink::new(if quiet { &mut quiet_out } else { out }).with_json(json);
+    if cc {
+        diff_merge(&repo, tree1.unwrap_or("HEAD"), &opts, &mut sink)
+    } else if cached {
+        diff_index(&repo, tree1, opts, &mut sink)
+    } else if tree1.is_none() && GitIndex::exists(&repo) {
+        diff_worktree(&repo, opts, &mut sink)
+    } else {
+        diff_revisions(&repo, tree1, tree2, opts, &mut sink)
+    }?;
+    let changed = sink.finish(terminate)?;
+
+    let status = if check && changed {
+        2
+    } else {
+        i32::from(exit_code && changed)
+    };
+    Ok(status)
+}
+
+/// Writes the output of each file to a [`Write`] sink as it is produced,
+/// separating files with newlines, or as the elements of a JSON array.
+#[allow(clippy::struct_excessive_bools)]
+struct DiffSink<'a> {
+    out: &'a mut dyn Write,
+    /// Whether the output of each file is a JSON value
+    json: bool,
+    /// Whether anything has been written
+    written: bool,
+    /// Whether the output so far ends with a newline
+    ends_with_newline: bool,
+    /// Set once the reader has gone away, after which output is dropped
+    closed: bool,
+}
+
+impl<'a> DiffSink<'a> {
+    fn new(out: &'a mut dyn Write) -> Self {
+        Self {
+            out,
+            json: false,
+            written: false,
+            ends_with_newline: false,
+            closed: false,
+        }
+    }
+
+    fn with_json(mut self, json: bool) -> Self {
+        self.json = json;
+        self
+    }
+
+    /// Writes the output of a file. Empty output is skipped.
+    fn push(&mut self, text: &str) -> Result<(), Error> {
+        if text.is_empty() {
+            return Ok(());
+        }
+        match (self.written, self.json) {
+            (false, true) => self.write(b"[")?,
+            (true, true) => self.write(b",")?,
+            (true, false) => self.write(b"\n")?,
+            (false, false) => {}
+        }
+        self.write(text.as_bytes())?;
+        self.written = true;
+        self.ends_with_newline = text.ends_with('\n');
+        Ok(())
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        if self.closed {
+            return Ok(());
+        }
+        match self.out.write_all(bytes) {
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {
+                self.closed = true;
+                Ok(())
+            }
+            res => res.map_err(|e| Error::io("Failed to write output", e)),
+        }
+    }
+
+    /// Flushes the output, ending it with a newline if `terminate` is set,
+    /// and returns whether anything was written.
+    fn finish(mut self, terminate: bool) -> Result<bool, Error> {
+        if self.json {
+            self.write(if self.written { b"]" } else { b"[]" })?;
+            self.ends_with_newline = false;
+        }
+        let ended = self.written || self.json;
+        if terminate && ended && !self.ends_with_newline {
+            self.write(b"\n")?;
+        }
+        match self.out.flush() {
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => {
+                return Err(format!("Failed to write output: {e}").into());
+            }
+            _ => {}
+        }
+        Ok(self.written)
+    }
+}
+
+/// Runs `write` against an in-memory sink, and returns what it wrote.
+fn collect_output(
+    write: impl FnOnce(&mut DiffSink) -> Result<(), Error>,
+) -> Result<String, Error> {
+    let mut output = vec![];
+    let mut sink = DiffSink::new(&mut output);
+    write(&mut sink)?;
+    sink.finish(false)?;
+    Ok(String::from_utf8_lossy(&output).into_owned())
 }
 
 // Main function simplified to orchestrate the workflow
-fn _diff(
-    repo: GitRepository,
+fn diff_revisions(
+    repo: &GitRepository,
     tree1: Option<&str>,
     tree2: Option<&str>,
     opts: DiffOpts,
-) -> Result<String, String> {
-    let (tree1, tree2) = resolve_trees(&repo, tree1, tree2)?;
+    sink: &mut DiffSink,
+) -> Result<(), Error> {
+    let (tree1, tree2) = resolve_trees(repo, tree1, tree2)?;
     let (files1, files2) =
-        get_file_contents(&repo, tree1.as_deref(), tree2.as_deref())?;
+        get_file_contents(repo, tree1.as_deref(), tree2.as_deref())?;
+    let all_files = collect_files_to_process(&files1, &files2, &opts.files);
+
+    process_files_in_parallel(repo, files1, files2, all_files, opts, sink)
+}
+
+/// Compares a commit against its parents. A merge commit is shown as a
+/// combined diff, while other commits are compared against their parent.
+fn diff_merge(
+    repo: &GitRepository,
+    commit: &str,
+    opts: &DiffOpts,
+    sink: &mut DiffSink,
+) -> Result<(), Error> {
+    let read_commit = |oid: &Oid| match objects::read_object(repo, oid)? {
+        GitObject::Commit(commit) => Ok(commit),
+        _ => Err(format!("{oid} is not a commit")),
+    };
+    let commit = read_commit(&objects::find_object(
+        repo,
+        commit,
+        Some("commit"),
+        true,
+    )?)?;
+    let tree = commit_tree(&commit)?;
+    let parents = commit
+        .parents()?
+        .iter()
+        .map(|parent| commit_tree(&read_commit(parent)?))
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    if parents.len() > 1 {
+        write_combined_diff(repo, tree.as_deref(), &parents, opts, sink)
+    } else {
+        let parent = parents.into_iter().flatten().next();
+        write_tree_diff(repo, parent.as_deref(), tree.as_deref(), opts, sink)
+    }
+}
+
+/// Compares the index against `tree`, or `HEAD` if not given, like
+/// `git diff-index --cached`. Before the first commit, there is no `HEAD`,
+/// so everything in the index is shown as added.
+fn diff_index(
+    repo: &GitRepository,
+    tree: Option<&str>,
+    opts: DiffOpts,
+    sink: &mut DiffSink,
+) -> Result<(), Error> {
+    let tree = match tree {
+        Some(tree) => Some(
+            objects::find_object(repo, tree, Some("tree"), true)?.to_string(),
+        ),
+        None => tree::Tree::get_head_tree_sha(repo).ok(),
+    };
+    let files1 = tree
+        .map_or_else(|| Ok(vec![]), |tree| tree::get_tree_files(repo, &tree))?;
+    let files2 = GitIndex::read(repo)?.files();
+    let all_files = collect_files_to_process(&files1, &files2, &opts.files);
+
+    process_files_in_parallel(repo, files1, files2, all_files, opts, sink)
+}
+
+/// Compares the working tree against the index, like `git diff-files`.
+/// Untracked files are not shown.
+fn diff_worktree(
+    repo: &GitRepository,
+    opts: DiffOpts,
+    sink: &mut DiffSink,
+) -> Result<(), Error> {
+    // Like git, the index is refreshed with the file system monitor, and
+    // the files it knows to be unchanged are not compared
+    let mut index = GitIndex::read(repo)?;
+    index.refresh_fsmonitor(repo)?;
+    if index.fsmonitor().is_some() {
+        index.write(repo)?;
+    }
+    let known_unchanged = |path: &String| {
+        index
+            .fsmonitor()
+            .is_some_and(|monitor| monitor.is_valid(path))
+    };
+
+    let files1 = index.files();
+    let tracked = files1.iter().map(FileSource::path).collect::<HashSet<_>>();
+    let mut files2 = worktree::get_worktree_files(repo, None)?
+        .into_iter()
+        .filter(|file| tracked.contains(&file.path()))
+        .collect::<Vec<_>>();
+
+    // Like git, a submodule that is not checked out is left as it is
+    let present = files2.iter().map(FileSource::path).collect::<HashSet<_>>();
+    let unchanged = files1.iter().filter_map(|file| match file {
+        FileSource::Gitlink { path, sha }
+            if !present.contains(path)
+                && repo.worktree().join(path).is_dir() =>
+        {
+            Some(FileSource::Gitlink {
+                path: path.clone(),
+                sha: sha.clone(),
+            })
+        }
+        _ => None,
+    });
+    files2.extend(unchanged.collect::<Vec<_>>());
+    let all_files = collect_files_to_process(&files1, &files2, &opts.files)
+        .into_iter()
+        .filter(|file| tracked.contains(file) && !known_unchanged(file))
+        .collect::<Vec<_>>();
+
+    process_files_in_parallel(repo, files1, files2, all_files, opts, sink)
+}
+
+/// Computes the differences between two trees, formatted according to `opts`.
+///
+/// This is the entry point for other commands that need to render diffs
+/// between objects in the repository, such as `log -p`. A `None` tree is
+/// treated as the empty tree.
+///
+/// # Errors
+/// Returns an error if either tree, or any blob within them, cannot be read.
+pub(super) fn diff_trees(
+    repo: &GitRepository,
+    old_tree: Option<&str>,
+    new_tree: Option<&str>,
+    opts: &DiffOpts,
+) -> Result<String, Error> {
+    collect_output(|sink| write_tree_diff(repo, old_tree, new_tree, opts, sink))
+}
+
+/// Writes the differences between two trees to `sink`, one file at a time.
+fn write_tree_diff(
+    repo: &GitRepository,
+    old_tree: Option<&str>,
+    new_tree: Option<&str>,
+    opts: &DiffOpts,
+    sink: &mut DiffSink,
+) -> Result<(), Error> {
+    // Only the files that changed can have differences to show
+    let old_tree = old_tree.map(str::parse).transpose()?;
+    let new_tree = new_tree.map(str::parse).transpose()?;
+    let (mut files1, mut files2) = (vec![], vec![]);
+    for delta in tree::diff_trees(repo, old_tree.as_ref(), new_tree.as_ref())? {
+        files1.extend(delta.old.map(FileSource::from));
+        files2.extend(delta.new.map(FileSource::from));
+    }
     let all_files = collect_files_to_process(&files1, &files2, &opts.files);
 
-    process_files_in_parallel(repo, files1, files2, &all_files, opts)
+    let (files1, files2) = (index_by_path(files1), index_by_path(files2));
+    let outputs = all_files
+        .iter()
+        .map(|file| process_single_file(repo, file, &files1, &files2, opts));
+    write_outputs(outputs, opts, sink)
+}
+
+/// Computes the combined diff of a merge result against all of its parents,
+/// like `git diff --cc`.
+///
+/// Only files that differ from every parent are shown. Each line is prefixed
+/// with one column per parent, marking whether it was added (`+`) or removed
+/// (`-`) relative to that parent. Hunks where the result matches one of the
+/// parents are left out. A `None` tree is treated as the empty tree.
+///
+/// # Errors
+/// Returns an error if any tree, or any blob within them, cannot be read.
+pub(super) fn combined_diff(
+    repo: &GitRepository,
+    tree: Option<&str>,
+    parents: &[Option<String>],
+    opts: &DiffOpts,
+) -> Result<String, Error> {
+    collect_output(|sink| write_combined_diff(repo, tree, parents, opts, sink))
+}
+
+/// Writes the combined diff of a merge result to `sink`, one file at a time.
+fn write_combined_diff(
+    repo: &GitRepository,
+    tree: Option<&str>,
+    parents: &[Option<String>],
+    opts: &DiffOpts,
+    sink: &mut DiffSink,
+) -> Result<(), Error> {
+    let read_tree = |tree: Option<&str>| -> Result<HashMap<_, _>, Error> {
+        let files =
+            tree.map_or_else(|| Ok(vec![]), |t| tree::get_tree_files(repo, t))?;
+        Ok(files.into_iter().map(|file| (file.path(), file)).collect())
+    };
+    let files = read_tree(tree)?;
+    let parent_files = parents
+        .iter()
+        .map(|parent| read_tree(parent.as_deref()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut paths = files
+        .keys()
+        .chain(parent_files.iter().flat_map(HashMap::keys))
+        .collect::<Vec<_>>();
+    paths.sort_unstable();
+    paths.dedup();
+
+    for path in paths {
+        let contents = |files: &HashMap<String, FileSource>| {
+            files
+                .get(path)
+                .map(|file| file.contents(repo))
+                .transpose()
+                .map(Option::unwrap_or_default)
+        };
+        let content = contents(&files)?;
+        let parent_contents = parent_files
+            .iter()
+            .map(contents)
+            .collect::<Result<Vec<_>, _>>()?;
+        // Files taken as is from any parent are not interesting
+        if parent_contents.contains(&content) {
+            continue;
+        }
+        sink.push(&format_combined_diff(
+            path,
+            &content,
+            &parent_contents,
+            opts,
+        ))?;
+    }
+    Ok(())
+}
+
+/// A line of a combined diff.
+struct CombinedLine<'a> {
+    text: &'a str,
+    /// One column per parent, holding `+`, `-` or a space
+    marks: Vec<u8>,
+    /// Whether the line is in the merge result
+    in_result: bool,
+}
+
+impl CombinedLine<'_> {
+    fn is_change(&self) -> bool {
+        self.marks.iter().any(|&mark| mark != b' ')
+    }
+
+    /// Whether the line is in the given parent.
+    fn in_parent(&self, parent: usize) -> bool {
+        if self.in_result {
+            self.marks[parent] != b'+'
+        } else {
+            self.marks[parent] == b'-'
+        }
+    }
+}
+
+fn format_combined_diff(
+    path: &str,
+    content: &[u8],
+    parents: &[Vec<u8>],
+    opts: &DiffOpts,
+) -> String {
+    let (src_path, dst_path) = if opts.no_prefix {
+        (path.to_string(), path.to_string())
+    } else {
+        (
+            format!("{}{path}", opts.src_prefix),
+            format!("{}{path}", opts.dst_prefix),
+        )
+    };
+
+    let Palette {
+        reset,
+        meta,
+        frag,
+        old,
+        new,
+        ..
+    } = opts.palette;
+    let mut output = String::new();
+    let _ = writeln!(output, "{meta}diff --cc {path}{reset}");
+    if blob::Blob::is_binary(content)
+        || parents.iter().any(|parent| blob::Blob::is_binary(parent))
+    {
+        output.push_str("Binary files differ");
+        return output;
+    }
+    output.push_str("index ....\n"); // Simplified index line
+    let _ = writeln!(output, "--- {src_path}");
+    let _ = write!(output, "+++ {dst_path}");
+
+    let result_str = String::from_utf8_lossy(content);
+    let parent_strs = parents
+        .iter()
+        .map(|parent| String::from_utf8_lossy(parent))
+        .collect::<Vec<_>>();
+    let result_lines: Vec<&str> = result_str.lines().collect();
+    let parent_lines = parent_strs
+        .iter()
+        .map(|parent| parent.lines().collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let lines = combine_lines(&result_lines, &parent_lines, opts.algorithm);
+
+    let markers = "@".repeat(parents.len() + 1);
+    for (start, end) in combined_hunks(&lines, opts.hunk_context_lines) {
+        let hunk = &lines[start..end];
+        // The result matches one of the parents here, so it is not
+        // interesting
+        if (0..parents.len()).any(|p| hunk.iter().all(|l| l.marks[p] == b' ')) {
+            continue;
+        }
+
+        let range = |in_side: &dyn Fn(&CombinedLine) -> bool| {
+            let before = lines[..start].iter().filter(|l| in_side(l)).count();
+            let count = hunk.iter().filter(|l| in_side(l)).count();
+            let start = if count == 0 { before } else { before + 1 };
+            format!("{start},{count}")
+        };
+        let _ = write!(output, "\n{frag}{markers}");
+        for parent in 0..parents.len() {
+            let _ = write!(output, " -{}", range(&|l| l.in_parent(parent)));
+        }
+        let _ =
+            write!(output, " +{} {markers}{reset}", range(&|l| l.in_result));
+
+        for line in hunk {
+            let marks = String::from_utf8_lossy(&line.marks);
+            let color = if line.marks.contains(&b'+') {
+                new
+            } else if line.marks.contains(&b'-') {
+                old
+            } else {
+                ""
+            };
+            let reset = if color.is_empty() { "" } else { reset };
+            let _ = write!(output, "\n{color}{marks}{}{reset}", line.text);
+        }
+    }
+    output
+}
+
+/// Lines the merge result and the lines lost from each parent, in order.
+fn combine_lines<'a>(
+    result: &[&'a str],
+    parents: &[Vec<&'a str>],
+    algorithm: DiffAlgorithm,
+) -> Vec<CombinedLine<'a>> {
+    // Which parents each result line was added relative to, and the lines
+    // lost from the parents just before it. The last entry only holds lines
+    // lost at the end.
+    let mut added = vec![vec![b' '; parents.len()]; result.len() + 1];
+    let mut lost: Vec<Vec<CombinedLine>> =
+        (0..=result.len()).map(|_| vec![]).collect();
+
+    for (p, parent) in parents.iter().enumerate() {
+        let (mut i, mut j) = (0, 0);
+        // Lost lines are shown before the whole run of changes, and matched
+        // up with identical lines lost from earlier parents
+        let (mut run_start, mut search_from) = (None, 0);
+        for change in compute_diff_with(parent, result, algorithm) {
+            if matches!(change, Change::Same) {
+                (i, j) = (i + 1, j + 1);
+                run_start = None;
+                continue;
+            }
+            let pos = *run_start.get_or_insert_with(|| {
+                search_from = 0;
+                j
+            });
+            if matches!(change, Change::Delete | Change::Replace) {
+                let lost = &mut lost[pos];
+                if let Some(k) = lost[search_from..].iter().position(|line| {
+                    line.text == parent[i] && line.marks[p] == b' '
+                }) {
+                    lost[search_from + k].marks[p] = b'-';
+                    search_from += k + 1;
+                } else {
+                    let mut marks = vec![b' '; parents.len()];
+                    marks[p] = b'-';
+                    lost.push(CombinedLine {
+                        text: parent[i],
+                        marks,
+                        in_result: false,
+                    });
+                    search_from = lost.len();
+                }
+                i += 1;
+            }
+            if matches!(change, Change::Insert | Change::Replace) {
+                added[j][p] = b'+';
+                j += 1;
+            }
+        }
+    }
+
+    let mut lines = vec![];
+    for (j, (lost, marks)) in lost.into_iter().zip(added).enumerate() {
+        lines.extend(lost);
+        if let Some(text) = result.get(j) {
+            lines.push(CombinedLine {
+                text,
+                marks,
+                in_result: true,
+            });
+        }
+    }
+    lines
+}
+
+/// Groups the changed lines into hunks with `context` lines around them,
+/// merging hunks whose context would overlap.
+fn combined_hunks(
+    lines: &[CombinedLine],
+    context: usize,
+) -> Vec<(usize, usize)> {
+    let mut hunks: Vec<(usize, usize)> = vec![];
+    for (i, _) in lines.iter().enumerate().filter(|(_, l)| l.is_change()) {
+        let (start, end) = (
+            i.saturating_sub(context),
+            (i + context + 1).min(lines.len()),
+        );
+        match hunks.last_mut() {
+            Some(last) if last.1 >= start => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+    hunks
+}
+
+/// Lists the files that differ between two trees, along with their contents
+/// on either side. A `None` tree is treated as the empty tree.
+///
+/// # Errors
+/// Returns an error if either tree, or any changed blob, cannot be read.
+pub(super) fn tree_changes(
+    repo: &GitRepository,
+    old_tree: Option<&str>,
+    new_tree: Option<&str>,
+) -> Result<Vec<FileChange>, Error> {
+    let old_tree = old_tree.map(str::parse).transpose()?;
+    let new_tree = new_tree.map(str::parse).transpose()?;
+    let deltas = tree::diff_trees(repo, old_tree.as_ref(), new_tree.as_ref())?;
+
+    let read_blob = |entry: Option<TreeEntry>| {
+        entry
+            .map(|entry| {
+                FileSource::Blob {
+                    path: entry.path,
+                    sha: entry.oid.to_string(),
+                }
+                .contents(repo)
+            })
+            .transpose()
+    };
+
+    let mut changes = vec![];
+    for delta in deltas {
+        // Submodules have no blob contents to search
+        let old = delta.old.filter(|entry| !entry.is_gitlink());
+        let new = delta.new.filter(|entry| !entry.is_gitlink());
+        // Identical blobs have identical hashes, no need to read them
+        if old.as_ref().map(|e| e.oid) == new.as_ref().map(|e| e.oid) {
+            continue;
+        }
+        changes.push(FileChange {
+            old: read_blob(old)?,
+            new: read_blob(new)?,
+        });
+    }
+
+    Ok(changes)
+}
+
+/// Returns the lines that were removed from `old` or added in `new`.
+pub(super) fn changed_lines<'a>(old: &'a str, new: &'a str) -> Vec<&'a str> {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+
+    let mut lines = vec![];
+    let (mut i, mut j) = (0, 0);
+    for change in compute_diff(&old_lines, &new_lines) {
+        match change {
+            Change::Same => {
+                i += 1;
+                j += 1;
+            }
+            Change::Delete => {
+                lines.push(old_lines[i]);
+                i += 1;
+            }
+            Change::Insert => {
+                lines.push(new_lines[j]);
+                j += 1;
+            }
+            Change::Replace => {
+                lines.push(old_lines[i]);
+                lines.push(new_lines[j]);
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    lines
 }
 
 // Resolves the tree references based on input parameters
@@ -134,22 +1058,25 @@ fn resolve_trees<'a>(
     repo: &GitRepository,
     tree1: Option<&'a str>,
     tree2: Option<&'a str>,
-) -> Result<(Option<String>, Option<String>), String> {
+) -> Result<(Option<String>, Option<String>), Error> {
     match (tree1, tree2) {
         (None, None) => {
             let head = tree::Tree::get_head_tree_sha(repo)?;
             Ok((Some(head), None))
         }
         (Some(tree), None) => {
-            let tree_sha = objects::find_object(repo, tree, None, true)?;
-            Ok((Some(tree_sha), None))
+            let tree_sha =
+                objects::find_object(repo, tree, Some("tree"), true)?;
+            Ok((Some(tree_sha.to_string()), None))
         }
         (Some(tree1), Some(tree2)) => {
-            let tree1_sha = objects::find_object(repo, tree1, None, true)?;
-            let tree2_sha = objects::find_object(repo, tree2, None, true)?;
-            Ok((Some(tree1_sha), Some(tree2_sha)))
+            let tree1_sha =
+                objects::find_object(repo, tree1, Some("tree"), true)?;
+            let tree2_sha =
+                objects::find_object(repo, tree2, Some("tree"), true)?;
+            Ok((Some(tree1_sha.to_string()), Some(tree2_sha.to_string())))
         }
-        _ => Err("Invalid tree arguments".to_owned()),
+        _ => Err("Invalid tree arguments".into()),
     }
 }
 
@@ -158,7 +1085,7 @@ fn get_file_contents(
     repo: &GitRepository,
     tree1: Option<&str>,
     tree2: Option<&str>,
-) -> Result<(Vec<FileSource>, Vec<FileSource>), String> {
+) -> Result<(Vec<FileSource>, Vec<FileSource>), Error> {
     let files1 = get_files(repo, tree1)?;
     let files2 = get_files(repo, tree2)?;
     Ok((files1, files2))
@@ -172,13 +1099,14 @@ fn get_file_contents(
 /// - `specified_files`: A slice of `String` with specific files to process, if any.
 ///
 /// # Returns
-/// A `Vec<String>` containing paths to all files that need processing.
+/// A `Vec<String>` containing paths to all files that need processing, in
+/// sorted order.
 pub(super) fn collect_files_to_process(
     files1: &[FileSource],
     files2: &[FileSource],
     specified_files: &[String],
 ) -> Vec<String> {
-    let mut all_files = HashSet::new();
+    let mut all_files = BTreeSet::new();
 
     if specified_files.is_empty() {
         all_files.extend(files1.iter().map(FileSource::path));
@@ -190,124 +1118,138 @@ pub(super) fn collect_files_to_process(
     all_files.into_iter().collect()
 }
 
-// Processes files in parallel using threads
+/// Processes files on the workers of `opts.pool`, writing the output for each
+/// file to `sink` in the order of `all_files`, as soon as it and the files
+/// before it are ready.
 fn process_files_in_parallel(
-    repo: GitRepository,
+    repo: &GitRepository,
     files1: Vec<FileSource>,
     files2: Vec<FileSource>,
-    all_files: &[String],
+    all_files: Vec<String>,
     opts: DiffOpts,
-) -> Result<String, String> {
-    let num_threads = usize::min(MAX_THREADS, all_files.len());
-    let chunk_size = (all_files.len() + num_threads - 1) / num_threads;
-
-    let file_chunks: Vec<Vec<String>> = all_files
-        .chunks(chunk_size)
-        .map(<[String]>::to_vec)
-        .collect();
-
-    let repo_ref = Arc::new(repo);
-    let files1_ref = Arc::new(files1);
-    let files2_ref = Arc::new(files2);
-    let opts_ref = Arc::new(opts);
-
-    let handles = spawn_worker_threads(
-        &repo_ref,
-        &file_chunks,
-        &files1_ref,
-        &files2_ref,
-        &opts_ref,
-    );
-    collect_thread_results(handles)
+    sink: &mut DiffSink,
+) -> Result<(), Error> {
+    if all_files.is_empty() {
+        return Ok(());
+    }
+
+    let opts = Arc::new(DiffOpts {
+        drivers: diff_drivers(repo, &all_files)?,
+        object_format: repo.object_format(),
+        ..opts
+    });
+    let repo = Arc::new(repo.clone());
+    let files1 = Arc::new(index_by_path(files1));
+    let files2 = Arc::new(index_by_path(files2));
+
+    let job_opts = Arc::clone(&opts);
+    let outputs = opts.pool.map(all_files, move |file| {
+        process_single_file(&repo, &file, &files1, &files2, &job_opts)
+    });
+    write_outputs(outputs.map(|output| output?), &opts, sink)
 }
 
-// Collects and sorts results from all threads
-fn collect_thread_results(
-    handles: Vec<thread::JoinHandle<Result<Vec<String>, String>>>,
-) -> Result<String, String> {
-    handles
-        .into_iter()
-        .try_fold(vec![], |mut results, handle| match handle.join() {
-            Ok(thread_results) => match thread_results {
-                Ok(result) => {
-                    results.extend(result);
-                    Ok(results)
-                }
-                Err(msg) => Err(msg),
-            },
-            Err(_) => Err("A thread panicked during execution".to_string()),
-        })
-        .map(|mut results| {
-            results.sort();
-            results.join("\n")
-        })
+/// Reads the number of workers to diff files on from `diff.jobs`, where 0
+/// or a missing value means one per CPU.
+fn config_jobs(repo: &GitRepository) -> Result<usize, Error> {
+    let Some(jobs) = repo.config().get("diff").and_then(|d| d.get("jobs"))
+    else {
+        return Ok(0);
+    };
+    jobs.parse().map_err(|_| {
+        Error::Parse(format!("Invalid number of jobs in diff.jobs: {jobs}"))
+    })
 }
 
-// Spawns worker threads to process file chunks
-fn spawn_worker_threads(
-    repo: &Arc<GitRepository>,
-    file_chunks: &[Vec<String>],
-    files1: &Arc<Vec<FileSource>>,
-    files2: &Arc<Vec<FileSource>>,
-    opts: &Arc<DiffOpts>,
-) -> Vec<thread::JoinHandle<Result<Vec<String>, String>>> {
-    let mut handles = Vec::new();
-
-    for chunk in file_chunks {
-        let repo = repo.clone();
-        let files1 = files1.clone();
-        let files2 = files2.clone();
-        let opts = opts.clone();
-        let chunk = chunk.clone();
-
-        let handle = thread::spawn(move || {
-            process_file_chunk(&repo, &chunk, &files1, &files2, &opts)
-        });
-
-        handles.push(handle);
+/// Looks up the diff driver of each path from the `diff` attribute. A driver
+/// named by `diff=<name>` is configured in the `[diff "<name>"]` section.
+fn diff_drivers(
+    repo: &GitRepository,
+    paths: &[String],
+) -> Result<HashMap<String, DiffDriver>, Error> {
+    let attributes = worktree::get_attributes(repo, paths)?;
+    if attributes.is_empty() {
+        return Ok(HashMap::new());
     }
 
-    handles
+    let drivers = paths
+        .iter()
+        .filter_map(|path| {
+            let driver = match attributes.get(path, "diff")? {
+                AttrValue::Set => return None,
+                AttrValue::Unset => DiffDriver {
+                    binary: true,
+                    ..DiffDriver::default()
+                },
+                AttrValue::Value(name) => {
+                    let section =
+                        repo.config().get(&format!("diff \"{name}\""))?;
+                    DiffDriver {
+                        binary: false,
+                        textconv: section.get("textconv").map(String::from),
+                        command: section.get("command").map(String::from),
+                    }
+                }
+            };
+            Some((path.clone(), driver))
+        })
+        .collect();
+    Ok(drivers)
 }
 
-// Processes a chunk of files in a single thread
-fn process_file_chunk(
-    repo: &GitRepository,
-    chunk: &[String],
-    files1: &[FileSource],
-    files2: &[FileSource],
+/// Writes the output for each file to `sink`. With `--shortstat`, the numbers
+/// of changed lines are summed up instead, and written at the end. The same
+/// goes for the changes per directory with `--dirstat`.
+fn write_outputs(
+    outputs: impl Iterator<Item = Result<Option<FileOutput>, Error>>,
     opts: &DiffOpts,
-) -> Result<Vec<String>, String> {
-    let mut results = Vec::new();
-
-    let tree1_files = files1
-        .iter()
-        .map(|f| (f.path(), f))
-        .collect::<HashMap<_, _>>();
-    let tree2_files = files2
-        .iter()
-        .map(|f| (f.path(), f))
-        .collect::<HashMap<_, _>>();
-
-    for file in chunk {
-        if let Some(output) =
-            process_single_file(repo, file, &tree1_files, &tree2_files, opts)?
-        {
-            results.push(output);
+    sink: &mut DiffSink,
+) -> Result<(), Error> {
+    let show_files = !(opts.shortstat || opts.dirstat) || opts.numstat;
+    let (mut files, mut additions, mut deletions) = (0, 0, 0);
+    let mut damage = vec![];
+    for output in outputs {
+        let Some(output) = output? else {
+            continue;
+        };
+        if sink.closed {
+            break;
+        }
+        if opts.shortstat {
+            let (added, deleted) = output.line_counts.unwrap_or_default();
+            files += 1;
+            additions += added;
+            deletions += deleted;
         }
+        if opts.dirstat {
+            damage.push((output.path, output.damage));
+        }
+        if show_files {
+            sink.push(&output.text)?;
+        }
+    }
+    if opts.dirstat {
+        sink.push(&format_dirstat(&damage))?;
+    }
+    if opts.shortstat {
+        sink.push(&format_shortstat(files, additions, deletions))?;
     }
+    Ok(())
+}
 
-    Ok(results)
+/// Indexes files by their path.
+fn index_by_path(files: Vec<FileSource>) -> HashMap<String, FileSource> {
+    files.into_iter().map(|f| (f.path(), f)).collect()
 }
 
 // Processes a single file and returns its diff output
 fn process_single_file(
     repo: &GitRepository,
     file: &str,
-    files1: &HashMap<String, &FileSource>,
-    files2: &HashMap<String, &FileSource>,
+    files1: &HashMap<String, FileSource>,
+    files2: &HashMap<String, FileSource>,
     opts: &DiffOpts,
-) -> Result<Option<String>, String> {
+) -> Result<Option<FileOutput>, Error> {
     let content1 = files1.get(file).map(|f| f.contents(repo)).transpose()?;
     let content2 = files2.get(file).map(|f| f.contents(repo)).transpose()?;
 
@@ -317,17 +1259,51 @@ fn process_single_file(
         return Ok(None);
     };
 
-    if !should_process_file(status, &opts.diff_filter) {
+    if !should_process_file(status, opts.diff_filter.as_deref()) {
         return Ok(None);
     }
 
-    Ok(Some(generate_output(
-        file,
-        status,
-        content1.as_deref(),
-        content2.as_deref(),
-        opts,
-    )))
+    // The status comes from the real contents, but a textconv driver decides
+    // what is diffed
+    let driver = opts.drivers.get(file);
+    let (content1, content2) = match driver.and_then(|d| d.textconv.as_ref()) {
+        Some(textconv) => (
+            content1
+                .map(|c| run_textconv(textconv, file, &c))
+                .transpose()?,
+            content2
+                .map(|c| run_textconv(textconv, file, &c))
+                .transpose()?,
+        ),
+        None => (content1, content2),
+    };
+
+    let (old, new) = (content1.as_deref(), content2.as_deref());
+    let binary = driver.is_some_and(|d| d.binary);
+    let line_counts = ((opts.shortstat || opts.dirstat) && !binary)
+        .then(|| line_counts(old, new, opts.algorithm))
+        .flatten();
+    // Like git, a binary file is counted as one changed line per 64 bytes
+    let damage = line_counts.map_or_else(
+        || {
+            let size = |c: Option<&[u8]>| c.map_or(0, <[u8]>::len);
+            (size(old) + size(new)).div_ceil(64)
+        },
+        |(added, deleted)| added + deleted,
+    );
+    Ok(Some(FileOutput {
+        path: file.to_owned(),
+        text: generate_output(
+            file,
+            status,
+            old,
+            new,
+            submodule_change(files1.get(file), files2.get(file)),
+            opts,
+        )?,
+        line_counts,
+        damage,
+    }))
 }
 
 // Determines the status of a file (Added, Modified, Deleted)
@@ -350,8 +1326,8 @@ fn determine_file_status(
 }
 
 // Checks if a file should be processed based on diff filter
-fn should_process_file(status: char, diff_filter: &Option<String>) -> bool {
-    if let Some(ref filter) = diff_filter {
+fn should_process_file(status: char, diff_filter: Option<&str>) -> bool {
+    if let Some(filter) = diff_filter {
         status_matches_filter(status, filter)
     } else {
         true
@@ -384,17 +1360,39 @@ fn generate_output(
     status: char,
     content1: Option<&[u8]>,
     content2: Option<&[u8]>,
+    submodule: Option<SubmoduleChange>,
     opts: &DiffOpts,
-) -> String {
-    if opts.name_only {
+) -> Result<String, Error> {
+    let binary = opts.drivers.get(file).is_some_and(|d| d.binary);
+    let output = if opts.name_only {
         file.to_string()
+    } else if opts.name_status && opts.json {
+        let status = status.to_string();
+        Json::object([("status", status.into()), ("path", file.into())])
+            .to_string()
     } else if opts.name_status {
         format!("{status}\t{file}")
+    } else if opts.check {
+        format_check(file, content1, content2, opts.algorithm)
+    } else if opts.numstat || opts.shortstat || opts.dirstat {
+        let line_counts = (!binary)
+            .then(|| line_counts(content1, content2, opts.algorithm))
+            .flatten();
+        format_numstat(file, line_counts)
     } else if opts.stat {
-        format_diffstat(file, content1.unwrap_or(&[]), content2.unwrap_or(&[]))
+        format_diffstat(
+            file,
+            content1.unwrap_or(&[]),
+            content2.unwrap_or(&[]),
+            opts.algorithm,
+            opts.palette,
+        )
     } else {
-        generate_full_diff(file, status, content1, content2, opts)
-    }
+        return generate_full_diff(
+            file, status, content1, content2, submodule, opts,
+        );
+    };
+    Ok(output)
 }
 
 // Generates full diff output based on file status
@@ -403,15 +1401,35 @@ fn generate_full_diff(
     status: char,
     content1: Option<&[u8]>,
     content2: Option<&[u8]>,
+    submodule: Option<SubmoduleChange>,
     opts: &DiffOpts,
-) -> String {
-    match status {
+) -> Result<String, Error> {
+    if let Some(submodule) = submodule {
+        return Ok(format_submodule(file, submodule));
+    }
+    if let Some(driver) = opts.drivers.get(file) {
+        if let Some(command) = &driver.command {
+            return run_external_diff(
+                command,
+                file,
+                content1,
+                content2,
+                opts.object_format,
+            );
+        }
+        if driver.binary {
+            return Ok(format_forced_binary(file, status, opts));
+        }
+    }
+
+    let output = match status {
         'A' => format_addition(
             file,
             content2.unwrap(),
             &opts.src_prefix,
             &opts.dst_prefix,
             opts.no_prefix,
+            opts.palette,
         ),
         'D' => format_deletion(
             file,
@@ -419,165 +1437,564 @@ fn generate_full_diff(
             &opts.src_prefix,
             &opts.dst_prefix,
             opts.no_prefix,
+            opts.palette,
         ),
-        'M' => format_diff(
-            file,
-            content1.unwrap(),
-            content2.unwrap(),
-            opts.hunk_context_lines,
-            &opts.src_prefix,
-            &opts.dst_prefix,
-            opts.no_prefix,
-        ),
+        'M' => format_diff(file, content1.unwrap(), content2.unwrap(), opts),
         _ => String::new(),
-    }
+    };
+    Ok(output)
 }
 
-fn compute_diff(old_lines: &[&str], new_lines: &[&str]) -> Vec<Change> {
-    let matches = find_matches_optimized(old_lines, new_lines);
-    let lcs = build_lcs(&matches);
-    generate_changes(old_lines, new_lines, &lcs)
+/// Finds the commits of a path that is a submodule on either side, or `None`
+/// if it is not a submodule on either side.
+fn submodule_change<'a>(
+    old: Option<&'a FileSource>,
+    new: Option<&'a FileSource>,
+) -> Option<SubmoduleChange<'a>> {
+    let commit = |file: Option<&'a FileSource>| match file {
+        Some(FileSource::Gitlink { sha, .. }) => Some(sha.as_str()),
+        _ => None,
+    };
+    let (old, new) = (commit(old), commit(new));
+    (old.is_some() || new.is_some()).then_some(SubmoduleChange { old, new })
 }
 
-fn find_matches_optimized(
-    old_lines: &[&str],
-    new_lines: &[&str],
-) -> Vec<(usize, usize)> {
-    let mut new_line_positions: HashMap<&str, Vec<usize>> = HashMap::new();
+/// Formats a change to a submodule as the range of commits it moved across,
+/// like git's `--submodule=log` without the log.
+fn format_submodule(path: &str, submodule: SubmoduleChange) -> String {
+    let abbrev = |sha: Option<&str>| {
+        let sha = sha.unwrap_or("0000000");
+        sha[..sha.len().min(objects::MIN_ABBREV_LEN)].to_owned()
+    };
+    let (old, new) = (abbrev(submodule.old), abbrev(submodule.new));
+    match (submodule.old, submodule.new) {
+        (None, _) => format!("Submodule {path} {old}...{new} (new submodule)"),
+        (_, None) => {
+            format!("Submodule {path} {old}...{new} (submodule deleted)")
+        }
+        _ => format!("Submodule {path} {old}..{new}"),
+    }
+}
+
+/// Formats a file marked with `-diff` as binary, whatever its contents.
+fn format_forced_binary(file: &str, status: char, opts: &DiffOpts) -> String {
+    let src_path = prefixed_path(file, &opts.src_prefix, opts.no_prefix);
+    let dst_path = prefixed_path(file, &opts.dst_prefix, opts.no_prefix);
+    match status {
+        'A' => format_binary_addition(
+            &prefixed_path("/dev/null", &opts.src_prefix, opts.no_prefix),
+            &dst_path,
+        ),
+        'D' => format_binary_deletion(
+            &src_path,
+            &prefixed_path("/dev/null", &opts.dst_prefix, opts.no_prefix),
+        ),
+        _ => format_binary_diff(&src_path, &dst_path),
+    }
+}
 
-    // Store all positions for each line
-    for (j, &line) in new_lines.iter().enumerate() {
-        new_line_positions.entry(line).or_default().push(j);
+/// Prefixes a path shown in a diff header, without doubling the slash of
+/// `/dev/null`.
+fn prefixed_path(path: &str, prefix: &str, no_prefix: bool) -> String {
+    if no_prefix {
+        path.to_owned()
+    } else if prefix.ends_with('/') {
+        format!("{prefix}{}", path.trim_start_matches('/'))
+    } else {
+        format!("{prefix}{path}")
     }
+}
 
-    let mut matches = Vec::new();
-    let mut matched_in_new: HashSet<usize> = HashSet::new();
+/// Converts the contents of `path` to text with a textconv driver. Like git,
+/// the command is run by the shell, with a temporary file holding the
+/// contents as its argument.
+fn run_textconv(
+    command: &str,
+    path: &str,
+    content: &[u8],
+) -> Result<Vec<u8>, Error> {
+    let tmp = write_temp_file(path, content)?;
+    let output = run_shell_command(command, &[tmp.to_string_lossy().as_ref()]);
+    let _ = std::fs::remove_file(&tmp);
+    output
+}
 
-    for (i, &line) in old_lines.iter().enumerate() {
-        if let Some(positions) = new_line_positions.get(line) {
-            // Find the first unmatched position
-            if let Some(&j) =
-                positions.iter().find(|&&j| !matched_in_new.contains(&j))
-            {
-                matches.push((i, j));
-                matched_in_new.insert(j);
+/// Produces the patch for `path` with an external diff driver. Like git, the
+/// command is given the path, then the file, object name and mode of each
+/// side, where a missing side is `/dev/null` with `.` as its name and mode.
+fn run_external_diff(
+    command: &str,
+    path: &str,
+    content1: Option<&[u8]>,
+    content2: Option<&[u8]>,
+    object_format: ObjectFormat,
+) -> Result<String, Error> {
+    let (tmp_files, errors): (Vec<_>, Vec<_>) = [content1, content2]
+        .into_iter()
+        .flatten()
+        .map(|content| write_temp_file(path, content))
+        .partition(Result::is_ok);
+    let tmp_files = tmp_files.into_iter().flatten().collect::<Vec<_>>();
+    if let Some(Err(e)) = errors.into_iter().next() {
+        remove_temp_files(&tmp_files);
+        return Err(e);
+    }
+
+    let mut args = vec![path.to_owned()];
+    let mut written = tmp_files.iter();
+    for content in [content1, content2] {
+        match content.zip(written.next()) {
+            Some((content, tmp)) => {
+                let blob = GitObject::Blob(blob::Blob::from(content));
+                args.push(tmp.to_string_lossy().into_owned());
+                let (_, mut hash) = objects::hash_object(&blob, object_format);
+                args.push(hash.hex_digest());
+                args.push("100644".to_owned());
             }
+            None => args.extend(["/dev/null", ".", "."].map(String::from)),
         }
     }
 
-    matches.sort_unstable_by(|a, b| a.0.cmp(&b.0));
-    matches
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    let output = run_shell_command(command, &args);
+    remove_temp_files(&tmp_files);
+    Ok(String::from_utf8_lossy(&output?).into_owned())
 }
 
-fn build_lcs(matches: &[(usize, usize)]) -> Vec<(usize, usize)> {
-    let mut paths: Vec<Vec<(usize, usize)>> = Vec::new();
+/// Runs a configured command with the shell, appending `args`, and returns
+/// what it writes to stdout.
+fn run_shell_command(command: &str, args: &[&str]) -> Result<Vec<u8>, Error> {
+    trace::command(command, args);
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{command} \"$@\""))
+        .arg(command)
+        .args(args)
+        .output()
+        .map_err(|e| format!("Failed to run '{command}': {e}"))?;
+    if !output.status.success() {
+        return Err(format!("'{command}' failed with {}", output.status).into());
+    }
+    Ok(output.stdout)
+}
 
-    for &(i, j) in matches {
-        let k = match paths
-            .binary_search_by(|path| path.last().unwrap().1.cmp(&j))
-        {
-            Err(k) | Ok(k) => k,
-        };
+/// Removes the temporary files written for an external diff driver.
+fn remove_temp_files(tmp_files: &[PathBuf]) {
+    for tmp in tmp_files {
+        let _ = std::fs::remove_file(tmp);
+    }
+}
 
-        let mut new_path = if k > 0 {
-            paths[k - 1].clone()
-        } else {
-            Vec::new()
-        };
-        new_path.push((i, j));
+/// Numbers the temporary files of the process
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
 
-        if k < paths.len() {
-            paths[k] = new_path;
-        } else {
-            paths.push(new_path);
+/// Writes `content` to a new temporary file, named after `path` so that
+/// drivers can tell the file type from its extension.
+///
+/// The file is created anew, and only readable by the user, so a file or
+/// symlink someone else put at its predictable name is never written
+/// through. Names that are taken are skipped.
+fn write_temp_file(path: &str, content: &[u8]) -> Result<PathBuf, Error> {
+    const ATTEMPTS: usize = 100;
+
+    let name = Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let failed = "Failed to write temporary file";
+    for _ in 0..ATTEMPTS {
+        let tmp = std::env::temp_dir().join(format!(
+            "mini_git_{}_{}_{name}",
+            std::process::id(),
+            TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(target_family = "unix")]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = match options.open(&tmp) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(Error::io(failed, e)),
+        };
+        if let Err(e) = file.write_all(content) {
+            let _ = std::fs::remove_file(&tmp);
+            return Err(Error::io(failed, e));
         }
+        return Ok(tmp);
     }
+    Err(Error::io(
+        failed,
+        io::Error::new(io::ErrorKind::AlreadyExists, "all names are taken"),
+    ))
+}
 
-    if let Some(lcs) = paths.last() {
-        lcs.clone()
-    } else {
-        Vec::new()
-    }
+/// Compute the changes turning `old_lines` into `new_lines`, using Myers'
+/// algorithm.
+fn compute_diff(old_lines: &[&str], new_lines: &[&str]) -> Vec<Change> {
+    compute_diff_with(old_lines, new_lines, DiffAlgorithm::Myers)
 }
 
-fn generate_changes(
+/// Compute the changes turning `old_lines` into `new_lines` with the given
+/// algorithm.
+///
+/// Removed lines directly followed by added lines are paired up as
+/// replacements.
+fn compute_diff_with(
     old_lines: &[&str],
     new_lines: &[&str],
-    lcs: &[(usize, usize)],
+    algorithm: DiffAlgorithm,
 ) -> Vec<Change> {
-    let mut changes = Vec::new();
-    let mut i = 0;
-    let mut j = 0;
-    let mut lcs_iter = lcs.iter().peekable();
-
-    while i < old_lines.len() || j < new_lines.len() {
-        if let Some(&(lcs_i, lcs_j)) = lcs_iter.peek() {
-            if i == *lcs_i && j == *lcs_j {
-                // Always compare the actual lines here
-                if old_lines[i] == new_lines[j] {
-                    changes.push(Change::Same);
-                } else {
-                    // This case should rarely happen now with improved matching
-                    changes.push(Change::Replace);
+    let mut changes = Vec::with_capacity(old_lines.len() + new_lines.len());
+    match algorithm {
+        DiffAlgorithm::Myers => {
+            Myers::new(old_lines.len(), new_lines.len()).diff(
+                old_lines,
+                new_lines,
+                &mut changes,
+            );
+        }
+        DiffAlgorithm::Patience => {
+            patience_diff(old_lines, new_lines, &mut changes);
+        }
+        DiffAlgorithm::Histogram => {
+            histogram_diff(old_lines, new_lines, &mut changes);
+        }
+    }
+    pair_replacements(&changes)
+}
+
+/// The number of leading and trailing elements that `old` and `new` have in
+/// common. The two never overlap.
+fn common_ends<T: PartialEq>(old: &[T], new: &[T]) -> (usize, usize) {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    (prefix, suffix)
+}
+
+/// Working space for Myers' algorithm, which finds a shortest edit script in
+/// O(ND) time, using the linear space refinement.
+///
+/// `forward[k]` holds the furthest `x` reached on diagonal `k = x - y` when
+/// searching from the start, and `backward[k]` the smallest `x` reached when
+/// searching from the end. Diagonals are offset so that they index from 0.
+struct Myers {
+    forward: Vec<isize>,
+    backward: Vec<isize>,
+}
+
+impl Myers {
+    fn new(old_len: usize, new_len: usize) -> Self {
+        // Diagonals range over -new_len - 1 ..= old_len + 1
+        let size = old_len + new_len + 3;
+        Self {
+            forward: vec![0; size],
+            backward: vec![0; size],
+        }
+    }
+
+    /// Append the changes turning `old` into `new` to `changes`.
+    fn diff<T: PartialEq>(
+        &mut self,
+        old: &[T],
+        new: &[T],
+        changes: &mut Vec<Change>,
+    ) {
+        let (prefix, suffix) = common_ends(old, new);
+        let old = &old[prefix..old.len() - suffix];
+        let new = &new[prefix..new.len() - suffix];
+
+        changes.extend(std::iter::repeat_n(Change::Same, prefix));
+        if old.is_empty() {
+            changes.extend(std::iter::repeat_n(Change::Insert, new.len()));
+        } else if new.is_empty() {
+            changes.extend(std::iter::repeat_n(Change::Delete, old.len()));
+        } else {
+            let (x, y) = self.split(old, new);
+            self.diff(&old[..x], &new[..y], changes);
+            self.diff(&old[x..], &new[y..], changes);
+        }
+        changes.extend(std::iter::repeat_n(Change::Same, suffix));
+    }
+
+    /// Find a point on a shortest edit path from the start of `old` and
+    /// `new` to their ends, by searching from both ends until the searches
+    /// meet.
+    ///
+    /// Both sequences must be non-empty, and differ in their first and last
+    /// elements. The returned point then splits the problem into two strictly
+    /// smaller ones.
+    #[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+    fn split<T: PartialEq>(&mut self, old: &[T], new: &[T]) -> (usize, usize) {
+        let (n, m) = (old.len() as isize, new.len() as isize);
+        let offset = m + 1;
+        let at = |k: isize| (k + offset) as usize;
+
+        let delta = n - m;
+        let odd = delta & 1 == 1;
+        // The forward search starts on diagonal 0, the backward on delta
+        let (mut fmin, mut fmax) = (0, 0);
+        let (mut bmin, mut bmax) = (delta, delta);
+        self.forward[at(0)] = 0;
+        self.backward[at(delta)] = n;
+
+        loop {
+            // Extend the forward search by one edit, keeping to diagonals
+            // that intersect the grid
+            if fmin > -m {
+                fmin -= 1;
+                self.forward[at(fmin - 1)] = -1;
+            } else {
+                fmin += 1;
+            }
+            if fmax < n {
+                fmax += 1;
+                self.forward[at(fmax + 1)] = -1;
+            } else {
+                fmax -= 1;
+            }
+            for k in (fmin..=fmax).rev().step_by(2) {
+                let (left, right) =
+                    (self.forward[at(k - 1)], self.forward[at(k + 1)]);
+                let mut x = if left >= right { left + 1 } else { right };
+                let mut y = x - k;
+                while x < n && y < m && old[x as usize] == new[y as usize] {
+                    x += 1;
+                    y += 1;
                 }
-                i += 1;
-                j += 1;
-                lcs_iter.next();
-            } else if i < *lcs_i && j < *lcs_j {
-                // Only mark as Replace if lines are actually different
-                if old_lines[i] == new_lines[j] {
-                    changes.push(Change::Same);
-                } else {
-                    changes.push(Change::Replace);
+                self.forward[at(k)] = x;
+                if odd
+                    && (bmin..=bmax).contains(&k)
+                    && self.backward[at(k)] <= x
+                {
+                    return (x as usize, y as usize);
                 }
-                i += 1;
-                j += 1;
-            } else if i < *lcs_i {
-                // Delete
-                changes.push(Change::Delete);
-                i += 1;
-            } else if j < *lcs_j {
-                // Insert
-                changes.push(Change::Insert);
-                j += 1;
             }
-        } else {
-            // No more LCS matches
-            if i < old_lines.len() && j < new_lines.len() {
-                if old_lines[i] == new_lines[j] {
-                    changes.push(Change::Same);
-                } else {
-                    changes.push(Change::Replace);
+
+            // Extend the backward search by one edit
+            if bmin > -m {
+                bmin -= 1;
+                self.backward[at(bmin - 1)] = isize::MAX;
+            } else {
+                bmin += 1;
+            }
+            if bmax < n {
+                bmax += 1;
+                self.backward[at(bmax + 1)] = isize::MAX;
+            } else {
+                bmax -= 1;
+            }
+            for k in (bmin..=bmax).rev().step_by(2) {
+                let (left, right) =
+                    (self.backward[at(k - 1)], self.backward[at(k + 1)]);
+                let mut x = if left < right { left } else { right - 1 };
+                let mut y = x - k;
+                while x > 0
+                    && y > 0
+                    && old[x as usize - 1] == new[y as usize - 1]
+                {
+                    x -= 1;
+                    y -= 1;
+                }
+                self.backward[at(k)] = x;
+                if !odd
+                    && (fmin..=fmax).contains(&k)
+                    && self.forward[at(k)] >= x
+                {
+                    return (x as usize, y as usize);
                 }
-                i += 1;
-                j += 1;
-            } else if i < old_lines.len() {
-                changes.push(Change::Delete);
-                i += 1;
-            } else if j < new_lines.len() {
-                changes.push(Change::Insert);
-                j += 1;
             }
         }
     }
+}
 
-    // Post-process changes to merge adjacent replacements
-    let mut optimized_changes = Vec::with_capacity(changes.len());
-    let mut i = 0;
-    while i < changes.len() {
-        if i + 1 < changes.len()
-            && matches!(changes[i], Change::Delete)
-            && matches!(changes[i + 1], Change::Insert)
-        {
-            optimized_changes.push(Change::Replace);
-            i += 2;
+/// Append the changes turning `old` into `new` to `changes`, using patience
+/// diff.
+///
+/// Lines that occur exactly once on both sides are matched up first, keeping
+/// the longest run of them that appears in the same order on both sides. The
+/// gaps between them are diffed recursively, falling back to Myers' algorithm
+/// when there are no such lines.
+fn patience_diff<T: Hash + Eq>(
+    old: &[T],
+    new: &[T],
+    changes: &mut Vec<Change>,
+) {
+    let (prefix, suffix) = common_ends(old, new);
+    let old = &old[prefix..old.len() - suffix];
+    let new = &new[prefix..new.len() - suffix];
+
+    changes.extend(std::iter::repeat_n(Change::Same, prefix));
+    let anchors = unique_anchors(old, new);
+    if anchors.is_empty() {
+        Myers::new(old.len(), new.len()).diff(old, new, changes);
+    } else {
+        let (mut i, mut j) = (0, 0);
+        for (anchor_i, anchor_j) in anchors {
+            patience_diff(&old[i..anchor_i], &new[j..anchor_j], changes);
+            changes.push(Change::Same);
+            (i, j) = (anchor_i + 1, anchor_j + 1);
+        }
+        patience_diff(&old[i..], &new[j..], changes);
+    }
+    changes.extend(std::iter::repeat_n(Change::Same, suffix));
+}
+
+/// Find the longest increasing sequence of positions of lines which are
+/// unique in both `old` and `new`.
+fn unique_anchors<T: Hash + Eq>(old: &[T], new: &[T]) -> Vec<(usize, usize)> {
+    // Occurrences and last position of each line on either side
+    let mut lines: HashMap<&T, [(usize, usize); 2]> = HashMap::new();
+    for (side, seq) in [old, new].into_iter().enumerate() {
+        for (i, line) in seq.iter().enumerate() {
+            let entry = &mut lines.entry(line).or_default()[side];
+            *entry = (entry.0 + 1, i);
+        }
+    }
+    let mut unique = lines
+        .into_values()
+        .filter(|[(old, _), (new, _)]| *old == 1 && *new == 1)
+        .map(|[(_, i), (_, j)]| (i, j))
+        .collect::<Vec<_>>();
+    unique.sort_unstable();
+
+    // Patience sorting, where each pile's top is the smallest position in
+    // `new` ending an increasing sequence of that length
+    let mut piles: Vec<usize> = vec![];
+    let mut prev = vec![None; unique.len()];
+    for (k, &(_, j)) in unique.iter().enumerate() {
+        let pile = piles.partition_point(|&top| unique[top].1 < j);
+        prev[k] = pile.checked_sub(1).map(|p| piles[p]);
+        if pile == piles.len() {
+            piles.push(k);
         } else {
-            optimized_changes.push(changes[i].clone());
-            i += 1;
+            piles[pile] = k;
+        }
+    }
+
+    let mut anchors = vec![];
+    let mut k = piles.last().copied();
+    while let Some(i) = k {
+        anchors.push(unique[i]);
+        k = prev[i];
+    }
+    anchors.reverse();
+    anchors
+}
+
+/// Lines occurring more often than this in the old file are never used to
+/// match up the two files in histogram diff.
+const MAX_CHAIN_LEN: usize = 64;
+
+/// Append the changes turning `old` into `new` to `changes`, using histogram
+/// diff.
+///
+/// This extends patience diff to lines that are not unique. The common region
+/// whose rarest line occurs least often in `old` is matched up first, and the
+/// parts before and after it are diffed recursively, falling back to Myers'
+/// algorithm when there is no such region.
+fn histogram_diff<T: Hash + Eq>(
+    old: &[T],
+    new: &[T],
+    changes: &mut Vec<Change>,
+) {
+    let (prefix, suffix) = common_ends(old, new);
+    let old = &old[prefix..old.len() - suffix];
+    let new = &new[prefix..new.len() - suffix];
+
+    changes.extend(std::iter::repeat_n(Change::Same, prefix));
+    if let Some((i, j, len)) = rarest_common_region(old, new) {
+        histogram_diff(&old[..i], &new[..j], changes);
+        changes.extend(std::iter::repeat_n(Change::Same, len));
+        histogram_diff(&old[i + len..], &new[j + len..], changes);
+    } else {
+        Myers::new(old.len(), new.len()).diff(old, new, changes);
+    }
+    changes.extend(std::iter::repeat_n(Change::Same, suffix));
+}
+
+/// Find the common region of `old` and `new` whose rarest line occurs least
+/// often in `old`, preferring longer regions.
+///
+/// Returns the start of the region in `old` and `new`, and its length.
+fn rarest_common_region<T: Hash + Eq>(
+    old: &[T],
+    new: &[T],
+) -> Option<(usize, usize, usize)> {
+    let mut positions: HashMap<&T, Vec<usize>> = HashMap::new();
+    for (i, line) in old.iter().enumerate() {
+        positions.entry(line).or_default().push(i);
+    }
+
+    let mut best = None;
+    let (mut best_len, mut best_count) = (0, MAX_CHAIN_LEN);
+    let mut j = 0;
+    while j < new.len() {
+        let mut next = j + 1;
+        let candidates = positions
+            .get(&new[j])
+            .filter(|candidates| candidates.len() <= best_count);
+        for &i in candidates.into_iter().flatten() {
+            let before = old[..i]
+                .iter()
+                .rev()
+                .zip(new[..j].iter().rev())
+                .take_while(|(a, b)| a == b)
+                .count();
+            let after = old[i..]
+                .iter()
+                .zip(&new[j..])
+                .take_while(|(a, b)| a == b)
+                .count();
+            let (start, len) = (i - before, before + after);
+            let count = old[start..start + len]
+                .iter()
+                .map(|line| positions[line].len())
+                .min()
+                .unwrap_or(usize::MAX);
+
+            if len > best_len || count < best_count {
+                best = Some((start, j - before, len));
+                (best_len, best_count) = (len, count);
+            }
+            // No need to look for regions starting inside this one
+            next = next.max(j + after);
         }
+        j = next;
     }
+    best
+}
+
+/// Pair up each run of deletions and insertions into replacements, followed
+/// by whichever deletions or insertions are left over.
+fn pair_replacements(changes: &[Change]) -> Vec<Change> {
+    let mut paired = Vec::with_capacity(changes.len());
+    let mut runs = changes.split(|change| matches!(change, Change::Same));
+    if let Some(run) = runs.next() {
+        push_run(&mut paired, run);
+    }
+    for run in runs {
+        paired.push(Change::Same);
+        push_run(&mut paired, run);
+    }
+    paired
+}
 
-    optimized_changes
+fn push_run(paired: &mut Vec<Change>, run: &[Change]) {
+    let deleted = run.iter().filter(|c| matches!(c, Change::Delete)).count();
+    let inserted = run.len() - deleted;
+    let replaced = deleted.min(inserted);
+    paired.extend(std::iter::repeat_n(Change::Replace, replaced));
+    paired.extend(std::iter::repeat_n(Change::Delete, deleted - replaced));
+    paired.extend(std::iter::repeat_n(Change::Insert, inserted - replaced));
 }
 
 #[allow(clippy::too_many_lines)]
@@ -588,7 +2005,7 @@ fn generate_hunks(
     hunk_context_lines: usize,
 ) -> Vec<Hunk> {
     let mut hunks = Vec::new();
-    let mut current_hunk = String::new();
+    let mut current_hunk = Vec::new();
     let mut old_start = 0;
     let mut new_start = 0;
     let mut old_count = 0;
@@ -598,7 +2015,7 @@ fn generate_hunks(
     // Keep track of context lines before changes
     let mut context_buffer = Vec::new();
     // Buffer for storing additions until we can write them after deletions
-    let mut additions_buffer = String::new();
+    let mut additions_buffer = Vec::new();
 
     let mut old_line_num = 1;
     let mut new_line_num = 1;
@@ -606,12 +2023,12 @@ fn generate_hunks(
     for (i, change) in changes.iter().enumerate() {
         // Helper function to write context buffer if needed
         let write_context_buffer =
-            |current_hunk: &mut String,
+            |current_hunk: &mut Vec<Line>,
              context_buffer: &[(String, usize, usize)],
              old_count: &mut usize,
              new_count: &mut usize| {
                 for (line, _, _) in context_buffer {
-                    current_hunk.push_str(&format!(" {line}\n"));
+                    current_hunk.push(Line::new(LineOrigin::Context, line));
                     *old_count += 1;
                     *new_count += 1;
                 }
@@ -620,10 +2037,7 @@ fn generate_hunks(
         match change {
             Change::Same => {
                 // If we have buffered additions, write them now
-                if !additions_buffer.is_empty() {
-                    current_hunk.push_str(&additions_buffer);
-                    additions_buffer.clear();
-                }
+                current_hunk.append(&mut additions_buffer);
 
                 let line = old_lines[old_line_num - 1];
 
@@ -646,7 +2060,7 @@ fn generate_hunks(
                 } else if let Some(last_idx) = last_change_idx {
                     if i - last_idx <= hunk_context_lines {
                         // Within range of last change
-                        current_hunk.push_str(&format!(" {line}\n"));
+                        current_hunk.push(Line::new(LineOrigin::Context, line));
                         old_count += 1;
                         new_count += 1;
                     } else {
@@ -657,9 +2071,8 @@ fn generate_hunks(
                                 old_count,
                                 new_start,
                                 new_count,
-                                content: current_hunk,
+                                lines: std::mem::take(&mut current_hunk),
                             });
-                            current_hunk = String::new();
                             context_buffer.clear();
                         }
                         context_buffer.push((
@@ -691,7 +2104,7 @@ fn generate_hunks(
                 }
 
                 let line = old_lines[old_line_num - 1];
-                current_hunk.push_str(&format!("{RED}-{line}{RESET}\n"));
+                current_hunk.push(Line::new(LineOrigin::Deletion, line));
                 old_count += 1;
                 old_line_num += 1;
                 last_change_idx = Some(i);
@@ -710,7 +2123,7 @@ fn generate_hunks(
 
                 let line = new_lines[new_line_num - 1];
                 // Buffer the addition instead of writing it immediately
-                additions_buffer.push_str(&format!("{GREEN}+{line}{RESET}\n"));
+                additions_buffer.push(Line::new(LineOrigin::Addition, line));
                 new_count += 1;
                 new_line_num += 1;
                 last_change_idx = Some(i);
@@ -730,9 +2143,9 @@ fn generate_hunks(
 
                 let old_line = old_lines[old_line_num - 1];
                 let new_line = new_lines[new_line_num - 1];
-                current_hunk.push_str(&format!("{RED}-{old_line}{RESET}\n"));
+                current_hunk.push(Line::new(LineOrigin::Deletion, old_line));
                 additions_buffer
-                    .push_str(&format!("{GREEN}+{new_line}{RESET}\n"));
+                    .push(Line::new(LineOrigin::Addition, new_line));
                 old_count += 1;
                 new_count += 1;
                 old_line_num += 1;
@@ -742,26 +2155,21 @@ fn generate_hunks(
         }
 
         // If this is a Same change or the last change, write any buffered additions
-        if (matches!(change, Change::Same) || i == changes.len() - 1)
-            && !additions_buffer.is_empty()
-        {
-            current_hunk.push_str(&additions_buffer);
-            additions_buffer.clear();
+        if matches!(change, Change::Same) || i == changes.len() - 1 {
+            current_hunk.append(&mut additions_buffer);
         }
     }
 
     // Add the last hunk if there is one
     if !current_hunk.is_empty() {
         // Make sure to write any remaining buffered additions
-        if !additions_buffer.is_empty() {
-            current_hunk.push_str(&additions_buffer);
-        }
+        current_hunk.append(&mut additions_buffer);
         hunks.push(Hunk {
             old_start,
             old_count,
             new_start,
             new_count,
-            content: current_hunk,
+            lines: current_hunk,
         });
     }
 
@@ -772,55 +2180,347 @@ fn format_diff(
     path: &str,
     content1: &[u8],
     content2: &[u8],
-    hunk_context_lines: usize,
-    src_prefix: &str,
-    dst_prefix: &str,
-    no_prefix: bool,
+    opts: &DiffOpts,
 ) -> String {
-    let src_path = if no_prefix {
-        path.to_string()
-    } else {
-        format!("{src_prefix}{path}")
+    let diff = file_diff(
+        path,
+        Some(content1),
+        Some(content2),
+        opts.hunk_context_lines,
+        opts.algorithm,
+    );
+    render_patch(&diff, PatchStyle::new(opts))
+}
+
+/// Diffs the contents of the file at `path`, where a missing side means the
+/// file was added or deleted, showing `context_lines` unchanged lines
+/// around each change.
+///
+/// # Examples
+///
+/// ```
+/// use mini_git::core::commands::diff::{diff_blobs, LineOrigin};
+/// use mini_git::core::objects::tree::Delta;
+///
+/// let diff = diff_blobs("a.txt", Some(b"one\ntwo\n"), Some(b"one\n2\n"), 3);
+/// assert_eq!(diff.status, Delta::Modified);
+/// let hunk = &diff.hunks[0];
+/// assert_eq!((hunk.old_start, hunk.old_count), (1, 2));
+/// assert_eq!(hunk.lines[1].origin, LineOrigin::Deletion);
+/// assert_eq!(hunk.lines[2].content, "2");
+/// ```
+#[must_use]
+pub fn diff_blobs(
+    path: &str,
+    old: Option<&[u8]>,
+    new: Option<&[u8]>,
+    context_lines: usize,
+) -> FileDiff {
+    file_diff(path, old, new, context_lines, DiffAlgorithm::Myers)
+}
+
+fn file_diff(
+    path: &str,
+    old: Option<&[u8]>,
+    new: Option<&[u8]>,
+    context_lines: usize,
+    algorithm: DiffAlgorithm,
+) -> FileDiff {
+    let status = match (old, new) {
+        (None, Some(_)) => Delta::Added,
+        (Some(_), None) => Delta::Deleted,
+        _ => Delta::Modified,
     };
-    let dst_path = if no_prefix {
-        path.to_string()
-    } else {
-        format!("{dst_prefix}{path}")
+    let binary = [old, new].into_iter().flatten().any(blob::Blob::is_binary);
+    let mut diff = FileDiff {
+        status,
+        old_path: old.map(|_| path.to_owned()),
+        new_path: new.map(|_| path.to_owned()),
+        binary,
+        hunks: vec![],
     };
-
-    if blob::Blob::is_binary(content1) || blob::Blob::is_binary(content2) {
-        return format_binary_diff(&src_path, &dst_path);
+    if binary {
+        return diff;
     }
 
-    let old_str = String::from_utf8_lossy(content1);
-    let new_str = String::from_utf8_lossy(content2);
-
+    let old_str = String::from_utf8_lossy(old.unwrap_or_default());
+    let new_str = String::from_utf8_lossy(new.unwrap_or_default());
     let old_lines: Vec<&str> = old_str.lines().collect();
     let new_lines: Vec<&str> = new_str.lines().collect();
 
-    let changes = compute_diff(&old_lines, &new_lines);
-    let hunks =
-        generate_hunks(&old_lines, &new_lines, &changes, hunk_context_lines);
+    // An added or deleted file is a single hunk of all of its lines
+    let all = |origin, lines: &[&str]| {
+        lines.iter().map(|line| Line::new(origin, line)).collect()
+    };
+    diff.hunks = match status {
+        Delta::Added if !new_lines.is_empty() => vec![Hunk {
+            old_start: 0,
+            old_count: 0,
+            new_start: 1,
+            new_count: new_lines.len(),
+            lines: all(LineOrigin::Addition, &new_lines),
+        }],
+        Delta::Deleted if !old_lines.is_empty() => vec![Hunk {
+            old_start: 1,
+            old_count: old_lines.len(),
+            new_start: 0,
+            new_count: 0,
+            lines: all(LineOrigin::Deletion, &old_lines),
+        }],
+        Delta::Added | Delta::Deleted => vec![],
+        Delta::Modified | Delta::TypeChanged => {
+            let changes = compute_diff_with(&old_lines, &new_lines, algorithm);
+            generate_hunks(&old_lines, &new_lines, &changes, context_lines)
+        }
+    };
+    diff
+}
+
+/// Renders `diff` as a patch with `a/` and `b/` prefixed paths, colored
+/// with `palette`, as `mini_git diff` shows it.
+///
+/// # Examples
+///
+/// ```
+/// use mini_git::core::commands::diff::{diff_blobs, format_file_diff};
+/// use mini_git::utils::color::Palette;
+///
+/// let diff = diff_blobs("a.txt", None, Some(b"hello\n"), 3);
+/// let patch = format_file_diff(&diff, Palette::PLAIN);
+/// assert!(patch.ends_with("@@ -0,0 +1,1 @@\n+hello\n"));
+/// ```
+#[must_use]
+pub fn format_file_diff(diff: &FileDiff, palette: Palette) -> String {
+    let style = PatchStyle {
+        src_prefix: "a/",
+        dst_prefix: "b/",
+        no_prefix: false,
+        word_diff: None,
+        palette,
+    };
+    render_patch(diff, style)
+}
+
+fn render_patch(diff: &FileDiff, style: PatchStyle) -> String {
+    let path = |path: &Option<String>, prefix| {
+        let path = path.as_deref().unwrap_or("/dev/null");
+        prefixed_path(path, prefix, style.no_prefix)
+    };
+    let src_path = path(&diff.old_path, style.src_prefix);
+    let dst_path = path(&diff.new_path, style.dst_prefix);
+
+    if diff.binary {
+        return match diff.status {
+            Delta::Added => format_binary_addition(&src_path, &dst_path),
+            Delta::Deleted => format_binary_deletion(&src_path, &dst_path),
+            Delta::Modified | Delta::TypeChanged => {
+                format_binary_diff(&src_path, &dst_path)
+            }
+        };
+    }
 
+    let Palette {
+        reset, meta, frag, ..
+    } = style.palette;
     let mut output = String::new();
-    output.push_str(&format!(
-        "{CYAN}diff --mini-git {src_path} {dst_path}{RESET}\n"
-    ));
-    output.push_str("index ....\n"); // Simplified index line
-    output.push_str(&format!("--- {src_path}\n"));
-    output.push_str(&format!("+++ {dst_path}\n"));
+    let _ =
+        writeln!(output, "{meta}diff --mini-git {src_path} {dst_path}{reset}");
+    output.push_str(match diff.status {
+        Delta::Added => "new file mode 100644\n",
+        Delta::Deleted => "deleted file mode 100644\n",
+        // Simplified index line
+        Delta::Modified | Delta::TypeChanged => "index ....\n",
+    });
+    let _ = writeln!(output, "--- {src_path}");
+    let _ = writeln!(output, "+++ {dst_path}");
+
+    // Only changed files are diffed word by word
+    let word_diff = style.word_diff.filter(|_| diff.status == Delta::Modified);
+    for hunk in &diff.hunks {
+        let _ = writeln!(
+            output,
+            "{frag}@@ -{},{} +{},{} @@{reset}",
+            hunk.old_start, hunk.old_count, hunk.new_start, hunk.new_count
+        );
+        match word_diff {
+            Some(mode) => {
+                output.push_str(&render_word_diff(hunk, mode, style.palette));
+            }
+            None => output.push_str(&render_lines(hunk, style.palette)),
+        }
+    }
+
+    output.push_str(reset);
+
+    output
+}
+
+/// Render a hunk line by line, coloring removed and added lines.
+fn render_lines(hunk: &Hunk, palette: Palette) -> String {
+    let Palette {
+        reset, old, new, ..
+    } = palette;
+    hunk.lines.iter().fold(String::new(), |mut acc, line| {
+        let (prefix, text) = (line.prefix(), &line.content);
+        let _ = match line.origin {
+            LineOrigin::Deletion => writeln!(acc, "{old}{prefix}{text}{reset}"),
+            LineOrigin::Addition => {
+                writeln!(acc, "{new}{prefix}{text}{reset}")
+            }
+            LineOrigin::Context => writeln!(acc, "{prefix}{text}"),
+        };
+        acc
+    })
+}
+
+/// Render a hunk word by word.
+///
+/// Each run of removed and added lines is split into words, which are diffed
+/// against each other. Unchanged words and whitespace are shown as they are
+/// on the new side, and changed words are marked according to `mode`.
+fn render_word_diff(hunk: &Hunk, mode: WordDiff, palette: Palette) -> String {
+    let mut output = String::new();
+    let mut lines = hunk.lines.iter().peekable();
+    while let Some(line) = lines.next() {
+        if line.origin == LineOrigin::Context {
+            let _ = writeln!(output, "{}", line.content);
+            continue;
+        }
+
+        let (mut old, mut new) = (vec![], vec![]);
+        let mut line = Some(line);
+        while let Some(changed) = line {
+            if changed.origin == LineOrigin::Deletion {
+                old.push(changed.content.as_str());
+            } else {
+                new.push(changed.content.as_str());
+            }
+            line = lines.next_if(|l| l.origin != LineOrigin::Context);
+        }
+
+        output.push_str(&diff_words(
+            &old.join("\n"),
+            &new.join("\n"),
+            mode,
+            palette,
+        ));
+        output.push('\n');
+    }
+    output
+}
+
+/// Diff the words in `old` against the words in `new`.
+fn diff_words(
+    old: &str,
+    new: &str,
+    mode: WordDiff,
+    palette: Palette,
+) -> String {
+    let old_words = split_words(old);
+    let new_words = split_words(new);
+    let old_tokens = old_words.iter().map(|w| w.1).collect::<Vec<_>>();
+    let new_tokens = new_words.iter().map(|w| w.1).collect::<Vec<_>>();
+
+    let mut output = String::new();
+    // Byte offset into `new` up to which the text has been written
+    let mut written = 0;
+    let (mut i, mut j) = (0, 0);
+    let changes = compute_diff(&old_tokens, &new_tokens);
+    let mut changes = changes.iter().peekable();
+    while let Some(change) = changes.next() {
+        if matches!(change, Change::Same) {
+            let (start, word) = new_words[j];
+            output.push_str(&new[written..start + word.len()]);
+            written = start + word.len();
+            i += 1;
+            j += 1;
+            continue;
+        }
+
+        // Gather the whole run of changed words
+        let (old_start, new_start) = (i, j);
+        let mut change = Some(change);
+        while let Some(c) = change {
+            match c {
+                Change::Delete => i += 1,
+                Change::Insert => j += 1,
+                Change::Replace | Change::Same => {
+                    i += 1;
+                    j += 1;
+                }
+            }
+            change = changes.next_if(|c| !matches!(c, Change::Same));
+        }
 
-    for hunk in hunks {
-        output.push_str(&format!(
-            "{CYAN}@@ -{},{} +{},{} @@{RESET}\n",
-            hunk.old_start, hunk.old_count, hunk.new_start, hunk.new_count
-        ));
-        output.push_str(&hunk.content);
+        // Whitespace before added words goes before the removed ones too
+        if let Some(&(start, _)) =
+            new_words.get(new_start).filter(|_| j > new_start)
+        {
+            output.push_str(&new[written..start]);
+            written = start;
+        }
+        if i > old_start {
+            let (start, _) = old_words[old_start];
+            let (end, word) = old_words[i - 1];
+            output.push_str(&mark_words(
+                &old[start..end + word.len()],
+                '-',
+                mode,
+                palette,
+            ));
+        }
+        if j > new_start {
+            let (end, word) = new_words[j - 1];
+            output.push_str(&mark_words(
+                &new[written..end + word.len()],
+                '+',
+                mode,
+                palette,
+            ));
+            written = end + word.len();
+        }
     }
+    output.push_str(&new[written..]);
+    output
+}
 
-    output.push_str(RESET);
+/// Split `text` into its words, each with its byte offset.
+fn split_words(text: &str) -> Vec<(usize, &str)> {
+    let mut words = vec![];
+    let mut start = None;
+    for (i, c) in text.char_indices().chain([(text.len(), ' ')]) {
+        match (start, c.is_whitespace()) {
+            (None, false) => start = Some(i),
+            (Some(s), true) => {
+                words.push((s, &text[s..i]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    words
+}
 
-    output
+/// Mark removed (`-`) or added (`+`) text, one line at a time.
+fn mark_words(
+    text: &str,
+    marker: char,
+    mode: WordDiff,
+    palette: Palette,
+) -> String {
+    let Palette {
+        reset, old, new, ..
+    } = palette;
+    text.split('\n')
+        .map(|line| match (mode, marker) {
+            _ if line.is_empty() => String::new(),
+            (WordDiff::Plain, '-') => format!("[-{line}-]"),
+            (WordDiff::Plain, _) => format!("{{+{line}+}}"),
+            (WordDiff::Color, '-') => format!("{old}{line}{reset}"),
+            (WordDiff::Color, _) => format!("{new}{line}{reset}"),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
 }
 
 fn format_binary_diff(src_path: &str, dst_path: &str) -> String {
@@ -833,50 +2533,17 @@ fn format_addition(
     src_prefix: &str,
     dst_prefix: &str,
     no_prefix: bool,
+    palette: Palette,
 ) -> String {
-    let src_path = if no_prefix {
-        "/dev/null".to_string()
-    } else {
-        format!(
-            "{}{}",
-            src_prefix,
-            if src_prefix.ends_with('/') {
-                "dev/null"
-            } else {
-                "/dev/null"
-            }
-        )
-    };
-    let dst_path = if no_prefix {
-        path.to_string()
-    } else {
-        format!("{dst_prefix}{path}")
+    let diff = file_diff(path, None, Some(content), 0, DiffAlgorithm::Myers);
+    let style = PatchStyle {
+        src_prefix,
+        dst_prefix,
+        no_prefix,
+        word_diff: None,
+        palette,
     };
-
-    if blob::Blob::is_binary(content) {
-        return format_binary_addition(&src_path, &dst_path);
-    }
-
-    let new_str = String::from_utf8_lossy(content);
-    let new_lines: Vec<&str> = new_str.lines().collect();
-
-    let mut output = String::new();
-    output.push_str(&format!(
-        "{CYAN}diff --mini-git {src_path} {dst_path}{RESET}\n"
-    ));
-    output.push_str("new file mode 100644\n");
-    output.push_str(&format!("--- {src_path}\n"));
-    output.push_str(&format!("+++ {dst_path}\n"));
-
-    output
-        .push_str(&format!("{CYAN}@@ -0,0 +1,{} @@{RESET}\n", new_lines.len()));
-    for line in new_lines {
-        output.push_str(&format!("{GREEN}+{line}\n"));
-    }
-
-    output.push_str(RESET);
-
-    output
+    render_patch(&diff, style)
 }
 
 fn format_binary_addition(src_path: &str, dst_path: &str) -> String {
@@ -889,75 +2556,36 @@ fn format_deletion(
     src_prefix: &str,
     dst_prefix: &str,
     no_prefix: bool,
+    palette: Palette,
 ) -> String {
-    let src_path = if no_prefix {
-        path.to_string()
-    } else {
-        format!("{src_prefix}{path}")
-    };
-    let dst_path = if no_prefix {
-        "/dev/null".to_string()
-    } else {
-        format!(
-            "{}{}",
-            dst_prefix,
-            if dst_prefix.ends_with('/') {
-                "dev/null"
-            } else {
-                "/dev/null"
-            }
-        )
+    let diff = file_diff(path, Some(content), None, 0, DiffAlgorithm::Myers);
+    let style = PatchStyle {
+        src_prefix,
+        dst_prefix,
+        no_prefix,
+        word_diff: None,
+        palette,
     };
-
-    if blob::Blob::is_binary(content) {
-        return format_binary_deletion(&src_path, &dst_path);
-    }
-
-    let old_str = String::from_utf8_lossy(content);
-    let old_lines: Vec<&str> = old_str.lines().collect();
-
-    let mut output = String::new();
-    output.push_str(&format!(
-        "{CYAN}diff --mini-git {src_path} {dst_path}{RESET}\n"
-    ));
-    output.push_str("deleted file mode 100644\n");
-    output.push_str(&format!("--- {src_path}\n"));
-    output.push_str(&format!("+++ {dst_path}\n"));
-
-    output
-        .push_str(&format!("{CYAN}@@ -1,{} +0,0 @@{RESET}\n", old_lines.len()));
-    for line in old_lines {
-        output.push_str(&format!("{RED}-{line}\n"));
-    }
-
-    output.push_str(RESET);
-
-    output
+    render_patch(&diff, style)
 }
 
 fn format_binary_deletion(src_path: &str, dst_path: &str) -> String {
     format!("diff --mini-git {src_path} {dst_path}\nBinary file deleted\n")
 }
 
-fn format_diffstat(path: &str, content1: &[u8], content2: &[u8]) -> String {
+fn format_diffstat(
+    path: &str,
+    content1: &[u8],
+    content2: &[u8],
+    algorithm: DiffAlgorithm,
+    palette: Palette,
+) -> String {
+    let Palette {
+        reset, old, new, ..
+    } = palette;
     // Generate a simple diffstat output
-    let old_lines = String::from_utf8_lossy(content1);
-    let old_lines: Vec<&str> = old_lines.lines().collect();
-    let new_lines = String::from_utf8_lossy(content2);
-    let new_lines: Vec<&str> = new_lines.lines().collect();
-
-    let changes = compute_diff(&old_lines, &new_lines);
-
     let (mut additions, mut deletions) =
-        changes.iter().filter(|x| !matches!(x, Change::Same)).fold(
-            (0usize, 0usize),
-            |(additions, deletions), change| match change {
-                Change::Insert => (additions + 1, deletions),
-                Change::Delete => (additions, deletions + 1),
-                Change::Replace => (additions + 1, deletions + 1),
-                Change::Same => unreachable!(),
-            },
-        );
+        count_lines(content1, content2, algorithm);
 
     // +3 for " | "
     let available_columns = STAT_WIDTH - (path.len() + 3);
@@ -976,14 +2604,196 @@ fn format_diffstat(path: &str, content1: &[u8], content2: &[u8]) -> String {
     }
 
     format!(
-        "{path} | {total_changes} {GREEN}{}{RED}{}{RESET}",
+        "{path} | {total_changes} {new}{}{old}{}{reset}",
         "+".repeat(additions),
         "-".repeat(deletions)
     )
 }
 
+/// Counts the added and deleted lines between two versions of a file.
+fn count_lines(
+    content1: &[u8],
+    content2: &[u8],
+    algorithm: DiffAlgorithm,
+) -> (usize, usize) {
+    let old_lines = String::from_utf8_lossy(content1);
+    let old_lines: Vec<&str> = old_lines.lines().collect();
+    let new_lines = String::from_utf8_lossy(content2);
+    let new_lines: Vec<&str> = new_lines.lines().collect();
+
+    let changes = compute_diff_with(&old_lines, &new_lines, algorithm);
+    changes
+        .iter()
+        .fold((0, 0), |(additions, deletions), change| match change {
+            Change::Same => (additions, deletions),
+            Change::Insert => (additions + 1, deletions),
+            Change::Delete => (additions, deletions + 1),
+            Change::Replace => (additions + 1, deletions + 1),
+        })
+}
+
+/// Counts the added and deleted lines of a file that may be missing on
+/// either side. Binary files have no lines, so give `None`.
+fn line_counts(
+    content1: Option<&[u8]>,
+    content2: Option<&[u8]>,
+    algorithm: DiffAlgorithm,
+) -> Option<(usize, usize)> {
+    let (content1, content2) =
+        (content1.unwrap_or(&[]), content2.unwrap_or(&[]));
+    if blob::Blob::is_binary(content1) || blob::Blob::is_binary(content2) {
+        None
+    } else {
+        Some(count_lines(content1, content2, algorithm))
+    }
+}
+
+/// Formats the added and deleted lines of a file as tab-separated numbers,
+/// or dashes for a binary file.
+fn format_numstat(path: &str, line_counts: Option<(usize, usize)>) -> String {
+    match line_counts {
+        Some((additions, deletions)) => {
+            format!("{additions}\t{deletions}\t{path}")
+        }
+        None => format!("-\t-\t{path}"),
+    }
+}
+
+/// Summarizes the number of changed files, and added and deleted lines.
+/// Nothing is shown if there are no changed files.
+fn format_shortstat(
+    files: usize,
+    additions: usize,
+    deletions: usize,
+) -> String {
+    if files == 0 {
+        return String::new();
+    }
+    let plural = |n: usize| if n == 1 { "" } else { "s" };
+
+    let mut summary = format!(" {files} file{} changed", plural(files));
+    if additions > 0 || deletions == 0 {
+        let _ =
+            write!(summary, ", {additions} insertion{}(+)", plural(additions));
+    }
+    if deletions > 0 || additions == 0 {
+        let _ =
+            write!(summary, ", {deletions} deletion{}(-)", plural(deletions));
+    }
+    summary
+}
+
+/// Shows the share of the changes in each directory, as a percentage of the
+/// changes in all files, given the path and number of changes of each file
+/// in sorted order.
+///
+/// Like git, changes in a subdirectory count towards its parent, unless the
+/// subdirectory is listed itself. Directories with less than 3% of the
+/// changes are left out, as are directories whose changes all come from a
+/// single subdirectory.
+fn format_dirstat(damage: &[(String, usize)]) -> String {
+    let total = damage.iter().map(|(_, changes)| changes).sum::<usize>();
+    let mut lines = vec![];
+    if total > 0 {
+        gather_dirstat(&mut &damage[..], "", total, &mut lines);
+    }
+    lines.join("\n")
+}
+
+/// Consumes the files under the directory `base` from the front of `files`,
+/// listing the directories under it that pass the threshold in `lines`.
+/// Returns the changes under `base` that were not listed.
+fn gather_dirstat(
+    files: &mut &[(String, usize)],
+    base: &str,
+    total: usize,
+    lines: &mut Vec<String>,
+) -> usize {
+    let mut changes = 0;
+    // A file counts as two sources, so a directory with any files of its own
+    // is always a candidate
+    let mut sources = 0;
+    while let Some((path, file_changes)) = files.first() {
+        let Some(rest) = path.strip_prefix(base) else {
+            break;
+        };
+        if let Some(slash) = rest.find('/') {
+            let dir = &path[..=base.len() + slash];
+            changes += gather_dirstat(files, dir, total, lines);
+            sources += 1;
+        } else {
+            changes += file_changes;
+            *files = &files[1..];
+            sources += 2;
+        }
+    }
+
+    if base.is_empty() || sources == 1 || changes == 0 {
+        return changes;
+    }
+    let permille = changes * 1000 / total;
+    if permille < DIRSTAT_THRESHOLD {
+        return changes;
+    }
+    lines.push(format!("{:4}.{}% {base}", permille / 10, permille % 10));
+    0
+}
+
+/// Reports whitespace errors in the lines added to a file, along with their
+/// line numbers. Binary files are not checked.
+fn format_check(
+    path: &str,
+    content1: Option<&[u8]>,
+    content2: Option<&[u8]>,
+    algorithm: DiffAlgorithm,
+) -> String {
+    let (content1, content2) =
+        (content1.unwrap_or(&[]), content2.unwrap_or(&[]));
+    if blob::Blob::is_binary(content1) || blob::Blob::is_binary(content2) {
+        return String::new();
+    }
+
+    let old_str = String::from_utf8_lossy(content1);
+    let new_str = String::from_utf8_lossy(content2);
+    let old_lines: Vec<&str> = old_str.lines().collect();
+    let new_lines: Vec<&str> = new_str.lines().collect();
+
+    let mut output = vec![];
+    let mut line_num = 0;
+    for change in compute_diff_with(&old_lines, &new_lines, algorithm) {
+        if matches!(change, Change::Delete) {
+            continue;
+        }
+        let line = new_lines[line_num];
+        line_num += 1;
+        if matches!(change, Change::Same) {
+            continue;
+        }
+        if let Some(errors) = whitespace_errors(line) {
+            output.push(format!("{path}:{line_num}: {errors}.\n+{line}"));
+        }
+    }
+    output.join("\n")
+}
+
+/// Describes the whitespace errors in a line, if it has any.
+fn whitespace_errors(line: &str) -> Option<String> {
+    let indent =
+        &line[..line.len() - line.trim_start_matches([' ', '\t']).len()];
+    let errors = [
+        (line.ends_with([' ', '\t']), "trailing whitespace"),
+        (indent.contains(" \t"), "space before tab in indent"),
+    ]
+    .into_iter()
+    .filter_map(|(found, error)| found.then_some(error))
+    .collect::<Vec<_>>();
+
+    (!errors.is_empty()).then(|| errors.join(", "))
+}
+
 /// Make parser for the diff command
 #[must_use]
+#[allow(clippy::too_many_lines)]
 pub fn make_parser() -> ArgumentParser {
     let mut parser = ArgumentParser::new(
         "Show changes between commits, commit and working tree, etc.",
@@ -999,11 +2809,71 @@ pub fn make_parser() -> ArgumentParser {
         .optional()
         .add_help("Show only the name(s) and status of each changed file.");
 
+    parser
+        .add_argument("json", ArgumentType::Boolean)
+        .optional()
+        .add_help("Print names and statuses as JSON, with --name-status");
+
     parser
         .add_argument("stat", ArgumentType::Boolean)
         .optional()
         .add_help("Generate a diffstat, instead of patch, using 80 columns.");
 
+    parser
+        .add_argument("numstat", ArgumentType::Boolean)
+        .optional()
+        .add_help(
+            "Show the number of added and deleted lines of each file, \
+            separated by tabs",
+        );
+
+    parser
+        .add_argument("shortstat", ArgumentType::Boolean)
+        .optional()
+        .add_help(
+            "Show only the total number of changed files, and added and \
+            deleted lines",
+        );
+
+    parser
+        .add_argument("dirstat", ArgumentType::Boolean)
+        .optional()
+        .add_help(
+            "Show the share of changed lines in each directory, leaving out \
+            directories with less than 3% of the changes",
+        );
+
+    parser
+        .add_argument("check", ArgumentType::Boolean)
+        .optional()
+        .add_help(
+            "Warn about added lines with trailing whitespace or a space \
+            before a tab in their indent, and exit with 2 if there are any",
+        );
+
+    parser
+        .add_argument("exit-code", ArgumentType::Boolean)
+        .optional()
+        .add_help("Exit with 1 if there are differences, and 0 otherwise");
+
+    parser
+        .add_argument("quiet", ArgumentType::Boolean)
+        .optional()
+        .short('q')
+        .add_help("Disable all output, implies --exit-code");
+
+    parser
+        .add_argument("cc", ArgumentType::Boolean)
+        .optional()
+        .add_help(
+            "Show a merge commit as a combined diff against all its parents",
+        );
+
+    parser
+        .add_argument("cached", ArgumentType::Boolean)
+        .optional()
+        .add_help("Compare the index against a tree, or HEAD by default");
+
     parser
         .add_argument("diff-filter", ArgumentType::String)
         .optional()
@@ -1013,7 +2883,8 @@ pub fn make_parser() -> ArgumentParser {
         .add_argument("files", ArgumentType::String)
         .short('f')
         .optional()
-        .add_help("Comma-separated list of files to diff");
+        .multiple()
+        .add_help("Files to diff");
 
     parser
         .add_argument("n-context-lines", ArgumentType::Integer)
@@ -1039,6 +2910,39 @@ pub fn make_parser() -> ArgumentParser {
         .optional()
         .add_help("Do not show any source or destination prefix");
 
+    parser
+        .add_argument("color", ArgumentType::String)
+        .optional()
+        .choices(&["auto", "always", "never"])
+        .add_help(
+            "Color the output: always, never, or auto to color only when \
+            writing to a terminal and NO_COLOR is not set. Defaults to \
+            color.diff or color.ui, or else auto",
+        );
+
+    parser
+        .add_argument("word-diff", ArgumentType::String)
+        .optional()
+        .choices(&["none", "plain", "color"])
+        .default("none")
+        .add_help("Show changed words instead of whole changed lines");
+
+    parser
+        .add_argument("diff-algorithm", ArgumentType::String)
+        .optional()
+        .choices(&["myers", "patience", "histogram"])
+        .default("myers")
+        .add_help("Choose the algorithm used to match up lines");
+
+    parser
+        .add_argument("jobs", ArgumentType::Integer)
+        .short('j')
+        .optional()
+        .add_help(
+            "Number of threads to diff files on, overriding diff.jobs. \
+            0 uses one per CPU, which is the default",
+        );
+
     parser
         .add_argument("tree1", ArgumentType::String)
         .required()
@@ -1557,6 +3461,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::needless_continue)]
     fn test_compute_diff_large_mixed_changes() {
         let size = 10_000;
         let mut rng = Rng::with(0xdead_beef, 0xdead_feed, 0xdead_bea7);
@@ -1660,6 +3565,135 @@ mod tests {
         }
     }
 
+    /// Check that replaying `changes` turns `old_lines` into `new_lines`.
+    fn assert_replays(
+        old_lines: &[&str],
+        new_lines: &[&str],
+        changes: &[Change],
+    ) {
+        let (mut i, mut j) = (0, 0);
+        for change in changes {
+            match change {
+                Change::Same => {
+                    assert_eq!(old_lines[i], new_lines[j]);
+                    i += 1;
+                    j += 1;
+                }
+                Change::Replace => {
+                    i += 1;
+                    j += 1;
+                }
+                Change::Delete => i += 1,
+                Change::Insert => j += 1,
+            }
+        }
+        assert_eq!((i, j), (old_lines.len(), new_lines.len()));
+    }
+
+    #[test]
+    fn test_compute_diff_is_minimal() {
+        fn lcs_len(a: &[&str], b: &[&str]) -> usize {
+            let mut table = vec![vec![0; b.len() + 1]; a.len() + 1];
+            for i in (0..a.len()).rev() {
+                for j in (0..b.len()).rev() {
+                    table[i][j] = if a[i] == b[j] {
+                        table[i + 1][j + 1] + 1
+                    } else {
+                        table[i + 1][j].max(table[i][j + 1])
+                    };
+                }
+            }
+            table[0][0]
+        }
+
+        let mut rng = Rng::with(0xdead_beef, 0xdead_feed, 0xdead_bea7);
+        let alphabet = ["a", "b", "c"];
+        for _ in 0..2000 {
+            let old_lines: Vec<_> = (0..rng.gen_range(0..12))
+                .map(|_| alphabet[rng.gen_range(0..3)])
+                .collect();
+            let new_lines: Vec<_> = (0..rng.gen_range(0..12))
+                .map(|_| alphabet[rng.gen_range(0..3)])
+                .collect();
+            let changes = compute_diff(&old_lines, &new_lines);
+
+            assert_replays(&old_lines, &new_lines, &changes);
+
+            let same = changes.iter().filter(|c| **c == Change::Same).count();
+            assert_eq!(
+                same,
+                lcs_len(&old_lines, &new_lines),
+                "{old_lines:?} vs {new_lines:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_compute_diff_with_other_algorithms() {
+        let mut rng = Rng::with(0xdead_beef, 0xdead_feed, 0xdead_bea7);
+        let alphabet = ["a", "b", "c", "d", "e", "f"];
+        for _ in 0..2000 {
+            let old_lines: Vec<_> = (0..rng.gen_range(0..16))
+                .map(|_| alphabet[rng.gen_range(0..6)])
+                .collect();
+            let new_lines: Vec<_> = (0..rng.gen_range(0..16))
+                .map(|_| alphabet[rng.gen_range(0..6)])
+                .collect();
+            for algorithm in [DiffAlgorithm::Patience, DiffAlgorithm::Histogram]
+            {
+                let changes =
+                    compute_diff_with(&old_lines, &new_lines, algorithm);
+                assert_replays(&old_lines, &new_lines, &changes);
+            }
+        }
+    }
+
+    #[test]
+    fn test_compute_diff_with_moved_function() {
+        let old_lines = ["a()", "{", "A", "}", "b()", "{", "B", "}", "main"];
+        let new_lines = ["b()", "{", "B", "}", "a()", "{", "A", "}", "main"];
+        // A function is moved as a whole instead of matching up braces
+        let expected = [
+            [Change::Delete; 4].as_slice(),
+            &[Change::Same; 3],
+            &[Change::Insert; 4],
+            &[Change::Same; 2],
+        ]
+        .concat();
+        for algorithm in [DiffAlgorithm::Patience, DiffAlgorithm::Histogram] {
+            let changes = compute_diff_with(&old_lines, &new_lines, algorithm);
+            assert_eq!(changes, expected, "{algorithm:?}");
+        }
+    }
+
+    #[test]
+    fn test_unique_anchors() {
+        let old_lines = ["a", "x", "b", "c", "x", "d"];
+        let new_lines = ["c", "a", "b", "d", "e"];
+        // "c" is out of order, and "x" is not unique
+        assert_eq!(
+            unique_anchors(&old_lines, &new_lines),
+            [(0, 1), (2, 2), (5, 3)]
+        );
+    }
+
+    #[test]
+    fn test_compute_diff_with_moved_repeated_lines() {
+        let old_lines = ["}", "fn a() {", "}", "fn b() {", "}"];
+        let new_lines = ["}", "fn b() {", "}"];
+        let changes = compute_diff(&old_lines, &new_lines);
+        assert_eq!(
+            changes,
+            [
+                Change::Same,
+                Change::Delete,
+                Change::Delete,
+                Change::Same,
+                Change::Same,
+            ]
+        );
+    }
+
     #[test]
     fn test_generate_hunks_simple_change() {
         let old_lines = ["Line 1", "Line 2", "Line 3"];
@@ -1672,8 +3706,15 @@ mod tests {
         assert_eq!(hunk.old_count, 3);
         assert_eq!(hunk.new_start, 1);
         assert_eq!(hunk.new_count, 3);
-        assert!(hunk.content.contains("-Line 2"));
-        assert!(hunk.content.contains("+Changed Line 2"));
+        assert_eq!(
+            hunk.lines,
+            [
+                Line::new(LineOrigin::Context, "Line 1"),
+                Line::new(LineOrigin::Deletion, "Line 2"),
+                Line::new(LineOrigin::Addition, "Changed Line 2"),
+                Line::new(LineOrigin::Context, "Line 3"),
+            ]
+        );
     }
 
     #[test]
@@ -1681,16 +3722,8 @@ mod tests {
         let path = "test.txt";
         let content1 = b"Line 1\nLine 2\nLine 3\n";
         let content2 = b"Line 1\nChanged Line 2\nLine 3\n";
-        let hunk_context_lines = 3;
-        let diff_output = format_diff(
-            path,
-            content1,
-            content2,
-            hunk_context_lines,
-            "a/",
-            "b/",
-            false,
-        );
+        let diff_output =
+            format_diff(path, content1, content2, &DiffOpts::default());
         assert!(diff_output.contains("diff --mini-git a/test.txt b/test.txt"));
         assert!(diff_output.contains("--- a/"));
         assert!(diff_output.contains("+++ b/"));
@@ -1733,7 +3766,8 @@ mod tests {
     fn test_format_addition() {
         let path = "new_file.txt";
         let content = b"New content\nLine 2\n";
-        let output = format_addition(path, content, "a/", "b/", false);
+        let output =
+            format_addition(path, content, "a/", "b/", false, Palette::COLOR);
         assert!(output.contains("diff --mini-git a/dev/null b/new_file.txt"),);
         assert!(output.contains("new file"));
         assert!(output.contains("+++ b/"));
@@ -1745,7 +3779,8 @@ mod tests {
     fn test_format_deletion() {
         let path = "old_file.txt";
         let content = b"Old content\nLine 2\n";
-        let output = format_deletion(path, content, "a/", "b/", false);
+        let output =
+            format_deletion(path, content, "a/", "b/", false, Palette::COLOR);
         assert!(output.contains("diff --mini-git a/old_file.txt b/dev/null"),);
         assert!(output.contains("deleted file"));
         assert!(output.contains("--- a/"));
@@ -1753,6 +3788,41 @@ mod tests {
         assert!(output.contains("-Line 2"));
     }
 
+    #[test]
+    fn test_file_diff() {
+        let diff = diff_blobs("new.txt", None, Some(b"one\ntwo\n"), 3);
+        assert_eq!(diff.status, Delta::Added);
+        assert_eq!(diff.old_path, None);
+        assert_eq!(diff.new_path.as_deref(), Some("new.txt"));
+        let hunk = &diff.hunks[0];
+        assert_eq!(
+            (
+                hunk.old_start,
+                hunk.old_count,
+                hunk.new_start,
+                hunk.new_count
+            ),
+            (0, 0, 1, 2)
+        );
+        assert!(hunk
+            .lines
+            .iter()
+            .all(|line| line.origin == LineOrigin::Addition));
+
+        let diff = diff_blobs("old.txt", Some(b"one\n"), None, 3);
+        assert_eq!(diff.status, Delta::Deleted);
+        assert_eq!(diff.hunks[0].lines[0].prefix(), '-');
+
+        // Empty files have no lines, and binary files are not diffed
+        assert!(diff_blobs("empty", None, Some(b""), 3).hunks.is_empty());
+        let diff = diff_blobs("bin", Some(b"\0\0a"), Some(b"\0\0b"), 3);
+        assert!(diff.binary && diff.hunks.is_empty());
+        assert_eq!(
+            format_file_diff(&diff, Palette::PLAIN),
+            "diff --mini-git a/bin b/bin\nBinary files differ\n"
+        );
+    }
+
     #[test]
     fn test_generate_hunks_with_multiple_changes() {
         let old_lines = ["Line 1", "Line 2", "Line 3", "Line 4"];
@@ -1761,10 +3831,9 @@ mod tests {
         let hunks = generate_hunks(&old_lines, &new_lines, &changes, 2);
         assert_eq!(hunks.len(), 1);
         let hunk = &hunks[0];
-        assert!(hunk.content.contains("-Line 2"));
-        assert!(hunk.content.contains("+Changed Line 2"));
-        assert!(hunk.content.contains("-Line 4"));
-        assert!(hunk.content.contains("+New Line 4"));
+        let text = render_lines(hunk, Palette::PLAIN);
+        assert!(text.contains("-Line 2\n+Changed Line 2\n"));
+        assert!(text.contains("-Line 4\n+New Line 4\n"));
     }
 
     #[test]
@@ -1772,7 +3841,7 @@ mod tests {
         let path = "unchanged.txt";
         let content = b"Line 1\nLine 2\n";
         let diff_output =
-            format_diff(path, content, content, 3, "a/", "b/", false);
+            format_diff(path, content, content, &DiffOpts::default());
         // Since there are no changes, diff output should be minimal
         assert!(diff_output
             .contains("diff --mini-git a/unchanged.txt b/unchanged.txt"));
@@ -1781,4 +3850,155 @@ mod tests {
         // No hunks should be present
         assert!(!diff_output.contains("@@"));
     }
+
+    #[test]
+    fn test_format_diff_word_diff_plain() {
+        let content1 = b"one two three\nkeep me\nalpha beta\ngamma\n";
+        let content2 = b"one 2 three four\nkeep me\nalpha  beta\ndelta\nnew\n";
+        let opts = DiffOpts {
+            word_diff: Some(WordDiff::Plain),
+            ..Default::default()
+        };
+        let diff_output = format_diff("a.txt", content1, content2, &opts);
+        assert!(diff_output.contains(
+            "one [-two-]{+2+} three {+four+}\nkeep me\nalpha  beta\n\
+            [-gamma-]{+delta+}\n{+new+}\n"
+        ));
+    }
+
+    #[test]
+    fn test_format_diff_word_diff_color() {
+        let content1 = b"a b c\nx y\n";
+        let content2 = b"a c\nx z\n";
+        let opts = DiffOpts {
+            word_diff: Some(WordDiff::Color),
+            ..Default::default()
+        };
+        let diff_output = format_diff("a.txt", content1, content2, &opts);
+        let Palette {
+            reset, old, new, ..
+        } = Palette::COLOR;
+        assert!(diff_output.contains(&format!(
+            "a{old}b{reset} c\nx {old}y{reset}{new}z{reset}\n"
+        )));
+    }
+
+    #[test]
+    fn test_whitespace_errors() {
+        assert_eq!(whitespace_errors("\tclean line"), None);
+        assert_eq!(whitespace_errors("tab\tinside"), None);
+        assert_eq!(
+            whitespace_errors("trailing \t").as_deref(),
+            Some("trailing whitespace")
+        );
+        assert_eq!(
+            whitespace_errors("  \tindent").as_deref(),
+            Some("space before tab in indent")
+        );
+        assert_eq!(
+            whitespace_errors(" \t ").as_deref(),
+            Some("trailing whitespace, space before tab in indent")
+        );
+    }
+
+    #[test]
+    fn test_format_check() {
+        let content1 = b"a \nb\n";
+        let content2 = b"a \nb\nc  \n \td\ne\n";
+        let output = format_check(
+            "f.txt",
+            Some(content1),
+            Some(content2),
+            DiffAlgorithm::Myers,
+        );
+        // Existing lines are not checked
+        assert_eq!(
+            output,
+            "f.txt:3: trailing whitespace.\n+c  \n\
+            f.txt:4: space before tab in indent.\n+ \td"
+        );
+    }
+
+    #[test]
+    fn test_format_combined_diff() {
+        let base = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n";
+        let ours = base.replace("\n2\n", "\nTWO\n").replace("8\n", "eight\n");
+        let theirs = base.replace("\n2\n", "\ntwo\n").replace("11", "eleven");
+        let merged = "1\nTwo!\n3\n4\n5\n6\n7\neight\n9\n10\neleven\n12\nnew\n";
+
+        let output = format_combined_diff(
+            "f",
+            merged.as_bytes(),
+            &[ours.into_bytes(), theirs.into_bytes()],
+            &DiffOpts {
+                palette: Palette::PLAIN,
+                ..DiffOpts::default()
+            },
+        );
+        assert_eq!(
+            output,
+            "diff --cc f\nindex ....\n--- a/f\n+++ b/f\n\
+            @@@ -1,12 -1,12 +1,13 @@@\n  1\n- TWO\n -two\n++Two!\n  3\n  4\n\
+            \x20 5\n  6\n  7\n -8\n +eight\n  9\n  10\n- 11\n+ eleven\n\
+            \x20 12\n++new"
+        );
+    }
+
+    #[test]
+    fn test_combined_diff_skips_uninteresting_hunks() {
+        let base = (1..=30).fold(String::new(), |mut acc, i| {
+            let _ = writeln!(acc, "{i}");
+            acc
+        });
+        let ours = base.replace("\n2\n", "\nours\n");
+        let theirs = base.replace("25", "theirs");
+        // Both changes are taken as is, and only line 14 is new
+        let merged = ours.replace("25", "theirs").replace("14", "merged");
+
+        let output = format_combined_diff(
+            "f",
+            merged.as_bytes(),
+            &[ours.into_bytes(), theirs.into_bytes()],
+            &DiffOpts::default(),
+        );
+        assert_eq!(output.matches("@@@ ").count(), 1);
+        assert!(output.contains("@@@ -11,7 -11,7 +11,7 @@@"));
+        assert!(output.contains("-14"));
+        assert!(output.contains("++merged"));
+        assert!(!output.contains("ours"));
+        assert!(!output.contains("theirs"));
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn test_write_temp_file_skips_planted_files() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir();
+        let victim =
+            dir.join(format!("mini_git_victim_{}", std::process::id()));
+        std::fs::write(&victim, "keep").unwrap();
+
+        // Plant a symlink at the next few names
+        let next = TEMP_COUNTER.load(Ordering::Relaxed);
+        let planted = (next..next + 3)
+            .map(|n| {
+                let name = format!("mini_git_{}_{n}_x.txt", std::process::id());
+                let link = dir.join(name);
+                std::os::unix::fs::symlink(&victim, &link).unwrap();
+                link
+            })
+            .collect::<Vec<_>>();
+
+        let tmp = write_temp_file("a/x.txt", b"blob").unwrap();
+        assert!(!planted.contains(&tmp));
+        assert_eq!(std::fs::read(&tmp).unwrap(), b"blob");
+        let mode = std::fs::metadata(&tmp).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(std::fs::read_to_string(&victim).unwrap(), "keep");
+
+        for path in planted.iter().chain([&tmp, &victim]) {
+            let _ = std::fs::remove_file(path);
+        }
+    }
 }
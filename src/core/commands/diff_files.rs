@@ -0,0 +1,79 @@
+use crate::core::commands::diff_tree::{
+    add_raw_format_arguments, format_raw_changes, RawChange, RawOpts,
+};
+use crate::core::index::{GitIndex, WorktreeState};
+use crate::core::{resolve_repository_context, Error};
+use crate::utils::argparse::{ArgumentParser, Namespace};
+
+/// Compare the files in the working tree and the index.
+/// This handles the subcommand
+///
+/// ```bash
+/// mini_git diff-files [--name-only] [--name-status] [-z]
+/// ```
+///
+/// Each changed path is shown in the raw format of `diff-tree`. Like git,
+/// the working tree side of a modified file has an all-zero object name, as
+/// it is not hashed. Mode changes are not detected, so a modified file keeps
+/// its mode from the index. A conflicted path is shown once, with status
+/// `U`, and untracked files are not shown. Files that the hook named by
+/// `core.fsmonitor` reports unchanged are not looked at.
+///
+/// # Errors
+///
+/// If the index is malformed, or a changed file cannot be read.
+/// An [`Error`] describing the failure is returned.
+#[allow(clippy::module_name_repetitions)]
+pub fn diff_files(args: &Namespace) -> Result<String, Error> {
+    let repo = resolve_repository_context()?.repo;
+    repo.require_worktree()?;
+    let mut index = GitIndex::read(&repo)?;
+    index.refresh_fsmonitor(&repo)?;
+    let opts = RawOpts::new(args);
+    let null = repo.object_format().null_id();
+
+    let mut changes: Vec<RawChange> = vec![];
+    for entry in index.entries() {
+        if entry.stage() != 0 {
+            if changes.last().is_none_or(|last| last.path != entry.path) {
+                changes.push(RawChange {
+                    old_mode: 0,
+                    new_mode: 0,
+                    old_sha: null.clone(),
+                    new_sha: null.clone(),
+                    status: 'U',
+                    path: entry.path.clone(),
+                });
+            }
+            continue;
+        }
+
+        let new_mode = match index.worktree_state(&repo, entry)? {
+            WorktreeState::Unchanged => continue,
+            WorktreeState::Modified => entry.mode,
+            WorktreeState::Deleted => 0,
+        };
+        changes.push(RawChange {
+            old_mode: entry.mode,
+            new_mode,
+            old_sha: entry.sha.clone(),
+            new_sha: null.clone(),
+            status: if new_mode == 0 { 'D' } else { 'M' },
+            path: entry.path.clone(),
+        });
+    }
+
+    Ok(format_raw_changes(&changes, &opts))
+}
+
+/// Make `diff-files` parser
+#[must_use]
+pub fn make_parser() -> ArgumentParser {
+    let mut parser = ArgumentParser::new(
+        "Compare the files in the working tree and the index",
+    );
+
+    add_raw_format_arguments(&mut parser);
+
+    parser
+}
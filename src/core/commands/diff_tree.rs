@@ -0,0 +1,241 @@
+use crate::core::commands::log::commit_trees;
+use crate::core::objects::tree::{self, TreeDelta, TreeEntry};
+use crate::core::objects::{self, GitObject};
+use crate::core::{
+    resolve_repository_context, Error, GitRepository, RepositoryContext,
+};
+use crate::utils::argparse::{ArgumentParser, ArgumentType, Namespace};
+
+/// Compare the content and mode of blobs found via two tree objects.
+/// This handles the subcommand
+///
+/// ```bash
+/// mini_git diff-tree [-r] [--name-only] [--name-status] [-z] tree1 [tree2]
+/// ```
+///
+/// Each changed path is shown in git's raw format,
+/// `:old_mode new_mode old_sha new_sha status\tpath`, where the status is one
+/// of `A`dded, `D`eleted, `M`odified or `T`ype changed. A side where the path
+/// is missing has mode `000000` and an all-zero object name.
+///
+/// Without `-r`, only the entries of the top-level trees are compared, so a
+/// changed directory is shown as a single modified tree. With a single
+/// commit, its id is shown first, followed by its changes against its first
+/// parent. A root commit has no changes to show.
+///
+/// # Errors
+///
+/// If the trees cannot be resolved, or their objects cannot be read.
+/// An [`Error`] describing the failure is returned.
+#[allow(clippy::module_name_repetitions)]
+pub fn diff_tree(args: &Namespace) -> Result<String, Error> {
+    let RepositoryContext { repo, .. } = resolve_repository_context()?;
+    let opts = RawOpts::new(args);
+    let recursive = args.get("recursive").is_some();
+
+    let tree1 = &args["tree1"];
+    let tree2 = args.get("tree2").filter(|s| *s != "*");
+
+    let mut output = String::new();
+    let (old_tree, new_tree) = if let Some(tree2) = tree2 {
+        let find_tree = |name| {
+            objects::find_object(&repo, name, Some("tree"), true)
+                .map(|oid| oid.to_string())
+        };
+        (Some(find_tree(tree1)?), Some(find_tree(tree2)?))
+    } else {
+        let sha = objects::find_object(&repo, tree1, Some("commit"), true)?;
+        let GitObject::Commit(commit) = objects::read_object(&repo, &sha)?
+        else {
+            return Err(format!("{tree1} is not a commit").into());
+        };
+        let parents = commit.parents()?;
+        if parents.is_empty() {
+            return Ok(output);
+        }
+        output.push_str(&sha.to_string());
+        output.push(opts.terminator);
+        commit_trees(&repo, &commit, &parents)?
+    };
+
+    let changes = tree_raw_changes(
+        &repo,
+        old_tree.as_deref(),
+        new_tree.as_deref(),
+        recursive,
+    )?;
+    output.push_str(&format_raw_changes(&changes, &opts));
+    Ok(output)
+}
+
+/// A changed path, as shown in the raw diff format. A side where the path is
+/// missing has mode 0 and the null object name of the repository, all
+/// zeros, as its object name.
+pub(super) struct RawChange {
+    pub(super) old_mode: u32,
+    pub(super) new_mode: u32,
+    pub(super) old_sha: String,
+    pub(super) new_sha: String,
+    pub(super) status: char,
+    pub(super) path: String,
+}
+
+impl RawChange {
+    /// The change of `delta`, where a missing side is named `null`.
+    fn from_delta(delta: TreeDelta, null: &str) -> Self {
+        let side = |entry: Option<TreeEntry>| {
+            entry.map_or_else(
+                || (0, null.to_owned()),
+                |entry| (entry.mode, entry.oid.to_string()),
+            )
+        };
+        let status = delta.delta().status();
+        let (old_mode, old_sha) = side(delta.old);
+        let (new_mode, new_sha) = side(delta.new);
+        Self {
+            old_mode,
+            new_mode,
+            old_sha,
+            new_sha,
+            status,
+            path: delta.path,
+        }
+    }
+}
+
+/// How raw changes are formatted
+pub(super) struct RawOpts {
+    name_only: bool,
+    name_status: bool,
+    pub(super) terminator: char,
+}
+
+impl RawOpts {
+    /// Reads `--name-only`, `--name-status` and `-z` from `args`.
+    pub(super) fn new(args: &Namespace) -> Self {
+        Self {
+            name_only: args.get("name-only").is_some(),
+            name_status: args.get("name-status").is_some(),
+            terminator: if args.get("nul-terminated").is_some() {
+                '\0'
+            } else {
+                '\n'
+            },
+        }
+    }
+}
+
+/// Formats changes one per line in the raw format, or as just their paths
+/// with `--name-only`, or statuses and paths with `--name-status`.
+///
+/// With `-z`, the path of each change is terminated by NUL, and so is the
+/// rest of the change in the raw and `--name-status` formats, in place of
+/// the tab.
+pub(super) fn format_raw_changes(
+    changes: &[RawChange],
+    opts: &RawOpts,
+) -> String {
+    let z = opts.terminator == '\0';
+    let sep = if z { '\0' } else { '\t' };
+    let lines = changes.iter().map(|change| {
+        let RawChange { status, path, .. } = change;
+        if opts.name_only {
+            path.clone()
+        } else if opts.name_status {
+            format!("{status}{sep}{path}")
+        } else {
+            format!(
+                ":{:06o} {:06o} {} {} {status}{sep}{path}",
+                change.old_mode,
+                change.new_mode,
+                change.old_sha,
+                change.new_sha
+            )
+        }
+    });
+
+    if z {
+        lines.fold(String::new(), |mut acc, line| {
+            acc.push_str(&line);
+            acc.push('\0');
+            acc
+        })
+    } else {
+        lines.collect::<Vec<_>>().join("\n")
+    }
+}
+
+/// Lists the paths that differ between two trees, in path order. A `None`
+/// tree is treated as the empty tree.
+///
+/// Without `recursive`, only the entries of the top-level trees are
+/// compared.
+fn tree_raw_changes(
+    repo: &GitRepository,
+    old_tree: Option<&str>,
+    new_tree: Option<&str>,
+    recursive: bool,
+) -> Result<Vec<RawChange>, Error> {
+    let old_tree = old_tree.map(str::parse).transpose()?;
+    let new_tree = new_tree.map(str::parse).transpose()?;
+    let deltas = if recursive {
+        tree::diff_trees(repo, old_tree.as_ref(), new_tree.as_ref())?
+    } else {
+        tree::diff_trees_shallow(repo, old_tree.as_ref(), new_tree.as_ref())?
+    };
+
+    let null = repo.object_format().null_id();
+    Ok(deltas
+        .into_iter()
+        .map(|delta| RawChange::from_delta(delta, &null))
+        .collect())
+}
+
+/// Adds the `--name-only`, `--name-status` and `-z` options that select the
+/// output format of raw changes.
+pub(super) fn add_raw_format_arguments(parser: &mut ArgumentParser) {
+    parser
+        .add_argument("name-only", ArgumentType::Boolean)
+        .optional()
+        .add_help("Show only the names of changed files");
+
+    parser
+        .add_argument("name-status", ArgumentType::Boolean)
+        .optional()
+        .add_help("Show only the names and statuses of changed files");
+
+    parser
+        .add_argument("nul-terminated", ArgumentType::Boolean)
+        .optional()
+        .short('z')
+        .add_help("Terminate paths with NUL instead of a newline");
+}
+
+/// Make `diff-tree` parser
+#[must_use]
+pub fn make_parser() -> ArgumentParser {
+    let mut parser = ArgumentParser::new(
+        "Compare the content and mode of blobs found via two tree objects",
+    );
+
+    add_raw_format_arguments(&mut parser);
+
+    parser
+        .add_argument("recursive", ArgumentType::Boolean)
+        .optional()
+        .short('r')
+        .add_help("Recurse into sub-trees");
+
+    parser
+        .add_argument("tree1", ArgumentType::String)
+        .required()
+        .add_help("A tree-ish, or a commit to compare with its first parent");
+
+    parser
+        .add_argument("tree2", ArgumentType::String)
+        .required()
+        .default("*") // * is not a valid branch name
+        .add_help("A tree-ish to compare with tree1");
+
+    parser
+}
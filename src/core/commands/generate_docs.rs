@@ -0,0 +1,72 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use crate::core::Error;
+use crate::utils::argparse::{
+    ArgumentParser, ArgumentType, DocFormat, Namespace,
+};
+
+/// The name the pages are named after
+const PROGRAM: &str = "mini_git";
+
+/// Write the reference documentation of `mini_git` and its commands.
+/// This handles the subcommand
+///
+/// ```bash
+/// mini_git generate-docs [--format FORMAT] directory
+/// ```
+///
+/// A page is written into `directory` for `mini_git` and for each of its
+/// commands, made from their parsers so that it is always in sync with the
+/// arguments they take. Pages are Markdown, or man pages with
+/// `--format man`. The paths of the pages written are shown.
+///
+/// The command is hidden from the help, as it is meant for maintainers.
+///
+/// # Errors
+///
+/// If the directory or the pages cannot be written.
+#[allow(clippy::module_name_repetitions)]
+pub fn generate_docs(args: &Namespace) -> Result<String, Error> {
+    let format = match args["format"].as_str() {
+        "man" => DocFormat::Man,
+        _ => DocFormat::Markdown,
+    };
+    let dir = Path::new(&args["directory"]);
+    fs::create_dir_all(dir).map_err(|e| {
+        Error::io(format!("Failed to create {}", dir.display()), e)
+    })?;
+
+    let mut parser = super::make_parser();
+    parser.compile();
+    let mut written = String::new();
+    for (file_name, page) in parser.docs(PROGRAM, format) {
+        let path = dir.join(file_name);
+        fs::write(&path, page).map_err(|e| {
+            Error::io(format!("Failed to write {}", path.display()), e)
+        })?;
+        let _ = writeln!(written, "{}", path.display());
+    }
+    Ok(written)
+}
+
+/// Make `generate-docs` parser
+#[must_use]
+pub fn make_parser() -> ArgumentParser {
+    let mut parser = ArgumentParser::new(
+        "Write the reference documentation of mini_git and its commands",
+    );
+    parser.hidden();
+    parser
+        .add_argument("directory", ArgumentType::String)
+        .required()
+        .add_help("The directory to write the pages into");
+    parser
+        .add_argument("format", ArgumentType::String)
+        .optional()
+        .default("markdown")
+        .choices(&["man", "markdown"])
+        .add_help("The format of the pages");
+    parser
+}
@@ -1,9 +1,14 @@
 use crate::utils::argparse::{ArgumentParser, ArgumentType, Namespace};
 
 use crate::core::objects::traits::{Deserialize, KVLM};
-use crate::core::objects::{self, write_object, GitObject};
+use crate::core::objects::{
+    self, write_object, write_object_stream, GitObject,
+    DEFAULT_BIG_FILE_THRESHOLD,
+};
 use crate::core::objects::{blob::Blob, commit::Commit, tag::Tag, tree::Tree};
-use crate::core::{resolve_repository_context, RepositoryContext};
+use crate::core::{
+    resolve_repository_context, Error, GitRepository, ObjectFormat,
+};
 
 /// Computes the hash for a git object
 ///
@@ -13,36 +18,79 @@ use crate::core::{resolve_repository_context, RepositoryContext};
 /// mini_git hash-object [--type TYPE] [--write] path
 /// ```
 ///
+/// Blobs larger than `core.bigFileThreshold` are streamed from the file,
+/// rather than read into memory.
+///
 /// # Errors
 ///
 /// If file system operations fail, or if input paths are not valid.
-/// A [`String`] message describing the error is returned.
+/// An [`Error`] describing the failure is returned.
 #[allow(clippy::module_name_repetitions)]
-pub fn hash_object(args: &Namespace) -> Result<String, String> {
-    let Ok(data) = std::fs::read(&args["path"]) else {
-        return Err(format!("failed to read file at {}", args["path"]));
+pub fn hash_object(args: &Namespace) -> Result<String, Error> {
+    let path = &args["path"];
+    let obj_type = args["type"].to_lowercase();
+    let write = matches!(args.get("write"), Some(..));
+    let read_error = || format!("failed to read file at {path}");
+
+    // The threshold is read from the repository, if there is one
+    let repo = if write {
+        Some(resolve_repository_context()?.repo)
+    } else {
+        resolve_repository_context()
+            .ok()
+            .map(|context| context.repo)
+    };
+    let threshold = repo
+        .as_ref()
+        .map_or(DEFAULT_BIG_FILE_THRESHOLD, objects::big_file_threshold);
+    // Objects are named like those of the repository, if there is one
+    let format = repo
+        .as_ref()
+        .map_or(ObjectFormat::Sha1, GitRepository::object_format);
+
+    let size = std::fs::metadata(path).map_err(|_| read_error())?.len();
+    if obj_type == "blob" && size > threshold {
+        let mut file = std::fs::File::open(path).map_err(|_| read_error())?;
+        return match repo.filter(|_| write) {
+            Some(repo) => write_object_stream(&repo, "blob", size, &mut file)
+                .map(|oid| oid.to_string()),
+            None => {
+                objects::hash_object_stream("blob", size, &mut file, format)
+            }
+        };
+    }
+
+    let Ok(data) = std::fs::read(path) else {
+        return Err(read_error().into());
     };
 
-    let obj = make_object(&args["type"].to_lowercase(), &data)?;
+    let obj = make_object(&obj_type, &data, format)?;
 
-    let sha = if matches!(args.get("write"), Some(..)) {
-        let RepositoryContext { repo, .. } = resolve_repository_context()?;
-        write_object(&obj, &repo)?
+    let sha = if let Some(repo) = repo.filter(|_| write) {
+        write_object(&obj, &repo)?.to_string()
     } else {
-        let (_, mut sha) = objects::hash_object(&obj);
-        sha.hex_digest()
+        let (_, mut hash) = objects::hash_object(&obj, format);
+        let sha = hash.hex_digest();
+        objects::reject_collision(&hash, &sha)?;
+        sha
     };
 
     Ok(sha)
 }
 
-fn make_object(obj_type: &str, data: &[u8]) -> Result<GitObject, String> {
+fn make_object(
+    obj_type: &str,
+    data: &[u8],
+    format: ObjectFormat,
+) -> Result<GitObject, Error> {
     Ok(match obj_type {
         "blob" => GitObject::Blob(Blob::deserialize(data)?),
         "commit" => GitObject::Commit(Commit::deserialize(data)?),
         "tag" => GitObject::Tag(Tag::deserialize(data)?),
-        "tree" => GitObject::Tree(Tree::deserialize(data)?),
-        _ => return Err(format!("{obj_type} is not a known object type")),
+        "tree" => GitObject::Tree(Tree::deserialize_with_format(data, format)?),
+        _ => {
+            return Err(format!("{obj_type} is not a known object type").into())
+        }
     })
 }
 
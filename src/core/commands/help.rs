@@ -0,0 +1,74 @@
+use std::fmt::Write as _;
+
+use crate::core::Error;
+use crate::utils::argparse::{ArgumentParser, ArgumentType, Namespace};
+
+/// Show the help of `mini_git` or one of its commands.
+/// This handles the subcommand
+///
+/// ```bash
+/// mini_git help [-a] [command]
+/// ```
+///
+/// With `-a`, all the commands are listed with a description of each.
+///
+/// # Errors
+///
+/// If the command named is not a command of `mini_git`.
+#[allow(clippy::module_name_repetitions)]
+pub fn help(args: &Namespace) -> Result<String, Error> {
+    let mut parser = super::make_parser();
+    parser.compile();
+
+    if args.get("all").is_some() {
+        let mut list = String::from("Available commands:\n");
+        for name in parser.subcommand_names() {
+            let Some(command) = parser.subcommand_parser(name) else {
+                continue;
+            };
+            if command.is_hidden() {
+                continue;
+            }
+            let description = command.description();
+            let mut names = name.to_owned();
+            for alias in parser.subcommand_aliases(name) {
+                let _ = write!(names, ", {alias}");
+            }
+            let _ = writeln!(list, "  {names:<16} {description}");
+        }
+        return Ok(list);
+    }
+
+    let name = &args["command"];
+    if name.is_empty() {
+        return Ok(parser.help());
+    }
+    if let Some(command) = parser.subcommand_parser(name) {
+        return Ok(command.help());
+    }
+
+    let mut msg = format!("No help for '{name}', which is not a command.");
+    let similar = parser.closest_subcommands(name, 3, 3);
+    if !similar.is_empty() {
+        let _ = write!(msg, " Similar commands are: {}", similar.join(", "));
+    }
+    Err(Error::Usage(msg))
+}
+
+/// Make `help` parser
+#[must_use]
+pub fn make_parser() -> ArgumentParser {
+    let mut parser =
+        ArgumentParser::new("Show the help of mini_git or one of its commands");
+    parser
+        .add_argument("command", ArgumentType::String)
+        .required()
+        .default("")
+        .add_help("The command to show the help of");
+    parser
+        .add_argument("all", ArgumentType::Boolean)
+        .short('a')
+        .optional()
+        .add_help("List all commands with a description of each");
+    parser
+}
@@ -1,6 +1,12 @@
-use crate::core::GitRepository;
+use crate::core::advice::{self, Advice};
+use crate::core::{
+    global_config, Error, GitRepository, InitOptions, ObjectFormat,
+    DEFAULT_BRANCH,
+};
 use crate::utils::argparse::{ArgumentParser, ArgumentType, Namespace};
-use std::path::Path;
+use crate::utils::path;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
 
 const DEFAULT_PATH: &str = ".";
 
@@ -8,19 +14,41 @@ const DEFAULT_PATH: &str = ".";
 /// This handles the subcommand
 ///
 /// ```bash
-/// mini_git init [path]
+/// mini_git init [--quiet] [--bare] [--initial-branch BRANCH]
+///               [--separate-git-dir DIR] [--template TEMPLATE]
+///               [--object-format FORMAT] [path]
 /// ```
 ///
+/// With `--bare`, `path` itself becomes the git directory of a repository
+/// without a working tree. With `--separate-git-dir`, the git directory is
+/// created at `DIR`, and `path` gets a `.git` file pointing to it.
+///
+/// `HEAD` points to `BRANCH`, or if not given, to the `init.defaultBranch`
+/// of the user's global config, falling back to `main` with a hint on how
+/// to name it.
+///
+/// The files in `TEMPLATE`, or if not given, in the `init.templateDir` of the
+/// user's global config, are copied into the new git directory. An empty
+/// `TEMPLATE` copies no files.
+///
+/// `FORMAT` is the hash algorithm naming the repository's objects, `sha1` by
+/// default. A `sha256` repository is recorded with `extensions.objectFormat`.
+///
+/// Running `init` in an existing repository reinitializes it, adding missing
+/// template files without touching `HEAD` or the config. `BRANCH` is ignored
+/// with a warning, and `FORMAT` must match the repository's. With `--quiet`,
+/// only warnings are shown.
+///
 /// # Errors
 ///
 /// If file system operations fail, or if input paths are not valid.
-/// A [`String`] message describing the error is returned.
+/// An [`Error`] describing the failure is returned.
 #[allow(clippy::module_name_repetitions)]
-pub fn init(args: &Namespace) -> Result<String, String> {
+pub fn init(args: &Namespace) -> Result<String, Error> {
     let path = &args["path"];
 
     let Ok(cwd) = std::env::current_dir() else {
-        return Err("failed to get cwd".to_owned());
+        return Err("failed to get cwd".into());
     };
 
     let path = if path == DEFAULT_PATH {
@@ -34,17 +62,123 @@ pub fn init(args: &Namespace) -> Result<String, String> {
         }
     };
 
-    let repo = GitRepository::create(&path)?;
-    Ok(format!(
-        "initialized empty repository in {:?}\n",
-        repo.worktree().as_os_str()
-    ))
+    let initial_branch = args
+        .get("initial-branch")
+        .cloned()
+        .or_else(|| global_config("init", "defaultBranch"));
+    let default_branch = initial_branch.is_none();
+    let opts = InitOptions {
+        bare: args.get("bare").is_some(),
+        initial_branch,
+        separate_git_dir: args.get("separate-git-dir").map(PathBuf::from),
+        template: args
+            .get("template")
+            .cloned()
+            .or_else(|| global_config("init", "templateDir"))
+            .filter(|template| !template.is_empty())
+            .map(PathBuf::from),
+        object_format: args
+            .get("object-format")
+            .map(|format| format.parse::<ObjectFormat>())
+            .transpose()?,
+    };
+
+    let gitdir = if opts.bare {
+        Some(path.clone())
+    } else if let Some(gitdir) = &opts.separate_git_dir {
+        Some(gitdir.clone())
+    } else {
+        path::resolve_gitdir(&path)
+    };
+    let reinit = gitdir.is_some_and(|gitdir| path::is_gitdir(&gitdir));
+
+    let mut warnings = String::new();
+    if let Some(template) = opts.template.as_deref().filter(|t| !t.is_dir()) {
+        writeln!(
+            warnings,
+            "warning: templates not found in {}",
+            template.display()
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    if let Some(branch) = args.get("initial-branch").filter(|_| reinit) {
+        writeln!(
+            warnings,
+            "warning: re-init: ignored --initial-branch={branch}"
+        )
+        .map_err(|e| e.to_string())?;
+    }
+
+    let repo = GitRepository::create_with(&path, &opts)?;
+    let quiet = args.get("quiet").is_some();
+    if default_branch && !reinit && !quiet {
+        advice::advise(
+            Advice::DefaultBranchName,
+            repo.config(),
+            &format!(
+                "Using '{DEFAULT_BRANCH}' as the name for the initial branch. \
+                To use another name\nfor the initial branch of all new \
+                repositories, set init.defaultBranch\nin your global \
+                config, or give --initial-branch to name it just this once."
+            ),
+        );
+    }
+    if quiet {
+        Ok(warnings)
+    } else if reinit {
+        Ok(format!(
+            "{warnings}Reinitialized existing Git repository in {:?}\n",
+            repo.worktree().as_os_str()
+        ))
+    } else {
+        Ok(format!(
+            "{warnings}initialized empty repository in {:?}\n",
+            repo.worktree().as_os_str()
+        ))
+    }
 }
 
 /// Make `init` parser
 #[must_use]
 pub fn make_parser() -> ArgumentParser {
     let mut parser = ArgumentParser::new("Initializes a new repository");
+    parser
+        .add_argument("quiet", ArgumentType::Boolean)
+        .optional()
+        .short('q')
+        .add_help("Only print warnings");
+
+    parser
+        .add_argument("bare", ArgumentType::Boolean)
+        .optional()
+        .add_help("Create a bare repository, without a working tree");
+
+    parser
+        .add_argument("initial-branch", ArgumentType::String)
+        .optional()
+        .short('b')
+        .add_help(
+            "The name of the initial branch, overriding init.defaultBranch",
+        );
+
+    parser
+        .add_argument("separate-git-dir", ArgumentType::String)
+        .optional()
+        .add_help(
+            "Create the git directory here, with a .git file pointing to it",
+        );
+
+    parser
+        .add_argument("template", ArgumentType::String)
+        .optional()
+        .add_help("The directory of files to copy into the git directory");
+
+    parser
+        .add_argument("object-format", ArgumentType::String)
+        .optional()
+        .choices(&["sha1", "sha256"])
+        .add_help("The hash algorithm naming the repository's objects");
+
     parser
         .add_argument("path", ArgumentType::String)
         .required()
@@ -1,18 +1,56 @@
 use crate::{kvlm_msg_to_string, kvlm_val_to_string, parse_arg_as_int};
 use std::fmt::Write;
 
-use crate::core::objects::{commit::Commit, traits::KVLM};
-use crate::core::objects::{find_object, read_object, GitObject};
+use crate::core::commands::diff::{
+    changed_lines, combined_diff, diff_trees, tree_changes, DiffOpts,
+};
+use crate::core::objects::revwalk::Revwalk;
+use crate::core::objects::{
+    abbreviate_object, find_object, read_object, GitObject,
+};
+use crate::core::objects::{commit::Commit, oid::Oid, traits::KVLM};
 use crate::core::{
-    resolve_repository_context, GitRepository, RepositoryContext,
+    resolve_repository_context, Error, GitRepository, RepositoryContext,
 };
 
 use crate::utils::argparse::{ArgumentParser, ArgumentType, Namespace};
-use crate::utils::datetime::DateTime;
+use crate::utils::color::{ColorMode, Palette};
+use crate::utils::datetime::{DateFormat, DateTime};
+use crate::utils::json::Json;
+use crate::utils::regex::Regex;
+
+// Pickaxe filters, matching only commits that touch certain content
+enum Pickaxe {
+    // -S: the number of occurrences of a string changed
+    Occurrences(String),
+    // -G: an added or removed line matches a regex
+    Regex(Regex),
+}
+
+#[allow(clippy::struct_excessive_bools)]
+struct LogOpts {
+    max_commits: usize,
+    oneline: bool,
+    abbrev_commit: bool,
+    show_author: bool,
+    stat: bool,
+    patch: bool,
+    merges: bool,
+    no_merges: bool,
+    first_parent: bool,
+    json: bool,
+    pickaxe: Option<Pickaxe>,
+    date_format: DateFormat,
+    palette: Palette,
+}
 
-const RESET: &str = "\x1b[0m";
-const YELLOW: &str = "\x1b[33m";
-const CYAN: &str = "\x1b[36m";
+impl LogOpts {
+    // Whether a commit with the given parents passes the history filters
+    fn shows(&self, parents: &[Oid]) -> bool {
+        let is_merge = parents.len() > 1;
+        !(self.merges && !is_merge || self.no_merges && is_merge)
+    }
+}
 
 /// Shows the history of commit logs
 /// This handles the subcommand
@@ -21,98 +59,158 @@ const CYAN: &str = "\x1b[36m";
 /// mini_git log [options] [ --count COUNT ] [ --treeish TREEISH ]
 /// ```
 ///
+/// With `--json`, the commits are printed as a JSON array of objects with
+/// their `commit`, `parents`, `author`, `committer` and `message`, and the
+/// options that change how commits are shown are ignored.
+///
 /// # Errors
 ///
 /// If file system operations fail, or if input paths are not valid.
-/// A [`String`] message describing the error is returned.
+/// An [`Error`] describing the failure is returned.
 #[allow(clippy::module_name_repetitions)]
-pub fn log(args: &Namespace) -> Result<String, String> {
+pub fn log(args: &Namespace) -> Result<String, Error> {
     let RepositoryContext { repo, .. } = resolve_repository_context()?;
 
     let max_commits = parse_arg_as_int!(args.get("max"), usize::MAX, "max");
-    let oneline = args.get("oneline").is_some();
-    let show_author = args.get("no-author").is_none();
     let revision = &args["revision"];
 
-    _log(&repo, revision, max_commits, oneline, show_author)
+    let pickaxe = match (args.get("pickaxe"), args.get("pickaxe-regex")) {
+        (Some(_), Some(_)) => {
+            return Err(Error::Usage(
+                "-S and -G cannot be used together".to_owned(),
+            ))
+        }
+        (Some(string), None) => Some(Pickaxe::Occurrences(string.clone())),
+        (None, Some(regex)) => Some(Pickaxe::Regex(Regex::new(regex)?)),
+        (None, None) => None,
+    };
+
+    let color = match args.get("color") {
+        Some(color) => color.parse()?,
+        None => ColorMode::from_config(repo.config(), "log")?,
+    };
+
+    let opts = LogOpts {
+        max_commits,
+        oneline: args.get("oneline").is_some(),
+        abbrev_commit: args.get("abbrev-commit").is_some(),
+        show_author: args.get("no-author").is_none(),
+        stat: args.get("stat").is_some(),
+        patch: args.get("patch").is_some(),
+        merges: args.get("merges").is_some(),
+        no_merges: args.get("no-merges").is_some(),
+        first_parent: args.get("first-parent").is_some(),
+        json: args.get("json").is_some(),
+        pickaxe,
+        date_format: args["date"].parse()?,
+        palette: Palette::from_config(color, repo.config())?,
+    };
+
+    log_history(&repo, revision, &opts)
 }
 
-fn _log(
+fn log_history(
     repo: &GitRepository,
     revision: &str,
-    max_commits: usize,
-    oneline: bool,
-    show_author: bool,
-) -> Result<String, String> {
-    let mut current = find_object(repo, revision, None, true)?;
+    opts: &LogOpts,
+) -> Result<String, Error> {
+    let start = find_object(repo, revision, None, true)?;
+    let mut walk = Revwalk::new(repo);
+    walk.push(&start)?;
+    walk.first_parent(opts.first_parent);
+
     let mut output = String::new();
+    let mut commits = Vec::new();
     let mut count = 0;
 
-    while count < max_commits {
-        let object = read_object(repo, &current)?;
-
-        let commit = match &object {
-            GitObject::Blob(_) => {
-                return Err(format!(
-                    "Cannot show history for a blob (sha {current})"
-                ))
-            }
-            GitObject::Tree(_) => {
-                return Err(format!(
-                    "Cannot show history for a tree (sha {current})"
-                ))
-            }
-            GitObject::Commit(commit) => commit,
-            GitObject::Tag(tag) => {
-                let Some(object) = tag.kvlm().get_key(b"object") else {
-                    return Err(format!(
-                        "Bad tag {current} does not have an object"
-                    ));
-                };
-                current = kvlm_val_to_string!(object);
-                continue;
-            }
+    while count < opts.max_commits {
+        let Some(next) = walk.next_commit() else {
+            break;
         };
+        let (current, commit) = next?;
 
-        let mut parents = Vec::new();
-
-        // Collect all parents
-        if let Some(parent_commits) = commit.kvlm().get_key(b"parent") {
-            for parent in parent_commits {
-                parents.push(kvlm_msg_to_string!(parent));
-            }
+        let mut parents = commit.parents()?;
+        let shown = opts.shows(&parents)
+            && pickaxe_matches(repo, &commit, &parents, opts)?;
+        if opts.first_parent {
+            parents.truncate(1);
         }
 
-        output.push_str(&format_commit(
-            &current,
-            commit,
-            oneline,
-            show_author,
-        )?);
-
-        if let Some(parent) = parents.first() {
-            current.clone_from(parent);
+        if shown && opts.json {
+            commits.push(commit_json(&current, &commit, &parents));
+            count += 1;
+        } else if shown {
+            output.push_str(&format_commit(repo, &current, &commit, opts)?);
+            output
+                .push_str(&format_commit_diff(repo, &commit, &parents, opts)?);
             count += 1;
-        } else {
-            break;
         }
     }
 
+    if opts.json {
+        return Ok(Json::from(commits).to_string());
+    }
     Ok(output)
 }
 
+fn commit_json(hash: &Oid, commit: &Commit, parents: &[Oid]) -> Json {
+    let kvlm = commit.kvlm();
+    let ident = |key: &[u8]| {
+        kvlm.get_key(key)
+            .map(|ident| ident_json(&String::from_utf8_lossy(&ident[0])))
+            .into()
+    };
+    let message = kvlm
+        .get_msg()
+        .map(|msg| String::from_utf8_lossy(msg).into_owned());
+    Json::object([
+        ("commit", hash.to_string().into()),
+        ("parents", parents.iter().map(Oid::to_string).collect()),
+        ("author", ident(b"author")),
+        ("committer", ident(b"committer")),
+        ("message", message.into()),
+    ])
+}
+
+/// An author or committer, as `Name <email> timestamp timezone`, as JSON.
+fn ident_json(ident: &str) -> Json {
+    let email = ident
+        .split_once('<')
+        .and_then(|(_, rest)| rest.split_once('>'))
+        .map(|(email, _)| email);
+    let date = DateTime::from_git_timestamp(ident)
+        .map(|date| date.format(DateFormat::IsoStrict));
+    Json::object([
+        ("name", extract_name(ident).into()),
+        ("email", email.into()),
+        ("date", date.into()),
+    ])
+}
+
 fn format_commit(
-    hash: &str,
+    repo: &GitRepository,
+    hash: &Oid,
     commit: &Commit,
-    oneline: bool,
-    show_author: bool,
-) -> Result<String, String> {
+    opts: &LogOpts,
+) -> Result<String, Error> {
     let kvlm = commit.kvlm();
+    let Palette {
+        reset,
+        commit: commit_color,
+        author: author_color,
+        ..
+    } = opts.palette;
     let mut output = String::new();
-    let short_hash = &hash[..7];
 
-    if oneline {
-        write!(output, "{YELLOW}{short_hash}{RESET} ")
+    // --oneline implies --abbrev-commit
+    let hash = if opts.oneline || opts.abbrev_commit {
+        abbreviate_object(repo, &hash.to_string())?
+    } else {
+        hash.to_string()
+    };
+
+    if opts.oneline {
+        write!(output, "{commit_color}{hash}{reset} ")
             .map_err(|e| e.to_string())?;
 
         let Some(msg) = kvlm.get_msg() else {
@@ -128,15 +226,15 @@ fn format_commit(
         return Ok(output);
     }
 
-    writeln!(output, "commit {YELLOW}{hash}{RESET}")
+    writeln!(output, "commit {commit_color}{hash}{reset}")
         .map_err(|e| e.to_string())?;
 
-    if show_author {
+    if opts.show_author {
         if let Some(author) = kvlm.get_key(b"author") {
             let author = kvlm_val_to_string!(author);
             let name = extract_name(&author)
                 .expect("Author should exist for a commit");
-            writeln!(output, "Author: {CYAN}{name}{RESET}")
+            writeln!(output, "Author: {author_color}{name}{reset}")
                 .map_err(|e| e.to_string())?;
         }
     }
@@ -144,7 +242,7 @@ fn format_commit(
     if let Some(committer) = kvlm.get_key(b"committer") {
         let committer = kvlm_val_to_string!(committer);
         if let Some(date) = DateTime::from_git_timestamp(&committer) {
-            writeln!(output, "Date:   {}", date.format_git())
+            writeln!(output, "Date:   {}", date.format(opts.date_format))
                 .map_err(|e| e.to_string())?;
         } else {
             writeln!(output, "Date:   {committer}")
@@ -165,6 +263,124 @@ fn format_commit(
     Ok(output)
 }
 
+// Diffs the commit against its first parent, if a stat or patch is requested.
+// The patch for a merge commit is a combined diff against all its parents
+fn format_commit_diff(
+    repo: &GitRepository,
+    commit: &Commit,
+    parents: &[Oid],
+    opts: &LogOpts,
+) -> Result<String, Error> {
+    let mut output = String::new();
+    if !opts.stat && !opts.patch {
+        return Ok(output);
+    }
+
+    let (old_tree, new_tree) = commit_trees(repo, commit, parents)?;
+
+    let mut render = |diff_opts: &DiffOpts| -> Result<(), Error> {
+        let diff = diff_trees(
+            repo,
+            old_tree.as_deref(),
+            new_tree.as_deref(),
+            diff_opts,
+        )?;
+        if !diff.is_empty() {
+            writeln!(output, "{diff}").map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    };
+
+    if opts.stat {
+        render(&DiffOpts {
+            stat: true,
+            palette: opts.palette,
+            ..DiffOpts::default()
+        })?;
+    }
+    if opts.patch && parents.len() > 1 {
+        let parent_trees = parents
+            .iter()
+            .map(|parent| match read_object(repo, parent)? {
+                GitObject::Commit(parent) => commit_tree(&parent),
+                _ => Err(format!("Parent {parent} is not a commit").into()),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let diff = combined_diff(
+            repo,
+            new_tree.as_deref(),
+            &parent_trees,
+            &DiffOpts {
+                palette: opts.palette,
+                ..DiffOpts::default()
+            },
+        )?;
+        if !diff.is_empty() {
+            writeln!(output, "{diff}").map_err(|e| e.to_string())?;
+        }
+    } else if opts.patch {
+        render(&DiffOpts {
+            palette: opts.palette,
+            ..DiffOpts::default()
+        })?;
+    }
+
+    Ok(output)
+}
+
+// Checks the commit against the pickaxe filter, if any
+fn pickaxe_matches(
+    repo: &GitRepository,
+    commit: &Commit,
+    parents: &[Oid],
+    opts: &LogOpts,
+) -> Result<bool, Error> {
+    let Some(pickaxe) = &opts.pickaxe else {
+        return Ok(true);
+    };
+
+    let (old_tree, new_tree) = commit_trees(repo, commit, parents)?;
+    let changes = tree_changes(repo, old_tree.as_deref(), new_tree.as_deref())?;
+
+    Ok(changes.iter().any(|change| {
+        let old = String::from_utf8_lossy(change.old.as_deref().unwrap_or(&[]));
+        let new = String::from_utf8_lossy(change.new.as_deref().unwrap_or(&[]));
+        match pickaxe {
+            Pickaxe::Occurrences(needle) => {
+                old.matches(needle.as_str()).count()
+                    != new.matches(needle.as_str()).count()
+            }
+            Pickaxe::Regex(regex) => changed_lines(&old, &new)
+                .into_iter()
+                .any(|line| regex.is_match(line)),
+        }
+    }))
+}
+
+// The trees of the commit's first parent and of the commit itself
+pub(super) fn commit_trees(
+    repo: &GitRepository,
+    commit: &Commit,
+    parents: &[Oid],
+) -> Result<(Option<String>, Option<String>), Error> {
+    let new_tree = commit_tree(commit)?;
+    let old_tree = match parents.first() {
+        Some(parent) => match read_object(repo, parent)? {
+            GitObject::Commit(parent) => commit_tree(&parent)?,
+            _ => return Err(format!("Parent {parent} is not a commit").into()),
+        },
+        None => None,
+    };
+    Ok((old_tree, new_tree))
+}
+
+pub(super) fn commit_tree(commit: &Commit) -> Result<Option<String>, Error> {
+    let Some(tree) = commit.kvlm().get_key(b"tree") else {
+        return Ok(None);
+    };
+    Ok(Some(kvlm_val_to_string!(tree)))
+}
+
 fn extract_name(author_string: &str) -> Option<&str> {
     // Format is typically "Name <email@example.com> timestamp timezone"
     let end = author_string.find('<').unwrap_or(author_string.len());
@@ -185,14 +401,88 @@ pub fn make_parser() -> ArgumentParser {
         .short('n')
         .optional()
         .add_help("Limit the number of commits to output");
+    parser
+        .add_argument("json", ArgumentType::Boolean)
+        .optional()
+        .add_help("Print the commits as JSON");
     parser
         .add_argument("oneline", ArgumentType::Boolean)
         .optional()
         .add_help("Show each commit on a single line");
+    parser
+        .add_argument("abbrev-commit", ArgumentType::Boolean)
+        .optional()
+        .add_help("Show the shortest unique prefix of each commit hash");
     parser
         .add_argument("no-author", ArgumentType::Boolean)
         .optional()
         .add_help("Don't show author information");
+    parser
+        .add_argument("stat", ArgumentType::Boolean)
+        .optional()
+        .add_help("Show a diffstat of the changes in each commit");
+    parser
+        .add_argument("patch", ArgumentType::Boolean)
+        .short('p')
+        .optional()
+        .add_help("Show the changes in each commit as a patch");
+    parser
+        .add_argument("merges", ArgumentType::Boolean)
+        .optional()
+        .add_help("Show only merge commits");
+    parser
+        .add_argument("no-merges", ArgumentType::Boolean)
+        .optional()
+        .add_help("Do not show merge commits");
+    parser
+        .add_argument("first-parent", ArgumentType::Boolean)
+        .optional()
+        .add_help("Follow only the first parent of merge commits");
+    parser
+        .add_argument("pickaxe", ArgumentType::String)
+        .short('S')
+        .optional()
+        .add_help(
+            "Show only commits that change the number of occurrences of \
+            the given string",
+        );
+    parser
+        .add_argument("pickaxe-regex", ArgumentType::String)
+        .short('G')
+        .optional()
+        .add_help(
+            "Show only commits with added or removed lines that match \
+            the given regex",
+        );
+    parser
+        .add_argument("date", ArgumentType::String)
+        .optional()
+        .default("default")
+        .choices(&[
+            "default",
+            "iso",
+            "iso8601",
+            "iso-strict",
+            "iso8601-strict",
+            "rfc",
+            "rfc2822",
+            "short",
+            "relative",
+            "unix",
+        ])
+        .add_help(
+            "Date format: default, iso, iso-strict, rfc, short, relative \
+            or unix",
+        );
+    parser
+        .add_argument("color", ArgumentType::String)
+        .optional()
+        .choices(&["auto", "always", "never"])
+        .add_help(
+            "Color the output: always, never, or auto to color only when \
+            writing to a terminal and NO_COLOR is not set. Defaults to \
+            color.log or color.ui, or else auto",
+        );
     parser
         .add_argument("revision", ArgumentType::String)
         .required()
@@ -0,0 +1,398 @@
+use std::path::Path;
+
+use crate::core::index::untracked_cache::{self, UntrackedCache};
+use crate::core::index::{GitIndex, WorktreeState};
+use crate::core::objects::worktree;
+use crate::core::{
+    resolve_repository_context, Error, GitRepository, RepositoryContext,
+};
+use crate::utils::argparse::{ArgumentParser, ArgumentType, Namespace};
+use crate::utils::gitignore::GitignoreStack;
+use crate::utils::path;
+use crate::utils::threadpool::ThreadPool;
+
+/// Show information about files in the index and the working tree.
+/// This handles the subcommand
+///
+/// ```bash
+/// mini_git ls-files [--cached] [--stage] [--deleted] [--modified] [--others]
+///                   [--exclude-standard] [--directory] [-z]
+///                   [--error-unmatch] [paths...]
+/// ```
+///
+/// Paths are shown relative to the current directory, and only files under
+/// the current directory are listed. The given `paths` further restrict the
+/// output to those files and directories.
+///
+/// With `--others --exclude-standard`, the untracked files are listed using
+/// the untracked cache of the index, if it has one or `core.untrackedCache`
+/// is set. Directories that have not changed are not read again, and the
+/// index is updated with the new listings.
+///
+/// # Errors
+///
+/// If file system operations fail, or if the index is malformed.
+/// An [`Error`] describing the failure is returned.
+#[allow(clippy::module_name_repetitions)]
+pub fn ls_files(args: &Namespace) -> Result<String, Error> {
+    let RepositoryContext { cwd, repo, .. } = resolve_repository_context()?;
+    repo.require_worktree()?;
+    let mut index = GitIndex::read(&repo)?;
+
+    let others = args.get("others").is_some();
+    let stage = args.get("stage").is_some();
+    let deleted = args.get("deleted").is_some();
+    let modified = args.get("modified").is_some();
+    let opts = IndexOpts {
+        // Cached files are shown by default, unless another mode is selected
+        cached: args.get("cached").is_some()
+            || stage
+            || !(others || deleted || modified),
+        stage,
+        deleted,
+        modified,
+    };
+    let terminator = if args.get("nul-terminated").is_some() {
+        '\0'
+    } else {
+        '\n'
+    };
+
+    // Like git, untracked files are listed before the index
+    let mut paths = vec![];
+    if others {
+        let mut untracked = others_files(&repo, &mut index, args)?;
+        untracked.sort();
+        paths.extend(untracked.into_iter().map(|path| (path, String::new())));
+    }
+    if deleted || modified {
+        index.refresh_fsmonitor(&repo)?;
+    }
+    paths.extend(index_files(&repo, &index, &opts)?);
+
+    let prefix = cwd
+        .canonicalize()
+        .ok()
+        .and_then(|cwd| {
+            cwd.strip_prefix(repo.worktree())
+                .ok()
+                .map(path::to_posix_path)
+        })
+        .transpose()?
+        .unwrap_or_default();
+
+    let pathspecs = args
+        .get_all("paths")
+        .unwrap_or_default()
+        .iter()
+        .filter(|path| !path.is_empty())
+        .map(|path| path.trim_end_matches('/'))
+        .collect::<Vec<_>>();
+    let mut unmatched = pathspecs.clone();
+
+    let listed = paths
+        .iter()
+        .filter_map(|(path, info)| Some((relative_to(path, &prefix)?, info)))
+        .filter(|(path, _)| {
+            if pathspecs.is_empty() {
+                return true;
+            }
+            let path = path.trim_end_matches('/');
+            let matches = |spec: &&str| {
+                path == *spec || relative_to(path, spec).is_some()
+            };
+            unmatched.retain(|spec| !matches(spec));
+            pathspecs.iter().any(matches)
+        })
+        .collect::<Vec<_>>();
+
+    if args.get("error-unmatch").is_some() {
+        if let Some(spec) = unmatched.first() {
+            return Err(format!(
+                "error: pathspec '{spec}' did not match any file(s) known \
+                to git\nDid you forget to 'git add'?"
+            )
+            .into());
+        }
+    }
+
+    Ok(listed
+        .into_iter()
+        .fold(String::new(), |mut acc, (path, info)| {
+            acc.push_str(info);
+            acc.push_str(path);
+            acc.push(terminator);
+            acc
+        }))
+}
+
+/// Which index entries to list, and how
+#[allow(clippy::struct_excessive_bools)]
+struct IndexOpts {
+    cached: bool,
+    stage: bool,
+    deleted: bool,
+    modified: bool,
+}
+
+/// Collect the index entries to list, each paired with the text shown
+/// before its path.
+fn index_files(
+    repo: &GitRepository,
+    index: &GitIndex,
+    opts: &IndexOpts,
+) -> Result<Vec<(String, String)>, Error> {
+    let mut paths = vec![];
+    let mut prev_path = None;
+    for entry in index.entries() {
+        // Conflicted paths have several entries, but are listed once
+        if !opts.stage && prev_path == Some(&entry.path) {
+            continue;
+        }
+        prev_path = Some(&entry.path);
+
+        let info = if opts.stage {
+            format!(
+                "{} {} {}\t",
+                entry.mode_as_string(),
+                entry.sha,
+                entry.stage()
+            )
+        } else {
+            String::new()
+        };
+
+        if opts.cached {
+            paths.push((entry.path.clone(), info.clone()));
+        }
+        if opts.deleted || opts.modified {
+            // Like git, a deleted file is also listed as modified
+            let (is_deleted, is_modified) =
+                match index.worktree_state(repo, entry)? {
+                    WorktreeState::Unchanged => (false, false),
+                    WorktreeState::Modified => (false, true),
+                    WorktreeState::Deleted => (true, true),
+                };
+            if opts.deleted && is_deleted {
+                paths.push((entry.path.clone(), info.clone()));
+            }
+            if opts.modified && is_modified {
+                paths.push((entry.path.clone(), info));
+            }
+        }
+    }
+    Ok(paths)
+}
+
+/// Strip the directory `prefix` from `path`, if `path` lies under it.
+fn relative_to<'a>(path: &'a str, prefix: &str) -> Option<&'a str> {
+    if prefix.is_empty() {
+        Some(path)
+    } else {
+        path.strip_prefix(prefix)?.strip_prefix('/')
+    }
+}
+
+/// Collect the untracked files for `--others`, using and updating the
+/// untracked cache of the index if there is one.
+fn others_files(
+    repo: &GitRepository,
+    index: &mut GitIndex,
+    args: &Namespace,
+) -> Result<Vec<String>, Error> {
+    let exclude_standard = args.get("exclude-standard").is_some();
+    let mut ignore = if exclude_standard {
+        worktree::get_ignore_stack(repo)?
+    } else {
+        GitignoreStack::default()
+    };
+    let collapse_dirs = args.get("directory").is_some();
+    let dir_flags = if collapse_dirs {
+        untracked_cache::SHOW_OTHER_DIRECTORIES
+    } else {
+        0
+    };
+
+    // The untracked cache assumes the standard ignore rules
+    let cache = exclude_standard
+        .then(|| UntrackedCache::take(repo, index, dir_flags))
+        .flatten();
+    let Some(mut cache) = cache else {
+        return untracked_files(repo.worktree(), index, ignore, collapse_dirs);
+    };
+    let (untracked, changed) = cache.walk(repo.worktree(), |dir| {
+        scan_dir(repo.worktree(), dir, index, &mut ignore, collapse_dirs)
+    })?;
+    index.set_untracked_cache(Some(cache));
+    if changed {
+        index.write(repo)?;
+    }
+    Ok(untracked)
+}
+
+/// Collect the files in the worktree that are neither in the index nor
+/// ignored, in no particular order. Directories are scanned in parallel, on
+/// the shared thread pool.
+///
+/// With `collapse_dirs`, a directory without any tracked files is listed
+/// once as `dir/` instead of listing its contents.
+pub(super) fn untracked_files(
+    root: &Path,
+    index: &GitIndex,
+    ignore: GitignoreStack,
+    collapse_dirs: bool,
+) -> Result<Vec<String>, Error> {
+    let root = root.to_path_buf();
+    // The workers outlive the borrow of the index
+    let index = index.clone();
+    let scan = move |(dir, mut ignore): (String, GitignoreStack)| {
+        let (files, subdirs) =
+            scan_dir(&root, &dir, &index, &mut ignore, collapse_dirs)?;
+        let join = |name: String| {
+            if dir.is_empty() {
+                name
+            } else {
+                format!("{dir}/{name}")
+            }
+        };
+        // Each subdirectory gets the rules of the directories above it
+        let subdirs = subdirs
+            .into_iter()
+            .map(|name| (join(name), ignore.clone()))
+            .collect();
+        Ok((files.into_iter().map(join).collect(), subdirs))
+    };
+    worktree::walk_dirs((String::new(), ignore), ThreadPool::shared(), scan)
+}
+
+/// List the directory `dir` of the worktree as its untracked entries and
+/// the subdirectories to search for more, both by name.
+///
+/// With `collapse_dirs`, a subdirectory without any tracked files is an
+/// untracked entry, `name/`, instead of being searched.
+fn scan_dir(
+    root: &Path,
+    dir: &str,
+    index: &GitIndex,
+    ignore: &mut GitignoreStack,
+    collapse_dirs: bool,
+) -> Result<(Vec<String>, Vec<String>), Error> {
+    ignore.enter(dir)?;
+    let mut untracked = vec![];
+    let mut subdirs = vec![];
+    for entry in std::fs::read_dir(root.join(dir))
+        .map_err(|e| format!("Failed to read directory: {e}"))?
+    {
+        let entry = entry.map_err(|e| format!("Failed to read entry: {e}"))?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name == ".git" {
+            continue;
+        }
+
+        let path = if dir.is_empty() {
+            name.clone()
+        } else {
+            format!("{dir}/{name}")
+        };
+        let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+        // Ignored directories are skipped whole, without reading them
+        if ignore.is_ignored(&path, is_dir) {
+            continue;
+        }
+
+        if !is_dir {
+            if !index.contains(&path) {
+                untracked.push(name);
+            }
+        } else if collapse_dirs && !has_tracked_files(index, &path) {
+            untracked.push(format!("{name}/"));
+        } else {
+            subdirs.push(name);
+        }
+    }
+    Ok((untracked, subdirs))
+}
+
+/// Whether the index has any entry in the directory `dir`.
+fn has_tracked_files(index: &GitIndex, dir: &str) -> bool {
+    let entries = index.entries();
+    let start = entries.partition_point(|entry| entry.path.as_str() <= dir);
+    entries[start..]
+        .iter()
+        .take_while(|entry| entry.path.starts_with(dir))
+        .any(|entry| entry.path[dir.len()..].starts_with('/'))
+}
+
+/// Make `ls-files` parser
+#[must_use]
+pub fn make_parser() -> ArgumentParser {
+    let mut parser = ArgumentParser::new(
+        "Show information about files in the index and the working tree",
+    );
+
+    parser
+        .add_argument("cached", ArgumentType::Boolean)
+        .optional()
+        .short('c')
+        .add_help("Show cached files in the output (default)");
+
+    parser
+        .add_argument("deleted", ArgumentType::Boolean)
+        .optional()
+        .short('d')
+        .add_help("Show files that are missing from the working tree");
+
+    parser
+        .add_argument("directory", ArgumentType::Boolean)
+        .optional()
+        .add_help(
+            "If a whole directory is untracked, show only its name \
+            with a trailing slash",
+        );
+
+    parser
+        .add_argument("error-unmatch", ArgumentType::Boolean)
+        .optional()
+        .add_help("Fail if any of the given paths matches no listed file");
+
+    parser
+        .add_argument("exclude-standard", ArgumentType::Boolean)
+        .optional()
+        .add_help(
+            "Skip files ignored by .gitignore, .git/info/exclude \
+            and core.excludesFile",
+        );
+
+    parser
+        .add_argument("modified", ArgumentType::Boolean)
+        .optional()
+        .short('m')
+        .add_help("Show files that differ from the index");
+
+    parser
+        .add_argument("nul-terminated", ArgumentType::Boolean)
+        .optional()
+        .short('z')
+        .add_help("Terminate entries with NUL instead of a newline");
+
+    parser
+        .add_argument("others", ArgumentType::Boolean)
+        .optional()
+        .short('o')
+        .add_help("Show other (i.e. untracked) files in the output");
+
+    parser
+        .add_argument("stage", ArgumentType::Boolean)
+        .optional()
+        .short('s')
+        .add_help("Show staged contents' mode bits, object name and stage");
+
+    parser
+        .add_argument("paths", ArgumentType::String)
+        .required()
+        .default("")
+        .multiple()
+        .add_help("Paths to restrict the output to");
+
+    parser
+}
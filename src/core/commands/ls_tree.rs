@@ -1,61 +1,148 @@
 use crate::core::objects::traits::KVLM;
 use crate::core::objects::{self, GitObject};
 use crate::core::{
-    resolve_repository_context, GitRepository, RepositoryContext,
+    resolve_repository_context, Error, GitRepository, RepositoryContext,
 };
 
 use crate::utils::argparse::{ArgumentParser, ArgumentType, Namespace};
 use crate::utils::collections::kvlm;
+use crate::utils::json::Json;
 
 /// Pretty-print a tree object.
 /// This handles the subcommand
 ///
 /// ```bash
-/// mini_git ls-tree [--recursive] tree
+/// mini_git ls-tree [--recursive] [--show-trees] [--long] [--name-only] [-z]
+///                  [--json] tree [paths...]
 /// ```
 ///
+/// Only entries equal to or under one of the given paths are shown, and trees
+/// leading up to them are descended into.
+///
+/// With `--json`, the entries are printed as a JSON array of objects with
+/// their `mode`, `type`, `oid` and `path`, and with `--long`, their `size`.
+///
 /// # Errors
 ///
 /// If file system operations fail, or if input paths are not valid.
-/// A [`String`] message describing the error is returned.
+/// An [`Error`] describing the failure is returned.
 #[allow(clippy::module_name_repetitions)]
-pub fn ls_tree(args: &Namespace) -> Result<String, String> {
+pub fn ls_tree(args: &Namespace) -> Result<String, Error> {
     let RepositoryContext { repo, .. } = resolve_repository_context()?;
-    let recursive = args.get("recursive").is_some();
     let tree_ref = &args["tree"];
-    let show_trees = args.get("show-trees").is_some();
-    let only_trees = args.get("only-trees").is_some();
-    let mut res = String::new();
-    tree(
-        &mut res, &repo, tree_ref, "", recursive, show_trees, only_trees,
-    )?;
-    Ok(res)
+    let opts = LsTreeOpts {
+        recursive: args.get("recursive").is_some(),
+        show_trees: args.get("show-trees").is_some(),
+        only_trees: args.get("only-trees").is_some(),
+        long: args.get("long").is_some(),
+        json: args.get("json").is_some(),
+        name_only: args.get("name-only").is_some()
+            || args.get("name-status").is_some(),
+        terminator: if args.get("nul-terminated").is_some() {
+            '\0'
+        } else {
+            '\n'
+        },
+        paths: args
+            .get_all("paths")
+            .unwrap_or_default()
+            .iter()
+            .filter(|path| !path.is_empty())
+            .cloned()
+            .collect(),
+    };
+    let mut entries = Vec::new();
+    tree(&mut entries, &repo, tree_ref, "", &opts)?;
+
+    if opts.json {
+        let entries = entries
+            .iter()
+            .map(|entry| json_entry(&repo, entry, &opts))
+            .collect::<Result<Json, Error>>()?;
+        return Ok(entries.to_string());
+    }
+    entries.iter().try_fold(String::new(), |mut res, entry| {
+        res.push_str(&repr_leaf(&repo, entry, &opts)?);
+        Ok(res)
+    })
+}
+
+#[allow(clippy::struct_excessive_bools)]
+struct LsTreeOpts {
+    recursive: bool,
+    show_trees: bool,
+    only_trees: bool,
+    long: bool,
+    json: bool,
+    name_only: bool,
+    terminator: char,
+    paths: Vec<String>,
+}
+
+/// An entry to list
+struct ListedEntry {
+    mode: String,
+    obj_type: &'static str,
+    sha: String,
+    path: String,
+}
+
+/// How an entry relates to the requested paths
+#[derive(PartialEq, Eq)]
+enum PathMatch {
+    /// The entry is one of the paths, or lies beneath one
+    Matched,
+    /// A requested path lies beneath this entry
+    Ancestor,
+    /// The entry is unrelated to every requested path
+    Unmatched,
+}
+
+impl LsTreeOpts {
+    fn match_path(&self, path: &str) -> PathMatch {
+        if self.paths.is_empty() {
+            return PathMatch::Matched;
+        }
+
+        let is_under = |path: &str, dir: &str| {
+            path.strip_prefix(dir)
+                .is_some_and(|rest| rest.starts_with('/'))
+        };
+
+        // A trailing slash selects the contents of a directory, not the
+        // directory itself
+        if self.paths.iter().any(|filter| {
+            filter == path || is_under(path, filter.trim_end_matches('/'))
+        }) {
+            PathMatch::Matched
+        } else if self.paths.iter().any(|filter| is_under(filter, path)) {
+            PathMatch::Ancestor
+        } else {
+            PathMatch::Unmatched
+        }
+    }
 }
 
 fn tree(
-    acc: &mut String,
+    acc: &mut Vec<ListedEntry>,
     repo: &GitRepository,
     tree_ref: &str,
     prefix: &str,
-    recursive: bool,
-    show_trees: bool,
-    only_trees: bool,
-) -> Result<(), String> {
+    opts: &LsTreeOpts,
+) -> Result<(), Error> {
     let sha = objects::find_object(repo, tree_ref, None, false)?;
     let obj = objects::read_object(repo, &sha)?;
 
     let mut f = |obj_type: &str, kvlm: &kvlm::KVLM| {
         let Some(obj_tree) = kvlm.get_key(b"tree") else {
-            return Err(format!(
+            return Err(Error::Parse(format!(
                 "{obj_type} {tree_ref} has no associated tree"
-            ));
+            )));
         };
         for subtree in obj_tree {
             let subtree =
                 subtree.iter().map(|x| char::from(*x)).collect::<String>();
-            tree(
-                acc, repo, &subtree, prefix, recursive, show_trees, only_trees,
-            )?;
+            tree(acc, repo, &subtree, prefix, opts)?;
         }
         Ok(())
     };
@@ -63,7 +150,7 @@ fn tree(
     let obj = match obj {
         GitObject::Commit(commit) => return f("commit", commit.kvlm()),
         GitObject::Blob(_) => {
-            return Err(format!("{tree_ref} is not a tree_object"))
+            return Err(format!("{tree_ref} is not a tree_object").into())
         }
         GitObject::Tag(tag) => return f("tag", tag.kvlm()),
         GitObject::Tree(obj) => obj,
@@ -72,25 +159,42 @@ fn tree(
     for leaf in obj.leaves() {
         let mode = leaf.mode_as_string();
         let Some(obj_type) = leaf.obj_type() else {
-            return Err(format!("Unknown object mode {mode}"));
+            return Err(format!("Unknown object mode {mode}").into());
         };
 
         let sha = leaf.sha();
         let path = join_path(prefix, &leaf.path_as_string());
 
-        if recursive && obj_type == "tree" {
-            if show_trees {
-                acc.push_str(&repr_leaf(&mode, obj_type, sha, &path));
+        let matched = opts.match_path(&path);
+        if matched == PathMatch::Unmatched {
+            continue;
+        }
+
+        if obj_type == "tree"
+            && (opts.recursive || matched == PathMatch::Ancestor)
+        {
+            if opts.show_trees {
+                acc.push(ListedEntry {
+                    mode: mode.clone(),
+                    obj_type,
+                    sha: sha.to_owned(),
+                    path: path.clone(),
+                });
             }
-            tree(acc, repo, sha, &path, recursive, show_trees, only_trees)?;
+            tree(acc, repo, sha, &path, opts)?;
         } else {
             // Base case
-            if only_trees && obj_type != "tree" {
+            if opts.only_trees && obj_type != "tree" {
                 continue;
             }
 
-            acc.push_str(&repr_leaf(&mode, obj_type, sha, &path));
-        };
+            acc.push(ListedEntry {
+                mode,
+                obj_type,
+                sha: sha.to_owned(),
+                path,
+            });
+        }
     }
     Ok(())
 }
@@ -101,9 +205,57 @@ fn join_path(prefix: &str, next: &str) -> String {
     path.as_os_str().to_str().expect("utf-8 path").to_owned()
 }
 
-#[inline]
-fn repr_leaf(mode: &str, obj_type: &str, sha: &str, path: &str) -> String {
-    format!("{mode} {obj_type} {sha}\t{path}\n")
+fn repr_leaf(
+    repo: &GitRepository,
+    entry: &ListedEntry,
+    opts: &LsTreeOpts,
+) -> Result<String, Error> {
+    let ListedEntry {
+        mode,
+        obj_type,
+        sha,
+        path,
+    } = entry;
+    let end = opts.terminator;
+    if opts.name_only {
+        return Ok(format!("{path}{end}"));
+    }
+    if !opts.long {
+        return Ok(format!("{mode} {obj_type} {sha}\t{path}{end}"));
+    }
+
+    let size = blob_size(repo, entry)?
+        .map_or_else(|| "-".to_owned(), |size| size.to_string());
+    Ok(format!("{mode} {obj_type} {sha} {size:>7}\t{path}{end}"))
+}
+
+fn json_entry(
+    repo: &GitRepository,
+    entry: &ListedEntry,
+    opts: &LsTreeOpts,
+) -> Result<Json, Error> {
+    let mut members = vec![
+        ("mode", Json::from(entry.mode.as_str())),
+        ("type", Json::from(entry.obj_type)),
+        ("oid", Json::from(entry.sha.as_str())),
+        ("path", Json::from(entry.path.as_str())),
+    ];
+    if opts.long {
+        members.push(("size", blob_size(repo, entry)?.into()));
+    }
+    Ok(Json::object(members))
+}
+
+/// The size of the entry, if it is a blob, which is the only kind of object
+/// with a meaningful size.
+fn blob_size(
+    repo: &GitRepository,
+    entry: &ListedEntry,
+) -> Result<Option<usize>, Error> {
+    if entry.obj_type != "blob" {
+        return Ok(None);
+    }
+    Ok(Some(objects::object_size(repo, &entry.sha.parse()?)?))
 }
 
 /// Make `ls-tree` parser
@@ -117,6 +269,33 @@ pub fn make_parser() -> ArgumentParser {
         .short('d')
         .add_help("Only show trees");
 
+    parser
+        .add_argument("long", ArgumentType::Boolean)
+        .optional()
+        .short('l')
+        .add_help("Show the size of blob entries");
+
+    parser
+        .add_argument("json", ArgumentType::Boolean)
+        .optional()
+        .add_help("Print the entries as JSON");
+
+    parser
+        .add_argument("name-only", ArgumentType::Boolean)
+        .optional()
+        .add_help("List only filenames, one per line");
+
+    parser
+        .add_argument("name-status", ArgumentType::Boolean)
+        .optional()
+        .add_help("Same as --name-only");
+
+    parser
+        .add_argument("nul-terminated", ArgumentType::Boolean)
+        .optional()
+        .short('z')
+        .add_help("Terminate entries with NUL instead of a newline");
+
     parser
         .add_argument("recursive", ArgumentType::Boolean)
         .optional()
@@ -134,5 +313,12 @@ pub fn make_parser() -> ArgumentParser {
         .required()
         .add_help("A tree-ish object.");
 
+    parser
+        .add_argument("paths", ArgumentType::String)
+        .required()
+        .default("")
+        .multiple()
+        .add_help("Paths to restrict the output to");
+
     parser
 }
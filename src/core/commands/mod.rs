@@ -1,19 +1,288 @@
 pub mod cat_file;
 pub mod diff;
+pub mod diff_files;
+pub mod diff_tree;
+pub mod generate_docs;
 pub mod hash_object;
+pub mod help;
 pub mod init;
 pub mod log;
+pub mod ls_files;
 pub mod ls_tree;
 pub mod rev_parse;
 pub mod show_ref;
+pub mod status;
+pub mod version;
+pub mod write_tree;
 
+use std::borrow::Cow;
+use std::io::Write;
 use std::path::Path;
 
 use crate::core::objects::worktree;
-use crate::core::GitRepository;
+use crate::core::{Error, GitRepository};
 
+use crate::utils::argparse::{ArgumentParser, ArgumentType, Namespace};
 use crate::utils::path;
 
+/// A command of the command line, with the parser of its arguments.
+pub struct Command {
+    name: &'static str,
+    make_parser: fn() -> ArgumentParser,
+    callback: Callback,
+}
+
+/// How a command hands over its output
+enum Callback {
+    /// All output is returned at once, along with the exit status
+    Buffered(fn(&Namespace) -> Result<(String, i32), Error>),
+    /// Output is written as it is produced, and the exit status is returned
+    Streamed(fn(&Namespace, &mut dyn Write) -> Result<i32, Error>),
+}
+
+impl Command {
+    const fn new(
+        name: &'static str,
+        make_parser: fn() -> ArgumentParser,
+        callback: Callback,
+    ) -> Self {
+        Self {
+            name,
+            make_parser,
+            callback,
+        }
+    }
+
+    /// The name the command is run by.
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Makes the parser of the arguments of the command.
+    #[must_use]
+    pub fn make_parser(&self) -> ArgumentParser {
+        (self.make_parser)()
+    }
+
+    /// Runs the command with the arguments parsed by its parser, writing
+    /// its output to `out`, and returns its exit status.
+    ///
+    /// # Errors
+    ///
+    /// If the command fails. Commands that stream their output may have
+    /// written some of it by then.
+    pub fn execute(
+        &self,
+        args: &Namespace,
+        out: &mut dyn Write,
+    ) -> Result<i32, Error> {
+        match self.callback {
+            Callback::Buffered(callback) => {
+                let (msg, exit_code) = callback(args)?;
+                let context = "Failed to write the output";
+                out.write_all(msg.as_bytes())
+                    .map_err(|e| Error::io(context, e))?;
+                // Empty and NUL-terminated output is meant for scripts, so
+                // leave it as is
+                if !msg.is_empty() && !msg.ends_with(['\n', '\0']) {
+                    out.write_all(b"\n").map_err(|e| Error::io(context, e))?;
+                }
+                Ok(exit_code)
+            }
+            Callback::Streamed(callback) => callback(args, out),
+        }
+    }
+}
+
+macro_rules! cmd {
+    // Commands that stream their output, and report their own exit status
+    ($name:literal, $cmd:ident, stream $callback:ident) => {
+        Command::new(
+            $name,
+            $cmd::make_parser,
+            Callback::Streamed($cmd::$callback),
+        )
+    };
+    ($name:literal, $cmd:ident) => {
+        Command::new(
+            $name,
+            $cmd::make_parser,
+            Callback::Buffered(|args| $cmd::$cmd(args).map(|msg| (msg, 0))),
+        )
+    };
+}
+
+/// All the commands, which need to be in sorted order by name
+pub const COMMANDS: &[Command] = &[
+    cmd!("cat-file", cat_file),
+    cmd!("diff", diff, stream write_diff),
+    cmd!("diff-files", diff_files),
+    cmd!("diff-tree", diff_tree),
+    cmd!("generate-docs", generate_docs),
+    cmd!("hash-object", hash_object),
+    cmd!("help", help),
+    cmd!("init", init),
+    cmd!("log", log),
+    cmd!("ls-files", ls_files),
+    cmd!("ls-tree", ls_tree),
+    cmd!("rev-parse", rev_parse),
+    cmd!("show-ref", show_ref),
+    cmd!("status", status),
+    cmd!("version", version),
+    cmd!("write-tree", write_tree),
+];
+
+// The following code ensures that the command array is sorted at compile time.
+// The command array is required to be sorted to be binary-search friendly,
+// and we enforce this at compile time.
+const fn str_le(a: &'static str, b: &'static str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    let mut i = 0;
+    let len = if a.len() < b.len() { a.len() } else { b.len() };
+
+    while i < len {
+        if a[i] < b[i] {
+            return true;
+        } else if a[i] > b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    len == a.len()
+}
+
+const fn is_cmd_sorted() -> bool {
+    let len = COMMANDS.len();
+    assert!(len > 1, "COMMANDS IS EMPTY");
+    let mut prev_name = &COMMANDS[0].name;
+    let mut i = 1;
+
+    while i < len {
+        if !str_le(prev_name, COMMANDS[i].name) {
+            return false;
+        }
+
+        prev_name = &COMMANDS[i].name;
+        i += 1;
+    }
+
+    true
+}
+
+// If this fails to compile, the command array is not sorted
+const _: () = assert!(is_cmd_sorted(), "COMMANDS is not sorted by name");
+
+/// The command named `name`, if there is one.
+#[must_use]
+pub fn find(name: &str) -> Option<&'static Command> {
+    COMMANDS
+        .binary_search_by(|cmd| cmd.name.cmp(name))
+        .ok()
+        .map(|i| &COMMANDS[i])
+}
+
+/// Make the parser of the command line, with every command as a subcommand.
+#[must_use]
+pub fn make_parser() -> ArgumentParser {
+    let mut parser = ArgumentParser::new("MiniGit, a git, but mini!");
+
+    for command in COMMANDS {
+        parser.add_subcommand(command.name, command.make_parser());
+    }
+
+    // Handled by the binary before the arguments are parsed, and listed
+    // here for the help
+    parser
+        .add_argument("path", ArgumentType::String)
+        .optional()
+        .short_only('C')
+        .add_help("Run as if started in the given directory");
+    parser
+        .add_argument("git-dir", ArgumentType::String)
+        .optional()
+        .add_help("Use the given git directory, with the current directory as the worktree");
+    parser
+        .add_argument("no-progress", ArgumentType::Boolean)
+        .optional()
+        .add_help("Do not show the progress of long operations");
+    parser
+        .add_argument("quiet", ArgumentType::Boolean)
+        .optional()
+        .add_help("Report only errors");
+    parser
+        .add_argument("verbose", ArgumentType::Boolean)
+        .optional()
+        .add_help("Trace what the command does on stderr");
+    parser
+        .add_argument("version", ArgumentType::Boolean)
+        .optional()
+        .add_help("Report the version of mini_git");
+
+    parser.require_subcommand();
+
+    parser
+}
+
+/// What a command run by [`run`] printed, and the status it exited with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandOutput {
+    /// The output of the command, as it is printed on the command line
+    pub stdout: Vec<u8>,
+    /// The exit status of the command
+    pub exit_code: i32,
+}
+
+impl CommandOutput {
+    /// The output as text, with invalid UTF-8 replaced.
+    #[must_use]
+    pub fn text(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.stdout)
+    }
+}
+
+/// Runs the command `name` with `args` in the current directory, as the
+/// command line does, but returns its output instead of printing it.
+///
+/// `--help` returns the help of the command as its output.
+///
+/// # Errors
+///
+/// An [`Error::Usage`] if there is no such command or the arguments are
+/// invalid, or the error the command fails with.
+///
+/// # Examples
+///
+/// ```
+/// use mini_git::core::commands;
+///
+/// let output = commands::run("version", &[])?;
+/// assert_eq!(output.exit_code, 0);
+/// assert!(output.text().starts_with("mini_git version "));
+///
+/// assert!(commands::run("no-such-command", &[]).is_err());
+/// # Ok::<(), mini_git::core::Error>(())
+/// ```
+pub fn run(name: &str, args: &[&str]) -> Result<CommandOutput, Error> {
+    let command = find(name).ok_or_else(|| {
+        Error::Usage(format!("\"{name}\" is not a mini_git command."))
+    })?;
+    let mut parser = command.make_parser();
+    parser.compile();
+    let args = parser.parse_args(args).map_err(Error::Usage)?;
+
+    if args.get("help").is_some() {
+        return Ok(CommandOutput {
+            stdout: parser.help().into_bytes(),
+            exit_code: 0,
+        });
+    }
+
+    let mut stdout = vec![];
+    let exit_code = command.execute(&args, &mut stdout)?;
+    Ok(CommandOutput { stdout, exit_code })
+}
+
 #[macro_export]
 macro_rules! parse_arg_as_int {
     ($value:expr, $err_msg:literal) => {
@@ -27,7 +296,7 @@ macro_rules! parse_arg_as_int {
             None => $default,
             Some(count) => {
                 let Ok(x) = count.parse::<usize>() else {
-                    return Err(format!("{} is not a number", $err_msg));
+                    return Err(format!("{} is not a number", $err_msg).into());
                 };
                 x
             }
@@ -40,11 +309,11 @@ macro_rules! parse_arg_as_int {
 /// # Parameters
 /// - `repo`: A reference to the `GitRepository`.
 /// - `cwd`: The current working directory.
-/// - `files`: A comma-separated list of file paths to resolve.
+/// - `files`: The file paths to resolve.
 ///
 /// # Returns
 /// - `Ok(Vec<String>)` with paths relative to the repository root.
-/// - `Err(String)` if any file cannot be resolved.
+/// - `Err(Error)` if any file cannot be resolved.
 ///
 /// # Errors
 /// - Returns an error if:
@@ -58,22 +327,25 @@ macro_rules! parse_arg_as_int {
 /// use mini_git::core::commands::resolve_cla_files;
 /// let RepositoryContext {cwd, repo, ..} = resolve_repository_context()?;
 ///
-/// let resolved_files = resolve_cla_files(&repo, &cwd, "src/main.rs,src/lib.rs")?;
+/// let files = ["src/main.rs".to_owned(), "src/lib.rs".to_owned()];
+/// let resolved_files = resolve_cla_files(&repo, &cwd, &files)?;
 ///
 /// # Ok::<(), String>(())
 /// ```
 pub fn resolve_cla_files(
     repo: &GitRepository,
     cwd: &Path,
-    files: &str,
-) -> Result<Vec<String>, String> {
+    files: &[String],
+) -> Result<Vec<String>, Error> {
     let mut resolved_files = vec![];
-    for file in files.split(',') {
+    for file in files {
         // Create a path by joining the current working directory with the file path
         let file_path = cwd.join(file);
 
         if !file_path.exists() {
-            return Err(format!("path '{file}' is not in the working tree"));
+            return Err(
+                format!("path '{file}' is not in the working tree").into()
+            );
         }
 
         // Canonicalize the path to get the absolute path
@@ -82,7 +354,9 @@ pub fn resolve_cla_files(
             .map_err(|_| format!("Could not canonicalize path {file}"))?;
 
         if !abs_path.exists() {
-            return Err(format!("File {file} does not exist in the worktree"));
+            return Err(
+                format!("File {file} does not exist in the worktree").into()
+            );
         }
 
         if abs_path.is_file() {
@@ -112,7 +386,9 @@ pub fn resolve_cla_files(
                 resolved_files.push(path::to_posix_path(rel_path)?);
             }
         } else {
-            return Err(format!("{file} is neither a file nor a directory"));
+            return Err(
+                format!("{file} is neither a file nor a directory").into()
+            );
         }
     }
 
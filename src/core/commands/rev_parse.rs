@@ -1,5 +1,5 @@
 use crate::core::commands::show_ref;
-use crate::core::objects;
+use crate::core::{objects, Error};
 use crate::core::{
     resolve_repository_context, GitRepository, RepositoryContext,
 };
@@ -11,18 +11,22 @@ macro_rules! path_to_string {
     ($path:expr, $err:literal) => {
         match $path.to_str() {
             Some(s) => Ok(String::from(s)),
-            None => Err($err.to_owned()),
+            None => Err($err.to_owned().into()),
         }
     };
 }
 
-type PathFunc = fn(&GitRepository) -> Result<String, String>;
+type PathFunc = fn(&GitRepository) -> Result<String, Error>;
 
 const OPTION_MAP: &[(&str, PathFunc)] = &[
     ("all", all_refs),
+    ("git-common-dir", |repo| {
+        path_to_string!(repo.common_dir(), "Could not determine common dir")
+    }),
     ("git-dir", gitdir),
+    ("is-bare-repository", |repo| Ok(repo.is_bare().to_string())),
     ("is-inside-git-dir", |repo| is_cwd_inside(repo.gitdir())),
-    ("is-inside-work-tree", |repo| is_cwd_inside(repo.worktree())),
+    ("is-inside-work-tree", is_inside_work_tree),
     ("show-toplevel", show_toplevel),
 ];
 
@@ -33,7 +37,9 @@ const OPTION_MAP: &[(&str, PathFunc)] = &[
 /// ```bash
 /// mini_git rev-parse [--type TREE] [ --revision REVISION ]
 /// mini_git rev-parse --all
+/// mini_git rev-parse --git-common-dir
 /// mini_git rev-parse --git-dir
+/// mini_git rev-parse --is-bare-repository
 /// mini_git rev-parse --is-inside-git-dir
 /// mini_git rev-parse --is-inside-work-tree
 /// mini_git rev-parse --show-toplevel
@@ -42,9 +48,9 @@ const OPTION_MAP: &[(&str, PathFunc)] = &[
 /// # Errors
 ///
 /// If file system operations fail, or if input paths are not valid.
-/// A [`String`] message describing the error is returned.
+/// An [`Error`] describing the failure is returned.
 #[allow(clippy::module_name_repetitions)]
-pub fn rev_parse(args: &Namespace) -> Result<String, String> {
+pub fn rev_parse(args: &Namespace) -> Result<String, Error> {
     let RepositoryContext { repo, .. } = resolve_repository_context()?;
 
     let mut output = String::new();
@@ -72,12 +78,12 @@ pub fn rev_parse(args: &Namespace) -> Result<String, String> {
 
     let res = objects::find_object(&repo, revision, type_, true)?;
 
-    output.push_str(&res);
+    output.push_str(&res.to_string());
     output.push('\n');
     Ok(output)
 }
 
-fn all_refs(repo: &GitRepository) -> Result<String, String> {
+fn all_refs(repo: &GitRepository) -> Result<String, Error> {
     show_ref::list_resolved_refs(&Namespace::new(), repo, None).map(|x| {
         x.iter()
             .filter_map(|s| s.split_whitespace().next())
@@ -86,16 +92,27 @@ fn all_refs(repo: &GitRepository) -> Result<String, String> {
     })
 }
 
-fn gitdir(repo: &GitRepository) -> Result<String, String> {
+fn gitdir(repo: &GitRepository) -> Result<String, Error> {
     path_to_string!(repo.gitdir(), "Could not determine repository gitdir")
 }
 
-fn is_cwd_inside(top: &std::path::Path) -> Result<String, String> {
+fn is_cwd_inside(top: &std::path::Path) -> Result<String, Error> {
     Ok(format!("{}", path::current_dir()?.starts_with(top)))
 }
 
-fn show_toplevel(repo: &GitRepository) -> Result<String, String> {
-    path_to_string!(repo.worktree(), "Could not determine repository toplevel")
+fn is_inside_work_tree(repo: &GitRepository) -> Result<String, Error> {
+    // A bare repository has no working tree to be inside of
+    if repo.is_bare() {
+        return Ok(false.to_string());
+    }
+    is_cwd_inside(repo.worktree())
+}
+
+fn show_toplevel(repo: &GitRepository) -> Result<String, Error> {
+    path_to_string!(
+        repo.require_worktree()?,
+        "Could not determine repository toplevel"
+    )
 }
 
 /// Make `rev-parse` parser
@@ -107,6 +124,12 @@ pub fn make_parser() -> ArgumentParser {
         .add_argument("all", ArgumentType::Boolean)
         .add_help("Show all refs found in `refs/`");
 
+    parser
+        .add_argument("is-bare-repository", ArgumentType::Boolean)
+        .add_help(
+            "When the repository is bare print \"true\", otherwise \"false\"",
+        );
+
     parser
         .add_argument("is-inside-git-dir", ArgumentType::Boolean)
         .add_help("When the current working directory is below the repository directory print \"true\", otherwise \"false\"");
@@ -130,6 +153,12 @@ pub fn make_parser() -> ArgumentParser {
         .add_argument("git-dir", ArgumentType::Boolean)
         .add_help("Show the absolute path to the .git directory.");
 
+    parser
+        .add_argument("git-common-dir", ArgumentType::Boolean)
+        .add_help(
+            "Show the absolute path to the directory shared by all worktrees.",
+        );
+
     parser
         .add_argument("revision", ArgumentType::String)
         .required()
@@ -1,13 +1,15 @@
 use std::path::PathBuf;
 
+use crate::core::objects::oid::Oid;
 use crate::core::objects::traits::KVLM;
 use crate::core::objects::{self, read_object, resolve_ref, GitObject};
 use crate::core::{
-    resolve_repository_context, GitRepository, RepositoryContext,
+    resolve_repository_context, Error, GitRepository, RepositoryContext,
 };
 
 use crate::utils::argparse::{ArgumentParser, ArgumentType, Namespace};
 use crate::utils::collections::ordered_map::OrderedMap;
+use crate::utils::json::Json;
 use crate::utils::path;
 
 const REF_DIR: &str = "refs";
@@ -18,7 +20,8 @@ const TAG_REFS: &str = "refs/tags";
 /// This handles the subcommand
 ///
 /// ```bash
-/// mini_git show-ref [--head] [--tags] [--heads] [--dereference] <pattern>
+/// mini_git show-ref [--head] [--tags] [--heads] [--dereference] [--json]
+///                   <pattern>
 /// ```
 ///
 /// or,
@@ -27,12 +30,15 @@ const TAG_REFS: &str = "refs/tags";
 /// mini_git show-ref [--exists] ref
 /// ```
 ///
+/// With `--json`, the refs are printed as a JSON array of objects with their
+/// `oid` and `ref`, and with `--dereference`, the `peeled` object of tags.
+///
 /// # Errors
 ///
 /// If file system operations fail, or if input paths are not valid.
-/// A [`String`] message describing the error is returned.
+/// An [`Error`] describing the failure is returned.
 #[allow(clippy::module_name_repetitions, clippy::missing_panics_doc)]
-pub fn show_ref(args: &Namespace) -> Result<String, String> {
+pub fn show_ref(args: &Namespace) -> Result<String, Error> {
     let RepositoryContext { repo, .. } = resolve_repository_context()?;
 
     let filter = args.get("pattern").and_then(|x| {
@@ -46,7 +52,7 @@ pub fn show_ref(args: &Namespace) -> Result<String, String> {
     let check_exists = args.get("exists").is_some();
 
     if check_exists && filter.is_none() {
-        return Err("--exists requires a reference".to_owned());
+        return Err(Error::Usage("--exists requires a reference".to_owned()));
     }
 
     if check_exists {
@@ -58,12 +64,32 @@ pub fn show_ref(args: &Namespace) -> Result<String, String> {
         {
             Ok(String::new())
         } else {
-            Err("error: reference not found".to_owned())
+            Err("error: reference not found".into())
         }
     } else {
         let result = list_resolved_refs(args, &repo, filter)?;
-        Ok(result.join("\n"))
+        if args.get("json").is_some() {
+            Ok(refs_json(&result).to_string())
+        } else {
+            Ok(result.join("\n"))
+        }
+    }
+}
+
+/// The refs listed by [`list_resolved_refs`] as JSON, with the objects
+/// dereferenced tags point to as the `peeled` member of the tags.
+fn refs_json(result: &[String]) -> Json {
+    let mut refs: Vec<Vec<(&str, Json)>> = Vec::new();
+    for line in result.iter().flat_map(|entry| entry.lines()) {
+        let Some((oid, name)) = line.split_once(' ') else {
+            continue;
+        };
+        match (name.strip_suffix("^{}"), refs.last_mut()) {
+            (Some(_), Some(tag)) => tag.push(("peeled", oid.into())),
+            _ => refs.push(vec![("oid", oid.into()), ("ref", name.into())]),
+        }
     }
+    refs.into_iter().map(Json::object).collect()
 }
 
 #[allow(clippy::similar_names)]
@@ -71,7 +97,7 @@ pub(crate) fn list_resolved_refs(
     args: &Namespace,
     repo: &GitRepository,
     filter: Option<&str>,
-) -> Result<Vec<String>, String> {
+) -> Result<Vec<String>, Error> {
     let dereference = args.get("dereference").is_some();
 
     let mut result = vec![];
@@ -90,7 +116,7 @@ pub(crate) fn list_resolved_refs(
     // Merge packed_refs into refs, ensuring loose refs take precedence
     for (refname, sha) in &packed_refs {
         if !refs.contains_key(refname) {
-            refs.insert(refname.clone(), sha.clone());
+            refs.insert(refname.clone(), *sha);
         }
     }
 
@@ -103,7 +129,7 @@ pub(crate) fn list_resolved_refs(
             return res;
         }
 
-        let Ok(GitObject::Tag(tag)) = read_object(repo, resolved) else {
+        let Ok(GitObject::Tag(tag)) = read_object(repo, &resolved) else {
             return res;
         };
 
@@ -133,17 +159,18 @@ pub(crate) fn list_resolved_refs(
 fn list_refs(
     repo: &GitRepository,
     filter: Option<&str>,
-) -> Result<OrderedMap<String, String>, String> {
-    let Some(initial_path) = path::repo_dir(repo.gitdir(), &[REF_DIR], false)?
+) -> Result<OrderedMap<String, Oid>, Error> {
+    let Some(initial_path) =
+        path::repo_dir(repo.common_dir(), &[REF_DIR], false)?
     else {
         return Err(
             "Fatal error: refs directory not found. This indicates the \
             repository is likely corrupted"
-                .to_owned(),
+                .into(),
         );
     };
 
-    let n_comps = repo.gitdir().components().count();
+    let n_comps = repo.common_dir().components().count();
     let initial_entries = sorted_dir(&initial_path)?;
 
     let mut stack = Vec::<Vec<PathBuf>>::new();
@@ -175,12 +202,14 @@ fn list_refs(
                 (Some(x), Some(y)) if x == y => {}
                 (None, _) => {}
                 _ => continue,
-            };
+            }
 
             // For operations, we use OS specific path separator
             let rec_ref = r#ref.join(std::path::MAIN_SEPARATOR_STR);
-            let resolved =
-                resolve_ref(repo, &rec_ref)?.unwrap_or(String::new());
+            // Symbolic refs to missing refs are skipped
+            let Some(resolved) = resolve_ref(repo, &rec_ref)? else {
+                continue;
+            };
 
             // For display we use the POSIX path separator '/'.
             let key_ref = r#ref.join("/");
@@ -192,9 +221,9 @@ fn list_refs(
 
 fn sorted_dir(
     path: &std::path::Path,
-) -> Result<Vec<std::path::PathBuf>, String> {
+) -> Result<Vec<std::path::PathBuf>, Error> {
     let Ok(ls) = std::fs::read_dir(path) else {
-        return Err(format!("failed to read dir {:?}", path.as_os_str()));
+        return Err(format!("failed to read dir {:?}", path.as_os_str()).into());
     };
 
     let mut ls = ls
@@ -208,9 +237,9 @@ fn sorted_dir(
 fn list_packed_refs(
     repo: &GitRepository,
     filter: Option<&str>,
-) -> Result<OrderedMap<String, String>, String> {
+) -> Result<OrderedMap<String, Oid>, Error> {
     objects::parse_packed_refs(repo)
-        .map_err(|e| format!("Failed to parse packed-refs file: {e}"))
+        .map_err(|e| format!("Failed to parse packed-refs file: {e}").into())
         .map(|packed_refs| match filter {
             Some(filter) => packed_refs
                 .into_iter()
@@ -259,6 +288,11 @@ pub fn make_parser() -> ArgumentParser {
         .short('d')
         .add_help("Dereference tags into object IDs");
 
+    parser
+        .add_argument("json", ArgumentType::Boolean)
+        .optional()
+        .add_help("Print the refs as JSON");
+
     parser
         .add_argument("exists", ArgumentType::Boolean)
         .optional()
@@ -0,0 +1,582 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+
+use crate::core::commands::ls_files::untracked_files;
+use crate::core::index::{GitIndex, WorktreeState};
+use crate::core::objects::tree::TreeWalker;
+use crate::core::objects::worktree;
+use crate::core::{
+    resolve_repository_context, Error, GitRepository, RepositoryContext,
+};
+use crate::utils::argparse::{ArgumentParser, ArgumentType, Namespace};
+use crate::utils::json::Json;
+
+/// The bits of a mode that tell the type of an entry.
+const TYPE_MASK: u32 = 0o170_000;
+/// The type bits of a submodule entry.
+const GITLINK_TYPE: u32 = 0o160_000;
+
+/// Show the state of the working tree.
+/// This handles the subcommand
+///
+/// ```bash
+/// mini_git status [--porcelain[=v1|v2]] [-z] [-b] [--json]
+///                 [--untracked-files[=no|normal|all]]
+/// ```
+///
+/// Each changed path has two status letters, like git: how the index
+/// differs from `HEAD`, and how the working tree differs from the index,
+/// each one of `M` (modified), `T` (type changed), `A` (added), `D`
+/// (deleted), `R` (renamed) or ` ` (unchanged). Conflicted paths have the
+/// codes of the stages they have, like `UU`, and untracked files are `??`.
+///
+/// By default, the changes are listed in sections for people to read. With
+/// `--porcelain`, or `--porcelain=v1`, each path is a line `XY path`, or
+/// `XY orig -> path` for a rename. `--porcelain=v2` has lines that also give
+/// the modes and object names of each side, as described in
+/// `git-status(1)`. `-z` terminates entries with NUL instead of a newline,
+/// writes renames as `XY path\0orig`, and implies `--porcelain=v1` unless
+/// another format is given. `-b` starts the output with the branch.
+///
+/// With `--json`, the status is printed as a JSON object with the `branch`,
+/// or `null` if `HEAD` is detached, the `oid` of `HEAD`, or `null` if there
+/// are no commits yet, the `changes`, and the `untracked` paths. Each change
+/// has its `path`, the `orig` path of a rename, or `null`, its `index` and
+/// `worktree` status letters, with `.` for unchanged, and whether it is
+/// `unmerged`.
+///
+/// Paths are relative to the root of the working tree, and are not quoted.
+/// Only renames of files whose contents did not change are detected, and
+/// upstream branches are not shown.
+///
+/// # Errors
+///
+/// If the index, `HEAD` or its tree cannot be read, or a changed file
+/// cannot be read.
+/// An [`Error`] describing the failure is returned.
+#[allow(clippy::module_name_repetitions)]
+pub fn status(args: &Namespace) -> Result<String, Error> {
+    let RepositoryContext { repo, .. } = resolve_repository_context()?;
+    repo.require_worktree()?;
+    let mut index = GitIndex::read(&repo)?;
+    index.refresh_fsmonitor(&repo)?;
+
+    let changes = tracked_changes(&repo, &index)?;
+    let untracked = match args.get("untracked-files").map(String::as_str) {
+        Some("no") => vec![],
+        mode => {
+            let ignore = worktree::get_ignore_stack(&repo)?;
+            let collapse_dirs = mode != Some("all");
+            let mut untracked = untracked_files(
+                repo.worktree(),
+                &index,
+                ignore,
+                collapse_dirs,
+            )?;
+            untracked.sort();
+            untracked
+        }
+    };
+    let branch = Branch::of(&repo)?;
+
+    let null = args.get("null-terminated").is_some();
+    let show_branch = args.get("branch").is_some();
+    let format = match args.get("porcelain").map(String::as_str) {
+        _ if args.get("json").is_some() => Format::Json,
+        Some("v2") => Format::V2,
+        Some(_) => Format::V1,
+        None if null => Format::V1,
+        None => Format::Long,
+    };
+    let eol = if null { '\0' } else { '\n' };
+
+    let mut out = String::new();
+    match format {
+        Format::Long => write_long(&mut out, &branch, &changes, &untracked),
+        Format::V1 => {
+            if show_branch {
+                let _ = write!(out, "## {}{eol}", branch.v1_header());
+            }
+            for change in &changes {
+                write_v1(&mut out, change, null);
+                out.push(eol);
+            }
+            for path in &untracked {
+                let _ = write!(out, "?? {path}{eol}");
+            }
+        }
+        Format::V2 => {
+            let null_id = repo.object_format().null_id();
+            if show_branch {
+                let oid = branch.head.as_deref().unwrap_or("(initial)");
+                let head = branch.name.as_deref().unwrap_or("(detached)");
+                let _ = write!(out, "# branch.oid {oid}{eol}");
+                let _ = write!(out, "# branch.head {head}{eol}");
+            }
+            for change in &changes {
+                write_v2(&mut out, change, &null_id, null);
+                out.push(eol);
+            }
+            for path in &untracked {
+                let _ = write!(out, "? {path}{eol}");
+            }
+        }
+        Format::Json => {
+            let status = Json::object([
+                ("branch", branch.name.into()),
+                ("oid", branch.head.into()),
+                ("changes", changes.iter().map(change_json).collect()),
+                ("untracked", untracked.into()),
+            ]);
+            out.push_str(&status.to_string());
+        }
+    }
+    Ok(out)
+}
+
+/// How the status is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    /// Sections for people to read
+    Long,
+    /// `--porcelain=v1`
+    V1,
+    /// `--porcelain=v2`
+    V2,
+    /// `--json`
+    Json,
+}
+
+/// The branch checked out, and the commit it is at.
+struct Branch {
+    /// The name of the branch, or `None` if `HEAD` is detached
+    name: Option<String>,
+    /// The commit `HEAD` points to, or `None` if there are no commits yet
+    head: Option<String>,
+}
+
+impl Branch {
+    fn of(repo: &GitRepository) -> Result<Self, Error> {
+        Ok(Self {
+            name: repo.head_branch(),
+            head: repo.head()?.map(|oid| oid.to_string()),
+        })
+    }
+
+    /// The branch, as after `##` in `--porcelain=v1 --branch`.
+    fn v1_header(&self) -> String {
+        match (&self.name, &self.head) {
+            (Some(name), Some(_)) => name.clone(),
+            (Some(name), None) => format!("No commits yet on {name}"),
+            (None, _) => "HEAD (no branch)".to_owned(),
+        }
+    }
+}
+
+/// A mode and an object name, of a path in `HEAD`, the index or a stage
+type Side = (u32, String);
+
+/// How a tracked path differs between `HEAD`, the index and the working
+/// tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Change {
+    path: String,
+    /// How the index differs from `HEAD`
+    staged: char,
+    /// How the working tree differs from the index
+    unstaged: char,
+    /// The path in `HEAD` of a renamed file
+    orig: Option<String>,
+    head: Option<Side>,
+    index: Option<Side>,
+    /// The mode in the working tree, 0 if the file is missing
+    worktree_mode: u32,
+    /// The stages 1 to 3 of a conflicted path
+    stages: Option<[Option<Side>; 3]>,
+}
+
+impl Change {
+    fn new(path: &str, head: Option<Side>, index: Option<Side>) -> Self {
+        let staged = match (&head, &index) {
+            (None, Some(_)) => 'A',
+            (Some(_), None) => 'D',
+            (Some(head), Some(index))
+                if head.0 & TYPE_MASK != index.0 & TYPE_MASK =>
+            {
+                'T'
+            }
+            (Some(head), Some(index)) if head != index => 'M',
+            _ => ' ',
+        };
+        Self {
+            path: path.to_owned(),
+            staged,
+            unstaged: ' ',
+            orig: None,
+            head,
+            index,
+            worktree_mode: 0,
+            stages: None,
+        }
+    }
+
+    /// The two status letters.
+    fn xy(&self) -> String {
+        format!("{}{}", self.staged, self.unstaged)
+    }
+
+    /// Whether the path is a submodule in any of its versions.
+    fn is_submodule(&self) -> bool {
+        let stages = self.stages.iter().flatten().flatten();
+        self.head
+            .iter()
+            .chain(&self.index)
+            .chain(stages)
+            .any(|(mode, _)| mode & TYPE_MASK == GITLINK_TYPE)
+    }
+}
+
+/// The tracked paths that differ between `HEAD`, the index and the working
+/// tree, in path order.
+fn tracked_changes(
+    repo: &GitRepository,
+    index: &GitIndex,
+) -> Result<Vec<Change>, Error> {
+    let mut head = BTreeMap::new();
+    if let Some(commit) = repo.head()? {
+        let tree = repo.find_commit(&commit)?.tree()?;
+        for entry in TreeWalker::new(repo, &tree) {
+            let entry = entry?;
+            head.insert(entry.path, (entry.mode, entry.oid.to_string()));
+        }
+    }
+
+    let mut changes = BTreeMap::new();
+    for entry in index.entries() {
+        let stage = usize::from(entry.stage());
+        if stage != 0 {
+            let change =
+                changes.entry(entry.path.clone()).or_insert_with(|| {
+                    let mut change = Change::new(&entry.path, None, None);
+                    change.head = head.get(&entry.path).cloned();
+                    change.stages = Some([None, None, None]);
+                    change
+                });
+            if let Some(stages) = &mut change.stages {
+                stages[stage - 1] = Some((entry.mode, entry.sha.clone()));
+            }
+            continue;
+        }
+
+        let side = (entry.mode, entry.sha.clone());
+        let mut change = Change::new(
+            &entry.path,
+            head.get(&entry.path).cloned(),
+            Some(side),
+        );
+        (change.unstaged, change.worktree_mode) =
+            match index.worktree_state(repo, entry)? {
+                WorktreeState::Unchanged => (' ', entry.mode),
+                WorktreeState::Modified => ('M', entry.mode),
+                WorktreeState::Deleted => ('D', 0),
+            };
+        changes.insert(entry.path.clone(), change);
+    }
+    for (path, side) in &head {
+        if !changes.contains_key(path) {
+            changes.insert(
+                path.clone(),
+                Change::new(path, Some(side.clone()), None),
+            );
+        }
+    }
+
+    for change in changes.values_mut() {
+        if let Some(stages) = &change.stages {
+            let codes = match stages.each_ref().map(Option::is_some) {
+                [true, false, false] => "DD",
+                [false, true, false] => "AU",
+                [true, true, false] => "UD",
+                [false, false, true] => "UA",
+                [true, false, true] => "DU",
+                [false, true, true] => "AA",
+                _ => "UU",
+            };
+            let mut codes = codes.chars();
+            change.staged = codes.next().unwrap_or('U');
+            change.unstaged = codes.next().unwrap_or('U');
+        }
+    }
+    find_renames(&mut changes);
+
+    Ok(changes
+        .into_values()
+        .filter(|change| change.staged != ' ' || change.unstaged != ' ')
+        .collect())
+}
+
+/// Pairs the files deleted from the index with those added to it with the
+/// same contents, and marks them as renamed.
+fn find_renames(changes: &mut BTreeMap<String, Change>) {
+    let mut deleted: HashMap<&Side, Vec<&String>> = HashMap::new();
+    for change in changes.values() {
+        if let (Some(head), 'D', None) =
+            (&change.head, change.staged, &change.stages)
+        {
+            deleted.entry(head).or_default().push(&change.path);
+        }
+    }
+
+    let mut renames = vec![];
+    for change in changes.values() {
+        let Some(index) =
+            change.index.as_ref().filter(|_| change.staged == 'A')
+        else {
+            continue;
+        };
+        // Renamed files keep their mode too
+        if let Some(orig) = deleted.get_mut(index).and_then(Vec::pop) {
+            renames.push((change.path.clone(), orig.clone()));
+        }
+    }
+
+    for (path, orig) in renames {
+        let Some(old) = changes.remove(&orig) else {
+            continue;
+        };
+        if let Some(change) = changes.get_mut(&path) {
+            change.staged = 'R';
+            change.head = old.head;
+            change.orig = Some(orig);
+        }
+    }
+}
+
+/// Writes `change` in the format of `--porcelain=v1`, without the
+/// terminator.
+fn write_v1(out: &mut String, change: &Change, null: bool) {
+    out.push_str(&change.xy());
+    out.push(' ');
+    match &change.orig {
+        Some(orig) if null => {
+            out.push_str(&change.path);
+            out.push('\0');
+            out.push_str(orig);
+        }
+        Some(orig) => {
+            out.push_str(orig);
+            out.push_str(" -> ");
+            out.push_str(&change.path);
+        }
+        None => out.push_str(&change.path),
+    }
+}
+
+/// Writes `change` in the format of `--porcelain=v2`, without the
+/// terminator.
+fn write_v2(out: &mut String, change: &Change, null_id: &str, null: bool) {
+    let xy = change.xy().replace(' ', ".");
+    let sub = if change.is_submodule() {
+        "S..."
+    } else {
+        "N..."
+    };
+    let mode = |side: Option<&Side>| format!("{:06o}", side.map_or(0, |s| s.0));
+    let sha =
+        |side: Option<&Side>| side.map_or(null_id, |s| s.1.as_str()).to_owned();
+    let worktree = format!("{:06o}", change.worktree_mode);
+
+    let _ = if let Some(stages) = &change.stages {
+        let [one, two, three] = stages.each_ref().map(Option::as_ref);
+        write!(
+            out,
+            "u {xy} {sub} {} {} {} {worktree} {} {} {} {}",
+            mode(one),
+            mode(two),
+            mode(three),
+            sha(one),
+            sha(two),
+            sha(three),
+            change.path
+        )
+    } else {
+        let (head, index) = (change.head.as_ref(), change.index.as_ref());
+        let fields = format!(
+            "{xy} {sub} {} {} {worktree} {} {}",
+            mode(head),
+            mode(index),
+            sha(head),
+            sha(index)
+        );
+        match &change.orig {
+            Some(orig) => {
+                let sep = if null { '\0' } else { '\t' };
+                write!(out, "2 {fields} R100 {}{sep}{orig}", change.path)
+            }
+            None => write!(out, "1 {fields} {}", change.path),
+        }
+    };
+}
+
+/// The change as a member of the `changes` of `--json`.
+fn change_json(change: &Change) -> Json {
+    let code = |status: char| {
+        let status = if status == ' ' { '.' } else { status };
+        Json::from(status.to_string())
+    };
+    Json::object([
+        ("path", change.path.as_str().into()),
+        ("orig", change.orig.as_deref().into()),
+        ("index", code(change.staged)),
+        ("worktree", code(change.unstaged)),
+        ("unmerged", change.stages.is_some().into()),
+    ])
+}
+
+/// Writes the status in sections for people to read.
+fn write_long(
+    out: &mut String,
+    branch: &Branch,
+    changes: &[Change],
+    untracked: &[String],
+) {
+    let _ = match (&branch.name, &branch.head) {
+        (Some(name), _) => writeln!(out, "On branch {name}"),
+        (None, Some(head)) => {
+            writeln!(out, "HEAD detached at {}", &head[..head.len().min(7)])
+        }
+        (None, None) => writeln!(out, "Not currently on any branch."),
+    };
+    if branch.head.is_none() {
+        out.push_str("\nNo commits yet\n");
+    }
+
+    let unmerged = changes
+        .iter()
+        .filter(|change| change.stages.is_some())
+        .map(|change| (unmerged_label(&change.xy()), change))
+        .collect::<Vec<_>>();
+    let staged = changes
+        .iter()
+        .filter(|change| change.stages.is_none() && change.staged != ' ')
+        .map(|change| (change_label(change.staged), change))
+        .collect::<Vec<_>>();
+    let unstaged = changes
+        .iter()
+        .filter(|change| change.stages.is_none() && change.unstaged != ' ')
+        .map(|change| (change_label(change.unstaged), change))
+        .collect::<Vec<_>>();
+
+    write_section(out, "Changes to be committed", &staged, 12);
+    write_section(out, "Unmerged paths", &unmerged, 17);
+    write_section(out, "Changes not staged for commit", &unstaged, 12);
+    if !untracked.is_empty() {
+        out.push_str("\nUntracked files:\n");
+        for path in untracked {
+            let _ = writeln!(out, "\t{path}");
+        }
+    }
+
+    let trailer = if !staged.is_empty() {
+        return;
+    } else if !unstaged.is_empty() || !unmerged.is_empty() {
+        "no changes added to commit"
+    } else if !untracked.is_empty() {
+        "nothing added to commit but untracked files present"
+    } else if branch.head.is_none() {
+        "nothing to commit"
+    } else {
+        "nothing to commit, working tree clean"
+    };
+    let _ = writeln!(out, "\n{trailer}");
+}
+
+/// Writes a section of the long format, with each path after its label
+/// padded to `width`.
+fn write_section(
+    out: &mut String,
+    title: &str,
+    changes: &[(&str, &Change)],
+    width: usize,
+) {
+    if changes.is_empty() {
+        return;
+    }
+    let _ = writeln!(out, "\n{title}:");
+    for (label, change) in changes {
+        let label = format!("{label}:");
+        let _ = match &change.orig {
+            Some(orig) => {
+                writeln!(out, "\t{label:<width$}{orig} -> {}", change.path)
+            }
+            None => writeln!(out, "\t{label:<width$}{}", change.path),
+        };
+    }
+}
+
+/// How the long format describes a change of status `status`.
+fn change_label(status: char) -> &'static str {
+    match status {
+        'A' => "new file",
+        'D' => "deleted",
+        'R' => "renamed",
+        'T' => "typechange",
+        _ => "modified",
+    }
+}
+
+/// How the long format describes a conflict with codes `xy`.
+fn unmerged_label(xy: &str) -> &'static str {
+    match xy {
+        "DD" => "both deleted",
+        "AU" => "added by us",
+        "UD" => "deleted by them",
+        "UA" => "added by them",
+        "DU" => "deleted by us",
+        "AA" => "both added",
+        _ => "both modified",
+    }
+}
+
+/// Make `status` parser
+#[must_use]
+pub fn make_parser() -> ArgumentParser {
+    let mut parser = ArgumentParser::new("Show the working tree status");
+
+    parser
+        .add_argument("branch", ArgumentType::Boolean)
+        .optional()
+        .short('b')
+        .add_help("Show the branch in the porcelain formats");
+
+    parser
+        .add_argument("json", ArgumentType::Boolean)
+        .optional()
+        .add_help("Print the status as JSON");
+
+    parser
+        .add_argument("null-terminated", ArgumentType::Boolean)
+        .optional()
+        .short('z')
+        .add_help("Terminate entries with NUL instead of a newline");
+
+    parser
+        .add_argument("porcelain", ArgumentType::String)
+        .optional()
+        .choices(&["v1", "v2"])
+        .implicit_value("v1")
+        .add_help("Give the output in a stable format for scripts");
+
+    parser
+        .add_argument("untracked-files", ArgumentType::String)
+        .optional()
+        .short('u')
+        .choices(&["no", "normal", "all"])
+        .implicit_value("all")
+        .add_help(
+            "Show no untracked files, untracked directories by name \
+            (normal), or all untracked files",
+        );
+
+    parser
+}
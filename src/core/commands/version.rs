@@ -0,0 +1,78 @@
+use std::fmt::Write as _;
+
+use crate::core::{Error, ObjectFormat};
+use crate::utils::argparse::{ArgumentParser, ArgumentType, Namespace};
+
+/// The optional cargo features the binary was built with. The crate has
+/// none yet, so this is always empty.
+const FEATURES: &[&str] = &[];
+
+/// Report the version of `mini_git`.
+/// This handles the subcommand
+///
+/// ```bash
+/// mini_git version [--build-options]
+/// ```
+///
+/// `mini_git --version` is the same as `mini_git version`. Besides the
+/// version, the object formats that are supported and the features that
+/// are enabled are reported, along with the platform the binary was built
+/// for with `--build-options`.
+///
+/// # Errors
+///
+/// This command does not fail, the [`Result`] is for consistency with the
+/// other commands.
+#[allow(clippy::module_name_repetitions)]
+#[allow(clippy::unnecessary_wraps)]
+pub fn version(args: &Namespace) -> Result<String, Error> {
+    Ok(version_info(args.get("build-options").is_some()))
+}
+
+/// The version report, with the platform the binary was built for if
+/// `build_options` is set.
+///
+/// # Examples
+///
+/// ```
+/// use mini_git::core::commands::version::version_info;
+///
+/// let info = version_info(false);
+/// assert!(info.starts_with("mini_git version "));
+/// assert!(info.contains("object formats: sha1 sha256"));
+/// ```
+#[must_use]
+pub fn version_info(build_options: bool) -> String {
+    let mut info = format!("mini_git version {}\n", env!("CARGO_PKG_VERSION"));
+
+    let formats = ObjectFormat::ALL.map(ObjectFormat::name);
+    let _ = writeln!(info, "object formats: {}", formats.join(" "));
+    let features = match FEATURES {
+        [] => "none".to_owned(),
+        features => features.join(" "),
+    };
+    let _ = writeln!(info, "features: {features}");
+
+    if build_options {
+        let _ = writeln!(info, "cpu: {}", std::env::consts::ARCH);
+        let _ = writeln!(info, "os: {}", std::env::consts::OS);
+        let profile = if cfg!(debug_assertions) {
+            "debug"
+        } else {
+            "release"
+        };
+        let _ = writeln!(info, "profile: {profile}");
+    }
+    info
+}
+
+/// Make `version` parser
+#[must_use]
+pub fn make_parser() -> ArgumentParser {
+    let mut parser = ArgumentParser::new("Report the version of mini_git");
+    parser
+        .add_argument("build-options", ArgumentType::Boolean)
+        .optional()
+        .add_help("Also report the platform mini_git was built for");
+    parser
+}
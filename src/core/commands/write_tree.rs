@@ -0,0 +1,34 @@
+use crate::core::index::GitIndex;
+use crate::core::{resolve_repository_context, Error};
+use crate::utils::argparse::{ArgumentParser, Namespace};
+
+/// Create a tree object from the current index.
+/// This handles the subcommand
+///
+/// ```bash
+/// mini_git write-tree
+/// ```
+///
+/// The name of the new tree object is shown. The trees of directories that
+/// have not changed since the last `write-tree` are reused from the index's
+/// cached trees, which are updated with the trees that are written.
+///
+/// # Errors
+///
+/// If the index is malformed or has conflicted entries, or the objects or
+/// the index cannot be written.
+/// An [`Error`] describing the failure is returned.
+#[allow(clippy::module_name_repetitions)]
+pub fn write_tree(_args: &Namespace) -> Result<String, Error> {
+    let repo = resolve_repository_context()?.repo;
+    let mut index = GitIndex::read(&repo)?;
+    let sha = index.write_tree(&repo)?;
+    index.write(&repo)?;
+    Ok(sha)
+}
+
+/// Make `write-tree` parser
+#[must_use]
+pub fn make_parser() -> ArgumentParser {
+    ArgumentParser::new("Create a tree object from the current index")
+}
@@ -0,0 +1,219 @@
+//! Loading git configuration files
+//!
+//! A configuration file can include other files with the `path` key of an
+//! `[include]` section, or of an `[includeIf "<condition>"]` section whose
+//! condition holds. The included settings take effect where the `path` key
+//! is, so settings after it override them.
+//!
+//! The conditions are
+//! - `gitdir:<pattern>`, if the git directory matches the glob `pattern`,
+//!   and `gitdir/i:<pattern>` to match case-insensitively.
+//! - `onbranch:<pattern>`, if the branch checked out matches `pattern`.
+//!
+//! A pattern that does not start with `/`, `~/` or `./` can match at any
+//! depth, and one that ends with `/` matches everything below it. Relative
+//! include paths, and `./` in patterns, are relative to the directory of the
+//! including file.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::core::Error;
+use crate::utils::configparser::ConfigParser;
+use crate::utils::gitignore::wildmatch;
+
+/// How deeply files may include each other.
+const MAX_INCLUDE_DEPTH: usize = 10;
+
+/// Load the configuration file `file`, along with the files it includes.
+///
+/// `gitdir` is the git directory the configuration is for, if any, which
+/// the include conditions are checked against. Without it, no conditional
+/// includes are followed.
+///
+/// # Errors
+///
+/// If `file` or an included file cannot be read, or files include each
+/// other.
+pub fn load(file: &Path, gitdir: Option<&Path>) -> Result<ConfigParser, Error> {
+    load_all(&[file], gitdir)
+}
+
+/// Load the configuration files `files`, along with the files they include,
+/// with the settings of later files overriding those of earlier ones.
+///
+/// # Errors
+///
+/// If any of the files or an included file cannot be read, or files include
+/// each other.
+pub fn load_all(
+    files: &[&Path],
+    gitdir: Option<&Path>,
+) -> Result<ConfigParser, Error> {
+    let mut lines = vec![];
+    for file in files {
+        expand_includes(file, gitdir, &mut vec![], &mut lines)?;
+    }
+    Ok(lines.into_iter().collect())
+}
+
+/// Append the lines of `file` to `lines`, with the lines of every file it
+/// includes in place of the `path` key that includes it.
+///
+/// `stack` holds the files that are being included, outermost first.
+fn expand_includes(
+    file: &Path,
+    gitdir: Option<&Path>,
+    stack: &mut Vec<PathBuf>,
+    lines: &mut Vec<String>,
+) -> Result<(), Error> {
+    let canonical = file.canonicalize().map_err(|e| {
+        format!("Failed to read config file {}: {e}", file.display())
+    })?;
+    if stack.contains(&canonical) {
+        return Err(
+            format!("Config file {} includes itself", file.display()).into()
+        );
+    }
+    if stack.len() > MAX_INCLUDE_DEPTH {
+        return Err(format!(
+            "Exceeded the maximum include depth ({MAX_INCLUDE_DEPTH}) \
+            while including {}",
+            file.display()
+        )
+        .into());
+    }
+    let text = fs::read_to_string(&canonical).map_err(|e| {
+        format!("Failed to read config file {}: {e}", file.display())
+    })?;
+
+    let dir = canonical.parent().unwrap_or(Path::new("/")).to_path_buf();
+    stack.push(canonical);
+    let mut section = String::new();
+    let mut including = false;
+    for line in text.lines() {
+        lines.push(line.to_owned());
+        let line = line.trim();
+        if let Some(name) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            name.clone_into(&mut section);
+            including = includes(&section, &dir, gitdir);
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        if !including || !key.trim().eq_ignore_ascii_case("path") {
+            continue;
+        }
+        let path = expand_home(unquote(value.trim()))
+            .map(|path| dir.join(path))
+            .filter(|path| path.is_file());
+        // Like git, files that do not exist are skipped
+        if let Some(path) = path {
+            expand_includes(&path, gitdir, stack, lines)?;
+            // The settings after the include are in this section again
+            lines.push(format!("[{section}]"));
+        }
+    }
+    stack.pop();
+    Ok(())
+}
+
+/// Whether the `path` keys of the section named `section` include files,
+/// for a file in `dir`.
+fn includes(section: &str, dir: &Path, gitdir: Option<&Path>) -> bool {
+    if section.eq_ignore_ascii_case("include") {
+        return true;
+    }
+    let Some((name, condition)) = section.split_once(' ') else {
+        return false;
+    };
+    if !name.eq_ignore_ascii_case("includeIf") {
+        return false;
+    }
+    let Some(gitdir) = gitdir else {
+        return false;
+    };
+
+    let condition = unquote(condition.trim());
+    if let Some(pattern) = condition.strip_prefix("gitdir:") {
+        gitdir_matches(pattern, dir, gitdir, false)
+    } else if let Some(pattern) = condition.strip_prefix("gitdir/i:") {
+        gitdir_matches(pattern, dir, gitdir, true)
+    } else if let Some(pattern) = condition.strip_prefix("onbranch:") {
+        current_branch(gitdir).is_some_and(|branch| {
+            let pattern = match pattern.strip_suffix('/') {
+                Some(_) => format!("{pattern}**"),
+                None => pattern.to_owned(),
+            };
+            wildmatch(pattern.as_bytes(), branch.as_bytes())
+        })
+    } else {
+        false
+    }
+}
+
+/// Whether the git directory matches the pattern of a `gitdir:` condition
+/// in a file in `dir`.
+fn gitdir_matches(
+    pattern: &str,
+    dir: &Path,
+    gitdir: &Path,
+    ignore_case: bool,
+) -> bool {
+    let Some(pattern) = expand_home(pattern) else {
+        return false;
+    };
+    let mut pattern = match pattern.strip_prefix("./").ok() {
+        Some(rest) => dir.join(rest),
+        None => pattern,
+    }
+    .to_string_lossy()
+    .into_owned();
+    if !pattern.starts_with('/') {
+        pattern.insert_str(0, "**/");
+    }
+    if pattern.ends_with('/') {
+        pattern.push_str("**");
+    }
+
+    let gitdir = gitdir
+        .canonicalize()
+        .unwrap_or_else(|_| gitdir.to_path_buf());
+    let mut gitdir = gitdir.to_string_lossy().into_owned();
+    if ignore_case {
+        pattern = pattern.to_lowercase();
+        gitdir = gitdir.to_lowercase();
+    }
+    wildmatch(pattern.as_bytes(), gitdir.as_bytes())
+}
+
+/// The name of the branch checked out in `gitdir`, if any.
+pub(crate) fn current_branch(gitdir: &Path) -> Option<String> {
+    let head = fs::read_to_string(gitdir.join("HEAD")).ok()?;
+    head.trim()
+        .strip_prefix("ref: refs/heads/")
+        .map(String::from)
+}
+
+/// Expand a leading `~/` to the home directory.
+fn expand_home(path: &str) -> Option<PathBuf> {
+    match path.strip_prefix("~/") {
+        Some(rest) => {
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(rest))
+        }
+        None => Some(PathBuf::from(path)),
+    }
+}
+
+/// Remove the double quotes around a value, if any.
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value)
+}
@@ -0,0 +1,182 @@
+//! Errors of the repository, object and command APIs
+//!
+//! Failures are an [`Error`], whose variants tell apart the failures a
+//! caller may want to handle, like a missing object or an abbreviation
+//! naming more than one, from those it can only report. Every variant
+//! displays as the message the commands print.
+//!
+//! Errors from the string based parsers in [`crate::utils`] become
+//! [`Error::Other`] through `?`, and an [`Error`] becomes its message again
+//! where a `String` error is expected, like at the command line.
+
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use crate::core::objects::oid::Oid;
+
+/// A failure of an operation on a repository.
+///
+/// # Examples
+///
+/// ```
+/// use mini_git::core::Error;
+///
+/// let err = Error::ObjectNotFound("e69de29".to_owned());
+/// assert!(matches!(err, Error::ObjectNotFound(_)));
+/// assert_eq!(err.to_string(), "Object e69de29 not found in repository");
+///
+/// let msg: String = Error::from("bad header").into();
+/// assert_eq!(msg, "bad header");
+/// ```
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// No object has the name, or abbreviated name, given.
+    ObjectNotFound(String),
+    /// An abbreviated name or ref names more than one object.
+    Ambiguous {
+        /// The name as given
+        name: String,
+        /// The objects it could name
+        candidates: Vec<Oid>,
+    },
+    /// A file or directory could not be read or written.
+    Io {
+        /// What was being done, like "Failed to read index file"
+        context: String,
+        /// The error of the operation
+        source: io::Error,
+    },
+    /// Compressed data, of an object or a pack entry, is corrupt.
+    Zlib(String),
+    /// Something read is malformed, like an object, a ref, an object name
+    /// or the index.
+    Parse(String),
+    /// The lock file of a ref or the index exists, so another process is
+    /// writing it, or crashed while doing so.
+    Locked(PathBuf),
+    /// The arguments of a command are invalid, like options that cannot be
+    /// used together.
+    Usage(String),
+    /// Any other failure, described by its message.
+    Other(String),
+}
+
+impl Error {
+    /// Makes an [`Error::Io`] of `source`, which failed while doing what
+    /// `context` describes.
+    pub fn io(context: impl Into<String>, source: io::Error) -> Self {
+        Self::Io {
+            context: context.into(),
+            source,
+        }
+    }
+
+    /// The status the command line exits with on this error, like git: 129
+    /// for invalid arguments, and 128 for any other failure.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mini_git::core::Error;
+    ///
+    /// assert_eq!(Error::Usage("bad option".to_owned()).exit_code(), 129);
+    /// assert_eq!(Error::from("failed").exit_code(), 128);
+    /// ```
+    #[must_use]
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Usage(_) => 129,
+            _ => 128,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ObjectNotFound(name) => {
+                write!(f, "Object {name} not found in repository")
+            }
+            Self::Ambiguous { name, candidates } => {
+                write!(f, "Ambiguous reference {name}: Candidates are:")?;
+                candidates
+                    .iter()
+                    .try_for_each(|oid| write!(f, "\n - {oid}"))
+            }
+            Self::Io { context, source } => write!(f, "{context}: {source}"),
+            Self::Zlib(msg)
+            | Self::Parse(msg)
+            | Self::Usage(msg)
+            | Self::Other(msg) => f.write_str(msg),
+            Self::Locked(path) => write!(
+                f,
+                "Unable to create '{}': File exists. Another process seems \
+                 to be running in this repository",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<String> for Error {
+    fn from(msg: String) -> Self {
+        Self::Other(msg)
+    }
+}
+
+impl From<&str> for Error {
+    fn from(msg: &str) -> Self {
+        Self::Other(msg.to_owned())
+    }
+}
+
+impl From<Error> for String {
+    fn from(err: Error) -> Self {
+        err.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let oid: Oid =
+            "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391".parse().unwrap();
+        let err = Error::Ambiguous {
+            name: "e69d".to_owned(),
+            candidates: vec![oid, oid],
+        };
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Ambiguous reference e69d: Candidates are:\n - {oid}\n - {oid}"
+            )
+        );
+
+        let source = io::Error::new(io::ErrorKind::NotFound, "gone");
+        let err = Error::io("Failed to read index file", source);
+        assert_eq!(err.to_string(), "Failed to read index file: gone");
+        assert!(std::error::Error::source(&err).is_some());
+
+        let err = Error::Locked(PathBuf::from(".git/index.lock"));
+        assert!(err
+            .to_string()
+            .starts_with("Unable to create '.git/index.lock'"));
+        assert!(
+            matches!(Error::from("bad"), Error::Other(msg) if msg == "bad")
+        );
+    }
+}
@@ -0,0 +1,313 @@
+//! High-level access to a repository
+//!
+//! The methods here read and write a [`GitRepository`] in terms of commits,
+//! trees, blobs and branches, for using mini-git as a library rather than
+//! through the commands, which parse arguments and return what they print.
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use mini_git::core::GitRepository;
+//!
+//! let repo = GitRepository::new(Path::new("."))?;
+//! if let Some(head) = repo.head()? {
+//!     let commit = repo.find_commit(&head)?;
+//!     println!("{head:.7} {}", commit.message().lines().next().unwrap_or(""));
+//! }
+//! for (branch, target) in repo.branches()? {
+//!     println!("{branch} {target}");
+//! }
+//! # Ok::<(), String>(())
+//! ```
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use crate::core::commands::diff::{self, FileDiff};
+use crate::core::config;
+use crate::core::objects::blob::Blob;
+use crate::core::objects::commit::{Commit, Signature};
+use crate::core::objects::oid::Oid;
+use crate::core::objects::revwalk::Revwalk;
+use crate::core::objects::traits::KVLM;
+use crate::core::objects::tree::{self, Tree, TreeEntry};
+use crate::core::objects::{self, GitObject};
+use crate::core::{has_lock_suffix, Error, GitRepository};
+
+/// Where branches are, relative to the common directory.
+const BRANCH_REFS: &str = "refs/heads/";
+
+impl GitRepository {
+    /// The commit `HEAD` points to, or `None` on a branch with no commits
+    /// yet.
+    ///
+    /// # Errors
+    ///
+    /// If `HEAD` cannot be read, or names a malformed object.
+    pub fn head(&self) -> Result<Option<Oid>, Error> {
+        objects::resolve_ref(self, "HEAD")
+    }
+
+    /// The branch checked out, or `None` if `HEAD` is detached.
+    #[must_use]
+    pub fn head_branch(&self) -> Option<String> {
+        config::current_branch(self.gitdir())
+    }
+
+    /// The object named by `name`, which can be a ref, a branch, a tag or
+    /// an abbreviated object name.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::ObjectNotFound`] if nothing has the name, and an
+    /// [`Error::Ambiguous`] if more than one object does.
+    pub fn resolve(&self, name: &str) -> Result<Oid, Error> {
+        objects::find_object(self, name, None, false)
+    }
+
+    /// Reads the commit named `oid`.
+    ///
+    /// # Errors
+    ///
+    /// If the object cannot be read, or is not a commit.
+    pub fn find_commit(&self, oid: &Oid) -> Result<Commit, Error> {
+        match objects::read_object(self, oid)? {
+            GitObject::Commit(commit) => Ok(commit),
+            obj => Err(not_a(oid, &obj, "commit")),
+        }
+    }
+
+    /// Reads the tree named `oid`.
+    ///
+    /// # Errors
+    ///
+    /// If the object cannot be read, or is not a tree.
+    pub fn find_tree(&self, oid: &Oid) -> Result<Tree, Error> {
+        match objects::read_object(self, oid)? {
+            GitObject::Tree(tree) => Ok(tree),
+            obj => Err(not_a(oid, &obj, "tree")),
+        }
+    }
+
+    /// Reads the blob named `oid`.
+    ///
+    /// # Errors
+    ///
+    /// If the object cannot be read, or is not a blob.
+    pub fn blob(&self, oid: &Oid) -> Result<Blob, Error> {
+        match objects::read_object(self, oid)? {
+            GitObject::Blob(blob) => Ok(blob),
+            obj => Err(not_a(oid, &obj, "blob")),
+        }
+    }
+
+    /// Writes `data` to the repository as a blob, and returns its name.
+    ///
+    /// # Errors
+    ///
+    /// If the blob cannot be written.
+    pub fn write_blob(&self, data: &[u8]) -> Result<Oid, Error> {
+        objects::write_object(&GitObject::Blob(Blob::from(data)), self)
+    }
+
+    /// The signature of the user at the current time, from `user.name` and
+    /// `user.email` in the configuration.
+    ///
+    /// # Errors
+    ///
+    /// If either is not configured.
+    pub fn signature(&self) -> Result<Signature, Error> {
+        let user = self.config().get("user");
+        let get = |key| user.and_then(|user| user.get(key));
+        let (Some(name), Some(email)) = (get("name"), get("email")) else {
+            return Err("Author identity unknown, set user.name and \
+                        user.email"
+                .into());
+        };
+        Ok(Signature::now(name, email))
+    }
+
+    /// Writes a commit of `tree` with `parents`, and returns its name.
+    ///
+    /// If `update_ref` is given, the ref is pointed at the new commit, so
+    /// `Some("HEAD")` advances the branch checked out. The message is
+    /// written as is, so it should end with a newline.
+    ///
+    /// # Errors
+    ///
+    /// If `tree` is not a tree, a parent is not a commit, or the commit or
+    /// the ref cannot be written.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use mini_git::core::index::GitIndex;
+    /// use mini_git::core::GitRepository;
+    ///
+    /// let repo = GitRepository::new(Path::new("."))?;
+    /// let tree = GitIndex::read(&repo)?.write_tree(&repo)?.parse()?;
+    /// let sig = repo.signature()?;
+    /// let parents: Vec<_> = repo.head()?.into_iter().collect();
+    /// repo.create_commit(Some("HEAD"), &sig, &sig, "Update\n", &tree, &parents)?;
+    /// # Ok::<(), String>(())
+    /// ```
+    pub fn create_commit(
+        &self,
+        update_ref: Option<&str>,
+        author: &Signature,
+        committer: &Signature,
+        message: &str,
+        tree: &Oid,
+        parents: &[Oid],
+    ) -> Result<Oid, Error> {
+        self.find_tree(tree)?;
+        let mut data = format!("tree {tree}\n");
+        for parent in parents {
+            self.find_commit(parent)?;
+            let _ = writeln!(data, "parent {parent}");
+        }
+        let _ = write!(data, "author {author}\ncommitter {committer}\n\n");
+        data.push_str(message);
+
+        let commit = Commit::deserialize(data.as_bytes())?;
+        let oid = objects::write_object(&GitObject::Commit(commit), self)?;
+        if let Some(name) = update_ref {
+            objects::update_ref(self, name, &oid)?;
+        }
+        Ok(oid)
+    }
+
+    /// A walk of the history of the repository, which lists nothing until
+    /// commits are pushed onto it.
+    #[must_use]
+    pub fn revwalk(&self) -> Revwalk<'_> {
+        Revwalk::new(self)
+    }
+
+    /// The differences between the trees `old` and `new`, one file at a
+    /// time in path order, where a `None` tree is the empty tree. Each
+    /// change has 3 lines of context, as `mini_git diff` shows.
+    ///
+    /// Submodules have no contents to diff, and are left out.
+    ///
+    /// # Errors
+    ///
+    /// If a tree or a changed blob cannot be read.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use mini_git::core::commands::diff::LineOrigin;
+    /// use mini_git::core::GitRepository;
+    ///
+    /// let repo = GitRepository::new(Path::new("."))?;
+    /// let commit = repo.find_commit(&repo.resolve("HEAD")?)?;
+    /// let parent = repo.find_commit(&commit.parents()?[0])?;
+    /// let diffs =
+    ///     repo.diff_tree_to_tree(Some(&parent.tree()?), Some(&commit.tree()?))?;
+    /// for diff in diffs {
+    ///     let lines = diff.hunks.iter().flat_map(|hunk| &hunk.lines);
+    ///     let added = lines
+    ///         .filter(|line| line.origin == LineOrigin::Addition)
+    ///         .count();
+    ///     println!("{:?} +{added}", diff.new_path);
+    /// }
+    /// # Ok::<(), String>(())
+    /// ```
+    pub fn diff_tree_to_tree(
+        &self,
+        old: Option<&Oid>,
+        new: Option<&Oid>,
+    ) -> Result<Vec<FileDiff>, Error> {
+        let read = |entry: &Option<TreeEntry>| {
+            entry
+                .as_ref()
+                .map(|entry| {
+                    self.blob(&entry.oid).map(|blob| blob.data().to_vec())
+                })
+                .transpose()
+        };
+        let mut diffs = Vec::new();
+        for delta in tree::diff_trees(self, old, new)? {
+            let is_gitlink = |entry: &Option<TreeEntry>| {
+                entry.as_ref().is_some_and(TreeEntry::is_gitlink)
+            };
+            if is_gitlink(&delta.old) || is_gitlink(&delta.new) {
+                continue;
+            }
+            let (old_data, new_data) = (read(&delta.old)?, read(&delta.new)?);
+            let mut diff = diff::diff_blobs(
+                &delta.path,
+                old_data.as_deref(),
+                new_data.as_deref(),
+                3,
+            );
+            diff.status = delta.delta();
+            diffs.push(diff);
+        }
+        Ok(diffs)
+    }
+
+    /// The branches of the repository and the commits they point to, sorted
+    /// by name, such as `main` or `feature/x`.
+    ///
+    /// # Errors
+    ///
+    /// If the refs cannot be read, or name malformed objects.
+    pub fn branches(&self) -> Result<Vec<(String, Oid)>, Error> {
+        let mut branches = objects::parse_packed_refs(self)?
+            .into_iter()
+            .filter_map(|(name, oid)| {
+                Some((name.strip_prefix(BRANCH_REFS)?.to_owned(), oid))
+            })
+            .collect::<Vec<_>>();
+
+        // Loose refs take precedence over packed ones
+        let mut loose = Vec::new();
+        list_files(&self.common_dir().join(BRANCH_REFS), "", &mut loose)?;
+        for name in loose {
+            let Some(oid) =
+                objects::resolve_ref(self, &format!("{BRANCH_REFS}{name}"))?
+            else {
+                continue;
+            };
+            branches.retain(|(other, _)| *other != name);
+            branches.push((name, oid));
+        }
+        branches.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(branches)
+    }
+}
+
+/// The error for `obj`, named `oid`, not being of the type `expected`.
+fn not_a(oid: &Oid, obj: &GitObject, expected: &str) -> Error {
+    let kind = String::from_utf8_lossy(obj.format());
+    Error::Other(format!("Object {oid} is a {kind}, not a {expected}"))
+}
+
+/// Adds the files below `dir` to `files`, as paths relative to it with `/`
+/// separators, where `prefix` is the path of `dir` itself.
+fn list_files(
+    dir: &Path,
+    prefix: &str,
+    files: &mut Vec<String>,
+) -> Result<(), Error> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Ok(());
+    };
+    for entry in entries {
+        let entry = entry.map_err(|e| {
+            Error::io(format!("Failed to read {}", dir.display()), e)
+        })?;
+        let name = format!("{prefix}{}", entry.file_name().to_string_lossy());
+        let path = entry.path();
+        if path.is_dir() {
+            list_files(&path, &format!("{name}/"), files)?;
+        } else if !has_lock_suffix(&name) {
+            files.push(name);
+        }
+    }
+    Ok(())
+}
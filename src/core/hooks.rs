@@ -0,0 +1,205 @@
+//! Running hooks
+//!
+//! Hooks are executables in the `hooks` directory of the common git
+//! directory, shared by all worktrees, or in the directory named by
+//! `core.hooksPath`, that are run at certain points of an operation. A hook
+//! that does not exist, or is not executable, is skipped.
+//!
+//! Hooks are run from the worktree root, or from the git directory of a bare
+//! repository, with `GIT_DIR` set. The hooks that run before an operation
+//! abort it by exiting with a non-zero status, while the exit status of the
+//! hooks that run after it is ignored.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::core::{Error, GitRepository};
+use crate::utils::path::is_executable;
+use crate::utils::trace;
+
+/// The hooks that are run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hook {
+    /// Run before a commit is made, with no arguments.
+    PreCommit,
+    /// Run before a commit is made, with the file holding the message, which
+    /// the hook may edit.
+    CommitMsg,
+    /// Run after a commit is made, with no arguments.
+    PostCommit,
+    /// Run before a push, with the name and URL of the remote, and a line
+    /// for each ref to update on its standard input.
+    PrePush,
+    /// Run after a checkout, with the previous and new `HEAD`, and whether
+    /// a branch was checked out.
+    PostCheckout,
+}
+
+impl Hook {
+    /// The file name of the hook.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::PreCommit => "pre-commit",
+            Self::CommitMsg => "commit-msg",
+            Self::PostCommit => "post-commit",
+            Self::PrePush => "pre-push",
+            Self::PostCheckout => "post-checkout",
+        }
+    }
+
+    /// Whether a non-zero exit status aborts the operation.
+    #[must_use]
+    pub fn can_abort(self) -> bool {
+        matches!(self, Self::PreCommit | Self::CommitMsg | Self::PrePush)
+    }
+}
+
+/// A ref a push updates, as given to the `pre-push` hook.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PushUpdate {
+    /// The local ref that is pushed, `(delete)` when deleting.
+    pub local_ref: String,
+    /// The object the local ref points to, all zeros when deleting.
+    pub local_sha: String,
+    /// The remote ref that is updated.
+    pub remote_ref: String,
+    /// The object the remote ref points to, all zeros if it does not exist.
+    pub remote_sha: String,
+}
+
+/// The path of the hook `hook` in `repo`, if it exists and is executable.
+#[must_use]
+pub fn find(repo: &GitRepository, hook: Hook) -> Option<PathBuf> {
+    let dir = repo
+        .config()
+        .get("core")
+        .and_then(|core| core.get("hooksPath"))
+        .filter(|dir| !dir.is_empty())
+        .map_or_else(
+            || repo.common_dir().join("hooks"),
+            |dir| repo.worktree().join(dir),
+        );
+    let path = dir.join(hook.name());
+    is_executable(&path).then_some(path)
+}
+
+/// Run the `pre-commit` hook.
+///
+/// # Errors
+///
+/// If the hook fails, which aborts the commit.
+pub fn pre_commit(repo: &GitRepository) -> Result<(), Error> {
+    run(repo, Hook::PreCommit, &[], None).map(|_| ())
+}
+
+/// Run the `commit-msg` hook with the file holding the commit message.
+///
+/// # Errors
+///
+/// If the hook fails, which aborts the commit.
+pub fn commit_msg(repo: &GitRepository, msg_file: &Path) -> Result<(), Error> {
+    let msg_file = msg_file.to_string_lossy();
+    run(repo, Hook::CommitMsg, &[&msg_file], None).map(|_| ())
+}
+
+/// Run the `post-commit` hook.
+///
+/// # Errors
+///
+/// If the hook cannot be run.
+pub fn post_commit(repo: &GitRepository) -> Result<(), Error> {
+    run(repo, Hook::PostCommit, &[], None).map(|_| ())
+}
+
+/// Run the `pre-push` hook for a push of `updates` to the remote named
+/// `remote` at `url`.
+///
+/// # Errors
+///
+/// If the hook fails, which aborts the push.
+pub fn pre_push(
+    repo: &GitRepository,
+    remote: &str,
+    url: &str,
+    updates: &[PushUpdate],
+) -> Result<(), Error> {
+    let mut stdin = vec![];
+    for update in updates {
+        writeln!(
+            stdin,
+            "{} {} {} {}",
+            update.local_ref,
+            update.local_sha,
+            update.remote_ref,
+            update.remote_sha
+        )
+        .map_err(|e| format!("Failed to write to a buffer: {e}"))?;
+    }
+    run(repo, Hook::PrePush, &[remote, url], Some(&stdin)).map(|_| ())
+}
+
+/// Run the `post-checkout` hook after `HEAD` moved from `old` to `new`.
+/// `branch` is whether a branch was checked out, rather than files.
+///
+/// # Errors
+///
+/// If the hook cannot be run.
+pub fn post_checkout(
+    repo: &GitRepository,
+    old: &str,
+    new: &str,
+    branch: bool,
+) -> Result<(), Error> {
+    let flag = if branch { "1" } else { "0" };
+    run(repo, Hook::PostCheckout, &[old, new, flag], None).map(|_| ())
+}
+
+/// Run the hook `hook` of `repo` with `args`, and `stdin` on its standard
+/// input. The output of the hook goes to the standard error.
+///
+/// Returns whether the hook exists and was run.
+///
+/// # Errors
+///
+/// If the hook cannot be run, or it can abort the operation and exits with
+/// a non-zero status.
+pub fn run(
+    repo: &GitRepository,
+    hook: Hook,
+    args: &[&str],
+    stdin: Option<&[u8]>,
+) -> Result<bool, Error> {
+    let Some(path) = find(repo, hook) else {
+        return Ok(false);
+    };
+    let name = hook.name();
+
+    trace::command(&path.display().to_string(), args);
+    let mut child = Command::new(&path)
+        .args(args)
+        .current_dir(repo.worktree())
+        .env("GIT_DIR", repo.gitdir())
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        // Keep the standard output for the operation, like git
+        .stdout(std::io::stderr())
+        .spawn()
+        .map_err(|e| format!("Failed to run the {name} hook: {e}"))?;
+    if let (Some(stdin), Some(mut pipe)) = (stdin, child.stdin.take()) {
+        // A hook may exit without reading everything
+        let _ = pipe.write_all(stdin);
+    }
+    let status = child
+        .wait()
+        .map_err(|e| format!("Failed to run the {name} hook: {e}"))?;
+
+    if hook.can_abort() && !status.success() {
+        return Err(format!("The {name} hook failed with {status}").into());
+    }
+    Ok(true)
+}
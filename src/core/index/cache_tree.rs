@@ -0,0 +1,228 @@
+//! The cached tree (`TREE`) index extension
+//!
+//! The extension records the tree object of each directory in the index,
+//! along with the number of entries it covers. A directory whose entries have
+//! not changed since its tree was written can reuse that tree instead of
+//! hashing it again. Changing an entry invalidates the trees of its parent
+//! directories, which are then rebuilt on the next write.
+//!
+//! Each tree is stored as its path component, NUL, the entry count and the
+//! number of subtrees in ASCII, a newline and, unless the entry count is -1
+//! for an invalidated tree, its object name. Its subtrees follow.
+
+use crate::core::objects::tree::{Leaf, Tree};
+use crate::core::objects::{write_object, GitObject};
+use crate::core::{Error, GitRepository, ObjectFormat};
+use crate::utils::hex;
+
+use super::IndexEntry;
+
+/// The signature of the cached tree extension.
+pub const SIGNATURE: &[u8; 4] = b"TREE";
+
+/// The cached tree of a directory in the index.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CacheTree {
+    /// The number of entries the tree covers and its object name, or `None`
+    /// if the tree was invalidated.
+    cached: Option<(usize, String)>,
+    /// The cached trees of the subdirectories, by name. Like git, these are
+    /// ordered by the length of their names, and then by their names.
+    subtrees: Vec<(String, CacheTree)>,
+}
+
+impl CacheTree {
+    /// The object name of this tree, if it is still valid.
+    #[must_use]
+    pub fn sha(&self) -> Option<&str> {
+        self.cached.as_ref().map(|(_, sha)| sha.as_str())
+    }
+
+    /// The number of index entries this tree covers, if it is still valid.
+    #[must_use]
+    pub fn entry_count(&self) -> Option<usize> {
+        self.cached.as_ref().map(|(count, _)| *count)
+    }
+
+    /// The cached tree of the directory at `path`, relative to this tree.
+    #[must_use]
+    pub fn find(&self, path: &str) -> Option<&Self> {
+        path.split('/').filter(|name| !name.is_empty()).try_fold(
+            self,
+            |tree, name| {
+                tree.subtrees
+                    .iter()
+                    .find(|(sub, _)| sub == name)
+                    .map(|(_, sub)| sub)
+            },
+        )
+    }
+
+    /// Invalidate the trees of the directories containing the file at
+    /// `path`, including this one.
+    pub fn invalidate(&mut self, path: &str) {
+        self.cached = None;
+        if let Some((dir, rest)) = path.split_once('/') {
+            if let Some((_, sub)) =
+                self.subtrees.iter_mut().find(|(name, _)| name == dir)
+            {
+                sub.invalidate(rest);
+            }
+        }
+    }
+
+    /// Parse the payload of the extension, whose trees are named with the
+    /// hash algorithm of `format`.
+    ///
+    /// # Errors
+    ///
+    /// If the payload is truncated or malformed.
+    pub fn parse(data: &[u8], format: ObjectFormat) -> Result<Self, Error> {
+        let (_, tree, end) = Self::parse_node(data, 0, format.hash_len())?;
+        if end != data.len() {
+            return Err("Cached tree has trailing data".into());
+        }
+        Ok(tree)
+    }
+
+    /// Parse the tree at `pos` and its subtrees, returning its name, the
+    /// tree and the position after it.
+    fn parse_node(
+        data: &[u8],
+        pos: usize,
+        hash_len: usize,
+    ) -> Result<(String, Self, usize), Error> {
+        let err = || "Cached tree is truncated".to_owned();
+        let rest = data.get(pos..).ok_or_else(err)?;
+        let nul = rest.iter().position(|&b| b == 0).ok_or_else(err)?;
+        let name = String::from_utf8(rest[..nul].to_vec())
+            .map_err(|_| "Cached tree path is not valid utf-8".to_owned())?;
+
+        let rest = &rest[nul + 1..];
+        let newline = rest.iter().position(|&b| b == b'\n').ok_or_else(err)?;
+        let header = std::str::from_utf8(&rest[..newline])
+            .map_err(|_| "Cached tree header is not valid utf-8".to_owned())?;
+        let bad_header = || format!("Invalid cached tree header '{header}'");
+        let (count, subtree_count) =
+            header.split_once(' ').ok_or_else(bad_header)?;
+        let count = count.parse::<isize>().map_err(|_| bad_header())?;
+        let subtree_count =
+            subtree_count.parse::<usize>().map_err(|_| bad_header())?;
+
+        let mut pos = pos + nul + 1 + newline + 1;
+        let cached = if let Ok(count) = usize::try_from(count) {
+            let sha = data.get(pos..pos + hash_len).ok_or_else(err)?;
+            pos += hash_len;
+            Some((count, hex::encode(sha)))
+        } else {
+            None
+        };
+
+        let mut subtrees = Vec::with_capacity(subtree_count);
+        for _ in 0..subtree_count {
+            let (name, subtree, next) = Self::parse_node(data, pos, hash_len)?;
+            subtrees.push((name, subtree));
+            pos = next;
+        }
+        sort_subtrees(&mut subtrees);
+
+        Ok((name, Self { cached, subtrees }, pos))
+    }
+
+    /// Serialize this tree as the payload of the extension, whose trees are
+    /// named with the hash algorithm of `format`.
+    #[must_use]
+    pub fn serialize(&self, format: ObjectFormat) -> Vec<u8> {
+        let mut data = vec![];
+        self.serialize_node("", &mut data, format.hash_len());
+        data
+    }
+
+    fn serialize_node(&self, name: &str, data: &mut Vec<u8>, hash_len: usize) {
+        data.extend_from_slice(name.as_bytes());
+        data.push(0);
+        let count = self
+            .entry_count()
+            .map_or_else(|| "-1".to_owned(), |count| count.to_string());
+        data.extend_from_slice(
+            format!("{count} {}\n", self.subtrees.len()).as_bytes(),
+        );
+        if let Some((_, sha)) = &self.cached {
+            data.extend_from_slice(
+                &hex::decode(sha).unwrap_or_else(|_| vec![0; hash_len]),
+            );
+        }
+        for (name, subtree) in &self.subtrees {
+            subtree.serialize_node(name, data, hash_len);
+        }
+    }
+
+    /// Write the tree of `entries`, which all start with `prefix`, reusing
+    /// the valid cached trees and caching the trees that are written.
+    ///
+    /// The entries must be sorted by path, and must all be merged.
+    pub(super) fn write(
+        &mut self,
+        repo: &GitRepository,
+        entries: &[IndexEntry],
+        prefix: &str,
+    ) -> Result<String, Error> {
+        if let Some((count, sha)) = &self.cached {
+            if *count == entries.len() {
+                return Ok(sha.clone());
+            }
+        }
+
+        let mut leaves = vec![];
+        let mut subtrees = vec![];
+        let mut i = 0;
+        while i < entries.len() {
+            let name = &entries[i].path[prefix.len()..];
+            let Some((dir, _)) = name.split_once('/') else {
+                let mode = format!("{:06o}", entries[i].mode);
+                let mode = mode.as_bytes().try_into().map_err(|_| {
+                    format!("Invalid mode for {}", entries[i].path)
+                })?;
+                leaves.push(Leaf::new(mode, name.as_bytes(), &entries[i].sha));
+                i += 1;
+                continue;
+            };
+
+            // Sorted entries in the same directory are next to each other
+            let sub_prefix = format!("{prefix}{dir}/");
+            let len = entries[i..]
+                .iter()
+                .take_while(|entry| entry.path.starts_with(&sub_prefix))
+                .count();
+            let mut subtree = self.take_subtree(dir);
+            let sha = subtree.write(repo, &entries[i..i + len], &sub_prefix)?;
+            leaves.push(Leaf::new(b"040000", dir.as_bytes(), &sha));
+            subtrees.push((dir.to_owned(), subtree));
+            i += len;
+        }
+
+        let mut tree = Tree::new();
+        tree.set_leaves(leaves);
+        let sha = write_object(&GitObject::Tree(tree), repo)?.to_string();
+
+        // Directories that are no longer in the index are dropped
+        sort_subtrees(&mut subtrees);
+        self.subtrees = subtrees;
+        self.cached = Some((entries.len(), sha.clone()));
+        Ok(sha)
+    }
+
+    /// Remove and return the cached tree of the subdirectory `name`, or an
+    /// invalid tree if there is none.
+    fn take_subtree(&mut self, name: &str) -> Self {
+        self.subtrees
+            .iter()
+            .position(|(sub, _)| sub == name)
+            .map(|pos| self.subtrees.remove(pos).1)
+            .unwrap_or_default()
+    }
+}
+
+fn sort_subtrees(subtrees: &mut [(String, CacheTree)]) {
+    subtrees.sort_by(|(a, _), (b, _)| a.len().cmp(&b.len()).then(a.cmp(b)));
+}
@@ -0,0 +1,264 @@
+//! The file system monitor (`FSMN`) index extension
+//!
+//! A file system monitor is a hook, named by `core.fsmonitor`, that reports
+//! the paths that changed since a point in time. Entries that did not change
+//! since the index was written, and that were unchanged then, can be assumed
+//! to still match the worktree without looking at the files.
+//!
+//! The extension holds the point in time the index is up to date with,
+//! followed by an EWAH bitmap of the entries that may not match the
+//! worktree. Version 1 of the extension records a time in nanoseconds, while
+//! version 2 records an opaque token chosen by the hook.
+//!
+//! The hook is run from the worktree root with the version of the protocol
+//! and the token. With version 2 it prints a new token and the changed
+//! paths, and with version 1 only the paths, each followed by a NUL. A path
+//! ending in `/` stands for everything under it, so `/` means that
+//! everything may have changed.
+
+use std::collections::HashSet;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::core::{Error, GitRepository};
+use crate::utils::ewah::EwahBitmap;
+use crate::utils::trace;
+
+use super::{read_u32, IndexEntry};
+
+/// The signature of the file system monitor extension.
+pub const SIGNATURE: &[u8; 4] = b"FSMN";
+
+/// The token passed to a version 2 hook when the index has none, which
+/// makes it report that everything changed along with a new token.
+const FAKE_TOKEN: &str = "builtin:fake";
+
+/// The state of the file system monitor.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FsMonitor {
+    /// The point in time the valid entries are up to date with.
+    token: String,
+    /// The paths of the entries that match the worktree.
+    valid: HashSet<String>,
+}
+
+impl FsMonitor {
+    /// The point in time the monitor is up to date with, as given to the
+    /// hook.
+    #[must_use]
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// Whether the entry at `path` is known to match the worktree.
+    #[must_use]
+    pub fn is_valid(&self, path: &str) -> bool {
+        self.valid.contains(path)
+    }
+
+    /// Record that the entry at `path` matches the worktree.
+    pub(super) fn mark_valid(&mut self, path: &str) {
+        self.valid.insert(path.to_owned());
+    }
+
+    /// Record that the entry at `path` may not match the worktree.
+    pub(super) fn invalidate(&mut self, path: &str) {
+        self.valid.remove(path);
+    }
+
+    /// Parse the payload of the extension, for the given entries.
+    pub(super) fn parse(
+        data: &[u8],
+        entries: &[IndexEntry],
+    ) -> Result<Self, Error> {
+        let err = || "File system monitor data is truncated".to_owned();
+        let (token, pos) = match read_u32(data, 0)? {
+            1 => {
+                let time = data.get(4..12).ok_or_else(err)?;
+                let time =
+                    time.iter().fold(0u64, |time, &b| time << 8 | u64::from(b));
+                (time.to_string(), 12)
+            }
+            2 => {
+                let nul =
+                    data[4..].iter().position(|&b| b == 0).ok_or_else(err)?;
+                let token = String::from_utf8_lossy(&data[4..4 + nul]);
+                (token.into_owned(), 4 + nul + 1)
+            }
+            version => {
+                return Err(format!(
+                    "Unknown file system monitor version {version}"
+                )
+                .into())
+            }
+        };
+
+        let size = read_u32(data, pos)? as usize;
+        let bitmap = data.get(pos + 4..pos + 4 + size).ok_or_else(err)?;
+        let (dirty, _) = EwahBitmap::parse(bitmap)?;
+        if dirty.bit_size() > entries.len() {
+            return Err("File system monitor data is corrupt".into());
+        }
+
+        let valid = entries
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !dirty.contains(*i))
+            .map(|(_, entry)| entry.path.clone())
+            .collect();
+        Ok(Self { token, valid })
+    }
+
+    /// Serialize the monitor as version 2 of the extension, for the given
+    /// entries.
+    pub(super) fn serialize(&self, entries: &[IndexEntry]) -> Vec<u8> {
+        let dirty = entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| !self.is_valid(&entry.path))
+            .map(|(i, _)| i);
+        let bitmap = EwahBitmap::from_positions(dirty).serialize();
+
+        let mut data = 2u32.to_be_bytes().to_vec();
+        data.extend_from_slice(self.token.as_bytes());
+        data.push(0);
+        #[allow(clippy::cast_possible_truncation)]
+        data.extend_from_slice(&(bitmap.len() as u32).to_be_bytes());
+        data.extend_from_slice(&bitmap);
+        data
+    }
+
+    /// Ask the hook of the repository what changed since this monitor's
+    /// token, or since the beginning of time for `None`, and return the
+    /// monitor that is up to date. Only the entries that were valid and did
+    /// not change stay valid.
+    ///
+    /// Returns `None` if `core.fsmonitor` does not name a hook.
+    pub(super) fn refresh(
+        monitor: Option<&Self>,
+        repo: &GitRepository,
+    ) -> Result<Option<Self>, Error> {
+        let core = repo.config().get("core");
+        let Some(hook) = core
+            .and_then(|core| core.get("fsmonitor"))
+            .filter(|hook| !hook.is_empty())
+            // A boolean asks for git's built-in daemon, which is unsupported
+            .filter(|_| {
+                core.and_then(|core| core.get_bool("fsmonitor")).is_none()
+            })
+        else {
+            return Ok(None);
+        };
+        let version = core.and_then(|core| core.get("fsmonitorHookVersion"));
+
+        let token = monitor.map(Self::token);
+        let (token, changed) = match version {
+            Some("1") => query_v1(repo, hook, token)?,
+            Some("2") => query_v2(repo, hook, token)?,
+            None => query_v2(repo, hook, token)
+                .or_else(|_| query_v1(repo, hook, token))?,
+            Some(version) => {
+                return Err(format!(
+                    "Unknown core.fsmonitorHookVersion {version}"
+                )
+                .into())
+            }
+        };
+
+        let mut valid = monitor
+            .map(|monitor| monitor.valid.clone())
+            .unwrap_or_default();
+        match changed {
+            Some(changed) => {
+                let (dirs, files): (Vec<_>, Vec<_>) =
+                    changed.iter().partition(|path| path.ends_with('/'));
+                let files = files.into_iter().collect::<HashSet<_>>();
+                valid.retain(|path| {
+                    !files.contains(path)
+                        && !dirs
+                            .iter()
+                            .any(|dir| path.starts_with(dir.as_str()))
+                });
+            }
+            None => valid.clear(),
+        }
+        Ok(Some(Self { token, valid }))
+    }
+}
+
+/// The new token and the changed paths reported by a version 2 hook, or
+/// `None` if everything may have changed.
+type QueryResult = (String, Option<Vec<String>>);
+
+fn query_v2(
+    repo: &GitRepository,
+    hook: &str,
+    token: Option<&str>,
+) -> Result<QueryResult, Error> {
+    let output = run_hook(repo, hook, "2", token.unwrap_or(FAKE_TOKEN))?;
+    let mut fields = output.split(|&b| b == 0);
+    let new_token = fields
+        .next()
+        .filter(|token| !token.is_empty())
+        .ok_or_else(|| format!("'{hook}' did not print a token"))?;
+    let new_token = String::from_utf8_lossy(new_token).into_owned();
+    let changed = token.and_then(|_| changed_paths(fields));
+    Ok((new_token, changed))
+}
+
+fn query_v1(
+    repo: &GitRepository,
+    hook: &str,
+    token: Option<&str>,
+) -> Result<QueryResult, Error> {
+    // Changes made while the hook runs are reported the next time
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| format!("Invalid system time: {e}"))?
+        .as_nanos()
+        .to_string();
+    let Some(since) = token.filter(|token| token.parse::<u64>().is_ok()) else {
+        return Ok((now, None));
+    };
+    let output = run_hook(repo, hook, "1", since)?;
+    Ok((now, changed_paths(output.split(|&b| b == 0))))
+}
+
+/// The changed paths printed by a hook, or `None` if everything may have
+/// changed.
+fn changed_paths<'a, I>(fields: I) -> Option<Vec<String>>
+where
+    I: Iterator<Item = &'a [u8]>,
+{
+    let mut changed = vec![];
+    for path in fields.filter(|path| !path.is_empty()) {
+        if path == b"/" {
+            return None;
+        }
+        changed.push(String::from_utf8_lossy(path).into_owned());
+    }
+    Some(changed)
+}
+
+/// Run the hook from the worktree root, with the shell like git, and
+/// return what it prints.
+fn run_hook(
+    repo: &GitRepository,
+    hook: &str,
+    version: &str,
+    token: &str,
+) -> Result<Vec<u8>, Error> {
+    trace::command(hook, &[version, token]);
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{hook} \"$@\""))
+        .arg(hook)
+        .args([version, token])
+        .current_dir(repo.worktree())
+        .output()
+        .map_err(|e| format!("Failed to run '{hook}': {e}"))?;
+    if !output.status.success() {
+        return Err(format!("'{hook}' failed with {}", output.status).into());
+    }
+    Ok(output.stdout)
+}
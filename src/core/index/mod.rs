@@ -0,0 +1,1073 @@
+//! Git Index (Staging Area) Implementation
+//!
+//! This module reads and writes the `.git/index` file, which records the
+//! files staged for the next commit along with cached filesystem metadata.
+//!
+//! Versions 2, 3 and 4 of the on-disk format are supported. The cached tree,
+//! split index, untracked cache and file system monitor extensions are
+//! parsed, see [`cache_tree`], [`split_index`], [`untracked_cache`] and
+//! [`fsmonitor`]. Other extensions are kept as opaque blobs so they survive a
+//! read/write round trip.
+//!
+//! See `gitformat-index(5)` for the details of the format.
+
+pub mod cache_tree;
+pub mod fsmonitor;
+pub mod split_index;
+pub mod untracked_cache;
+
+use std::fs;
+use std::time::UNIX_EPOCH;
+
+use cache_tree::CacheTree;
+use fsmonitor::FsMonitor;
+use split_index::{Link, SharedIndex};
+use untracked_cache::UntrackedCache;
+
+use crate::core::objects::{self, blob::Blob, FileSource, GitObject};
+use crate::core::{write_locked, Error, GitRepository, ObjectFormat};
+use crate::utils::hex;
+use crate::utils::path;
+
+/// The signature at the start of every index file.
+const SIGNATURE: &[u8; 4] = b"DIRC";
+/// The size of the index header: signature, version and entry count.
+const HEADER_SIZE: usize = 12;
+/// The size of an entry's fixed-width fields, up to and including flags,
+/// besides its object name.
+const ENTRY_FIXED_SIZE: usize = 42;
+
+/// Mask for the path length stored in the entry flags.
+const NAME_MASK: u16 = 0x0fff;
+/// Flag indicating that an extended flags field follows (version 3+).
+const EXTENDED_FLAG: u16 = 0x4000;
+
+/// The object type bits of a symbolic link's mode.
+const SYMLINK_MODE: u32 = 0o120_000;
+/// The object type bits of a submodule's mode.
+const GITLINK_MODE: u32 = 0o160_000;
+/// Mask for the object type bits of a mode.
+const TYPE_MASK: u32 = 0o170_000;
+
+/// A single file staged in the index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+    /// Metadata change time, as seconds and nanoseconds.
+    pub ctime: (u32, u32),
+    /// Data modification time, as seconds and nanoseconds.
+    pub mtime: (u32, u32),
+    /// The device containing the file.
+    pub dev: u32,
+    /// The inode number of the file.
+    pub ino: u32,
+    /// The object type and unix permissions of the file.
+    pub mode: u32,
+    /// The owner of the file.
+    pub uid: u32,
+    /// The group of the file.
+    pub gid: u32,
+    /// The on-disk size of the file, truncated to 32 bits.
+    pub size: u32,
+    /// The SHA-1 of the staged blob.
+    pub sha: String,
+    /// The entry flags, including the merge stage.
+    pub flags: u16,
+    /// Extra flags, only present in version 3 and above.
+    pub extended_flags: u16,
+    /// The path of the file, relative to the worktree root.
+    pub path: String,
+}
+
+/// How the worktree copy of an index entry compares to the entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorktreeState {
+    /// The file matches the staged blob.
+    Unchanged,
+    /// The file differs from the staged blob.
+    Modified,
+    /// The file is missing from the worktree.
+    Deleted,
+}
+
+/// An index extension, kept as raw bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexExtension {
+    /// The four byte signature identifying the extension.
+    pub signature: [u8; 4],
+    /// The extension payload.
+    pub data: Vec<u8>,
+}
+
+/// The parsed contents of an index file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitIndex {
+    /// The on-disk format version.
+    version: u32,
+    /// The entries, sorted by path and stage.
+    entries: Vec<IndexEntry>,
+    /// The cached trees of the directories, from the `TREE` extension.
+    cache_tree: Option<CacheTree>,
+    /// The untracked files of the worktree, from the `UNTR` extension.
+    untracked: Option<UntrackedCache>,
+    /// The entries known to match the worktree, from the `FSMN` extension.
+    fsmonitor: Option<FsMonitor>,
+    /// The other extensions following the entries.
+    extensions: Vec<IndexExtension>,
+    /// The shared index, if the index was read from a split index.
+    split: Option<SharedIndex>,
+    /// The hash algorithm the objects of the entries are named with.
+    format: ObjectFormat,
+}
+
+impl IndexEntry {
+    /// Create an entry with the given mode, blob and path, and no
+    /// filesystem metadata.
+    #[must_use]
+    pub fn new(mode: u32, sha: &str, path: &str) -> Self {
+        #[allow(clippy::cast_possible_truncation)]
+        let name_len = path.len().min(usize::from(NAME_MASK)) as u16;
+        Self {
+            ctime: (0, 0),
+            mtime: (0, 0),
+            dev: 0,
+            ino: 0,
+            mode,
+            uid: 0,
+            gid: 0,
+            size: 0,
+            sha: sha.to_owned(),
+            flags: name_len,
+            extended_flags: 0,
+            path: path.to_owned(),
+        }
+    }
+
+    /// The merge stage of this entry, 0 unless the path is conflicted.
+    #[must_use]
+    pub fn stage(&self) -> u8 {
+        ((self.flags >> 12) & 0b11) as u8
+    }
+
+    /// The mode formatted as a 6 digit octal string, as git displays it.
+    #[must_use]
+    pub fn mode_as_string(&self) -> String {
+        format!("{:06o}", self.mode)
+    }
+
+    /// Compare the worktree copy of this entry against the staged blob.
+    ///
+    /// If the file's size and modification time match the cached metadata,
+    /// it is assumed to be unchanged. Otherwise its contents are hashed.
+    ///
+    /// # Errors
+    ///
+    /// If the file exists but cannot be read.
+    pub fn worktree_state(
+        &self,
+        repo: &GitRepository,
+    ) -> Result<WorktreeState, Error> {
+        let path = repo.worktree().join(&self.path);
+        let Ok(metadata) = fs::symlink_metadata(&path) else {
+            return Ok(WorktreeState::Deleted);
+        };
+
+        let content = match self.mode & TYPE_MASK {
+            // Submodule contents are tracked by their own repository
+            GITLINK_MODE => {
+                return Ok(if metadata.is_dir() {
+                    WorktreeState::Unchanged
+                } else {
+                    WorktreeState::Deleted
+                })
+            }
+            SYMLINK_MODE => fs::read_link(&path).map(|target| {
+                target.to_string_lossy().into_owned().into_bytes()
+            }),
+            _ if metadata.is_dir() => return Ok(WorktreeState::Deleted),
+            _ => {
+                if self.stat_matches(&metadata) {
+                    return Ok(WorktreeState::Unchanged);
+                }
+                fs::read(&path)
+            }
+        }
+        .map_err(|e| format!("Failed to read {}: {e}", self.path))?;
+
+        let blob = GitObject::Blob(Blob::from(content.as_slice()));
+        let (_, mut hash) = objects::hash_object(&blob, repo.object_format());
+        Ok(if hash.hex_digest() == self.sha {
+            WorktreeState::Unchanged
+        } else {
+            WorktreeState::Modified
+        })
+    }
+
+    /// Whether the cached size and modification time match `metadata`.
+    fn stat_matches(&self, metadata: &fs::Metadata) -> bool {
+        // Entries written without filesystem metadata must be hashed
+        if self.mtime == (0, 0) {
+            return false;
+        }
+        let Some(mtime) = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        else {
+            return false;
+        };
+
+        #[allow(clippy::cast_possible_truncation)]
+        let stat = (
+            metadata.len() as u32,
+            (mtime.as_secs() as u32, mtime.subsec_nanos()),
+        );
+        stat == (self.size, self.mtime)
+    }
+}
+
+impl GitIndex {
+    /// Create an empty version 2 index.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            version: 2,
+            entries: vec![],
+            cache_tree: None,
+            untracked: None,
+            fsmonitor: None,
+            extensions: vec![],
+            split: None,
+            format: ObjectFormat::Sha1,
+        }
+    }
+
+    /// Names the objects of the entries with the hash algorithm of `format`.
+    #[must_use]
+    pub fn with_object_format(mut self, format: ObjectFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Read the index of the given repository.
+    ///
+    /// A repository without an index file has an empty index. A split index
+    /// is read along with its shared index. The untracked cache is left out
+    /// if `core.untrackedCache` is `false`, so it is dropped when the index
+    /// is written.
+    ///
+    /// # Errors
+    ///
+    /// If the index file or its shared index cannot be read or is
+    /// malformed.
+    pub fn read(repo: &GitRepository) -> Result<Self, Error> {
+        let format = repo.object_format();
+        let Some(file) = path::repo_file(repo.gitdir(), &["index"], false)?
+        else {
+            return Ok(Self::new().with_object_format(format));
+        };
+        if !file.is_file() {
+            return Ok(Self::new().with_object_format(format));
+        }
+
+        let data = fs::read(&file)
+            .map_err(|e| format!("Failed to read index file: {e}"))?;
+        let (mut index, link) = Self::parse_parts(&data, format)?;
+        let untracked_cache = repo
+            .config()
+            .get("core")
+            .and_then(|core| core.get_bool("untrackedCache"));
+        if untracked_cache == Some(false) {
+            index.untracked = None;
+        }
+        if let Some(link) = link {
+            let shared_file = shared_index_path(repo, &link.shared);
+            let shared_data = fs::read(&shared_file).map_err(|e| {
+                format!("Failed to read shared index {}: {e}", link.shared)
+            })?;
+            let (shared, shared_link) =
+                Self::parse_parts(&shared_data, format)?;
+            if shared_link.is_some()
+                || checksum_of(&shared_data, format) != link.shared
+            {
+                return Err(
+                    format!("Shared index {} is corrupt", link.shared).into()
+                );
+            }
+
+            let split_entries = std::mem::take(&mut index.entries);
+            index.entries =
+                split_index::merge(&shared.entries, &link, split_entries)?;
+            index.split = Some(SharedIndex {
+                sha: link.shared,
+                entries: shared.entries,
+            });
+        }
+        index.load_fsmonitor()?;
+        Ok(index)
+    }
+
+    /// Parse the file system monitor extension, which describes the
+    /// entries by position, once all the entries are known.
+    fn load_fsmonitor(&mut self) -> Result<(), Error> {
+        let Some(pos) = self
+            .extensions
+            .iter()
+            .position(|ext| &ext.signature == fsmonitor::SIGNATURE)
+        else {
+            return Ok(());
+        };
+        let extension = self.extensions.remove(pos);
+        self.fsmonitor =
+            Some(FsMonitor::parse(&extension.data, &self.entries)?);
+        Ok(())
+    }
+
+    /// Whether the given repository has an index file.
+    #[must_use]
+    pub fn exists(repo: &GitRepository) -> bool {
+        repo.gitdir().join("index").is_file()
+    }
+
+    /// Write this index to the given repository.
+    ///
+    /// The index is written as a split index if it was read from one, or if
+    /// `core.splitIndex` is `true`, unless `core.splitIndex` is `false`.
+    /// Only the entries that differ from the shared index are
+    /// written, unless they are more than `splitIndex.maxPercentChange`
+    /// percent of the entries, 20 by default. Then a new shared index is
+    /// written.
+    ///
+    /// Objects are named in the object format of the repository.
+    ///
+    /// # Errors
+    ///
+    /// If the index file or the shared index cannot be written, and an
+    /// [`Error::Locked`] if `index.lock` exists, as another process is
+    /// writing the index.
+    pub fn write(&self, repo: &GitRepository) -> Result<(), Error> {
+        let Some(file) = path::repo_file(repo.gitdir(), &["index"], false)?
+        else {
+            return Err("Failed to resolve index file path".into());
+        };
+
+        let split_config = repo
+            .config()
+            .get("core")
+            .and_then(|core| core.get_bool("splitIndex"));
+        let format = repo.object_format();
+        let data = match (split_config, &self.split) {
+            (Some(false), _) | (None, None) => serialize_index(
+                self.version,
+                &self.entries,
+                &self.all_extensions(None),
+                format,
+            ),
+            (_, shared) => self.serialize_split(repo, shared.as_ref())?,
+        };
+        write_locked(&file, &data)
+    }
+
+    /// Serialize this index as a split index, based on `shared` if few
+    /// enough entries differ from it, or on a new shared index written to
+    /// the repository.
+    fn serialize_split(
+        &self,
+        repo: &GitRepository,
+        shared: Option<&SharedIndex>,
+    ) -> Result<Vec<u8>, Error> {
+        let format = repo.object_format();
+        let max_percent = repo
+            .config()
+            .get("splitIndex")
+            .and_then(|split| split.get("maxPercentChange"))
+            .and_then(|percent| percent.parse().ok())
+            .unwrap_or(split_index::DEFAULT_MAX_PERCENT_CHANGE);
+
+        let mut split =
+            shared.map(|shared| split_index::split(shared, &self.entries));
+        let too_many = |link: &Link, entries: &[IndexEntry]| {
+            (entries.len() + link.delete.count()) * 100
+                > max_percent * self.entries.len()
+        };
+        if split
+            .as_ref()
+            .is_none_or(|(link, entries)| too_many(link, entries))
+        {
+            let data =
+                serialize_index(self.version, &self.entries, &[], format);
+            let sha = checksum_of(&data, format);
+            let shared_file = shared_index_path(repo, &sha);
+            if !shared_file.exists() {
+                fs::write(&shared_file, &data).map_err(|e| {
+                    format!("Failed to write shared index {sha}: {e}")
+                })?;
+            }
+            // The previous shared index is only used by this index
+            if let Some(old) = shared.filter(|old| old.sha != sha) {
+                let _ = fs::remove_file(shared_index_path(repo, &old.sha));
+            }
+            split = Some(split_index::split(
+                &SharedIndex {
+                    sha,
+                    entries: self.entries.clone(),
+                },
+                &self.entries,
+            ));
+        }
+
+        let (link, entries) = split.expect("Should be split");
+        let link = IndexExtension {
+            signature: *split_index::SIGNATURE,
+            data: link.serialize(format),
+        };
+        Ok(serialize_index(
+            self.version,
+            &entries,
+            &self.all_extensions(Some(link)),
+            format,
+        ))
+    }
+
+    /// Parse the raw contents of an index file.
+    ///
+    /// A split index cannot be parsed on its own, as most of its entries
+    /// are in its shared index. Use [`GitIndex::read`] to read it.
+    ///
+    /// # Errors
+    ///
+    /// If the data is truncated, has an unknown version, fails the trailing
+    /// checksum, or is a split index.
+    pub fn parse(data: &[u8]) -> Result<Self, Error> {
+        Self::parse_with_format(data, ObjectFormat::Sha1)
+    }
+
+    /// Parse the raw contents of an index file, like [`GitIndex::parse`],
+    /// whose objects are named with the hash algorithm of `format`.
+    ///
+    /// # Errors
+    ///
+    /// If the data is truncated, has an unknown version, fails the trailing
+    /// checksum, or is a split index.
+    pub fn parse_with_format(
+        data: &[u8],
+        format: ObjectFormat,
+    ) -> Result<Self, Error> {
+        match Self::parse_parts(data, format)? {
+            (mut index, None) => {
+                index.load_fsmonitor()?;
+                Ok(index)
+            }
+            (_, Some(_)) => {
+                Err("Index is split, and needs its shared index".into())
+            }
+        }
+    }
+
+    /// Parse the raw contents of an index file, along with the split index
+    /// extension if there is one.
+    fn parse_parts(
+        data: &[u8],
+        format: ObjectFormat,
+    ) -> Result<(Self, Option<Link>), Error> {
+        let hash_len = format.hash_len();
+        if data.len() < HEADER_SIZE + hash_len {
+            return Err("Index file is too short".into());
+        }
+
+        let (body, checksum) = data.split_at(data.len() - hash_len);
+        if format.hash(body) != checksum {
+            return Err("Index file checksum mismatch".into());
+        }
+
+        if &body[..4] != SIGNATURE {
+            return Err("Index file has an invalid signature".into());
+        }
+
+        let version = read_u32(body, 4)?;
+        if !(2..=4).contains(&version) {
+            return Err(format!("Unsupported index version {version}").into());
+        }
+
+        let count = read_u32(body, 8)? as usize;
+        let mut entries = Vec::with_capacity(count);
+        let mut pos = HEADER_SIZE;
+        for _ in 0..count {
+            let prev_path = entries
+                .last()
+                .map_or(&[][..], |entry: &IndexEntry| entry.path.as_bytes());
+            let (entry, next) =
+                parse_entry(body, pos, version, prev_path, format)?;
+            entries.push(entry);
+            pos = next;
+        }
+
+        let mut cache_tree = None;
+        let mut untracked = None;
+        let mut link = None;
+        let mut extensions = vec![];
+        while pos < body.len() {
+            if body.len() - pos < 8 {
+                return Err("Index extension header is truncated".into());
+            }
+            let mut signature = [0u8; 4];
+            signature.copy_from_slice(&body[pos..pos + 4]);
+            let size = read_u32(body, pos + 4)? as usize;
+            let start = pos + 8;
+            let Some(data) = body.get(start..start + size) else {
+                return Err(format!(
+                    "Index extension {} is truncated",
+                    String::from_utf8_lossy(&signature)
+                )
+                .into());
+            };
+            if &signature == cache_tree::SIGNATURE {
+                cache_tree = Some(CacheTree::parse(data, format)?);
+            } else if &signature == untracked_cache::SIGNATURE {
+                untracked = Some(UntrackedCache::parse(data, format)?);
+            } else if &signature == split_index::SIGNATURE {
+                link = Some(Link::parse(data, format)?);
+            } else {
+                extensions.push(IndexExtension {
+                    signature,
+                    data: data.to_vec(),
+                });
+            }
+            pos = start + size;
+        }
+
+        let index = Self {
+            version,
+            entries,
+            cache_tree,
+            untracked,
+            fsmonitor: None,
+            extensions,
+            split: None,
+            format,
+        };
+        Ok((index, link))
+    }
+
+    /// Serialize this index to its on-disk format, including the trailing
+    /// checksum. A split index is serialized whole, without its link to the
+    /// shared index.
+    #[must_use]
+    pub fn serialize(&self) -> Vec<u8> {
+        serialize_index(
+            self.version,
+            &self.entries,
+            &self.all_extensions(None),
+            self.format,
+        )
+    }
+
+    /// The extensions to write after the entries, starting with `first`.
+    fn all_extensions(
+        &self,
+        first: Option<IndexExtension>,
+    ) -> Vec<IndexExtension> {
+        let cache_tree = self.cache_tree.as_ref().map(|tree| IndexExtension {
+            signature: *cache_tree::SIGNATURE,
+            data: tree.serialize(self.format),
+        });
+        let untracked = self.untracked.as_ref().map(|cache| IndexExtension {
+            signature: *untracked_cache::SIGNATURE,
+            data: cache.serialize(),
+        });
+        let fsmonitor = self.fsmonitor.as_ref().map(|monitor| IndexExtension {
+            signature: *fsmonitor::SIGNATURE,
+            data: monitor.serialize(&self.entries),
+        });
+        first
+            .into_iter()
+            .chain(cache_tree)
+            .chain(untracked)
+            .chain(fsmonitor)
+            .chain(self.extensions.iter().cloned())
+            .collect()
+    }
+
+    /// The on-disk format version.
+    #[must_use]
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// The entries, sorted by path and stage.
+    #[must_use]
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.entries
+    }
+
+    /// The extensions that followed the entries, other than the cached
+    /// tree, the untracked cache and the file system monitor.
+    #[must_use]
+    pub fn extensions(&self) -> &[IndexExtension] {
+        &self.extensions
+    }
+
+    /// The cached trees of the directories, if the index has them.
+    #[must_use]
+    pub fn cache_tree(&self) -> Option<&CacheTree> {
+        self.cache_tree.as_ref()
+    }
+
+    /// The untracked cache, if the index has one.
+    #[must_use]
+    pub fn untracked_cache(&self) -> Option<&UntrackedCache> {
+        self.untracked.as_ref()
+    }
+
+    /// Replace the untracked cache.
+    pub fn set_untracked_cache(&mut self, cache: Option<UntrackedCache>) {
+        self.untracked = cache;
+    }
+
+    /// The file system monitor, if the index has one.
+    #[must_use]
+    pub fn fsmonitor(&self) -> Option<&FsMonitor> {
+        self.fsmonitor.as_ref()
+    }
+
+    /// Ask the hook named by `core.fsmonitor` which files changed since the
+    /// index was written, then check the entries that are not known to
+    /// match the worktree, marking those that do. Without a hook, the file
+    /// system monitor is dropped.
+    ///
+    /// # Errors
+    ///
+    /// If the hook fails, or a file cannot be read.
+    pub fn refresh_fsmonitor(
+        &mut self,
+        repo: &GitRepository,
+    ) -> Result<(), Error> {
+        self.fsmonitor = FsMonitor::refresh(self.fsmonitor.as_ref(), repo)?;
+        let Some(monitor) = &mut self.fsmonitor else {
+            return Ok(());
+        };
+        for entry in &self.entries {
+            if entry.stage() == 0
+                && !monitor.is_valid(&entry.path)
+                && entry.worktree_state(repo)? == WorktreeState::Unchanged
+            {
+                monitor.mark_valid(&entry.path);
+            }
+        }
+        Ok(())
+    }
+
+    /// Compare the worktree copy of `entry` against the staged blob, like
+    /// [`IndexEntry::worktree_state`], unless the file system monitor knows
+    /// it to be unchanged.
+    ///
+    /// # Errors
+    ///
+    /// If the file exists but cannot be read.
+    pub fn worktree_state(
+        &self,
+        repo: &GitRepository,
+        entry: &IndexEntry,
+    ) -> Result<WorktreeState, Error> {
+        if self
+            .fsmonitor
+            .as_ref()
+            .is_some_and(|monitor| monitor.is_valid(&entry.path))
+        {
+            return Ok(WorktreeState::Unchanged);
+        }
+        entry.worktree_state(repo)
+    }
+
+    /// Write the tree objects for the merged entries, returning the name of
+    /// the root tree.
+    ///
+    /// The trees of directories that have not changed since they were last
+    /// written are reused from the cached tree extension, which is updated
+    /// with the trees that are written.
+    ///
+    /// # Errors
+    ///
+    /// If the index has conflicted entries, or an object cannot be written.
+    pub fn write_tree(
+        &mut self,
+        repo: &GitRepository,
+    ) -> Result<String, Error> {
+        if let Some(entry) = self.entries.iter().find(|e| e.stage() != 0) {
+            return Err(format!("{}: unmerged entries", entry.path).into());
+        }
+        self.cache_tree
+            .get_or_insert_with(CacheTree::default)
+            .write(repo, &self.entries, "")
+    }
+
+    /// The merged (stage 0) entries as file sources, for diffing. Submodules
+    /// are gitlinks, and other entries are blobs.
+    ///
+    /// Conflicted paths have no single staged version, so are left out.
+    #[must_use]
+    pub fn files(&self) -> Vec<FileSource> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.stage() == 0)
+            .map(|entry| {
+                let (path, sha) = (entry.path.clone(), entry.sha.clone());
+                if entry.mode & TYPE_MASK == GITLINK_MODE {
+                    FileSource::Gitlink { path, sha }
+                } else {
+                    FileSource::Blob { path, sha }
+                }
+            })
+            .collect()
+    }
+
+    /// Whether any stage of the given path is in the index.
+    #[must_use]
+    pub fn contains(&self, path: &str) -> bool {
+        self.entries
+            .binary_search_by(|entry| entry.path.as_str().cmp(path))
+            .is_ok()
+    }
+
+    /// Add an entry, replacing any existing entry with the same path and
+    /// stage, and keep the entries sorted. The cached trees and untracked
+    /// files of its directories are invalidated, and the entry is no longer
+    /// known to match the worktree.
+    pub fn add(&mut self, entry: IndexEntry) -> &mut Self {
+        if let Some(tree) = &mut self.cache_tree {
+            tree.invalidate(&entry.path);
+        }
+        if let Some(cache) = &mut self.untracked {
+            cache.invalidate(&entry.path);
+        }
+        if let Some(monitor) = &mut self.fsmonitor {
+            monitor.invalidate(&entry.path);
+        }
+        let key = (entry.path.clone(), entry.stage());
+        match self.entries.binary_search_by(|e| {
+            (e.path.as_str(), e.stage()).cmp(&(&key.0, key.1))
+        }) {
+            Ok(pos) => self.entries[pos] = entry,
+            Err(pos) => self.entries.insert(pos, entry),
+        }
+        self
+    }
+}
+
+impl Default for GitIndex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Serialize an index file with the given entries and extensions, including
+/// the trailing checksum, whose objects are named with the hash algorithm of
+/// `format`.
+fn serialize_index(
+    version: u32,
+    entries: &[IndexEntry],
+    extensions: &[IndexExtension],
+    format: ObjectFormat,
+) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend_from_slice(SIGNATURE);
+    data.extend_from_slice(&version.to_be_bytes());
+    #[allow(clippy::cast_possible_truncation)]
+    data.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+
+    let mut prev_path = "";
+    for entry in entries {
+        serialize_entry(&mut data, entry, version, prev_path, format);
+        prev_path = &entry.path;
+    }
+
+    for extension in extensions {
+        data.extend_from_slice(&extension.signature);
+        #[allow(clippy::cast_possible_truncation)]
+        data.extend_from_slice(&(extension.data.len() as u32).to_be_bytes());
+        data.extend_from_slice(&extension.data);
+    }
+
+    let checksum = format.hash(&data);
+    data.extend_from_slice(&checksum);
+    data
+}
+
+/// The trailing checksum of an index file, which names a shared index.
+fn checksum_of(data: &[u8], format: ObjectFormat) -> String {
+    hex::encode(&data[data.len().saturating_sub(format.hash_len())..])
+}
+
+/// The path of the shared index with the given checksum.
+fn shared_index_path(repo: &GitRepository, sha: &str) -> std::path::PathBuf {
+    repo.gitdir().join(format!("sharedindex.{sha}"))
+}
+
+fn read_u32(data: &[u8], pos: usize) -> Result<u32, Error> {
+    data.get(pos..pos + 4)
+        .map(|bytes| u32::from_be_bytes(bytes.try_into().expect("4 bytes")))
+        .ok_or_else(|| Error::Parse("Index file is truncated".to_owned()))
+}
+
+fn read_u16(data: &[u8], pos: usize) -> Result<u16, Error> {
+    data.get(pos..pos + 2)
+        .map(|bytes| u16::from_be_bytes(bytes.try_into().expect("2 bytes")))
+        .ok_or_else(|| Error::Parse("Index file is truncated".to_owned()))
+}
+
+/// Parse the entry at `pos`, returning it along with the position of the
+/// next entry.
+fn parse_entry(
+    data: &[u8],
+    pos: usize,
+    version: u32,
+    prev_path: &[u8],
+    format: ObjectFormat,
+) -> Result<(IndexEntry, usize), Error> {
+    let field = |n: usize| read_u32(data, pos + 4 * n);
+    let hash_len = format.hash_len();
+    let Some(sha) = data.get(pos + 40..pos + 40 + hash_len) else {
+        return Err("Index entry is truncated".into());
+    };
+    let flags = read_u16(data, pos + 40 + hash_len)?;
+
+    let mut cursor = pos + ENTRY_FIXED_SIZE + hash_len;
+    let extended_flags = if version >= 3 && flags & EXTENDED_FLAG != 0 {
+        cursor += 2;
+        read_u16(data, cursor - 2)?
+    } else {
+        0
+    };
+
+    let (path, next) = if version == 4 {
+        // The path is stored as the number of bytes to drop from the end of
+        // the previous path, followed by the suffix to append
+        let (strip, len) = read_varint(data, cursor)?;
+        cursor += len;
+        let end = find_nul(data, cursor)?;
+        let Some(keep) = prev_path.len().checked_sub(strip) else {
+            return Err("Index entry has an invalid path prefix".into());
+        };
+        let mut path = prev_path[..keep].to_vec();
+        path.extend_from_slice(&data[cursor..end]);
+        (path, end + 1)
+    } else {
+        let end = find_nul(data, cursor)?;
+        // Entries are padded with 1-8 NUL bytes to a multiple of 8
+        let entry_len = end - pos;
+        let next = pos + (entry_len + 8) / 8 * 8;
+        (data[cursor..end].to_vec(), next)
+    };
+
+    let entry = IndexEntry {
+        ctime: (field(0)?, field(1)?),
+        mtime: (field(2)?, field(3)?),
+        dev: field(4)?,
+        ino: field(5)?,
+        mode: field(6)?,
+        uid: field(7)?,
+        gid: field(8)?,
+        size: field(9)?,
+        sha: hex::encode(sha),
+        flags,
+        extended_flags,
+        path: String::from_utf8(path)
+            .map_err(|_| "Index entry path is not valid utf-8".to_owned())?,
+    };
+    Ok((entry, next))
+}
+
+fn serialize_entry(
+    data: &mut Vec<u8>,
+    entry: &IndexEntry,
+    version: u32,
+    prev_path: &str,
+    format: ObjectFormat,
+) {
+    let start = data.len();
+    for value in [
+        entry.ctime.0,
+        entry.ctime.1,
+        entry.mtime.0,
+        entry.mtime.1,
+        entry.dev,
+        entry.ino,
+        entry.mode,
+        entry.uid,
+        entry.gid,
+        entry.size,
+    ] {
+        data.extend_from_slice(&value.to_be_bytes());
+    }
+    data.extend_from_slice(
+        &hex::decode(&entry.sha).unwrap_or_else(|_| vec![0; format.hash_len()]),
+    );
+
+    let extended = version >= 3 && entry.extended_flags != 0;
+    let flags = if extended {
+        entry.flags | EXTENDED_FLAG
+    } else {
+        entry.flags & !EXTENDED_FLAG
+    };
+    data.extend_from_slice(&flags.to_be_bytes());
+    if extended {
+        data.extend_from_slice(&entry.extended_flags.to_be_bytes());
+    }
+
+    if version == 4 {
+        let common = prev_path
+            .bytes()
+            .zip(entry.path.bytes())
+            .take_while(|(a, b)| a == b)
+            .count();
+        write_varint(data, prev_path.len() - common);
+        data.extend_from_slice(&entry.path.as_bytes()[common..]);
+        data.push(0);
+    } else {
+        data.extend_from_slice(entry.path.as_bytes());
+        let entry_len = data.len() - start;
+        let padded = (entry_len + 8) / 8 * 8;
+        data.resize(start + padded, 0);
+    }
+}
+
+fn find_nul(data: &[u8], pos: usize) -> Result<usize, Error> {
+    data.get(pos..)
+        .and_then(|rest| rest.iter().position(|&b| b == 0))
+        .map(|offset| pos + offset)
+        .ok_or_else(|| {
+            Error::Parse("Index entry path is not terminated".to_owned())
+        })
+}
+
+/// Read git's offset varint, returning the value and the bytes consumed.
+fn read_varint(data: &[u8], pos: usize) -> Result<(usize, usize), Error> {
+    let mut len = 0;
+    let mut value = 0usize;
+    loop {
+        let Some(&byte) = data.get(pos + len) else {
+            return Err("Index entry is truncated".into());
+        };
+        len += 1;
+        value = (value << 7) | usize::from(byte & 0x7f);
+        if byte & 0x80 == 0 {
+            return Ok((value, len));
+        }
+        value += 1;
+    }
+}
+
+/// Write git's offset varint.
+fn write_varint(data: &mut Vec<u8>, mut value: usize) {
+    #[allow(clippy::cast_possible_truncation)]
+    let mut bytes = vec![(value & 0x7f) as u8];
+    value >>= 7;
+    while value != 0 {
+        value -= 1;
+        #[allow(clippy::cast_possible_truncation)]
+        bytes.push(0x80 | (value & 0x7f) as u8);
+        value >>= 7;
+    }
+    bytes.reverse();
+    data.extend_from_slice(&bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHA_SIZE: usize = 20;
+
+    fn sample_index(version: u32) -> GitIndex {
+        let mut index = GitIndex::new();
+        index.version = version;
+        index
+            .add(IndexEntry::new(0o100_644, &"a".repeat(40), "src/main.rs"))
+            .add(IndexEntry::new(0o100_755, &"b".repeat(40), "run.sh"))
+            .add(IndexEntry::new(0o100_644, &"c".repeat(40), "src/lib.rs"));
+        index.extensions.push(IndexExtension {
+            signature: *b"OPAQ",
+            data: b"opaque".to_vec(),
+        });
+        index
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for version in 2..=4 {
+            let index = sample_index(version);
+            let parsed = GitIndex::parse(&index.serialize()).unwrap();
+            assert_eq!(parsed, index);
+        }
+    }
+
+    #[test]
+    fn test_entries_sorted() {
+        let index = sample_index(2);
+        let paths = index
+            .entries()
+            .iter()
+            .map(|entry| entry.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, ["run.sh", "src/lib.rs", "src/main.rs"]);
+        assert!(index.contains("src/lib.rs"));
+        assert!(!index.contains("src"));
+    }
+
+    #[test]
+    fn test_v2_padding() {
+        let data = sample_index(2).serialize();
+        // 62 bytes of fields plus "run.sh" pads to 72
+        assert_eq!(&data[HEADER_SIZE + 62..HEADER_SIZE + 68], b"run.sh");
+        assert!(data[HEADER_SIZE + 68..HEADER_SIZE + 72]
+            .iter()
+            .all(|&b| b == 0));
+        assert_eq!(
+            &data[HEADER_SIZE + 72 + 62..HEADER_SIZE + 72 + 72],
+            b"src/lib.rs"
+        );
+    }
+
+    #[test]
+    fn test_checksum_mismatch() {
+        let mut data = sample_index(2).serialize();
+        let last = data.len() - 1;
+        data[last] ^= 0xff;
+        assert!(GitIndex::parse(&data).is_err());
+    }
+
+    #[test]
+    fn test_cache_tree_round_trip() {
+        // One valid tree with an invalidated subtree, as git writes them
+        let mut data = b"\x006 2\n".to_vec();
+        data.extend_from_slice(&[0xab; SHA_SIZE]);
+        data.extend_from_slice(b"c\x001 0\n");
+        data.extend_from_slice(&[0xcd; SHA_SIZE]);
+        data.extend_from_slice(b"src\x00-1 0\n");
+
+        let tree = CacheTree::parse(&data, ObjectFormat::Sha1).unwrap();
+        assert_eq!(tree.entry_count(), Some(6));
+        assert_eq!(tree.sha(), Some("ab".repeat(SHA_SIZE).as_str()));
+        assert_eq!(tree.find("src").unwrap().sha(), None);
+        assert_eq!(tree.serialize(ObjectFormat::Sha1), data);
+
+        let mut index = sample_index(2);
+        index.cache_tree = Some(tree);
+        let parsed = GitIndex::parse(&index.serialize()).unwrap();
+        assert_eq!(parsed, index);
+        assert_eq!(parsed.extensions().len(), 1);
+
+        assert!(
+            CacheTree::parse(&data[..data.len() - 1], ObjectFormat::Sha1)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_varint() {
+        for value in [0, 1, 127, 128, 255, 16_511, 16_512, 1 << 20] {
+            let mut data = vec![];
+            write_varint(&mut data, value);
+            assert_eq!(read_varint(&data, 0).unwrap(), (value, data.len()));
+        }
+    }
+}
@@ -0,0 +1,173 @@
+//! The split index (`link`) extension
+//!
+//! A split index keeps most of its entries in a shared index file,
+//! `sharedindex.<sha>` in the git directory, named after its checksum. The
+//! index file itself only holds the entries that changed since the shared
+//! index was written, so updating a large index writes a small file.
+//!
+//! The extension holds the name of the shared index, followed by two EWAH
+//! bitmaps of positions in the shared index: the entries that are deleted,
+//! and the entries that are replaced. The replacements are the first entries
+//! of the split index, in order, with empty paths as their paths are those
+//! of the entries they replace. The remaining entries are added.
+
+use crate::core::{Error, ObjectFormat};
+use crate::utils::ewah::EwahBitmap;
+use crate::utils::hex;
+
+use super::{IndexEntry, NAME_MASK};
+
+/// The signature of the split index extension.
+pub const SIGNATURE: &[u8; 4] = b"link";
+
+/// The default share of entries that may be outside the shared index before
+/// it is rewritten, as a percentage of all entries.
+pub(super) const DEFAULT_MAX_PERCENT_CHANGE: usize = 20;
+
+/// The shared index a split index is based on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct SharedIndex {
+    /// The checksum of the shared index file, which names it
+    pub(super) sha: String,
+    /// The entries of the shared index
+    pub(super) entries: Vec<IndexEntry>,
+}
+
+/// The payload of the extension.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct Link {
+    /// The checksum of the shared index file
+    pub(super) shared: String,
+    /// The positions of the shared entries that are deleted
+    pub(super) delete: EwahBitmap,
+    /// The positions of the shared entries that are replaced
+    pub(super) replace: EwahBitmap,
+}
+
+impl Link {
+    pub(super) fn parse(
+        data: &[u8],
+        format: ObjectFormat,
+    ) -> Result<Self, Error> {
+        let Some(sha) = data.get(..format.hash_len()) else {
+            return Err("Split index link is truncated".into());
+        };
+        let mut pos = sha.len();
+        let (delete, replace) = if pos == data.len() {
+            (EwahBitmap::default(), EwahBitmap::default())
+        } else {
+            let (delete, len) = EwahBitmap::parse(&data[pos..])?;
+            pos += len;
+            let (replace, len) = EwahBitmap::parse(&data[pos..])?;
+            pos += len;
+            (delete, replace)
+        };
+        if pos != data.len() {
+            return Err("Split index link has trailing data".into());
+        }
+
+        Ok(Self {
+            shared: hex::encode(sha),
+            delete,
+            replace,
+        })
+    }
+
+    pub(super) fn serialize(&self, format: ObjectFormat) -> Vec<u8> {
+        let mut data = hex::decode(&self.shared)
+            .unwrap_or_else(|_| vec![0; format.hash_len()]);
+        data.extend_from_slice(&self.delete.serialize());
+        data.extend_from_slice(&self.replace.serialize());
+        data
+    }
+}
+
+/// Apply the entries of a split index to the entries of its shared index,
+/// returning the entries of the whole index, sorted.
+pub(super) fn merge(
+    shared: &[IndexEntry],
+    link: &Link,
+    split: Vec<IndexEntry>,
+) -> Result<Vec<IndexEntry>, Error> {
+    let corrupt = || "Split index link is corrupt".to_owned();
+    if link.delete.bit_size() > shared.len()
+        || link.replace.bit_size() > shared.len()
+    {
+        return Err(corrupt().into());
+    }
+
+    let mut split = split.into_iter();
+    let mut entries = Vec::with_capacity(shared.len() + split.len());
+    for (pos, entry) in shared.iter().enumerate() {
+        let replacement = if link.replace.contains(pos) {
+            let replacement = split.next().ok_or_else(corrupt)?;
+            if !replacement.path.is_empty() {
+                return Err(corrupt().into());
+            }
+            Some(replacement)
+        } else {
+            None
+        };
+        if link.delete.contains(pos) {
+            continue;
+        }
+
+        entries.push(match replacement {
+            Some(mut replacement) => {
+                replacement.path.clone_from(&entry.path);
+                replacement.flags = (replacement.flags & !NAME_MASK)
+                    | (entry.flags & NAME_MASK);
+                replacement
+            }
+            None => entry.clone(),
+        });
+    }
+
+    entries.extend(split);
+    entries.sort_by(|a, b| (&a.path, a.stage()).cmp(&(&b.path, b.stage())));
+    Ok(entries)
+}
+
+/// Split `entries` against the entries of the shared index, returning the
+/// bitmaps of deleted and replaced shared entries, and the entries to write
+/// to the split index.
+pub(super) fn split(
+    shared: &SharedIndex,
+    entries: &[IndexEntry],
+) -> (Link, Vec<IndexEntry>) {
+    let mut replaced = vec![];
+    let mut replacements = vec![];
+    let mut added = vec![];
+    let mut kept = vec![false; shared.entries.len()];
+    for entry in entries {
+        let found = shared.entries.binary_search_by(|e| {
+            (e.path.as_str(), e.stage()).cmp(&(&entry.path, entry.stage()))
+        });
+        match found {
+            Ok(pos) => {
+                kept[pos] = true;
+                if shared.entries[pos] != *entry {
+                    replaced.push(pos);
+                    let mut replacement = entry.clone();
+                    replacement.path.clear();
+                    replacement.flags &= !NAME_MASK;
+                    replacements.push(replacement);
+                }
+            }
+            Err(_) => added.push(entry.clone()),
+        }
+    }
+
+    let deleted = kept
+        .iter()
+        .enumerate()
+        .filter(|(_, kept)| !**kept)
+        .map(|(pos, _)| pos);
+    let link = Link {
+        shared: shared.sha.clone(),
+        delete: EwahBitmap::from_positions(deleted),
+        replace: EwahBitmap::from_positions(replaced),
+    };
+    replacements.extend(added);
+    (link, replacements)
+}
@@ -0,0 +1,614 @@
+//! The untracked cache (`UNTR`) index extension
+//!
+//! Listing untracked files means reading every directory of the worktree
+//! that is not ignored. The untracked cache records, for each directory, the
+//! untracked files found in it along with the directory's modification time
+//! and the hash of its `.gitignore`. A directory that has not changed since
+//! can reuse its listing without being read again. Adding or removing files
+//! in a directory changes its modification time, while staging a file
+//! invalidates the listings of its directories.
+//!
+//! The cache is only valid for the environment it was made in, recorded as
+//! the location of the worktree and the operating system, for the flags
+//! that decide how untracked directories are listed, and for the contents of
+//! `$GIT_DIR/info/exclude` and `core.excludesFile`.
+//!
+//! The directories are stored depth first, each as its number of untracked
+//! entries and of subdirectories, its name and its untracked entries. EWAH
+//! bitmaps of the valid directories, the directories that were only checked
+//! for untracked files, and the directories with a `.gitignore` follow, then
+//! the stat data of the valid directories and the hashes of the `.gitignore`
+//! files.
+
+use std::fs;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use crate::core::objects::{self, blob::Blob, worktree, GitObject};
+use crate::core::{Error, GitRepository, ObjectFormat};
+use crate::utils::ewah::EwahBitmap;
+use crate::utils::hex;
+
+use super::{read_u32, read_varint, write_varint, GitIndex};
+
+/// The signature of the untracked cache extension.
+pub const SIGNATURE: &[u8; 4] = b"UNTR";
+
+/// Untracked directories are listed as `dir/` instead of by their files.
+pub const SHOW_OTHER_DIRECTORIES: u32 = 1 << 1;
+/// Untracked directories without untracked files are not listed.
+pub const HIDE_EMPTY_DIRECTORIES: u32 = 1 << 2;
+
+/// The name of the ignore file read in each directory.
+const EXCLUDE_PER_DIR: &str = ".gitignore";
+/// The size of stat data on disk, as nine 32 bit fields.
+const STAT_SIZE: usize = 36;
+
+/// The cached filesystem metadata of a file or directory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct StatData {
+    ctime: (u32, u32),
+    mtime: (u32, u32),
+    dev: u32,
+    ino: u32,
+    uid: u32,
+    gid: u32,
+    size: u32,
+}
+
+impl StatData {
+    /// The metadata of the file at `path`, or `None` if it does not exist.
+    /// Like index entries, only the modification time and size are kept.
+    fn of(path: &Path) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        let mtime = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .unwrap_or_default();
+        #[allow(clippy::cast_possible_truncation)]
+        Some(Self {
+            mtime: (mtime.as_secs() as u32, mtime.subsec_nanos()),
+            size: metadata.len() as u32,
+            ..Self::default()
+        })
+    }
+
+    /// Whether `other` describes the same unchanged file.
+    fn matches(&self, other: &Self) -> bool {
+        self.mtime != (0, 0)
+            && (self.mtime, self.size) == (other.mtime, other.size)
+    }
+
+    fn parse(data: &[u8]) -> Result<Self, Error> {
+        let field = |i: usize| read_u32(data, i * 4);
+        Ok(Self {
+            ctime: (field(0)?, field(1)?),
+            mtime: (field(2)?, field(3)?),
+            dev: field(4)?,
+            ino: field(5)?,
+            uid: field(6)?,
+            gid: field(7)?,
+            size: field(8)?,
+        })
+    }
+
+    fn serialize(&self, data: &mut Vec<u8>) {
+        let fields = [
+            self.ctime.0,
+            self.ctime.1,
+            self.mtime.0,
+            self.mtime.1,
+            self.dev,
+            self.ino,
+            self.uid,
+            self.gid,
+            self.size,
+        ];
+        for field in fields {
+            data.extend_from_slice(&field.to_be_bytes());
+        }
+    }
+}
+
+/// The metadata and blob hash of an ignore file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct OidStat {
+    stat: StatData,
+    /// The hash of the file's contents, or `None` if it does not exist
+    sha: Option<String>,
+}
+
+impl OidStat {
+    fn of(path: &Path, format: ObjectFormat) -> Self {
+        Self {
+            stat: StatData::of(path).unwrap_or_default(),
+            sha: blob_sha(path, format),
+        }
+    }
+}
+
+/// The cached listing of a directory.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct UntrackedDir {
+    /// The name of the directory in its parent, empty for the root.
+    name: String,
+    /// Whether `untracked` and `stat` describe the directory.
+    valid: bool,
+    /// Whether the directory was only checked for any untracked file.
+    check_only: bool,
+    stat: StatData,
+    /// The hash of the directory's `.gitignore`, if it has one.
+    exclude_sha: Option<String>,
+    /// The untracked files in the directory, with a trailing `/` for
+    /// untracked directories.
+    untracked: Vec<String>,
+    /// The listings of the subdirectories that were searched, by name.
+    dirs: Vec<UntrackedDir>,
+}
+
+impl UntrackedDir {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            ..Self::default()
+        }
+    }
+
+    /// The number of directories in this tree, including this one.
+    fn count(&self) -> usize {
+        1 + self.dirs.iter().map(Self::count).sum::<usize>()
+    }
+
+    /// Invalidate this directory, and the directories on the way to
+    /// `path` below it.
+    fn invalidate(&mut self, path: &str) {
+        self.valid = false;
+        if let Some((dir, rest)) = path.split_once('/') {
+            if let Some(sub) = self.dirs.iter_mut().find(|sub| sub.name == dir)
+            {
+                sub.invalidate(rest);
+            }
+        }
+    }
+
+    /// Visit this directory and its subdirectories, depth first.
+    fn walk_all<'a>(&'a self, dirs: &mut Vec<&'a Self>) {
+        dirs.push(self);
+        for sub in &self.dirs {
+            sub.walk_all(dirs);
+        }
+    }
+
+    fn for_each_mut<F>(&mut self, f: &mut F) -> Result<(), Error>
+    where
+        F: FnMut(&mut Self) -> Result<(), Error>,
+    {
+        f(self)?;
+        self.dirs.iter_mut().try_for_each(|sub| sub.for_each_mut(f))
+    }
+}
+
+/// The untracked cache of an index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UntrackedCache {
+    /// The environments the cache is valid in.
+    ident: Vec<String>,
+    info_exclude: OidStat,
+    excludes_file: OidStat,
+    /// How untracked directories are listed, see [`SHOW_OTHER_DIRECTORIES`]
+    /// and [`HIDE_EMPTY_DIRECTORIES`].
+    dir_flags: u32,
+    exclude_per_dir: String,
+    /// The listing of the worktree root, if any.
+    root: Option<UntrackedDir>,
+    /// The hash algorithm the ignore files are hashed with.
+    format: ObjectFormat,
+}
+
+impl UntrackedCache {
+    /// Create an empty cache for this environment and `dir_flags`.
+    #[must_use]
+    pub fn new(repo: &GitRepository, dir_flags: u32) -> Self {
+        Self {
+            ident: vec![ident(repo)],
+            info_exclude: OidStat::default(),
+            excludes_file: OidStat::default(),
+            dir_flags,
+            exclude_per_dir: EXCLUDE_PER_DIR.to_owned(),
+            root: None,
+            format: repo.object_format(),
+        }
+    }
+
+    /// Take the cache to list the untracked files of `index` with
+    /// `dir_flags` out of the index, if it should be used.
+    ///
+    /// A cache is made if there is none and `core.untrackedCache` is true,
+    /// and none is used if it is false. A cache made for other flags is
+    /// replaced, while a cache made elsewhere is left alone unless
+    /// `core.untrackedCache` is true. Every listing is dropped if the ignore
+    /// files outside the worktree changed.
+    pub fn take(
+        repo: &GitRepository,
+        index: &mut GitIndex,
+        dir_flags: u32,
+    ) -> Option<Self> {
+        let setting = repo
+            .config()
+            .get("core")
+            .and_then(|core| core.get_bool("untrackedCache"));
+        let ident = ident(repo);
+        let usable = |cache: &Self| cache.ident.contains(&ident);
+
+        let mut cache = match (setting, index.untracked_cache()) {
+            (Some(false), _) => return None,
+            (_, Some(cache)) if usable(cache) => {
+                let cache = index.untracked.take()?;
+                if cache.dir_flags == dir_flags
+                    && cache.exclude_per_dir == EXCLUDE_PER_DIR
+                {
+                    cache
+                } else {
+                    Self::new(repo, dir_flags)
+                }
+            }
+            (Some(true), _) => Self::new(repo, dir_flags),
+            _ => return None,
+        };
+
+        let format = repo.object_format();
+        let info_exclude = OidStat::of(
+            &repo.common_dir().join("info").join("exclude"),
+            format,
+        );
+        let excludes_file = worktree::global_excludes_file(repo)
+            .map(|file| OidStat::of(&file, format))
+            .unwrap_or_default();
+        if info_exclude.sha != cache.info_exclude.sha
+            || excludes_file.sha != cache.excludes_file.sha
+        {
+            cache.root = None;
+        }
+        cache.info_exclude = info_exclude;
+        cache.excludes_file = excludes_file;
+        Some(cache)
+    }
+
+    /// The flags the cache lists untracked directories with.
+    #[must_use]
+    pub fn dir_flags(&self) -> u32 {
+        self.dir_flags
+    }
+
+    /// The cached untracked entries of the directory at `path`, if its
+    /// listing is valid.
+    #[must_use]
+    pub fn untracked(&self, path: &str) -> Option<&[String]> {
+        let dir = path
+            .split('/')
+            .filter(|name| !name.is_empty())
+            .try_fold(self.root.as_ref()?, |dir, name| {
+                dir.dirs.iter().find(|sub| sub.name == name)
+            })?;
+        dir.valid.then_some(dir.untracked.as_slice())
+    }
+
+    /// Invalidate the listings of the directories containing `path`.
+    pub fn invalidate(&mut self, path: &str) {
+        if let Some(root) = &mut self.root {
+            root.invalidate(path);
+        }
+    }
+
+    /// List the untracked files under `root`, reusing the listings of the
+    /// directories that have not changed.
+    ///
+    /// `scan` lists a directory, given its path relative to `root`, as its
+    /// untracked entries and the subdirectories to search, both by name.
+    /// The untracked files are returned by path, along with whether any
+    /// listing changed.
+    ///
+    /// # Errors
+    ///
+    /// If `scan` fails.
+    pub fn walk<F>(
+        &mut self,
+        root: &Path,
+        mut scan: F,
+    ) -> Result<(Vec<String>, bool), Error>
+    where
+        F: FnMut(&str) -> Result<(Vec<String>, Vec<String>), Error>,
+    {
+        let mut changed = self.root.is_none();
+        let dir = self.root.get_or_insert_with(UntrackedDir::default);
+        let mut untracked = vec![];
+        let format = self.format;
+        walk_dir(
+            root,
+            "",
+            dir,
+            &mut scan,
+            &mut untracked,
+            &mut changed,
+            format,
+        )?;
+        Ok((untracked, changed))
+    }
+
+    /// Parse the payload of the extension, whose ignore files are hashed
+    /// with the hash algorithm of `format`.
+    ///
+    /// # Errors
+    ///
+    /// If the payload is truncated or malformed.
+    pub fn parse(data: &[u8], format: ObjectFormat) -> Result<Self, Error> {
+        let hash_len = format.hash_len();
+        let err = || "Untracked cache is truncated".to_owned();
+        let (ident_len, len) = read_varint(data, 0)?;
+        let ident = data.get(len..len + ident_len).ok_or_else(err)?;
+        let ident = ident
+            .split(|&b| b == 0)
+            .filter(|env| !env.is_empty())
+            .map(|env| String::from_utf8_lossy(env).into_owned())
+            .collect();
+
+        let mut pos = len + ident_len;
+        let fixed = data
+            .get(pos..pos + 2 * STAT_SIZE + 4 + 2 * hash_len)
+            .ok_or_else(err)?;
+        let shas = &fixed[2 * STAT_SIZE + 4..];
+        let info_exclude = OidStat {
+            stat: StatData::parse(fixed)?,
+            sha: parse_sha(&shas[..hash_len]),
+        };
+        let excludes_file = OidStat {
+            stat: StatData::parse(&fixed[STAT_SIZE..])?,
+            sha: parse_sha(&shas[hash_len..]),
+        };
+        let dir_flags = read_u32(fixed, 2 * STAT_SIZE)?;
+        pos += fixed.len();
+
+        let nul = find_nul(data, pos)?;
+        let exclude_per_dir =
+            String::from_utf8_lossy(&data[pos..nul]).into_owned();
+        pos = nul + 1;
+
+        let mut cache = Self {
+            ident,
+            info_exclude,
+            excludes_file,
+            dir_flags,
+            exclude_per_dir,
+            root: None,
+            format,
+        };
+        if pos >= data.len() {
+            return Ok(cache);
+        }
+        let (dir_count, len) = read_varint(data, pos)?;
+        pos += len;
+        if dir_count == 0 {
+            return Ok(cache);
+        }
+
+        let mut root = parse_dir(data, &mut pos)?;
+        if root.count() != dir_count {
+            return Err(
+                "Untracked cache has the wrong number of directories".into()
+            );
+        }
+        let mut bitmap = || -> Result<EwahBitmap, Error> {
+            let (bitmap, len) =
+                EwahBitmap::parse(data.get(pos..).ok_or_else(err)?)?;
+            pos += len;
+            Ok(bitmap)
+        };
+        let (valid, check_only, exclude_valid) =
+            (bitmap()?, bitmap()?, bitmap()?);
+
+        // The stat data and hashes are in the order of their directories
+        let mut stat_pos = pos;
+        let mut sha_pos = pos + valid.count() * STAT_SIZE;
+        let mut i = 0;
+        root.for_each_mut(&mut |dir| {
+            if valid.contains(i) {
+                let stat = data.get(stat_pos..stat_pos + STAT_SIZE);
+                dir.valid = true;
+                dir.stat = StatData::parse(stat.ok_or_else(err)?)?;
+                stat_pos += STAT_SIZE;
+            }
+            dir.check_only = check_only.contains(i);
+            if exclude_valid.contains(i) {
+                let sha = data.get(sha_pos..sha_pos + hash_len);
+                dir.exclude_sha = Some(hex::encode(sha.ok_or_else(err)?));
+                sha_pos += hash_len;
+            }
+            i += 1;
+            Ok(())
+        })?;
+
+        cache.root = Some(root);
+        Ok(cache)
+    }
+
+    /// Serialize the cache as the payload of the extension.
+    #[must_use]
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut data = vec![];
+        let ident = self
+            .ident
+            .iter()
+            .flat_map(|env| env.bytes().chain([0]))
+            .collect::<Vec<_>>();
+        write_varint(&mut data, ident.len());
+        data.extend_from_slice(&ident);
+        self.info_exclude.stat.serialize(&mut data);
+        self.excludes_file.stat.serialize(&mut data);
+        data.extend_from_slice(&self.dir_flags.to_be_bytes());
+        for oid_stat in [&self.info_exclude, &self.excludes_file] {
+            data.extend_from_slice(&serialize_sha(
+                oid_stat.sha.as_deref(),
+                self.format,
+            ));
+        }
+        data.extend_from_slice(self.exclude_per_dir.as_bytes());
+        data.push(0);
+
+        let mut dirs = vec![];
+        if let Some(root) = &self.root {
+            root.walk_all(&mut dirs);
+        }
+        write_varint(&mut data, dirs.len());
+        if !dirs.is_empty() {
+            for dir in &dirs {
+                write_varint(&mut data, dir.untracked.len());
+                write_varint(&mut data, dir.dirs.len());
+                for name in std::iter::once(&dir.name).chain(&dir.untracked) {
+                    data.extend_from_slice(name.as_bytes());
+                    data.push(0);
+                }
+            }
+
+            let positions = |keep: fn(&UntrackedDir) -> bool| {
+                let positions =
+                    dirs.iter().enumerate().filter(|(_, dir)| keep(dir));
+                EwahBitmap::from_positions(positions.map(|(i, _)| i))
+            };
+            data.extend_from_slice(&positions(|dir| dir.valid).serialize());
+            data.extend_from_slice(
+                &positions(|dir| dir.check_only).serialize(),
+            );
+            data.extend_from_slice(
+                &positions(|dir| dir.exclude_sha.is_some()).serialize(),
+            );
+            for dir in dirs.iter().filter(|dir| dir.valid) {
+                dir.stat.serialize(&mut data);
+            }
+            for sha in dirs.iter().filter_map(|dir| dir.exclude_sha.as_deref())
+            {
+                data.extend_from_slice(&serialize_sha(Some(sha), self.format));
+            }
+        }
+        data.push(0);
+        data
+    }
+}
+
+/// List the untracked files of the directory at `path` and its
+/// subdirectories into `untracked`, using and updating the listing `dir`.
+fn walk_dir<F>(
+    root: &Path,
+    path: &str,
+    dir: &mut UntrackedDir,
+    scan: &mut F,
+    untracked: &mut Vec<String>,
+    changed: &mut bool,
+    format: ObjectFormat,
+) -> Result<(), Error>
+where
+    F: FnMut(&str) -> Result<(Vec<String>, Vec<String>), Error>,
+{
+    let abs_dir = root.join(path);
+    // The listings below depend on the rules of this directory's .gitignore
+    let exclude_sha = blob_sha(&abs_dir.join(EXCLUDE_PER_DIR), format);
+    if exclude_sha != dir.exclude_sha {
+        dir.exclude_sha = exclude_sha;
+        dir.valid = false;
+        dir.dirs.clear();
+    }
+
+    let stat = StatData::of(&abs_dir).unwrap_or_default();
+    if !dir.valid || dir.check_only || !dir.stat.matches(&stat) {
+        let (files, subdirs) = scan(path)?;
+        let mut dirs = std::mem::take(&mut dir.dirs);
+        dir.dirs = subdirs
+            .iter()
+            .map(|name| match dirs.iter().position(|sub| sub.name == *name) {
+                Some(i) => dirs.swap_remove(i),
+                None => UntrackedDir::new(name),
+            })
+            .collect();
+        dir.dirs.sort_by(|a, b| a.name.cmp(&b.name));
+        dir.untracked = files;
+        dir.untracked.sort();
+        dir.valid = true;
+        dir.check_only = false;
+        dir.stat = stat;
+        *changed = true;
+    }
+
+    let join = |name: &str| {
+        if path.is_empty() {
+            name.to_owned()
+        } else {
+            format!("{path}/{name}")
+        }
+    };
+    untracked.extend(dir.untracked.iter().map(|name| join(name)));
+    for sub in &mut dir.dirs {
+        let sub_path = join(&sub.name);
+        walk_dir(root, &sub_path, sub, scan, untracked, changed, format)?;
+    }
+    Ok(())
+}
+
+fn parse_dir(data: &[u8], pos: &mut usize) -> Result<UntrackedDir, Error> {
+    let (untracked_count, len) = read_varint(data, *pos)?;
+    *pos += len;
+    let (dir_count, len) = read_varint(data, *pos)?;
+    *pos += len;
+
+    let mut read_name = || -> Result<String, Error> {
+        let nul = find_nul(data, *pos)?;
+        let name = String::from_utf8_lossy(&data[*pos..nul]).into_owned();
+        *pos = nul + 1;
+        Ok(name)
+    };
+    let mut dir = UntrackedDir::new(&read_name()?);
+    for _ in 0..untracked_count {
+        dir.untracked.push(read_name()?);
+    }
+    for _ in 0..dir_count {
+        dir.dirs.push(parse_dir(data, pos)?);
+    }
+    Ok(dir)
+}
+
+fn find_nul(data: &[u8], pos: usize) -> Result<usize, Error> {
+    data.get(pos..)
+        .and_then(|rest| rest.iter().position(|&b| b == 0))
+        .map(|offset| pos + offset)
+        .ok_or_else(|| {
+            Error::Parse("Untracked cache name is not terminated".to_owned())
+        })
+}
+
+/// A hash on disk, where all zeros stands for a missing file.
+fn parse_sha(sha: &[u8]) -> Option<String> {
+    sha.iter().any(|&b| b != 0).then(|| hex::encode(sha))
+}
+
+fn serialize_sha(sha: Option<&str>, format: ObjectFormat) -> Vec<u8> {
+    sha.and_then(|sha| hex::decode(sha).ok())
+        .unwrap_or_else(|| vec![0; format.hash_len()])
+}
+
+/// The hash of the file at `path` as a blob, if it exists.
+fn blob_sha(path: &Path, format: ObjectFormat) -> Option<String> {
+    let content = fs::read(path).ok()?;
+    let blob = GitObject::Blob(Blob::from(content.as_slice()));
+    Some(objects::hash_object(&blob, format).1.hex_digest())
+}
+
+/// Describes the environment a cache is made in, like git does.
+fn ident(repo: &GitRepository) -> String {
+    let system = match std::env::consts::OS {
+        "linux" => "Linux",
+        "macos" => "Darwin",
+        "windows" => "Windows",
+        "freebsd" => "FreeBSD",
+        os => os,
+    };
+    format!("Location {}, system {system}", repo.worktree().display())
+}
@@ -1,5 +1,12 @@
+pub mod advice;
 pub mod commands;
+pub mod config;
+pub mod error;
+pub mod facade;
+pub mod hooks;
+pub mod index;
 pub mod objects;
 pub mod repository;
 
+pub use error::Error;
 pub use repository::*;
@@ -12,6 +12,7 @@
 //! and format identification.
 
 use crate::core::objects::traits;
+use crate::core::Error;
 
 const BINARY_CHECK_BYTES: usize = 8000;
 
@@ -131,7 +132,7 @@ impl traits::Deserialize for Blob {
     ///
     /// # Errors
     /// This implementation always succeeds, so it never returns an `Err` variant.
-    fn deserialize(data: &[u8]) -> Result<Self, String> {
+    fn deserialize(data: &[u8]) -> Result<Self, Error> {
         Ok(Blob {
             data: Vec::from(data),
         })
@@ -192,7 +193,7 @@ mod tests {
         let chars = (0..=required_len)
             .map(|i| control_chars[i % n])
             .collect::<String>();
-        let content = format!("{content}{chars}",);
+        let content = format!("{content}{chars}");
         assert!(Blob::is_binary(content.as_bytes()));
     }
 
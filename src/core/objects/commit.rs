@@ -13,8 +13,15 @@
 //! Git-compatible operations such as serialization, deserialization,
 //! and format identification.
 
+use std::fmt;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use crate::core::objects::oid::Oid;
 use crate::core::objects::traits;
+use crate::core::Error;
 use crate::utils::collections::kvlm::KVLM;
+use crate::utils::datetime::DateTime;
 
 /// Represents a Git commit object, encapsulating commit metadata.
 #[derive(Debug)]
@@ -32,6 +39,164 @@ impl Commit {
     pub fn new() -> Self {
         Self { kvlm: KVLM::new() }
     }
+
+    /// The name of the tree of the commit.
+    ///
+    /// # Errors
+    ///
+    /// If the commit has no tree, or its name is malformed.
+    pub fn tree(&self) -> Result<Oid, Error> {
+        self.value(b"tree")
+            .ok_or_else(|| Error::Parse("Commit has no tree".to_owned()))?
+            .parse()
+    }
+
+    /// The names of the parents of the commit, in order, which are none for
+    /// a root commit and more than one for a merge.
+    ///
+    /// # Errors
+    ///
+    /// If the name of a parent is malformed.
+    pub fn parents(&self) -> Result<Vec<Oid>, Error> {
+        self.kvlm
+            .get_key(b"parent")
+            .into_iter()
+            .flatten()
+            .map(|parent| String::from_utf8_lossy(parent).parse())
+            .collect()
+    }
+
+    /// Who wrote the changes of the commit, and when.
+    ///
+    /// # Errors
+    ///
+    /// If the commit has no author, or it is malformed.
+    pub fn author(&self) -> Result<Signature, Error> {
+        self.value(b"author")
+            .ok_or_else(|| Error::Parse("Commit has no author".to_owned()))?
+            .parse()
+    }
+
+    /// Who made the commit, and when.
+    ///
+    /// # Errors
+    ///
+    /// If the commit has no committer, or it is malformed.
+    pub fn committer(&self) -> Result<Signature, Error> {
+        self.value(b"committer")
+            .ok_or_else(|| Error::Parse("Commit has no committer".to_owned()))?
+            .parse()
+    }
+
+    /// The message of the commit, empty if it has none.
+    #[must_use]
+    pub fn message(&self) -> String {
+        self.kvlm
+            .get_msg()
+            .map(|msg| String::from_utf8_lossy(msg).into_owned())
+            .unwrap_or_default()
+    }
+
+    /// The first value of the header `key`, if the commit has it.
+    fn value(&self, key: &[u8]) -> Option<String> {
+        let value = self.kvlm.get_key(key)?.first()?;
+        Some(String::from_utf8_lossy(value).into_owned())
+    }
+}
+
+/// Who authored or committed a commit, and when, as in the `author` and
+/// `committer` headers of a commit, like
+/// `A U Thor <author@example.com> 1112911993 -0700`.
+///
+/// # Examples
+///
+/// ```
+/// use mini_git::core::objects::commit::Signature;
+///
+/// let sig: Signature = "A U Thor <author@example.com> 1112911993 -0700".parse()?;
+/// assert_eq!(sig.name, "A U Thor");
+/// assert_eq!(sig.email, "author@example.com");
+/// assert_eq!(sig.time, 1_112_911_993);
+/// assert_eq!(sig.offset, -420);
+/// assert_eq!(sig.to_string(), "A U Thor <author@example.com> 1112911993 -0700");
+/// # Ok::<(), String>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    /// The name of the person
+    pub name: String,
+    /// The email address of the person
+    pub email: String,
+    /// Seconds since the Unix epoch
+    pub time: u64,
+    /// The offset of the time zone from UTC, in minutes
+    pub offset: i32,
+}
+
+impl Signature {
+    /// Makes a signature of `name` and `email` at the current time, in the
+    /// local time zone.
+    ///
+    /// # Panics
+    ///
+    /// If the system time is before the Unix epoch.
+    #[must_use]
+    pub fn now(name: &str, email: &str) -> Self {
+        let time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("Time went backwards");
+        Self {
+            name: name.to_owned(),
+            email: email.to_owned(),
+            time: time.as_secs(),
+            offset: DateTime::now().tz().offset_minutes(),
+        }
+    }
+}
+
+impl FromStr for Signature {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let malformed = || Error::Parse(format!("Malformed signature: {s}"));
+        let (name, rest) = s.split_once('<').ok_or_else(malformed)?;
+        let (email, date) = rest.split_once('>').ok_or_else(malformed)?;
+        let (time, offset) =
+            date.trim().split_once(' ').ok_or_else(malformed)?;
+        let offset = offset.trim();
+        let sign = match offset.as_bytes().first() {
+            Some(b'+') => 1,
+            Some(b'-') => -1,
+            _ => return Err(malformed()),
+        };
+        if offset.len() != 5 || !offset[1..].bytes().all(|b| b.is_ascii_digit())
+        {
+            return Err(malformed());
+        }
+        let hhmm = offset[1..].parse::<i32>().map_err(|_| malformed())?;
+        Ok(Self {
+            name: name.trim().to_owned(),
+            email: email.to_owned(),
+            time: time.parse().map_err(|_| malformed())?,
+            offset: sign * (hhmm / 100 * 60 + hhmm % 100),
+        })
+    }
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.offset < 0 { '-' } else { '+' };
+        let offset = self.offset.unsigned_abs();
+        write!(
+            f,
+            "{} <{}> {} {sign}{:02}{:02}",
+            self.name,
+            self.email,
+            self.time,
+            offset / 60,
+            offset % 60
+        )
+    }
 }
 
 impl Default for Commit {
@@ -63,7 +228,7 @@ impl traits::KVLM for Commit {
     ///
     /// # Returns
     /// A new `Commit` instance initialized with the provided KVLM data.
-    fn with_kvlm(kvlm: crate::utils::collections::kvlm::KVLM) -> Self {
+    fn with_kvlm(kvlm: KVLM) -> Self {
         Self { kvlm }
     }
 
@@ -71,7 +236,7 @@ impl traits::KVLM for Commit {
     ///
     /// # Returns
     /// A reference to the KVLM structure containing the commit's metadata.
-    fn kvlm(&self) -> &crate::utils::collections::kvlm::KVLM {
+    fn kvlm(&self) -> &KVLM {
         &self.kvlm
     }
 }
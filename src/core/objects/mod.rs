@@ -1,26 +1,40 @@
 pub mod blob;
 pub mod commit;
+pub mod oid;
 pub mod packfiles;
+pub mod packwriter;
+pub mod revwalk;
+pub mod store;
 pub mod tag;
 pub mod traits;
 pub mod tree;
 pub mod worktree;
 
 use std::fs;
+use std::io::Read;
 use std::path::Path;
 
-use crate::core::GitRepository;
+use crate::core::{
+    is_valid_branch_name, write_locked, Error, GitRepository, ObjectFormat,
+    ObjectHasher,
+};
 use crate::utils::collections::ordered_map::OrderedMap;
-use crate::utils::hex;
 use crate::utils::path;
-use crate::utils::sha1;
-use crate::utils::zlib;
+use crate::utils::trace;
+use oid::Oid;
+use store::{ObjectDatabase, ObjectReader};
 use traits::{Deserialize, Format, Serialize, KVLM};
 
 // Defined below
 use GitObject::{Blob, Commit, Tag, Tree};
 
-static OBJECTS_DIR: &str = "objects";
+/// The minimum length of an abbreviated object name, same as git's default.
+pub const MIN_ABBREV_LEN: usize = 7;
+/// The default of `core.bigFileThreshold`, same as git's default.
+pub const DEFAULT_BIG_FILE_THRESHOLD: u64 = 512 * 1024 * 1024;
+
+/// How many symbolic refs are followed when updating a ref, like git.
+const MAX_SYMREF_DEPTH: usize = 5;
 static SPACE_BYTE: u8 = b' ';
 static NULL_BYTE: u8 = b'\0';
 
@@ -32,6 +46,9 @@ pub enum FileSource {
 
     /// A file located in the working tree with a specified path.
     Worktree { path: String },
+
+    /// A submodule, checked out at the commit with the given SHA identifier.
+    Gitlink { path: String, sha: String },
 }
 
 impl FileSource {
@@ -52,6 +69,9 @@ impl FileSource {
     ///   - For `Blob` sources, the object is not a blob.
     ///   - For `Worktree` sources, the file could not be read from the filesystem.
     ///
+    /// `Gitlink` sources never fail. Like git, their contents are the line
+    /// `Subproject commit <sha>`.
+    ///
     /// # Examples
     ///
     /// ```no_run
@@ -65,29 +85,37 @@ impl FileSource {
     ///
     /// # Ok::<(), String>(())
     /// ```
-    pub fn contents(&self, repo: &GitRepository) -> Result<Vec<u8>, String> {
+    pub fn contents(&self, repo: &GitRepository) -> Result<Vec<u8>, Error> {
         Ok(match self {
-            FileSource::Blob { sha, .. } => match read_object(repo, sha)? {
-                GitObject::Blob(blob) => blob.data,
-                x => {
-                    return Err(format!(
-                        "Expect object {sha} to be a blob, but was {}",
-                        String::from_utf8_lossy(x.format())
-                    ))
+            FileSource::Blob { sha, .. } => {
+                match read_object(repo, &sha.parse()?)? {
+                    GitObject::Blob(blob) => blob.data,
+                    x => {
+                        return Err(format!(
+                            "Expect object {sha} to be a blob, but was {}",
+                            String::from_utf8_lossy(x.format())
+                        )
+                        .into())
+                    }
                 }
-            },
+            }
             FileSource::Worktree { path } => match fs::read(path) {
                 Ok(data) => data,
                 Err(e) => {
                     return Err(format!(
                         "Failed to read file {path}! Error: {e}"
-                    ))
+                    )
+                    .into())
                 }
             },
+            FileSource::Gitlink { sha, .. } => {
+                format!("Subproject commit {sha}\n").into_bytes()
+            }
         })
     }
 
-    /// Returns the path of the file, either from a Git blob or working tree.
+    /// Returns the path of the file, whether from a Git blob, the working tree
+    /// or a submodule.
     ///
     /// # Returns
     ///
@@ -105,17 +133,19 @@ impl FileSource {
     #[must_use]
     pub fn path(&self) -> String {
         match self {
-            FileSource::Blob { path, .. } | FileSource::Worktree { path } => {
-                path.clone()
-            }
+            FileSource::Blob { path, .. }
+            | FileSource::Worktree { path }
+            | FileSource::Gitlink { path, .. } => path.clone(),
         }
     }
 }
 
 impl AsRef<Path> for FileSource {
     fn as_ref(&self) -> &Path {
-        use FileSource::{Blob, Worktree};
-        let (Worktree { ref path } | Blob { ref path, .. }) = self;
+        use FileSource::{Blob, Gitlink, Worktree};
+        let (Worktree { ref path }
+        | Blob { ref path, .. }
+        | Gitlink { ref path, .. }) = self;
         Path::new(path.as_str())
     }
 }
@@ -128,7 +158,7 @@ impl AsRef<Path> for FileSource {
 ///
 /// # Returns
 /// - `Ok(Vec<FileSource>)` containing files from the specified tree or working directory.
-/// - `Err(String)` if an error occurs while retrieving files.
+/// - `Err(Error)` if an error occurs while retrieving files.
 ///
 /// # Errors
 /// - Returns an error if:
@@ -137,7 +167,7 @@ impl AsRef<Path> for FileSource {
 pub(super) fn get_files(
     repo: &GitRepository,
     tree: Option<&str>,
-) -> Result<Vec<FileSource>, String> {
+) -> Result<Vec<FileSource>, Error> {
     Ok(match tree {
         // Get contents from the specified tree
         Some(treeish) => {
@@ -179,7 +209,7 @@ impl GitObject {
     /// - Raw data is malformed.
     /// - It's attempted on an object of the wrong kind.
     ///
-    /// A [`String`] describing the error message is returned.
+    /// An [`Error`] describing the failure is returned.
     ///
     /// # Example
     /// ```
@@ -191,7 +221,7 @@ impl GitObject {
     /// let blob = blob.deserialize(data);
     /// println!("{blob:?}");
     /// ```
-    pub fn deserialize(&self, data: &[u8]) -> Result<GitObject, String> {
+    pub fn deserialize(&self, data: &[u8]) -> Result<GitObject, Error> {
         Ok(match self {
             Blob(..) => Blob(blob::Blob::deserialize(data)?),
             Commit(..) => Commit(commit::Commit::deserialize(data)?),
@@ -271,34 +301,48 @@ impl GitObject {
     ///
     /// # Ok::<(), String>(())
     /// ```
-    pub fn from_raw_data(raw: &[u8]) -> Result<GitObject, String> {
+    pub fn from_raw_data(raw: &[u8]) -> Result<GitObject, Error> {
+        Self::from_raw_data_with_format(raw, ObjectFormat::Sha1)
+    }
+
+    /// Builds a `GitObject` from raw data, like
+    /// [`GitObject::from_raw_data`], for a repository whose objects are named
+    /// with the hash algorithm of `object_format`.
+    ///
+    /// # Errors
+    /// This method may fail if the raw data was malformed. A error message
+    /// describing the failure is returned
+    pub fn from_raw_data_with_format(
+        raw: &[u8],
+        object_format: ObjectFormat,
+    ) -> Result<GitObject, Error> {
         let total_size = raw.len();
         let mut raw_iter = raw.iter();
 
         // Read the object format
         let Some(space_idx) = raw_iter.position(|byte| *byte == SPACE_BYTE)
         else {
-            return Err("format not specified".to_owned());
+            return Err("format not specified".into());
         };
         let format = raw[..space_idx].to_vec();
 
         // Read the object size
         let Some(null_idx) = raw_iter.position(|byte| *byte == 0) else {
-            return Err("size not specified".to_owned());
+            return Err("size not specified".into());
         };
         // Iterator position restarts from 0, add prev offset
         let null_idx = null_idx + space_idx + 1;
         let Ok(size) = String::from_utf8(raw[space_idx..null_idx].to_vec())
         else {
-            return Err("invalid size".to_owned());
+            return Err("invalid size".into());
         };
         let Ok(size) = size.trim().parse::<usize>() else {
-            return Err("failed to read size".to_owned());
+            return Err("failed to read size".into());
         };
 
         // Ensure size matches contents
         if size != (total_size - null_idx - 1) {
-            return Err("size mismatch!".to_owned());
+            return Err("size mismatch!".into());
         }
 
         let raw = &raw[(null_idx + 1)..];
@@ -308,8 +352,11 @@ impl GitObject {
             b"blob" => Ok(Blob(blob::Blob::deserialize(raw)?)),
             b"commit" => Ok(Commit(commit::Commit::deserialize(raw)?)),
             b"tag" => Ok(Tag(tag::Tag::deserialize(raw)?)),
-            b"tree" => Ok(Tree(tree::Tree::deserialize(raw)?)),
-            _ => Err(format!("Unknown format {format:?}")),
+            b"tree" => Ok(Tree(tree::Tree::deserialize_with_format(
+                raw,
+                object_format,
+            )?)),
+            _ => Err(format!("Unknown format {format:?}").into()),
         }
     }
 }
@@ -320,6 +367,8 @@ impl GitObject {
 ///
 /// This function will return an error if:
 ///
+/// * No object has the name, an [`Error::ObjectNotFound`].
+/// * More than one object has the name, an [`Error::Ambiguous`].
 /// * The reference file cannot be found or accessed.
 /// * Reading the reference file fails.
 /// * An I/O error occurs while accessing the filesystem.
@@ -345,21 +394,21 @@ pub fn find_object(
     name: &str,
     format: Option<&str>,
     follow: bool,
-) -> Result<String, String> {
+) -> Result<Oid, Error> {
     let candidates = resolve_object(repo, name)?;
 
     if candidates.is_empty() {
-        return Err(format!("No such reference {name}"));
+        return Err(Error::ObjectNotFound(name.to_owned()));
     }
 
     if candidates.len() > 1 {
-        let candidates_str = candidates.join("\n - ");
-        return Err(format!(
-            "Ambiguous reference {name}: Candidates are:\n - {candidates_str}"
-        ));
+        return Err(Error::Ambiguous {
+            name: name.to_owned(),
+            candidates,
+        });
     }
 
-    let object_id = candidates[0].clone();
+    let object_id = candidates[0];
 
     if let Some(obj_format) = format {
         let mut sha = object_id;
@@ -373,16 +422,21 @@ pub fn find_object(
                 return Ok(sha);
             }
 
-            // Follow tags
-            if obj.format() == b"tag" {
-                sha = String::from_utf8_lossy(&obj.serialize()[8..28])
-                    .to_string();
-            } else if obj.format() == b"commit" && obj_format == "tree" {
-                sha = String::from_utf8_lossy(&obj.serialize()[12..32])
-                    .to_string();
-            } else {
+            // Follow tags to their object, and commits to their tree
+            let next = match &obj {
+                Tag(tag) => tag.kvlm().get_key(b"object"),
+                Commit(commit) if obj_format == "tree" => {
+                    commit.kvlm().get_key(b"tree")
+                }
+                _ => return Ok(sha),
+            };
+            let Some(next) = next.and_then(|values| values.first()) else {
                 return Ok(sha);
-            }
+            };
+            sha = Oid::parse(
+                &String::from_utf8_lossy(next),
+                repo.object_format(),
+            )?;
         }
     } else {
         Ok(object_id)
@@ -401,7 +455,7 @@ pub fn find_object(
 ///
 /// # Returns
 ///
-/// * `Ok(Vec<String>)` - A vector of object IDs that match the given reference.
+/// * `Ok(Vec<Oid>)` - A vector of object IDs that match the given reference.
 /// * `Err(error_message)` - If an error occurs during resolution.
 ///
 /// # Errors
@@ -412,10 +466,7 @@ pub fn find_object(
 /// * Reading the reference file fails.
 /// * An I/O error occurs while accessing the filesystem.
 ///
-fn resolve_object(
-    repo: &GitRepository,
-    name: &str,
-) -> Result<Vec<String>, String> {
+fn resolve_object(repo: &GitRepository, name: &str) -> Result<Vec<Oid>, Error> {
     let mut candidates = Vec::new();
 
     // Handle the "HEAD" reference
@@ -424,34 +475,18 @@ fn resolve_object(
             candidates.push(oid);
             return Ok(candidates);
         }
-        return Err("Could not find HEAD".to_owned());
+        return Err("Could not find HEAD".into());
     }
 
     // Check for a hex string (short or full hash)
     if name.len() >= 4 && name.chars().all(|c| c.is_ascii_hexdigit()) {
-        // Check loose objects
-        let prefix = &name[..2];
-        let remainder = &name[2..];
-        if let Some(path) =
-            path::repo_dir(repo.gitdir(), &["objects", prefix], false)?
-        {
-            for entry in fs::read_dir(path).map_err(|e| e.to_string())? {
-                let entry = entry.map_err(|e| e.to_string())?;
-                let file_name = entry.file_name().to_string_lossy().to_string();
-                if file_name.starts_with(remainder) {
-                    candidates.push(format!("{prefix}{file_name}"));
-                }
-            }
-        }
-    }
-
-    // Then check packfiles
-    if let Ok(packfiles) = packfiles::find_packfiles(repo) {
-        for packfile in packfiles {
-            if let Some(full_hash) = packfile.find_object_with_prefix(name) {
-                candidates.push(full_hash);
-            }
-        }
+        let format = repo.object_format();
+        candidates.extend(
+            ObjectDatabase::open(repo)
+                .ids_with_prefix(name)?
+                .iter()
+                .filter_map(|id| Oid::parse(id, format).ok()),
+        );
     }
 
     // Check for tags
@@ -468,26 +503,77 @@ fn resolve_object(
     Ok(candidates)
 }
 
+/// Abbreviates an object id to the shortest prefix that is unique among all
+/// objects in the repository.
+///
+/// The returned prefix is never shorter than [`MIN_ABBREV_LEN`] characters,
+/// and grows past that only when another loose or packed object shares a
+/// longer prefix with `sha`.
+///
+/// # Errors
+///
+/// This function will return an error if the loose objects directory cannot
+/// be read.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use std::path::Path;
+/// use mini_git::core::GitRepository;
+/// use mini_git::core::objects::abbreviate_object;
+///
+/// let repo = GitRepository::new(Path::new("."))?;
+/// let sha = "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391";
+/// let short = abbreviate_object(&repo, sha)?;
+/// assert!(sha.starts_with(&short));
+/// # Ok::<(), String>(())
+/// ```
+pub fn abbreviate_object(
+    repo: &GitRepository,
+    sha: &str,
+) -> Result<String, Error> {
+    if sha.len() <= MIN_ABBREV_LEN {
+        return Ok(sha.to_owned());
+    }
+
+    let longest_shared =
+        ObjectDatabase::open(repo).longest_shared_prefix(sha)?;
+    let len = (longest_shared + 1).clamp(MIN_ABBREV_LEN, sha.len());
+    Ok(sha[..len].to_owned())
+}
+
 /// Creates a object Hash from an object
 ///
 /// This function returns a tuple of two values
 /// - The contents over which the hash was built
-/// - The SHA1 object built from the contents
+/// - The hasher of `object_format` built from the contents, which checks the
+///   contents for being part of a collision attack, see [`reject_collision`]
 ///
 /// Example
 /// ```
+/// use mini_git::core::ObjectFormat;
 /// use mini_git::core::objects::{hash_object, GitObject, blob};
 /// use GitObject::*;
 ///
 /// let obj = Blob(blob::Blob::default());
-/// let (contents, mut hash) = hash_object(&obj);
+/// let (contents, mut hash) = hash_object(&obj, ObjectFormat::Sha1);
 /// assert_eq!(contents, b"blob 0\0");
 /// let digest = hash.hex_digest();
 /// assert_eq!(digest, "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391");
+///
+/// let (_, mut hash) = hash_object(&obj, ObjectFormat::Sha256);
+/// let digest = hash.hex_digest();
+/// assert_eq!(
+///     digest,
+///     "473a0f4c3be8a93681a267e3b1e9a7dcda1185436fe141f7749120a303721813"
+/// );
 /// ```
 #[allow(clippy::module_name_repetitions)]
 #[must_use]
-pub fn hash_object(obj: &GitObject) -> (Vec<u8>, sha1::SHA1) {
+pub fn hash_object(
+    obj: &GitObject,
+    object_format: ObjectFormat,
+) -> (Vec<u8>, ObjectHasher) {
     let data = obj.serialize();
     let len = data.len().to_string();
     let res = [
@@ -499,17 +585,47 @@ pub fn hash_object(obj: &GitObject) -> (Vec<u8>, sha1::SHA1) {
     ]
     .concat();
 
-    let mut hash = sha1::SHA1::new();
+    let mut hash = object_format.hasher().with_collision_detection();
     let _ = hash.update(&res);
 
     (res, hash)
 }
 
+/// Rejects the object named `digest` if `hash`, the hasher it was hashed
+/// with, found it to be part of a SHA-1 collision attack.
+///
+/// # Errors
+///
+/// If the object is part of a collision attack.
+///
+/// Example
+/// ```
+/// use mini_git::core::ObjectFormat;
+/// use mini_git::core::objects::{hash_object, reject_collision, GitObject, blob};
+///
+/// let blob = GitObject::Blob(blob::Blob::default());
+/// let (_, mut hash) = hash_object(&blob, ObjectFormat::Sha1);
+/// let digest = hash.hex_digest();
+/// assert!(reject_collision(&hash, &digest).is_ok());
+/// ```
+pub fn reject_collision(
+    hash: &ObjectHasher,
+    digest: &str,
+) -> Result<(), Error> {
+    if hash.found_collision() {
+        return Err(format!(
+            "Object {digest} appears to be part of a SHA-1 collision attack"
+        )
+        .into());
+    }
+    Ok(())
+}
+
 /// Reads an object from the given repository with the given SHA digest
 ///
 /// # Errors
 /// This function may fail if,
-/// - Request object does not exist
+/// - Request object does not exist, an [`Error::ObjectNotFound`]
 /// - I/O errors occur while reading object files
 /// - Object files are corrupted/malformed
 ///
@@ -520,7 +636,7 @@ pub fn hash_object(obj: &GitObject) -> (Vec<u8>, sha1::SHA1) {
 /// use mini_git::core::objects::read_object;
 ///
 /// // This is an example digest (highly unlikely digest)
-/// let digest = "deadbeefdecadedefacecafec0ffeedadfacade8";
+/// let digest = "deadbeefdecadedefacecafec0ffeedadfacade8".parse()?;
 /// // Get current repository
 /// let repo = GitRepository::new(Path::new("."))?;
 ///
@@ -530,68 +646,47 @@ pub fn hash_object(obj: &GitObject) -> (Vec<u8>, sha1::SHA1) {
 /// ```
 pub fn read_object(
     repo: &GitRepository,
-    sha: &str,
-) -> Result<GitObject, String> {
-    if sha.len() > 40 {
-        return Err(format!("Invalid SHA digest: {sha}"));
-    }
-
-    // Try reading from loose objects first
-    let loose_result = read_loose_object(repo, sha);
-    if loose_result.is_ok() {
-        return loose_result;
-    }
-
-    // Convert hex sha to bytes
-    let hash = {
-        let decoded = hex::decode(sha)
-            .map_err(|_| format!("Invalid SHA digest: {sha}"))?;
-        let mut buf = [0u8; 20];
-        buf[..decoded.len()].copy_from_slice(&decoded);
-        buf
-    };
-
-    // Try reading from packfiles
-    let Ok(packfiles) = packfiles::find_packfiles(repo) else {
-        return Err(format!("Object {sha} not found in repository"));
-    };
+    oid: &Oid,
+) -> Result<GitObject, Error> {
+    check_format(repo, oid)?;
+    ObjectDatabase::open(repo).read(&oid.to_string())
+}
 
-    for mut packfile in packfiles {
-        let object = packfile.read_object(&hash);
-        if object.is_ok() {
-            return object;
-        }
+/// Checks that `oid` names objects of the format of `repo`.
+fn check_format(repo: &GitRepository, oid: &Oid) -> Result<(), Error> {
+    if oid.format() == repo.object_format() {
+        Ok(())
+    } else {
+        Err(format!("Invalid SHA digest: {oid}").into())
     }
-
-    Err(format!("Object {sha} not found in repository"))
 }
 
-#[allow(clippy::module_name_repetitions)]
-fn read_loose_object(
-    repo: &GitRepository,
-    sha: &str,
-) -> Result<GitObject, String> {
-    // Calculate the path to the object
-    let path = path::repo_file(
-        repo.gitdir(),
-        &[OBJECTS_DIR, &sha[..2], &sha[2..]],
-        false,
-    )?;
-
-    // Ensure the path is a valid file
-    let path = match path {
-        Some(path) if path.is_file() => path,
-        _ => return Err(format!("failed to find object with digest {sha}")),
-    };
-
-    // Read and decompress the file
-    let Ok(raw) = fs::read(path) else {
-        return Err(format!("failed to read object with digest {sha}"));
-    };
-    let raw = zlib::decompress(&raw)?;
-    let res = GitObject::from_raw_data(&raw)
-        .map_err(|msg| format!("malformed object with digest {sha}, {msg}"))?;
-    Ok(res)
+/// Returns the size in bytes of the object with the given SHA digest.
+///
+/// For loose objects only the object header is parsed, the object itself is
+/// not deserialized.
+///
+/// # Errors
+/// This function may fail if,
+/// - Requested object does not exist
+/// - I/O errors occur while reading object files
+/// - Object files are corrupted/malformed
+///
+/// Example
+/// ```no_run
+/// use std::path::Path;
+/// use mini_git::core::GitRepository;
+/// use mini_git::core::objects::object_size;
+///
+/// let repo = GitRepository::new(Path::new("."))?;
+/// let digest = "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391".parse()?;
+/// let size = object_size(&repo, &digest)?;
+/// assert_eq!(size, 0);
+/// # Ok::<(), String>(())
+/// ```
+pub fn object_size(repo: &GitRepository, oid: &Oid) -> Result<usize, Error> {
+    check_format(repo, oid)?;
+    ObjectDatabase::open(repo).size(&oid.to_string())
 }
 
 /// Resolves a Git reference to an object ID.
@@ -636,8 +731,9 @@ fn read_loose_object(
 pub fn resolve_ref(
     repo: &GitRepository,
     r#ref: &str,
-) -> Result<Option<String>, String> {
-    let Some(path) = path::repo_file(repo.gitdir(), &[r#ref], false)? else {
+) -> Result<Option<Oid>, Error> {
+    let Some(path) = path::repo_file(repo.ref_dir(r#ref), &[r#ref], false)?
+    else {
         unreachable!();
     };
 
@@ -646,24 +742,90 @@ pub fn resolve_ref(
         return resolve_ref_packed(repo, r#ref);
     }
 
-    let Ok(contents) = std::fs::read_to_string(&path) else {
-        return Err(format!("Failed to read file at {:?}", path.as_os_str()));
-    };
+    let contents = std::fs::read_to_string(&path).map_err(|e| {
+        Error::io(format!("Failed to read file at {:?}", path.as_os_str()), e)
+    })?;
 
     let contents = contents.trim();
     if let Some(stripped) = contents.strip_prefix("ref: ") {
         resolve_ref(repo, stripped)
     } else {
-        Ok(Some(contents.to_owned()))
+        Oid::parse(contents, repo.object_format())
+            .map(Some)
+            .map_err(|e| Error::Parse(format!("{e} in {ref}")))
     }
 }
 
 fn resolve_ref_packed(
     repo: &GitRepository,
     r#ref: &str,
-) -> Result<Option<String>, String> {
+) -> Result<Option<Oid>, Error> {
     let packed_refs = parse_packed_refs(repo)?;
-    Ok(packed_refs.get(&r#ref.to_owned()).cloned())
+    Ok(packed_refs.get(&r#ref.to_owned()).copied())
+}
+
+/// Points the ref `ref` at the object `oid`, like `git update-ref`.
+///
+/// A symbolic ref, such as `HEAD` on a branch, updates the ref it points to,
+/// which need not exist yet. The ref is written as a loose ref, which takes
+/// precedence over the `packed-refs` file.
+///
+/// # Errors
+///
+/// If `ref` is neither `HEAD` nor a valid name under `refs/`, if `oid` is
+/// not named in the object format of the repository, or if the ref cannot
+/// be written. An [`Error::Locked`] if another process is writing the ref.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use std::path::Path;
+/// use mini_git::core::objects::{find_object, update_ref};
+/// use mini_git::core::GitRepository;
+///
+/// let repo = GitRepository::new(Path::new("."))?;
+/// let head = find_object(&repo, "HEAD", None, false)?;
+/// update_ref(&repo, "refs/heads/backup", &head)?;
+/// # Ok::<(), String>(())
+/// ```
+pub fn update_ref(
+    repo: &GitRepository,
+    r#ref: &str,
+    oid: &Oid,
+) -> Result<(), Error> {
+    check_format(repo, oid)?;
+
+    let mut name = r#ref.to_owned();
+    let mut depth = 0;
+    while let Some(target) = symbolic_ref_target(repo, &name) {
+        depth += 1;
+        if depth > MAX_SYMREF_DEPTH {
+            return Err(
+                format!("Too many levels of symbolic refs: {ref}").into()
+            );
+        }
+        name = target;
+    }
+    let valid = name == "HEAD"
+        || name.strip_prefix("refs/").is_some_and(is_valid_branch_name);
+    if !valid {
+        return Err(format!("Invalid ref name: {name}").into());
+    }
+
+    let path = repo.ref_dir(&name).join(&name);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| {
+            Error::io(format!("Failed to create {}", dir.display()), e)
+        })?;
+    }
+    trace::log("ref", format_args!("update {name} to {oid}"));
+    write_locked(&path, format!("{oid}\n").as_bytes())
+}
+
+/// The ref the loose ref `name` points to, if it is a symbolic ref.
+fn symbolic_ref_target(repo: &GitRepository, name: &str) -> Option<String> {
+    let contents = fs::read_to_string(repo.ref_dir(name).join(name)).ok()?;
+    contents.trim().strip_prefix("ref: ").map(String::from)
 }
 
 /// Parses the `packed-refs` file in the specified `GitRepository`.
@@ -678,14 +840,15 @@ fn resolve_ref_packed(
 ///
 /// * Reading the reference file fails.
 /// * An I/O error occurs while accessing the filesystem.
+/// * A reference names a malformed object.
 ///
 pub(super) fn parse_packed_refs(
     repo: &GitRepository,
-) -> Result<OrderedMap<String, String>, String> {
+) -> Result<OrderedMap<String, Oid>, Error> {
     const COMMENT_CHAR: char = '#';
     const PEELED_TAG_CHAR: char = '^';
 
-    let packed_refs_path = repo.gitdir().join("packed-refs");
+    let packed_refs_path = repo.common_dir().join("packed-refs");
     if !packed_refs_path.exists() {
         return Ok(OrderedMap::new());
     }
@@ -735,15 +898,121 @@ pub(super) fn parse_packed_refs(
             sha.to_owned()
         };
 
-        res.insert(refname.to_owned(), final_sha);
+        let oid = Oid::parse(&final_sha, repo.object_format())
+            .map_err(|e| format!("{e} in {refname}"))?;
+        res.insert(refname.to_owned(), oid);
     }
     Ok(res)
 }
 
+/// Opens the object with the given SHA digest to stream its contents,
+/// without reading it into memory first if it is a loose object.
+///
+/// # Errors
+/// This function may fail if,
+/// - Requested object does not exist
+/// - I/O errors occur while opening object files
+/// - The object header is malformed
+///
+/// Example
+/// ```no_run
+/// use std::io::Read;
+/// use std::path::Path;
+/// use mini_git::core::GitRepository;
+/// use mini_git::core::objects::read_object_stream;
+///
+/// let repo = GitRepository::new(Path::new("."))?;
+/// let digest = "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391".parse()?;
+/// let mut reader = read_object_stream(&repo, &digest)?;
+/// std::io::copy(&mut reader, &mut std::io::stdout())
+///     .map_err(|e| e.to_string())?;
+/// # Ok::<(), String>(())
+/// ```
+pub fn read_object_stream(
+    repo: &GitRepository,
+    oid: &Oid,
+) -> Result<ObjectReader, Error> {
+    check_format(repo, oid)?;
+    ObjectDatabase::open(repo).read_stream(&oid.to_string())
+}
+
+/// Computes the digest of the object of type `format` with `size` bytes of
+/// contents read from `data`, without holding it in memory.
+///
+/// # Errors
+/// This function may fail if `data` cannot be read, or does not have `size`
+/// bytes.
+///
+/// Example
+/// ```
+/// use mini_git::core::ObjectFormat;
+/// use mini_git::core::objects::hash_object_stream;
+///
+/// let digest =
+///     hash_object_stream("blob", 0, &mut std::io::empty(), ObjectFormat::Sha1)?;
+/// assert_eq!(digest, "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391");
+/// # Ok::<(), String>(())
+/// ```
+pub fn hash_object_stream(
+    format: &str,
+    size: u64,
+    data: &mut dyn Read,
+    object_format: ObjectFormat,
+) -> Result<String, Error> {
+    store::stream_object(format, size, data, object_format, |_| Ok(()))
+}
+
+/// Writes the object of type `format` with `size` bytes of contents read
+/// from `data` to the repository files, without holding it in memory.
+///
+/// # Returns
+/// The name of the object written.
+///
+/// # Errors
+/// This function may fail if,
+/// - `data` cannot be read, or does not have `size` bytes
+/// - I/O errors occur while writing object files
+///
+/// Example
+/// ```no_run
+/// use std::fs::File;
+/// use std::path::Path;
+/// use mini_git::core::GitRepository;
+/// use mini_git::core::objects::write_object_stream;
+///
+/// let repo = GitRepository::new(Path::new("."))?;
+/// let mut file = File::open("large.bin").map_err(|e| e.to_string())?;
+/// let size = file.metadata().map_err(|e| e.to_string())?.len();
+/// let digest = write_object_stream(&repo, "blob", size, &mut file)?;
+/// # Ok::<(), String>(())
+/// ```
+pub fn write_object_stream(
+    repo: &GitRepository,
+    format: &str,
+    size: u64,
+    data: &mut dyn Read,
+) -> Result<Oid, Error> {
+    ObjectDatabase::open(repo)
+        .write_stream(format, size, data)?
+        .parse()
+}
+
+/// Returns the size in bytes from which files are streamed into blobs,
+/// rather than read into memory, set by `core.bigFileThreshold`.
+///
+/// Defaults to 512 MiB, like git.
+#[must_use]
+pub fn big_file_threshold(repo: &GitRepository) -> u64 {
+    repo.config()
+        .get("core")
+        .and_then(|core| core.get_size("bigFileThreshold"))
+        .unwrap_or(DEFAULT_BIG_FILE_THRESHOLD)
+}
+
 /// Writes an object to the repository files
 ///
 /// # Returns
-/// The sha1 hex-digest of the object written.
+/// The name of the object written.
 ///
 /// ## Note
 /// This function will **never** overwrite the contents of the
@@ -763,56 +1032,60 @@ pub(super) fn parse_packed_refs(
 ///
 /// let obj = Blob(blob::Blob::default());
 /// let repo = GitRepository::new(Path::new("."))?;
-/// let digest = write_object(&obj, &repo)?;
-/// assert_eq!(digest, "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391");
+/// let oid = write_object(&obj, &repo)?;
+/// assert_eq!(oid, "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391");
 /// Ok::<(), String>(())
 /// ```
 #[allow(clippy::module_name_repetitions)]
 pub fn write_object(
     obj: &GitObject,
     repo: &GitRepository,
-) -> Result<String, String> {
-    let (res, mut hash) = hash_object(obj);
-
-    let digest = hash.hex_digest();
-
-    let path = path::repo_file(
-        repo.gitdir(),
-        &[OBJECTS_DIR, &digest[..2], &digest[2..]],
-        true,
-    )?;
-    let Some(path) = path else {
-        return Err(format!(
-            "Failed to create object file for digest {digest}"
-        ));
-    };
-
-    if !path.exists() {
-        let compressed = zlib::compress(&res, &zlib::Strategy::Auto);
-        fs::write(&path, compressed).map_err(|_| {
-            format!("Failed to write to file {:?}", path.as_os_str())
-        })?;
-    }
-
-    Ok(digest)
+) -> Result<Oid, Error> {
+    ObjectDatabase::open(repo).write(obj)?.parse()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::utils::path::repo_dir;
+    use crate::utils::sha1;
     use crate::utils::test::TempDir;
+    use crate::utils::zlib;
     use GitObject::{Blob, Commit, Tag, Tree};
 
+    #[test]
+    fn test_abbreviate_object() {
+        let tmp_dir = TempDir::<()>::create("test_abbreviate_object");
+        let repo = GitRepository::create(tmp_dir.tmp_dir())
+            .expect("Should create repo");
+
+        let sha = "deadbeefdecadedefacecafec0ffeedadfacade8";
+        assert_eq!(abbreviate_object(&repo, sha).unwrap(), "deadbee");
+
+        // Another object sharing the first 9 characters forces a longer prefix
+        let path = repo_dir(&repo.objects_dir(), &["de"], true)
+            .expect("Should create dir!")
+            .expect("Should contain path!");
+        fs::write(path.join(&sha[2..]), b"").expect("Should write");
+        fs::write(path.join("adbeefd0000000000000000000000000000000"), b"")
+            .expect("Should write");
+        // Objects written behind the back of the database are not indexed yet
+        repo.object_ids().clear();
+
+        assert_eq!(abbreviate_object(&repo, sha).unwrap(), "deadbeefde");
+    }
+
     #[test]
     fn test_read_object_bad_path() {
         let tmp_dir = TempDir::<()>::create("test_read_object_bad_path");
-        let sha = "abcdef09123456789abc";
+        let sha = "abcdef09123456789abcabcdef09123456789abc";
 
         let repo = GitRepository::create(tmp_dir.tmp_dir())
             .expect("Should create repo");
 
-        assert!(read_object(&repo, sha).is_err_and(|msg| msg.contains(sha)));
+        let err = read_object(&repo, &sha.parse().unwrap()).unwrap_err();
+        assert!(matches!(err, Error::ObjectNotFound(_)), "{err}");
+        assert!(err.to_string().contains(sha));
     }
 
     #[test]
@@ -823,7 +1096,7 @@ mod tests {
         let repo = GitRepository::create(tmp_dir.tmp_dir())
             .expect("Should create repo");
 
-        let path = repo_dir(repo.gitdir(), &[OBJECTS_DIR, &sha[..2]], true)
+        let path = repo_dir(&repo.objects_dir(), &[&sha[..2]], true)
             .expect("Should create dir!")
             .expect("Should contain path!");
 
@@ -833,9 +1106,8 @@ mod tests {
         fs::write(path.join(&sha[2..]), compressed)
             .expect("Should write contents");
 
-        assert!(
-            read_object(&repo, sha).is_ok_and(|obj| matches!(obj, Blob(..)))
-        );
+        assert!(read_object(&repo, &sha.parse().unwrap())
+            .is_ok_and(|obj| matches!(obj, Blob(..))));
     }
 
     #[test]
@@ -858,7 +1130,7 @@ mod tests {
                 .update(&b"0".repeat(20))
                 .hex_digest();
 
-            let (_, mut actual_hash) = hash_object(&obj);
+            let (_, mut actual_hash) = hash_object(&obj, ObjectFormat::Sha1);
             let actual_hash = actual_hash.hex_digest();
 
             assert_eq!(expected_hash, actual_hash);
@@ -877,11 +1149,13 @@ mod tests {
             data: blob_data.to_vec(),
         });
 
-        let digest = write_object(&blob, &repo).expect("Should write object");
+        let digest = write_object(&blob, &repo)
+            .expect("Should write object")
+            .to_string();
 
         let file = path::repo_file(
-            repo.gitdir(),
-            &[OBJECTS_DIR, &digest[..2], &digest[2..]],
+            &repo.objects_dir(),
+            &[&digest[..2], &digest[2..]],
             false,
         )
         .expect("Should have been created")
@@ -0,0 +1,304 @@
+//! Object names
+//!
+//! An [`Oid`] is the hash that names an object, as many bytes as the hash
+//! algorithm of the [`ObjectFormat`] of its repository. Object names are
+//! parsed into an [`Oid`] once, so that a malformed name is caught where it
+//! is read rather than where the object is looked up.
+//!
+//! An [`OidPrefix`] is an abbreviated object name, the leading hex digits of
+//! one or more object names.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::core::{Error, ObjectFormat};
+use crate::utils::hex;
+
+/// The size in bytes of the longest object names, those of SHA-256.
+const MAX_HASH_LEN: usize = 32;
+
+/// The name of an object, the hash of its header and contents.
+///
+/// Both SHA-1 and SHA-256 names fit in an `Oid`, which is copied like the
+/// bytes it holds. It is parsed from, and shown as, its hex digits.
+///
+/// # Examples
+///
+/// ```
+/// use mini_git::core::objects::oid::Oid;
+/// use mini_git::core::ObjectFormat;
+///
+/// let oid: Oid = "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391".parse()?;
+/// assert_eq!(oid.format(), ObjectFormat::Sha1);
+/// assert_eq!(oid.as_bytes().len(), 20);
+/// assert_eq!(format!("{oid:.7}"), "e69de29");
+///
+/// assert!("e69de29".parse::<Oid>().is_err());
+/// # Ok::<(), String>(())
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Oid {
+    bytes: [u8; MAX_HASH_LEN],
+    len: u8,
+}
+
+impl Oid {
+    /// Makes the name of an object from its hash, whose length gives the
+    /// format of the name.
+    ///
+    /// # Errors
+    ///
+    /// If `bytes` is not as long as the hashes of any format.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let format = match bytes.len() {
+            20 => ObjectFormat::Sha1,
+            32 => ObjectFormat::Sha256,
+            len => {
+                return Err(Error::Parse(format!(
+                    "Invalid object name of {len} bytes"
+                )))
+            }
+        };
+        let mut oid = Self::null(format);
+        oid.bytes[..bytes.len()].copy_from_slice(bytes);
+        Ok(oid)
+    }
+
+    /// Parses the hex digits of a full object name of `format`.
+    ///
+    /// # Errors
+    ///
+    /// If `hex` is not as long as the names of `format`, or is not hex.
+    pub fn parse(hex: &str, format: ObjectFormat) -> Result<Self, Error> {
+        if hex.len() != format.hex_len() {
+            return Err(Error::Parse(format!("Invalid object name: {hex}")));
+        }
+        hex.parse()
+    }
+
+    /// The name of all zeros, which names no object.
+    #[must_use]
+    pub fn null(format: ObjectFormat) -> Self {
+        Self {
+            bytes: [0; MAX_HASH_LEN],
+            #[allow(clippy::cast_possible_truncation)]
+            len: format.hash_len() as u8,
+        }
+    }
+
+    /// Returns whether this is the name of all zeros.
+    #[must_use]
+    pub fn is_null(&self) -> bool {
+        self.as_bytes().iter().all(|&b| b == 0)
+    }
+
+    /// The format of the name, given by its length.
+    #[must_use]
+    pub fn format(&self) -> ObjectFormat {
+        if usize::from(self.len) == ObjectFormat::Sha1.hash_len() {
+            ObjectFormat::Sha1
+        } else {
+            ObjectFormat::Sha256
+        }
+    }
+
+    /// The bytes of the hash.
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..usize::from(self.len)]
+    }
+
+    /// Returns whether the hex digits of the name start with `prefix`.
+    #[must_use]
+    pub fn starts_with(&self, prefix: &OidPrefix) -> bool {
+        self.to_string().starts_with(prefix.as_str())
+    }
+}
+
+impl FromStr for Oid {
+    type Err = Error;
+
+    /// Parses the hex digits of a full object name, of any format.
+    fn from_str(hex: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::Parse(format!("Invalid object name: {hex}"));
+        let bytes = hex::decode(hex).map_err(|_| invalid())?;
+        Self::from_bytes(&bytes).map_err(|_| invalid())
+    }
+}
+
+impl fmt::Display for Oid {
+    /// Shows the hex digits of the name, only the first ones with a
+    /// precision, like `{oid:.7}`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(&hex::encode(self.as_bytes()))
+    }
+}
+
+impl fmt::Debug for Oid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Oid({self})")
+    }
+}
+
+impl PartialEq<str> for Oid {
+    fn eq(&self, other: &str) -> bool {
+        self.to_string().eq_ignore_ascii_case(other)
+    }
+}
+
+impl PartialEq<&str> for Oid {
+    fn eq(&self, other: &&str) -> bool {
+        self == *other
+    }
+}
+
+impl PartialEq<String> for Oid {
+    fn eq(&self, other: &String) -> bool {
+        self == other.as_str()
+    }
+}
+
+impl PartialEq<Oid> for str {
+    fn eq(&self, other: &Oid) -> bool {
+        other == self
+    }
+}
+
+impl PartialEq<Oid> for &str {
+    fn eq(&self, other: &Oid) -> bool {
+        other == *self
+    }
+}
+
+impl PartialEq<Oid> for String {
+    fn eq(&self, other: &Oid) -> bool {
+        other == self
+    }
+}
+
+/// The leading hex digits of an object name, which are enough to name an
+/// object when no other object name starts with them.
+///
+/// # Examples
+///
+/// ```
+/// use mini_git::core::objects::oid::{Oid, OidPrefix};
+///
+/// let oid: Oid = "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391".parse()?;
+/// let prefix: OidPrefix = "E69DE".parse()?;
+/// assert_eq!(prefix.as_str(), "e69de");
+/// assert!(oid.starts_with(&prefix));
+/// assert!("e69dx".parse::<OidPrefix>().is_err());
+/// # Ok::<(), String>(())
+/// ```
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct OidPrefix {
+    hex: String,
+}
+
+impl OidPrefix {
+    /// The lowercase hex digits of the prefix.
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.hex
+    }
+
+    /// The lowest name of `format` starting with the prefix, the prefix
+    /// followed by zeros, or `None` if the prefix is longer than the names.
+    #[must_use]
+    pub fn lowest(&self, format: ObjectFormat) -> Option<Oid> {
+        let hex_len = format.hex_len();
+        if self.hex.len() > hex_len {
+            return None;
+        }
+        Oid::parse(&format!("{:0<hex_len$}", self.hex), format).ok()
+    }
+}
+
+impl FromStr for OidPrefix {
+    type Err = Error;
+
+    /// Parses one to as many hex digits as the longest object names have.
+    fn from_str(hex: &str) -> Result<Self, Self::Err> {
+        if hex.is_empty()
+            || hex.len() > 2 * MAX_HASH_LEN
+            || !hex.bytes().all(|b| b.is_ascii_hexdigit())
+        {
+            return Err(Error::Parse(format!("Invalid object name: {hex}")));
+        }
+        Ok(Self {
+            hex: hex.to_ascii_lowercase(),
+        })
+    }
+}
+
+impl From<Oid> for OidPrefix {
+    fn from(oid: Oid) -> Self {
+        Self {
+            hex: oid.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EMPTY_BLOB: &str = "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391";
+    const EMPTY_BLOB_256: &str =
+        "473a0f4c3be8a93681a267e3b1e9a7dcda1185436fe141f7749120a303721813";
+
+    #[test]
+    fn test_parse() {
+        let oid: Oid = EMPTY_BLOB.parse().unwrap();
+        assert_eq!(oid.format(), ObjectFormat::Sha1);
+        assert_eq!(oid.to_string(), EMPTY_BLOB);
+        assert_eq!(oid, EMPTY_BLOB.to_uppercase());
+        assert_eq!(Oid::from_bytes(oid.as_bytes()).unwrap(), oid);
+
+        let oid = Oid::parse(EMPTY_BLOB_256, ObjectFormat::Sha256).unwrap();
+        assert_eq!(oid.format(), ObjectFormat::Sha256);
+        assert_eq!(oid.as_bytes().len(), 32);
+        assert_eq!(oid, EMPTY_BLOB_256);
+        assert_ne!(oid, EMPTY_BLOB);
+
+        assert!(Oid::parse(EMPTY_BLOB, ObjectFormat::Sha256).is_err());
+        for invalid in ["", "e69de29", &EMPTY_BLOB.replace('e', "g")] {
+            assert!(invalid.parse::<Oid>().is_err(), "{invalid}");
+        }
+        assert!(Oid::from_bytes(&[0; 21]).is_err());
+    }
+
+    #[test]
+    fn test_null_and_order() {
+        let null = Oid::null(ObjectFormat::Sha1);
+        assert!(null.is_null());
+        assert_eq!(null, "0".repeat(40));
+        assert_eq!(Oid::null(ObjectFormat::Sha256).as_bytes(), [0; 32]);
+
+        let oid: Oid = EMPTY_BLOB.parse().unwrap();
+        assert!(!oid.is_null());
+        assert!(null < oid);
+        assert_eq!(format!("{oid:?}"), format!("Oid({EMPTY_BLOB})"));
+        assert_eq!(format!("{oid:.4}"), "e69d");
+    }
+
+    #[test]
+    fn test_prefix() {
+        let oid: Oid = EMPTY_BLOB.parse().unwrap();
+        let prefix: OidPrefix = "E69D".parse().unwrap();
+        assert!(oid.starts_with(&prefix));
+        assert!(!oid.starts_with(&"e69e".parse().unwrap()));
+        assert!(oid.starts_with(&OidPrefix::from(oid)));
+
+        let lowest = prefix.lowest(ObjectFormat::Sha1).unwrap();
+        assert_eq!(lowest, format!("e69d{}", "0".repeat(36)));
+        assert!(lowest <= oid);
+        let full: OidPrefix = EMPTY_BLOB_256.parse().unwrap();
+        assert!(full.lowest(ObjectFormat::Sha1).is_none());
+
+        for invalid in ["", "xyz", &"a".repeat(65)] {
+            assert!(invalid.parse::<OidPrefix>().is_err(), "{invalid}");
+        }
+    }
+}
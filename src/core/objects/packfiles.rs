@@ -2,18 +2,26 @@
 
 use std::collections::HashMap;
 use std::fs;
-use std::io::{Read, Seek, SeekFrom};
-use std::path::Path;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
+use crate::core::objects::oid::{Oid, OidPrefix};
 use crate::core::objects::traits::{Deserialize, KVLM};
 use crate::core::objects::{blob, commit, tag, tree, GitObject};
-use crate::core::GitRepository;
-use crate::utils::hex;
+use crate::core::{Error, GitRepository, ObjectFormat};
+use crate::utils::collections::lru_cache::LruCache;
+use crate::utils::mmap::Mmap;
 use crate::utils::path;
-use crate::utils::zlib;
+use crate::utils::zlib::{self, crc32::crc32};
 
-const HASH_SIZE: usize = 20;
-type Hash = [u8; HASH_SIZE];
+/// The most bytes of objects each packfile keeps cached, like the default
+/// `core.deltaBaseCacheLimit` of git.
+const OBJECT_CACHE_LIMIT: usize = 96 * 1024 * 1024;
+
+/// The longest delta chain that is resolved, like the deepest chain git
+/// writes, so that a cycle of deltas is found to be corrupt.
+const MAX_DELTA_DEPTH: usize = 4095;
 
 /// Represents a Git packfile, which contains multiple Git objects in a compressed format.
 ///
@@ -32,10 +40,25 @@ type Hash = [u8; HASH_SIZE];
 ///     .expect("Failed to load packfile");
 /// ```
 #[derive(Debug)]
+#[allow(clippy::struct_field_names)]
 pub struct PackFile {
-    index: HashMap<Hash, u64>,
-    pack_file: fs::File,
-    object_cache: HashMap<u64, Vec<u8>>,
+    /// The hash algorithm the objects are named with.
+    format: ObjectFormat,
+    /// The hashes of the objects, in sorted order, one after the other.
+    hashes: Vec<u8>,
+    /// The offset of each object, in the order of `hashes`.
+    offsets: Vec<u64>,
+    /// The number of objects whose hash starts with each byte or a lower
+    /// one, which narrows down searches to the objects starting with a byte.
+    fanout: [u32; 256],
+    /// The CRC of the entry at each offset, from version 2 indexes.
+    crcs: HashMap<u64, u32>,
+    /// The offsets of the entries in order, each one ends where the next
+    /// one starts.
+    entry_offsets: Vec<u64>,
+    idx_path: PathBuf,
+    pack: Mmap,
+    object_cache: LruCache<u64, Arc<[u8]>>,
 }
 
 impl PackFile {
@@ -50,12 +73,13 @@ impl PackFile {
     ///
     /// # Errors
     ///
-    /// Returns an `Err(String)` in the following cases:
+    /// Returns an `Err(Error)` in the following cases:
     ///
     /// - The index file cannot be opened or read.
     /// - The index file has an unsupported version.
     /// - The packfile cannot be opened or read.
     /// - The packfile has an invalid signature or unsupported version.
+    /// - The packfile is not the one the index file was made for.
     ///
     /// # Examples
     ///
@@ -69,13 +93,31 @@ impl PackFile {
     /// let packfile = PackFile::from_files(idx_path, pack_path)
     ///     .expect("Failed to load packfile");
     /// ```
-    #[allow(clippy::similar_names, clippy::cast_possible_wrap)]
     pub fn from_files(
         idx_path: &Path,
         pack_path: &Path,
-    ) -> Result<Self, String> {
+    ) -> Result<Self, Error> {
+        Self::from_files_with_format(idx_path, pack_path, ObjectFormat::Sha1)
+    }
+
+    /// Creates a new `PackFile` from the given index and pack file paths, for
+    /// a repository whose objects are named with the hash algorithm of
+    /// `format`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err(Error)` in the same cases as [`PackFile::from_files`].
+    #[allow(clippy::similar_names)]
+    pub fn from_files_with_format(
+        idx_path: &Path,
+        pack_path: &Path,
+        format: ObjectFormat,
+    ) -> Result<Self, Error> {
+        let hash_len = format.hash_len();
+
         // Parse the index file
         let idx_file = fs::File::open(idx_path).map_err(|e| e.to_string())?;
+        let idx_len = idx_file.metadata().map_err(|e| e.to_string())?.len();
         let mut idx_reader = std::io::BufReader::new(&idx_file);
 
         // Read the header
@@ -84,7 +126,7 @@ impl PackFile {
             .read_exact(&mut header)
             .map_err(|e| e.to_string())?;
 
-        if &header[0..4] == b"\xfftOc" {
+        let index = if &header[0..4] == b"\xfftOc" {
             // Version 2
             let version = u32::from_be_bytes([
                 header[4], header[5], header[6], header[7],
@@ -92,123 +134,76 @@ impl PackFile {
             if version != 2 {
                 return Err(format!(
                     "Unsupported pack index version: {version}"
-                ));
-            }
-
-            // Read fan-out table
-            let mut fanout_table = [0u32; 256];
-            for item in &mut fanout_table {
-                let mut buf = [0u8; 4];
-                idx_reader.read_exact(&mut buf).map_err(|e| e.to_string())?;
-                *item = u32::from_be_bytes(buf);
-            }
-
-            let num_objects = fanout_table[255] as usize;
-
-            // Read object hashes
-            let mut hashes = Vec::with_capacity(num_objects);
-            for _ in 0..num_objects {
-                let mut hash = [0u8; 20];
-                idx_reader
-                    .read_exact(&mut hash)
-                    .map_err(|e| e.to_string())?;
-                hashes.push(hash);
-            }
-
-            // Skip CRC32 checksums
-            idx_reader
-                .seek(SeekFrom::Current((num_objects * 4) as i64))
-                .map_err(|e| e.to_string())?;
-
-            // Read 4-byte offsets
-            let mut offsets = Vec::with_capacity(num_objects);
-            let mut large_offsets_indices = Vec::new();
-            for i in 0..num_objects {
-                let mut buf = [0u8; 4];
-                idx_reader.read_exact(&mut buf).map_err(|e| e.to_string())?;
-                let offset = u32::from_be_bytes(buf);
-                if offset & 0x8000_0000 != 0 {
-                    // Large offset
-                    large_offsets_indices.push(i);
-                    offsets.push(0);
-                } else {
-                    offsets.push(u64::from(offset));
-                }
+                )
+                .into());
             }
+            read_index_v2(&mut idx_reader, idx_len, hash_len)?
+        } else {
+            // Version 1 (legacy), the header is the start of the fan-out
+            // table
+            read_index_v1(header, &mut idx_reader, idx_len, hash_len)?
+        };
 
-            // Read large offsets
-            let num_large_offsets = large_offsets_indices.len();
-            let mut large_offsets = Vec::with_capacity(num_large_offsets);
-            for _ in 0..num_large_offsets {
-                let mut buf = [0u8; 8];
-                idx_reader.read_exact(&mut buf).map_err(|e| e.to_string())?;
-                let offset = u64::from_be_bytes(buf);
-                large_offsets.push(offset);
-            }
-
-            // Map large offsets
-            for (i, &index) in large_offsets_indices.iter().enumerate() {
-                offsets[index] = large_offsets[i];
-            }
-
-            // Build the index
-            let mut index = HashMap::new();
-            for i in 0..num_objects {
-                index.insert(hashes[i], offsets[i]);
-            }
-
-            // Open the pack file
-            let pack_file =
-                fs::File::open(pack_path).map_err(|e| e.to_string())?;
-
-            // Read packfile header to get version and object count
-            let mut pack_reader = std::io::BufReader::new(&pack_file);
-            let mut pack_header = [0u8; 12];
-            pack_reader
-                .read_exact(&mut pack_header)
-                .map_err(|e| e.to_string())?;
-
-            if &pack_header[0..4] != b"PACK" {
-                return Err("Invalid packfile signature".to_string());
-            }
-            let pack_version = u32::from_be_bytes([
-                pack_header[4],
-                pack_header[5],
-                pack_header[6],
-                pack_header[7],
-            ]);
-            if pack_version != 2 {
-                return Err(format!(
-                    "Packfile version not supported: {pack_version}."
-                ));
-            }
+        // Map the pack file, and check its header
+        let pack = Mmap::open(pack_path)?;
+        if pack.len() < 12 + hash_len || &pack[0..4] != b"PACK" {
+            return Err("Invalid packfile signature".into());
+        }
+        let pack_version =
+            u32::from_be_bytes([pack[4], pack[5], pack[6], pack[7]]);
+        if pack_version != 2 {
+            return Err(format!(
+                "Packfile version not supported: {pack_version}."
+            )
+            .into());
+        }
+        if pack[pack.len() - hash_len..] != index.pack_checksum {
+            return Err(format!(
+                "Packfile {} does not match its index",
+                pack_path.display()
+            )
+            .into());
+        }
 
-            Ok(PackFile {
-                index,
-                pack_file,
-                object_cache: HashMap::new(),
-            })
-        } else {
-            // Version 1 (legacy) format is not supported
-            Err("Unsupported pack index version".to_string())
+        let hashes = || index.hashes.chunks_exact(hash_len);
+        if hashes().zip(hashes().skip(1)).any(|(a, b)| a >= b) {
+            return Err("Pack index is not sorted".into());
+        }
+        let mut fanout = [0u32; 256];
+        for hash in hashes() {
+            fanout[usize::from(hash[0])] += 1;
         }
+        for i in 1..fanout.len() {
+            fanout[i] += fanout[i - 1];
+        }
+        let mut entry_offsets = index.offsets.clone();
+        entry_offsets.sort_unstable();
+
+        Ok(PackFile {
+            format,
+            hashes: index.hashes,
+            offsets: index.offsets,
+            fanout,
+            crcs: index.crcs,
+            entry_offsets,
+            idx_path: idx_path.to_path_buf(),
+            pack,
+            object_cache: LruCache::new(OBJECT_CACHE_LIMIT),
+        })
     }
 
-    /// Finds an object in the index whose hash matches the given hex-encoded prefix.
-    ///
-    /// ## Note
+    /// Finds an object in the index whose name starts with the given prefix.
     ///
-    /// This function implicitly truncates the prefix to an even number of hex characters.
-    /// This may in rare cases lead to ambiguity in objects.
+    /// If several objects match, the one with the lowest hash is returned,
+    /// see [`PackFile::objects_with_prefix`] to find all of them.
     ///
     /// # Arguments
     ///
-    /// * `prefix` - A hex-encoded string representing the beginning of the hash to search for.
-    ///              If the length of the prefix is odd, it is truncated to make it even.
+    /// * `prefix` - The leading hex digits of the name to search for.
     ///
     /// # Returns
     ///
-    /// Returns an `Option<String>` containing the hex-encoded hash of the first object that matches
+    /// Returns an `Option<Oid>` containing the name of the first object that matches
     /// the prefix, or `None` if no match is found.
     ///
     /// # Examples
@@ -220,43 +215,157 @@ impl PackFile {
     /// let (pack_idx, pack_file) = (Path::new("packfile.idx"), Path::new("packfile.pack"));
     /// let packfile = PackFile::from_files(pack_idx, pack_file)
     ///     .expect("Should load packfile");
-    /// let prefix = "a3f";
-    /// if let Some(object_hash) = packfile.find_object_with_prefix(prefix) {
-    ///     println!("Found object with hash: {}", object_hash);
+    /// let prefix = "a3f".parse()?;
+    /// if let Some(oid) = packfile.find_object_with_prefix(&prefix) {
+    ///     println!("Found object with hash: {oid}");
     /// } else {
     ///     println!("No matching object found.");
     /// }
+    /// # Ok::<(), String>(())
     /// ```
     #[must_use]
-    pub fn find_object_with_prefix(&self, prefix: &str) -> Option<String> {
-        let prefix = if prefix.len() % 2 == 1 {
-            &prefix[..(prefix.len() - 1)]
-        } else {
-            prefix
+    pub fn find_object_with_prefix(&self, prefix: &OidPrefix) -> Option<Oid> {
+        self.objects_with_prefix(prefix).into_iter().next()
+    }
+
+    /// Returns the names of all objects in the index that start with
+    /// `prefix`, in sorted order.
+    ///
+    /// The objects are found with a binary search of the sorted hashes, so
+    /// that more than one match means the prefix is ambiguous.
+    #[must_use]
+    pub fn objects_with_prefix(&self, prefix: &OidPrefix) -> Vec<Oid> {
+        let Some(lowest) = prefix.lowest(self.format) else {
+            return Vec::new();
         };
+        let lowest = lowest.as_bytes();
 
-        let Ok(prefix) = hex::decode(prefix) else {
-            return None;
+        let range = if prefix.as_str().len() >= 2 {
+            self.fanout_range(lowest[0])
+        } else {
+            0..self.len()
         };
-        for hash in self.index.keys() {
-            if prefix.iter().zip(hash.iter()).all(|(&a, &b)| a == b) {
-                return Some(hex::encode(hash));
+        let start = self.lower_bound(range.clone(), lowest);
+        (start..range.end)
+            .map(|i| self.oid(i))
+            .take_while(|oid| oid.starts_with(prefix))
+            .collect()
+    }
+
+    /// Verifies the checksums of the packfile and its index, which cover
+    /// every byte of both, like `git verify-pack` and `git fsck` do.
+    ///
+    /// Loading a packfile only checks that it is the one its index was made
+    /// for, since hashing the whole packfile is slow. This finds corruption
+    /// anywhere in either file.
+    ///
+    /// # Errors
+    ///
+    /// If the index file cannot be read, or either checksum does not match.
+    pub fn verify(&self) -> Result<(), Error> {
+        let hash_len = self.format.hash_len();
+        let (data, checksum) = self.pack.split_at(self.pack.len() - hash_len);
+        if self.format.hash(data) != checksum {
+            return Err("Packfile checksum mismatch".into());
+        }
+
+        let idx = fs::read(&self.idx_path).map_err(|e| e.to_string())?;
+        if idx.len() < 2 * hash_len {
+            return Err("Pack index is truncated".into());
+        }
+        let (data, idx_checksum) = idx.split_at(idx.len() - hash_len);
+        if self.format.hash(data) != idx_checksum {
+            return Err("Pack index checksum mismatch".into());
+        }
+        if data[data.len() - hash_len..] != *checksum {
+            return Err("Packfile does not match its index".into());
+        }
+        Ok(())
+    }
+
+    /// Returns whether the object named `oid` is in this packfile.
+    #[must_use]
+    pub fn contains(&self, oid: &Oid) -> bool {
+        self.find_offset(oid.as_bytes()).is_some()
+    }
+
+    /// Returns the number of objects in this packfile.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// Returns whether this packfile has no objects.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// Returns an iterator over the names of all objects in this packfile,
+    /// in sorted order.
+    pub fn object_ids(&self) -> impl Iterator<Item = Oid> + '_ {
+        (0..self.len()).map(|i| self.oid(i))
+    }
+
+    /// The hash of the object at position `i` of the sorted hashes.
+    fn hash(&self, i: usize) -> &[u8] {
+        let hash_len = self.format.hash_len();
+        &self.hashes[i * hash_len..(i + 1) * hash_len]
+    }
+
+    /// The name of the object at position `i` of the sorted hashes.
+    fn oid(&self, i: usize) -> Oid {
+        Oid::from_bytes(self.hash(i)).expect("hashes have the pack's length")
+    }
+
+    /// The first position in `range` of the sorted hashes whose hash is not
+    /// less than `hash`.
+    fn lower_bound(&self, range: std::ops::Range<usize>, hash: &[u8]) -> usize {
+        let (mut low, mut high) = (range.start, range.end);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if self.hash(mid) < hash {
+                low = mid + 1;
+            } else {
+                high = mid;
             }
         }
-        None
+        low
+    }
+
+    /// Finds the offset of the object with the given hash, with a binary
+    /// search of the hashes starting with the same byte.
+    fn find_offset(&self, hash: &[u8]) -> Option<u64> {
+        if hash.len() != self.format.hash_len() {
+            return None;
+        }
+        let range = self.fanout_range(hash[0]);
+        let i = self.lower_bound(range.clone(), hash);
+        (i < range.end && self.hash(i) == hash).then(|| self.offsets[i])
+    }
+
+    /// The positions of the hashes starting with the byte `first`.
+    fn fanout_range(&self, first: u8) -> std::ops::Range<usize> {
+        let first = usize::from(first);
+        let start = if first == 0 {
+            0
+        } else {
+            self.fanout[first - 1]
+        };
+        start as usize..self.fanout[first] as usize
     }
 
-    /// Reads a Git object from the packfile by its hash.
+    /// Reads a Git object from the packfile by its name.
     ///
     /// This function locates the object in the packfile using the index and returns the corresponding `GitObject`.
     ///
     /// # Arguments
     ///
-    /// * `hash` - The hash of the Git object to retrieve.
+    /// * `oid` - The name of the Git object to retrieve.
     ///
     /// # Errors
     ///
-    /// Returns an `Err(String)` in the following cases:
+    /// Returns an `Err(Error)` in the following cases:
     ///
     /// - The object is not found in the packfile.
     /// - The object type is unknown or unsupported.
@@ -275,19 +384,19 @@ impl PackFile {
     /// let mut packfile = PackFile::from_files(idx_path, pack_path)
     ///     .expect("Failed to load packfile");
     ///
-    /// let hash: [u8; 20] = [/* object hash */];
+    /// let oid = "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391".parse()?;
     ///
-    /// match packfile.read_object(&hash) {
+    /// match packfile.read_object(&oid) {
     ///     Ok(git_object) => {
     ///         // Process the GitObject
     ///     },
     ///     Err(e) => eprintln!("Error reading object: {}", e),
     /// }
+    /// # Ok::<(), String>(())
     /// ```
-    pub fn read_object(&mut self, hash: &Hash) -> Result<GitObject, String> {
-        let &offset = self
-            .index
-            .get(hash)
+    pub fn read_object(&mut self, oid: &Oid) -> Result<GitObject, Error> {
+        let offset = self
+            .find_offset(oid.as_bytes())
             .ok_or_else(|| "Object not found in packfile".to_string())?;
 
         let data = self.read_object_at_offset(offset)?;
@@ -305,7 +414,8 @@ impl PackFile {
             }
             2 => {
                 // Tree
-                let tree = tree::Tree::deserialize(&data)?;
+                let tree =
+                    tree::Tree::deserialize_with_format(&data, self.format)?;
                 GitObject::Tree(tree)
             }
             3 => {
@@ -319,172 +429,457 @@ impl PackFile {
                 GitObject::Tag(tag)
             }
             _ => {
-                return Err(format!("Unknown object type: {base_object_type}"));
+                return Err(
+                    format!("Unknown object type: {base_object_type}").into()
+                );
             }
         };
 
         Ok(git_object)
     }
 
+    /// Reads a Git object from the packfile by its name, as its header
+    /// followed by its contents, like a loose object.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err(Error)` in the same cases as [`PackFile::read_object`].
+    pub fn read_raw_object(&mut self, oid: &Oid) -> Result<Vec<u8>, Error> {
+        let offset = self
+            .find_offset(oid.as_bytes())
+            .ok_or_else(|| "Object not found in packfile".to_string())?;
+
+        let data = self.read_object_at_offset(offset)?;
+        let format: &[u8] =
+            match self.find_base_object_type_at_offset(offset)? {
+                1 => b"commit",
+                2 => b"tree",
+                3 => b"blob",
+                4 => b"tag",
+                object_type => {
+                    return Err(
+                        format!("Unknown object type: {object_type}").into()
+                    );
+                }
+            };
+
+        let len = data.len().to_string();
+        Ok([format, b" ", len.as_bytes(), b"\0", &data].concat())
+    }
+
+    /// Reads the object at `offset`, resolving its delta chain without
+    /// recursion, so a corrupt or cyclic chain cannot overflow the stack.
     fn read_object_at_offset(
         &mut self,
         offset: u64,
-    ) -> Result<Vec<u8>, String> {
-        if let Some(data) = self.object_cache.get(&offset) {
-            return Ok(data.clone());
-        }
+    ) -> Result<Arc<[u8]>, Error> {
+        // Walk down the chain to an object that is cached or not a delta,
+        // then apply the deltas on the way back up
+        let mut chain = Vec::new();
+        let mut current = offset;
+        let mut data: Arc<[u8]> = loop {
+            if let Some(data) = self.object_cache.get(&current) {
+                break Arc::clone(data);
+            }
 
-        self.pack_file
-            .seek(SeekFrom::Start(offset))
-            .map_err(|e| e.to_string())?;
-        let mut reader = std::io::BufReader::new(&self.pack_file);
+            self.check_crc(current)?;
+            let header = self.entry_header(current)?;
+            let entry = self.inflate_entry(current, &header)?;
+            let Some(base) = header.base else {
+                let data: Arc<[u8]> = entry.into();
+                self.object_cache.insert(
+                    current,
+                    Arc::clone(&data),
+                    data.len(),
+                );
+                break data;
+            };
 
-        // Read object header
-        let mut first_byte = [0u8; 1];
-        reader
-            .read_exact(&mut first_byte)
-            .map_err(|e| e.to_string())?;
-        let mut c = first_byte[0];
-        let object_type = (c >> 4) & 0x07;
-        while c & 0x80 != 0 {
-            reader
-                .read_exact(&mut first_byte)
-                .map_err(|e| e.to_string())?;
-            c = first_byte[0];
+            if chain.len() == MAX_DELTA_DEPTH {
+                return Err(delta_chain_too_deep(offset));
+            }
+            chain.push((current, entry));
+            current = self.base_offset(base)?;
+        };
+
+        for (offset, delta) in chain.into_iter().rev() {
+            data = delta::apply_delta(&data, &delta)?.into();
+            self.object_cache
+                .insert(offset, Arc::clone(&data), data.len());
         }
 
-        let mut base_offset = 0u64;
-        let mut base_hash = [0u8; 20];
-        match object_type {
-            1..=4 => {
-                // Base object types
-            }
-            6 => {
-                // OFS_DELTA
-                let mut c = [0u8; 1];
-                reader.read_exact(&mut c).map_err(|e| e.to_string())?;
-                base_offset = u64::from(c[0] & 0x7F);
-                while c[0] & 0x80 != 0 {
-                    base_offset += 1;
-                    base_offset <<= 7;
-                    reader.read_exact(&mut c).map_err(|e| e.to_string())?;
-                    base_offset |= u64::from(c[0] & 0x7F);
-                }
-                base_offset = offset - base_offset;
-            }
-            7 => {
-                // REF_DELTA
-                reader
-                    .read_exact(&mut base_hash)
-                    .map_err(|e| e.to_string())?;
-            }
-            _ => {
-                return Err(format!("Unknown object type: {object_type}"));
+        Ok(data)
+    }
+
+    fn find_base_object_type_at_offset(
+        &self,
+        offset: u64,
+    ) -> Result<u8, Error> {
+        let mut current = offset;
+        for _ in 0..=MAX_DELTA_DEPTH {
+            let header = self.entry_header(current)?;
+            match header.base {
+                None => return Ok(header.object_type),
+                Some(base) => current = self.base_offset(base)?,
             }
         }
+        Err(delta_chain_too_deep(offset))
+    }
 
-        // Read compressed data
-        let compressed_data = {
-            let mut buf = vec![];
-            reader.read_to_end(&mut buf).map_err(|e| e.to_string())?;
-            zlib::decompress(&buf)?
+    /// Checks the entry at `offset` against its CRC from the index, if the
+    /// index has one, which finds a corrupt entry before it is inflated.
+    fn check_crc(&self, offset: u64) -> Result<(), Error> {
+        let Some(&crc) = self.crcs.get(&offset) else {
+            return Ok(());
         };
 
-        let data = if object_type == 6 || object_type == 7 {
-            let base_data = if object_type == 6 {
-                self.read_object_at_offset(base_offset)?
-            } else {
-                let &base_offset =
-                    self.index.get(&base_hash).ok_or_else(|| {
-                        "Base object not found in packfile".to_string()
-                    })?;
-                self.read_object_at_offset(base_offset)?
-            };
-            delta::apply_delta(&base_data, &compressed_data)?
-        } else {
-            compressed_data
-        };
+        let entry = usize::try_from(offset)
+            .ok()
+            .and_then(|start| self.pack.get(start..self.entry_end(offset)))
+            .ok_or_else(|| format!("Truncated packfile entry at {offset}"))?;
 
-        self.object_cache.insert(offset, data.clone());
+        if crc32(entry) == crc {
+            Ok(())
+        } else {
+            Err(
+                format!("Packfile entry at {offset} is corrupt, CRC mismatch")
+                    .into(),
+            )
+        }
+    }
 
-        Ok(data)
+    /// The position just after the entry at `offset`, where the next entry
+    /// or the trailer of the packfile starts.
+    fn entry_end(&self, offset: u64) -> usize {
+        let trailer = self.pack.len().saturating_sub(self.format.hash_len());
+        let next = self.entry_offsets.partition_point(|&start| start <= offset);
+        self.entry_offsets
+            .get(next)
+            .and_then(|&end| usize::try_from(end).ok())
+            .map_or(trailer, |end| end.min(trailer))
     }
 
-    fn find_base_object_type_at_offset(
-        &mut self,
+    /// Inflates the compressed data of the entry at `offset`, which is
+    /// exactly one zlib stream of the size in its header.
+    fn inflate_entry(
+        &self,
         offset: u64,
-    ) -> Result<u8, String> {
-        // Seek to the object's offset in the packfile
-        self.pack_file
-            .seek(SeekFrom::Start(offset))
-            .map_err(|e| e.to_string())?;
+        header: &EntryHeader,
+    ) -> Result<Vec<u8>, Error> {
+        let end = self.entry_end(offset);
+        let compressed = self
+            .pack
+            .get(header.data_start..end)
+            .ok_or_else(|| format!("Truncated packfile entry at {offset}"))?;
+
+        // The size in the header is not trusted to allocate up front, as
+        // DEFLATE expands data at most 1032 times
+        let capacity = header.size.min(compressed.len().saturating_mul(1032));
+        let mut inflater =
+            zlib::Inflater::new(compressed).with_limit(header.size as u64);
+        let mut data = Vec::with_capacity(capacity);
+        inflater
+            .read_to_end(&mut data)
+            .map_err(|e| format!("Invalid packfile entry at {offset}: {e}"))?;
+        if data.len() != header.size {
+            return Err(format!(
+                "Packfile entry at {offset} has the wrong size"
+            )
+            .into());
+        }
+        Ok(data)
+    }
 
-        // Read the first byte to get the object type and size
-        let mut first_byte = [0u8; 1];
-        self.pack_file
-            .read_exact(&mut first_byte)
-            .map_err(|e| e.to_string())?;
-        let mut c = first_byte[0];
+    /// Parses the header of the entry at `offset` in the packfile.
+    fn entry_header(&self, offset: u64) -> Result<EntryHeader, Error> {
+        let truncated = || format!("Truncated packfile entry at {offset}");
+        let byte =
+            |pos: usize| self.pack.get(pos).copied().ok_or_else(truncated);
+        let mut pos = usize::try_from(offset).map_err(|_| truncated())?;
 
+        // Read the object type, and the size (variable-length encoding)
+        let mut c = byte(pos)?;
+        pos += 1;
         let object_type = (c >> 4) & 0x07;
-
-        // Read the size (variable-length encoding)
-        while (c & 0x80) != 0 {
-            self.pack_file
-                .read_exact(&mut first_byte)
-                .map_err(|e| e.to_string())?;
-            c = first_byte[0];
+        let mut size = usize::from(c & 0x0F);
+        let mut shift = 4;
+        while c & 0x80 != 0 {
+            c = byte(pos)?;
+            pos += 1;
+            size |= usize::from(c & 0x7F)
+                .checked_shl(shift)
+                .filter(|_| shift < usize::BITS)
+                .ok_or_else(|| format!("Invalid object size at {offset}"))?;
+            shift += 7;
         }
 
-        match object_type {
-            1..=4 => Ok(object_type), // Base object types
+        let base = match object_type {
+            1..=4 => None, // Base object types
             6 => {
-                // OFS_DELTA: Read base object offset
-                let base_offset = self
-                    .read_ofs_delta_base_offset(offset)
-                    .map_err(|e| e.to_string())?;
-                self.find_base_object_type_at_offset(base_offset)
+                // OFS_DELTA: the base is at a negative offset
+                let mut c = byte(pos)?;
+                pos += 1;
+                let invalid =
+                    || format!("Invalid delta base offset at {offset}");
+                let mut value = u64::from(c & 0x7F);
+                while c & 0x80 != 0 {
+                    c = byte(pos)?;
+                    pos += 1;
+                    value = value
+                        .checked_add(1)
+                        .and_then(|value| value.checked_mul(0x80))
+                        .ok_or_else(invalid)?
+                        | u64::from(c & 0x7F);
+                }
+                // The base must come before the delta, anything else is
+                // corrupt and would make the delta its own base
+                let base_offset = offset
+                    .checked_sub(value)
+                    .filter(|_| value > 0)
+                    .ok_or_else(invalid)?;
+                Some(DeltaBase::Offset(base_offset))
             }
             7 => {
-                // REF_DELTA: Read 20-byte base object hash
-                let mut base_hash = [0u8; 20];
-                self.pack_file
-                    .read_exact(&mut base_hash)
-                    .map_err(|e| e.to_string())?;
-
-                // Find the base object's offset using the index
-                if let Some(&base_offset) = self.index.get(&base_hash) {
-                    self.find_base_object_type_at_offset(base_offset)
-                } else {
-                    Err("Base object not found in packfile".to_string())
+                // REF_DELTA: the base is named by its hash
+                let hash_len = self.format.hash_len();
+                if self.pack.len() < pos + hash_len {
+                    return Err(truncated().into());
                 }
+                pos += hash_len;
+                Some(DeltaBase::Hash(pos - hash_len))
             }
-            _ => Err(format!("Unknown object type: {object_type}")),
+            _ => {
+                return Err(format!("Unknown object type: {object_type}").into())
+            }
+        };
+
+        Ok(EntryHeader {
+            object_type,
+            size,
+            base,
+            data_start: pos,
+        })
+    }
+
+    /// The offset in the packfile of the base of a delta.
+    fn base_offset(&self, base: DeltaBase) -> Result<u64, Error> {
+        match base {
+            DeltaBase::Offset(offset) => Ok(offset),
+            DeltaBase::Hash(pos) => self
+                .find_offset(&self.pack[pos..pos + self.format.hash_len()])
+                .ok_or_else(|| "Base object not found in packfile".into()),
         }
     }
+}
 
-    fn read_ofs_delta_base_offset(
-        &mut self,
-        current_offset: u64,
-    ) -> Result<u64, std::io::Error> {
-        let mut buf = [0u8; 1];
-        let mut c;
+/// The error for a delta chain from `offset` that is longer than
+/// [`MAX_DELTA_DEPTH`], or never ends.
+fn delta_chain_too_deep(offset: u64) -> Error {
+    format!(
+        "Packfile entry at {offset} has a delta chain deeper than \
+         {MAX_DELTA_DEPTH}"
+    )
+    .into()
+}
+
+/// The contents of a pack index.
+struct PackIndex {
+    /// The hashes of the objects, in the order of the index, one after the
+    /// other.
+    hashes: Vec<u8>,
+    /// The offset of each object, in the order of `hashes`.
+    offsets: Vec<u64>,
+    /// The CRC of the entry at each offset, version 1 indexes have none.
+    crcs: HashMap<u64, u32>,
+    /// The checksum of the packfile the index was made for.
+    pack_checksum: Vec<u8>,
+}
 
-        self.pack_file.read_exact(&mut buf)?;
-        c = buf[0];
-        let mut value = u64::from(c & 0x7F);
+/// The number of objects in a pack index of `idx_len` bytes, as given by
+/// its `fanout` table, where each object takes `entry_len` bytes and the
+/// rest of the index `fixed_len` bytes.
+///
+/// The fan-out table is read before anything else, so it is checked to
+/// never decrease, and to not count more objects than the index can hold,
+/// before it is trusted to size anything.
+fn count_objects(
+    fanout: &[u32; 256],
+    idx_len: u64,
+    entry_len: usize,
+    fixed_len: usize,
+) -> Result<usize, Error> {
+    if fanout.windows(2).any(|pair| pair[0] > pair[1]) {
+        return Err("Pack index fan-out table is not sorted".into());
+    }
+    let num_objects = fanout[255] as usize;
+    let needed = num_objects
+        .checked_mul(entry_len)
+        .and_then(|len| len.checked_add(fixed_len));
+    if needed.is_none_or(|needed| needed as u64 > idx_len) {
+        return Err(format!(
+            "Pack index of {idx_len} bytes is too short for {num_objects} \
+            objects"
+        )
+        .into());
+    }
+    Ok(num_objects)
+}
+
+/// Reads the rest of a version 2 pack index of `idx_len` bytes, after its
+/// header, whose hashes are `hash_len` bytes long.
+fn read_index_v2(
+    idx_reader: &mut impl Read,
+    idx_len: u64,
+    hash_len: usize,
+) -> Result<PackIndex, Error> {
+    // Read fan-out table
+    let mut fanout_table = [0u32; 256];
+    for item in &mut fanout_table {
+        let mut buf = [0u8; 4];
+        idx_reader.read_exact(&mut buf).map_err(|e| e.to_string())?;
+        *item = u32::from_be_bytes(buf);
+    }
+
+    // Each object has a hash, a CRC and an offset, and the trailer has two
+    // checksums
+    let num_objects = count_objects(
+        &fanout_table,
+        idx_len,
+        hash_len + 8,
+        8 + 256 * 4 + 2 * hash_len,
+    )?;
+
+    // Read object hashes
+    let mut hashes = vec![0u8; num_objects * hash_len];
+    idx_reader
+        .read_exact(&mut hashes)
+        .map_err(|e| e.to_string())?;
+
+    // Read CRC32 checksums
+    let mut crcs = Vec::with_capacity(num_objects);
+    for _ in 0..num_objects {
+        let mut buf = [0u8; 4];
+        idx_reader.read_exact(&mut buf).map_err(|e| e.to_string())?;
+        crcs.push(u32::from_be_bytes(buf));
+    }
 
-        while (c & 0x80) != 0 {
-            value += 1;
-            value <<= 7;
-            self.pack_file.read_exact(&mut buf)?;
-            c = buf[0];
-            value |= u64::from(c & 0x7F);
+    // Read 4-byte offsets
+    let mut offsets = Vec::with_capacity(num_objects);
+    let mut large_offsets_indices = Vec::new();
+    for i in 0..num_objects {
+        let mut buf = [0u8; 4];
+        idx_reader.read_exact(&mut buf).map_err(|e| e.to_string())?;
+        let offset = u32::from_be_bytes(buf);
+        if offset & 0x8000_0000 != 0 {
+            // Large offset
+            large_offsets_indices.push(i);
+            offsets.push(0);
+        } else {
+            offsets.push(u64::from(offset));
         }
+    }
 
-        let base_offset = current_offset - value;
-        Ok(base_offset)
+    // Read large offsets
+    let num_large_offsets = large_offsets_indices.len();
+    let mut large_offsets = Vec::with_capacity(num_large_offsets);
+    for _ in 0..num_large_offsets {
+        let mut buf = [0u8; 8];
+        idx_reader.read_exact(&mut buf).map_err(|e| e.to_string())?;
+        let offset = u64::from_be_bytes(buf);
+        large_offsets.push(offset);
+    }
+
+    // Map large offsets
+    for (i, &index) in large_offsets_indices.iter().enumerate() {
+        offsets[index] = large_offsets[i];
+    }
+
+    // The trailer starts with the checksum of the packfile
+    let mut pack_checksum = vec![0u8; hash_len];
+    idx_reader
+        .read_exact(&mut pack_checksum)
+        .map_err(|e| e.to_string())?;
+
+    Ok(PackIndex {
+        crcs: offsets.iter().copied().zip(crcs).collect(),
+        hashes,
+        offsets,
+        pack_checksum,
+    })
+}
+
+/// Reads the rest of a version 1 pack index of `idx_len` bytes, which starts
+/// with `header`, whose hashes are `hash_len` bytes long.
+fn read_index_v1(
+    header: [u8; 8],
+    idx_reader: &mut impl Read,
+    idx_len: u64,
+    hash_len: usize,
+) -> Result<PackIndex, Error> {
+    // Read fan-out table, the header holds its first two entries
+    let mut fanout_table = [0u32; 256];
+    fanout_table[0] =
+        u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+    fanout_table[1] =
+        u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+    for item in &mut fanout_table[2..] {
+        let mut buf = [0u8; 4];
+        idx_reader.read_exact(&mut buf).map_err(|e| e.to_string())?;
+        *item = u32::from_be_bytes(buf);
+    }
+    if fanout_table.windows(2).any(|pair| pair[0] > pair[1]) {
+        return Err("Unsupported pack index version".into());
     }
+
+    // Read the 4-byte offset and the hash of each object
+    let num_objects = count_objects(
+        &fanout_table,
+        idx_len,
+        4 + hash_len,
+        256 * 4 + 2 * hash_len,
+    )?;
+    let mut hashes = Vec::with_capacity(num_objects * hash_len);
+    let mut offsets = Vec::with_capacity(num_objects);
+    let mut buf = vec![0u8; 4 + hash_len];
+    for _ in 0..num_objects {
+        idx_reader.read_exact(&mut buf).map_err(|e| e.to_string())?;
+        let offset = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]);
+        offsets.push(u64::from(offset));
+        hashes.extend_from_slice(&buf[4..]);
+    }
+
+    // The trailer starts with the checksum of the packfile
+    let mut pack_checksum = vec![0u8; hash_len];
+    idx_reader
+        .read_exact(&mut pack_checksum)
+        .map_err(|e| e.to_string())?;
+
+    Ok(PackIndex {
+        hashes,
+        offsets,
+        crcs: HashMap::new(),
+        pack_checksum,
+    })
+}
+
+/// The header of an entry in a packfile.
+#[derive(Debug)]
+struct EntryHeader {
+    object_type: u8,
+    /// The size of the object, or of the delta if it is a delta.
+    size: usize,
+    base: Option<DeltaBase>,
+    /// The position of the compressed data.
+    data_start: usize,
+}
+
+/// Where the base of a delta entry is.
+#[derive(Debug, Clone, Copy)]
+enum DeltaBase {
+    /// At an offset in the same packfile.
+    Offset(u64),
+    /// The object with the hash at a position in the packfile.
+    Hash(usize),
 }
 
 /// Finds and loads all packfiles in the repository.
@@ -497,7 +892,7 @@ impl PackFile {
 ///
 /// # Errors
 ///
-/// Returns an `Err(String)` in the following cases:
+/// Returns an `Err(Error)` in the following cases:
 ///
 /// - The pack directory is not found in the repository.
 /// - Any index file or packfile cannot be opened or read.
@@ -524,8 +919,23 @@ impl PackFile {
 ///     Err(e) => eprintln!("Error finding packfiles: {}", e),
 /// }
 /// ```
-pub fn find_packfiles(repo: &GitRepository) -> Result<Vec<PackFile>, String> {
-    let pack_dir = path::repo_dir(repo.gitdir(), &["objects", "pack"], false)?
+pub fn find_packfiles(repo: &GitRepository) -> Result<Vec<PackFile>, Error> {
+    find_packfiles_in(&repo.objects_dir(), repo.object_format())
+}
+
+/// Finds all packfiles in the `pack` directory of the object directory
+/// `objects_dir`, whose objects are named with the hash algorithm of
+/// `format`.
+///
+/// # Errors
+///
+/// Returns an `Err(Error)` if the pack directory does not exist or cannot be
+/// read, or if any packfile fails to load.
+pub fn find_packfiles_in(
+    objects_dir: &Path,
+    format: ObjectFormat,
+) -> Result<Vec<PackFile>, Error> {
+    let pack_dir = path::repo_dir(objects_dir, &["pack"], false)?
         .ok_or_else(|| "Pack directory not found".to_string())?;
 
     let mut packfiles = Vec::new();
@@ -538,7 +948,9 @@ pub fn find_packfiles(repo: &GitRepository) -> Result<Vec<PackFile>, String> {
             if extension == "idx" {
                 let pack_path = path.with_extension("pack");
                 if pack_path.exists() {
-                    let packfile = PackFile::from_files(&path, &pack_path)?;
+                    let packfile = PackFile::from_files_with_format(
+                        &path, &pack_path, format,
+                    )?;
                     packfiles.push(packfile);
                 }
             }
@@ -548,7 +960,115 @@ pub fn find_packfiles(repo: &GitRepository) -> Result<Vec<PackFile>, String> {
     Ok(packfiles)
 }
 
-mod delta {
+pub(super) mod delta {
+    use std::collections::HashMap;
+
+    /// The length of the blocks of the base that deltas copy from.
+    const BLOCK_SIZE: usize = 16;
+    /// The most bytes a single copy instruction copies.
+    const MAX_COPY_SIZE: usize = 0x10000;
+    /// The most bytes a single insert instruction inserts.
+    const MAX_INSERT_SIZE: usize = 0x7F;
+
+    /// Computes a delta, which [`apply_delta`] applies to `base` to get
+    /// `target`.
+    ///
+    /// Blocks of the base are matched in the target, and extended as far as
+    /// they go, the rest of the target is inserted as it is.
+    pub fn create_delta(base: &[u8], target: &[u8]) -> Vec<u8> {
+        let mut delta = Vec::new();
+        write_varint(&mut delta, base.len());
+        write_varint(&mut delta, target.len());
+
+        // Copy offsets are at most 32 bits
+        let base = &base[..base.len().min(u32::MAX as usize)];
+        let mut blocks = HashMap::new();
+        for (i, block) in base.chunks_exact(BLOCK_SIZE).enumerate() {
+            blocks.entry(block).or_insert(i * BLOCK_SIZE);
+        }
+
+        let (mut pos, mut pending) = (0, 0);
+        while pos + BLOCK_SIZE <= target.len() {
+            let Some(&found) = blocks.get(&target[pos..pos + BLOCK_SIZE])
+            else {
+                pos += 1;
+                continue;
+            };
+
+            // Extend the match backwards over pending inserts, and forwards
+            let (mut start, mut target_start) = (found, pos);
+            while target_start > pending
+                && start > 0
+                && base[start - 1] == target[target_start - 1]
+            {
+                start -= 1;
+                target_start -= 1;
+            }
+            let (mut end, mut target_end) =
+                (found + BLOCK_SIZE, pos + BLOCK_SIZE);
+            while end < base.len()
+                && target_end < target.len()
+                && base[end] == target[target_end]
+            {
+                end += 1;
+                target_end += 1;
+            }
+
+            write_insert(&mut delta, &target[pending..target_start]);
+            write_copy(&mut delta, start, end - start);
+            pos = target_end;
+            pending = target_end;
+        }
+        write_insert(&mut delta, &target[pending..]);
+
+        delta
+    }
+
+    fn write_insert(delta: &mut Vec<u8>, data: &[u8]) {
+        for chunk in data.chunks(MAX_INSERT_SIZE) {
+            #[allow(clippy::cast_possible_truncation)]
+            delta.push(chunk.len() as u8);
+            delta.extend_from_slice(chunk);
+        }
+    }
+
+    fn write_copy(delta: &mut Vec<u8>, mut offset: usize, mut size: usize) {
+        while size > 0 {
+            let len = size.min(MAX_COPY_SIZE);
+            let opcode_pos = delta.len();
+            let mut opcode = 0x80;
+            // Only the non-zero bytes of the offset and size are written
+            #[allow(clippy::cast_possible_truncation)]
+            for (i, value) in (0..4)
+                .map(|i| (offset >> (8 * i)) as u8)
+                .chain((0..3).map(|i| (len >> (8 * i)) as u8))
+                .enumerate()
+            {
+                if value != 0 {
+                    opcode |= 1 << i;
+                    delta.push(value);
+                }
+            }
+            delta.insert(opcode_pos, opcode);
+            offset += len;
+            size -= len;
+        }
+    }
+
+    fn write_varint(delta: &mut Vec<u8>, mut value: usize) {
+        while value >= 0x80 {
+            #[allow(clippy::cast_possible_truncation)]
+            delta.push((value as u8 & 0x7F) | 0x80);
+            value >>= 7;
+        }
+        #[allow(clippy::cast_possible_truncation)]
+        delta.push(value as u8);
+    }
+
+    /// Applies `delta` to `base`.
+    ///
+    /// Deltas come from packfiles, so nothing in them is trusted: sizes are
+    /// checked against the data before anything is allocated or copied.
     pub fn apply_delta(base: &[u8], delta: &[u8]) -> Result<Vec<u8>, String> {
         let mut delta = delta;
 
@@ -564,55 +1084,56 @@ mod delta {
         let (result_size, offset) = read_varint(delta)?;
         delta = &delta[offset..];
 
-        let mut result = Vec::with_capacity(result_size);
-
-        while !delta.is_empty() {
-            let opcode = delta[0];
-            delta = &delta[1..];
-            if opcode & 0x80 != 0 {
+        // Most deltas copy most of the base and insert the rest, a larger
+        // result is left to grow as it is built
+        let mut result = Vec::with_capacity(
+            result_size.min(base.len().saturating_add(delta.len())),
+        );
+
+        while let Some((&opcode, rest)) = delta.split_first() {
+            delta = rest;
+            let data = if opcode & 0x80 != 0 {
+                // The bits of the opcode say which bytes of the offset and
+                // size follow, least significant first
                 let mut copy_offset = 0usize;
                 let mut copy_size = 0usize;
-                if opcode & 0x01 != 0 {
-                    copy_offset |= delta[0] as usize;
-                    delta = &delta[1..];
-                }
-                if opcode & 0x02 != 0 {
-                    copy_offset |= (delta[0] as usize) << 8;
-                    delta = &delta[1..];
-                }
-                if opcode & 0x04 != 0 {
-                    copy_offset |= (delta[0] as usize) << 16;
-                    delta = &delta[1..];
-                }
-                if opcode & 0x08 != 0 {
-                    copy_offset |= (delta[0] as usize) << 24;
-                    delta = &delta[1..];
-                }
-                if opcode & 0x10 != 0 {
-                    copy_size |= delta[0] as usize;
-                    delta = &delta[1..];
-                }
-                if opcode & 0x20 != 0 {
-                    copy_size |= (delta[0] as usize) << 8;
-                    delta = &delta[1..];
-                }
-                if opcode & 0x40 != 0 {
-                    copy_size |= (delta[0] as usize) << 16;
-                    delta = &delta[1..];
+                for bit in 0..7 {
+                    if opcode & (1 << bit) == 0 {
+                        continue;
+                    }
+                    let (&byte, rest) = delta
+                        .split_first()
+                        .ok_or("Unexpected end of delta data")?;
+                    delta = rest;
+                    if bit < 4 {
+                        copy_offset |= usize::from(byte) << (8 * bit);
+                    } else {
+                        copy_size |= usize::from(byte) << (8 * (bit - 4));
+                    }
                 }
                 if copy_size == 0 {
                     copy_size = 0x10000;
                 }
-                result.extend_from_slice(
-                    &base[copy_offset..copy_offset + copy_size],
-                );
+                copy_offset
+                    .checked_add(copy_size)
+                    .and_then(|end| base.get(copy_offset..end))
+                    .ok_or("Delta copies past the end of its base")?
             } else if opcode != 0 {
                 let insert_size = opcode as usize;
-                result.extend_from_slice(&delta[..insert_size]);
-                delta = &delta[insert_size..];
+                if insert_size > delta.len() {
+                    return Err("Unexpected end of delta data".to_string());
+                }
+                let (data, rest) = delta.split_at(insert_size);
+                delta = rest;
+                data
             } else {
                 return Err("Invalid delta opcode 0".to_string());
+            };
+
+            if data.len() > result_size - result.len() {
+                return Err("Delta result size mismatch".to_string());
             }
+            result.extend_from_slice(data);
         }
 
         if result.len() != result_size {
@@ -622,35 +1143,39 @@ mod delta {
         Ok(result)
     }
 
+    /// Reads a size at the start of a delta, returning it and the number of
+    /// bytes it took.
     pub(super) fn read_varint(data: &[u8]) -> Result<(usize, usize), String> {
         let mut result = 0usize;
         let mut shift = 0;
-        let mut offset = 0;
-        loop {
-            if offset >= data.len() {
-                return Err("Unexpected end of delta data".to_string());
+        for (offset, &byte) in data.iter().enumerate() {
+            let bits = usize::from(byte & 0x7F);
+            // The bits shifted out would be lost
+            if shift >= usize::BITS || (bits << shift) >> shift != bits {
+                return Err("Delta size is too large".to_string());
             }
-            let byte = data[offset];
-            offset += 1;
-            result |= ((byte & 0x7F) as usize) << shift;
+            result |= bits << shift;
             if byte & 0x80 == 0 {
-                break;
+                return Ok((result, offset + 1));
             }
             shift += 7;
         }
-        Ok((result, offset))
+        Err("Unexpected end of delta data".to_string())
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::utils::sha1;
     use crate::utils::test::TempDir;
 
-    use super::delta::{apply_delta, read_varint};
+    use super::delta::{apply_delta, create_delta, read_varint};
     use super::*;
     use std::fs::File;
     use std::io::Write;
 
+    const HASH_SIZE: usize = 20;
+
     #[test]
     fn test_read_varint() {
         // Test reading single-byte varint
@@ -675,6 +1200,58 @@ mod tests {
         let data: [u8; 0] = [];
         let result = read_varint(&data);
         assert!(result.is_err());
+
+        // The largest value, and those that would not fit
+        let mut data = [0xFF; 10];
+        data[9] = 0x01;
+        assert_eq!(read_varint(&data).unwrap(), (usize::MAX, 10));
+        data[9] = 0x02;
+        assert!(read_varint(&data).is_err());
+        let data = [0x80; 11];
+        assert!(read_varint(&data).is_err());
+        let mut data = [0x80; 12];
+        data[11] = 0;
+        assert!(read_varint(&data).is_err());
+    }
+
+    #[test]
+    fn test_apply_delta_corrupt() {
+        let base = b"Hello, world!";
+        let err = |delta: &[u8]| apply_delta(base, delta).unwrap_err();
+
+        // A result size no allocation can hold
+        let mut huge = vec![0x0D];
+        huge.extend_from_slice(&[0xFF; 9]);
+        huge.extend_from_slice(&[0x01, 0x91, 0x00, 0x0D]);
+        assert_eq!(err(&huge), "Delta result size mismatch");
+
+        // Copies and inserts past the end of their data
+        assert_eq!(
+            err(&[0x0D, 0x0E, 0x91, 0x01, 0x0D]),
+            "Delta copies past the end of its base"
+        );
+        assert_eq!(
+            err(&[0x0D, 0x10, 0x9F, 0xFF, 0xFF, 0xFF, 0xFF, 0x01]),
+            "Delta copies past the end of its base"
+        );
+        assert_eq!(
+            err(&[0x0D, 0x0D, 0x91, 0x00]),
+            "Unexpected end of delta data"
+        );
+        assert_eq!(
+            err(&[0x0D, 0x05, 0x05, b'a']),
+            "Unexpected end of delta data"
+        );
+
+        // More data than the result size says
+        assert_eq!(
+            err(&[0x0D, 0x01, 0x91, 0x00, 0x0D]),
+            "Delta result size mismatch"
+        );
+        assert_eq!(
+            err(&[0x0D, 0x0E, 0x91, 0x00, 0x0D]),
+            "Delta result size mismatch"
+        );
     }
 
     #[test]
@@ -748,6 +1325,378 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_create_delta() {
+        let base: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        let mut target = b"prefix".to_vec();
+        target.extend_from_slice(&base[1000..150_000]);
+        target.extend_from_slice(b"middle");
+        target.extend_from_slice(&base[..20]);
+        target.extend_from_slice(&[0xFF; 300]);
+
+        for (base, target) in [
+            (&base[..], &target[..]),
+            (&base[..], &[][..]),
+            (&[][..], &target[..]),
+            (b"Hello, world!", b"Hello, Rust!"),
+        ] {
+            let delta = create_delta(base, target);
+            assert_eq!(apply_delta(base, &delta).unwrap(), target);
+        }
+
+        // Shared contents are copied rather than inserted
+        assert!(create_delta(&base, &target).len() < 500);
+    }
+
+    #[test]
+    fn test_verify_checksums() {
+        use crate::core::objects::packwriter::{index_pack, PackWriter};
+
+        let tmp_dir = TempDir::<()>::create("test_packfile_verify_checksums");
+        let mut writer = PackWriter::new();
+        writer.add(b"blob 10\0readme.md\n").unwrap();
+        let mut pack = Vec::new();
+        writer.write(&mut pack).unwrap();
+        let idx_path = index_pack(pack, None)
+            .unwrap()
+            .write_to(tmp_dir.tmp_dir())
+            .unwrap();
+        let pack_path = idx_path.with_extension("pack");
+        let pack = fs::read(&pack_path).unwrap();
+        let idx = fs::read(&idx_path).unwrap();
+
+        PackFile::from_files(&idx_path, &pack_path)
+            .unwrap()
+            .verify()
+            .unwrap();
+
+        // Corrupt contents are only found by verifying
+        let mut corrupt = pack.clone();
+        corrupt[15] ^= 1;
+        fs::write(&pack_path, &corrupt).unwrap();
+        let packfile = PackFile::from_files(&idx_path, &pack_path).unwrap();
+        assert_eq!(
+            packfile.verify().unwrap_err().to_string(),
+            "Packfile checksum mismatch"
+        );
+
+        // A different pack does not match the index
+        let len = corrupt.len();
+        corrupt[len - 1] ^= 1;
+        fs::write(&pack_path, &corrupt).unwrap();
+        let err = PackFile::from_files(&idx_path, &pack_path)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("does not match its index"), "{err}");
+
+        // The CRC of the only object, after the fan-out table and its hash
+        let mut corrupt = idx.clone();
+        corrupt[8 + 1024 + HASH_SIZE] ^= 1;
+        fs::write(&pack_path, &pack).unwrap();
+        fs::write(&idx_path, &corrupt).unwrap();
+        let packfile = PackFile::from_files(&idx_path, &pack_path).unwrap();
+        assert_eq!(
+            packfile.verify().unwrap_err().to_string(),
+            "Pack index checksum mismatch"
+        );
+    }
+
+    #[test]
+    fn test_index_v1() {
+        use crate::core::objects::packwriter::{index_pack, PackWriter};
+
+        let tmp_dir = TempDir::<()>::create("test_packfile_index_v1");
+        let raws: [&[u8]; 2] = [b"blob 10\0readme.md\n", b"blob 9\0testfile\n"];
+        let mut writer = PackWriter::new();
+        for raw in raws {
+            writer.add(raw).unwrap();
+        }
+        let mut pack = Vec::new();
+        writer.write(&mut pack).unwrap();
+        let idx_path = index_pack(pack, None)
+            .unwrap()
+            .write_to(tmp_dir.tmp_dir())
+            .unwrap();
+
+        // Rewrite the version 2 index as a version 1 index
+        let v2 = fs::read(&idx_path).unwrap();
+        let count = raws.len();
+        let hashes = &v2[8 + 1024..8 + 1024 + HASH_SIZE * count];
+        let offsets = &v2[8 + 1024 + 24 * count..8 + 1024 + 28 * count];
+        let mut v1 = v2[8..8 + 1024].to_vec();
+        for (offset, hash) in offsets.chunks(4).zip(hashes.chunks(HASH_SIZE)) {
+            v1.extend_from_slice(offset);
+            v1.extend_from_slice(hash);
+        }
+        v1.extend_from_slice(
+            &v2[v2.len() - 2 * HASH_SIZE..v2.len() - HASH_SIZE],
+        );
+        let checksum = sha1::hash(&v1);
+        v1.extend_from_slice(&checksum);
+        fs::write(&idx_path, &v1).unwrap();
+
+        let mut packfile =
+            PackFile::from_files(&idx_path, &idx_path.with_extension("pack"))
+                .unwrap();
+        packfile.verify().unwrap();
+        for raw in raws {
+            let oid = Oid::from_bytes(&sha1::hash(raw)).unwrap();
+            assert_eq!(packfile.read_raw_object(&oid).unwrap(), raw);
+        }
+
+        // Fan-out tables never decrease
+        let mut corrupt = v1.clone();
+        corrupt[3] = 0xFF;
+        fs::write(&idx_path, &corrupt).unwrap();
+        assert!(PackFile::from_files(
+            &idx_path,
+            &idx_path.with_extension("pack")
+        )
+        .is_err());
+
+        // Nor count more objects than the index holds
+        v1[1020..1024].copy_from_slice(&0xFFFF_FFF0u32.to_be_bytes());
+        fs::write(&idx_path, &v1).unwrap();
+        let err =
+            PackFile::from_files(&idx_path, &idx_path.with_extension("pack"))
+                .unwrap_err()
+                .to_string();
+        assert!(err.contains("too short"), "{err}");
+    }
+
+    #[test]
+    fn test_index_corrupt_fanout() {
+        use crate::core::objects::packwriter::{index_pack, PackWriter};
+
+        let tmp_dir = TempDir::<()>::create("test_packfile_corrupt_fanout");
+        let mut writer = PackWriter::new();
+        writer.add(b"blob 10\0readme.md\n").unwrap();
+        writer.add(b"blob 9\0testfile\n").unwrap();
+        let mut pack = Vec::new();
+        writer.write(&mut pack).unwrap();
+        let idx_path = index_pack(pack, None)
+            .unwrap()
+            .write_to(tmp_dir.tmp_dir())
+            .unwrap();
+        let pack_path = idx_path.with_extension("pack");
+        let idx = fs::read(&idx_path).unwrap();
+        let load = |idx: &[u8]| {
+            fs::write(&idx_path, idx).unwrap();
+            PackFile::from_files(&idx_path, &pack_path)
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        };
+        load(&idx).unwrap();
+
+        // An object count the index cannot hold is not allocated for
+        let mut corrupt = idx.clone();
+        corrupt[8 + 1020..8 + 1024]
+            .copy_from_slice(&0xFFFF_FFF0u32.to_be_bytes());
+        let err = load(&corrupt).unwrap_err();
+        assert!(err.contains("too short"), "{err}");
+
+        // One more object than there is room for
+        let mut corrupt = idx.clone();
+        corrupt[8 + 1023] += 1;
+        let err = load(&corrupt).unwrap_err();
+        assert!(err.contains("too short"), "{err}");
+
+        let mut corrupt = idx;
+        corrupt[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+        let err = load(&corrupt).unwrap_err();
+        assert!(err.contains("not sorted"), "{err}");
+    }
+
+    #[test]
+    fn test_entry_crc() {
+        use crate::core::objects::packwriter::{index_pack, PackWriter};
+
+        let tmp_dir = TempDir::<()>::create("test_packfile_entry_crc");
+        let readme: &[u8] = b"blob 10\0readme.md\n";
+        let testfile: &[u8] = b"blob 9\0testfile\n";
+        let mut writer = PackWriter::new();
+        writer.add(readme).unwrap();
+        writer.add(testfile).unwrap();
+        let mut pack = Vec::new();
+        writer.write(&mut pack).unwrap();
+        let idx_path = index_pack(pack, None)
+            .unwrap()
+            .write_to(tmp_dir.tmp_dir())
+            .unwrap();
+        let pack_path = idx_path.with_extension("pack");
+
+        // Corrupt the first entry, which is the larger object
+        let mut pack = fs::read(&pack_path).unwrap();
+        pack[16] ^= 0x40;
+        fs::write(&pack_path, &pack).unwrap();
+
+        let mut packfile = PackFile::from_files(&idx_path, &pack_path).unwrap();
+        let oid = |data| Oid::from_bytes(&sha1::hash(data)).unwrap();
+        let err = packfile
+            .read_raw_object(&oid(readme))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("CRC mismatch"), "{err}");
+        assert_eq!(packfile.read_raw_object(&oid(testfile)).unwrap(), testfile);
+    }
+
+    #[test]
+    fn test_inflate_entry() {
+        let tmp_dir = TempDir::<()>::create("test_packfile_inflate_entry");
+        let pack_path = tmp_dir.tmp_dir().join("packfile.pack");
+        let compressed = zlib::compress(b"hello", &zlib::Strategy::Fixed);
+        let load = |size: u8, entry_offsets: Vec<u64>| {
+            let mut pack = b"PACK\0\0\0\x02\0\0\0\x01".to_vec();
+            pack.push(0x30 | size); // Blob
+            pack.extend_from_slice(&compressed);
+            pack.extend_from_slice(&[0; HASH_SIZE]);
+            fs::write(&pack_path, pack).unwrap();
+            PackFile {
+                format: ObjectFormat::Sha1,
+                hashes: Vec::new(),
+                offsets: Vec::new(),
+                fanout: [0; 256],
+                crcs: HashMap::new(),
+                entry_offsets,
+                idx_path: PathBuf::new(),
+                pack: Mmap::open(&pack_path).unwrap(),
+                object_cache: LruCache::new(OBJECT_CACHE_LIMIT),
+            }
+        };
+
+        let mut packfile = load(5, vec![12]);
+        assert_eq!(&*packfile.read_object_at_offset(12).unwrap(), b"hello");
+
+        // The size in the header must match
+        let err = load(6, vec![12])
+            .read_object_at_offset(12)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("wrong size"), "{err}");
+
+        // The stream may not run into the next entry
+        let err = load(5, vec![12, 16])
+            .read_object_at_offset(12)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Invalid packfile entry"), "{err}");
+    }
+
+    #[test]
+    fn test_delta_cycles() {
+        let tmp_dir = TempDir::<()>::create("test_packfile_delta_cycles");
+        let pack_path = tmp_dir.tmp_dir().join("packfile.pack");
+        // A delta of a 5 byte base that copies it whole
+        let delta = [0x05, 0x05, 0x90, 0x05];
+        let compressed = zlib::compress(&delta, &zlib::Strategy::Fixed);
+        let load = |entries: &[Vec<u8>], hashes: &[u8], offsets: Vec<u64>| {
+            let mut pack = b"PACK\0\0\0\x02\0\0\0".to_vec();
+            pack.push(u8::try_from(entries.len()).unwrap());
+            let mut entry_offsets = Vec::new();
+            for entry in entries {
+                entry_offsets.push(pack.len() as u64);
+                pack.extend_from_slice(entry);
+                pack.extend_from_slice(&compressed);
+            }
+            pack.extend_from_slice(&[0; HASH_SIZE]);
+            fs::write(&pack_path, pack).unwrap();
+            let mut fanout = [0; 256];
+            for hash in hashes.chunks(HASH_SIZE) {
+                for count in &mut fanout[usize::from(hash[0])..] {
+                    *count += 1;
+                }
+            }
+            PackFile {
+                format: ObjectFormat::Sha1,
+                hashes: hashes.to_vec(),
+                offsets,
+                fanout,
+                crcs: HashMap::new(),
+                entry_offsets,
+                idx_path: PathBuf::new(),
+                pack: Mmap::open(&pack_path).unwrap(),
+                object_cache: LruCache::new(OBJECT_CACHE_LIMIT),
+            }
+        };
+        let check = |packfile: &mut PackFile, expected: &str| {
+            let err = packfile.read_object_at_offset(12).unwrap_err();
+            assert!(err.to_string().contains(expected), "{err}");
+            let err = packfile.find_base_object_type_at_offset(12).unwrap_err();
+            assert!(err.to_string().contains(expected), "{err}");
+        };
+
+        // An OFS_DELTA whose base is itself
+        let mut packfile = load(&[vec![0x64, 0x00]], &[], vec![]);
+        check(&mut packfile, "Invalid delta base offset");
+
+        // Two REF_DELTAs that are each other's base
+        let (first, second) = ([0x11; HASH_SIZE], [0x22; HASH_SIZE]);
+        let entry_len = 1 + HASH_SIZE + compressed.len();
+        let mut packfile = load(
+            &[
+                [&[0x74][..], &second].concat(),
+                [&[0x74][..], &first].concat(),
+            ],
+            &[first, second].concat(),
+            vec![12, 12 + entry_len as u64],
+        );
+        check(&mut packfile, "delta chain deeper than");
+
+        // A REF_DELTA that is its own base
+        let mut packfile =
+            load(&[[&[0x74][..], &first].concat()], &first, vec![12]);
+        check(&mut packfile, "delta chain deeper than");
+    }
+
+    #[test]
+    fn test_objects_with_prefix() {
+        use crate::core::objects::packwriter::{index_pack, PackWriter};
+
+        let tmp_dir = TempDir::<()>::create("test_packfile_objects_prefix");
+        let mut writer = PackWriter::new().with_depth(0);
+        let mut ids: Vec<Oid> = (0..40)
+            .map(|i| {
+                let data = format!("blob {}\0{i}", i.to_string().len());
+                writer.add(data.as_bytes()).unwrap().parse().unwrap()
+            })
+            .collect();
+        ids.sort();
+        let mut pack = Vec::new();
+        writer.write(&mut pack).unwrap();
+        let idx_path = index_pack(pack, None)
+            .unwrap()
+            .write_to(tmp_dir.tmp_dir())
+            .unwrap();
+        let packfile =
+            PackFile::from_files(&idx_path, &idx_path.with_extension("pack"))
+                .unwrap();
+
+        assert_eq!(packfile.object_ids().collect::<Vec<_>>(), ids);
+        for id in &ids {
+            assert!(packfile.contains(id));
+            let hex = id.to_string();
+            for len in [1, 2, 3, 7, 40] {
+                let prefix: OidPrefix = hex[..len].parse().unwrap();
+                let expected: Vec<Oid> = ids
+                    .iter()
+                    .filter(|id| id.starts_with(&prefix))
+                    .copied()
+                    .collect();
+                assert_eq!(packfile.objects_with_prefix(&prefix), expected);
+            }
+            let upper = hex[..5].to_ascii_uppercase().parse().unwrap();
+            assert_eq!(packfile.find_object_with_prefix(&upper), Some(*id));
+        }
+
+        let mut missing = ids[0].as_bytes().to_vec();
+        missing[HASH_SIZE - 1] ^= 1;
+        let missing = Oid::from_bytes(&missing).unwrap();
+        assert!(!packfile.contains(&missing));
+        let long: OidPrefix = "a".repeat(41).parse().unwrap();
+        assert!(packfile.objects_with_prefix(&long).is_empty());
+        assert_eq!(packfile.find_object_with_prefix(&missing.into()), None);
+    }
+
     #[test]
     fn test_packfile_from_files_invalid_paths() {
         let idx_path = Path::new("nonexistent.idx");
@@ -788,9 +1737,15 @@ mod tests {
         pack_file.flush().unwrap();
 
         let packfile = PackFile {
-            index: HashMap::new(),
-            pack_file: File::open(&pack_path).unwrap(),
-            object_cache: HashMap::new(),
+            format: ObjectFormat::Sha1,
+            hashes: Vec::new(),
+            offsets: Vec::new(),
+            fanout: [0; 256],
+            crcs: HashMap::new(),
+            entry_offsets: Vec::new(),
+            idx_path: PathBuf::new(),
+            pack: Mmap::open(&pack_path).unwrap(),
+            object_cache: LruCache::new(OBJECT_CACHE_LIMIT),
         };
 
         // Since there's no real object, we can't read it, but we can test that
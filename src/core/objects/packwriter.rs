@@ -0,0 +1,1140 @@
+//! Writing packfiles, and indexing packfiles received from elsewhere
+//!
+//! A [`PackWriter`] writes objects into a packfile, storing objects as deltas
+//! against similar objects where that is smaller. A delta against an object
+//! earlier in the pack is an `OFS_DELTA` entry, which names its base by its
+//! offset. A thin pack also has deltas against objects only the receiving
+//! side has, which are `REF_DELTA` entries naming their base by its hash.
+//!
+//! [`index_pack`] checks a packfile, resolves its deltas and makes its index,
+//! like `git index-pack`. The bases missing from a thin pack are read from an
+//! object database and appended to the pack, like `git index-pack --fix-thin`.
+//!
+//! Both can report what they are doing to a [`Progress`], through
+//! [`PackWriter::write_with_progress`] and [`index_pack_with_progress`].
+
+#![allow(clippy::module_name_repetitions)]
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::core::objects::packfiles::delta::{apply_delta, create_delta};
+use crate::core::objects::reject_collision;
+use crate::core::objects::store::ObjectDatabase;
+use crate::core::{Error, GitRepository, ObjectFormat, ObjectHasher};
+use crate::utils::hex;
+use crate::utils::progress::{self, NoProgress, Progress};
+use crate::utils::threadpool::ThreadPool;
+use crate::utils::zlib::{self, crc32::crc32};
+
+type Hash = Vec<u8>;
+
+/// The length of the header of a packfile.
+const PACK_HEADER_SIZE: usize = 12;
+const OBJ_OFS_DELTA: u8 = 6;
+const OBJ_REF_DELTA: u8 = 7;
+/// Offsets in an index from this one on are stored in the large offset table.
+const LARGE_OFFSET: u64 = 0x8000_0000;
+
+/// The number of objects each object is compared with for deltas, like the
+/// default `pack.window` of git.
+pub const DEFAULT_WINDOW: usize = 10;
+/// The longest chain of deltas in a pack, like the default `pack.depth` of
+/// git.
+pub const DEFAULT_DEPTH: usize = 50;
+
+/// Writes objects into a packfile.
+///
+/// Objects are added as their header followed by their contents, like
+/// [`ObjectDatabase::read_raw`] returns them. Objects added with
+/// [`PackWriter::add_base`] are not written, but are used as delta bases,
+/// which makes the pack thin.
+///
+/// # Examples
+///
+/// ```
+/// use mini_git::core::objects::packwriter::{index_pack, PackWriter};
+///
+/// let mut writer = PackWriter::new();
+/// let sha = writer.add(b"blob 6\0hello\n")?;
+/// assert_eq!(sha, "ce013625030ba8dba906f756967f9e9ca394464a");
+///
+/// let mut pack = Vec::new();
+/// let checksum = writer.write(&mut pack)?;
+///
+/// let indexed = index_pack(pack, None)?;
+/// assert_eq!(indexed.checksum(), checksum);
+/// assert_eq!(indexed.object_count(), 1);
+/// # Ok::<(), String>(())
+/// ```
+#[derive(Debug)]
+pub struct PackWriter {
+    objects: Vec<PackObject>,
+    /// Objects the receiving side has, that deltas may be against.
+    bases: Vec<PackObject>,
+    hashes: HashSet<Hash>,
+    offset_deltas: bool,
+    window: usize,
+    depth: usize,
+    format: ObjectFormat,
+}
+
+/// A delta written by a [`PackWriter`], and its base.
+type Delta = (Base, Vec<u8>);
+
+/// Where the base of a delta written by a [`PackWriter`] is.
+#[derive(Debug, Clone, Copy)]
+enum Base {
+    /// An object written to the pack.
+    Packed(usize),
+    /// An object the receiving side has.
+    External(usize),
+}
+
+impl Default for PackWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PackWriter {
+    /// Creates a writer with no objects, which writes `OFS_DELTA` entries.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            objects: Vec::new(),
+            bases: Vec::new(),
+            hashes: HashSet::new(),
+            offset_deltas: true,
+            window: DEFAULT_WINDOW,
+            depth: DEFAULT_DEPTH,
+            format: ObjectFormat::Sha1,
+        }
+    }
+
+    /// Sets whether deltas against objects in the pack name their base by
+    /// its offset, or by its hash like deltas against external bases.
+    ///
+    /// Receivers that do not support `OFS_DELTA` entries need the latter.
+    #[must_use]
+    pub fn with_offset_deltas(mut self, offset_deltas: bool) -> Self {
+        self.offset_deltas = offset_deltas;
+        self
+    }
+
+    /// Sets the number of objects each object is compared with for deltas.
+    #[must_use]
+    pub fn with_window(mut self, window: usize) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Sets the longest chain of deltas, `0` writes no deltas.
+    #[must_use]
+    pub fn with_depth(mut self, depth: usize) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    /// Sets the hash algorithm that names objects and checksums the pack.
+    #[must_use]
+    pub fn with_object_format(mut self, format: ObjectFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Adds an object to the pack, returning its hex-encoded hash.
+    ///
+    /// Objects that were already added are only written once.
+    ///
+    /// # Errors
+    ///
+    /// If the object is malformed.
+    pub fn add(&mut self, raw: &[u8]) -> Result<String, Error> {
+        let object = PackObject::from_raw(raw, self.format)?;
+        let sha = hex::encode(&object.hash);
+        if self.hashes.insert(object.hash.clone()) {
+            self.objects.push(object);
+        }
+        Ok(sha)
+    }
+
+    /// Adds an object the receiving side has, which is not written to the
+    /// pack but may be the base of deltas in it, returning its hex-encoded
+    /// hash.
+    ///
+    /// # Errors
+    ///
+    /// If the object is malformed.
+    pub fn add_base(&mut self, raw: &[u8]) -> Result<String, Error> {
+        let object = PackObject::from_raw(raw, self.format)?;
+        let sha = hex::encode(&object.hash);
+        self.bases.push(object);
+        Ok(sha)
+    }
+
+    /// Returns the number of objects written to the pack.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    /// Returns whether no objects are written to the pack.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    /// Writes the pack to `out`, returning its hex-encoded checksum.
+    ///
+    /// # Errors
+    ///
+    /// If writing to `out` fails, or there are too many objects for a pack.
+    pub fn write(&self, out: &mut dyn Write) -> Result<String, Error> {
+        self.write_with_progress(out, &mut NoProgress)
+    }
+
+    /// Like [`write`](Self::write), reporting the objects written to
+    /// `progress`.
+    ///
+    /// # Errors
+    ///
+    /// If writing to `out` fails, or there are too many objects for a pack.
+    pub fn write_with_progress(
+        &self,
+        out: &mut dyn Write,
+        progress: &mut dyn Progress,
+    ) -> Result<String, Error> {
+        let count = u32::try_from(self.objects.len())
+            .map_err(|_| "Too many objects for a packfile".to_string())?;
+
+        let mut out = PackOutput {
+            out,
+            hasher: self.format.hasher(),
+            offset: 0,
+        };
+        out.write(b"PACK")?;
+        out.write(&2u32.to_be_bytes())?;
+        out.write(&count.to_be_bytes())?;
+
+        let mut offsets = HashMap::new();
+        progress.start("Writing objects", Some(u64::from(count)));
+        for (done, (i, delta)) in (1..).zip(self.find_deltas()) {
+            let object = &self.objects[i];
+            offsets.insert(i, out.offset);
+
+            let data = match delta {
+                None => {
+                    out.write(&entry_header(object.kind, object.data.len()))?;
+                    &object.data
+                }
+                Some((Base::Packed(base), ref delta)) if self.offset_deltas => {
+                    out.write(&entry_header(OBJ_OFS_DELTA, delta.len()))?;
+                    out.write(&encode_offset(offsets[&i] - offsets[&base]))?;
+                    delta
+                }
+                Some((base, ref delta)) => {
+                    let base = match base {
+                        Base::Packed(base) => &self.objects[base],
+                        Base::External(base) => &self.bases[base],
+                    };
+                    out.write(&entry_header(OBJ_REF_DELTA, delta.len()))?;
+                    out.write(&base.hash)?;
+                    delta
+                }
+            };
+            out.write(&zlib::compress_parallel(
+                data,
+                &zlib::Strategy::Fixed,
+                ThreadPool::shared(),
+            ))?;
+            progress.update(done, Some(out.offset));
+        }
+        progress.finish();
+
+        let checksum = out.hasher.finalize();
+        out.out.write_all(&checksum).map_err(|e| e.to_string())?;
+        Ok(hex::encode(&checksum))
+    }
+
+    /// Orders the objects as they are written, and finds the delta each one
+    /// is written as, if any.
+    ///
+    /// Like git, objects are ordered by type and then by decreasing size, so
+    /// that objects are deltas against larger similar objects before them.
+    fn find_deltas(&self) -> Vec<(usize, Option<Delta>)> {
+        let mut order: Vec<usize> = (0..self.objects.len()).collect();
+        order.sort_by_key(|&i| {
+            let object = &self.objects[i];
+            (object.kind, std::cmp::Reverse(object.data.len()))
+        });
+
+        let mut depths = vec![0; self.objects.len()];
+        let mut deltas = Vec::with_capacity(order.len());
+        for (pos, &i) in order.iter().enumerate() {
+            let object = &self.objects[i];
+
+            let packed = order[pos.saturating_sub(self.window)..pos]
+                .iter()
+                .filter(|&&base| depths[base] < self.depth)
+                .map(|&base| (Base::Packed(base), &self.objects[base]));
+            let mut external: Vec<_> = self
+                .bases
+                .iter()
+                .enumerate()
+                .filter(|(_, base)| self.depth > 0 && base.kind == object.kind)
+                .filter(|(_, base)| !self.hashes.contains(&base.hash))
+                .collect();
+            external.sort_by_key(|(_, base)| {
+                base.data.len().abs_diff(object.data.len())
+            });
+            let external = external
+                .into_iter()
+                .take(self.window)
+                .map(|(base, object)| (Base::External(base), object));
+
+            // A delta is only worth it if it is much smaller than the object
+            let mut best = None;
+            let mut limit = object.data.len() / 2;
+            for (base, base_object) in packed.chain(external) {
+                if base_object.kind != object.kind {
+                    continue;
+                }
+                let delta = create_delta(&base_object.data, &object.data);
+                if delta.len() < limit {
+                    limit = delta.len();
+                    best = Some((base, delta));
+                }
+            }
+
+            depths[i] = match best {
+                Some((Base::Packed(base), _)) => depths[base] + 1,
+                Some((Base::External(_), _)) => 1,
+                None => 0,
+            };
+            deltas.push((i, best));
+        }
+        deltas
+    }
+}
+
+/// An object in a pack.
+#[derive(Debug)]
+struct PackObject {
+    hash: Hash,
+    kind: u8,
+    data: Vec<u8>,
+}
+
+impl PackObject {
+    /// Parses an object from its header followed by its contents, naming it
+    /// with the hash of `format`.
+    fn from_raw(raw: &[u8], format: ObjectFormat) -> Result<Self, Error> {
+        let malformed = || "Malformed object".to_string();
+
+        let null =
+            raw.iter().position(|&b| b == b'\0').ok_or_else(malformed)?;
+        let header =
+            std::str::from_utf8(&raw[..null]).map_err(|_| malformed())?;
+        let (name, size) = header.split_once(' ').ok_or_else(malformed)?;
+        let kind = object_type(name)
+            .ok_or_else(|| format!("Unknown object type: {name}"))?;
+        let data = &raw[null + 1..];
+        if size.parse() != Ok(data.len()) {
+            return Err(malformed().into());
+        }
+
+        Ok(Self {
+            hash: format.hash(raw),
+            kind,
+            data: data.to_vec(),
+        })
+    }
+}
+
+/// Writes to the output of a [`PackWriter`], hashing what is written.
+struct PackOutput<'a> {
+    out: &'a mut dyn Write,
+    hasher: ObjectHasher,
+    offset: u64,
+}
+
+impl PackOutput<'_> {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        let _ = self.hasher.update(bytes);
+        self.offset += bytes.len() as u64;
+        self.out
+            .write_all(bytes)
+            .map_err(|e| Error::io("Failed to write pack", e))
+    }
+}
+
+/// A packfile and its index, made by [`index_pack`].
+#[derive(Debug)]
+pub struct IndexedPack {
+    pack: Vec<u8>,
+    index: Vec<u8>,
+    checksum: Hash,
+    object_count: usize,
+}
+
+impl IndexedPack {
+    /// Returns the packfile, with the bases it was missing if it was thin.
+    #[must_use]
+    pub fn pack(&self) -> &[u8] {
+        &self.pack
+    }
+
+    /// Returns the version 2 index of the packfile.
+    #[must_use]
+    pub fn index(&self) -> &[u8] {
+        &self.index
+    }
+
+    /// Returns the hex-encoded checksum of the packfile, which names it.
+    #[must_use]
+    pub fn checksum(&self) -> String {
+        hex::encode(&self.checksum)
+    }
+
+    /// Returns the number of objects in the packfile.
+    #[must_use]
+    pub fn object_count(&self) -> usize {
+        self.object_count
+    }
+
+    /// Writes the packfile and its index into `pack_dir`, named after the
+    /// checksum like git names them, returning the path to the index.
+    ///
+    /// The index is written last, so that the pack is complete once it is
+    /// found.
+    ///
+    /// # Errors
+    ///
+    /// If the files cannot be written.
+    pub fn write_to(&self, pack_dir: &Path) -> Result<PathBuf, Error> {
+        fs::create_dir_all(pack_dir).map_err(|e| e.to_string())?;
+        let name = format!("pack-{}", self.checksum());
+        let pack_path = pack_dir.join(format!("{name}.pack"));
+        let idx_path = pack_dir.join(format!("{name}.idx"));
+
+        fs::write(&pack_path, &self.pack).map_err(|e| {
+            format!("Failed to write to file {}: {e}", pack_path.display())
+        })?;
+        fs::write(&idx_path, &self.index).map_err(|e| {
+            format!("Failed to write to file {}: {e}", idx_path.display())
+        })?;
+        Ok(idx_path)
+    }
+}
+
+/// An entry read from a packfile by [`index_pack`].
+#[derive(Debug)]
+struct Entry {
+    offset: usize,
+    /// The offset just after the entry.
+    end: usize,
+    kind: u8,
+    base: Option<EntryBase>,
+    /// The contents of the object, or the delta against its base, until it
+    /// is resolved.
+    data: Vec<u8>,
+    /// The hash of the object, once it is resolved.
+    hash: Hash,
+}
+
+/// Where the base of a delta entry is.
+#[derive(Debug, Clone)]
+enum EntryBase {
+    /// At an offset in the same packfile.
+    Offset(usize),
+    /// The object with the hash.
+    Hash(Hash),
+}
+
+/// Checks the packfile `pack`, resolves its deltas and makes its index.
+///
+/// If `thin_bases` is given, delta bases missing from the pack are read from
+/// it and appended to the pack, which completes a thin pack. Otherwise, thin
+/// packs are refused.
+///
+/// Objects are named with SHA-1, see [`index_pack_with_format`] for other
+/// hash algorithms.
+///
+/// # Errors
+///
+/// If the packfile is malformed, its checksum does not match, or a delta
+/// base is missing.
+pub fn index_pack(
+    pack: Vec<u8>,
+    thin_bases: Option<&mut ObjectDatabase>,
+) -> Result<IndexedPack, Error> {
+    index_pack_with_format(pack, thin_bases, ObjectFormat::Sha1)
+}
+
+/// Like [`index_pack`], for a packfile whose objects are named, and which is
+/// checksummed, with the hash of `format`.
+///
+/// # Errors
+///
+/// If the packfile is malformed, its checksum does not match, or a delta
+/// base is missing.
+pub fn index_pack_with_format(
+    pack: Vec<u8>,
+    thin_bases: Option<&mut ObjectDatabase>,
+    format: ObjectFormat,
+) -> Result<IndexedPack, Error> {
+    index_pack_with_progress(pack, thin_bases, format, &mut NoProgress)
+}
+
+/// Like [`index_pack_with_format`], reporting the objects indexed and the
+/// deltas resolved to `progress`.
+///
+/// # Errors
+///
+/// If the packfile is malformed, its checksum does not match, or a delta
+/// base is missing.
+pub fn index_pack_with_progress(
+    mut pack: Vec<u8>,
+    thin_bases: Option<&mut ObjectDatabase>,
+    format: ObjectFormat,
+    progress: &mut dyn Progress,
+) -> Result<IndexedPack, Error> {
+    let hash_len = format.hash_len();
+    if pack.len() < PACK_HEADER_SIZE + hash_len || &pack[0..4] != b"PACK" {
+        return Err("Invalid packfile signature".into());
+    }
+    let version = u32::from_be_bytes([pack[4], pack[5], pack[6], pack[7]]);
+    if version != 2 {
+        return Err(
+            format!("Packfile version not supported: {version}.").into()
+        );
+    }
+    let count = u32::from_be_bytes([pack[8], pack[9], pack[10], pack[11]]);
+
+    let end = pack.len() - hash_len;
+    if format.hash(&pack[..end])[..] != pack[end..] {
+        return Err("Packfile checksum mismatch".into());
+    }
+
+    let mut entries = Vec::new();
+    let mut offset = PACK_HEADER_SIZE;
+    // One inflater is reused for every entry, rather than allocating its
+    // buffers again for each
+    let mut inflater = zlib::Inflater::new(&pack[..0]);
+    progress.start("Indexing objects", Some(u64::from(count)));
+    for done in 1..=count {
+        let entry = read_entry(&pack[..end], offset, hash_len, &mut inflater)?;
+        offset = entry.end;
+        entries.push(entry);
+        progress.update(u64::from(done), Some(offset as u64));
+    }
+    progress.finish();
+    if offset != end {
+        return Err("Packfile has data after its objects".into());
+    }
+
+    let external = resolve_deltas(&mut entries, thin_bases, format, progress)?;
+
+    // Make the pack complete with its missing bases
+    let mut objects: Vec<(Hash, u64, u32)> = entries
+        .iter_mut()
+        .map(|entry| {
+            (
+                std::mem::take(&mut entry.hash),
+                entry.offset as u64,
+                crc32(&pack[entry.offset..entry.end]),
+            )
+        })
+        .collect();
+    if !external.is_empty() {
+        pack.truncate(end);
+        for (hash, (kind, data)) in &external {
+            let offset = pack.len();
+            pack.extend(entry_header(*kind, data.len()));
+            pack.extend(zlib::compress_parallel(
+                data,
+                &zlib::Strategy::Fixed,
+                ThreadPool::shared(),
+            ));
+            objects.push((
+                hash.to_owned(),
+                offset as u64,
+                crc32(&pack[offset..]),
+            ));
+        }
+        let count = u32::try_from(objects.len())
+            .map_err(|_| "Too many objects for a packfile".to_string())?;
+        pack[8..PACK_HEADER_SIZE].copy_from_slice(&count.to_be_bytes());
+        let checksum = format.hash(&pack);
+        pack.extend_from_slice(&checksum);
+    }
+
+    let checksum = pack[pack.len() - hash_len..].to_vec();
+    let object_count = objects.len();
+    let index = make_index(&mut objects, &checksum, format);
+
+    Ok(IndexedPack {
+        pack,
+        index,
+        checksum,
+        object_count,
+    })
+}
+
+/// Indexes the packfile `pack` and stores it in the object directory of
+/// `repo`, returning its hex-encoded checksum.
+///
+/// If `fix_thin` is set, delta bases missing from the pack are read from
+/// the repository and appended to it.
+///
+/// # Errors
+///
+/// If the pack cannot be indexed, see [`index_pack`], or cannot be written.
+pub fn store_pack(
+    repo: &GitRepository,
+    pack: Vec<u8>,
+    fix_thin: bool,
+) -> Result<String, Error> {
+    let mut db = ObjectDatabase::open(repo);
+    let indexed = index_pack_with_progress(
+        pack,
+        fix_thin.then_some(&mut db),
+        repo.object_format(),
+        &mut *progress::default(),
+    )?;
+    indexed.write_to(&repo.objects_dir().join("pack"))?;
+    repo.object_ids().clear();
+    Ok(indexed.checksum())
+}
+
+/// An object as its type and contents.
+type Object = (u8, Arc<[u8]>);
+
+/// Resolves the deltas of the entries of a pack and hashes the entries,
+/// returning the bases missing from the pack, read from `thin_bases`.
+fn resolve_deltas(
+    entries: &mut [Entry],
+    mut thin_bases: Option<&mut ObjectDatabase>,
+    format: ObjectFormat,
+    progress: &mut dyn Progress,
+) -> Result<Vec<(Hash, Object)>, Error> {
+    let by_offset: HashMap<usize, usize> = entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| (entry.offset, i))
+        .collect();
+    let mut resolved: Vec<Option<Object>> = vec![None; entries.len()];
+    let mut by_hash: HashMap<Hash, usize> = HashMap::new();
+    // Bases missing from the pack, in the order they are appended
+    let mut external: Vec<(Hash, Object)> = Vec::new();
+
+    let deltas = entries.iter().filter(|entry| entry.base.is_some()).count();
+    let mut resolved_deltas = 0;
+    progress.start("Resolving deltas", Some(deltas as u64));
+
+    // Deltas are resolved once their base is, until none are left
+    let mut unresolved = entries.len();
+    while unresolved > 0 {
+        let mut advanced = false;
+        for i in 0..entries.len() {
+            if resolved[i].is_some() {
+                continue;
+            }
+
+            let (kind, data): (u8, Arc<[u8]>) = match &entries[i].base {
+                None => (
+                    entries[i].kind,
+                    std::mem::take(&mut entries[i].data).into(),
+                ),
+                Some(base) => {
+                    let base = match base {
+                        EntryBase::Offset(offset) => by_offset
+                            .get(offset)
+                            .and_then(|&j| resolved[j].clone()),
+                        EntryBase::Hash(hash) => by_hash
+                            .get(hash)
+                            .and_then(|&j| resolved[j].clone())
+                            .or_else(|| {
+                                external
+                                    .iter()
+                                    .find(|(base, _)| base == hash)
+                                    .map(|(_, object)| object.clone())
+                            }),
+                    };
+                    let Some((kind, base)) = base else {
+                        continue;
+                    };
+                    let data = apply_delta(&base, &entries[i].data)?;
+                    entries[i].data = Vec::new();
+                    resolved_deltas += 1;
+                    progress.update(resolved_deltas, None);
+                    (kind, data.into())
+                }
+            };
+
+            let hash = object_hash(kind, &data, format)?;
+            if by_hash.insert(hash.clone(), i).is_some() {
+                return Err(format!(
+                    "Packfile has object {} twice",
+                    hex::encode(&hash)
+                )
+                .into());
+            }
+            entries[i].hash = hash;
+            resolved[i] = Some((kind, data));
+            unresolved -= 1;
+            advanced = true;
+        }
+
+        if advanced {
+            continue;
+        }
+
+        // The missing bases are outside the pack, or are deltas against
+        // objects outside the pack
+        let missing: Vec<Hash> = entries
+            .iter()
+            .zip(&resolved)
+            .filter(|(_, object)| object.is_none())
+            .filter_map(|(entry, _)| match &entry.base {
+                Some(EntryBase::Hash(hash)) => Some(hash.clone()),
+                _ => None,
+            })
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        let Some(db) =
+            thin_bases.as_deref_mut().filter(|_| !missing.is_empty())
+        else {
+            return Err(
+                format!("Packfile has {unresolved} unresolved deltas").into()
+            );
+        };
+        for hash in missing {
+            let sha = hex::encode(&hash);
+            let raw = db.read_raw(&sha).map_err(|_| {
+                format!("Delta base {sha} is missing from the packfile")
+            })?;
+            let object = PackObject::from_raw(&raw, format)?;
+            external.push((hash, (object.kind, object.data.into())));
+        }
+    }
+
+    progress.finish();
+
+    // Bases of deltas against objects in the pack that were themselves
+    // unresolved are read too, but are not missing
+    external.retain(|(hash, _)| !by_hash.contains_key(hash));
+    Ok(external)
+}
+
+/// Reads the entry at `offset` in `pack`, which excludes the trailer, where
+/// `REF_DELTA` bases are named by hashes of `hash_len` bytes, inflating it
+/// with `inflater`.
+fn read_entry<'a>(
+    pack: &'a [u8],
+    offset: usize,
+    hash_len: usize,
+    inflater: &mut zlib::Inflater<&'a [u8]>,
+) -> Result<Entry, Error> {
+    let truncated = || format!("Truncated packfile entry at {offset}");
+    let byte = |pos: usize| pack.get(pos).copied().ok_or_else(truncated);
+    let mut pos = offset;
+
+    // The object type, and its size (variable-length encoding)
+    let mut c = byte(pos)?;
+    pos += 1;
+    let kind = (c >> 4) & 0x07;
+    let mut size = usize::from(c & 0x0F);
+    let mut shift = 4;
+    while c & 0x80 != 0 {
+        c = byte(pos)?;
+        pos += 1;
+        let bits = usize::from(c & 0x7F)
+            .checked_shl(shift)
+            .filter(|_| shift < usize::BITS)
+            .ok_or_else(|| format!("Invalid object size at {offset}"))?;
+        size |= bits;
+        shift += 7;
+    }
+
+    let base = match kind {
+        1..=4 => None,
+        OBJ_OFS_DELTA => {
+            let invalid = || format!("Invalid delta base offset at {offset}");
+            let mut c = byte(pos)?;
+            pos += 1;
+            let mut value = usize::from(c & 0x7F);
+            while c & 0x80 != 0 {
+                c = byte(pos)?;
+                pos += 1;
+                value = value
+                    .checked_add(1)
+                    .and_then(|value| value.checked_mul(0x80))
+                    .ok_or_else(invalid)?
+                    | usize::from(c & 0x7F);
+            }
+            let base = offset.checked_sub(value).filter(|&base| base > 0);
+            Some(EntryBase::Offset(base.ok_or_else(invalid)?))
+        }
+        OBJ_REF_DELTA => {
+            let hash = pack
+                .get(pos..pos + hash_len)
+                .ok_or_else(truncated)?
+                .to_vec();
+            pos += hash_len;
+            Some(EntryBase::Hash(hash))
+        }
+        _ => return Err(format!("Unknown object type: {kind}").into()),
+    };
+
+    inflater.reset(&pack[pos..]);
+    inflater.set_limit(Some(size as u64));
+    let mut data = Vec::new();
+    inflater
+        .read_to_end(&mut data)
+        .map_err(|e| format!("Invalid packfile entry at {offset}: {e}"))?;
+    if data.len() != size {
+        return Err(
+            format!("Packfile entry at {offset} has the wrong size").into()
+        );
+    }
+    let end =
+        usize::try_from(inflater.total_in()).map_err(|_| truncated())? + pos;
+
+    Ok(Entry {
+        offset,
+        end,
+        kind,
+        base,
+        data,
+        hash: Hash::new(),
+    })
+}
+
+/// Makes a version 2 pack index, of objects with their offset and the CRC
+/// of their entry.
+fn make_index(
+    objects: &mut [(Hash, u64, u32)],
+    checksum: &[u8],
+    format: ObjectFormat,
+) -> Vec<u8> {
+    objects.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+    let mut index = b"\xfftOc".to_vec();
+    index.extend(2u32.to_be_bytes());
+
+    let mut fanout = [0u32; 256];
+    for (hash, ..) in objects.iter() {
+        fanout[usize::from(hash[0])] += 1;
+    }
+    let mut total = 0;
+    for count in fanout {
+        total += count;
+        index.extend(total.to_be_bytes());
+    }
+
+    for (hash, ..) in objects.iter() {
+        index.extend(hash);
+    }
+    for (_, _, crc) in objects.iter() {
+        index.extend(crc.to_be_bytes());
+    }
+    let mut large_offsets = Vec::new();
+    for &(_, offset, _) in objects.iter() {
+        if offset < LARGE_OFFSET {
+            #[allow(clippy::cast_possible_truncation)]
+            index.extend((offset as u32).to_be_bytes());
+        } else {
+            #[allow(clippy::cast_possible_truncation)]
+            let position = large_offsets.len() as u32 | 0x8000_0000;
+            index.extend(position.to_be_bytes());
+            large_offsets.push(offset);
+        }
+    }
+    for offset in large_offsets {
+        index.extend(offset.to_be_bytes());
+    }
+
+    index.extend(checksum);
+    let index_checksum = format.hash(&index);
+    index.extend(index_checksum);
+    index
+}
+
+/// Encodes the type and size of an entry, as the header of the entry.
+fn entry_header(kind: u8, size: usize) -> Vec<u8> {
+    #[allow(clippy::cast_possible_truncation)]
+    let mut header = vec![(kind << 4) | (size & 0x0F) as u8];
+    let mut size = size >> 4;
+    while size > 0 {
+        *header.last_mut().expect("header is not empty") |= 0x80;
+        #[allow(clippy::cast_possible_truncation)]
+        header.push((size & 0x7F) as u8);
+        size >>= 7;
+    }
+    header
+}
+
+/// Encodes the distance back to the base of an `OFS_DELTA` entry.
+fn encode_offset(mut distance: u64) -> Vec<u8> {
+    #[allow(clippy::cast_possible_truncation)]
+    let mut encoded = vec![(distance & 0x7F) as u8];
+    distance >>= 7;
+    while distance > 0 {
+        distance -= 1;
+        #[allow(clippy::cast_possible_truncation)]
+        encoded.push(0x80 | (distance & 0x7F) as u8);
+        distance >>= 7;
+    }
+    encoded.reverse();
+    encoded
+}
+
+/// The hash of `format` of an object with the type and contents.
+///
+/// Objects that are part of a SHA-1 collision attack are rejected.
+fn object_hash(
+    kind: u8,
+    data: &[u8],
+    format: ObjectFormat,
+) -> Result<Hash, Error> {
+    let name = object_format(kind).unwrap_or_default();
+    let mut hasher = format.hasher().with_collision_detection();
+    let hash = hasher
+        .update(format!("{name} {}\0", data.len()).as_bytes())
+        .update(data)
+        .finalize();
+    reject_collision(&hasher, &hex::encode(&hash))?;
+    Ok(hash)
+}
+
+fn object_type(format: &str) -> Option<u8> {
+    match format {
+        "commit" => Some(1),
+        "tree" => Some(2),
+        "blob" => Some(3),
+        "tag" => Some(4),
+        _ => None,
+    }
+}
+
+fn object_format(kind: u8) -> Option<&'static str> {
+    match kind {
+        1 => Some("commit"),
+        2 => Some("tree"),
+        3 => Some("blob"),
+        4 => Some("tag"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::objects::oid::Oid;
+    use crate::core::objects::packfiles::PackFile;
+    use crate::core::objects::store::{MemoryObjectStore, ObjectStore};
+    use crate::utils::sha1;
+    use crate::utils::test::TempDir;
+
+    const HASH_SIZE: usize = 20;
+
+    fn raw_blob(data: &[u8]) -> Vec<u8> {
+        [format!("blob {}\0", data.len()).as_bytes(), data].concat()
+    }
+
+    /// Blobs with mostly the same contents.
+    fn similar_blobs() -> (Vec<u8>, Vec<u8>) {
+        let base: Vec<u8> = (0..2000u32)
+            .flat_map(|i| format!("line {i}\n").into_bytes())
+            .collect();
+        let mut changed = base.clone();
+        changed.splice(5000..5010, b"changed".iter().copied());
+        (raw_blob(&base), raw_blob(&changed))
+    }
+
+    /// The types of the entries of a pack, in order.
+    fn entry_types(pack: &[u8]) -> Vec<u8> {
+        let count = u32::from_be_bytes([pack[8], pack[9], pack[10], pack[11]]);
+        let mut offset = PACK_HEADER_SIZE;
+        let mut inflater = zlib::Inflater::new(&pack[..0]);
+        (0..count)
+            .map(|_| {
+                let entry = read_entry(
+                    &pack[..pack.len() - HASH_SIZE],
+                    offset,
+                    HASH_SIZE,
+                    &mut inflater,
+                )
+                .expect("Read entry");
+                offset = entry.end;
+                entry.kind
+            })
+            .collect()
+    }
+
+    /// Reads every object of `raws` back from the written pack.
+    fn check_pack(indexed: &IndexedPack, raws: &[&[u8]], name: &str) {
+        let tmp_dir = TempDir::<()>::create(name);
+        let idx_path = indexed.write_to(tmp_dir.tmp_dir()).unwrap();
+        let mut packfile =
+            PackFile::from_files(&idx_path, &idx_path.with_extension("pack"))
+                .unwrap();
+        for raw in raws {
+            let oid = Oid::from_bytes(&sha1::hash(raw)).unwrap();
+            assert_eq!(&packfile.read_raw_object(&oid).unwrap(), raw);
+        }
+    }
+
+    #[test]
+    fn test_offset_deltas() {
+        let (base, changed) = similar_blobs();
+        let mut writer = PackWriter::new();
+        writer.add(&changed).unwrap();
+        writer.add(&base).unwrap();
+        writer.add(&base).unwrap();
+        writer.add(&raw_blob(b"")).unwrap();
+        assert_eq!(writer.len(), 3);
+
+        let mut pack = Vec::new();
+        writer.write(&mut pack).unwrap();
+        assert_eq!(entry_types(&pack), [3, OBJ_OFS_DELTA, 3]);
+
+        let indexed = index_pack(pack, None).unwrap();
+        assert_eq!(indexed.object_count(), 3);
+        check_pack(&indexed, &[&base, &changed], "test_pack_offset_deltas");
+
+        // Without offset deltas, deltas name their base
+        let mut writer = PackWriter::new().with_offset_deltas(false);
+        writer.add(&base).unwrap();
+        writer.add(&changed).unwrap();
+        let mut pack = Vec::new();
+        writer.write(&mut pack).unwrap();
+        assert_eq!(entry_types(&pack), [3, OBJ_REF_DELTA]);
+        let indexed = index_pack(pack, None).unwrap();
+        check_pack(&indexed, &[&base, &changed], "test_pack_ref_deltas");
+
+        // Or are not written at all
+        let mut writer = PackWriter::new().with_depth(0);
+        writer.add(&base).unwrap();
+        writer.add(&changed).unwrap();
+        let mut pack = Vec::new();
+        writer.write(&mut pack).unwrap();
+        assert_eq!(entry_types(&pack), [3, 3]);
+    }
+
+    #[test]
+    fn test_sha256_pack() {
+        let format = ObjectFormat::Sha256;
+        let (base, changed) = similar_blobs();
+        let mut writer = PackWriter::new()
+            .with_object_format(format)
+            .with_offset_deltas(false);
+        let sha = writer.add(&raw_blob(b"")).unwrap();
+        assert_eq!(
+            sha,
+            "473a0f4c3be8a93681a267e3b1e9a7dcda1185436fe141f7749120a303721813"
+        );
+        writer.add(&base).unwrap();
+        writer.add(&changed).unwrap();
+
+        let mut pack = Vec::new();
+        let checksum = writer.write(&mut pack).unwrap();
+        assert_eq!(checksum.len(), 64);
+        assert!(index_pack(pack.clone(), None).is_err());
+
+        let indexed = index_pack_with_format(pack, None, format).unwrap();
+        assert_eq!(indexed.checksum(), checksum);
+        let tmp_dir = TempDir::<()>::create("test_pack_sha256");
+        let idx_path = indexed.write_to(tmp_dir.tmp_dir()).unwrap();
+        let mut packfile = PackFile::from_files_with_format(
+            &idx_path,
+            &idx_path.with_extension("pack"),
+            format,
+        )
+        .unwrap();
+        packfile.verify().unwrap();
+        for raw in [&base, &changed] {
+            let oid = Oid::from_bytes(&format.hash(raw)).unwrap();
+            assert_eq!(&packfile.read_raw_object(&oid).unwrap(), raw);
+        }
+    }
+
+    #[test]
+    fn test_thin_pack() {
+        let (base, changed) = similar_blobs();
+        let mut writer = PackWriter::new();
+        let base_sha = writer.add_base(&base).unwrap();
+        writer.add(&changed).unwrap();
+
+        let mut pack = Vec::new();
+        let checksum = writer.write(&mut pack).unwrap();
+        assert_eq!(entry_types(&pack), [OBJ_REF_DELTA]);
+        assert!(index_pack(pack.clone(), None)
+            .unwrap_err()
+            .to_string()
+            .contains("unresolved"));
+
+        // The base has to be there to fix the pack
+        let mut db = ObjectDatabase::new().with_store(MemoryObjectStore::new());
+        let err = index_pack(pack.clone(), Some(&mut db))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains(&base_sha), "{err}");
+
+        let mut store = MemoryObjectStore::new();
+        store.write(&base_sha, &base).unwrap();
+        let mut db = ObjectDatabase::new().with_store(store);
+        let indexed = index_pack(pack, Some(&mut db)).unwrap();
+        assert_eq!(indexed.object_count(), 2);
+        assert_ne!(indexed.checksum(), checksum);
+        assert_eq!(entry_types(indexed.pack()), [OBJ_REF_DELTA, 3]);
+        check_pack(&indexed, &[&base, &changed], "test_pack_thin");
+    }
+
+    #[test]
+    fn test_index_pack_errors() {
+        let mut writer = PackWriter::new();
+        writer.add(&raw_blob(b"readme.md\n")).unwrap();
+        let mut pack = Vec::new();
+        writer.write(&mut pack).unwrap();
+
+        let mut corrupt = pack.clone();
+        corrupt[PACK_HEADER_SIZE + 3] ^= 1;
+        assert!(index_pack(corrupt, None)
+            .unwrap_err()
+            .to_string()
+            .contains("checksum"));
+
+        let mut bad_signature = pack.clone();
+        bad_signature[0] = b'K';
+        assert!(index_pack(bad_signature, None)
+            .unwrap_err()
+            .to_string()
+            .contains("signature"));
+
+        // The count in the header must match the entries
+        let mut extra = pack[..pack.len() - HASH_SIZE].to_vec();
+        extra[11] = 2;
+        let checksum = sha1::hash(&extra);
+        extra.extend_from_slice(&checksum);
+        assert!(index_pack(extra, None)
+            .unwrap_err()
+            .to_string()
+            .contains("Truncated"));
+    }
+
+    #[test]
+    fn test_encodings() {
+        assert_eq!(entry_header(3, 10), [0x3A]);
+        assert_eq!(entry_header(1, 100), [0x94, 0x06]);
+        assert_eq!(encode_offset(127), [0x7F]);
+        assert_eq!(encode_offset(128), [0x80, 0x00]);
+        assert_eq!(encode_offset(16511), [0xFF, 0x7F]);
+    }
+}
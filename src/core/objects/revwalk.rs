@@ -0,0 +1,500 @@
+//! Walking the history of commits
+//!
+//! A [`Revwalk`] lists the commits reachable from those pushed onto it and
+//! not from those hidden, which is what `git log A ^B` and `git rev-list`
+//! show. Commits are listed newest first, or in the order picked by
+//! [`Revwalk::sorting`] and [`Revwalk::reverse`], and can be limited to
+//! those changing some paths.
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use mini_git::core::GitRepository;
+//! use mini_git::core::objects::revwalk::Sort;
+//!
+//! let repo = GitRepository::new(Path::new("."))?;
+//! let mut walk = repo.revwalk();
+//! walk.push_head()?;
+//! walk.sorting(Sort::Topological).limit_path("src");
+//! for oid in walk {
+//!     println!("{}", oid?);
+//! }
+//! # Ok::<(), String>(())
+//! ```
+
+use std::cmp::Reverse;
+use std::collections::hash_map::Entry;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+
+use crate::core::objects::commit::Commit;
+use crate::core::objects::oid::Oid;
+use crate::core::objects::traits::KVLM;
+use crate::core::objects::{read_object, resolve_ref, GitObject};
+use crate::core::{Error, GitRepository};
+use crate::kvlm_val_to_string;
+
+/// The order in which a [`Revwalk`] lists commits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Sort {
+    /// Newest first by committer date, as `git log` does.
+    #[default]
+    Time,
+    /// No commit before all of its children, with the commits of each line
+    /// of history together, as `git log --topo-order` does.
+    Topological,
+}
+
+/// An iterator over the names of commits in the history of a repository.
+///
+/// Commits are pushed to start the walk from, and hidden to leave out them
+/// and their ancestors. The walk starts on the first call to
+/// [`Iterator::next`], so commits pushed or hidden after that are not
+/// walked.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::Path;
+/// use mini_git::core::GitRepository;
+///
+/// // The commits on `topic` that are not on `main`, oldest first
+/// let repo = GitRepository::new(Path::new("."))?;
+/// let mut walk = repo.revwalk();
+/// walk.push(&repo.resolve("topic")?)?;
+/// walk.hide(&repo.resolve("main")?)?;
+/// walk.reverse(true);
+/// let commits = walk.collect::<Result<Vec<_>, _>>()?;
+/// # Ok::<(), String>(())
+/// ```
+pub struct Revwalk<'r> {
+    repo: &'r GitRepository,
+    sort: Sort,
+    reverse: bool,
+    opts: WalkOpts,
+    tips: Vec<(Oid, Commit)>,
+    hidden: Vec<Oid>,
+    state: State,
+}
+
+// What changes which commits are walked and listed
+#[derive(Default)]
+struct WalkOpts {
+    first_parent: bool,
+    // The components of each path the commits are limited to
+    paths: Vec<Vec<String>>,
+}
+
+enum State {
+    // The walk has not started
+    Pending,
+    // Commits are listed as they are walked
+    Walking(Walk),
+    // Every commit was walked up front, to be sorted
+    Sorted(VecDeque<(Oid, Commit)>),
+    // The walk ended, or failed
+    Done,
+}
+
+// A commit taken off the queue, with the parents walked from it
+struct Visit {
+    oid: Oid,
+    commit: Commit,
+    parents: Vec<Oid>,
+    shown: bool,
+}
+
+struct Walk {
+    // Commits are visited newest first, by committer date. Ties are broken
+    // by the order in which the commits were discovered.
+    queue: BinaryHeap<(u64, Reverse<usize>, Oid)>,
+    commits: HashMap<Oid, Commit>,
+    seen: HashSet<Oid>,
+    hidden: HashSet<Oid>,
+    discovered: usize,
+    // What the limiting paths name in each commit visited
+    entries: HashMap<Oid, Vec<Option<Oid>>>,
+}
+
+impl<'r> Revwalk<'r> {
+    /// Makes a walk of `repo` that lists nothing until commits are pushed.
+    #[must_use]
+    pub fn new(repo: &'r GitRepository) -> Self {
+        Self {
+            repo,
+            sort: Sort::default(),
+            reverse: false,
+            opts: WalkOpts::default(),
+            tips: Vec::new(),
+            hidden: Vec::new(),
+            state: State::Pending,
+        }
+    }
+
+    /// Walks from the commit `oid`, or the commit a tag named `oid` points
+    /// to.
+    ///
+    /// # Errors
+    ///
+    /// If the object cannot be read, or is not a commit or a tag of one.
+    pub fn push(&mut self, oid: &Oid) -> Result<(), Error> {
+        self.tips.push(peel_to_commit(self.repo, *oid)?);
+        Ok(())
+    }
+
+    /// Walks from the commit `HEAD` points to, if any.
+    ///
+    /// # Errors
+    ///
+    /// If `HEAD` cannot be read, or does not name a commit.
+    pub fn push_head(&mut self) -> Result<(), Error> {
+        match resolve_ref(self.repo, "HEAD")? {
+            Some(head) => self.push(&head),
+            None => Ok(()),
+        }
+    }
+
+    /// Leaves out the commit `oid`, or the commit a tag named `oid` points
+    /// to, and all of its ancestors.
+    ///
+    /// # Errors
+    ///
+    /// If the object cannot be read, or is not a commit or a tag of one.
+    pub fn hide(&mut self, oid: &Oid) -> Result<(), Error> {
+        let (oid, _) = peel_to_commit(self.repo, *oid)?;
+        self.hidden.push(oid);
+        Ok(())
+    }
+
+    /// Sets the order commits are listed in, [`Sort::Time`] by default.
+    pub fn sorting(&mut self, sort: Sort) -> &mut Self {
+        self.sort = sort;
+        self
+    }
+
+    /// Whether to list commits in the opposite order, so oldest first.
+    pub fn reverse(&mut self, reverse: bool) -> &mut Self {
+        self.reverse = reverse;
+        self
+    }
+
+    /// Whether to follow only the first parent of merge commits.
+    pub fn first_parent(&mut self, first_parent: bool) -> &mut Self {
+        self.opts.first_parent = first_parent;
+        self
+    }
+
+    /// Lists only commits changing `path`, a file or a directory relative
+    /// to the top of the worktree. Once limited, commits changing any of
+    /// the paths given are listed.
+    ///
+    /// A merge that has the same content at the paths as one of its parents
+    /// is not listed, and only that parent is walked from it, as `git log`
+    /// does by default.
+    pub fn limit_path(&mut self, path: &str) -> &mut Self {
+        let components = path
+            .split('/')
+            .filter(|part| !part.is_empty() && *part != ".")
+            .map(str::to_owned)
+            .collect();
+        self.opts.paths.push(components);
+        self
+    }
+
+    /// The next commit of the walk, like [`Iterator::next`], along with the
+    /// commit itself.
+    ///
+    /// The walk ends after an error.
+    pub fn next_commit(&mut self) -> Option<Result<(Oid, Commit), Error>> {
+        let next = self.advance();
+        if next.is_err() {
+            self.state = State::Done;
+        }
+        next.transpose()
+    }
+
+    fn advance(&mut self) -> Result<Option<(Oid, Commit)>, Error> {
+        if matches!(self.state, State::Pending) {
+            self.start()?;
+        }
+        match &mut self.state {
+            State::Walking(walk) => {
+                while let Some(visit) = walk.step(self.repo, &self.opts)? {
+                    if visit.shown {
+                        return Ok(Some((visit.oid, visit.commit)));
+                    }
+                }
+                Ok(None)
+            }
+            State::Sorted(commits) => Ok(commits.pop_front()),
+            State::Pending | State::Done => Ok(None),
+        }
+    }
+
+    fn start(&mut self) -> Result<(), Error> {
+        let mut walk = Walk {
+            queue: BinaryHeap::new(),
+            commits: HashMap::new(),
+            seen: HashSet::new(),
+            hidden: hidden_commits(self.repo, &self.hidden)?,
+            discovered: 0,
+            entries: HashMap::new(),
+        };
+        for (oid, commit) in std::mem::take(&mut self.tips) {
+            if !walk.hidden.contains(&oid) && walk.seen.insert(oid) {
+                walk.enqueue(oid, commit);
+            }
+        }
+
+        // Listing newest first needs nothing but the queue
+        if self.sort == Sort::Time && !self.reverse {
+            self.state = State::Walking(walk);
+            return Ok(());
+        }
+
+        let mut visits = Vec::new();
+        while let Some(visit) = walk.step(self.repo, &self.opts)? {
+            visits.push(visit);
+        }
+        if self.sort == Sort::Topological {
+            visits = topo_sort(visits);
+        }
+        let mut commits = visits
+            .into_iter()
+            .filter(|visit| visit.shown)
+            .map(|visit| (visit.oid, visit.commit))
+            .collect::<VecDeque<_>>();
+        if self.reverse {
+            commits.make_contiguous().reverse();
+        }
+        self.state = State::Sorted(commits);
+        Ok(())
+    }
+}
+
+impl Iterator for Revwalk<'_> {
+    type Item = Result<Oid, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.next_commit()?.map(|(oid, _)| oid))
+    }
+}
+
+impl Walk {
+    fn enqueue(&mut self, oid: Oid, commit: Commit) {
+        self.queue.push((
+            commit_timestamp(&commit),
+            Reverse(self.discovered),
+            oid,
+        ));
+        self.commits.insert(oid, commit);
+        self.discovered += 1;
+    }
+
+    // Visits the next commit, queueing the parents walked from it
+    fn step(
+        &mut self,
+        repo: &GitRepository,
+        opts: &WalkOpts,
+    ) -> Result<Option<Visit>, Error> {
+        let Some((_, _, oid)) = self.queue.pop() else {
+            return Ok(None);
+        };
+        let commit = self
+            .commits
+            .remove(&oid)
+            .expect("Queued commits should be loaded");
+
+        let mut parents = commit.parents()?;
+        if opts.first_parent {
+            parents.truncate(1);
+        }
+        let shown = if opts.paths.is_empty() {
+            true
+        } else {
+            self.simplify(repo, opts, oid, &commit, &mut parents)?
+        };
+
+        for &parent in &parents {
+            if self.hidden.contains(&parent) || !self.seen.insert(parent) {
+                continue;
+            }
+            let (parent, parent_commit) = peel_to_commit(repo, parent)?;
+            self.enqueue(parent, parent_commit);
+        }
+
+        Ok(Some(Visit {
+            oid,
+            commit,
+            parents,
+            shown,
+        }))
+    }
+
+    // Whether a commit changes the limiting paths. A commit that does not
+    // change them from a parent is walked through that parent alone.
+    fn simplify(
+        &mut self,
+        repo: &GitRepository,
+        opts: &WalkOpts,
+        oid: Oid,
+        commit: &Commit,
+        parents: &mut Vec<Oid>,
+    ) -> Result<bool, Error> {
+        let entries = path_entries(repo, commit, &opts.paths)?;
+        if parents.is_empty() {
+            let shown = entries.iter().any(Option::is_some);
+            self.entries.insert(oid, entries);
+            return Ok(shown);
+        }
+
+        for &parent in parents.iter() {
+            let parent_entries = match self.entries.entry(parent) {
+                Entry::Occupied(cached) => cached.into_mut(),
+                Entry::Vacant(slot) => {
+                    let (_, parent_commit) = peel_to_commit(repo, parent)?;
+                    slot.insert(path_entries(
+                        repo,
+                        &parent_commit,
+                        &opts.paths,
+                    )?)
+                }
+            };
+            if *parent_entries == entries {
+                *parents = vec![parent];
+                self.entries.insert(oid, entries);
+                return Ok(false);
+            }
+        }
+        self.entries.insert(oid, entries);
+        Ok(true)
+    }
+}
+
+// Orders the visits so that no commit comes before its children. Each
+// commit is followed by its first parent when it has no other children
+// left, which keeps lines of history together.
+fn topo_sort(visits: Vec<Visit>) -> Vec<Visit> {
+    let index = visits
+        .iter()
+        .enumerate()
+        .map(|(i, visit)| (visit.oid, i))
+        .collect::<HashMap<_, _>>();
+    let mut children = vec![0usize; visits.len()];
+    for visit in &visits {
+        for parent in &visit.parents {
+            if let Some(&i) = index.get(parent) {
+                children[i] += 1;
+            }
+        }
+    }
+
+    // The visits are newest first, so the newest tip is on top
+    let mut stack = (0..visits.len())
+        .filter(|&i| children[i] == 0)
+        .rev()
+        .collect::<Vec<_>>();
+    let mut order = Vec::with_capacity(visits.len());
+    while let Some(i) = stack.pop() {
+        order.push(i);
+        for parent in visits[i].parents.iter().rev() {
+            if let Some(&j) = index.get(parent) {
+                children[j] -= 1;
+                if children[j] == 0 {
+                    stack.push(j);
+                }
+            }
+        }
+    }
+
+    let mut visits = visits.into_iter().map(Some).collect::<Vec<_>>();
+    order.into_iter().filter_map(|i| visits[i].take()).collect()
+}
+
+// The commits `hidden` and all of their ancestors
+fn hidden_commits(
+    repo: &GitRepository,
+    hidden: &[Oid],
+) -> Result<HashSet<Oid>, Error> {
+    let mut seen = hidden.iter().copied().collect::<HashSet<_>>();
+    let mut queue = hidden.to_vec();
+    while let Some(oid) = queue.pop() {
+        let (_, commit) = peel_to_commit(repo, oid)?;
+        for parent in commit.parents()? {
+            if seen.insert(parent) {
+                queue.push(parent);
+            }
+        }
+    }
+    Ok(seen)
+}
+
+// What each path names in the tree of `commit`, if anything
+fn path_entries(
+    repo: &GitRepository,
+    commit: &Commit,
+    paths: &[Vec<String>],
+) -> Result<Vec<Option<Oid>>, Error> {
+    let root = commit.tree()?;
+    paths
+        .iter()
+        .map(|path| tree_entry(repo, root, path))
+        .collect()
+}
+
+fn tree_entry(
+    repo: &GitRepository,
+    mut oid: Oid,
+    path: &[String],
+) -> Result<Option<Oid>, Error> {
+    for name in path {
+        let GitObject::Tree(tree) = read_object(repo, &oid)? else {
+            return Ok(None);
+        };
+        let leaf = tree
+            .leaves()
+            .iter()
+            .find(|leaf| leaf.path() == name.as_bytes());
+        let Some(leaf) = leaf else {
+            return Ok(None);
+        };
+        oid = leaf.sha().parse()?;
+    }
+    Ok(Some(oid))
+}
+
+// Resolves `sha` to a commit, following tags
+fn peel_to_commit(
+    repo: &GitRepository,
+    mut sha: Oid,
+) -> Result<(Oid, Commit), Error> {
+    loop {
+        match read_object(repo, &sha)? {
+            GitObject::Blob(_) => {
+                return Err(format!(
+                    "Cannot show history for a blob (sha {sha})"
+                )
+                .into())
+            }
+            GitObject::Tree(_) => {
+                return Err(format!(
+                    "Cannot show history for a tree (sha {sha})"
+                )
+                .into())
+            }
+            GitObject::Commit(commit) => return Ok((sha, commit)),
+            GitObject::Tag(tag) => {
+                let Some(object) = tag.kvlm().get_key(b"object") else {
+                    return Err(format!(
+                        "Bad tag {sha} does not have an object"
+                    )
+                    .into());
+                };
+                sha = kvlm_val_to_string!(object).parse()?;
+            }
+        }
+    }
+}
+
+// Committer timestamp in seconds since the epoch, 0 if unavailable
+fn commit_timestamp(commit: &Commit) -> u64 {
+    commit.committer().map_or(0, |committer| committer.time)
+}
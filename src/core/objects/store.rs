@@ -0,0 +1,1369 @@
+//! Object storage backends
+//!
+//! An [`ObjectStore`] is one place objects are kept in, like the loose object
+//! files of an object directory, or its packfiles. An [`ObjectDatabase`]
+//! composes several stores, and looks an object up in each of them in turn,
+//! so that new backends can be added without touching the lookup itself.
+//!
+//! The database of a repository, given by [`ObjectDatabase::open`], is made of
+//! the loose objects and the packfiles of its object directory, followed by
+//! those of every alternate object directory listed in
+//! `objects/info/alternates`.
+//!
+//! A [`MemoryObjectStore`] keeps objects in memory instead, to build objects
+//! without touching the filesystem.
+//!
+//! Objects can also be streamed, with [`ObjectDatabase::read_stream`] and
+//! [`ObjectDatabase::write_stream`], so that large blobs need not fit in
+//! memory. Loose objects are streamed from and to the disk, other stores read
+//! and write whole objects by default.
+//!
+//! Objects read through a database are kept in an [`ObjectCache`], which the
+//! repository shares between all of its databases, so that reading an object
+//! again does not decompress it from the disk again. Likewise, the names of
+//! its objects are listed once into an [`ObjectIdIndex`], to resolve and
+//! abbreviate object names by prefix.
+
+#![allow(clippy::module_name_repetitions)]
+
+use std::cell::OnceCell;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufWriter, Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::core::objects::oid::{Oid, OidPrefix};
+use crate::core::objects::packfiles::{self, PackFile};
+use crate::core::objects::{
+    hash_object, reject_collision, GitObject, NULL_BYTE, SPACE_BYTE,
+};
+use crate::core::{Error, GitRepository, ObjectFormat};
+use crate::utils::collections::lru_cache::LruCache;
+use crate::utils::path;
+use crate::utils::threadpool::ThreadPool;
+use crate::utils::trace;
+use crate::utils::zlib;
+
+/// How deep alternates of alternates are followed, like git.
+const MAX_ALTERNATE_DEPTH: usize = 5;
+
+/// The most bytes of objects the cache of a repository keeps.
+const OBJECT_CACHE_LIMIT: usize = 32 * 1024 * 1024;
+/// The size of the chunks objects are streamed in.
+const STREAM_CHUNK: usize = 1 << 16;
+/// The longest object header, a format and a size.
+const MAX_HEADER_LEN: usize = 32;
+
+/// A place objects are stored in.
+pub trait ObjectStore {
+    /// Reads the object named `sha`, as its header followed by its contents,
+    /// like [`hash_object`] returns.
+    ///
+    /// Returns `None` if the object is not in this store.
+    ///
+    /// # Errors
+    ///
+    /// If the object is in this store but cannot be read.
+    fn read_raw(&mut self, sha: &str) -> Result<Option<Vec<u8>>, Error>;
+
+    /// Reads the object named `sha`.
+    ///
+    /// Returns `None` if the object is not in this store.
+    ///
+    /// # Errors
+    ///
+    /// If the object is in this store but cannot be read, or is malformed.
+    fn read(&mut self, sha: &str) -> Result<Option<GitObject>, Error> {
+        let Some(raw) = self.read_raw(sha)? else {
+            return Ok(None);
+        };
+        parse_raw(sha, &raw, self.object_format()).map(Some)
+    }
+
+    /// Returns the hash algorithm the objects of this store are named with.
+    fn object_format(&self) -> ObjectFormat {
+        ObjectFormat::Sha1
+    }
+
+    /// Returns whether the object named `sha` is in this store.
+    fn contains(&self, sha: &str) -> bool;
+
+    /// Returns the size in bytes of the object named `sha`.
+    ///
+    /// Returns `None` if the object is not in this store. Only the header of
+    /// the object is parsed, the object is not deserialized.
+    ///
+    /// # Errors
+    ///
+    /// If the object is in this store but cannot be read.
+    fn size(&mut self, sha: &str) -> Result<Option<usize>, Error> {
+        let Some(raw) = self.read_raw(sha)? else {
+            return Ok(None);
+        };
+        raw_size(sha, &raw).map(Some)
+    }
+
+    /// Returns the names of all objects in this store that start with
+    /// `prefix`, in no particular order.
+    ///
+    /// # Errors
+    ///
+    /// If the objects of this store cannot be listed.
+    fn ids_with_prefix(&self, prefix: &str) -> Result<Vec<String>, Error>;
+
+    /// Returns whether objects can be written to this store.
+    fn is_writable(&self) -> bool {
+        false
+    }
+
+    /// Writes the object named `sha`, where `raw` is its header followed by
+    /// its contents, as returned by [`hash_object`].
+    ///
+    /// # Errors
+    ///
+    /// If this store is read-only, or the object cannot be written.
+    fn write(&mut self, sha: &str, raw: &[u8]) -> Result<(), Error> {
+        let _ = raw;
+        Err(format!("Cannot write object {sha} to a read-only store").into())
+    }
+
+    /// Opens the object named `sha` to stream its contents.
+    ///
+    /// Returns `None` if the object is not in this store. The default
+    /// implementation reads the whole object into memory.
+    ///
+    /// # Errors
+    ///
+    /// If the object is in this store but cannot be read, or its header is
+    /// malformed.
+    fn read_stream(
+        &mut self,
+        sha: &str,
+    ) -> Result<Option<ObjectReader>, Error> {
+        let Some(raw) = self.read_raw(sha)? else {
+            return Ok(None);
+        };
+        ObjectReader::from_raw(sha, raw.into()).map(Some)
+    }
+
+    /// Writes the object of type `format` with `size` bytes of contents read
+    /// from `data`.
+    ///
+    /// Returns the digest of the object. The default implementation reads the
+    /// whole object into memory, then writes it with [`ObjectStore::write`].
+    ///
+    /// # Errors
+    ///
+    /// If this store is read-only, `data` cannot be read or does not have
+    /// `size` bytes, or the object cannot be written.
+    fn write_stream(
+        &mut self,
+        format: &str,
+        size: u64,
+        data: &mut dyn Read,
+    ) -> Result<String, Error> {
+        let mut raw = vec![];
+        let object_format = self.object_format();
+        let sha = stream_object(format, size, data, object_format, |chunk| {
+            raw.extend_from_slice(chunk);
+            Ok(())
+        })?;
+        self.write(&sha, &raw)?;
+        Ok(sha)
+    }
+}
+
+/// The contents of an object, streamed from its store.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::io::Read;
+/// use std::path::Path;
+/// use mini_git::core::GitRepository;
+/// use mini_git::core::objects::read_object_stream;
+///
+/// let repo = GitRepository::new(Path::new("."))?;
+/// let oid = "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391".parse()?;
+/// let mut reader = read_object_stream(&repo, &oid)?;
+/// assert_eq!(reader.format(), "blob");
+///
+/// let mut contents = vec![];
+/// reader.read_to_end(&mut contents).map_err(|e| e.to_string())?;
+/// assert_eq!(contents.len() as u64, reader.size());
+/// # Ok::<(), String>(())
+/// ```
+pub struct ObjectReader {
+    format: String,
+    size: u64,
+    data: Box<dyn Read>,
+}
+
+impl std::fmt::Debug for ObjectReader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ObjectReader")
+            .field("format", &self.format)
+            .field("size", &self.size)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ObjectReader {
+    /// Streams the contents of the object named `sha` from `raw`, its header
+    /// followed by its contents.
+    fn from_raw(sha: &str, raw: Arc<[u8]>) -> Result<Self, Error> {
+        let malformed =
+            || Error::Parse(format!("malformed object with digest {sha}"));
+        let size = raw_size(sha, &raw)?;
+        let space = raw.iter().position(|&b| b == SPACE_BYTE);
+        let null = raw.iter().position(|&b| b == NULL_BYTE);
+        let (Some(space), Some(null)) = (space, null) else {
+            return Err(malformed());
+        };
+        let format = String::from_utf8_lossy(&raw[..space]).into_owned();
+
+        let mut data = Cursor::new(raw);
+        data.set_position((null + 1) as u64);
+        Ok(Self {
+            format,
+            size: size as u64,
+            data: Box::new(data),
+        })
+    }
+
+    /// Streams the contents of the object named `sha` from `raw`, which
+    /// starts with its header.
+    fn from_stream(
+        sha: &str,
+        mut raw: impl Read + 'static,
+    ) -> Result<Self, Error> {
+        let malformed =
+            || Error::Parse(format!("malformed object with digest {sha}"));
+        let mut header = vec![];
+        let mut byte = [0u8];
+        loop {
+            raw.read_exact(&mut byte).map_err(|_| malformed())?;
+            if byte[0] == NULL_BYTE {
+                break;
+            }
+            if header.len() == MAX_HEADER_LEN {
+                return Err(malformed());
+            }
+            header.push(byte[0]);
+        }
+
+        let header = String::from_utf8(header).map_err(|_| malformed())?;
+        let (format, size) = header.split_once(' ').ok_or_else(malformed)?;
+        let size = size.parse().map_err(|_| malformed())?;
+        Ok(Self {
+            format: format.to_owned(),
+            size,
+            data: Box::new(raw.take(size)),
+        })
+    }
+
+    /// Returns the type of the object, like `blob`.
+    #[must_use]
+    pub fn format(&self) -> &str {
+        &self.format
+    }
+
+    /// Returns the size in bytes of the contents of the object.
+    #[must_use]
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+impl Read for ObjectReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.data.read(buf)
+    }
+}
+
+/// Passes the header and the contents of the object of type `format` with
+/// `size` bytes of contents read from `data` to `sink`, in chunks.
+///
+/// Returns the digest of the object, hashed with the hash algorithm of
+/// `object_format`.
+pub(super) fn stream_object(
+    format: &str,
+    size: u64,
+    data: &mut dyn Read,
+    object_format: ObjectFormat,
+    mut sink: impl FnMut(&[u8]) -> Result<(), Error>,
+) -> Result<String, Error> {
+    let header = format!("{format} {size}\0");
+    let mut hash = object_format.hasher().with_collision_detection();
+    let _ = hash.update(header.as_bytes());
+    sink(header.as_bytes())?;
+
+    let mut buf = vec![0; STREAM_CHUNK];
+    let mut total = 0u64;
+    loop {
+        let len = match data.read(&mut buf) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                return Err(format!("Failed to read object data: {e}").into())
+            }
+        };
+        total += len as u64;
+        if total > size {
+            break;
+        }
+        let _ = hash.update(&buf[..len]);
+        sink(&buf[..len])?;
+    }
+
+    if total != size {
+        return Err(format!(
+            "Object data does not match its size, expected {size} bytes"
+        )
+        .into());
+    }
+    let digest = hash.hex_digest();
+    reject_collision(&hash, &digest)?;
+    Ok(digest)
+}
+
+/// Parses the object named `sha` from its header and contents, where objects
+/// are named with the hash algorithm of `format`.
+fn parse_raw(
+    sha: &str,
+    raw: &[u8],
+    format: ObjectFormat,
+) -> Result<GitObject, Error> {
+    GitObject::from_raw_data_with_format(raw, format).map_err(|msg| {
+        Error::Parse(format!("malformed object with digest {sha}, {msg}"))
+    })
+}
+
+/// Parses the size of the object named `sha` from its header.
+fn raw_size(sha: &str, raw: &[u8]) -> Result<usize, Error> {
+    let malformed =
+        || Error::Parse(format!("malformed object with digest {sha}"));
+
+    let space = raw
+        .iter()
+        .position(|&b| b == SPACE_BYTE)
+        .ok_or_else(malformed)?;
+    let null = raw
+        .iter()
+        .position(|&b| b == NULL_BYTE)
+        .ok_or_else(malformed)?;
+    if null < space {
+        return Err(malformed());
+    }
+    String::from_utf8_lossy(&raw[space + 1..null])
+        .parse()
+        .map_err(|_| malformed())
+}
+
+/// The loose objects of an object directory, each one stored as a zlib
+/// compressed file named after its digest.
+#[derive(Debug)]
+pub struct LooseStore {
+    dir: PathBuf,
+    writable: bool,
+    format: ObjectFormat,
+}
+
+impl LooseStore {
+    /// Creates a store over the loose objects in the object directory `dir`.
+    /// Objects are only written to it if `writable` is set.
+    #[must_use]
+    pub fn new(dir: &Path, writable: bool) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            writable,
+            format: ObjectFormat::Sha1,
+        }
+    }
+
+    /// Names the objects of this store with the hash algorithm of `format`.
+    #[must_use]
+    pub fn with_object_format(mut self, format: ObjectFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    fn object_path(&self, sha: &str) -> Option<PathBuf> {
+        if sha.len() <= 2 || !sha.is_ascii() {
+            return None;
+        }
+        Some(self.dir.join(&sha[..2]).join(&sha[2..]))
+    }
+}
+
+impl ObjectStore for LooseStore {
+    fn read_raw(&mut self, sha: &str) -> Result<Option<Vec<u8>>, Error> {
+        let Some(path) = self.object_path(sha).filter(|path| path.is_file())
+        else {
+            return Ok(None);
+        };
+        let raw = fs::read(path).map_err(|e| {
+            Error::io(format!("failed to read object with digest {sha}"), e)
+        })?;
+        zlib::decompress(&raw).map(Some).map_err(Error::Zlib)
+    }
+
+    fn contains(&self, sha: &str) -> bool {
+        self.object_path(sha).is_some_and(|path| path.is_file())
+    }
+
+    fn object_format(&self) -> ObjectFormat {
+        self.format
+    }
+
+    fn ids_with_prefix(&self, prefix: &str) -> Result<Vec<String>, Error> {
+        let mut ids = Vec::new();
+        if !prefix.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Ok(ids);
+        }
+        let prefix = prefix.to_ascii_lowercase();
+
+        // Objects are bucketed by the first two characters of their name
+        let buckets = if prefix.len() >= 2 {
+            vec![prefix[..2].to_owned()]
+        } else {
+            (0..=u8::MAX)
+                .map(|byte| format!("{byte:02x}"))
+                .filter(|bucket| bucket.starts_with(&prefix))
+                .collect()
+        };
+
+        for bucket in buckets {
+            let Some(dir) = path::repo_dir(&self.dir, &[&bucket], false)?
+            else {
+                continue;
+            };
+            for entry in fs::read_dir(dir).map_err(|e| e.to_string())? {
+                let entry = entry.map_err(|e| e.to_string())?;
+                let id =
+                    format!("{bucket}{}", entry.file_name().to_string_lossy());
+                if id.starts_with(&prefix) {
+                    ids.push(id);
+                }
+            }
+        }
+        Ok(ids)
+    }
+
+    fn is_writable(&self) -> bool {
+        self.writable
+    }
+
+    fn write(&mut self, sha: &str, raw: &[u8]) -> Result<(), Error> {
+        if !self.writable {
+            return Err(format!(
+                "Cannot write object {sha} to a read-only store"
+            )
+            .into());
+        }
+
+        let path = path::repo_file(&self.dir, &[&sha[..2], &sha[2..]], true)?;
+        let Some(path) = path else {
+            return Err(format!(
+                "Failed to create object file for digest {sha}"
+            )
+            .into());
+        };
+
+        // Never overwrite an existing object
+        if !path.exists() {
+            let compressed = zlib::compress_parallel(
+                raw,
+                &zlib::Strategy::Auto,
+                ThreadPool::shared(),
+            );
+            fs::write(&path, compressed).map_err(|_| {
+                format!("Failed to write to file {:?}", path.as_os_str())
+            })?;
+        }
+        Ok(())
+    }
+
+    fn read_stream(
+        &mut self,
+        sha: &str,
+    ) -> Result<Option<ObjectReader>, Error> {
+        let Some(path) = self.object_path(sha).filter(|path| path.is_file())
+        else {
+            return Ok(None);
+        };
+        let Ok(file) = fs::File::open(path) else {
+            return Err(
+                format!("failed to read object with digest {sha}").into()
+            );
+        };
+        ObjectReader::from_stream(sha, zlib::Inflater::new(file)).map(Some)
+    }
+
+    /// Streams the object to a temporary file, which is then renamed to the
+    /// object file once its digest is known.
+    fn write_stream(
+        &mut self,
+        format: &str,
+        size: u64,
+        data: &mut dyn Read,
+    ) -> Result<String, Error> {
+        static TEMP_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+        if !self.writable {
+            return Err("Cannot write object to a read-only store".into());
+        }
+        fs::create_dir_all(&self.dir).map_err(|e| e.to_string())?;
+        let temp = self.dir.join(format!(
+            "tmp_obj_{}_{}",
+            std::process::id(),
+            TEMP_COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let write_error = |e: io::Error| {
+            Error::io(format!("Failed to write to file {}", temp.display()), e)
+        };
+
+        let res = fs::File::create(&temp)
+            .map_err(write_error)
+            .and_then(|file| {
+                let mut encoder = zlib::ZlibEncoder::new(
+                    BufWriter::new(file),
+                    zlib::Strategy::Auto,
+                );
+                let sha =
+                    stream_object(format, size, data, self.format, |chunk| {
+                        encoder.write_all(chunk).map_err(write_error)
+                    })?;
+                encoder
+                    .finish()
+                    .and_then(|file| {
+                        file.into_inner()
+                            .map_err(io::IntoInnerError::into_error)
+                    })
+                    .and_then(|file| file.sync_all())
+                    .map_err(write_error)?;
+                Ok(sha)
+            })
+            .and_then(|sha| {
+                let path =
+                    path::repo_file(&self.dir, &[&sha[..2], &sha[2..]], true)?
+                        .ok_or_else(|| {
+                            format!(
+                                "Failed to create object file for digest {sha}"
+                            )
+                        })?;
+                // Never overwrite an existing object
+                if !path.exists() {
+                    fs::rename(&temp, &path).map_err(write_error)?;
+                }
+                Ok(sha)
+            });
+
+        let _ = fs::remove_file(&temp);
+        res
+    }
+}
+
+/// The packfiles of an object directory.
+///
+/// The packfiles are only loaded when the store is first used.
+#[derive(Debug)]
+pub struct PackStore {
+    dir: PathBuf,
+    format: ObjectFormat,
+    packs: OnceCell<Vec<PackFile>>,
+}
+
+impl PackStore {
+    /// Creates a store over the packfiles in the `pack` directory of the
+    /// object directory `dir`.
+    #[must_use]
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            format: ObjectFormat::Sha1,
+            packs: OnceCell::new(),
+        }
+    }
+
+    /// Names the objects of this store with the hash algorithm of `format`.
+    #[must_use]
+    pub fn with_object_format(mut self, format: ObjectFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    fn packs(&self) -> &[PackFile] {
+        // Packfiles that cannot be loaded are ignored, like a missing
+        // pack directory
+        self.packs.get_or_init(|| {
+            packfiles::find_packfiles_in(&self.dir, self.format)
+                .unwrap_or_default()
+        })
+    }
+
+    /// Parses a full hex object name of the format of the store.
+    fn parse_oid(&self, sha: &str) -> Option<Oid> {
+        Oid::parse(sha, self.format).ok()
+    }
+
+    fn packs_mut(&mut self) -> &mut [PackFile] {
+        let _ = self.packs();
+        self.packs.get_mut().map_or(&mut [], Vec::as_mut_slice)
+    }
+}
+
+impl ObjectStore for PackStore {
+    fn read_raw(&mut self, sha: &str) -> Result<Option<Vec<u8>>, Error> {
+        let Some(oid) = self.parse_oid(sha) else {
+            return Ok(None);
+        };
+        for pack in self.packs_mut() {
+            if pack.contains(&oid) {
+                return pack.read_raw_object(&oid).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    fn read(&mut self, sha: &str) -> Result<Option<GitObject>, Error> {
+        let Some(oid) = self.parse_oid(sha) else {
+            return Ok(None);
+        };
+        for pack in self.packs_mut() {
+            if pack.contains(&oid) {
+                return pack.read_object(&oid).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    fn contains(&self, sha: &str) -> bool {
+        self.parse_oid(sha)
+            .is_some_and(|oid| self.packs().iter().any(|p| p.contains(&oid)))
+    }
+
+    fn object_format(&self) -> ObjectFormat {
+        self.format
+    }
+
+    fn ids_with_prefix(&self, prefix: &str) -> Result<Vec<String>, Error> {
+        let packs = self.packs().iter();
+        if prefix.is_empty() {
+            return Ok(packs
+                .flat_map(PackFile::object_ids)
+                .map(|oid| oid.to_string())
+                .collect());
+        }
+        let Ok(prefix) = prefix.parse::<OidPrefix>() else {
+            return Ok(Vec::new());
+        };
+        Ok(packs
+            .flat_map(|pack| pack.objects_with_prefix(&prefix))
+            .map(|oid| oid.to_string())
+            .collect())
+    }
+}
+
+/// Objects kept in memory, for building objects without touching the
+/// filesystem.
+///
+/// # Examples
+///
+/// ```
+/// use mini_git::core::objects::blob::Blob;
+/// use mini_git::core::objects::store::{MemoryObjectStore, ObjectDatabase};
+/// use mini_git::core::objects::traits::Deserialize;
+/// use mini_git::core::objects::GitObject;
+///
+/// let mut db = ObjectDatabase::new().with_store(MemoryObjectStore::new());
+/// let blob = GitObject::Blob(Blob::deserialize(b"")?);
+/// let sha = db.write(&blob)?;
+/// assert_eq!(sha, "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391");
+/// assert_eq!(db.size(&sha)?, 0);
+/// # Ok::<(), String>(())
+/// ```
+#[derive(Debug, Default)]
+pub struct MemoryObjectStore {
+    objects: HashMap<String, Vec<u8>>,
+    format: ObjectFormat,
+}
+
+impl MemoryObjectStore {
+    /// Creates an empty store.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Names the objects of this store with the hash algorithm of `format`.
+    #[must_use]
+    pub fn with_object_format(mut self, format: ObjectFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Returns the number of objects in this store.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    /// Returns whether this store has no objects.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+}
+
+impl ObjectStore for MemoryObjectStore {
+    fn read_raw(&mut self, sha: &str) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.objects.get(sha).cloned())
+    }
+
+    fn contains(&self, sha: &str) -> bool {
+        self.objects.contains_key(sha)
+    }
+
+    fn object_format(&self) -> ObjectFormat {
+        self.format
+    }
+
+    fn ids_with_prefix(&self, prefix: &str) -> Result<Vec<String>, Error> {
+        let prefix = prefix.to_ascii_lowercase();
+        Ok(self
+            .objects
+            .keys()
+            .filter(|id| id.starts_with(&prefix))
+            .cloned()
+            .collect())
+    }
+
+    fn is_writable(&self) -> bool {
+        true
+    }
+
+    fn write(&mut self, sha: &str, raw: &[u8]) -> Result<(), Error> {
+        self.objects
+            .entry(sha.to_owned())
+            .or_insert_with(|| raw.to_vec());
+        Ok(())
+    }
+}
+
+/// Objects recently read from a database, bounded by their total size.
+///
+/// Clones of a cache share the same objects, so the cache of a repository is
+/// shared by every database opened for it, and by every clone of it.
+#[derive(Clone)]
+pub struct ObjectCache {
+    objects: Arc<Mutex<LruCache<String, Arc<[u8]>>>>,
+}
+
+impl Default for ObjectCache {
+    fn default() -> Self {
+        Self::new(OBJECT_CACHE_LIMIT)
+    }
+}
+
+impl std::fmt::Debug for ObjectCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ObjectCache")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl ObjectCache {
+    /// Creates an empty cache that keeps at most `capacity` bytes of objects.
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            objects: Arc::new(Mutex::new(LruCache::new(capacity))),
+        }
+    }
+
+    /// Returns the header and contents of the object named `sha`, if cached.
+    #[must_use]
+    pub fn get(&self, sha: &str) -> Option<Arc<[u8]>> {
+        let mut objects = self.objects.lock().ok()?;
+        objects.get(&sha.to_owned()).cloned()
+    }
+
+    /// Caches `raw`, the header and contents of the object named `sha`.
+    pub fn insert(&self, sha: &str, raw: Arc<[u8]>) {
+        if let Ok(mut objects) = self.objects.lock() {
+            let len = raw.len();
+            objects.insert(sha.to_owned(), raw, len);
+        }
+    }
+
+    /// Returns the number of cached objects.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.objects.lock().map_or(0, |objects| objects.len())
+    }
+
+    /// Returns whether no objects are cached.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// The sorted names of all objects of a database, to look objects up by
+/// prefix without listing the objects of every store again.
+///
+/// The names are listed from the stores the first time they are needed, and
+/// objects written through a database afterwards are added to them. Objects
+/// added to the object directory by other means are only seen once the index
+/// is cleared.
+///
+/// Clones of an index share the same names, so the index of a repository is
+/// shared by every database opened for it, and by every clone of it.
+#[derive(Clone, Default)]
+pub struct ObjectIdIndex {
+    ids: Arc<Mutex<Option<Vec<String>>>>,
+}
+
+impl std::fmt::Debug for ObjectIdIndex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ObjectIdIndex")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl ObjectIdIndex {
+    /// Creates an index that lists the names of objects when first needed.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the object named `sha`, if the names have been listed already.
+    pub fn insert(&self, sha: &str) {
+        let Ok(mut ids) = self.ids.lock() else {
+            return;
+        };
+        if let Some(ids) = ids.as_mut() {
+            if let Err(i) = ids.binary_search_by(|id| id.as_str().cmp(sha)) {
+                ids.insert(i, sha.to_owned());
+            }
+        }
+    }
+
+    /// Forgets the listed names, so that they are listed again when next
+    /// needed.
+    pub fn clear(&self) {
+        if let Ok(mut ids) = self.ids.lock() {
+            *ids = None;
+        }
+    }
+
+    /// Returns the number of listed names, which is zero until they are
+    /// first needed.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.ids
+            .lock()
+            .map_or(0, |ids| ids.as_ref().map_or(0, Vec::len))
+    }
+
+    /// Returns whether no names are listed.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Calls `f` with the sorted names of all objects in `stores`, listing
+    /// them first if needed.
+    fn with_ids<T>(
+        &self,
+        stores: &[Box<dyn ObjectStore>],
+        format: ObjectFormat,
+        f: impl FnOnce(&[String]) -> T,
+    ) -> Result<T, Error> {
+        let Ok(mut ids) = self.ids.lock() else {
+            return Ok(f(&list_ids(stores, format)?));
+        };
+        if ids.is_none() {
+            *ids = Some(list_ids(stores, format)?);
+        }
+        Ok(f(ids.as_deref().unwrap_or_default()))
+    }
+}
+
+/// The sorted names of all objects in `stores`, each named once, which are
+/// names of the hash algorithm of `format`.
+fn list_ids(
+    stores: &[Box<dyn ObjectStore>],
+    format: ObjectFormat,
+) -> Result<Vec<String>, Error> {
+    let mut ids = Vec::new();
+    for store in stores {
+        ids.extend(store.ids_with_prefix("")?);
+    }
+    ids.retain(|id| {
+        id.len() == format.hex_len()
+            && id.bytes().all(|b| b.is_ascii_hexdigit())
+    });
+    ids.sort_unstable();
+    ids.dedup();
+    Ok(ids)
+}
+
+/// The names in the sorted `ids` that start with `prefix`.
+fn ids_in_range<'a>(ids: &'a [String], prefix: &str) -> &'a [String] {
+    let start = ids.partition_point(|id| id.as_str() < prefix);
+    let len = ids[start..]
+        .iter()
+        .take_while(|id| id.starts_with(prefix))
+        .count();
+    &ids[start..start + len]
+}
+
+/// The length of the longest prefix `sha` shares with another name in `ids`.
+fn longest_shared_prefix<'a>(
+    ids: impl IntoIterator<Item = &'a String>,
+    sha: &str,
+) -> usize {
+    ids.into_iter()
+        .filter(|id| *id != sha)
+        .map(|id| {
+            sha.bytes()
+                .zip(id.bytes())
+                .take_while(|(a, b)| a == b)
+                .count()
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// The objects of a repository, looked up in a list of stores in order.
+///
+/// Objects are written to the first writable store. If the database has a
+/// cache, it is looked in before the stores, and objects read from the
+/// stores are added to it.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::Path;
+/// use mini_git::core::GitRepository;
+/// use mini_git::core::objects::store::ObjectDatabase;
+///
+/// let repo = GitRepository::new(Path::new("."))?;
+/// let mut db = ObjectDatabase::open(&repo);
+/// let obj = db.read("e69de29bb2d1d6434b8b29ae775ad8c2e48c5391")?;
+/// # Ok::<(), String>(())
+/// ```
+#[derive(Default)]
+pub struct ObjectDatabase {
+    stores: Vec<Box<dyn ObjectStore>>,
+    cache: Option<ObjectCache>,
+    ids: Option<ObjectIdIndex>,
+    format: ObjectFormat,
+}
+
+impl std::fmt::Debug for ObjectDatabase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ObjectDatabase")
+            .field("stores", &self.stores.len())
+            .field("cache", &self.cache)
+            .field("ids", &self.ids)
+            .field("format", &self.format)
+            .finish()
+    }
+}
+
+impl ObjectDatabase {
+    /// Creates a database with no stores.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates the database of `repo`, made of the loose objects and the
+    /// packfiles of its object directory, then those of its alternates.
+    ///
+    /// Only the loose objects of the repository itself are writable. Objects
+    /// are cached in the cache of the repository, and looked up by prefix in
+    /// its index of object names. Every store names objects with the object
+    /// format of the repository.
+    #[must_use]
+    pub fn open(repo: &GitRepository) -> Self {
+        let objects_dir = repo.objects_dir();
+        let format = repo.object_format();
+        let loose = |dir: &Path, writable| {
+            LooseStore::new(dir, writable).with_object_format(format)
+        };
+        let packed =
+            |dir: &Path| PackStore::new(dir).with_object_format(format);
+        let mut db = Self::new()
+            .with_object_format(format)
+            .with_cache(repo.object_cache().clone())
+            .with_id_index(repo.object_ids().clone())
+            .with_store(loose(&objects_dir, true))
+            .with_store(packed(&objects_dir));
+
+        let mut seen = vec![fs::canonicalize(&objects_dir)
+            .unwrap_or_else(|_| objects_dir.clone())];
+        for dir in alternates(&objects_dir, &mut seen, 0) {
+            db = db.with_store(loose(&dir, false)).with_store(packed(&dir));
+        }
+        db
+    }
+
+    /// Adds `store` after the stores of this database.
+    #[must_use]
+    pub fn with_store(mut self, store: impl ObjectStore + 'static) -> Self {
+        self.stores.push(Box::new(store));
+        self
+    }
+
+    /// Names the objects written to this database with the hash algorithm
+    /// of `format`, which should be the format of its stores.
+    #[must_use]
+    pub fn with_object_format(mut self, format: ObjectFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Caches the objects read from this database in `cache`.
+    #[must_use]
+    pub fn with_cache(mut self, cache: ObjectCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Looks objects up by prefix in `index`, rather than listing the
+    /// objects of every store for each lookup.
+    #[must_use]
+    pub fn with_id_index(mut self, index: ObjectIdIndex) -> Self {
+        self.ids = Some(index);
+        self
+    }
+
+    /// Reads the object named `sha` from the first store that has it.
+    ///
+    /// # Errors
+    ///
+    /// If no store has the object, or it cannot be read from any store
+    /// that has it.
+    pub fn read(&mut self, sha: &str) -> Result<GitObject, Error> {
+        if self.cache.is_some() {
+            return parse_raw(sha, &self.read_raw(sha)?, self.format);
+        }
+
+        trace::log("object", format_args!("read {sha}"));
+        let mut error = None;
+        for store in &mut self.stores {
+            match store.read(sha) {
+                Ok(Some(obj)) => return Ok(obj),
+                Ok(None) => {}
+                Err(e) => {
+                    error.get_or_insert(e);
+                }
+            }
+        }
+        Err(error.unwrap_or_else(|| Error::ObjectNotFound(sha.to_owned())))
+    }
+
+    /// Reads the object named `sha` from the cache, or the first store that
+    /// has it, as its header followed by its contents.
+    ///
+    /// # Errors
+    ///
+    /// If no store has the object, or it cannot be read from any store
+    /// that has it.
+    pub fn read_raw(&mut self, sha: &str) -> Result<Arc<[u8]>, Error> {
+        trace::log("object", format_args!("read {sha}"));
+        if let Some(raw) = self.cache.as_ref().and_then(|cache| cache.get(sha))
+        {
+            return Ok(raw);
+        }
+
+        let mut error = None;
+        for store in &mut self.stores {
+            match store.read_raw(sha) {
+                Ok(Some(raw)) => {
+                    let raw: Arc<[u8]> = raw.into();
+                    if let Some(cache) = &self.cache {
+                        cache.insert(sha, Arc::clone(&raw));
+                    }
+                    return Ok(raw);
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    error.get_or_insert(e);
+                }
+            }
+        }
+        Err(error.unwrap_or_else(|| Error::ObjectNotFound(sha.to_owned())))
+    }
+
+    /// Opens the object named `sha` from the cache, or the first store that
+    /// has it, to stream its contents.
+    ///
+    /// Objects streamed from a store are not cached.
+    ///
+    /// # Errors
+    ///
+    /// If no store has the object, or it cannot be read from any store
+    /// that has it.
+    pub fn read_stream(&mut self, sha: &str) -> Result<ObjectReader, Error> {
+        trace::log("object", format_args!("stream {sha}"));
+        if let Some(raw) = self.cache.as_ref().and_then(|cache| cache.get(sha))
+        {
+            return ObjectReader::from_raw(sha, raw);
+        }
+
+        let mut error = None;
+        for store in &mut self.stores {
+            match store.read_stream(sha) {
+                Ok(Some(reader)) => return Ok(reader),
+                Ok(None) => {}
+                Err(e) => {
+                    error.get_or_insert(e);
+                }
+            }
+        }
+        Err(error.unwrap_or_else(|| Error::ObjectNotFound(sha.to_owned())))
+    }
+
+    /// Writes the object of type `format` with `size` bytes of contents read
+    /// from `data` to the first writable store.
+    ///
+    /// Returns the digest of the object.
+    ///
+    /// # Errors
+    ///
+    /// If no store is writable, `data` cannot be read or does not have
+    /// `size` bytes, or the object cannot be written.
+    pub fn write_stream(
+        &mut self,
+        format: &str,
+        size: u64,
+        data: &mut dyn Read,
+    ) -> Result<String, Error> {
+        let Some(store) = self.stores.iter_mut().find(|s| s.is_writable())
+        else {
+            return Err("Cannot write object, no store is writable".into());
+        };
+        let digest = store.write_stream(format, size, data)?;
+        if let Some(ids) = &self.ids {
+            ids.insert(&digest);
+        }
+        Ok(digest)
+    }
+
+    /// Returns whether any store has the object named `sha`.
+    #[must_use]
+    pub fn contains(&self, sha: &str) -> bool {
+        self.stores.iter().any(|store| store.contains(sha))
+    }
+
+    /// Returns the size in bytes of the object named `sha`.
+    ///
+    /// # Errors
+    ///
+    /// If no store has the object, or it cannot be read.
+    pub fn size(&mut self, sha: &str) -> Result<usize, Error> {
+        if let Some(raw) = self.cache.as_ref().and_then(|cache| cache.get(sha))
+        {
+            return raw_size(sha, &raw);
+        }
+        for store in &mut self.stores {
+            if let Some(size) = store.size(sha)? {
+                return Ok(size);
+            }
+        }
+        Err(Error::ObjectNotFound(sha.to_owned()))
+    }
+
+    /// Returns the sorted names of all objects that start with `prefix`,
+    /// each named once even if several stores have it.
+    ///
+    /// # Errors
+    ///
+    /// If the objects of a store cannot be listed.
+    pub fn ids_with_prefix(&self, prefix: &str) -> Result<Vec<String>, Error> {
+        if let Some(index) = &self.ids {
+            if !prefix.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Ok(Vec::new());
+            }
+            let prefix = prefix.to_ascii_lowercase();
+            return index.with_ids(&self.stores, self.format, |ids| {
+                ids_in_range(ids, &prefix).to_vec()
+            });
+        }
+
+        let mut ids = Vec::new();
+        for store in &self.stores {
+            ids.extend(store.ids_with_prefix(prefix)?);
+        }
+        ids.sort_unstable();
+        ids.dedup();
+        Ok(ids)
+    }
+
+    /// Returns the length of the longest prefix that the name `sha` shares
+    /// with the name of another object.
+    ///
+    /// # Errors
+    ///
+    /// If the objects of a store cannot be listed.
+    pub fn longest_shared_prefix(&self, sha: &str) -> Result<usize, Error> {
+        let sha = sha.to_ascii_lowercase();
+        if let Some(index) = &self.ids {
+            // Names sharing the most with `sha` are sorted next to it
+            return index.with_ids(&self.stores, self.format, |ids| {
+                let i = ids.partition_point(|id| id.as_str() < sha.as_str());
+                let neighbours =
+                    &ids[i.saturating_sub(1)..(i + 2).min(ids.len())];
+                longest_shared_prefix(neighbours, &sha)
+            });
+        }
+
+        // Only objects in the same fan-out bucket can share a prefix with `sha`
+        let bucket = sha.get(..2).unwrap_or(&sha);
+        Ok(longest_shared_prefix(&self.ids_with_prefix(bucket)?, &sha))
+    }
+
+    /// Writes `obj` to the first writable store, unless some store already
+    /// has it.
+    ///
+    /// Returns the digest of the object.
+    ///
+    /// # Errors
+    ///
+    /// If no store is writable, or the object cannot be written.
+    pub fn write(&mut self, obj: &GitObject) -> Result<String, Error> {
+        let (raw, mut hash) = hash_object(obj, self.format);
+        let digest = hash.hex_digest();
+        reject_collision(&hash, &digest)?;
+
+        if self.contains(&digest) {
+            return Ok(digest);
+        }
+        let Some(store) = self.stores.iter_mut().find(|s| s.is_writable())
+        else {
+            return Err(format!(
+                "Cannot write object {digest}, no store is writable"
+            )
+            .into());
+        };
+        store.write(&digest, &raw)?;
+        if let Some(ids) = &self.ids {
+            ids.insert(&digest);
+        }
+        Ok(digest)
+    }
+}
+
+/// The alternate object directories listed in `objects/info/alternates` of
+/// the object directory `dir`, followed by their own alternates.
+///
+/// Relative paths are relative to `dir`. Directories in `seen`, or that do
+/// not exist, are skipped.
+fn alternates(
+    dir: &Path,
+    seen: &mut Vec<PathBuf>,
+    depth: usize,
+) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if depth >= MAX_ALTERNATE_DEPTH {
+        return dirs;
+    }
+    let Ok(contents) = fs::read_to_string(dir.join("info").join("alternates"))
+    else {
+        return dirs;
+    };
+
+    for line in contents.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Ok(alternate) = fs::canonicalize(dir.join(line)) else {
+            continue;
+        };
+        if seen.contains(&alternate) || !alternate.is_dir() {
+            continue;
+        }
+        seen.push(alternate.clone());
+        dirs.push(alternate.clone());
+        dirs.extend(alternates(&alternate, seen, depth + 1));
+    }
+    dirs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::objects::traits::{Deserialize, Serialize};
+    use crate::core::objects::{blob, tree};
+    use crate::utils::hex;
+
+    #[test]
+    fn test_memory_store() {
+        let mut store = MemoryObjectStore::new();
+        assert!(store.is_empty());
+
+        let readme = GitObject::Blob(
+            blob::Blob::deserialize(b"readme.md\n").expect("Create blob"),
+        );
+        let (raw, mut hash) = hash_object(&readme, ObjectFormat::Sha1);
+        let sha = hash.hex_digest();
+        store.write(&sha, &raw).expect("Write object");
+        store.write(&sha, &raw).expect("Write object again");
+        assert_eq!(store.len(), 1);
+
+        assert!(store.contains(&sha));
+        assert_eq!(store.size(&sha).unwrap(), Some(10));
+        assert_eq!(
+            store.ids_with_prefix("CDB5").unwrap(),
+            std::slice::from_ref(&sha)
+        );
+        assert!(store.ids_with_prefix("cdb6").unwrap().is_empty());
+        let Some(GitObject::Blob(obj)) = store.read(&sha).unwrap() else {
+            panic!("Expected a blob");
+        };
+        assert_eq!(obj.data(), b"readme.md\n");
+        assert!(store
+            .read("e69de29bb2d1d6434b8b29ae775ad8c2e48c5391")
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_memory_database() {
+        let mut db = ObjectDatabase::new().with_store(MemoryObjectStore::new());
+
+        let readme = GitObject::Blob(
+            blob::Blob::deserialize(b"readme.md\n").expect("Create blob"),
+        );
+        let blob_sha = db.write(&readme).expect("Write blob");
+
+        let tree = GitObject::Tree(
+            tree::Tree::deserialize(
+                &[
+                    b"100644 readme.md\0".as_slice(),
+                    &hex::decode(&blob_sha).expect("Decode digest"),
+                ]
+                .concat(),
+            )
+            .expect("Create tree"),
+        );
+        let tree_sha = db.write(&tree).expect("Write tree");
+        assert_eq!(tree_sha, "3c6da21eb9fbc41969464f9b07845fdc483fa265");
+
+        let Ok(GitObject::Tree(read)) = db.read(&tree_sha) else {
+            panic!("Expected a tree");
+        };
+        assert_eq!(read.serialize(), tree.serialize());
+    }
+}
@@ -10,6 +10,7 @@
 //! necessary requirements for Git operations and maintain compatibility with
 //! the Git format.
 
+use crate::core::Error;
 use crate::utils::collections::kvlm;
 
 /// Trait for Git object types that have a specific format representation.
@@ -64,9 +65,8 @@ pub trait KVLM {
     /// error message.
     ///
     /// # Errors
-    /// Returns an `Err` with a `String` describing the error if parsing the
-    /// KVLM structure fails.
-    fn deserialize(data: &[u8]) -> Result<Self, String>
+    /// Returns an [`Error`] if parsing the KVLM structure fails.
+    fn deserialize(data: &[u8]) -> Result<Self, Error>
     where
         Self: Sized,
     {
@@ -95,8 +95,8 @@ pub trait Deserialize {
     /// error message.
     ///
     /// # Errors
-    /// Returns a [`String`] describing the error if deserialization fails.
-    fn deserialize(data: &[u8]) -> Result<Self, String>
+    /// Returns an [`Error`] if deserialization fails.
+    fn deserialize(data: &[u8]) -> Result<Self, Error>
     where
         Self: Sized;
 }
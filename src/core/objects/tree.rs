@@ -10,9 +10,10 @@
 //! Git-compatible operations such as serialization, deserialization,
 //! and format identification.
 
+use crate::core::objects::oid::Oid;
 use crate::core::objects::traits;
 use crate::core::objects::{self, FileSource, GitObject};
-use crate::core::GitRepository;
+use crate::core::{Error, GitRepository, ObjectFormat};
 use crate::utils::hex;
 
 /// The byte representation of a space character.
@@ -32,7 +33,7 @@ pub struct Leaf {
     mode: [u8; MODE_SIZE],
     /// The path (name) of the entry.
     path: Vec<u8>,
-    /// The SHA-1 hash of the object this entry points to.
+    /// The name of the object this entry points to.
     sha: String,
     /// The total length of this leaf entry when serialized.
     len: usize,
@@ -125,13 +126,13 @@ impl PartialEq for Leaf {
 
 impl PartialOrd for Leaf {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp_path().cmp(&other.cmp_path()))
+        Some(self.cmp(other))
     }
 }
 
 impl Ord for Leaf {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.partial_cmp(other).unwrap()
+        self.cmp_path().cmp(&other.cmp_path())
     }
 }
 
@@ -146,10 +147,23 @@ impl traits::Deserialize for Leaf {
     /// error message.
     ///
     /// # Errors
-    /// Returns a [`String`] with a descriptive error message if deserialization
-    /// fails.
-    fn deserialize(data: &[u8]) -> Result<Self, String> {
-        let err = |x| Err(format!("invalid tree leaf: {x}"));
+    /// Returns an [`Error`] if deserialization fails.
+    fn deserialize(data: &[u8]) -> Result<Self, Error> {
+        Self::deserialize_with_format(data, ObjectFormat::Sha1)
+    }
+}
+
+impl Leaf {
+    /// Deserializes a byte slice into a Leaf object, whose object name is
+    /// hashed with the hash algorithm of `format`.
+    ///
+    /// # Errors
+    /// Returns an [`Error`] if deserialization fails.
+    pub fn deserialize_with_format(
+        data: &[u8],
+        format: ObjectFormat,
+    ) -> Result<Self, Error> {
+        let err = |x| Err(Error::Parse(format!("invalid tree leaf: {x}")));
         let Some(space_idx) = data.iter().position(|x| *x == SPACE_BYTE) else {
             return err("mode not found");
         };
@@ -191,17 +205,18 @@ impl traits::Deserialize for Leaf {
             return err("empty path");
         }
 
-        if data.len() < null_idx + 21 {
+        let len = null_idx + 1 + format.hash_len();
+        if data.len() < len {
             return err("sha not found");
         }
 
-        let sha = hex::encode(&data[(null_idx + 1)..(null_idx + 21)]);
+        let sha = hex::encode(&data[(null_idx + 1)..len]);
 
         Ok(Self {
             mode,
             path,
             sha,
-            len: null_idx + 21,
+            len,
         })
     }
 }
@@ -272,7 +287,7 @@ impl Tree {
     /// # Returns
     ///
     /// - `Ok(String)` containing the SHA-1 hash of the tree object if successful.
-    /// - `Err(String)` containing an error message if the HEAD is not a commit
+    /// - `Err(Error)` containing an error message if the HEAD is not a commit
     ///   or if it lacks a tree.
     ///
     /// # Errors
@@ -281,7 +296,7 @@ impl Tree {
     ///
     /// - The HEAD reference does not point to a valid commit.
     /// - The commit object does not contain a tree SHA.
-    pub fn get_head_tree_sha(repo: &GitRepository) -> Result<String, String> {
+    pub fn get_head_tree_sha(repo: &GitRepository) -> Result<String, Error> {
         use crate::core::objects::traits::KVLM;
         let head_ref =
             objects::find_object(repo, "HEAD", Some("commit"), true)?;
@@ -293,9 +308,9 @@ impl Tree {
                 .get_key(b"tree")
                 .and_then(|t| t.first())
                 .map(|t| String::from_utf8_lossy(t).to_string())
-                .ok_or_else(|| "HEAD commit has no tree".to_owned())
+                .ok_or_else(|| "HEAD commit has no tree".into())
         } else {
-            Err("HEAD is not a commit".to_owned())
+            Err("HEAD is not a commit".into())
         }
     }
 }
@@ -334,11 +349,26 @@ impl traits::Deserialize for Tree {
     /// # Errors
     /// Returns an `Err` with a descriptive error message if deserialization of
     /// any leaf fails.
-    fn deserialize(data: &[u8]) -> Result<Self, String> {
+    fn deserialize(data: &[u8]) -> Result<Self, Error> {
+        Self::deserialize_with_format(data, ObjectFormat::Sha1)
+    }
+}
+
+impl Tree {
+    /// Deserializes a byte slice into a Tree object, whose leaves name
+    /// objects hashed with the hash algorithm of `format`.
+    ///
+    /// # Errors
+    /// Returns an `Err` with a descriptive error message if deserialization of
+    /// any leaf fails.
+    pub fn deserialize_with_format(
+        data: &[u8],
+        format: ObjectFormat,
+    ) -> Result<Self, Error> {
         let mut pos = 0;
         let mut leaves = vec![];
         while pos < data.len() {
-            let leaf = Leaf::deserialize(&data[pos..])?;
+            let leaf = Leaf::deserialize_with_format(&data[pos..], format)?;
             pos += leaf.len();
             leaves.push(leaf);
         }
@@ -387,9 +417,10 @@ impl traits::Serialize for Tree {
 /// Returns a `Result` containing:
 /// * `Ok(Vec<FileSource>)` - A vector of `FileSource::Blob`s, which contains
 ///   the file path (relative to the tree's root) and the corresponding SHA hash
-///   of tree's version of the file. This function will never return any other
-///   variant of `FileSource`
-/// * `Err(String)` - An error message if any operation fails, such as reading
+///   of tree's version of the file. Submodules are returned as
+///   `FileSource::Gitlink`s, with the SHA hash of their commit. This function
+///   will never return `FileSource::Worktree`
+/// * `Err(Error)` - An error message if any operation fails, such as reading
 ///   the tree object or encountering an unknown object type.
 ///
 /// # Errors
@@ -410,7 +441,7 @@ impl traits::Serialize for Tree {
 /// let tree_sha = "abcdef1234567890"; // Example tree SHA
 /// let files = get_tree_files(&repo, tree_sha)?;
 /// for file in files {
-///     let FileSource::Blob {path, sha} = file else {
+///     let (FileSource::Blob {path, sha} | FileSource::Gitlink {path, sha}) = file else {
 ///         unreachable!("Should not get worktree files from a git tree")
 ///     };
 ///     println!("{}: {}", path, sha);
@@ -421,45 +452,446 @@ impl traits::Serialize for Tree {
 pub fn get_tree_files(
     repo: &GitRepository,
     tree_sha: &str,
-) -> Result<Vec<FileSource>, String> {
-    let mut contents = Vec::new();
-    collect_tree_files(repo, tree_sha, "", &mut contents)?;
-    Ok(contents)
+) -> Result<Vec<FileSource>, Error> {
+    TreeWalker::new(repo, &tree_sha.parse()?)
+        .map(|entry| entry.map(FileSource::from))
+        .collect()
 }
 
-fn collect_tree_files(
-    repo: &GitRepository,
-    tree_sha: &str,
-    prefix: &str,
-    contents: &mut Vec<FileSource>,
-) -> Result<(), String> {
-    let tree_obj = objects::read_object(repo, tree_sha)?;
-
-    if let GitObject::Tree(tree) = tree_obj {
-        for leaf in tree.leaves() {
-            let path = if prefix.is_empty() {
-                leaf.path_as_string()
-            } else {
-                format!("{}/{}", prefix, leaf.path_as_string())
+/// The bits of a mode that tell the type of an entry.
+const TYPE_MASK: u32 = 0o170_000;
+/// The type bits of a tree entry.
+const TREE_TYPE: u32 = 0o040_000;
+/// The type bits of a submodule entry, which points to a commit.
+const GITLINK_TYPE: u32 = 0o160_000;
+
+/// An entry found walking or comparing trees.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeEntry {
+    /// The path of the entry from the root of the walk, with `/` separators
+    pub path: String,
+    /// The mode of the entry, like `0o100644` for a file
+    pub mode: u32,
+    /// The name of the object the entry points to
+    pub oid: Oid,
+}
+
+impl TreeEntry {
+    /// Makes the entry of `leaf`, in the tree at `prefix`.
+    fn new(leaf: &Leaf, prefix: &str) -> Result<Self, Error> {
+        let path = if prefix.is_empty() {
+            leaf.path_as_string()
+        } else {
+            format!("{prefix}/{}", leaf.path_as_string())
+        };
+        let mode = leaf.mode_as_string();
+        let mode = match leaf.obj_type() {
+            Some(_) => u32::from_str_radix(&mode, 8).ok(),
+            None => None,
+        }
+        .ok_or_else(|| {
+            Error::Parse(format!("Unknown object mode {mode} for {path}"))
+        })?;
+        Ok(Self {
+            path,
+            mode,
+            oid: leaf.sha().parse()?,
+        })
+    }
+
+    /// Whether the entry is a tree.
+    #[must_use]
+    pub fn is_tree(&self) -> bool {
+        self.mode & TYPE_MASK == TREE_TYPE
+    }
+
+    /// Whether the entry is a submodule, which points to a commit.
+    #[must_use]
+    pub fn is_gitlink(&self) -> bool {
+        self.mode & TYPE_MASK == GITLINK_TYPE
+    }
+
+    /// The type of the object the entry points to: `tree`, `commit` or
+    /// `blob`.
+    #[must_use]
+    pub fn obj_type(&self) -> &'static str {
+        if self.is_tree() {
+            "tree"
+        } else if self.is_gitlink() {
+            "commit"
+        } else {
+            "blob"
+        }
+    }
+}
+
+impl From<TreeEntry> for FileSource {
+    /// The tree's version of the file of `entry`, which is a
+    /// [`FileSource::Gitlink`] for a submodule, and a [`FileSource::Blob`]
+    /// otherwise.
+    fn from(entry: TreeEntry) -> Self {
+        let is_gitlink = entry.is_gitlink();
+        let (path, sha) = (entry.path, entry.oid.to_string());
+        if is_gitlink {
+            Self::Gitlink { path, sha }
+        } else {
+            Self::Blob { path, sha }
+        }
+    }
+}
+
+/// What a [`TreeWalker::walk`] callback has the walk do next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TreeWalkResult {
+    /// Go on with the next entry
+    Continue,
+    /// Do not walk into the tree just given, and go on after it
+    Skip,
+    /// Stop the walk
+    Abort,
+}
+
+/// An iterator over the entries of a tree and, by default, of the trees in
+/// it, each with its full path.
+///
+/// Entries are listed in tree order, with the entries of a tree right
+/// after the tree, which is how `git ls-tree -r` lists them. Trees are
+/// read as they are walked into.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::Path;
+/// use mini_git::core::GitRepository;
+/// use mini_git::core::objects::tree::TreeWalker;
+///
+/// let repo = GitRepository::new(Path::new("."))?;
+/// let commit = repo.find_commit(&repo.resolve("HEAD")?)?;
+/// for entry in TreeWalker::new(&repo, &commit.tree()?) {
+///     let entry = entry?;
+///     println!("{:06o} {} {}", entry.mode, entry.oid, entry.path);
+/// }
+/// # Ok::<(), String>(())
+/// ```
+pub struct TreeWalker<'r> {
+    repo: &'r GitRepository,
+    recursive: bool,
+    include_trees: bool,
+    // The tree the walk starts from, until it is read
+    root: Option<Oid>,
+    // The entries left of each tree being walked, innermost last
+    stack: Vec<std::vec::IntoIter<TreeEntry>>,
+    // A tree just listed, to be walked into next
+    pending: Option<TreeEntry>,
+}
+
+impl<'r> TreeWalker<'r> {
+    /// Makes a walk of the tree `tree` of `repo`.
+    #[must_use]
+    pub fn new(repo: &'r GitRepository, tree: &Oid) -> Self {
+        Self {
+            repo,
+            recursive: true,
+            include_trees: false,
+            root: Some(*tree),
+            stack: Vec::new(),
+            pending: None,
+        }
+    }
+
+    /// Whether to walk into the trees in the tree, which it does by default.
+    /// If not, only the entries of the tree itself are listed.
+    pub fn recursive(&mut self, recursive: bool) -> &mut Self {
+        self.recursive = recursive;
+        self
+    }
+
+    /// Whether to list the trees walked into, before their entries, like
+    /// `git ls-tree -r -t`. They are not listed by default.
+    pub fn include_trees(&mut self, include_trees: bool) -> &mut Self {
+        self.include_trees = include_trees;
+        self
+    }
+
+    /// Does not walk into the tree listed last, if it was one.
+    pub fn skip_subtree(&mut self) {
+        self.pending = None;
+    }
+
+    /// Calls `callback` with each entry listed, where trees are listed
+    /// before their entries, and skipped if it returns
+    /// [`TreeWalkResult::Skip`].
+    ///
+    /// # Errors
+    ///
+    /// If a tree cannot be read.
+    pub fn walk<F>(&mut self, mut callback: F) -> Result<(), Error>
+    where
+        F: FnMut(&TreeEntry) -> TreeWalkResult,
+    {
+        self.include_trees(true);
+        while let Some(entry) = self.next() {
+            match callback(&entry?) {
+                TreeWalkResult::Continue => {}
+                TreeWalkResult::Skip => self.skip_subtree(),
+                TreeWalkResult::Abort => break,
+            }
+        }
+        Ok(())
+    }
+
+    fn advance(&mut self) -> Result<Option<TreeEntry>, Error> {
+        if let Some(root) = self.root.take() {
+            self.stack
+                .push(read_entries(self.repo, &root, "")?.into_iter());
+        }
+        if let Some(tree) = self.pending.take() {
+            self.stack.push(
+                read_entries(self.repo, &tree.oid, &tree.path)?.into_iter(),
+            );
+        }
+
+        while let Some(entries) = self.stack.last_mut() {
+            let Some(entry) = entries.next() else {
+                self.stack.pop();
+                continue;
             };
+            if !self.recursive || !entry.is_tree() {
+                return Ok(Some(entry));
+            }
+            if self.include_trees {
+                self.pending = Some(entry.clone());
+                return Ok(Some(entry));
+            }
+            self.stack.push(
+                read_entries(self.repo, &entry.oid, &entry.path)?.into_iter(),
+            );
+        }
+        Ok(None)
+    }
+}
 
-            match leaf.obj_type() {
-                Some("blob") => {
-                    contents.push(FileSource::Blob {
-                        path,
-                        sha: leaf.sha().to_string(),
-                    });
-                }
-                Some("tree") => {
-                    collect_tree_files(repo, leaf.sha(), &path, contents)?;
+impl Iterator for TreeWalker<'_> {
+    type Item = Result<TreeEntry, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = self.advance();
+        if next.is_err() {
+            self.stack.clear();
+            self.pending = None;
+        }
+        next.transpose()
+    }
+}
+
+/// How a path differs between two trees.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delta {
+    /// The path is only in the new tree
+    Added,
+    /// The path is only in the old tree
+    Deleted,
+    /// The path has other contents or permissions
+    Modified,
+    /// The path changed between a file, a symbolic link and a submodule
+    TypeChanged,
+}
+
+impl Delta {
+    /// The letter git shows for the change, like `M` for modified.
+    #[must_use]
+    pub fn status(self) -> char {
+        match self {
+            Self::Added => 'A',
+            Self::Deleted => 'D',
+            Self::Modified => 'M',
+            Self::TypeChanged => 'T',
+        }
+    }
+}
+
+/// A path that differs between two trees, with its entry in either.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeDelta {
+    /// The path from the root of the trees
+    pub path: String,
+    /// The entry in the old tree, if the path is in it
+    pub old: Option<TreeEntry>,
+    /// The entry in the new tree, if the path is in it
+    pub new: Option<TreeEntry>,
+}
+
+impl TreeDelta {
+    /// How the path differs.
+    #[must_use]
+    pub fn delta(&self) -> Delta {
+        match (&self.old, &self.new) {
+            (None, _) => Delta::Added,
+            (_, None) => Delta::Deleted,
+            (Some(old), Some(new))
+                if old.mode & TYPE_MASK != new.mode & TYPE_MASK =>
+            {
+                Delta::TypeChanged
+            }
+            _ => Delta::Modified,
+        }
+    }
+}
+
+/// Lists the files that differ between the trees `old` and `new`, in path
+/// order, where a `None` tree is the empty tree.
+///
+/// Trees are compared entry by entry, and subtrees with the same name in
+/// both are not read, so the cost is in what changed rather than in the size
+/// of the trees. Submodules are compared as entries, and not walked into.
+///
+/// # Errors
+///
+/// If a tree cannot be read.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::Path;
+/// use mini_git::core::GitRepository;
+/// use mini_git::core::objects::tree::diff_trees;
+///
+/// let repo = GitRepository::new(Path::new("."))?;
+/// let commit = repo.find_commit(&repo.resolve("HEAD")?)?;
+/// let parent = repo.find_commit(&commit.parents()?[0])?;
+/// for delta in diff_trees(&repo, Some(&parent.tree()?), Some(&commit.tree()?))? {
+///     println!("{}\t{}", delta.delta().status(), delta.path);
+/// }
+/// # Ok::<(), String>(())
+/// ```
+pub fn diff_trees(
+    repo: &GitRepository,
+    old: Option<&Oid>,
+    new: Option<&Oid>,
+) -> Result<Vec<TreeDelta>, Error> {
+    let mut deltas = Vec::new();
+    diff_tree_entries(repo, old, new, "", true, &mut deltas)?;
+    Ok(deltas)
+}
+
+/// Lists the entries that differ between the trees `old` and `new`, like
+/// [`diff_trees`], but without walking into subtrees, so a changed
+/// directory is a single modified tree.
+///
+/// # Errors
+///
+/// If a tree cannot be read.
+pub fn diff_trees_shallow(
+    repo: &GitRepository,
+    old: Option<&Oid>,
+    new: Option<&Oid>,
+) -> Result<Vec<TreeDelta>, Error> {
+    let mut deltas = Vec::new();
+    diff_tree_entries(repo, old, new, "", false, &mut deltas)?;
+    Ok(deltas)
+}
+
+fn diff_tree_entries(
+    repo: &GitRepository,
+    old: Option<&Oid>,
+    new: Option<&Oid>,
+    prefix: &str,
+    recursive: bool,
+    deltas: &mut Vec<TreeDelta>,
+) -> Result<(), Error> {
+    let read = |tree: Option<&Oid>| {
+        tree.map_or_else(|| Ok(Vec::new()), |oid| read_tree(repo, oid))
+    };
+    let (old_tree, new_tree) = (read(old)?, read(new)?);
+    let (mut old_leaves, mut new_leaves) = (old_tree.iter(), new_tree.iter());
+    let (mut old_leaf, mut new_leaf) = (old_leaves.next(), new_leaves.next());
+
+    // Both trees are sorted, so entries with the same name meet, where a
+    // tree and a file of the same name do not
+    loop {
+        let (old_entry, new_entry) = match (old_leaf, new_leaf) {
+            (None, None) => break,
+            (Some(old), Some(new)) if old.cmp_path() == new.cmp_path() => {
+                old_leaf = old_leaves.next();
+                new_leaf = new_leaves.next();
+                if old.mode == new.mode && old.sha == new.sha {
+                    continue;
                 }
-                _ => return Err(format!("Unknown object type for {path}")),
+                (Some(old), Some(new))
+            }
+            (Some(old), new)
+                if new.is_none_or(|new| old.cmp_path() < new.cmp_path()) =>
+            {
+                old_leaf = old_leaves.next();
+                (Some(old), None)
             }
+            (_, new) => {
+                new_leaf = new_leaves.next();
+                (None, new)
+            }
+        };
+        let old_entry = old_entry
+            .map(|leaf| TreeEntry::new(leaf, prefix))
+            .transpose()?;
+        let new_entry = new_entry
+            .map(|leaf| TreeEntry::new(leaf, prefix))
+            .transpose()?;
+
+        let subtree = |entry: &Option<TreeEntry>| {
+            entry
+                .as_ref()
+                .filter(|entry| entry.is_tree())
+                .map(|e| e.oid)
+        };
+        let path = old_entry
+            .as_ref()
+            .or(new_entry.as_ref())
+            .map(|entry| entry.path.clone())
+            .unwrap_or_default();
+        let (old_subtree, new_subtree) =
+            (subtree(&old_entry), subtree(&new_entry));
+        if recursive && (old_subtree.is_some() || new_subtree.is_some()) {
+            diff_tree_entries(
+                repo,
+                old_subtree.as_ref(),
+                new_subtree.as_ref(),
+                &path,
+                recursive,
+                deltas,
+            )?;
+            continue;
         }
+        deltas.push(TreeDelta {
+            path,
+            old: old_entry,
+            new: new_entry,
+        });
     }
     Ok(())
 }
 
+/// The leaves of the tree `oid`, sorted as git sorts them.
+fn read_tree(repo: &GitRepository, oid: &Oid) -> Result<Vec<Leaf>, Error> {
+    let GitObject::Tree(tree) = objects::read_object(repo, oid)? else {
+        return Err(format!("{oid} is not a tree").into());
+    };
+    let mut leaves = tree.leaves;
+    leaves.sort();
+    Ok(leaves)
+}
+
+/// The entries of the tree `oid`, which is at `prefix`.
+fn read_entries(
+    repo: &GitRepository,
+    oid: &Oid,
+    prefix: &str,
+) -> Result<Vec<TreeEntry>, Error> {
+    read_tree(repo, oid)?
+        .iter()
+        .map(|leaf| TreeEntry::new(leaf, prefix))
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use self::traits::{Deserialize, Serialize};
@@ -1,6 +1,13 @@
-use std::path::Path;
+use std::collections::{BTreeSet, VecDeque};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc};
 
-use crate::core::{objects::FileSource, GitRepository};
+use crate::core::objects::{resolve_ref, FileSource};
+use crate::core::{global_config, Error, GitRepository};
+use crate::utils::gitattributes::GitAttributes;
+use crate::utils::gitignore::{GitignoreSet, GitignoreStack, IGNORE_FILE};
+use crate::utils::threadpool::ThreadPool;
 
 /// Retrieves a list of all file paths in the worktree of a given Git repository,
 /// optionally starting from a specified subdirectory.
@@ -8,19 +15,24 @@ use crate::core::{objects::FileSource, GitRepository};
 /// This function starts from either the repository's worktree root or the specified
 /// `top` path and recursively collects all file paths within the directory structure,
 /// excluding any `.git` directory. It returns the file paths relative to the `top`
-/// directory or to the worktree root if no `top` is specified.
+/// directory or to the worktree root if no `top` is specified, sorted by
+/// path. Directories are scanned in parallel, on the shared thread pool.
+///
+/// Nested repositories, such as submodules, are not descended into. Each is
+/// returned as a single [`FileSource::Gitlink`] for its checked out commit, or
+/// left out if nothing is checked out.
 ///
 /// # Arguments
 ///
 /// * `repo` - A reference to the [`GitRepository`] from which to retrieve the worktree paths.
 /// * `top` - An optional path within the worktree to start collecting files from.
-///           If `None`, it defaults to the worktree root.
+///   If `None`, it defaults to the worktree root.
 ///
 /// # Returns
 ///
 /// A `Result` with:
 /// * `Ok(Vec<String>)` - A list of file paths relative to the specified `top` or worktree root.
-/// * `Err(String)` - An error message if the function fails to read directories or retrieve paths.
+/// * `Err(Error)` - An error message if the function fails to read directories or retrieve paths.
 ///
 /// # Errors
 ///
@@ -45,39 +57,101 @@ use crate::core::{objects::FileSource, GitRepository};
 pub fn get_worktree_files(
     repo: &GitRepository,
     top: Option<&Path>,
-) -> Result<Vec<FileSource>, String> {
-    let mut paths = Vec::new();
+) -> Result<Vec<FileSource>, Error> {
     let work_tree = repo.worktree();
     let base = top
         .map(Path::canonicalize)
         .transpose()
         .map_err(|x| match top {
-            Some(top) => format!("Failed to resolve path {top:?} {x}"),
+            Some(top) => {
+                format!("Failed to resolve path {} {x}", top.display())
+            }
             None => unreachable!("Map would not work if path was none"),
         })?
         .unwrap_or(work_tree.to_path_buf());
-    collect_worktree_files(&base, &base, &mut paths)?;
+    let root = base.clone();
+    let mut paths = walk_dirs(root, ThreadPool::shared(), move |dir| {
+        scan_worktree_dir(&base, &dir)
+    })?;
+    paths.sort_by_cached_key(FileSource::path);
     Ok(paths)
 }
 
-fn collect_worktree_files(
+/// What a job of [`walk_dirs`] found in its directory: the entries to
+/// collect, and the subdirectories to scan next.
+type Scanned<T, D> = Result<(Vec<T>, Vec<D>), Error>;
+
+/// Collects the entries that `scan` finds in the directory `root` and in
+/// the subdirectories it lists, scanning each directory as a job on the
+/// workers of `pool`.
+///
+/// The subdirectories a job lists are queued as new jobs as soon as it is
+/// done. All the workers take their jobs from the same queue, so idle
+/// workers take over the directories found by busy ones. The entries come
+/// out in no particular order.
+///
+/// This waits for the workers, so it must not run on one of them.
+pub(crate) fn walk_dirs<D, T, F>(
+    root: D,
+    pool: &ThreadPool,
+    scan: F,
+) -> Result<Vec<T>, Error>
+where
+    D: Send + 'static,
+    T: Send + 'static,
+    F: Fn(D) -> Scanned<T, D> + Send + Sync + 'static,
+{
+    let scan = Arc::new(scan);
+    let (sender, receiver) = mpsc::channel::<Scanned<T, D>>();
+    let queue = |dir: D| {
+        let (scan, sender) = (Arc::clone(&scan), sender.clone());
+        pool.execute(move || {
+            let scanned = panic::catch_unwind(AssertUnwindSafe(|| scan(dir)))
+                .unwrap_or_else(|_| {
+                    Err("A thread panicked during execution".to_owned().into())
+                });
+            // The receiver is gone if the walk failed already
+            let _ = sender.send(scanned);
+        });
+    };
+
+    let mut entries = Vec::new();
+    queue(root);
+    let mut pending = 1;
+    while pending > 0 {
+        let Ok(scanned) = receiver.recv() else {
+            return Err("A worker exited without a result".to_owned().into());
+        };
+        pending -= 1;
+        let (found, subdirs) = scanned?;
+        entries.extend(found);
+        pending += subdirs.len();
+        subdirs.into_iter().for_each(queue);
+    }
+    Ok(entries)
+}
+
+/// Lists the directory `current` as the files and nested repositories in
+/// it, relative to `base`, and the subdirectories to scan next.
+fn scan_worktree_dir(
     base: &Path,
     current: &Path,
-    paths: &mut Vec<FileSource>,
-) -> Result<(), String> {
+) -> Result<(Vec<FileSource>, Vec<PathBuf>), Error> {
+    let mut paths = Vec::new();
+    let mut subdirs = Vec::new();
     for entry in std::fs::read_dir(current)
         .map_err(|e| format!("Failed to read directory: {e}"))?
     {
         let entry = entry.map_err(|e| format!("Failed to read entry: {e}"))?;
         let path = entry.path();
 
-        if path.is_dir()
-            && path
-                .strip_prefix(base)
-                .map_err(|e| format!("Failed to get relative path: {e}"))?
-                .file_name()
-                .and_then(|n| n.to_str())
-                .is_some_and(|n| n == ".git")
+        // A `.git` file points to a separate git directory
+        if path
+            .strip_prefix(base)
+            .map_err(|e| format!("Failed to get relative path: {e}"))?
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n == ".git")
         {
             continue;
         }
@@ -89,9 +163,162 @@ fn collect_worktree_files(
             paths.push(FileSource::Worktree {
                 path: crate::utils::path::to_posix_path(relative)?,
             });
+        } else if path.join(".git").exists() {
+            // A nested repository, such as a submodule, is tracked by its
+            // checked out commit rather than its files
+            let relative = path
+                .strip_prefix(base)
+                .map_err(|_| "Failed to get relative path".to_owned())?;
+            if let Some(sha) = submodule_head(&path) {
+                paths.push(FileSource::Gitlink {
+                    path: crate::utils::path::to_posix_path(relative)?,
+                    sha,
+                });
+            }
         } else if path.is_dir() {
-            collect_worktree_files(base, &path, paths)?;
+            subdirs.push(path);
+        }
+    }
+    Ok((paths, subdirs))
+}
+
+/// The commit checked out in the repository at `path`, if any.
+fn submodule_head(path: &Path) -> Option<String> {
+    let repo = GitRepository::new(path).ok()?;
+    let oid = resolve_ref(&repo, "HEAD").ok().flatten()?;
+    Some(oid.to_string())
+}
+
+/// Collects the ignore rules that apply to the worktree of a repository.
+///
+/// Rules are gathered in increasing order of precedence:
+/// 1. The file named by `core.excludesFile`, or `$XDG_CONFIG_HOME/git/ignore`
+///    if unset.
+/// 2. `.git/info/exclude`.
+/// 3. Every `.gitignore` in the worktree, outermost first. Directories that
+///    are themselves ignored are not searched.
+///
+/// # Errors
+///
+/// Returns an error if a directory or ignore file cannot be read.
+pub fn get_ignore_rules(repo: &GitRepository) -> Result<GitignoreSet, Error> {
+    let mut rules = get_exclude_rules(repo)?;
+
+    // Walk breadth-first so parent rules are known before visiting children
+    let mut queue = VecDeque::from([String::new()]);
+    while let Some(dir) = queue.pop_front() {
+        let abs_dir = repo.worktree().join(&dir);
+        rules.add_file(&dir, &abs_dir.join(IGNORE_FILE))?;
+
+        let mut subdirs = vec![];
+        for entry in std::fs::read_dir(&abs_dir)
+            .map_err(|e| format!("Failed to read directory: {e}"))?
+        {
+            let entry =
+                entry.map_err(|e| format!("Failed to read entry: {e}"))?;
+            let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !is_dir || name == ".git" {
+                continue;
+            }
+            let path = if dir.is_empty() {
+                name
+            } else {
+                format!("{dir}/{name}")
+            };
+            if rules.should_descend(&path) {
+                subdirs.push(path);
+            }
         }
+        subdirs.sort();
+        queue.extend(subdirs);
+    }
+
+    Ok(rules)
+}
+
+/// Collects the ignore rules for a walk of the worktree of a repository,
+/// which reads the `.gitignore` of each directory as it enters it.
+///
+/// The rules of `.gitignore` files take precedence over those of
+/// `.git/info/exclude`, which take precedence over those of the file named
+/// by `core.excludesFile`.
+///
+/// # Errors
+///
+/// Returns an error if an ignore file outside the worktree cannot be read.
+pub fn get_ignore_stack(repo: &GitRepository) -> Result<GitignoreStack, Error> {
+    Ok(GitignoreStack::new(
+        repo.worktree(),
+        get_exclude_rules(repo)?,
+    ))
+}
+
+/// The ignore rules of the repository and the user, which apply to the
+/// whole worktree.
+fn get_exclude_rules(repo: &GitRepository) -> Result<GitignoreSet, Error> {
+    let mut rules = GitignoreSet::new();
+    if let Some(excludes_file) = global_excludes_file(repo) {
+        rules.add_file("", &excludes_file)?;
+    }
+    rules.add_file("", &repo.common_dir().join("info").join("exclude"))?;
+    Ok(rules)
+}
+
+/// Collects the attribute rules that apply to the given paths.
+///
+/// Rules are gathered in increasing order of precedence:
+/// 1. The `.gitattributes` in the worktree root, and in every directory
+///    containing one of `paths`, outermost first.
+/// 2. `.git/info/attributes`.
+///
+/// # Errors
+///
+/// Returns an error if an attributes file cannot be read.
+pub fn get_attributes(
+    repo: &GitRepository,
+    paths: &[String],
+) -> Result<GitAttributes, Error> {
+    let mut attributes = GitAttributes::new();
+
+    // Sorting puts every directory before the directories inside it
+    let mut dirs = BTreeSet::from([String::new()]);
+    for path in paths {
+        dirs.extend(path.match_indices('/').map(|(i, _)| path[..i].to_owned()));
+    }
+    for dir in dirs {
+        let file = repo.worktree().join(&dir).join(".gitattributes");
+        attributes.add_file(&dir, &file)?;
+    }
+    attributes
+        .add_file("", &repo.common_dir().join("info").join("attributes"))?;
+
+    Ok(attributes)
+}
+
+/// The file of ignore rules for every repository of the user, named by
+/// `core.excludesFile` in the repository's or the user's global config, or
+/// `$XDG_CONFIG_HOME/git/ignore` if unset. A relative path is relative to
+/// the worktree root.
+#[must_use]
+pub fn global_excludes_file(repo: &GitRepository) -> Option<PathBuf> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let configured = repo
+        .config()
+        .get("core")
+        .and_then(|core| core.get("excludesFile"))
+        .map(String::from)
+        .or_else(|| global_config("core", "excludesFile"));
+
+    match configured {
+        Some(file) => match file.strip_prefix("~/") {
+            Some(rest) => home.map(|home| home.join(rest)),
+            None => Some(repo.worktree().join(file)),
+        },
+        None => std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| home.map(|home| home.join(".config")))
+            .map(|config| config.join("git").join("ignore")),
     }
-    Ok(())
 }
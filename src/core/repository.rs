@@ -1,21 +1,220 @@
 #![forbid(clippy::complexity)]
 
 use std::fs;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 
+use crate::core::objects::store::{ObjectCache, ObjectIdIndex};
+use crate::core::{config, Error};
 use crate::utils::configparser::ConfigParser;
 use crate::utils::path;
+use crate::utils::sha1::SHA1;
+use crate::utils::sha256::SHA256;
+
+/// The environment variable naming the git directory to use, instead of
+/// finding it from the current directory. The `--git-dir` option sets it.
+pub const GIT_DIR_ENVIRONMENT: &str = "GIT_DIR";
+
+/// The environment variable naming the worktree to use with the git
+/// directory, instead of the current directory or the one it was found in.
+pub const WORK_TREE_ENVIRONMENT: &str = "GIT_WORK_TREE";
+
+/// The environment variable naming the directory objects are stored in,
+/// instead of the `objects` directory of the git directory.
+pub const OBJECT_DIRECTORY_ENVIRONMENT: &str = "GIT_OBJECT_DIRECTORY";
+
+/// The environment variable listing the directories the search for a
+/// repository does not go up into, separated like `PATH`.
+pub const CEILING_DIRECTORIES_ENVIRONMENT: &str = "GIT_CEILING_DIRECTORIES";
+
+/// The branch `HEAD` points to in a new repository, unless configured.
+pub const DEFAULT_BRANCH: &str = "main";
+
+/// The hash algorithm that names the objects of a repository.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ObjectFormat {
+    /// 40 hex digit object names, the format of repositories without an
+    /// `extensions.objectFormat`
+    #[default]
+    Sha1,
+    /// 64 hex digit object names
+    Sha256,
+}
+
+impl ObjectFormat {
+    /// Every supported format, the default first.
+    pub const ALL: [Self; 2] = [Self::Sha1, Self::Sha256];
+
+    /// The name of the format, as used by `extensions.objectFormat`.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Sha1 => "sha1",
+            Self::Sha256 => "sha256",
+        }
+    }
+
+    /// The size in bytes of the object names of the format.
+    #[must_use]
+    pub fn hash_len(self) -> usize {
+        match self {
+            Self::Sha1 => 20,
+            Self::Sha256 => 32,
+        }
+    }
+
+    /// The number of hex digits of the object names of the format.
+    #[must_use]
+    pub fn hex_len(self) -> usize {
+        self.hash_len() * 2
+    }
+
+    /// The object name of all zeros, which names no object.
+    #[must_use]
+    pub fn null_id(self) -> String {
+        "0".repeat(self.hex_len())
+    }
+
+    /// Creates a hasher for the hash algorithm of the format.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mini_git::core::ObjectFormat;
+    ///
+    /// let mut hasher = ObjectFormat::Sha256.hasher();
+    /// let _ = hasher.update(b"blob 0\0");
+    /// assert_eq!(hasher.hex_digest().len(), 64);
+    /// ```
+    #[must_use]
+    pub fn hasher(self) -> ObjectHasher {
+        match self {
+            Self::Sha1 => ObjectHasher::Sha1(SHA1::new()),
+            Self::Sha256 => ObjectHasher::Sha256(SHA256::new()),
+        }
+    }
+
+    /// Hashes `data` in one step with the hash algorithm of the format.
+    #[must_use]
+    pub fn hash(self, data: &[u8]) -> Vec<u8> {
+        self.hasher().update(data).finalize()
+    }
+}
+
+/// A hasher for the hash algorithm of an [`ObjectFormat`].
+#[derive(Clone)]
+pub enum ObjectHasher {
+    /// A SHA-1 hasher
+    Sha1(SHA1),
+    /// A SHA-256 hasher
+    Sha256(SHA256),
+}
+
+impl ObjectHasher {
+    /// Checks what is hashed for being part of a collision attack, for the
+    /// hash algorithms that have known attacks.
+    #[must_use]
+    pub fn with_collision_detection(self) -> Self {
+        match self {
+            Self::Sha1(hasher) => Self::Sha1(hasher.with_collision_detection()),
+            Self::Sha256(_) => self,
+        }
+    }
+
+    /// Updates the hasher with `data`.
+    #[must_use]
+    pub fn update(&mut self, data: &[u8]) -> &mut Self {
+        match self {
+            Self::Sha1(hasher) => {
+                let _ = hasher.update(data);
+            }
+            Self::Sha256(hasher) => {
+                let _ = hasher.update(data);
+            }
+        }
+        self
+    }
+
+    /// Returns the hash of the data given so far.
+    pub fn finalize(&mut self) -> Vec<u8> {
+        match self {
+            Self::Sha1(hasher) => hasher.finalize().to_vec(),
+            Self::Sha256(hasher) => hasher.finalize().to_vec(),
+        }
+    }
+
+    /// Returns the hash of the data given so far, as hex digits.
+    pub fn hex_digest(&mut self) -> String {
+        match self {
+            Self::Sha1(hasher) => hasher.hex_digest(),
+            Self::Sha256(hasher) => hasher.hex_digest(),
+        }
+    }
+
+    /// Returns whether the data given so far is part of a collision attack,
+    /// see [`ObjectHasher::with_collision_detection`].
+    #[must_use]
+    pub fn found_collision(&self) -> bool {
+        match self {
+            Self::Sha1(hasher) => hasher.found_collision(),
+            Self::Sha256(_) => false,
+        }
+    }
+}
+
+impl std::str::FromStr for ObjectFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "sha1" => Self::Sha1,
+            "sha256" => Self::Sha256,
+            _ => return Err(format!("unknown object format '{s}'")),
+        })
+    }
+}
 
 /// A struct representing a Git repository.
-#[allow(clippy::module_name_repetitions, dead_code)]
-#[derive(Debug)]
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Clone)]
 pub struct GitRepository {
     /// The working tree of the repository.
     worktree: PathBuf,
     /// The `.git` directory of the repository.
     gitdir: PathBuf,
+    /// The directory with what the worktrees of the repository share, which
+    /// is the git directory unless this is an added worktree.
+    commondir: PathBuf,
     /// The configuration of the repository.
     config: ConfigParser,
+    /// Whether the repository has no working tree.
+    bare: bool,
+    /// Where objects are stored, if not in the `objects` directory of the
+    /// git directory.
+    objects: Option<PathBuf>,
+    /// The objects recently read from the repository.
+    object_cache: ObjectCache,
+    /// The sorted names of the objects of the repository.
+    object_ids: ObjectIdIndex,
+}
+
+/// Options for initializing a new repository with
+/// [`GitRepository::create_with`].
+#[derive(Debug, Clone, Default)]
+pub struct InitOptions {
+    /// Create a bare repository, without a working tree.
+    pub bare: bool,
+    /// The branch `HEAD` points to, [`DEFAULT_BRANCH`] if not given.
+    pub initial_branch: Option<String>,
+    /// Where to create the git directory, instead of `.git` in the working
+    /// tree. The working tree gets a `.git` file pointing to it.
+    pub separate_git_dir: Option<PathBuf>,
+    /// A directory whose files are copied into the new git directory, such
+    /// as hooks and `info/exclude`. Ignored if it does not exist.
+    pub template: Option<PathBuf>,
+    /// The hash algorithm of the new repository, [`ObjectFormat::Sha1`] if
+    /// not given.
+    pub object_format: Option<ObjectFormat>,
 }
 
 impl GitRepository {
@@ -24,10 +223,12 @@ impl GitRepository {
     /// # Arguments
     ///
     /// * `path` - A reference to the path where the repository is located.
+    ///   For a bare repository, this is its git directory. If `path` has a
+    ///   `.git` file, the git directory is the one it points to.
     ///
     /// # Errors
     ///
-    /// Returns a `String` error if the repository could not be created.
+    /// Returns an [`Error`] if the repository could not be created.
     ///
     /// # Examples
     ///
@@ -37,12 +238,17 @@ impl GitRepository {
     /// let repo = GitRepository::new(Path::new("/path/to/repo"))?;
     /// # Ok::<(), String>(())
     /// ```
-    pub fn new(path: &Path) -> Result<Self, String> {
-        Self::new_repo(path, false)
+    pub fn new(path: &Path) -> Result<Self, Error> {
+        let bare =
+            path::resolve_gitdir(path).is_none() && path::is_bare_repo(path);
+        Self::new_repo(path, false, bare)
     }
 
     /// Returns the working tree path of the repository.
     ///
+    /// A bare repository has no working tree, so this is its git directory.
+    /// Use [`GitRepository::require_worktree`] for operations that need one.
+    ///
     /// # Examples
     ///
     /// ```no_run
@@ -75,17 +281,147 @@ impl GitRepository {
         &self.gitdir
     }
 
+    /// Returns the common directory of the repository, which holds what its
+    /// worktrees share, such as objects, refs and the configuration. This is
+    /// the git directory, unless the worktree was added to a repository.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use mini_git::core::GitRepository;
+    /// let repo = GitRepository::new(Path::new("."))?;
+    /// let packed_refs = repo.common_dir().join("packed-refs");
+    /// println!("{packed_refs:?}");
+    /// # Ok::<(), String>(())
+    /// ```
+    #[must_use]
+    pub fn common_dir(&self) -> &Path {
+        &self.commondir
+    }
+
+    /// Returns the directory the ref `name` is stored under. `HEAD`, and the
+    /// other refs outside of `refs/`, belong to each worktree, along with
+    /// the refs under `refs/bisect/`, `refs/worktree/` and `refs/rewritten/`.
+    /// The rest are in the common directory.
+    #[must_use]
+    pub fn ref_dir(&self, name: &str) -> &Path {
+        const PER_WORKTREE: &[&str] =
+            &["refs/bisect/", "refs/worktree/", "refs/rewritten/"];
+        if !name.starts_with("refs/")
+            || PER_WORKTREE.iter().any(|prefix| name.starts_with(prefix))
+        {
+            &self.gitdir
+        } else {
+            &self.commondir
+        }
+    }
+
+    /// Returns the directory objects are stored in, the `objects` directory
+    /// of the common directory unless [`OBJECT_DIRECTORY_ENVIRONMENT`] names
+    /// another one.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use mini_git::core::GitRepository;
+    /// let repo = GitRepository::new(Path::new("."))?;
+    /// let pack_dir = repo.objects_dir().join("pack");
+    /// println!("{pack_dir:?}");
+    /// # Ok::<(), String>(())
+    /// ```
+    #[must_use]
+    pub fn objects_dir(&self) -> PathBuf {
+        self.objects
+            .clone()
+            .unwrap_or_else(|| self.commondir.join("objects"))
+    }
+
+    /// Returns the hash algorithm that names the objects of the repository,
+    /// set by `extensions.objectFormat`.
+    #[must_use]
+    pub fn object_format(&self) -> ObjectFormat {
+        self.config
+            .get("extensions")
+            .and_then(|ext| ext.get("objectformat"))
+            .and_then(|format| format.parse().ok())
+            .unwrap_or_default()
+    }
+
+    /// Returns the cache of the objects recently read from the repository,
+    /// which is shared by its clones.
+    #[must_use]
+    pub fn object_cache(&self) -> &ObjectCache {
+        &self.object_cache
+    }
+
+    /// Returns the index of the names of the objects of the repository, used
+    /// to look objects up by prefix, which is shared by its clones.
+    #[must_use]
+    pub fn object_ids(&self) -> &ObjectIdIndex {
+        &self.object_ids
+    }
+
+    /// Returns the configuration of the repository.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use mini_git::core::GitRepository;
+    /// let repo = GitRepository::new(Path::new("."))?;
+    /// let bare = repo.config().get("core").and_then(|core| core.get_bool("bare"));
+    /// println!("{bare:?}");
+    /// # Ok::<(), String>(())
+    /// ```
+    #[must_use]
+    pub fn config(&self) -> &ConfigParser {
+        &self.config
+    }
+
+    /// Returns whether the repository is bare, i.e. has no working tree.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use mini_git::core::GitRepository;
+    /// let repo = GitRepository::create_bare(Path::new("/path/to/repo.git"))?;
+    /// assert!(repo.is_bare());
+    /// # Ok::<(), String>(())
+    /// ```
+    #[must_use]
+    pub fn is_bare(&self) -> bool {
+        self.bare
+    }
+
+    /// Returns the working tree path of the repository, for operations that
+    /// need one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`Error`] if the repository is bare.
+    pub fn require_worktree(&self) -> Result<&Path, Error> {
+        if self.bare {
+            Err("this operation must be run in a work tree".into())
+        } else {
+            Ok(&self.worktree)
+        }
+    }
+
     /// Creates a new repository object at the specified path.
     ///
     /// # Arguments
     ///
     /// * `path` - A reference to the path where the repository should be created.
     /// * `forced` - A boolean indicating whether the creation should be forced.
+    /// * `bare` - Whether `path` is the git directory of a bare repository.
     ///
     /// # Errors
     ///
-    /// Returns a `String` error if the repository could not be created.
-    fn new_repo(path: &Path, forced: bool) -> Result<Self, String> {
+    /// Returns an [`Error`] if the repository could not be created.
+    fn new_repo(path: &Path, forced: bool, bare: bool) -> Result<Self, Error> {
         let not_forced = !forced;
 
         let path = if path.is_relative() && !path.starts_with(".") {
@@ -95,11 +431,15 @@ impl GitRepository {
         };
 
         let Some(parent) = path.parent() else {
-            return Err(format!("{:?} is not a valid path!", path.as_os_str()));
+            return Err(
+                format!("{:?} is not a valid path!", path.as_os_str()).into()
+            );
         };
 
         let Ok(parent) = parent.canonicalize() else {
-            return Err(format!("{:?} is not a valid path!", path.as_os_str()));
+            return Err(
+                format!("{:?} is not a valid path!", path.as_os_str()).into()
+            );
         };
 
         let worktree = parent.join(
@@ -107,47 +447,134 @@ impl GitRepository {
                 .expect("Should be a valid path unless it ends with .."),
         );
 
-        let gitdir = path.join(".git");
+        let gitdir = if bare {
+            path.to_path_buf()
+        } else {
+            path::resolve_gitdir(path).unwrap_or_else(|| path.join(".git"))
+        };
 
         if not_forced && !gitdir.is_dir() {
-            return Err(format!("not a git repository {:?}", path.as_os_str()));
+            return Err(
+                format!("not a git repository {:?}", path.as_os_str()).into()
+            );
         }
 
-        let config;
-        let config_file = path::repo_file(&gitdir, &["config"], false)?;
-        if let Some(config_file) = config_file {
-            config = ConfigParser::from(config_file.as_path());
-        } else if not_forced {
-            return Err("missing configuration file!".to_string());
-        } else {
-            config = ConfigParser::default();
-        }
+        let commondir = path::common_dir(&gitdir);
+        let config = Self::load_config(&commondir, &gitdir, forced)?;
 
-        if not_forced {
-            let Some(core) = config.get("core") else {
-                return Err("section \"core\" is missing!".to_string());
-            };
-            match core.get_int("repositoryformatversion") {
-                Some(0) => {}
-                Some(version) => {
-                    return Err(format!(
-                        "unsupported repositoryformatversion {version}"
-                    ))
-                }
-                None => {
-                    return Err("key \"repositoryformatversion\" is missing"
-                        .to_string())
-                }
-            }
+        Ok(Self {
+            worktree,
+            gitdir,
+            commondir,
+            config,
+            bare,
+            objects: None,
+            object_cache: ObjectCache::default(),
+            object_ids: ObjectIdIndex::default(),
+        })
+    }
+
+    /// Opens the repository with its git directory at `gitdir`, rather than
+    /// finding it from a working tree. The working tree is `worktree`,
+    /// unless the repository is bare.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`Error`] if `gitdir` is not a git directory, or its
+    /// configuration is invalid.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use mini_git::core::GitRepository;
+    /// let repo = GitRepository::with_gitdir(
+    ///     Path::new("/path/to/store.git"),
+    ///     Path::new("/path/to/work"),
+    /// )?;
+    /// # Ok::<(), String>(())
+    /// ```
+    pub fn with_gitdir(gitdir: &Path, worktree: &Path) -> Result<Self, Error> {
+        let not_a_repo =
+            || format!("not a git repository: {:?}", gitdir.as_os_str());
+        if !path::is_gitdir(gitdir) {
+            return Err(not_a_repo().into());
         }
+        let gitdir = gitdir.canonicalize().map_err(|_| not_a_repo())?;
+        let commondir = path::common_dir(&gitdir);
+        let config = Self::load_config(&commondir, &gitdir, false)?;
+
+        let bare = config
+            .get("core")
+            .and_then(|core| core.get_bool("bare"))
+            .unwrap_or(false);
+        let worktree = if bare {
+            gitdir.clone()
+        } else {
+            worktree.canonicalize().map_err(|_| {
+                format!("{:?} is not a valid path!", worktree.as_os_str())
+            })?
+        };
 
         Ok(Self {
             worktree,
             gitdir,
+            commondir,
             config,
+            bare,
+            objects: None,
+            object_cache: ObjectCache::default(),
+            object_ids: ObjectIdIndex::default(),
         })
     }
 
+    /// Loads the configuration of the repository with its git directory at
+    /// `gitdir`, from its common directory `commondir`, and checks that it
+    /// can be used.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`Error`] if the configuration is missing or invalid,
+    /// unless `forced`, in which case a missing one is empty.
+    fn load_config(
+        commondir: &Path,
+        gitdir: &Path,
+        forced: bool,
+    ) -> Result<ConfigParser, Error> {
+        let not_forced = !forced;
+
+        let mut config;
+        let config_file = path::repo_file(commondir, &["config"], false)?;
+        if let Some(config_file) = &config_file {
+            config = config::load(config_file, Some(gitdir))?;
+        } else if not_forced {
+            return Err("missing configuration file!".into());
+        } else {
+            config = ConfigParser::default();
+        }
+
+        if not_forced {
+            check_format(&config)?;
+        }
+
+        // Settings for this worktree alone override the shared ones
+        let worktree_config = gitdir.join("config.worktree");
+        let per_worktree = config
+            .get("extensions")
+            .and_then(|ext| ext.get_bool("worktreeConfig"))
+            .unwrap_or(false);
+        if let Some(config_file) =
+            config_file.filter(|_| per_worktree && worktree_config.is_file())
+        {
+            config = config::load_all(
+                &[&config_file, &worktree_config],
+                Some(gitdir),
+            )?;
+        }
+
+        Ok(config)
+    }
+
     /// Initializes and creates a new Git repository at the specified path.
     ///
     /// # Arguments
@@ -156,7 +583,7 @@ impl GitRepository {
     ///
     /// # Errors
     ///
-    /// Returns a `String` error if the repository could not be created.
+    /// Returns an [`Error`] if the repository could not be created.
     ///
     /// # Panics
     ///
@@ -170,23 +597,145 @@ impl GitRepository {
     /// let repo = GitRepository::create(Path::new("/path/to/repo"))?;
     /// # Ok::<(), String>(())
     /// ```
-    pub fn create(path: &Path) -> Result<Self, String> {
-        let repo = Self::new_repo(path, true)?;
+    pub fn create(path: &Path) -> Result<Self, Error> {
+        Self::create_with(path, &InitOptions::default())
+    }
+
+    /// Initializes and creates a new bare Git repository, which has no
+    /// working tree, with its git directory at the specified path.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A reference to the path where the repository should be created.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`Error`] if the repository could not be created.
+    ///
+    /// # Panics
+    ///
+    /// If an I/O error occurs while creating a repository
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use mini_git::core::GitRepository;
+    /// let repo = GitRepository::create_bare(Path::new("/path/to/repo.git"))?;
+    /// # Ok::<(), String>(())
+    /// ```
+    pub fn create_bare(path: &Path) -> Result<Self, Error> {
+        Self::create_with(
+            path,
+            &InitOptions {
+                bare: true,
+                ..InitOptions::default()
+            },
+        )
+    }
+
+    /// Initializes and creates a new Git repository at the specified path,
+    /// as configured by `opts`.
+    ///
+    /// If the git directory already holds a repository, it is reinitialized
+    /// instead. Missing directories and template files are added, but
+    /// `HEAD`, the config and existing files are left as they are.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A reference to the path where the repository should be created.
+    /// * `opts` - How the repository should be set up.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`Error`] if the repository could not be created, if
+    /// the initial branch name is not valid, if a bare repository is given
+    /// a separate git directory, or if an existing repository has a different
+    /// object format.
+    ///
+    /// # Panics
+    ///
+    /// If an I/O error occurs while creating a repository
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use mini_git::core::{GitRepository, InitOptions};
+    /// let opts = InitOptions {
+    ///     initial_branch: Some("trunk".to_owned()),
+    ///     ..InitOptions::default()
+    /// };
+    /// let repo = GitRepository::create_with(Path::new("/path/to/repo"), &opts)?;
+    /// # Ok::<(), String>(())
+    /// ```
+    #[allow(clippy::too_many_lines)]
+    pub fn create_with(path: &Path, opts: &InitOptions) -> Result<Self, Error> {
+        let branch = opts.initial_branch.as_deref().unwrap_or(DEFAULT_BRANCH);
+        if !is_valid_branch_name(branch) {
+            return Err(
+                format!("invalid initial branch name: '{branch}'").into()
+            );
+        }
+
+        let bare = opts.bare;
+        let mut repo = Self::new_repo(path, true, bare)?;
+
+        let gitfile = repo.worktree.join(".git");
+        if let Some(gitdir) = &opts.separate_git_dir {
+            if bare {
+                return Err("a bare repository cannot have a separate git \
+                            directory"
+                    .into());
+            }
+            if gitfile.exists() {
+                return Err(format!(
+                    "{:?} already exists",
+                    gitfile.as_os_str()
+                )
+                .into());
+            }
+            repo.gitdir = std::path::absolute(gitdir).map_err(|_| {
+                format!("{:?} is not a valid path!", gitdir.as_os_str())
+            })?;
+            repo.commondir.clone_from(&repo.gitdir);
+        }
+
+        let reinit = path::is_gitdir(&repo.gitdir);
+        if reinit {
+            let existing = repo
+                .config
+                .get("extensions")
+                .and_then(|ext| ext.get("objectformat"))
+                .map_or(Ok(ObjectFormat::Sha1), str::parse)?;
+            if opts.object_format.is_some_and(|format| format != existing) {
+                return Err("attempt to reinitialize repository with \
+                            different hash"
+                    .into());
+            }
+        }
 
         if repo.worktree.exists() {
             if !repo.worktree.is_dir() {
-                return Err(format!("not a directory {:?}", path.as_os_str()));
+                return Err(
+                    format!("not a directory {:?}", path.as_os_str()).into()
+                );
             }
 
-            if repo
-                .gitdir
-                .read_dir()
-                .map_or(false, |mut e| e.next().is_some())
+            if !reinit
+                && repo.gitdir.read_dir().is_ok_and(|mut e| e.next().is_some())
             {
-                return Err(format!("{:?} is not empty", path.as_os_str()));
+                return Err(
+                    format!("{:?} is not empty", path.as_os_str()).into()
+                );
             }
         } else if fs::create_dir_all(&repo.worktree).is_err() {
-            return Err("error in making directories".to_string());
+            return Err("error in making directories".into());
+        }
+
+        if let Some(template) = opts.template.as_deref().filter(|t| t.is_dir())
+        {
+            copy_template(template, &repo.gitdir)?;
         }
 
         path::repo_dir(&repo.gitdir, &["branches"], true)?;
@@ -197,6 +746,7 @@ impl GitRepository {
 
         if let Some(file) =
             path::repo_file(&repo.gitdir, &["description"], false)?
+                .filter(|file| !file.exists())
         {
             fs::write(
                 file,
@@ -206,34 +756,227 @@ impl GitRepository {
             .expect("Should write to file!");
         }
 
+        if opts.separate_git_dir.is_some() {
+            fs::write(gitfile, format!("gitdir: {}\n", repo.gitdir.display()))
+                .expect("Should write to file!");
+        }
+
+        if reinit {
+            return Ok(repo);
+        }
+
         if let Some(file) = path::repo_file(&repo.gitdir, &["HEAD"], false)? {
-            fs::write(file, "ref: refs/heads/main\n")
+            fs::write(file, format!("ref: refs/heads/{branch}\n"))
                 .expect("Should write to file!");
         }
 
         if let Some(file) = path::repo_file(&repo.gitdir, &["config"], false)? {
-            let default_config = Self::default_config();
+            // Settings from a template config are kept
+            let config = if file.is_file() {
+                ConfigParser::from(file.as_path())
+            } else {
+                ConfigParser::new()
+            };
+            let default_config = Self::default_config(
+                config,
+                bare,
+                opts.object_format.unwrap_or_default(),
+            );
             if default_config.write_to_file(&file).is_err() {
                 return Err("error occurred while writing \
                             configuration file"
-                    .to_string());
+                    .into());
             }
+            // The repository is read with the configuration it was created
+            // with, such as its object format
+            repo.config =
+                Self::load_config(&repo.commondir, &repo.gitdir, false)?;
         }
 
         Ok(repo)
     }
 
-    /// Creates the default configuration for a Git repository.
-    fn default_config() -> ConfigParser {
-        let mut config = ConfigParser::new();
-        config["core"]["repositoryformatversion"] = String::from("0");
+    /// Adds the default configuration for a Git repository to `config`.
+    ///
+    /// Any object format other than SHA-1 is an extension, which needs
+    /// version 1 of the repository format.
+    fn default_config(
+        mut config: ConfigParser,
+        bare: bool,
+        object_format: ObjectFormat,
+    ) -> ConfigParser {
+        if object_format == ObjectFormat::Sha1 {
+            config["core"]["repositoryformatversion"] = String::from("0");
+        } else {
+            config["core"]["repositoryformatversion"] = String::from("1");
+            config["extensions"]["objectformat"] =
+                String::from(object_format.name());
+        }
         config["core"]["filemode"] = String::from("false");
-        config["core"]["bare"] = String::from("false");
+        config["core"]["bare"] = bare.to_string();
 
         config
     }
 }
 
+/// The extensions of version 1 of the repository format that are
+/// understood, in lowercase.
+const KNOWN_EXTENSIONS: &[&str] = &["objectformat", "worktreeconfig"];
+
+/// Checks that the repository format version and extensions in the
+/// configuration of a repository are understood, so that a repository that
+/// needs more is not silently misused.
+///
+/// Like git, version 0 ignores extensions, except for the ones that only
+/// version 1 allows, while version 1 refuses the extensions it does not know.
+fn check_format(config: &ConfigParser) -> Result<(), Error> {
+    let Some(core) = config.get("core") else {
+        return Err("section \"core\" is missing!".into());
+    };
+    let Some(version) = core.get("repositoryformatversion") else {
+        return Err("key \"repositoryformatversion\" is missing".into());
+    };
+    let version = version
+        .parse::<u32>()
+        .map_err(|_| format!("invalid repositoryformatversion '{version}'"))?;
+    if version > 1 {
+        return Err(format!(
+            "unsupported repositoryformatversion {version}, expected at most 1"
+        )
+        .into());
+    }
+
+    let Some(extensions) = config.get("extensions") else {
+        return Ok(());
+    };
+    for key in extensions.keys() {
+        let known = KNOWN_EXTENSIONS.contains(&key.to_lowercase().as_str());
+        if version == 0 && key.eq_ignore_ascii_case("objectFormat") {
+            return Err(format!(
+                "repositoryformatversion 0 does not allow extensions.{key}"
+            )
+            .into());
+        } else if version == 1 && !known {
+            return Err(
+                format!("unknown repository extension found: {key}").into()
+            );
+        }
+    }
+
+    extensions
+        .get("objectFormat")
+        .map_or(Ok(ObjectFormat::Sha1), str::parse)?;
+    Ok(())
+}
+
+/// Returns the user's global configuration files that exist, in increasing
+/// order of precedence: `$XDG_CONFIG_HOME/git/config` (or
+/// `~/.config/git/config`) and `~/.gitconfig`.
+#[must_use]
+pub fn global_config_files() -> Vec<PathBuf> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let xdg = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| home.as_ref().map(|home| home.join(".config")))
+        .map(|config| config.join("git").join("config"));
+
+    [xdg, home.map(|home| home.join(".gitconfig"))]
+        .into_iter()
+        .flatten()
+        .filter(|file| file.is_file())
+        .collect()
+}
+
+/// The value of `key` in `section` of the user's global config, if set.
+#[must_use]
+pub fn global_config(section: &str, key: &str) -> Option<String> {
+    global_config_files().iter().rev().find_map(|file| {
+        config::load(file, None)
+            .ok()?
+            .get(section)?
+            .get(key)
+            .map(String::from)
+    })
+}
+
+/// Copies the files of the `template` directory into `gitdir`, keeping the
+/// files that already exist there.
+fn copy_template(template: &Path, gitdir: &Path) -> Result<(), Error> {
+    fs::create_dir_all(gitdir)
+        .map_err(|_| "error in making directories".to_string())?;
+    let entries = fs::read_dir(template).map_err(|e| {
+        format!("Failed to read template {:?}: {e}", template.as_os_str())
+    })?;
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("Failed to read entry: {e}"))?;
+        let src = entry.path();
+        let dst = gitdir.join(entry.file_name());
+        if src.is_dir() {
+            copy_template(&src, &dst)?;
+        } else if !dst.exists() {
+            fs::copy(&src, &dst).map_err(|e| {
+                format!("Failed to copy template {:?}: {e}", src.as_os_str())
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes `data` to `file` through the lock file `<file>.lock`, which is
+/// renamed over `file` once written. Readers never see a partly written
+/// file, and other writers fail rather than overwrite it.
+///
+/// # Errors
+///
+/// An [`Error::Locked`] if the lock file exists, or an [`Error::Io`] if
+/// either file cannot be written.
+pub(crate) fn write_locked(file: &Path, data: &[u8]) -> Result<(), Error> {
+    let mut lock = file.as_os_str().to_owned();
+    lock.push(".lock");
+    let lock = PathBuf::from(lock);
+    let context = || format!("Failed to write {}", file.display());
+    let mut out = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&lock)
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::AlreadyExists => Error::Locked(lock.clone()),
+            _ => Error::io(context(), e),
+        })?;
+    out.write_all(data)
+        .and_then(|()| fs::rename(&lock, file))
+        .map_err(|e| {
+            let _ = fs::remove_file(&lock);
+            Error::io(context(), e)
+        })
+}
+
+/// Whether `name` can be used as a branch name, following the rules of
+/// `git check-ref-format --branch`.
+pub(crate) fn is_valid_branch_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('-')
+        && name != "HEAD"
+        && !name.ends_with(['/', '.'])
+        && !name.contains("..")
+        && !name.contains("@{")
+        && !name.contains("//")
+        && !name
+            .chars()
+            .any(|c| c.is_ascii_control() || " ~^:?*[\\".contains(c))
+        && name
+            .split('/')
+            .all(|part| !part.starts_with('.') && !has_lock_suffix(part))
+}
+
+/// Whether `part` is named like a lock file. Ref names are case sensitive,
+/// so unlike a file extension, only a lowercase suffix is reserved.
+pub(crate) fn has_lock_suffix(part: &str) -> bool {
+    part.strip_suffix("lock")
+        .is_some_and(|rest| rest.ends_with('.'))
+}
+
 // Holds the context of a Git repository, including the current working directory,
 /// repository path, and a reference to the Git repository.
 #[allow(clippy::module_name_repetitions)]
@@ -252,24 +995,53 @@ pub struct RepositoryContext {
 /// Resolves the repository context, including the current working directory, repository path,
 /// and repository object.
 ///
+/// The repository is found from the current working directory, without going
+/// up into the directories [`CEILING_DIRECTORIES_ENVIRONMENT`] lists, unless
+/// [`GIT_DIR_ENVIRONMENT`] names its git directory. The worktree is then the
+/// current working directory, or the one [`WORK_TREE_ENVIRONMENT`] names.
+/// [`OBJECT_DIRECTORY_ENVIRONMENT`] can name another directory to store
+/// objects in.
+///
 /// # Returns
 /// - `Ok(RepositoryContext)` containing the current working directory, repository path, and Git repository object.
-/// - `Err(String)` if the repository context cannot be determined.
+/// - `Err(Error)` if the repository context cannot be determined.
 ///
 /// # Errors
 /// - Returns an error if:
 ///   - The current working directory cannot be determined.
 ///   - The repository path cannot be determined.
 ///   - The Git repository object cannot be initialized.
-pub fn resolve_repository_context() -> Result<RepositoryContext, String> {
+pub fn resolve_repository_context() -> Result<RepositoryContext, Error> {
     let cwd = std::env::current_dir().map_err(|_| {
         "Could not determine current working directory".to_owned()
     })?;
+    let env = |name| std::env::var_os(name).filter(|value| !value.is_empty());
+    let worktree = env(WORK_TREE_ENVIRONMENT).map(PathBuf::from);
 
-    let repo_path = path::repo_find(&cwd)?
-        .canonicalize()
-        .map_err(|_| "Could not determine repository path".to_owned())?;
-    let repo = GitRepository::new(&repo_path)?;
+    let mut repo = if let Some(gitdir) = env(GIT_DIR_ENVIRONMENT) {
+        GitRepository::with_gitdir(
+            Path::new(&gitdir),
+            worktree.as_deref().unwrap_or(&cwd),
+        )?
+    } else {
+        let ceilings = env(CEILING_DIRECTORIES_ENVIRONMENT)
+            .map(|dirs| std::env::split_paths(&dirs).collect::<Vec<_>>())
+            .unwrap_or_default();
+        let repo_path = path::repo_find_within(&cwd, &ceilings)?
+            .canonicalize()
+            .map_err(|_| "Could not determine repository path".to_owned())?;
+        GitRepository::new(&repo_path)?
+    };
+
+    // A worktree given explicitly is used even by a bare repository
+    if let Some(worktree) = worktree {
+        repo.worktree = worktree.canonicalize().map_err(|_| {
+            format!("{:?} is not a valid path!", worktree.as_os_str())
+        })?;
+        repo.bare = false;
+    }
+    repo.objects = env(OBJECT_DIRECTORY_ENVIRONMENT).map(|dir| cwd.join(dir));
+    let repo_path = repo.worktree().to_path_buf();
 
     Ok(RepositoryContext {
         cwd,
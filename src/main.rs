@@ -1,138 +1,244 @@
-use mini_git::core::commands::{
-    cat_file, diff, hash_object, init, log, ls_tree, rev_parse, show_ref,
+use mini_git::core::commands;
+use mini_git::core::{
+    config, global_config_files, resolve_repository_context, Error,
+    GIT_DIR_ENVIRONMENT,
 };
-use mini_git::utils::argparse::{ArgumentParser, Namespace};
+use mini_git::utils::configparser::ConfigParser;
+use mini_git::utils::path;
+use mini_git::utils::progress;
+use mini_git::utils::trace::{self, Verbosity};
 
-struct Command {
-    name: &'static str,
-    make_parser: fn() -> ArgumentParser,
-    callback: fn(&Namespace) -> Result<String, String>,
-}
-
-impl Command {
-    pub const fn new(
-        name: &'static str,
-        make_parser: fn() -> ArgumentParser,
-        callback: fn(&Namespace) -> Result<String, String>,
-    ) -> Self {
-        Self {
-            name,
-            make_parser,
-            callback,
-        }
-    }
-}
+use std::io::{self, BufWriter, Write};
+use std::process;
 
-macro_rules! cmd {
-    ($name:literal, $cmd:ident) => {
-        Command::new($name, $cmd::make_parser, $cmd::$cmd)
-    };
-}
+/// Exit status for errors, like git's fatal errors
+const ERROR_EXIT_CODE: i32 = 128;
+/// Exit status for invalid arguments, like git's usage errors
+const USAGE_EXIT_CODE: i32 = 129;
 
-// Needs to be in sorted order by name
-const COMMAND_MAP: &[Command] = &[
-    cmd!("cat-file", cat_file),
-    cmd!("diff", diff),
-    cmd!("hash-object", hash_object),
-    cmd!("init", init),
-    cmd!("log", log),
-    cmd!("ls-tree", ls_tree),
-    cmd!("rev-parse", rev_parse),
-    cmd!("show-ref", show_ref),
-];
+/// The prefix of the executables that provide commands of their own
+const EXTERNAL_COMMAND_PREFIX: &str = "mini_git-";
 
 fn main() {
     let exit_code = run();
     std::process::exit(exit_code);
 }
 
+/// Run the command given on the command line, and return the status to exit
+/// with.
+///
+/// Only the output of the command goes to stdout. Errors go to stderr, and
+/// exit with [`USAGE_EXIT_CODE`] if the arguments are invalid, or the exit
+/// code of the error otherwise, which is [`ERROR_EXIT_CODE`] unless the
+/// command says otherwise.
 fn run() -> i32 {
-    let mut parser = make_parser();
+    let mut parser = commands::make_parser();
+    // Errors are reported here, to exit with the right status
+    parser.auto_exit(false);
     parser.compile();
-    let Ok(args) = parser.parse_cli() else {
-        unreachable!();
+    let args = apply_global_options(std::env::args().skip(1).collect())
+        .and_then(expand_aliases);
+    let args = match args {
+        Ok(args) => args,
+        Err(msg) => {
+            eprintln!("{msg}");
+            return ERROR_EXIT_CODE;
+        }
+    };
+    if let Some(exit_code) = run_external(&args) {
+        return exit_code;
+    }
+    let args = match parser.parse_cli_from(args) {
+        Ok(args) => args,
+        Err(msg) => {
+            eprintln!("{msg}");
+            return USAGE_EXIT_CODE;
+        }
     };
 
+    // Without a command, only the help was asked for, which is printed
     let Some((command, args)) = args.subcommand() else {
-        unreachable!();
+        return 0;
     };
 
-    let res = COMMAND_MAP
-        .binary_search_by(|cmd| cmd.name.cmp(command))
-        .map(|x| (COMMAND_MAP[x].callback)(args))
-        .expect("Should not be an invalid command");
+    let command = commands::find(command).expect("Should be a command");
+    let mut out = BufWriter::new(io::stdout().lock());
+    let res = command.execute(args, &mut out).and_then(|exit_code| {
+        out.flush()
+            .map_err(|e| Error::io("Failed to write the output", e))?;
+        Ok(exit_code)
+    });
+    drop(out);
 
     match res {
-        Ok(msg) => {
+        Ok(exit_code) => exit_code,
+        Err(err) => {
+            let msg = err.to_string();
             if msg.ends_with('\n') {
-                print!("{msg}");
+                eprint!("{msg}");
             } else {
-                println!("{msg}");
+                eprintln!("{msg}");
             }
-            0
-        }
-        Err(msg) => {
-            if msg.ends_with('\n') {
-                print!("{msg}");
-            } else {
-                println!("{msg}");
-            }
-            -1
+            err.exit_code()
         }
     }
 }
 
-fn make_parser() -> ArgumentParser {
-    let mut parser = ArgumentParser::new("MiniGit, a git, but mini!");
-
-    for command in COMMAND_MAP {
-        parser.add_subcommand(command.name, (command.make_parser)());
+/// Apply the options before the command, which change where the repository
+/// is found and how commands report, and return the remaining arguments.
+///
+/// `-C <path>` changes the current directory, and can be repeated with
+/// paths relative to the previous one. `--git-dir <path>` names the git
+/// directory through [`GIT_DIR_ENVIRONMENT`], so the commands that run
+/// hooks pass it on too. `--no-progress` stops long operations from showing
+/// their progress on stderr. `--quiet` does too, and `--verbose` traces what
+/// the command does, see [`trace`]. `--version` runs the `version` command
+/// in place of the rest of the arguments.
+fn apply_global_options(args: Vec<String>) -> Result<Vec<String>, String> {
+    let mut args = args.into_iter().peekable();
+    while let Some(arg) = args.next_if(|arg| arg.starts_with('-')) {
+        if arg == "-C" {
+            let dir = args.next().ok_or("Missing value for argument: -C")?;
+            // An empty path leaves the directory as is, like git
+            if !dir.is_empty() {
+                std::env::set_current_dir(&dir)
+                    .map_err(|e| format!("Cannot change to '{dir}': {e}"))?;
+            }
+        } else if arg == "--git-dir" {
+            let dir =
+                args.next().ok_or("Missing value for argument: git-dir")?;
+            std::env::set_var(GIT_DIR_ENVIRONMENT, dir);
+        } else if let Some(dir) = arg.strip_prefix("--git-dir=") {
+            std::env::set_var(GIT_DIR_ENVIRONMENT, dir);
+        } else if arg == "--no-progress" {
+            progress::disable();
+        } else if arg == "--quiet" {
+            trace::set_verbosity(Verbosity::Quiet);
+            progress::disable();
+        } else if arg == "--verbose" {
+            trace::set_verbosity(Verbosity::Verbose);
+        } else if arg == "--version" {
+            return Ok(vec!["version".to_owned()]);
+        } else {
+            // Left to the parser, such as --help
+            return Ok(std::iter::once(arg).chain(args).collect());
+        }
     }
-
-    parser.require_subcommand();
-
-    parser
+    Ok(args.collect())
 }
 
-// The following code ensures that the Command array is sorted at compile time.
-// The Command array is required to be sorted to be binary-search friendly,
-// and we enforce this at compile time.
-#[allow(dead_code)]
-const fn str_le(a: &'static str, b: &'static str) -> bool {
-    let (a, b) = (a.as_bytes(), b.as_bytes());
-    let mut i = 0;
-    let len = if a.len() < b.len() { a.len() } else { b.len() };
-
-    while i < len {
-        if a[i] < b[i] {
-            return true;
-        } else if a[i] > b[i] {
-            return false;
+/// Run an external command, if the command given is not one of ours but a
+/// `mini_git-<name>` executable is found on `PATH`, with the arguments
+/// after it, and return its exit status. Aliases are expanded first, so an
+/// alias takes precedence over an external command of the same name.
+fn run_external(args: &[String]) -> Option<i32> {
+    let (name, args) = args.split_first()?;
+    if name.starts_with('-') || name.contains(['/', '\\']) || is_command(name) {
+        return None;
+    }
+    let search_path = std::env::var_os("PATH")?;
+    let program = format!("{EXTERNAL_COMMAND_PREFIX}{name}");
+    let path = path::find_executable(&program, &search_path)?;
+
+    trace::command(&path.to_string_lossy(), args);
+    match process::Command::new(&path).args(args).status() {
+        // Killed by a signal, so there is no exit status to pass on
+        Ok(status) => Some(status.code().unwrap_or(ERROR_EXIT_CODE)),
+        Err(e) => {
+            eprintln!("Cannot run '{}': {e}", path.display());
+            Some(ERROR_EXIT_CODE)
         }
-        i += 1;
     }
-    len == a.len()
 }
 
-#[allow(dead_code)]
-const fn is_cmd_sorted() -> bool {
-    let len = COMMAND_MAP.len();
-    assert!(len > 1, "COMMAND MAP IS EMPTY");
-    let mut prev_name = &COMMAND_MAP[0].name;
-    let mut i = 1;
+/// Replace a leading alias from `[alias]` in the configuration with what it
+/// stands for, along with any arguments it includes. Aliases can refer to
+/// other aliases, but not replace a command.
+fn expand_aliases(mut args: Vec<String>) -> Result<Vec<String>, String> {
+    let Some(name) = args.first().cloned() else {
+        return Ok(args);
+    };
+    let configs = alias_configs();
+    let mut seen = vec![];
+    let mut command = name.clone();
+    loop {
+        if is_command(&command) {
+            return Ok(args);
+        }
+        let Some(alias) = configs
+            .iter()
+            .rev()
+            .find_map(|config| config.get("alias")?.get(&command))
+        else {
+            // Not an alias, so the parser reports it
+            return Ok(args);
+        };
+        if seen.contains(&command) {
+            return Err(format!(
+                "Alias loop detected: expansion of '{name}' does not terminate"
+            ));
+        }
 
-    while i < len {
-        if !str_le(prev_name, COMMAND_MAP[i].name) {
-            return false;
+        if let Some(shell) = alias.strip_prefix('!') {
+            return Err(format!(
+                "Shell aliases are not supported: '{command}' is aliased to \
+                '{}'",
+                shell.trim()
+            ));
+        }
+        let words = split_cmdline(alias)
+            .map_err(|e| format!("Bad alias '{command}': {e}"))?;
+        if words.is_empty() {
+            return Err(format!("Empty alias for '{command}'"));
         }
 
-        prev_name = &COMMAND_MAP[i].name;
-        i += 1;
+        seen.push(std::mem::replace(&mut command, words[0].clone()));
+        args.splice(..1, words);
     }
+}
 
-    true
+/// The configurations aliases are looked up in, in increasing order of
+/// precedence. Files that cannot be read are left out.
+fn alias_configs() -> Vec<ConfigParser> {
+    let mut configs = global_config_files()
+        .iter()
+        .filter_map(|file| config::load(file, None).ok())
+        .collect::<Vec<_>>();
+    if let Ok(context) = resolve_repository_context() {
+        configs.push(context.repo.config().clone());
+    }
+    configs
+}
+
+fn is_command(name: &str) -> bool {
+    commands::find(name).is_some()
 }
 
-// If this fails to compile, the command array is not sorted
-#[allow(clippy::erasing_op)]
-const _: u8 = 0 / is_cmd_sorted() as u8;
+/// Split the value of an alias into words like the shell, with quotes and
+/// backslashes.
+fn split_cmdline(value: &str) -> Result<Vec<String>, String> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            (Some(q), c) if q == c => quote = None,
+            (None | Some('"'), '\\') => {
+                let c = chars.next().ok_or("unfinished escape")?;
+                word.get_or_insert_with(String::new).push(c);
+            }
+            (_, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quote.is_some() {
+        return Err("unclosed quote".to_owned());
+    }
+    words.extend(word);
+    Ok(words)
+}
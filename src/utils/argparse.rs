@@ -6,10 +6,16 @@
 //! ## Features
 //!
 //! - Support for different argument types (String, Integer, Float, Boolean)
-//! - Short and long option formats
+//! - Flags that are counted, like `-vvv`
+//! - Short and long option formats, with short flags bundled as `-am`
 //! - Required and optional arguments
-//! - Subcommand support
+//! - Bounds on the values of numeric arguments
+//! - Arguments hidden from the help, for internal or deprecated options
+//! - Arguments that take multiple values, like `<path>...`
+//! - Subcommand support, with aliases
 //! - Automatic help message generation
+//! - Reference documentation as man pages or Markdown, see
+//!   [`ArgumentParser::docs`]
 //!
 //! ## Example
 //!
@@ -33,8 +39,16 @@
 //! ```
 
 use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Write;
+use std::iter::Peekable;
 use std::ops::Index;
 
+/// How many edits away a name can be from a mistyped one to be suggested
+const SUGGESTION_DISTANCE: usize = 3;
+
+/// How many names are suggested for a mistyped one at most
+const SUGGESTION_COUNT: usize = 3;
+
 /// Represents the type of an argument.
 #[derive(Debug, Clone)]
 pub enum ArgumentType {
@@ -46,10 +60,34 @@ pub enum ArgumentType {
     Float,
     /// A boolean flag.
     Boolean,
+    /// A flag that can be repeated, as `-vv` or `--verbose --verbose`, whose
+    /// value is the number of times it is given. See [`Namespace::count`].
+    Count,
+}
+
+/// The formats [`ArgumentParser::docs`] renders documentation in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocFormat {
+    /// A roff man page, in section 1
+    Man,
+    /// A Markdown reference page
+    Markdown,
+}
+
+impl DocFormat {
+    /// The extension of the files of pages in this format.
+    #[must_use]
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Man => "1",
+            Self::Markdown => "md",
+        }
+    }
 }
 
 /// Represents a single command-line argument.
 #[derive(Debug)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Argument {
     name: String,
     short: Option<char>,
@@ -59,17 +97,29 @@ pub struct Argument {
     default: Option<String>,
     choices: Option<HashSet<String>>,
     ignore_case: bool,
+    multiple: bool,
+    hidden: bool,
+    short_only: bool,
+    min: Option<f64>,
+    max: Option<f64>,
+    implicit: Option<String>,
 }
 
 /// Represents a subcommand in the argument parser.
 #[derive(Debug)]
 struct SubCommand {
     name: String,
+    aliases: Vec<String>,
     parser: ArgumentParser,
 }
 
+/// The options in an argument, each with how it is called in errors, and
+/// the value attached to a long option.
+type GivenOptions<'a> = (Vec<(&'a Argument, String)>, Option<String>);
+
 /// The main argument parser struct.
 #[derive(Debug)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct ArgumentParser {
     description: String,
     arguments: Vec<Argument>,
@@ -79,6 +129,7 @@ pub struct ArgumentParser {
     exit_code: i32,
     compiled: bool,
     subcommand_required: bool,
+    hidden: bool,
     max_arg_len: usize,
 }
 
@@ -86,6 +137,7 @@ pub struct ArgumentParser {
 #[derive(Debug)]
 pub struct Namespace {
     values: HashMap<String, String>,
+    lists: HashMap<String, Vec<String>>,
     pub order: Vec<String>,
     subcommand: Option<(String, Box<Namespace>)>,
 }
@@ -101,6 +153,12 @@ impl Default for Argument {
             default: None,
             choices: None,
             ignore_case: false,
+            multiple: false,
+            hidden: false,
+            short_only: false,
+            min: None,
+            max: None,
+            implicit: None,
         }
     }
 }
@@ -166,6 +224,30 @@ impl Argument {
         self
     }
 
+    /// Sets the short option character for the argument, and makes it the
+    /// only way to give the argument, like `-C` of git. The name of the
+    /// argument is then only the key of its value in the [`Namespace`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mini_git::utils::argparse::{ArgumentParser, ArgumentType};
+    ///
+    /// let mut parser = ArgumentParser::new("My CLI Application");
+    /// parser.add_argument("path", ArgumentType::String).short_only('C');
+    /// parser.compile();
+    ///
+    /// let args = parser.parse_args(&["-C", "src"]).unwrap();
+    /// assert_eq!(args["path"], "src");
+    /// assert!(parser.parse_args(&["--path", "src"]).is_err());
+    /// assert!(parser.help().contains("  -C             "));
+    /// ```
+    pub fn short_only(&mut self, short: char) -> &mut Self {
+        self.short = Some(short);
+        self.short_only = true;
+        self
+    }
+
     /// Sets the choices for a given argument. A parser will fail if the value
     /// provided is not one of the given choices.
     ///
@@ -174,7 +256,8 @@ impl Argument {
     ///
     /// # Panics
     ///
-    /// If called on an argument that has type [`ArgumentType::Boolean`]
+    /// If called on an argument that has type [`ArgumentType::Boolean`] or
+    /// [`ArgumentType::Count`]
     ///
     /// # Example
     ///
@@ -186,8 +269,11 @@ impl Argument {
     /// ```
     pub fn choices(&mut self, choices: &[&str]) -> &mut Self {
         assert!(
-            !matches!(self.arg_type, ArgumentType::Boolean),
-            "Choices cannot be used with boolean arguments"
+            !matches!(
+                self.arg_type,
+                ArgumentType::Boolean | ArgumentType::Count
+            ),
+            "Choices cannot be used with flags"
         );
         self.choices = Some(
             choices
@@ -221,6 +307,54 @@ impl Argument {
         self
     }
 
+    /// Lets the argument take multiple values, which are all kept, in order,
+    /// and are retrieved with [`Namespace::get_all`].
+    ///
+    /// A positional argument that takes multiple values takes all the
+    /// positional arguments that are left, so it should be the last one.
+    /// An option takes the arguments after it up to the next option, and
+    /// adds to its values each time it is given.
+    ///
+    /// # Panics
+    ///
+    /// If called on an argument that has type [`ArgumentType::Boolean`] or
+    /// [`ArgumentType::Count`]
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mini_git::utils::argparse::{ArgumentParser, ArgumentType};
+    ///
+    /// let mut parser = ArgumentParser::new("My CLI Application");
+    /// parser.add_argument("paths", ArgumentType::String)
+    ///     .required()
+    ///     .multiple();
+    /// parser.add_argument("exclude", ArgumentType::String)
+    ///     .short('e')
+    ///     .multiple();
+    /// parser.compile();
+    ///
+    /// let args = parser
+    ///     .parse_args(&["a.txt", "b.txt", "-e", "c.txt", "d.txt", "-e", "e.txt"])
+    ///     .expect("Failed to parse arguments");
+    /// assert_eq!(args.get_all("paths").unwrap(), ["a.txt", "b.txt"]);
+    /// assert_eq!(
+    ///     args.get_all("exclude").unwrap(),
+    ///     ["c.txt", "d.txt", "e.txt"]
+    /// );
+    /// ```
+    pub fn multiple(&mut self) -> &mut Self {
+        assert!(
+            !matches!(
+                self.arg_type,
+                ArgumentType::Boolean | ArgumentType::Count
+            ),
+            "Flags cannot take multiple values"
+        );
+        self.multiple = true;
+        self
+    }
+
     /// Makes the argument required.
     ///
     /// # Example
@@ -307,6 +441,200 @@ impl Argument {
         self.default = Some(default.to_owned());
         self
     }
+
+    /// Hides the argument from the help and documentation, for internal or
+    /// deprecated options. It is still parsed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mini_git::utils::argparse::{ArgumentParser, ArgumentType};
+    ///
+    /// let mut parser = ArgumentParser::new("My CLI Application");
+    /// parser.add_argument("debug-dump", ArgumentType::Boolean).hidden();
+    /// parser.compile();
+    ///
+    /// assert!(!parser.help().contains("debug-dump"));
+    /// assert!(parser.parse_args(&["--debug-dump"]).is_ok());
+    /// ```
+    pub fn hidden(&mut self) -> &mut Self {
+        self.hidden = true;
+        self
+    }
+
+    /// Sets the smallest value the argument accepts.
+    ///
+    /// # Panics
+    ///
+    /// If called on an argument that is not of type [`ArgumentType::Integer`]
+    /// or [`ArgumentType::Float`]
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mini_git::utils::argparse::{ArgumentParser, ArgumentType};
+    ///
+    /// let mut parser = ArgumentParser::new("My CLI Application");
+    /// parser.add_argument("jobs", ArgumentType::Integer).min(1.0).max(64.0);
+    /// parser.compile();
+    ///
+    /// assert!(parser.parse_args(&["--jobs", "8"]).is_ok());
+    /// assert!(parser.parse_args(&["--jobs", "0"]).is_err());
+    /// assert!(parser.parse_args(&["--jobs", "65"]).is_err());
+    /// ```
+    pub fn min(&mut self, min: f64) -> &mut Self {
+        self.assert_numeric();
+        self.min = Some(min);
+        self
+    }
+
+    /// Sets the largest value the argument accepts.
+    ///
+    /// # Panics
+    ///
+    /// If called on an argument that is not of type [`ArgumentType::Integer`]
+    /// or [`ArgumentType::Float`]
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mini_git::utils::argparse::{ArgumentParser, ArgumentType};
+    ///
+    /// let mut parser = ArgumentParser::new("My CLI Application");
+    /// parser.add_argument("ratio", ArgumentType::Float).max(1.0);
+    /// parser.compile();
+    ///
+    /// assert!(parser.parse_args(&["--ratio", "0.5"]).is_ok());
+    /// assert!(parser.parse_args(&["--ratio", "1.5"]).is_err());
+    /// ```
+    pub fn max(&mut self, max: f64) -> &mut Self {
+        self.assert_numeric();
+        self.max = Some(max);
+        self
+    }
+
+    /// Sets the value the option has when given without one. It then only
+    /// takes a value attached to it, as in `--porcelain=v2`, so the argument
+    /// after it is not taken as its value.
+    ///
+    /// # Panics
+    ///
+    /// If called on an argument of type [`ArgumentType::Boolean`] or
+    /// [`ArgumentType::Count`]
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mini_git::utils::argparse::{ArgumentParser, ArgumentType};
+    ///
+    /// let mut parser = ArgumentParser::new("My CLI Application");
+    /// parser
+    ///     .add_argument("porcelain", ArgumentType::String)
+    ///     .optional()
+    ///     .choices(&["v1", "v2"])
+    ///     .implicit_value("v1");
+    /// parser.compile();
+    ///
+    /// let args = parser.parse_args(&["--porcelain"]).unwrap();
+    /// assert_eq!(args.get("porcelain").unwrap(), "v1");
+    /// let args = parser.parse_args(&["--porcelain=v2"]).unwrap();
+    /// assert_eq!(args.get("porcelain").unwrap(), "v2");
+    /// ```
+    pub fn implicit_value(&mut self, value: &str) -> &mut Self {
+        assert!(
+            !matches!(
+                self.arg_type,
+                ArgumentType::Boolean | ArgumentType::Count
+            ),
+            "Flags cannot have an implicit value"
+        );
+        self.implicit = Some(value.to_owned());
+        self
+    }
+
+    fn assert_numeric(&self) {
+        assert!(
+            matches!(
+                self.arg_type,
+                ArgumentType::Integer | ArgumentType::Float
+            ),
+            "Bounds can only be used with numeric arguments"
+        );
+    }
+
+    /// The values the argument accepts, as in `from 1 to 64`, if it has
+    /// bounds.
+    fn range(&self) -> Option<String> {
+        match (self.min, self.max) {
+            (Some(min), Some(max)) => Some(format!("from {min} to {max}")),
+            (Some(min), None) => Some(format!("at least {min}")),
+            (None, Some(max)) => Some(format!("at most {max}")),
+            (None, None) => None,
+        }
+    }
+
+    /// How the argument is given, as in `-f, --files FILES...`.
+    fn synopsis(&self) -> String {
+        let mut synopsis = String::new();
+        if let Some(short) = self.short {
+            synopsis.push('-');
+            synopsis.push(short);
+        }
+        if !self.short_only {
+            if !synopsis.is_empty() {
+                synopsis.push_str(", ");
+            }
+            synopsis.push_str("--");
+            synopsis.push_str(&self.name);
+        }
+        if self.implicit.is_some() {
+            synopsis.push_str("[=");
+            synopsis.push_str(&self.name.to_uppercase());
+            synopsis.push(']');
+        } else if !matches!(
+            self.arg_type,
+            ArgumentType::Boolean | ArgumentType::Count
+        ) {
+            synopsis.push(' ');
+            synopsis.push_str(&self.name.to_uppercase());
+        }
+        if self.multiple {
+            synopsis.push_str("...");
+        }
+        synopsis
+    }
+
+    /// Notes on the argument for its documentation: whether it is required,
+    /// its choices and its default.
+    fn notes(&self) -> Vec<String> {
+        let mut notes = vec![];
+        match &self.default {
+            Some(default) if !default.is_empty() => {
+                notes.push(format!("Defaults to {default}."));
+            }
+            None if self.required => notes.push("(required)".to_owned()),
+            _ => {}
+        }
+        if let Some(choices) = &self.choices {
+            // A set, sorted for consistent documentation
+            let mut choices =
+                choices.iter().map(String::as_str).collect::<Vec<_>>();
+            choices.sort_unstable();
+            let case = if self.ignore_case {
+                " (case insensitive)"
+            } else {
+                ""
+            };
+            notes.push(format!("Choices: {}{case}.", choices.join(", ")));
+        }
+        if let Some(implicit) = &self.implicit {
+            notes.push(format!("Given alone, it is {implicit}."));
+        }
+        if let Some(range) = self.range() {
+            notes.push(format!("Range: {range}."));
+        }
+        notes
+    }
 }
 
 impl SubCommand {
@@ -323,9 +651,15 @@ impl SubCommand {
 
         SubCommand {
             name: name.to_string(),
+            aliases: vec![],
             parser,
         }
     }
+
+    /// Whether the subcommand is called `name`, or has it as an alias.
+    fn is_called(&self, name: &str) -> bool {
+        self.name == name || self.aliases.iter().any(|alias| alias == name)
+    }
 }
 
 impl Default for Namespace {
@@ -340,6 +674,7 @@ impl Namespace {
     pub fn new() -> Self {
         Self {
             values: HashMap::new(),
+            lists: HashMap::new(),
             subcommand: None,
             order: vec![],
         }
@@ -351,11 +686,44 @@ impl Namespace {
     }
 
     /// Gets the value of an argument by its name.
+    ///
+    /// For arguments that take multiple values, this is the last value.
     #[must_use]
     pub fn get(&self, key: &str) -> Option<&String> {
         self.values.get(key)
     }
 
+    /// Gets all the values of an argument that takes multiple values, see
+    /// [`Argument::multiple`], in the order they were given.
+    #[must_use]
+    pub fn get_all(&self, key: &str) -> Option<&[String]> {
+        self.lists.get(key).map(Vec::as_slice)
+    }
+
+    /// The number of times a flag of type [`ArgumentType::Count`] was given,
+    /// which is 0 if it was not.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mini_git::utils::argparse::{ArgumentParser, ArgumentType};
+    ///
+    /// let mut parser = ArgumentParser::new("My CLI Application");
+    /// parser.add_argument("verbose", ArgumentType::Count).short('v');
+    /// parser.compile();
+    ///
+    /// let args = parser.parse_args(&["-vv", "--verbose"]).unwrap();
+    /// assert_eq!(args.count("verbose"), 3);
+    /// let args = parser.parse_args(&[]).unwrap();
+    /// assert_eq!(args.count("verbose"), 0);
+    /// ```
+    #[must_use]
+    pub fn count(&self, key: &str) -> usize {
+        self.get(key)
+            .and_then(|count| count.parse().ok())
+            .unwrap_or(0)
+    }
+
     /// Gets the subcommand, if any.
     #[must_use]
     pub fn subcommand(&self) -> Option<(&String, &Namespace)> {
@@ -387,6 +755,7 @@ impl Default for ArgumentParser {
             exit_code: 0,
             compiled: false,
             subcommand_required: false,
+            hidden: false,
             max_arg_len: 0,
         }
     }
@@ -515,12 +884,50 @@ impl ArgumentParser {
         name: &str,
         parser: ArgumentParser,
     ) -> &mut Self {
-        assert!(
-            !self.subcommands.iter().any(|c| c.name == name),
-            "Subcommand \"{name}\" already exists."
-        );
+        self.add_subcommand_with_aliases(name, &[], parser)
+    }
+
+    /// Adds a subcommand to the parser, which can also be called by any of
+    /// `aliases`.
+    ///
+    /// The subcommand is parsed into the [`Namespace`] under `name`, even
+    /// when it is called by an alias. The help lists the aliases after the
+    /// name.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a subcommand with the same name or alias is added twice.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mini_git::utils::argparse::ArgumentParser;
+    ///
+    /// let mut main_parser = ArgumentParser::new("Main Application");
+    /// let sub_parser = ArgumentParser::new("Show the status");
+    /// main_parser.add_subcommand_with_aliases("status", &["st"], sub_parser);
+    /// main_parser.compile();
+    ///
+    /// let args = main_parser.parse_args(&["st"]).unwrap();
+    /// assert_eq!(args.subcommand().unwrap().0, "status");
+    /// ```
+    pub fn add_subcommand_with_aliases(
+        &mut self,
+        name: &str,
+        aliases: &[&str],
+        parser: ArgumentParser,
+    ) -> &mut Self {
+        for name in std::iter::once(&name).chain(aliases) {
+            assert!(
+                !self.subcommands.iter().any(|c| c.is_called(name)),
+                "Subcommand \"{name}\" already exists."
+            );
+        }
         self.compiled = false;
-        self.subcommands.push(SubCommand::new(name, parser));
+        let mut subcommand = SubCommand::new(name, parser);
+        subcommand.aliases =
+            aliases.iter().copied().map(String::from).collect();
+        self.subcommands.push(subcommand);
         self
     }
 
@@ -549,6 +956,39 @@ impl ArgumentParser {
         self
     }
 
+    /// Hides the parser from the help, suggestions and documentation of its
+    /// parent, when it is added as a subcommand. It can still be run, and
+    /// has a help of its own.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mini_git::utils::argparse::ArgumentParser;
+    ///
+    /// let mut main_parser = ArgumentParser::new("Main Application");
+    /// let mut sub_parser = ArgumentParser::new("Internal command");
+    /// sub_parser.hidden();
+    /// main_parser.add_subcommand("internal", sub_parser);
+    /// main_parser.compile();
+    ///
+    /// assert!(!main_parser.help().contains("internal"));
+    /// assert!(main_parser.parse_args(&["internal"]).is_ok());
+    /// ```
+    pub fn hidden(&mut self) -> &mut Self {
+        self.hidden = true;
+        self
+    }
+
+    /// Whether the parser is hidden from the help of its parent, see
+    /// [`ArgumentParser::hidden`].
+    #[must_use]
+    pub fn is_hidden(&self) -> bool {
+        self.hidden
+    }
+
+    /// The names of at most `count` subcommands within `max_dist` edits of
+    /// `to`. Aliases count too, and the closest of the name and aliases of
+    /// a subcommand is given.
     #[must_use]
     pub fn closest_subcommands(
         &self,
@@ -558,12 +998,76 @@ impl ArgumentParser {
     ) -> Vec<String> {
         self.subcommands
             .iter()
-            .map(|cmd| cmd.name.clone())
-            .filter(|name| dl_distance(name, to) <= max_dist)
+            .filter(|cmd| !cmd.parser.hidden)
+            .filter_map(|cmd| {
+                std::iter::once(&cmd.name)
+                    .chain(&cmd.aliases)
+                    .map(|name| (dl_distance(name, to), name))
+                    .min_by_key(|&(dist, _)| dist)
+                    .filter(|&(dist, _)| dist <= max_dist)
+                    .map(|(_, name)| name.clone())
+            })
             .take(count)
             .collect()
     }
 
+    /// The description of the parser, as shown in its help.
+    #[must_use]
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// The parser of the subcommand `name`, or with the alias `name`, if
+    /// there is one.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mini_git::utils::argparse::ArgumentParser;
+    ///
+    /// let mut main_parser = ArgumentParser::new("Main Application");
+    /// main_parser.add_subcommand("sub", ArgumentParser::new("Subcommand"));
+    ///
+    /// let sub_parser = main_parser.subcommand_parser("sub").unwrap();
+    /// assert_eq!(sub_parser.description(), "Subcommand");
+    /// assert!(main_parser.subcommand_parser("other").is_none());
+    /// ```
+    #[must_use]
+    pub fn subcommand_parser(&self, name: &str) -> Option<&ArgumentParser> {
+        self.subcommands
+            .iter()
+            .find(|cmd| cmd.is_called(name))
+            .map(|cmd| &cmd.parser)
+    }
+
+    /// The names of the subcommands, in the order they were added.
+    pub fn subcommand_names(&self) -> impl Iterator<Item = &str> {
+        self.subcommands.iter().map(|cmd| cmd.name.as_str())
+    }
+
+    /// The aliases of the subcommand `name`, which are empty if there is no
+    /// such subcommand.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mini_git::utils::argparse::ArgumentParser;
+    ///
+    /// let mut main_parser = ArgumentParser::new("Main Application");
+    /// let sub_parser = ArgumentParser::new("Switch branches");
+    /// main_parser.add_subcommand_with_aliases("checkout", &["co"], sub_parser);
+    ///
+    /// assert_eq!(main_parser.subcommand_aliases("checkout"), ["co"]);
+    /// assert!(main_parser.subcommand_aliases("other").is_empty());
+    /// ```
+    #[must_use]
+    pub fn subcommand_aliases(&self, name: &str) -> &[String] {
+        self.subcommands
+            .iter()
+            .find(|cmd| cmd.name == name)
+            .map_or(&[], |cmd| &cmd.aliases)
+    }
+
     /// Compiles the argument parser, checking for any conflicts in the
     /// argument definitions.
     ///
@@ -671,11 +1175,43 @@ impl ArgumentParser {
     /// println!("Hello, {}!", args["name"]);
     /// ```
     pub fn parse_cli(&self) -> Result<Namespace, String> {
-        let args = std::env::args().skip(1);
-        match self.parse(args, true) {
+        self.parse_cli_from(std::env::args().skip(1))
+    }
+
+    /// Parses command-line arguments that were obtained elsewhere, such as
+    /// [`std::env::args`] after some rewriting.
+    ///
+    /// Like [`ArgumentParser::parse_cli`], this automatically exits the
+    /// program on errors unless auto exit is disabled.
+    ///
+    /// # Errors
+    ///
+    /// This function may fail for the same reasons as
+    /// [`ArgumentParser::parse_cli`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mini_git::utils::argparse::{ArgumentParser, ArgumentType};
+    ///
+    /// let mut parser = ArgumentParser::new("My CLI Application");
+    /// parser.add_argument("name", ArgumentType::String)
+    ///     .required()
+    ///     .add_help("Your name");
+    ///
+    /// parser.compile();
+    /// let args = vec![String::from("world")];
+    /// let args = parser.parse_cli_from(args).expect("Failed to parse");
+    /// assert_eq!(args["name"], "world");
+    /// ```
+    pub fn parse_cli_from<I>(&self, args: I) -> Result<Namespace, String>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        match self.parse(&mut args.into_iter().peekable(), true) {
             Ok(res) => Ok(res),
             Err(msg) if self.auto_exit => {
-                println!("{msg}");
+                eprintln!("{msg}");
                 std::process::exit(0);
             }
             Err(msg) => Err(msg),
@@ -714,10 +1250,14 @@ impl ArgumentParser {
     /// }
     /// ```
     pub fn parse_args(&self, args: &[&str]) -> Result<Namespace, String> {
-        self.parse(args.iter().map(|&x| x.to_owned()), false)
+        self.parse(&mut args.iter().map(|&x| x.to_owned()).peekable(), false)
     }
 
-    fn parse<I>(&self, mut args: I, cli: bool) -> Result<Namespace, String>
+    fn parse<I>(
+        &self,
+        args: &mut Peekable<I>,
+        cli: bool,
+    ) -> Result<Namespace, String>
     where
         I: Iterator<Item = String>,
     {
@@ -735,7 +1275,7 @@ impl ArgumentParser {
         while let Some(arg) = args.next() {
             // Check for subcommand
             if let Some(subcommand) =
-                self.subcommands.iter().find(|s| s.name == *arg)
+                self.subcommands.iter().find(|s| s.is_called(&arg))
             {
                 parsed.set_subcommand(
                     &subcommand.name,
@@ -750,14 +1290,14 @@ impl ArgumentParser {
                 if (self.handle_optional(
                     &mut parsed,
                     &arg,
-                    &mut args,
+                    args,
                     &mut positionals,
                     cli,
                 )?)
                 .is_some()
                 {
                     return Ok(parsed);
-                };
+                }
             } else {
                 self.handle_positional(
                     &mut parsed,
@@ -774,34 +1314,74 @@ impl ArgumentParser {
         Ok(parsed)
     }
 
-    fn handle_optional<'a, 'b, I>(
-        &'a self,
-        parsed: &'b mut Namespace,
-        arg: &String,
-        args: &mut I,
-        positionals: &mut VecDeque<&Argument>,
-        cli: bool,
-    ) -> Result<Option<&'b mut Namespace>, String>
+    /// The options given in `arg`, with how each is called in errors, and
+    /// the value attached to a long option, as in `--name=value`.
+    fn find_options(&self, arg: &str) -> Result<GivenOptions<'_>, String> {
+        // Short flags can be bundled, as -am, where only the last can take
+        // a value
+        let mut attached = None;
+        let options = if let Some(name) = arg.strip_prefix("--") {
+            // A value can be attached to a long option, as --name=value
+            let name = match name.split_once('=') {
+                Some((name, value)) => {
+                    attached = Some(value.to_owned());
+                    name
+                }
+                None => name,
+            };
+            let Some(argument) = self
+                .arguments
+                .iter()
+                .find(|a| a.name == name && !a.short_only)
+            else {
+                let names = self
+                    .arguments
+                    .iter()
+                    .filter(|a| !a.hidden && !a.short_only)
+                    .map(|a| a.name.as_str());
+                let similar = closest(names, name)
+                    .into_iter()
+                    .map(|name| format!("--{name}"))
+                    .collect::<Vec<_>>();
+                let msg = format!("Unknown argument: {arg}");
+                return Err(with_suggestions(msg, "options", &similar));
+            };
+            vec![(argument, name.to_owned())]
+        } else {
+            let shorts = &arg[1..];
+            if shorts.is_empty() {
+                return Err(format!("Unknown argument: {arg}"));
+            }
+            shorts
+                .chars()
+                .map(|short| {
+                    self.arguments
+                        .iter()
+                        .find(|a| a.short == Some(short))
+                        .map(|argument| (argument, format!("-{short}")))
+                        .ok_or_else(|| format!("Unknown argument: -{short}"))
+                })
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        Ok((options, attached))
+    }
+
+    fn handle_optional<'a, 'b, I>(
+        &'a self,
+        parsed: &'b mut Namespace,
+        arg: &String,
+        args: &mut Peekable<I>,
+        positionals: &mut VecDeque<&Argument>,
+        cli: bool,
+    ) -> Result<Option<&'b mut Namespace>, String>
     where
         I: Iterator<Item = String>,
         'a: 'b,
     {
-        let (find_strategy, err) = if let Some(name) = arg.strip_prefix("--") {
-            (
-                Box::new(move |a: &&Argument| a.name == name)
-                    as Box<dyn Fn(&&Argument) -> bool>,
-                Err(format!("Missing value for argument: {name}")),
-            )
-        } else {
-            let short = arg.chars().nth(1).unwrap();
-            (
-                Box::new(move |a: &&Argument| a.short == Some(short))
-                    as Box<dyn Fn(&&Argument) -> bool>,
-                Err(format!("Missing value for argument: -{short}")),
-            )
-        };
+        let (options, mut attached) = self.find_options(arg)?;
 
-        if let Some(argument) = self.arguments.iter().find(find_strategy) {
+        let last = options.len() - 1;
+        for (i, (argument, called)) in options.into_iter().enumerate() {
             if argument.name == "help" {
                 if cli {
                     println!("{}", self.help());
@@ -810,25 +1390,63 @@ impl ArgumentParser {
                     }
                 } else {
                     parsed.values.clear();
+                    parsed.lists.clear();
                     Self::insert_argument(parsed, argument, arg.clone())?;
                 }
                 return Ok(Some(parsed));
             }
 
-            if matches!(argument.arg_type, ArgumentType::Boolean) {
-                parsed
-                    .values
-                    .insert(argument.name.clone(), "true".to_string());
-                parsed.order.push(argument.name.clone());
-            } else {
-                let Some(val) = args.next() else {
-                    return err;
-                };
-                Self::insert_argument(parsed, argument, val)?;
+            if attached.is_some()
+                && matches!(
+                    argument.arg_type,
+                    ArgumentType::Boolean | ArgumentType::Count
+                )
+            {
+                return Err(format!("Option --{called} takes no value"));
+            }
+
+            match argument.arg_type {
+                ArgumentType::Boolean => {
+                    parsed
+                        .values
+                        .insert(argument.name.clone(), "true".to_string());
+                    parsed.order.push(argument.name.clone());
+                }
+                ArgumentType::Count => {
+                    let count = parsed.count(&argument.name) + 1;
+                    parsed
+                        .values
+                        .insert(argument.name.clone(), count.to_string());
+                    parsed.order.push(argument.name.clone());
+                }
+                _ if i < last => {
+                    return Err(format!(
+                        "Option {called} takes a value, so it must be last \
+                        in {arg}"
+                    ));
+                }
+                _ => {
+                    // Options with an implicit value only take attached ones
+                    let given =
+                        attached.take().or_else(|| argument.implicit.clone());
+                    let separate = given.is_none();
+                    let Some(val) = given.or_else(|| args.next()) else {
+                        return Err(format!(
+                            "Missing value for argument: {called}"
+                        ));
+                    };
+                    Self::insert_argument(parsed, argument, val)?;
+                    // Take the values up to the next option
+                    if argument.multiple && separate {
+                        while let Some(val) =
+                            args.next_if(|a| !a.starts_with('-'))
+                        {
+                            Self::insert_argument(parsed, argument, val)?;
+                        }
+                    }
+                }
             }
             positionals.retain(|a| a.name != argument.name);
-        } else {
-            return Err(format!("Unknown argument: {arg}"));
         }
 
         Ok(None)
@@ -857,7 +1475,11 @@ impl ArgumentParser {
             if first_positional.is_none() {
                 *first_positional = Some(arg.clone());
             }
-            Self::insert_argument(parsed, argument, arg.to_string())?;
+            Self::insert_argument(parsed, argument, arg.clone())?;
+            // Arguments that take multiple values take all that are left
+            if argument.multiple {
+                return Ok(());
+            }
         } else {
             return Err(format!("Unexpected argument: {arg}"));
         }
@@ -882,33 +1504,60 @@ impl ArgumentParser {
             };
 
             if !options.iter().any(compare_strategy) {
-                return Err(format!("not a choice: {value}"));
+                // A set, sorted for consistent suggestions
+                let mut choices =
+                    options.iter().map(String::as_str).collect::<Vec<_>>();
+                choices.sort_unstable();
+                let similar = if argument.ignore_case {
+                    closest(choices, &value.to_lowercase())
+                } else {
+                    closest(choices, &value)
+                };
+                let msg = format!("not a choice: {value}");
+                return Err(with_suggestions(msg, "choices", &similar));
             }
         }
 
         match argument.arg_type {
-            ArgumentType::Integer => {
-                if value.parse::<isize>().is_err() {
-                    return Err(format!(
-                        "Expected integer value for '{}', \
+            ArgumentType::Integer if value.parse::<isize>().is_err() => {
+                return Err(format!(
+                    "Expected integer value for '{}', \
                     found {value}",
-                        argument.name,
-                    ));
-                }
+                    argument.name,
+                ));
             }
-            ArgumentType::Float => {
-                if value.parse::<f64>().is_err() {
-                    return Err(format!(
-                        "Expected float value for '{}', \
+            ArgumentType::Float if value.parse::<f64>().is_err() => {
+                return Err(format!(
+                    "Expected float value for '{}', \
                     found {value}",
-                        argument.name,
-                    ));
-                }
+                    argument.name,
+                ));
             }
             ArgumentType::Boolean if argument.name != "help" => unreachable!(),
+            ArgumentType::Count => unreachable!(),
             _ => {}
-        };
+        }
 
+        if let Some(range) = argument.range() {
+            // The value is known to be a number by now
+            let number = value.parse::<f64>().unwrap_or(f64::NAN);
+            if argument.min.is_some_and(|min| number < min)
+                || argument.max.is_some_and(|max| number > max)
+            {
+                return Err(format!(
+                    "Expected a value {range} for '{}', found {value}",
+                    argument.name,
+                ));
+            }
+        }
+
+        if argument.multiple {
+            parsed
+                .lists
+                .entry(argument.name.clone())
+                .or_default()
+                .push(value.clone());
+        }
         parsed.values.insert(argument.name.clone(), value);
         parsed.order.push(argument.name.clone());
         Ok(())
@@ -928,22 +1577,18 @@ impl ArgumentParser {
         };
 
         let name = Self::exec_name();
-        let mut help = format!("\"{first}\" is not a {name} command.");
-        let matches = self.closest_subcommands(&first, 3, 3);
+        let help = format!("\"{first}\" is not a {name} command.");
+        let matches = self.closest_subcommands(
+            &first,
+            SUGGESTION_DISTANCE,
+            SUGGESTION_COUNT,
+        );
 
         if matches.is_empty() {
             return Err(format!("{help} See '{name} --help'"));
         }
 
-        help.push_str("\n\nSimilar subcommands are:\n");
-
-        for sub in matches {
-            help.push_str("  ");
-            help.push_str(&sub);
-            help.push('\n');
-        }
-
-        Err(help)
+        Err(with_suggestions(help, "subcommands", &matches))
     }
 
     // Check all required arguments are provided, and set defaults otherwise
@@ -954,6 +1599,11 @@ impl ArgumentParser {
                 // If has default, use default
                 if let Some(default) = &arg.default {
                     parsed.values.insert(arg.name.clone(), default.clone());
+                    if arg.multiple {
+                        parsed
+                            .lists
+                            .insert(arg.name.clone(), vec![default.clone()]);
+                    }
                     continue;
                 }
 
@@ -991,34 +1641,9 @@ impl ArgumentParser {
     #[allow(clippy::missing_panics_doc)]
     #[must_use]
     pub fn help(&self) -> String {
-        let name = Self::exec_name();
-
         // First line, usage text
-        let mut help_text = format!(
-            "Usage: {name} {} [options]",
-            self.cmd_chain.as_ref().map_or("", |x| x.as_str())
-        );
-
-        // First line, usage text positional args
-        for positional in self.required_positionals() {
-            help_text.push(' ');
-            // If positional arg has default, display it as an optional arg
-            if positional.default.is_some() {
-                help_text.push_str("[ --");
-                help_text.push_str(&positional.name);
-                help_text.push(' ');
-                help_text.push_str(&positional.name.to_uppercase());
-                help_text.push_str(" ]");
-            } else {
-                help_text.push_str(&positional.name.to_uppercase());
-            }
-        }
-
-        // First line, usage text, subcommands if any
-        if !self.subcommands.is_empty() {
-            help_text.push_str(" [SUBCOMMAND]");
-        }
-
+        let mut help_text =
+            format!("Usage: {}", self.usage(&Self::exec_name()));
         help_text.push_str("\n\n");
 
         // Next line, descriptoin
@@ -1028,11 +1653,13 @@ impl ArgumentParser {
         help_text.push_str("\n\nOptions:\n");
 
         // List all options
-        for arg in &self.arguments {
+        for arg in self.arguments.iter().filter(|arg| !arg.hidden) {
             let has_default = arg.default.is_some();
-            let short = arg
-                .short
-                .map_or_else(|| " ".repeat(4), |c| format!("-{c}, "));
+            let short = match arg.short {
+                Some(c) if arg.short_only => format!("-{c}  "),
+                Some(c) => format!("-{c}, "),
+                None => " ".repeat(4),
+            };
 
             let required = if arg.required && !has_default {
                 " (required)"
@@ -1042,12 +1669,18 @@ impl ArgumentParser {
 
             // Spaces to ensure all help text starts on the same column
             let padding = " ".repeat(self.max_arg_len - arg.name.len() + 4);
+            let long = if arg.short_only {
+                " ".repeat(arg.name.len() + 2)
+            } else {
+                format!("--{}", arg.name)
+            };
 
             // {short} {name} {padding} {help} {required}
-            help_text.push_str(&format!(
-                "  {short}--{}{padding} {} {required}\n",
-                arg.name, arg.help
-            ));
+            let _ = writeln!(
+                help_text,
+                "  {short}{long}{padding} {} {required}",
+                arg.help
+            );
 
             // For options that have choices, list the choices on the next line
             if let Some(ref choices) = arg.choices {
@@ -1069,22 +1702,232 @@ impl ArgumentParser {
                 }
                 help_text.push_str(" ]\n");
             }
+
+            // Likewise for the bounds of numeric options
+            if let Some(range) = arg.range() {
+                let indent = 2 + 4 + 2 + self.max_arg_len + 1 + 4 + 2;
+                help_text.push_str(&" ".repeat(indent));
+                let _ = writeln!(help_text, "Range: {range}");
+            }
         }
 
         // List all subcommands and their descriptions
         if !self.subcommands.is_empty() {
             help_text.push_str("\nSubcommands:\n");
-            for subcommand in &self.subcommands {
-                help_text.push_str(&format!(
-                    "  {:<16} {}\n",
-                    subcommand.name, subcommand.parser.description
-                ));
+            for subcommand in self.visible_subcommands() {
+                let names = std::iter::once(&subcommand.name)
+                    .chain(&subcommand.aliases)
+                    .map(String::as_str)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let _ = writeln!(
+                    help_text,
+                    "  {names:<16} {}",
+                    subcommand.parser.description
+                );
             }
         }
 
         help_text
     }
 
+    /// The usage line of the parser, when run as the program `name`.
+    fn usage(&self, name: &str) -> String {
+        let mut usage = name.to_owned();
+        if let Some(chain) = &self.cmd_chain {
+            usage.push(' ');
+            usage.push_str(chain);
+        }
+        usage.push_str(" [options]");
+
+        // Positional args
+        for positional in self
+            .required_positionals()
+            .into_iter()
+            .filter(|a| !a.hidden)
+        {
+            usage.push(' ');
+            // If positional arg has default, display it as an optional arg
+            if positional.default.is_some() {
+                usage.push_str("[ --");
+                usage.push_str(&positional.name);
+                usage.push(' ');
+                usage.push_str(&positional.name.to_uppercase());
+                usage.push_str(" ]");
+            } else {
+                usage.push_str(&positional.name.to_uppercase());
+            }
+            if positional.multiple {
+                usage.push_str("...");
+            }
+        }
+
+        // Subcommands if any
+        if !self.subcommands.is_empty() {
+            usage.push_str(" [SUBCOMMAND]");
+        }
+        usage
+    }
+
+    fn visible_subcommands(&self) -> impl Iterator<Item = &SubCommand> {
+        self.subcommands.iter().filter(|cmd| !cmd.parser.hidden)
+    }
+
+    /// Renders reference documentation of the parser and, recursively, of
+    /// its subcommands that are not hidden, as pairs of a file name and the
+    /// contents of the page.
+    ///
+    /// `program` is the name the program is run by. The page of the parser
+    /// is named after it, and the pages of subcommands after the commands
+    /// that run them, as in `mini_git-diff.1`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mini_git::utils::argparse::{ArgumentParser, ArgumentType, DocFormat};
+    ///
+    /// let mut main_parser = ArgumentParser::new("Main Application");
+    /// let mut sub_parser = ArgumentParser::new("Say hello");
+    /// sub_parser.add_argument("name", ArgumentType::String)
+    ///     .required()
+    ///     .add_help("Who to greet");
+    /// main_parser.add_subcommand("greet", sub_parser);
+    /// main_parser.compile();
+    ///
+    /// let pages = main_parser.docs("app", DocFormat::Markdown);
+    /// assert_eq!(pages[0].0, "app.md");
+    /// assert_eq!(pages[1].0, "app-greet.md");
+    /// assert!(pages[1].1.starts_with("# app-greet\n\nSay hello\n"));
+    /// assert!(pages[1].1.contains("- `--name NAME`: Who to greet (required)"));
+    /// ```
+    #[must_use]
+    pub fn docs(
+        &self,
+        program: &str,
+        format: DocFormat,
+    ) -> Vec<(String, String)> {
+        let mut pages = vec![];
+        self.collect_docs(program, format, &mut pages);
+        pages
+    }
+
+    fn collect_docs(
+        &self,
+        program: &str,
+        format: DocFormat,
+        pages: &mut Vec<(String, String)>,
+    ) {
+        let page = match format {
+            DocFormat::Man => self.man_page(program),
+            DocFormat::Markdown => self.markdown_page(program),
+        };
+        let file_name =
+            format!("{}.{}", self.page_name(program), format.extension());
+        pages.push((file_name, page));
+        for subcommand in self.visible_subcommands() {
+            subcommand.parser.collect_docs(program, format, pages);
+        }
+    }
+
+    /// The name of the documentation page of the parser, like
+    /// `mini_git-diff`.
+    fn page_name(&self, program: &str) -> String {
+        match &self.cmd_chain {
+            Some(chain) => format!("{program}-{}", chain.replace(' ', "-")),
+            None => program.to_owned(),
+        }
+    }
+
+    fn markdown_page(&self, program: &str) -> String {
+        let mut page = format!("# {}\n\n", self.page_name(program));
+        let _ = writeln!(page, "{}\n", self.description);
+        let _ =
+            writeln!(page, "## Synopsis\n\n```\n{}\n```", self.usage(program));
+
+        page.push_str("\n## Options\n\n");
+        for arg in self.arguments.iter().filter(|arg| !arg.hidden) {
+            let mut line = format!("- `{}`: {}", arg.synopsis(), arg.help);
+            for note in arg.notes() {
+                line.push(' ');
+                line.push_str(&note);
+            }
+            let _ = writeln!(page, "{line}");
+        }
+
+        if self.visible_subcommands().next().is_some() {
+            page.push_str("\n## Commands\n\n");
+            for subcommand in self.visible_subcommands() {
+                let parser = &subcommand.parser;
+                let _ = writeln!(
+                    page,
+                    "- [`{}`]({}.md): {}",
+                    subcommand.name,
+                    parser.page_name(program),
+                    parser.description
+                );
+            }
+        }
+        page
+    }
+
+    fn man_page(&self, program: &str) -> String {
+        let name = self.page_name(program);
+        let mut page = format!(".TH \"{}\" \"1\"\n", name.to_uppercase());
+        let _ = writeln!(
+            page,
+            ".SH NAME\n{} \\- {}",
+            roff_escape(&name),
+            roff_escape(&self.description)
+        );
+        let _ = writeln!(
+            page,
+            ".SH SYNOPSIS\n.nf\n{}\n.fi",
+            roff_escape(&self.usage(program))
+        );
+
+        page.push_str(".SH OPTIONS\n");
+        for arg in self.arguments.iter().filter(|arg| !arg.hidden) {
+            let _ = writeln!(
+                page,
+                ".TP\n.B {}\n{}",
+                roff_escape(&arg.synopsis()),
+                roff_escape(&arg.help)
+            );
+            for note in arg.notes() {
+                let _ = writeln!(page, "{}", roff_escape(&note));
+            }
+        }
+
+        if self.visible_subcommands().next().is_some() {
+            page.push_str(".SH COMMANDS\n");
+            for subcommand in self.visible_subcommands() {
+                let _ = writeln!(
+                    page,
+                    ".TP\n.B {}\n{}",
+                    roff_escape(&subcommand.name),
+                    roff_escape(&subcommand.parser.description)
+                );
+            }
+        }
+
+        // Subcommands refer to the program, and the program to them
+        let see_also = if self.cmd_chain.is_some() {
+            vec![format!("{program}(1)")]
+        } else {
+            self.visible_subcommands()
+                .map(|cmd| format!("{}(1)", cmd.parser.page_name(program)))
+                .collect()
+        };
+        if !see_also.is_empty() {
+            let _ = writeln!(
+                page,
+                ".SH SEE ALSO\n{}",
+                roff_escape(&see_also.join(", "))
+            );
+        }
+        page
+    }
+
     fn exec_name() -> String {
         let name = std::env::args().next().expect("executable path");
         std::path::Path::new(&name)
@@ -1096,6 +1939,61 @@ impl ArgumentParser {
     }
 }
 
+/// The `candidates` within [`SUGGESTION_DISTANCE`] edits of `to`, closest
+/// first, and at most [`SUGGESTION_COUNT`] of them.
+fn closest<'a>(
+    candidates: impl IntoIterator<Item = &'a str>,
+    to: &str,
+) -> Vec<String> {
+    let mut similar = candidates
+        .into_iter()
+        .map(|candidate| (dl_distance(candidate, to), candidate))
+        .filter(|&(dist, _)| dist <= SUGGESTION_DISTANCE)
+        .collect::<Vec<_>>();
+    // Stable, so equally close candidates stay in order
+    similar.sort_by_key(|&(dist, _)| dist);
+    similar
+        .into_iter()
+        .take(SUGGESTION_COUNT)
+        .map(|(_, candidate)| candidate.to_owned())
+        .collect()
+}
+
+/// `msg`, followed by a list of the `similar` names of `kind`, if there are
+/// any.
+fn with_suggestions(mut msg: String, kind: &str, similar: &[String]) -> String {
+    if similar.is_empty() {
+        return msg;
+    }
+    let _ = write!(msg, "\n\nSimilar {kind} are:\n");
+    for name in similar {
+        let _ = writeln!(msg, "  {name}");
+    }
+    msg
+}
+
+/// Escapes `text` for roff, so that it is shown as is.
+fn roff_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for line in text.lines() {
+        if !escaped.is_empty() {
+            escaped.push('\n');
+        }
+        // Lines starting with these are requests
+        if line.starts_with(['.', '\'']) {
+            escaped.push_str("\\&");
+        }
+        for c in line.chars() {
+            match c {
+                '\\' => escaped.push_str("\\e"),
+                '-' => escaped.push_str("\\-"),
+                c => escaped.push(c),
+            }
+        }
+    }
+    escaped
+}
+
 /// Damerau–Levenshtein distance with adjacent transpositions
 /// This function is case sensitive
 #[allow(
@@ -1253,6 +2151,23 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_subcommand_parser() {
+        let mut parser = ArgumentParser::new("Main parser");
+        parser.add_subcommand("sub", ArgumentParser::new("Sub parser"));
+        parser.add_subcommand("other", ArgumentParser::new("Other parser"));
+        parser.compile();
+
+        let sub = parser.subcommand_parser("sub").expect("sub parser");
+        assert_eq!(sub.description(), "Sub parser");
+        assert!(sub.help().contains(" sub [options]"));
+        assert!(parser.subcommand_parser("missing").is_none());
+        assert_eq!(
+            parser.subcommand_names().collect::<Vec<_>>(),
+            ["sub", "other"]
+        );
+    }
+
     #[test]
     fn test_parse_args_basic() {
         let parser = create_basic_parser();
@@ -1294,6 +2209,69 @@ mod tests {
         assert_eq!(namespace.values.get("flag"), Some(&"true".to_string()));
     }
 
+    #[test]
+    fn test_parse_args_count_flag() {
+        let mut parser = create_basic_parser();
+        parser
+            .add_argument("verbose", ArgumentType::Count)
+            .short('v')
+            .add_help("Verbosity");
+        parser.compile();
+
+        let namespace = parser.parse_args(&["-n", "John"]).unwrap();
+        assert_eq!(namespace.count("verbose"), 0);
+        assert!(namespace.get("verbose").is_none());
+
+        let namespace = parser.parse_args(&["-v", "-n", "John"]).unwrap();
+        assert_eq!(namespace.count("verbose"), 1);
+
+        let args = ["-vv", "-n", "John", "--verbose", "-v"];
+        let namespace = parser.parse_args(&args).unwrap();
+        assert_eq!(namespace.count("verbose"), 4);
+        assert_eq!(namespace.get("verbose"), Some(&"4".to_string()));
+
+        let result = parser.parse_args(&["-vx", "-n", "John"]);
+        assert_eq!(result.unwrap_err(), "Unknown argument: -x");
+    }
+
+    #[test]
+    fn test_parse_args_bundled_short_flags() {
+        let mut parser = ArgumentParser::new("Test parser");
+        parser.add_argument("all", ArgumentType::Boolean).short('a');
+        parser
+            .add_argument("verbose", ArgumentType::Count)
+            .short('v');
+        parser
+            .add_argument("message", ArgumentType::String)
+            .short('m')
+            .required();
+        parser.compile();
+
+        let namespace = parser.parse_args(&["-am", "msg"]).unwrap();
+        assert_eq!(namespace["all"], "true");
+        assert_eq!(namespace["message"], "msg");
+        assert!(namespace.get("verbose").is_none());
+
+        let namespace = parser.parse_args(&["-vav", "-vm", "msg"]).unwrap();
+        assert_eq!(namespace["all"], "true");
+        assert_eq!(namespace.count("verbose"), 3);
+        assert_eq!(namespace["message"], "msg");
+
+        let result = parser.parse_args(&["-ma", "msg"]);
+        assert_eq!(
+            result.unwrap_err(),
+            "Option -m takes a value, so it must be last in -ma"
+        );
+        let result = parser.parse_args(&["-am"]);
+        assert_eq!(result.unwrap_err(), "Missing value for argument: -m");
+        let result = parser.parse_args(&["-ax", "-m", "msg"]);
+        assert_eq!(result.unwrap_err(), "Unknown argument: -x");
+
+        // Help is shown even in a bundle
+        let namespace = parser.parse_args(&["-ah"]).unwrap();
+        assert_eq!(namespace["help"], "-ah");
+    }
+
     #[test]
     fn test_parse_args_short_options() {
         let parser = create_basic_parser();
@@ -1312,6 +2290,123 @@ mod tests {
         assert_eq!(result.unwrap_err(), "Missing value for argument: name");
     }
 
+    #[test]
+    fn test_subcommand_aliases() {
+        let mut parser = ArgumentParser::new("Main parser");
+        let mut sub_parser = ArgumentParser::new("Switch branches");
+        sub_parser
+            .add_argument("branch", ArgumentType::String)
+            .required();
+        parser.add_subcommand_with_aliases("checkout", &["co"], sub_parser);
+        parser.add_subcommand("status", ArgumentParser::new("Show status"));
+        parser.compile();
+
+        for name in ["checkout", "co"] {
+            let namespace = parser.parse_args(&[name, "main"]).unwrap();
+            let (cmd, sub_ns) = namespace.subcommand().unwrap();
+            assert_eq!(cmd, "checkout");
+            assert_eq!(sub_ns["branch"], "main");
+        }
+
+        let sub = parser.subcommand_parser("co").expect("sub parser");
+        assert_eq!(sub.description(), "Switch branches");
+        assert!(sub.help().contains(" checkout [options]"));
+        assert_eq!(
+            parser.subcommand_names().collect::<Vec<_>>(),
+            ["checkout", "status"]
+        );
+        assert_eq!(parser.subcommand_aliases("checkout"), ["co"]);
+        assert!(parser.subcommand_aliases("status").is_empty());
+        assert!(parser.help().contains(" checkout, co     Switch branches"));
+
+        assert_eq!(parser.closest_subcommands("cp", 1, 3), ["co"]);
+        assert_eq!(parser.closest_subcommands("chekout", 1, 3), ["checkout"]);
+        assert_eq!(parser.closest_subcommands("stats", 1, 3), ["status"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Subcommand \"co\" already exists.")]
+    fn test_subcommand_alias_conflict() {
+        let mut parser = ArgumentParser::new("Main parser");
+        parser.add_subcommand("co", ArgumentParser::new("Commit"));
+        parser.add_subcommand_with_aliases(
+            "checkout",
+            &["co"],
+            ArgumentParser::new("Switch branches"),
+        );
+    }
+
+    #[test]
+    fn test_hidden_subcommand() {
+        let mut parser = ArgumentParser::new("Main parser");
+        let mut hidden = ArgumentParser::new("Internal parser");
+        hidden.hidden();
+        parser.add_subcommand("internal", hidden);
+        parser.add_subcommand("status", ArgumentParser::new("Show status"));
+        parser.compile();
+
+        let namespace = parser.parse_args(&["internal"]).unwrap();
+        assert_eq!(namespace.subcommand().unwrap().0, "internal");
+        assert!(!parser.help().contains("internal"));
+        assert!(parser.closest_subcommands("internl", 3, 3).is_empty());
+        let pages = parser.docs("app", DocFormat::Markdown);
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[1].0, "app-status.md");
+    }
+
+    #[test]
+    fn test_docs() {
+        let mut parser = ArgumentParser::new("Main parser");
+        let mut sub_parser = ArgumentParser::new(".Starts with a dot");
+        sub_parser
+            .add_argument("mode", ArgumentType::String)
+            .short('m')
+            .default("fast")
+            .choices(&["fast", "slow"])
+            .add_help("How to go");
+        sub_parser
+            .add_argument("verbose", ArgumentType::Count)
+            .short('v')
+            .add_help("Say more");
+        parser.add_subcommand("run-it", sub_parser);
+        parser.compile();
+
+        let pages = parser.docs("app", DocFormat::Markdown);
+        assert_eq!(pages[0].0, "app.md");
+        assert!(pages[0].1.contains(
+            "\n## Commands\n\n- [`run-it`](app-run-it.md): .Starts with a dot\n"
+        ));
+        assert_eq!(
+            pages[1].1,
+            "# app-run-it\n\n.Starts with a dot\n\n\
+            ## Synopsis\n\n```\napp run-it [options]\n```\n\n\
+            ## Options\n\n\
+            - `-h, --help`: Display this help message\n\
+            - `-m, --mode MODE`: How to go Defaults to fast. \
+            Choices: fast, slow.\n\
+            - `-v, --verbose`: Say more\n"
+        );
+
+        let pages = parser.docs("app", DocFormat::Man);
+        assert_eq!(pages[1].0, "app-run-it.1");
+        assert_eq!(
+            pages[1].1,
+            ".TH \"APP-RUN-IT\" \"1\"\n\
+            .SH NAME\napp\\-run\\-it \\- \\&.Starts with a dot\n\
+            .SH SYNOPSIS\n.nf\napp run\\-it [options]\n.fi\n\
+            .SH OPTIONS\n\
+            .TP\n.B \\-h, \\-\\-help\nDisplay this help message\n\
+            .TP\n.B \\-m, \\-\\-mode MODE\nHow to go\n\
+            Defaults to fast.\nChoices: fast, slow.\n\
+            .TP\n.B \\-v, \\-\\-verbose\nSay more\n\
+            .SH SEE ALSO\napp(1)\n"
+        );
+        assert!(pages[0]
+            .1
+            .contains(".SH COMMANDS\n.TP\n.B run\\-it\n\\&.Starts"));
+        assert!(pages[0].1.ends_with(".SH SEE ALSO\napp\\-run\\-it(1)\n"));
+    }
+
     #[test]
     fn test_parse_args_with_subcommand() {
         let mut parser = create_basic_parser();
@@ -1386,6 +2481,72 @@ mod tests {
         assert_eq!(namespace.values.get("name"), Some(&"Jane".to_string()));
     }
 
+    #[test]
+    fn test_parse_args_multiple_positional() {
+        let mut parser = ArgumentParser::new("Test parser");
+        parser
+            .add_argument("paths", ArgumentType::String)
+            .required()
+            .multiple();
+        parser
+            .add_argument("force", ArgumentType::Boolean)
+            .short('f');
+        parser.compile();
+
+        let namespace = parser.parse_args(&["a", "-f", "b", "c"]).unwrap();
+        assert_eq!(namespace.get_all("paths").unwrap(), ["a", "b", "c"]);
+        assert_eq!(namespace.get("paths"), Some(&"c".to_string()));
+        assert_eq!(namespace.get("force"), Some(&"true".to_string()));
+
+        let result = parser.parse_args(&["-f"]);
+        assert_eq!(result.unwrap_err(), "Missing required argument: paths");
+    }
+
+    #[test]
+    fn test_parse_args_multiple_option() {
+        let mut parser = ArgumentParser::new("Test parser");
+        parser
+            .add_argument("num", ArgumentType::Integer)
+            .short('n')
+            .multiple();
+        parser.add_argument("name", ArgumentType::String).required();
+        parser.compile();
+
+        let namespace = parser
+            .parse_args(&["-n", "1", "2", "--name", "x", "--num", "3"])
+            .unwrap();
+        assert_eq!(namespace.get_all("num").unwrap(), ["1", "2", "3"]);
+        assert_eq!(namespace["name"], "x");
+
+        let namespace = parser.parse_args(&["x"]).unwrap();
+        assert!(namespace.get_all("num").is_none());
+
+        assert!(parser.parse_args(&["x", "-n", "1", "a"]).is_err());
+        let result = parser.parse_args(&["x", "-n"]);
+        assert_eq!(result.unwrap_err(), "Missing value for argument: -n");
+    }
+
+    #[test]
+    fn test_parse_args_multiple_default() {
+        let mut parser = ArgumentParser::new("Test parser");
+        parser
+            .add_argument("paths", ArgumentType::String)
+            .required()
+            .default(".")
+            .multiple();
+        parser.compile();
+
+        let namespace = parser.parse_args(&[]).unwrap();
+        assert_eq!(namespace.get_all("paths").unwrap(), ["."]);
+        assert!(parser.help().contains("[ --paths PATHS ]..."));
+    }
+
+    #[test]
+    #[should_panic(expected = "Flags cannot take multiple values")]
+    fn test_multiple_boolean() {
+        Argument::new("flag", ArgumentType::Boolean).multiple();
+    }
+
     #[test]
     fn test_subcommand_chain() {
         let mut main_parser = ArgumentParser::new("Main parser");
@@ -1651,6 +2812,204 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_hidden_argument() {
+        let mut parser = create_basic_parser();
+        parser
+            .add_argument("internal", ArgumentType::String)
+            .required()
+            .default("")
+            .hidden()
+            .add_help("Internal");
+        parser.compile();
+
+        let namespace = parser
+            .parse_args(&["-n", "John", "--internal", "x"])
+            .unwrap();
+        assert_eq!(namespace["internal"], "x");
+        let help = parser.help();
+        assert!(!help.contains("internal") && !help.contains("INTERNAL"));
+        let pages = parser.docs("app", DocFormat::Markdown);
+        assert!(!pages[0].1.contains("internal"));
+    }
+
+    #[test]
+    fn test_parse_args_range() {
+        let mut parser = ArgumentParser::new("Test parser");
+        parser.add_argument("jobs", ArgumentType::Integer).min(1.0);
+        parser
+            .add_argument("ratio", ArgumentType::Float)
+            .min(0.0)
+            .max(1.0);
+        parser
+            .add_argument("depth", ArgumentType::Integer)
+            .max(10.0)
+            .multiple();
+        parser.compile();
+
+        let args = ["--jobs", "1", "--ratio", "0.5", "--depth", "-3", "10"];
+        let namespace = parser.parse_args(&args).unwrap();
+        assert_eq!(namespace["jobs"], "1");
+        assert_eq!(namespace["ratio"], "0.5");
+
+        let bad_args: [(&[&str], &str); 4] = [
+            (
+                &["--jobs", "0"],
+                "Expected a value at least 1 for 'jobs', found 0",
+            ),
+            (
+                &["--ratio", "1.01"],
+                "Expected a value from 0 to 1 for 'ratio', found 1.01",
+            ),
+            (
+                &["--ratio", "-0.5"],
+                "Expected a value from 0 to 1 for 'ratio', found -0.5",
+            ),
+            (
+                &["--depth", "1", "11"],
+                "Expected a value at most 10 for 'depth', found 11",
+            ),
+        ];
+        for (args, err) in bad_args {
+            assert_eq!(parser.parse_args(args).unwrap_err(), err);
+        }
+
+        let help = parser.help();
+        assert!(help.contains("Range: at least 1\n"));
+        assert!(help.contains("Range: from 0 to 1\n"));
+        let pages = parser.docs("app", DocFormat::Markdown);
+        assert!(pages[0].1.contains(
+            "- `--depth DEPTH...`: No help provided \
+            Range: at most 10.\n"
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "Bounds can only be used with numeric arguments")]
+    fn test_range_string() {
+        Argument::new("name", ArgumentType::String).min(0.0);
+    }
+
+    #[test]
+    fn test_attached_and_implicit_values() {
+        let mut parser = ArgumentParser::new("Test parser");
+        parser
+            .add_argument("path", ArgumentType::String)
+            .required()
+            .default("");
+        parser
+            .add_argument("porcelain", ArgumentType::String)
+            .choices(&["v1", "v2"])
+            .implicit_value("v1");
+        parser.add_argument("jobs", ArgumentType::Integer);
+        parser.add_argument("z", ArgumentType::Boolean);
+        parser.compile();
+
+        // The argument after an option with an implicit value is not its
+        // value
+        let namespace = parser.parse_args(&["--porcelain", "v2"]).unwrap();
+        assert_eq!(namespace["porcelain"], "v1");
+        assert_eq!(namespace["path"], "v2");
+
+        let namespace =
+            parser.parse_args(&["--porcelain=v2", "--jobs=4"]).unwrap();
+        assert_eq!(namespace["porcelain"], "v2");
+        assert_eq!(namespace["jobs"], "4");
+        assert!(namespace.get("path").is_some_and(String::is_empty));
+
+        assert!(parser.parse_args(&["--porcelain=v3"]).is_err());
+        assert!(parser.parse_args(&["--jobs=x"]).is_err());
+        assert_eq!(
+            parser.parse_args(&["--z=1"]).unwrap_err(),
+            "Option --z takes no value"
+        );
+
+        let pages = parser.docs("app", DocFormat::Markdown);
+        assert!(pages[0].1.contains("`--porcelain[=PORCELAIN]`"));
+        assert!(pages[0].1.contains("Given alone, it is v1."));
+    }
+
+    #[test]
+    #[should_panic(expected = "Flags cannot have an implicit value")]
+    fn test_implicit_value_flag() {
+        Argument::new("all", ArgumentType::Boolean).implicit_value("yes");
+    }
+
+    #[test]
+    fn test_help_layout() {
+        let mut parser = ArgumentParser::new("Layout");
+        parser
+            .add_argument("mode", ArgumentType::String)
+            .optional()
+            .short('m')
+            .choices(&["fast", "slow"])
+            .add_help("How to run");
+        parser
+            .add_argument("path", ArgumentType::String)
+            .optional()
+            .short_only('C')
+            .add_help("Where to run");
+        parser.add_subcommand("sub", ArgumentParser::new("Sub parser"));
+        parser.compile();
+
+        // Choices are indented under the help of their option
+        let help = parser.help();
+        assert!(
+            help.contains(
+                "\n  -h, --help     Display this help message \n\
+            \x20 -m, --mode     How to run \n\
+            \x20                  Choices: [ fast, slow ]\n\
+            \x20 -C             Where to run \n"
+            ),
+            "{help}"
+        );
+        assert!(help.contains("\n  sub              Sub parser\n"), "{help}");
+    }
+
+    #[test]
+    fn test_suggestions() {
+        let mut parser = create_basic_parser();
+        parser.add_argument("verbose", ArgumentType::Boolean);
+        parser.add_argument("verify", ArgumentType::Boolean);
+        parser
+            .add_argument("version", ArgumentType::Boolean)
+            .hidden();
+        parser
+            .add_argument("ops", ArgumentType::String)
+            .choices(&["add", "subtract", "multiply", "divide"]);
+        parser
+            .add_argument("color", ArgumentType::String)
+            .choices(&["auto", "always", "never"])
+            .ignore_case();
+        parser.compile();
+
+        let result = parser.parse_args(&["-n", "x", "--verbos"]);
+        assert_eq!(
+            result.unwrap_err(),
+            "Unknown argument: --verbos\n\n\
+            Similar options are:\n  --verbose\n  --verify\n"
+        );
+        // Hidden options are not suggested
+        let result = parser.parse_args(&["-n", "x", "--versio"]);
+        assert_eq!(
+            result.unwrap_err(),
+            "Unknown argument: --versio\n\nSimilar options are:\n  --verify\n"
+        );
+
+        let result = parser.parse_args(&["-n", "x", "--ops", "ad"]);
+        assert_eq!(
+            result.unwrap_err(),
+            "not a choice: ad\n\nSimilar choices are:\n  add\n"
+        );
+        let result = parser.parse_args(&["-n", "x", "--color", "NEVR"]);
+        assert_eq!(
+            result.unwrap_err(),
+            "not a choice: NEVR\n\nSimilar choices are:\n  never\n"
+        );
+        let result = parser.parse_args(&["-n", "x", "--ops", "power"]);
+        assert_eq!(result.unwrap_err(), "not a choice: power");
+    }
+
     #[test]
     fn test_dl_distance() {
         let data = [
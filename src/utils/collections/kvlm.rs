@@ -2,6 +2,15 @@
 //!
 //! This module provides functionality for parsing and serializing
 //! Key Value List with Messages (KVLM) data format.
+//!
+//! Serializing parsed data gives back the same bytes, so that the name of a
+//! re-serialized object does not change. The headers keep their order, even
+//! when a key appears more than once or between other keys, and their values
+//! are kept as bytes, whatever their encoding. Values that span lines, like a
+//! `gpgsig`, are stored without the space that starts each continuation
+//! line, which is added back when serializing.
+
+use std::collections::HashMap;
 
 use crate::utils::collections::ordered_map::OrderedMap;
 
@@ -30,6 +39,10 @@ pub const NEWLINE_BYTE: u8 = b'\n';
 #[derive(Debug)]
 pub struct KVLM {
     store: OrderedMap<Keys, Values>,
+    // The key of each header, and the index of its value among the values
+    // of that key, in the order the headers appear. This only orders the
+    // values in `store`, values without a position follow in store order
+    headers: Vec<(Vec<u8>, usize)>,
 }
 
 #[derive(Debug, Hash, PartialEq, Eq, Clone)]
@@ -64,6 +77,7 @@ impl KVLM {
     pub fn new() -> Self {
         Self {
             store: OrderedMap::new(),
+            headers: Vec::new(),
         }
     }
 
@@ -135,26 +149,37 @@ impl KVLM {
                 }
             }
 
-            let value = data[(space_idx + 1)..end]
-                .iter()
-                .map(|b| char::from(*b))
-                .collect::<String>()
-                .replace("\n ", "\n") // Drop leading spaces on lines
-                .into_bytes();
-
-            if let Some(v) = kvlm.store.get_mut(&key) {
-                let Values::Value(ref mut list) = v else {
-                    unreachable!();
-                };
-                list.push(value);
-            } else {
-                kvlm.store.insert(key, Values::Value(vec![value]));
+            // Drop the space that starts each continuation line
+            let mut value = Vec::with_capacity(end - space_idx);
+            let mut continued = false;
+            for &byte in &data[(space_idx + 1)..end] {
+                if !continued {
+                    value.push(byte);
+                }
+                continued = byte == NEWLINE_BYTE;
             }
 
+            kvlm.push_value(key, value);
+
             start = end + 1;
         }
     }
 
+    /// Adds `value` as the last header, of key `key`.
+    fn push_value(&mut self, key: Keys, value: Vec<u8>) {
+        let Keys::Key(name) = &key else {
+            unreachable!("the message is not a header");
+        };
+        let name = name.clone();
+        let Values::Value(values) =
+            self.store.entry(key).or_insert(Values::Value(vec![]))
+        else {
+            unreachable!("headers have values");
+        };
+        self.headers.push((name, values.len()));
+        values.push(value);
+    }
+
     /// Serializes the KVLM instance into a byte vector
     ///
     /// # Returns
@@ -172,24 +197,18 @@ impl KVLM {
     pub fn serialize(&self) -> Vec<u8> {
         let mut res = vec![];
 
-        let items = self.store.iter().filter_map(|(k, v)| match (&k, v) {
-            (Keys::Key(key), Values::Value(values)) => Some((key, values)),
-            _ => None,
-        });
-
         // Fields
-        for (key, values) in items {
-            let values = values
-                .iter()
-                .flat_map(|vec: &Vec<u8>| String::from_utf8(vec.clone()))
-                .map(|s| s.replace('\n', "\n "))
-                .map(String::into_bytes);
-            for value in values {
-                res.extend_from_slice(key);
-                res.push(SPACE_BYTE);
-                res.extend_from_slice(&value);
-                res.push(NEWLINE_BYTE);
+        for (key, value) in self.headers() {
+            res.extend_from_slice(key);
+            res.push(SPACE_BYTE);
+            for &byte in value {
+                res.push(byte);
+                // Continuation lines start with a space
+                if byte == NEWLINE_BYTE {
+                    res.push(SPACE_BYTE);
+                }
             }
+            res.push(NEWLINE_BYTE);
         }
 
         // Message
@@ -232,6 +251,41 @@ impl KVLM {
         }
     }
 
+    /// The headers, as key and value, in the order they appear.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mini_git::utils::collections::kvlm::KVLM;
+    /// let kvlm = KVLM::parse(b"parent a\nauthor b\nparent c\n\nMessage").unwrap();
+    /// let keys: Vec<_> = kvlm.headers().map(|(key, _)| key).collect();
+    /// assert_eq!(keys, [b"parent", b"author", b"parent"]);
+    /// ```
+    pub fn headers(&self) -> impl Iterator<Item = (&[u8], &[u8])> {
+        let mut placed: HashMap<&[u8], usize> = HashMap::new();
+        let mut headers = Vec::with_capacity(self.headers.len());
+        for (key, index) in &self.headers {
+            let value = self.get_key(key).and_then(|values| values.get(*index));
+            debug_assert!(value.is_some(), "header without a value");
+            if let Some(value) = value {
+                headers.push((key.as_slice(), value.as_slice()));
+                *placed.entry(key).or_default() += 1;
+            }
+        }
+
+        // Values stored without a position follow, so none are left out
+        for (key, values) in &self.store {
+            let (Keys::Key(key), Values::Value(values)) = (key, values) else {
+                continue;
+            };
+            let start = placed.get(key.as_slice()).copied().unwrap_or(0);
+            let rest = values.get(start..).unwrap_or_default();
+            headers.extend(rest.iter().map(|v| (key.as_slice(), v.as_slice())));
+        }
+
+        headers.into_iter()
+    }
+
     /// Retrieves the message content of the KVLM instance
     ///
     /// # Returns
@@ -386,4 +440,84 @@ mod tests {
 
         assert_eq!(combined[..len], serialized[..len]);
     }
+
+    fn assert_round_trip(data: &[u8]) -> KVLM {
+        let kvlm = KVLM::parse(data).expect("Should parse");
+        assert_eq!(kvlm.serialize(), data);
+        kvlm
+    }
+
+    #[test]
+    fn test_kvlm_round_trip() {
+        assert_round_trip(&TEST_DATA.concat());
+    }
+
+    #[test]
+    fn test_kvlm_round_trip_keeps_header_order() {
+        let data = b"tree c14e2652b22f8b78b29ff16c9b08a5a07930c147
+parent aea388a7ae24d49a0206941306e8a8af65b66eaa
+parent 4b825dc642cb6eb9a060e54bf8d69288fbee4904
+author John Doe <john@doe.com> 1845675023 +0200
+committer John Doe <john@doe.com> 1845675044 +0200
+mergetag object aea388a7ae24d49a0206941306e8a8af65b66eaa
+ type commit
+ tag v1.0
+ tagger John Doe <john@doe.com> 1845675023 +0200
+ \n \n Release 1.0
+parent c14e2652b22f8b78b29ff16c9b08a5a07930c147
+encoding ISO-8859-1
+
+Merge
+";
+        let kvlm = assert_round_trip(data);
+
+        let parents = kvlm.get_key(b"parent").expect("Should have parents");
+        assert_eq!(parents.len(), 3);
+        assert_eq!(
+            parents[2],
+            b"c14e2652b22f8b78b29ff16c9b08a5a07930c147".to_vec()
+        );
+
+        let keys: Vec<_> = kvlm.headers().map(|(key, _)| key).collect();
+        assert_eq!(
+            keys,
+            [
+                &b"tree"[..],
+                b"parent",
+                b"parent",
+                b"author",
+                b"committer",
+                b"mergetag",
+                b"parent",
+                b"encoding"
+            ]
+        );
+
+        let mergetag = &kvlm.get_key(b"mergetag").expect("Should parse")[0];
+        assert!(mergetag.ends_with(b"+0200\n\n\nRelease 1.0"));
+    }
+
+    #[test]
+    fn test_kvlm_round_trip_keeps_bytes() {
+        // Latin-1 and invalid UTF-8 in headers and message
+        let data = b"tree c14e2652b22f8b78b29ff16c9b08a5a07930c147
+author J\xf6rg <j@example.com> 1845675023 +0200
+gpgsig -----BEGIN PGP SIGNATURE-----
+ \xff\xfe
+ 
+ -----END PGP SIGNATURE-----
+
+Caf\xe9
+";
+        let kvlm = assert_round_trip(data);
+        assert_eq!(
+            kvlm.get_key(b"author").expect("Should parse")[0],
+            b"J\xf6rg <j@example.com> 1845675023 +0200".to_vec()
+        );
+        assert_eq!(
+            kvlm.get_key(b"gpgsig").expect("Should parse")[0],
+            b"-----BEGIN PGP SIGNATURE-----\n\xff\xfe\n\n-----END PGP SIGNATURE-----"
+                .to_vec()
+        );
+    }
 }
@@ -0,0 +1,194 @@
+//! A cache bounded by the total size of its values, which evicts the least
+//! recently used entries first.
+//!
+//! ## Example
+//!
+//! ```rust
+//! use mini_git::utils::collections::lru_cache::LruCache;
+//!
+//! let mut cache = LruCache::new(10);
+//! cache.insert("a", "aaaa", 4);
+//! cache.insert("b", "bbbb", 4);
+//!
+//! // Using "a" makes "b" the least recently used entry
+//! assert_eq!(cache.get(&"a"), Some(&"aaaa"));
+//! cache.insert("c", "cccc", 4);
+//!
+//! assert_eq!(cache.get(&"b"), None);
+//! assert_eq!(cache.get(&"a"), Some(&"aaaa"));
+//! assert_eq!(cache.get(&"c"), Some(&"cccc"));
+//! ```
+
+#![allow(clippy::module_name_repetitions)]
+
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+/// A least recently used cache, bounded by the total weight of its values.
+///
+/// Each value is inserted with a weight, like its size in bytes. When the
+/// total weight goes over the capacity, the least recently used entries are
+/// evicted until it fits again.
+///
+/// # Type Parameters
+///
+/// - `K`: The key type. Must implement `Hash`, `Eq`, and `Clone`.
+/// - `V`: The value type.
+#[derive(Debug)]
+pub struct LruCache<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    entries: HashMap<K, Entry<V>>,
+    /// The keys by the time they were last used, oldest first.
+    order: BTreeMap<u64, K>,
+    tick: u64,
+    weight: usize,
+    capacity: usize,
+}
+
+#[derive(Debug)]
+struct Entry<V> {
+    value: V,
+    weight: usize,
+    used: u64,
+}
+
+impl<K, V> LruCache<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    /// Creates an empty cache holding values of at most `capacity` total
+    /// weight.
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+            weight: 0,
+            capacity,
+        }
+    }
+
+    /// Returns the value of `key`, and marks it as the most recently used.
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let tick = self.next_tick();
+        let entry = self.entries.get_mut(key)?;
+        self.order.remove(&entry.used);
+        self.order.insert(tick, key.clone());
+        entry.used = tick;
+        Some(&entry.value)
+    }
+
+    /// Inserts `value` with `weight` for `key`, replacing any previous value,
+    /// and evicts the least recently used entries to fit it.
+    ///
+    /// A value heavier than the capacity is not cached.
+    pub fn insert(&mut self, key: K, value: V, weight: usize) {
+        self.remove(&key);
+        if weight > self.capacity {
+            return;
+        }
+
+        while self.weight + weight > self.capacity {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&oldest) {
+                self.weight -= entry.weight;
+            }
+        }
+
+        let used = self.next_tick();
+        self.order.insert(used, key.clone());
+        self.entries.insert(
+            key,
+            Entry {
+                value,
+                weight,
+                used,
+            },
+        );
+        self.weight += weight;
+    }
+
+    /// Removes `key` from the cache, returning its value.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let entry = self.entries.remove(key)?;
+        self.order.remove(&entry.used);
+        self.weight -= entry.weight;
+        Some(entry.value)
+    }
+
+    /// Returns the number of entries in the cache.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns whether the cache has no entries.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the total weight of the values in the cache.
+    #[must_use]
+    pub fn weight(&self) -> usize {
+        self.weight
+    }
+
+    /// Returns the largest total weight the cache holds.
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eviction_order() {
+        let mut cache = LruCache::new(3);
+        cache.insert(1, 'a', 1);
+        cache.insert(2, 'b', 1);
+        cache.insert(3, 'c', 1);
+        assert_eq!(cache.get(&1), Some(&'a'));
+
+        cache.insert(4, 'd', 2);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.weight(), 3);
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&3), None);
+        assert_eq!(cache.get(&1), Some(&'a'));
+        assert_eq!(cache.get(&4), Some(&'d'));
+    }
+
+    #[test]
+    fn test_replace_and_oversized() {
+        let mut cache = LruCache::new(4);
+        cache.insert("key", 1, 2);
+        cache.insert("key", 2, 3);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.weight(), 3);
+        assert_eq!(cache.get(&"key"), Some(&2));
+
+        // Too heavy to cache, and the old value is dropped
+        cache.insert("key", 3, 5);
+        assert!(cache.is_empty());
+        assert_eq!(cache.weight(), 0);
+
+        cache.insert("other", 4, 4);
+        assert_eq!(cache.remove(&"other"), Some(4));
+        assert_eq!(cache.remove(&"other"), None);
+        assert_eq!(cache.capacity(), 4);
+    }
+}
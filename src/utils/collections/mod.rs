@@ -5,6 +5,9 @@
 //!   offer a map that maintains insertion order.
 //! - [`kvlm::KVLM`], A Key-Value List with Messages, a data structure used by git to
 //!   store commits and tags
+//! - [`lru_cache::LruCache`], a cache bounded by the total size of its values, that
+//!   evicts the least recently used entries first
 
 pub mod kvlm;
+pub mod lru_cache;
 pub mod ordered_map;
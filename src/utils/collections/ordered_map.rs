@@ -4,6 +4,8 @@
 //!
 //! - Fast key-value lookups (average O(1))
 //! - Ordered iteration based on insertion sequence
+//! - Removal that keeps the order of the remaining entries (O(n))
+//! - An entry API, like the one of `HashMap`
 //!
 //! ## Example
 //!
@@ -28,6 +30,7 @@
 
 #![allow(clippy::module_name_repetitions)]
 
+use std::borrow::Borrow;
 use std::collections::HashMap;
 use std::hash::Hash;
 
@@ -55,22 +58,59 @@ use std::hash::Hash;
 /// let keys: Vec<_> = map.iter().map(|(k, _)| k).collect();
 /// assert_eq!(keys, vec![&"a", &"b"]);
 /// ```
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct OrderedMap<K, V>
 where
     K: Hash + Eq + Clone,
 {
-    map: HashMap<K, V>,
-    list: Vec<K>,
+    // The index of each key in entries
+    map: HashMap<K, usize>,
+    entries: Vec<(K, V)>,
 }
 
 /// An iterator over the entries of an `OrderedMap`.
-pub struct OrderedMapIter<'a, K, V>
+pub struct OrderedMapIter<'a, K, V> {
+    entries: std::slice::Iter<'a, (K, V)>,
+}
+
+/// An iterator over the entries of an `OrderedMap`, with mutable values.
+pub struct OrderedMapIterMut<'a, K, V> {
+    entries: std::slice::IterMut<'a, (K, V)>,
+}
+
+/// An iterator that moves the entries out of an `OrderedMap`.
+pub struct OrderedMapIntoIter<K, V> {
+    entries: std::vec::IntoIter<(K, V)>,
+}
+
+/// A view into a single entry of an `OrderedMap`, which is either occupied
+/// or vacant. It is made by [`OrderedMap::entry`].
+pub enum Entry<'a, K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    /// An entry for a key that is in the map
+    Occupied(OccupiedEntry<'a, K, V>),
+    /// An entry for a key that is not in the map
+    Vacant(VacantEntry<'a, K, V>),
+}
+
+/// An entry for a key that is in an `OrderedMap`.
+pub struct OccupiedEntry<'a, K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    map: &'a mut OrderedMap<K, V>,
+    index: usize,
+}
+
+/// An entry for a key that is not in an `OrderedMap`.
+pub struct VacantEntry<'a, K, V>
 where
     K: Hash + Eq + Clone,
 {
-    map: &'a OrderedMap<K, V>,
-    idx: usize,
+    map: &'a mut OrderedMap<K, V>,
+    key: K,
 }
 
 impl<K, V> Default for OrderedMap<K, V>
@@ -87,7 +127,6 @@ where
     /// let map: OrderedMap<String, i32> = OrderedMap::default();
     /// assert!(map.iter().next().is_none());
     /// ```
-    #[must_use]
     fn default() -> Self {
         Self::new()
     }
@@ -110,10 +149,65 @@ where
     pub fn new() -> Self {
         Self {
             map: HashMap::new(),
-            list: Vec::new(),
+            entries: Vec::new(),
         }
     }
 
+    /// Creates a new, empty `OrderedMap` with room for at least `capacity`
+    /// entries before it reallocates.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mini_git::utils::collections::ordered_map::OrderedMap;
+    ///
+    /// let map: OrderedMap<&str, i32> = OrderedMap::with_capacity(10);
+    /// assert!(map.capacity() >= 10);
+    /// ```
+    #[must_use]
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            map: HashMap::with_capacity(capacity),
+            entries: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// The number of entries the map can hold without reallocating.
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.map.capacity().min(self.entries.capacity())
+    }
+
+    /// Reserves room for at least `additional` more entries.
+    pub fn reserve(&mut self, additional: usize) {
+        self.map.reserve(additional);
+        self.entries.reserve(additional);
+    }
+
+    /// The number of entries in the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mini_git::utils::collections::ordered_map::OrderedMap;
+    ///
+    /// let mut map = OrderedMap::new();
+    /// assert_eq!(map.len(), 0);
+    /// map.insert("a", 1);
+    /// map.insert("a", 2);
+    /// assert_eq!(map.len(), 1);
+    /// ```
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the map has no entries.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
     /// Checks whether the key exists in the map
     ///
     /// # Examples
@@ -131,7 +225,7 @@ where
     /// ```
     pub fn contains_key<Q>(&self, key: &Q) -> bool
     where
-        K: core::borrow::Borrow<Q>,
+        K: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
         self.map.contains_key(key)
@@ -156,11 +250,14 @@ where
     /// assert_eq!(map.get(&"a"), Some(&3));
     /// ```
     pub fn insert(&mut self, key: K, value: V) {
-        if !self.map.contains_key(&key) {
-            self.list.push(key.clone());
+        match self.entry(key) {
+            Entry::Occupied(mut entry) => {
+                entry.insert(value);
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+            }
         }
-
-        self.map.insert(key, value);
     }
 
     /// Retrieves a reference to the value associated with the given key.
@@ -178,8 +275,13 @@ where
     /// assert_eq!(map.get(&"a"), Some(&1));
     /// assert_eq!(map.get(&"b"), None);
     /// ```
-    pub fn get(&self, key: &K) -> Option<&V> {
-        self.map.get(key)
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let &index = self.map.get(key)?;
+        Some(&self.entries[index].1)
     }
 
     /// Retrieves a mutable reference to the value associated with the given key.
@@ -198,8 +300,71 @@ where
     /// *map.get_mut(&"a").unwrap() = 42;
     /// assert_eq!(map.get(&"a"), Some(&42));
     /// ```
-    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
-        self.map.get_mut(key)
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let &index = self.map.get(key)?;
+        Some(&mut self.entries[index].1)
+    }
+
+    /// Removes the entry of the given key from the map, and returns its
+    /// value, if it was in the map.
+    ///
+    /// The remaining entries keep their order, so this takes time linear in
+    /// the number of entries after the one removed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mini_git::utils::collections::ordered_map::OrderedMap;
+    ///
+    /// let mut map: OrderedMap<_, _> = [("a", 1), ("b", 2), ("c", 3)].into_iter().collect();
+    ///
+    /// assert_eq!(map.remove(&"b"), Some(2));
+    /// assert_eq!(map.remove(&"b"), None);
+    /// assert_eq!(map.keys().collect::<Vec<_>>(), [&"a", &"c"]);
+    /// ```
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let index = self.map.remove(key)?;
+        Some(self.remove_index(index))
+    }
+
+    /// Removes the entry at `index`, whose key is no longer in `map`.
+    fn remove_index(&mut self, index: usize) -> V {
+        let (_, value) = self.entries.remove(index);
+        for (key, _) in &self.entries[index..] {
+            if let Some(i) = self.map.get_mut(key) {
+                *i -= 1;
+            }
+        }
+        value
+    }
+
+    /// Gets the entry of the given key in the map, to read, insert or
+    /// change it in place.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mini_git::utils::collections::ordered_map::OrderedMap;
+    ///
+    /// let mut counts = OrderedMap::new();
+    /// for word in ["b", "a", "b"] {
+    ///     *counts.entry(word).or_insert(0) += 1;
+    /// }
+    /// assert_eq!(counts.iter().collect::<Vec<_>>(), [(&"b", &2), (&"a", &1)]);
+    /// ```
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V> {
+        match self.map.get(&key) {
+            Some(&index) => Entry::Occupied(OccupiedEntry { map: self, index }),
+            None => Entry::Vacant(VacantEntry { map: self, key }),
+        }
     }
 
     /// Returns an iterator over the key-value pairs in the map, in order of insertion.
@@ -217,30 +382,239 @@ where
     /// assert_eq!(pairs, vec![(&"a", &1), (&"b", &2)]);
     /// ```
     #[must_use]
-    pub fn iter(&self) -> OrderedMapIter<K, V> {
-        OrderedMapIter { map: self, idx: 0 }
+    pub fn iter(&self) -> OrderedMapIter<'_, K, V> {
+        OrderedMapIter {
+            entries: self.entries.iter(),
+        }
+    }
+
+    /// Returns an iterator over the key-value pairs in the map, in order of
+    /// insertion, with mutable references to the values.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mini_git::utils::collections::ordered_map::OrderedMap;
+    ///
+    /// let mut map: OrderedMap<_, _> = [("a", 1), ("b", 2)].into_iter().collect();
+    /// for (key, value) in map.iter_mut() {
+    ///     if *key == "b" {
+    ///         *value *= 10;
+    ///     }
+    /// }
+    /// assert_eq!(map.get(&"b"), Some(&20));
+    /// ```
+    pub fn iter_mut(&mut self) -> OrderedMapIterMut<'_, K, V> {
+        OrderedMapIterMut {
+            entries: self.entries.iter_mut(),
+        }
+    }
+
+    /// Returns an iterator over the keys in the map, in order of insertion.
+    #[must_use]
+    pub fn keys(
+        &self,
+    ) -> impl DoubleEndedIterator<Item = &K> + ExactSizeIterator {
+        self.iter().map(|(key, _)| key)
+    }
+
+    /// Returns an iterator over the values in the map, in order of
+    /// insertion of their keys.
+    #[must_use]
+    pub fn values(
+        &self,
+    ) -> impl DoubleEndedIterator<Item = &V> + ExactSizeIterator {
+        self.iter().map(|(_, value)| value)
+    }
+
+    /// Returns an iterator over mutable references to the values in the
+    /// map, in order of insertion of their keys.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mini_git::utils::collections::ordered_map::OrderedMap;
+    ///
+    /// let mut map: OrderedMap<_, _> = [("a", 1), ("b", 2)].into_iter().collect();
+    /// map.values_mut().for_each(|value| *value += 1);
+    /// assert_eq!(map.values().collect::<Vec<_>>(), [&2, &3]);
+    /// ```
+    pub fn values_mut(
+        &mut self,
+    ) -> impl DoubleEndedIterator<Item = &mut V> + ExactSizeIterator {
+        self.iter_mut().map(|(_, value)| value)
     }
 }
 
-impl<'a, K, V> Iterator for OrderedMapIter<'a, K, V>
+impl<'a, K, V> Entry<'a, K, V>
 where
     K: Hash + Eq + Clone,
 {
+    /// The key of the entry.
+    pub fn key(&self) -> &K {
+        match self {
+            Self::Occupied(entry) => entry.key(),
+            Self::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// The value of the entry, after inserting `default` if it was vacant.
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        self.or_insert_with(|| default)
+    }
+
+    /// The value of the entry, after inserting the result of `default` if
+    /// it was vacant.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Self::Occupied(entry) => entry.into_mut(),
+            Self::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    /// Calls `f` on the value of the entry, if it is occupied.
+    #[must_use]
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, f: F) -> Self {
+        if let Self::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+impl<'a, K, V> Entry<'a, K, V>
+where
+    K: Hash + Eq + Clone,
+    V: Default,
+{
+    /// The value of the entry, after inserting the default value if it was
+    /// vacant.
+    pub fn or_default(self) -> &'a mut V {
+        self.or_insert_with(V::default)
+    }
+}
+
+impl<'a, K, V> OccupiedEntry<'a, K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    /// The key of the entry.
+    #[must_use]
+    pub fn key(&self) -> &K {
+        &self.map.entries[self.index].0
+    }
+
+    /// The value of the entry.
+    #[must_use]
+    pub fn get(&self) -> &V {
+        &self.map.entries[self.index].1
+    }
+
+    /// The value of the entry, mutably.
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self.map.entries[self.index].1
+    }
+
+    /// The value of the entry, mutably for as long as the map is borrowed.
+    #[must_use]
+    pub fn into_mut(self) -> &'a mut V {
+        &mut self.map.entries[self.index].1
+    }
+
+    /// Replaces the value of the entry, keeping its place in the order, and
+    /// returns the old value.
+    pub fn insert(&mut self, value: V) -> V {
+        std::mem::replace(self.get_mut(), value)
+    }
+
+    /// Removes the entry from the map, keeping the order of the others, and
+    /// returns its value.
+    #[must_use]
+    pub fn remove(self) -> V {
+        let key = &self.map.entries[self.index].0;
+        self.map.map.remove(key);
+        self.map.remove_index(self.index)
+    }
+}
+
+impl<'a, K, V> VacantEntry<'a, K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    /// The key of the entry.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Inserts the entry at the end of the map, and returns its value.
+    pub fn insert(self, value: V) -> &'a mut V {
+        let index = self.map.entries.len();
+        self.map.map.insert(self.key.clone(), index);
+        self.map.entries.push((self.key, value));
+        &mut self.map.entries[index].1
+    }
+}
+
+impl<'a, K, V> Iterator for OrderedMapIter<'a, K, V> {
     type Item = (&'a K, &'a V);
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.idx >= self.map.list.len() {
-            return None;
-        }
+        self.entries.next().map(|(key, value)| (key, value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for OrderedMapIter<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.entries.next_back().map(|(key, value)| (key, value))
+    }
+}
+
+impl<K, V> ExactSizeIterator for OrderedMapIter<'_, K, V> {}
+
+impl<'a, K, V> Iterator for OrderedMapIterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.next().map(|(key, value)| (&*key, value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for OrderedMapIterMut<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.entries.next_back().map(|(key, value)| (&*key, value))
+    }
+}
 
-        let idx = self.idx;
-        self.idx += 1;
+impl<K, V> ExactSizeIterator for OrderedMapIterMut<'_, K, V> {}
 
-        let key = &self.map.list[idx];
-        Some((key, &self.map.map[key]))
+impl<K, V> Iterator for OrderedMapIntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
     }
 }
 
+impl<K, V> DoubleEndedIterator for OrderedMapIntoIter<K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.entries.next_back()
+    }
+}
+
+impl<K, V> ExactSizeIterator for OrderedMapIntoIter<K, V> {}
+
 impl<'a, K, V> IntoIterator for &'a OrderedMap<K, V>
 where
     K: Hash + Eq + Clone,
@@ -252,12 +626,37 @@ where
     }
 }
 
+impl<'a, K, V> IntoIterator for &'a mut OrderedMap<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    type IntoIter = OrderedMapIterMut<'a, K, V>;
+    type Item = (&'a K, &'a mut V);
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+impl<K, V> IntoIterator for OrderedMap<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    type IntoIter = OrderedMapIntoIter<K, V>;
+    type Item = (K, V);
+    fn into_iter(self) -> Self::IntoIter {
+        OrderedMapIntoIter {
+            entries: self.entries.into_iter(),
+        }
+    }
+}
+
 impl<K, V> FromIterator<(K, V)> for OrderedMap<K, V>
 where
     K: Hash + Eq + Clone,
 {
     fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
-        let mut map = Self::new();
+        let iter = iter.into_iter();
+        let mut map = Self::with_capacity(iter.size_hint().0);
 
         for (k, v) in iter {
             map.insert(k, v);
@@ -273,7 +672,8 @@ where
     V: Clone,
 {
     fn from_iter<T: IntoIterator<Item = (&'a K, &'a V)>>(iter: T) -> Self {
-        let mut map = Self::new();
+        let iter = iter.into_iter();
+        let mut map = Self::with_capacity(iter.size_hint().0);
 
         for (k, v) in iter {
             map.insert(k.clone(), v.clone());
@@ -283,6 +683,17 @@ where
     }
 }
 
+impl<K, V> Extend<(K, V)> for OrderedMap<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, iter: T) {
+        for (k, v) in iter {
+            self.insert(k, v);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -409,4 +820,96 @@ mod tests {
         let keys: Vec<_> = map.iter().map(|(k, _)| *k).collect();
         assert_eq!(keys, vec!["a", "b", "c"]);
     }
+
+    #[test]
+    fn test_len_and_capacity() {
+        let mut map = OrderedMap::with_capacity(4);
+        assert!(map.is_empty());
+        assert!(map.capacity() >= 4);
+        map.insert("a", 1);
+        map.insert("b", 2);
+        map.insert("a", 3);
+        assert_eq!(map.len(), 2);
+        assert!(!map.is_empty());
+        map.reserve(100);
+        assert!(map.capacity() >= 102);
+    }
+
+    #[test]
+    fn test_remove_keeps_order() {
+        let mut map: OrderedMap<_, _> = (0..10).map(|i| (i, i * 2)).collect();
+        assert_eq!(map.remove(&3), Some(6));
+        assert_eq!(map.remove(&0), Some(0));
+        assert_eq!(map.remove(&9), Some(18));
+        assert_eq!(map.remove(&3), None);
+        assert_eq!(map.len(), 7);
+
+        let keys: Vec<_> = map.keys().copied().collect();
+        assert_eq!(keys, [1, 2, 4, 5, 6, 7, 8]);
+        for key in keys {
+            assert_eq!(map.get(&key), Some(&(key * 2)));
+        }
+
+        // Keys removed can be added again, at the end
+        map.insert(3, 0);
+        assert_eq!(map.keys().last(), Some(&3));
+        assert_eq!(map.get(&8), Some(&16));
+    }
+
+    #[test]
+    fn test_entry() {
+        let mut map = OrderedMap::new();
+        map.entry("a").or_insert_with(Vec::new).push(1);
+        map.entry("b").or_default().push(2);
+        map.entry("a").or_default().push(3);
+        assert_eq!(map.get("a"), Some(&vec![1, 3]));
+
+        let entry = map.entry("b").and_modify(|v| v.push(4));
+        assert_eq!(entry.key(), &"b");
+        assert_eq!(map.get("b"), Some(&vec![2, 4]));
+
+        match map.entry("a") {
+            Entry::Occupied(mut entry) => {
+                assert_eq!(entry.get(), &vec![1, 3]);
+                assert_eq!(entry.insert(vec![5]), vec![1, 3]);
+            }
+            Entry::Vacant(_) => unreachable!(),
+        }
+        match map.entry("a") {
+            Entry::Occupied(entry) => assert_eq!(entry.remove(), vec![5]),
+            Entry::Vacant(_) => unreachable!(),
+        }
+        match map.entry("c") {
+            Entry::Vacant(entry) => entry.insert(vec![6]).push(7),
+            Entry::Occupied(_) => unreachable!(),
+        }
+        let pairs: Vec<_> = map.into_iter().collect();
+        assert_eq!(pairs, [("b", vec![2, 4]), ("c", vec![6, 7])]);
+    }
+
+    #[test]
+    fn test_mutable_iterators() {
+        let mut map: OrderedMap<_, _> =
+            [("c", 3), ("a", 1), ("b", 2)].into_iter().collect();
+        for value in map.values_mut() {
+            *value *= 10;
+        }
+        for (key, value) in &mut map {
+            if *key == "a" {
+                *value += 1;
+            }
+        }
+        let values: Vec<_> = map.values().copied().collect();
+        assert_eq!(values, [30, 11, 20]);
+        assert_eq!(map.iter().next_back(), Some((&"b", &20)));
+        assert_eq!(map.iter().len(), 3);
+    }
+
+    #[test]
+    fn test_extend() {
+        let mut map: OrderedMap<_, _> = [("a", 1)].into_iter().collect();
+        map.extend([("b", 2), ("a", 3)]);
+        let pairs: Vec<_> = map.iter().map(|(&k, &v)| (k, v)).collect();
+        assert_eq!(pairs, [("a", 3), ("b", 2)]);
+    }
 }
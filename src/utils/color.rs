@@ -0,0 +1,366 @@
+//! Terminal colors for command output
+//!
+//! This module decides whether output should be colored, following the
+//! `--color` option, the `color.ui` configuration, whether stdout is a
+//! terminal and the `NO_COLOR` environment variable, and provides the escape
+//! codes to color it with.
+//!
+//! The color of each part of the output is a slot of a [`Palette`], which
+//! can be changed in the configuration like git, as in
+//!
+//! ```text
+//! [color "diff"]
+//!     old = red bold
+//!     new = 34
+//! ```
+
+use std::io::IsTerminal;
+use std::sync::Mutex;
+
+use crate::utils::configparser::ConfigParser;
+
+/// Escape codes parsed from the configuration, kept for the rest of the run
+/// so that palettes stay `Copy`
+static CODES: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+
+/// When to color output, as given by `--color`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorMode {
+    /// Color output only if stdout is a terminal and `NO_COLOR` is not set
+    Auto,
+    /// Always color output
+    Always,
+    /// Never color output
+    Never,
+}
+
+impl std::str::FromStr for ColorMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "auto" => Self::Auto,
+            "always" => Self::Always,
+            "never" => Self::Never,
+            _ => return Err(format!("unknown color mode {s}")),
+        })
+    }
+}
+
+impl ColorMode {
+    /// The color mode configured for `command`, by `color.<command>` or
+    /// else `color.ui`, which is [`ColorMode::Auto`] if neither is set.
+    ///
+    /// Like git, `true` stands for `auto` and `false` for `never`.
+    ///
+    /// # Errors
+    ///
+    /// If the configured value is not a color mode.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mini_git::utils::color::ColorMode;
+    /// use mini_git::utils::configparser::ConfigParser;
+    ///
+    /// let mut config = ConfigParser::new();
+    /// config.add_config("color", "ui", "never");
+    /// assert_eq!(ColorMode::from_config(&config, "diff"), Ok(ColorMode::Never));
+    ///
+    /// config.add_config("color", "diff", "always");
+    /// assert_eq!(ColorMode::from_config(&config, "diff"), Ok(ColorMode::Always));
+    /// # Ok::<(), String>(())
+    /// ```
+    pub fn from_config(
+        config: &ConfigParser,
+        command: &str,
+    ) -> Result<Self, String> {
+        let Some(color) = config.get("color") else {
+            return Ok(Self::Auto);
+        };
+        let Some((key, value)) = [command, "ui"]
+            .into_iter()
+            .find_map(|key| color.get(key).map(|value| (key, value)))
+        else {
+            return Ok(Self::Auto);
+        };
+        match value.to_ascii_lowercase().as_str() {
+            "true" => Ok(Self::Auto),
+            "false" => Ok(Self::Never),
+            value => value
+                .parse()
+                .map_err(|e| format!("Invalid value for color.{key}: {e}")),
+        }
+    }
+
+    /// Whether output written to stdout should be colored.
+    ///
+    /// A non-empty `NO_COLOR` only changes the default, so it is overridden
+    /// by `--color always`.
+    #[must_use]
+    pub fn enabled(self) -> bool {
+        let no_color =
+            std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        self.resolve(no_color, std::io::stdout().is_terminal())
+    }
+
+    /// Whether to color output, given whether `NO_COLOR` is set and whether
+    /// stdout is a terminal.
+    #[must_use]
+    pub fn resolve(self, no_color: bool, is_terminal: bool) -> bool {
+        match self {
+            Self::Always => true,
+            Self::Never => false,
+            Self::Auto => !no_color && is_terminal,
+        }
+    }
+}
+
+/// The escape codes used to color output, one for each part of it.
+///
+/// Every code is empty in [`Palette::PLAIN`], so the same format strings
+/// produce plain text when color is disabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    /// Ends a colored part
+    pub reset: &'static str,
+    /// Diff headers, `color.diff.meta`
+    pub meta: &'static str,
+    /// Hunk headers, `color.diff.frag`
+    pub frag: &'static str,
+    /// Removed lines, `color.diff.old`
+    pub old: &'static str,
+    /// Added lines, `color.diff.new`
+    pub new: &'static str,
+    /// Commit hashes, `color.diff.commit`
+    pub commit: &'static str,
+    /// Commit authors, `color.log.author`
+    pub author: &'static str,
+}
+
+/// Gives access to a slot of a palette
+type Slot = fn(&mut Palette) -> &mut &'static str;
+
+/// The slots of a palette, by their section and name in the configuration
+const SLOTS: &[(&str, &str, Slot)] = &[
+    ("diff", "meta", |palette| &mut palette.meta),
+    ("diff", "frag", |palette| &mut palette.frag),
+    ("diff", "old", |palette| &mut palette.old),
+    ("diff", "new", |palette| &mut palette.new),
+    ("diff", "commit", |palette| &mut palette.commit),
+    ("log", "author", |palette| &mut palette.author),
+];
+
+impl Palette {
+    /// ANSI escape codes
+    pub const COLOR: Self = Self {
+        reset: "\x1b[0m",
+        meta: "\x1b[36m",
+        frag: "\x1b[36m",
+        old: "\x1b[31m",
+        new: "\x1b[32m",
+        commit: "\x1b[33m",
+        author: "\x1b[36m",
+    };
+
+    /// No escape codes at all
+    pub const PLAIN: Self = Self {
+        reset: "",
+        meta: "",
+        frag: "",
+        old: "",
+        new: "",
+        commit: "",
+        author: "",
+    };
+
+    /// The palette for the given color mode.
+    #[must_use]
+    pub fn new(mode: ColorMode) -> Self {
+        if mode.enabled() {
+            Self::COLOR
+        } else {
+            Self::PLAIN
+        }
+    }
+
+    /// The palette for the given color mode, with the colors configured in
+    /// the `color "diff"` and `color "log"` sections of `config`.
+    ///
+    /// # Errors
+    ///
+    /// If a configured color is not valid, see [`parse_color`].
+    pub fn from_config(
+        mode: ColorMode,
+        config: &ConfigParser,
+    ) -> Result<Self, String> {
+        let palette = Self::new(mode);
+        if palette == Self::PLAIN {
+            return Ok(palette);
+        }
+        palette.with_config(config)
+    }
+
+    /// This palette, with the slots configured in `config` replaced.
+    ///
+    /// # Errors
+    ///
+    /// If a configured color is not valid, see [`parse_color`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mini_git::utils::color::Palette;
+    /// use mini_git::utils::configparser::ConfigParser;
+    ///
+    /// let mut config = ConfigParser::new();
+    /// config.add_config("color \"diff\"", "old", "blue bold");
+    ///
+    /// let palette = Palette::COLOR.with_config(&config)?;
+    /// assert_eq!(palette.old, "\x1b[1;34m");
+    /// assert_eq!(palette.new, Palette::COLOR.new);
+    /// # Ok::<(), String>(())
+    /// ```
+    pub fn with_config(
+        mut self,
+        config: &ConfigParser,
+    ) -> Result<Self, String> {
+        for (section, name, slot) in SLOTS {
+            let value = config
+                .get(&format!("color \"{section}\""))
+                .and_then(|section| section.get(name));
+            if let Some(value) = value {
+                let code = parse_color(value).map_err(|e| {
+                    format!("Invalid value for color.{section}.{name}: {e}")
+                })?;
+                *slot(&mut self) = intern(code);
+            }
+        }
+        Ok(self)
+    }
+}
+
+/// Parses a color as git does, into its escape code.
+///
+/// A color is made of up to two colors, the foreground and then the
+/// background, and any attributes, separated by spaces. Colors are named,
+/// as `red` or `brightred`, numbered from 0 to 255, or given as `#rrggbb`.
+/// `normal` leaves a color as is, and `default` resets it. The attributes
+/// are `bold`, `dim`, `italic`, `ul`, `blink`, `reverse` and `strike`, which
+/// are turned off when prefixed with `no` or `no-`.
+///
+/// # Errors
+///
+/// If a word is neither a color nor an attribute, or there are more than
+/// two colors.
+///
+/// # Examples
+///
+/// ```
+/// use mini_git::utils::color::parse_color;
+///
+/// assert_eq!(parse_color("red")?, "\x1b[31m");
+/// assert_eq!(parse_color("bold yellow blue")?, "\x1b[1;33;44m");
+/// assert_eq!(parse_color("208 nobold")?, "\x1b[22;38;5;208m");
+/// assert_eq!(parse_color("#ff8000")?, "\x1b[38;2;255;128;0m");
+/// assert_eq!(parse_color("normal")?, "");
+/// assert!(parse_color("purple").is_err());
+/// # Ok::<(), String>(())
+/// ```
+pub fn parse_color(value: &str) -> Result<String, String> {
+    let mut attributes = Vec::new();
+    let mut colors = Vec::new();
+    let mut count = 0;
+    for word in value.split_whitespace() {
+        let word = word.to_ascii_lowercase();
+        if let Some(attribute) = parse_attribute(&word) {
+            attributes.push(attribute.to_string());
+            continue;
+        }
+        let Some(color) = parse_one_color(&word, count == 1) else {
+            return Err(format!("unknown color '{word}'"));
+        };
+        count += 1;
+        if count > 2 {
+            return Err(format!("too many colors in '{value}'"));
+        }
+        colors.extend(color);
+    }
+
+    attributes.extend(colors);
+    if attributes.is_empty() {
+        Ok(String::new())
+    } else {
+        Ok(format!("\x1b[{}m", attributes.join(";")))
+    }
+}
+
+/// The code of an attribute, as `bold` or `no-bold`.
+fn parse_attribute(word: &str) -> Option<u8> {
+    const ATTRIBUTES: &[(&str, u8, u8)] = &[
+        ("bold", 1, 22),
+        ("dim", 2, 22),
+        ("italic", 3, 23),
+        ("ul", 4, 24),
+        ("blink", 5, 25),
+        ("reverse", 7, 27),
+        ("strike", 9, 29),
+    ];
+    let negated = word.strip_prefix("no-").or_else(|| word.strip_prefix("no"));
+    ATTRIBUTES
+        .iter()
+        .find_map(|&(name, on, off)| match negated {
+            Some(negated) if negated == name => Some(off),
+            _ => (word == name).then_some(on),
+        })
+}
+
+/// The code of a color, or `None` for `normal`, as a foreground color or
+/// else a background color.
+#[allow(clippy::option_option)]
+fn parse_one_color(word: &str, background: bool) -> Option<Option<String>> {
+    const NAMES: &[&str] = &[
+        "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+    ];
+    let base: u8 = if background { 40 } else { 30 };
+    let extended = if background { 48 } else { 38 };
+
+    if word == "normal" {
+        return Some(None);
+    }
+    if word == "default" {
+        return Some(Some((base + 9).to_string()));
+    }
+    let named = |name: &str| (0..).zip(NAMES).find(|(_, &n)| n == name);
+    if let Some((i, _)) = named(word) {
+        return Some(Some((base + i).to_string()));
+    }
+    if let Some(name) = word.strip_prefix("bright") {
+        let (i, _) = named(name)?;
+        return Some(Some((base + 60 + i).to_string()));
+    }
+    if let Some(hex) = word.strip_prefix('#') {
+        if hex.len() != 6 {
+            return None;
+        }
+        let channel =
+            |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+        let (r, g, b) = (channel(0)?, channel(2)?, channel(4)?);
+        return Some(Some(format!("{extended};2;{r};{g};{b}")));
+    }
+    let number: u8 = word.parse().ok()?;
+    Some(Some(format!("{extended};5;{number}")))
+}
+
+/// Keeps `code` for the rest of the run, once for each distinct code.
+fn intern(code: String) -> &'static str {
+    let mut codes = CODES
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    if let Some(&interned) = codes.iter().find(|&&interned| interned == code) {
+        return interned;
+    }
+    let interned: &'static str = Box::leak(code.into_boxed_str());
+    codes.push(interned);
+    interned
+}
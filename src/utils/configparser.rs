@@ -45,6 +45,7 @@
 use core::fmt::Display;
 use core::ops::Index;
 use std::borrow::Borrow;
+use std::fmt::Write;
 use std::fs::{self, canonicalize};
 use std::iter::FromIterator;
 use std::path::Path;
@@ -65,7 +66,7 @@ use std::{collections::HashMap, ops::IndexMut};
 ///
 /// assert_eq!(section["key1"], "value1");
 /// ```
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ConfigSection {
     configs: HashMap<String, String>,
 }
@@ -86,7 +87,7 @@ pub struct ConfigSection {
 ///
 /// assert_eq!(config["database"]["host"], "localhost");
 /// ```
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ConfigParser {
     sections: HashMap<String, ConfigSection>,
 }
@@ -96,7 +97,7 @@ impl Display for ConfigSection {
         let string = self.configs.iter().fold(
             String::new(),
             |mut string, (key, value)| {
-                string.push_str(&format!("    {key}={value}\n"));
+                let _ = writeln!(string, "    {key}={value}");
                 string
             },
         );
@@ -141,23 +142,96 @@ impl ConfigSection {
         self
     }
 
+    /// Gets the value of a configuration item.
+    ///
+    /// Keys are matched case-insensitively, as git does.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mini_git::utils::configparser::ConfigSection;
+    ///
+    /// let mut section = ConfigSection::new();
+    /// section.add_config("excludesFile", "~/.gitignore");
+    ///
+    /// assert_eq!(section.get("excludesfile"), Some("~/.gitignore"));
+    /// assert_eq!(section.get("missing"), None);
+    /// ```
     #[must_use]
-    pub fn get_int(&self, key: &str) -> Option<isize> {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        let key = key.trim();
         self.configs
             .get(key)
+            .or_else(|| {
+                self.configs
+                    .iter()
+                    .find(|(k, _)| k.eq_ignore_ascii_case(key))
+                    .map(|(_, value)| value)
+            })
+            .map(String::as_str)
+    }
+
+    /// Returns the keys of the section, as written, in no particular order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mini_git::utils::configparser::ConfigSection;
+    ///
+    /// let mut section = ConfigSection::new();
+    /// section.add_config("objectFormat", "sha1");
+    ///
+    /// assert_eq!(section.keys().collect::<Vec<_>>(), ["objectFormat"]);
+    /// ```
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.configs.keys().map(String::as_str)
+    }
+
+    #[must_use]
+    pub fn get_int(&self, key: &str) -> Option<isize> {
+        self.get(key)
             .map(|value| value.parse().expect("Should be parsed as float"))
     }
 
+    /// Returns the value of `key` as a size in bytes, which like in git may
+    /// have a `k`, `m` or `g` suffix, for a multiple of 1024, 1024^2 or
+    /// 1024^3.
+    ///
+    /// Returns `None` if the key does not exist or is not a valid size.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mini_git::utils::configparser::ConfigSection;
+    ///
+    /// let mut section = ConfigSection::new();
+    /// section.add_config("bigFileThreshold", "512m");
+    /// section.add_config("plain", "100");
+    ///
+    /// assert_eq!(section.get_size("bigFileThreshold"), Some(512 << 20));
+    /// assert_eq!(section.get_size("plain"), Some(100));
+    /// ```
+    #[must_use]
+    pub fn get_size(&self, key: &str) -> Option<u64> {
+        let value = self.get(key)?.trim();
+        let (number, shift) = match value.char_indices().last()? {
+            (i, 'k' | 'K') => (&value[..i], 10),
+            (i, 'm' | 'M') => (&value[..i], 20),
+            (i, 'g' | 'G') => (&value[..i], 30),
+            _ => (value, 0),
+        };
+        number.parse::<u64>().ok()?.checked_mul(1 << shift)
+    }
+
     #[must_use]
     pub fn get_float(&self, key: &str) -> Option<f64> {
-        self.configs
-            .get(key)
+        self.get(key)
             .map(|value| value.parse().expect("Should be parsed as float"))
     }
 
     #[must_use]
     pub fn get_bool(&self, key: &str) -> Option<bool> {
-        match self.configs.get(key) {
+        match self.get(key) {
             Some(value) => match value.to_lowercase().as_str() {
                 "true" | "1" | "on" | "yes" => Some(true),
                 "false" | "0" | "off" | "no" => Some(false),
@@ -211,7 +285,7 @@ impl Display for ConfigParser {
             sections
                 .into_iter()
                 .fold(String::new(), |mut string, section| {
-                    string.push_str(&format!("[{section}]\n"));
+                    let _ = writeln!(string, "[{section}]");
                     string.push_str(&self[section].to_string());
                     string.push('\n');
                     string
@@ -308,7 +382,12 @@ impl ConfigParser {
 
     #[must_use]
     pub fn get(&self, key: &str) -> Option<&ConfigSection> {
-        self.sections.get(key)
+        self.sections.get(key).or_else(|| {
+            self.sections
+                .iter()
+                .find(|(section, _)| section.eq_ignore_ascii_case(key))
+                .map(|(_, section)| section)
+        })
     }
 
     #[must_use]
@@ -404,7 +483,7 @@ impl From<&Path> for ConfigParser {
         use std::fs::File;
         use std::io::{BufRead, BufReader};
 
-        assert!(path.exists(), "File {path:?} does not exist");
+        assert!(path.exists(), "File {} does not exist", path.display());
 
         let file = File::open(path).expect("Should be able to open the file");
         let iter = BufReader::new(file).lines().map_while(Result::ok);
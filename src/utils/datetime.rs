@@ -16,6 +16,43 @@ const MONTHS: [&str; 12] = [
     "Nov", "Dec",
 ];
 
+/// The styles in which a [`DateTime`] can be rendered, mirroring the values
+/// accepted by git's `--date` option.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateFormat {
+    /// `Fri Feb 13 23:31:30 2009 +0000`
+    Default,
+    /// `2009-02-13 23:31:30 +0000`
+    Iso,
+    /// `2009-02-13T23:31:30+00:00`
+    IsoStrict,
+    /// `Fri, 13 Feb 2009 23:31:30 +0000`
+    Rfc,
+    /// `2009-02-13`
+    Short,
+    /// `3 years, 2 months ago`
+    Relative,
+    /// `1234567890`
+    Unix,
+}
+
+impl std::str::FromStr for DateFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "default" => Self::Default,
+            "iso" | "iso8601" => Self::Iso,
+            "iso-strict" | "iso8601-strict" => Self::IsoStrict,
+            "rfc" | "rfc2822" => Self::Rfc,
+            "short" => Self::Short,
+            "relative" => Self::Relative,
+            "unix" => Self::Unix,
+            _ => return Err(format!("unknown date format {s}")),
+        })
+    }
+}
+
 /// Represents timezone information.
 #[derive(Debug)]
 pub struct TZInfo {
@@ -145,6 +182,27 @@ impl TZInfo {
         repr
     }
 
+    /// The offset of the timezone from UTC in minutes, negative if local
+    /// time is behind UTC.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mini_git::utils::datetime::TZInfo;
+    /// assert_eq!(TZInfo::from_git_string("+0530").unwrap().offset_minutes(), 330);
+    /// assert_eq!(TZInfo::from_git_string("-0800").unwrap().offset_minutes(), -480);
+    /// ```
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    #[must_use]
+    pub fn offset_minutes(&self) -> i32 {
+        let minutes = (self.hours * 60 + self.minutes) as i32;
+        if self.ahead {
+            minutes
+        } else {
+            -minutes
+        }
+    }
+
     /// Creates a new `TZInfo` from a Git timezone string (e.g. "+0530" or "-0800")
     ///
     /// # Examples
@@ -224,6 +282,21 @@ impl DateTime {
         }
     }
 
+    /// The timezone of the date and time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mini_git::utils::datetime::DateTime;
+    ///
+    /// let now = DateTime::now();
+    /// assert!(now.tz().to_str().starts_with(['+', '-']));
+    /// ```
+    #[must_use]
+    pub fn tz(&self) -> &TZInfo {
+        &self.tz
+    }
+
     /// Converts the `DateTime` to a string representation.
     ///
     /// # Examples
@@ -300,6 +373,124 @@ impl DateTime {
         }
     }
 
+    /// Formats the date in the given style.
+    ///
+    /// Relative dates are computed against the current system time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mini_git::utils::datetime::{DateFormat, DateTime};
+    /// let dt = DateTime::from_git_timestamp("John <j@x.com> 1234567890 +0530").unwrap();
+    /// assert_eq!(dt.format(DateFormat::Iso), "2009-02-14 05:01:30 +0530");
+    /// assert_eq!(dt.format(DateFormat::IsoStrict), "2009-02-14T05:01:30+05:30");
+    /// assert_eq!(dt.format(DateFormat::Rfc), "Sat, 14 Feb 2009 05:01:30 +0530");
+    /// assert_eq!(dt.format(DateFormat::Short), "2009-02-14");
+    /// assert_eq!(dt.format(DateFormat::Unix), "1234567890");
+    /// ```
+    #[allow(clippy::cast_sign_loss, clippy::cast_possible_wrap)]
+    #[must_use]
+    pub fn format(&self, format: DateFormat) -> String {
+        let tm = match format {
+            DateFormat::Default => return self.format_git(),
+            DateFormat::Unix => return self.unix_timestamp().to_string(),
+            DateFormat::Relative => {
+                // `DateTime::now` is not adjusted for its timezone
+                let now = DateTime::now().time.as_secs() as i64;
+                return Self::format_relative(now - self.unix_timestamp());
+            }
+            _ => unsafe {
+                let time_secs = self.time.as_secs();
+                let tm = gmtime(std::ptr::from_ref(&time_secs));
+                if tm.is_null() {
+                    return self.to_str();
+                }
+                *tm
+            },
+        };
+
+        let date =
+            format!("{}-{:02}-{:02}", 1900 + tm.year, tm.mon + 1, tm.mday);
+        let time = format!("{:02}:{:02}:{:02}", tm.hour, tm.min, tm.sec);
+        let tz = self.tz.to_str();
+
+        match format {
+            DateFormat::Iso => format!("{date} {time} {tz}"),
+            DateFormat::IsoStrict => {
+                format!("{date}T{time}{}:{}", &tz[..3], &tz[3..])
+            }
+            DateFormat::Rfc => format!(
+                "{}, {} {} {} {time} {tz}",
+                WEEKDAYS[tm.wday as usize],
+                tm.mday,
+                MONTHS[tm.mon as usize],
+                1900 + tm.year,
+            ),
+            DateFormat::Short => date,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Seconds since the Unix epoch, undoing the timezone adjustment
+    #[allow(clippy::cast_possible_wrap)]
+    fn unix_timestamp(&self) -> i64 {
+        let offset =
+            (self.tz.hours * ONE_HOUR + self.tz.minutes * ONE_MINUTE) as i64;
+        let local = self.time.as_secs() as i64;
+        if self.tz.ahead {
+            local - offset
+        } else {
+            local + offset
+        }
+    }
+
+    // Describes an elapsed duration the same way git's relative dates do
+    #[allow(clippy::cast_sign_loss)]
+    fn format_relative(elapsed: i64) -> String {
+        let plural = |n: u64, unit: &str| {
+            format!("{n} {unit}{}", if n == 1 { "" } else { "s" })
+        };
+
+        if elapsed < 0 {
+            return "in the future".to_owned();
+        }
+        let secs = elapsed as u64;
+        if secs < 90 {
+            return format!("{} ago", plural(secs, "second"));
+        }
+        let minutes = (secs + 30) / 60;
+        if minutes < 90 {
+            return format!("{} ago", plural(minutes, "minute"));
+        }
+        let hours = (minutes + 30) / 60;
+        if hours < 36 {
+            return format!("{} ago", plural(hours, "hour"));
+        }
+        let days = (hours + 12) / 24;
+        if days < 14 {
+            return format!("{} ago", plural(days, "day"));
+        }
+        if days < 70 {
+            return format!("{} ago", plural((days + 3) / 7, "week"));
+        }
+        if days < 365 {
+            return format!("{} ago", plural((days + 15) / 30, "month"));
+        }
+        if days < 1825 {
+            let total_months = (days * 12 * 2 + 365) / (365 * 2);
+            let (years, months) = (total_months / 12, total_months % 12);
+            if months == 0 {
+                return format!("{} ago", plural(years, "year"));
+            }
+            return format!(
+                "{}, {} ago",
+                plural(years, "year"),
+                plural(months, "month")
+            );
+        }
+        format!("{} ago", plural((days + 183) / 365, "year"))
+    }
+
     /// Format the date in Git's preferred format (e.g. "Fri Feb 13 23:31:30 2009 +0000")
     ///
     /// # Examples
@@ -439,6 +630,42 @@ mod tests {
         assert!(DateTime::from_git_timestamp("invalid timestamp").is_none());
     }
 
+    #[test]
+    fn test_format_negative_offset() {
+        let dt =
+            DateTime::from_git_timestamp("A <a@b.c> 1234567890 -0800").unwrap();
+        assert_eq!(dt.format(DateFormat::Iso), "2009-02-13 15:31:30 -0800");
+        assert_eq!(
+            dt.format(DateFormat::IsoStrict),
+            "2009-02-13T15:31:30-08:00"
+        );
+        assert_eq!(dt.format(DateFormat::Unix), "1234567890");
+    }
+
+    #[test]
+    fn test_format_relative() {
+        assert_eq!(DateTime::format_relative(1), "1 second ago");
+        assert_eq!(DateTime::format_relative(45), "45 seconds ago");
+        assert_eq!(DateTime::format_relative(600), "10 minutes ago");
+        assert_eq!(DateTime::format_relative(3 * 3600), "3 hours ago");
+        assert_eq!(DateTime::format_relative(3 * 86400), "3 days ago");
+        assert_eq!(DateTime::format_relative(21 * 86400), "3 weeks ago");
+        assert_eq!(DateTime::format_relative(100 * 86400), "3 months ago");
+        assert_eq!(
+            DateTime::format_relative(500 * 86400),
+            "1 year, 4 months ago"
+        );
+        assert_eq!(DateTime::format_relative(3650 * 86400), "10 years ago");
+        assert_eq!(DateTime::format_relative(-5), "in the future");
+    }
+
+    #[test]
+    fn test_date_format_from_str() {
+        assert_eq!("iso".parse(), Ok(DateFormat::Iso));
+        assert_eq!("rfc2822".parse(), Ok(DateFormat::Rfc));
+        assert!("bogus".parse::<DateFormat>().is_err());
+    }
+
     #[test]
     fn test_git_format() {
         let dt = DateTime::from_timestamp(1_234_567_890);
@@ -0,0 +1,225 @@
+//! EWAH compressed bitmaps
+//!
+//! Git stores bitmaps in index extensions and pack bitmaps using the EWAH
+//! run length encoding of 64 bit words. A run length word (RLW) describes a
+//! run of words that are all 0s or all 1s, followed by a number of literal
+//! words that are stored as they are.
+//!
+//! An RLW stores the bit of its run in bit 0, the number of words in the run
+//! in the next 32 bits, and the number of literal words after it in the
+//! remaining 31 bits.
+//!
+//! On disk, a bitmap is its size in bits, the number of words, the words and
+//! the position of the last RLW, all big endian.
+//!
+//! # Examples
+//!
+//! ```
+//! use mini_git::utils::ewah::EwahBitmap;
+//!
+//! let bitmap = EwahBitmap::from_positions([1, 5, 200]);
+//! let (parsed, len) = EwahBitmap::parse(&bitmap.serialize())?;
+//!
+//! assert_eq!(len, bitmap.serialize().len());
+//! assert!(parsed.contains(200));
+//! assert_eq!(parsed.positions().collect::<Vec<_>>(), [1, 5, 200]);
+//! # Ok::<(), String>(())
+//! ```
+
+/// The number of bits in a word.
+const WORD_BITS: usize = 64;
+/// The largest run a single RLW can describe.
+const MAX_RUN: u64 = (1 << 32) - 1;
+/// The most literal words a single RLW can be followed by.
+const MAX_LITERALS: u64 = (1 << 31) - 1;
+
+/// A bitmap, decompressed in memory.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EwahBitmap {
+    /// The number of bits in the bitmap.
+    bit_size: usize,
+    /// The bits, 64 to a word, starting from the least significant bit.
+    words: Vec<u64>,
+}
+
+impl EwahBitmap {
+    /// Create a bitmap with the bits at `positions` set. The bitmap ends
+    /// after the last set bit.
+    #[must_use]
+    pub fn from_positions<I>(positions: I) -> Self
+    where
+        I: IntoIterator<Item = usize>,
+    {
+        let mut bitmap = Self::default();
+        for pos in positions {
+            let word = pos / WORD_BITS;
+            if word >= bitmap.words.len() {
+                bitmap.words.resize(word + 1, 0);
+            }
+            bitmap.words[word] |= 1 << (pos % WORD_BITS);
+            bitmap.bit_size = bitmap.bit_size.max(pos + 1);
+        }
+        bitmap
+    }
+
+    /// The number of bits in the bitmap.
+    #[must_use]
+    pub fn bit_size(&self) -> usize {
+        self.bit_size
+    }
+
+    /// Whether the bit at `pos` is set.
+    #[must_use]
+    pub fn contains(&self, pos: usize) -> bool {
+        pos < self.bit_size
+            && self
+                .words
+                .get(pos / WORD_BITS)
+                .is_some_and(|word| word & (1 << (pos % WORD_BITS)) != 0)
+    }
+
+    /// The positions of the set bits, in increasing order.
+    pub fn positions(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.bit_size).filter(|&pos| self.contains(pos))
+    }
+
+    /// The number of set bits.
+    #[must_use]
+    pub fn count(&self) -> usize {
+        self.positions().count()
+    }
+
+    /// Parse a bitmap from the start of `data`, returning it along with the
+    /// number of bytes it took.
+    ///
+    /// # Errors
+    ///
+    /// If the data is truncated, or the words do not describe `bit_size`
+    /// bits.
+    pub fn parse(data: &[u8]) -> Result<(Self, usize), String> {
+        let truncated = || "EWAH bitmap is truncated".to_owned();
+        let read_u32 = |pos: usize| {
+            data.get(pos..pos + 4)
+                .and_then(|b| b.try_into().ok())
+                .map(u32::from_be_bytes)
+                .ok_or_else(truncated)
+        };
+
+        let bit_size = read_u32(0)? as usize;
+        let word_count = read_u32(4)? as usize;
+        let words_end = word_count
+            .checked_mul(8)
+            .and_then(|len| len.checked_add(8))
+            .ok_or_else(truncated)?;
+        let compressed = data
+            .get(8..words_end)
+            .ok_or_else(truncated)?
+            .chunks_exact(8)
+            .map(|b| b.iter().fold(0, |word, &b| word << 8 | u64::from(b)))
+            .collect::<Vec<_>>();
+        // The position of the last RLW is only needed to append in place
+        read_u32(words_end)?;
+
+        let mut words = vec![];
+        let mut i = 0;
+        while i < compressed.len() {
+            let rlw = compressed[i];
+            let run = usize::try_from((rlw >> 1) & MAX_RUN)
+                .map_err(|_| "EWAH run is too long".to_owned())?;
+            let literals = usize::try_from(rlw >> 33)
+                .map_err(|_| "EWAH run is too long".to_owned())?;
+            let fill = if rlw & 1 == 0 { 0 } else { u64::MAX };
+            words.extend(std::iter::repeat_n(fill, run));
+            i += 1;
+            let literal_words =
+                compressed.get(i..i + literals).ok_or_else(truncated)?;
+            words.extend_from_slice(literal_words);
+            i += literals;
+        }
+
+        if words.len() * WORD_BITS < bit_size {
+            return Err("EWAH bitmap is shorter than its size".to_owned());
+        }
+        Ok((Self { bit_size, words }, words_end + 4))
+    }
+
+    /// Serialize the bitmap, compressing it the way git does.
+    #[must_use]
+    pub fn serialize(&self) -> Vec<u8> {
+        let used_words = self.bit_size.div_ceil(WORD_BITS);
+        let mut encoder = Encoder {
+            buffer: vec![0],
+            rlw: 0,
+        };
+        for &word in &self.words[..used_words.min(self.words.len())] {
+            match word {
+                0 => encoder.add_run(false),
+                u64::MAX => encoder.add_run(true),
+                _ => encoder.add_literal(word),
+            }
+        }
+
+        let mut data = vec![];
+        #[allow(clippy::cast_possible_truncation)]
+        {
+            data.extend_from_slice(&(self.bit_size as u32).to_be_bytes());
+            data.extend_from_slice(
+                &(encoder.buffer.len() as u32).to_be_bytes(),
+            );
+            for word in &encoder.buffer {
+                data.extend_from_slice(&word.to_be_bytes());
+            }
+            data.extend_from_slice(&(encoder.rlw as u32).to_be_bytes());
+        }
+        data
+    }
+}
+
+/// Builds the compressed words of a bitmap, one word at a time.
+struct Encoder {
+    buffer: Vec<u64>,
+    /// The position of the current RLW in `buffer`
+    rlw: usize,
+}
+
+impl Encoder {
+    fn run_bit(&self) -> bool {
+        self.buffer[self.rlw] & 1 != 0
+    }
+
+    fn run_len(&self) -> u64 {
+        (self.buffer[self.rlw] >> 1) & MAX_RUN
+    }
+
+    fn literals(&self) -> u64 {
+        self.buffer[self.rlw] >> 33
+    }
+
+    fn push_rlw(&mut self, bit: bool) {
+        self.buffer.push(u64::from(bit));
+        self.rlw = self.buffer.len() - 1;
+    }
+
+    /// Add a word of all `bit`s, extending the current run if possible.
+    fn add_run(&mut self, bit: bool) {
+        if self.run_bit() != bit && self.run_len() == 0 && self.literals() == 0
+        {
+            self.buffer[self.rlw] ^= 1;
+        } else if self.literals() != 0
+            || self.run_bit() != bit
+            || self.run_len() == MAX_RUN
+        {
+            self.push_rlw(bit);
+        }
+        self.buffer[self.rlw] += 1 << 1;
+    }
+
+    /// Add a word that is stored as it is.
+    fn add_literal(&mut self, word: u64) {
+        if self.literals() == MAX_LITERALS {
+            self.push_rlw(false);
+        }
+        self.buffer[self.rlw] += 1 << 33;
+        self.buffer.push(word);
+    }
+}
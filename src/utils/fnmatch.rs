@@ -1,8 +1,14 @@
 //! A cross-platform file globbing module.
 //!
-//! This module provides functionality to perform file globbing (pattern matching for file paths)
-//! on both Unix-like systems and Windows. It uses the native globbing functions of each platform
-//! for efficient file matching.
+//! This module provides functionality to perform file globbing (pattern
+//! matching for file paths). Patterns are split into their components, and
+//! the directories are read one component at a time, with each name matched
+//! using the same glob matcher as gitignore patterns, [`wildmatch_with`].
+//!
+//! Like `glob(3)`, `*`, `?` and `[...]` only match within a component, and
+//! names starting with a `.` are only matched by components that start with
+//! a `.` too. Matching is case-insensitive on Windows, as its file systems
+//! are, and case-sensitive elsewhere, unless [`fnmatch_with`] says otherwise.
 //!
 //! # Examples
 //!
@@ -20,281 +26,159 @@
 //! }
 //! ```
 
-#![allow(unsafe_code)]
-#![allow(clippy::missing_panics_doc, clippy::missing_errors_doc)]
-
-/// Unix-specific globbing implementation.
-#[allow(clippy::struct_field_names)]
-#[cfg(target_family = "unix")]
-pub mod glob {
-    use std::error::Error;
-    use std::ffi::{c_char, c_int, CStr, CString};
-    use std::fs::canonicalize;
-    use std::path::PathBuf;
-    use std::ptr;
-
-    #[cfg(target_os = "macos")]
-    use std::ffi::c_void;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf, MAIN_SEPARATOR_STR};
+
+use crate::utils::gitignore::wildmatch_with;
+
+/// Whether matching ignores case unless told otherwise
+const IGNORE_CASE: bool = cfg!(target_family = "windows");
+
+/// Whether a backslash escapes the character after it, which is not the
+/// case on Windows, where it separates components.
+const ESCAPES: bool = cfg!(not(target_family = "windows"));
+
+/// Finds the files matching `pattern`.
+///
+/// On Unix-like systems the paths are canonical. On Windows, they are the
+/// directories of the pattern joined with the names that matched.
+///
+/// # Returns
+///
+/// * `Ok(Vec<String>)` - The matched file paths, sorted.
+/// * `Err(Box<dyn Error>)` - An error if no matches are found or if the
+///   pattern is invalid.
+///
+/// # Errors
+///
+/// If nothing matches the pattern, or it contains a NUL byte.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mini_git::utils::fnmatch::fnmatch;
+///
+/// #[cfg(target_family = "unix")]
+/// fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let matches = fnmatch("/home/user/*.rs")?;
+///     for file in matches {
+///         println!("Matched Rust file: {}", file);
+///     }
+///     Ok(())
+/// }
+/// ```
+pub fn fnmatch(pattern: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    fnmatch_with(pattern, IGNORE_CASE)
+}
 
-    #[cfg(target_os = "macos")]
-    #[repr(C)]
-    struct Glob {
-        gl_pathc: usize,            // Count of total paths so far.
-        gl_matchc: usize,           // Count of paths matching pattern.
-        gl_offs: usize,             // Reserved at beginning of gl_pathv.
-        gl_flags: c_int,            // Copy of flags parameter to glob.
-        gl_pathv: *mut *mut c_char, // List of paths matching pattern.
-        // Additional fields specific to macOS
-        gl_errfunc:
-            Option<extern "C" fn(epath: *const c_char, errno: c_int) -> c_int>,
-        gl_closedir: Option<unsafe extern "C" fn(dirp: *mut c_void) -> c_int>,
-        gl_readdir:
-            Option<unsafe extern "C" fn(dirp: *mut c_void) -> *mut c_void>,
-        gl_opendir:
-            Option<unsafe extern "C" fn(name: *const c_char) -> *mut c_void>,
-        gl_lstat: Option<
-            unsafe extern "C" fn(
-                pathname: *const c_char,
-                buf: *mut c_void,
-            ) -> c_int,
-        >,
-        gl_stat: Option<
-            unsafe extern "C" fn(
-                pathname: *const c_char,
-                buf: *mut c_void,
-            ) -> c_int,
-        >,
+/// Finds the files matching `pattern` like [`fnmatch`], ignoring the case of
+/// ASCII letters if `ignore_case` is set.
+///
+/// # Errors
+///
+/// If nothing matches the pattern, or it contains a NUL byte.
+pub fn fnmatch_with(
+    pattern: &str,
+    ignore_case: bool,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    if pattern.contains('\0') {
+        return Err("Pattern cannot contain NUL bytes".into());
     }
 
-    #[cfg(target_os = "macos")]
-    impl Default for Glob {
-        fn default() -> Self {
-            unsafe { std::mem::zeroed() }
+    let mut components = pattern.split(is_separator).peekable();
+    let mut candidates = match components.next_if(|c| is_root(c)) {
+        Some(root) => {
+            vec![PathBuf::from(format!("{root}{MAIN_SEPARATOR_STR}"))]
         }
-    }
-
-    #[cfg(not(target_os = "macos"))]
-    #[repr(C)]
-    struct Glob {
-        gl_pathc: usize,
-        gl_pathv: *mut *mut c_char,
-        gl_offs: usize,
-        _reserved: [usize; 6],
-    }
+        None => vec![PathBuf::new()],
+    };
 
-    #[cfg(not(target_os = "macos"))]
-    impl Default for Glob {
-        fn default() -> Self {
-            Self {
-                gl_pathc: 0,
-                gl_pathv: ptr::null_mut(),
-                gl_offs: 0,
-                _reserved: [0usize; 6],
+    for component in components.filter(|c| !c.is_empty()) {
+        if !is_glob(component) {
+            for candidate in &mut candidates {
+                candidate.push(component);
             }
+            continue;
         }
+        candidates = candidates
+            .iter()
+            .flat_map(|dir| read_matching(dir, component, ignore_case))
+            .collect();
     }
 
-    const GLOB_NOMATCH: c_int = 3;
-
-    #[link(name = "c")]
-    extern "C" {
-        fn glob(
-            pattern: *const c_char,
-            flags: c_int,
-            errfunc: *mut std::os::raw::c_void,
-            pglob: *mut Glob,
-        ) -> c_int;
-        fn globfree(pglob: *mut Glob);
-    }
-
-    /// Performs file globbing on Unix-like systems.
-    ///
-    /// This function uses the system's `glob` function to find files matching the given pattern.
-    ///
-    /// # Arguments
-    ///
-    /// * `pattern` - A string slice that holds the pattern to match against.
-    ///
-    /// # Returns
-    ///
-    /// * `Ok(Vec<String>)` - A vector of strings containing the matched file paths.
-    /// * `Err(Box<dyn Error>)` - An error if no matches are found or if an error occurs during globbing.
-    ///
-    /// # Examples
-    ///
-    /// ```no_run
-    /// use mini_git::utils::fnmatch::fnmatch;
-    ///
-    /// #[cfg(target_family = "unix")]
-    /// fn main() -> Result<(), Box<dyn std::error::Error>> {
-    ///     let matches = fnmatch("/home/user/*.rs")?;
-    ///     for file in matches {
-    ///         println!("Matched Rust file: {}", file);
-    ///     }
-    ///     Ok(())
-    /// }
-    /// ```
-    pub fn fnmatch(pattern: &str) -> Result<Vec<String>, Box<dyn Error>> {
-        let pattern = CString::new(pattern)?;
-        let mut glob_result = Glob::default();
-
-        let mut paths = vec![];
-
-        unsafe {
-            let result =
-                glob(pattern.as_ptr(), 0, ptr::null_mut(), &mut glob_result);
-
-            match result {
-                0 => {
-                    for i in 0..glob_result.gl_pathc {
-                        let path = CStr::from_ptr(*glob_result.gl_pathv.add(i));
-                        let file_name =
-                            String::from_utf8_lossy(path.to_bytes())
-                                .to_string();
-                        paths.push(
-                            canonicalize(PathBuf::from(file_name))
-                                .expect("Should be able to get canonical path")
-                                .to_str()
-                                .expect(
-                                    "Should be able to convert path to string",
-                                )
-                                .to_string(),
-                        );
-                    }
-
-                    globfree(&mut glob_result);
-                    Ok(paths)
-                }
-                GLOB_NOMATCH => Err("No matches found!".into()),
-                _ => Err("An error occurred while globbing.".into()),
-            }
-        }
+    let mut paths = candidates
+        .into_iter()
+        .filter(|path| !path.as_os_str().is_empty())
+        .filter(|path| path.symlink_metadata().is_ok())
+        .filter_map(|path| display(&path))
+        .collect::<Vec<_>>();
+    if paths.is_empty() {
+        return Err("No matches found!".into());
     }
+    paths.sort();
+    paths.dedup();
+    Ok(paths)
 }
 
-/// Windows-specific globbing implementation.
-#[cfg(target_family = "windows")]
-pub mod glob {
-    use std::error::Error;
-    use std::ffi::OsString;
-    use std::os::windows::ffi::OsStrExt;
-    use std::os::windows::ffi::OsStringExt;
-    use std::path::{Path, PathBuf};
-    use std::ptr;
-
-    use std::os::raw::c_void;
-
-    type Handle = *mut c_void;
-    type Dword = u32;
-    type Lpcwstr = *const u16;
-
-    #[repr(C)]
-    struct Win32FindDataw {
-        dw_file_attributes: Dword,
-        ft_creation_time: [Dword; 2],
-        ft_last_access_time: [Dword; 2],
-        ft_last_write_time: [Dword; 2],
-        n_file_size_high: Dword,
-        n_file_size_low: Dword,
-        dw_reserved0: Dword,
-        dw_reserved1: Dword,
-        c_file_name: [u16; 260],
-        c_alternate_file_name: [u16; 14],
-    }
-
-    #[link(name = "kernel32")]
-    extern "system" {
-        fn FindFirstFileW(
-            lpFileName: Lpcwstr,
-            lpFindFileData: *mut Win32FindDataw,
-        ) -> Handle;
-        fn FindNextFileW(
-            hFindFile: Handle,
-            lpFindFileData: *mut Win32FindDataw,
-        ) -> i32;
-        fn FindClose(hFindFile: Handle) -> i32;
-    }
-
-    /// Performs file globbing on Windows systems.
-    ///
-    /// This function uses the Windows API functions to find files matching the given pattern.
-    ///
-    /// # Arguments
-    ///
-    /// * `pattern` - A string slice that holds the pattern to match against.
-    ///
-    /// # Returns
-    ///
-    /// * `Ok(Vec<String>)` - A vector of strings containing the matched file paths.
-    /// * `Err(Box<dyn Error>)` - An error if no matches are found or if the pattern is invalid.
-    ///
-    /// # Examples
-    ///
-    /// ```no_run
-    /// use mini_git::utils::fnmatch::fnmatch;
-    ///
-    /// #[cfg(target_family = "windows")]
-    /// fn main() -> Result<(), Box<dyn std::error::Error>> {
-    ///     let matches = fnmatch("C:\\Users\\*.txt")?;
-    ///     for file in matches {
-    ///         println!("Matched text file: {}", file);
-    ///     }
-    ///     Ok(())
-    /// }
-    /// ```
-    #[allow(clippy::cmp_null)]
-    pub fn fnmatch(pattern: &str) -> Result<Vec<String>, Box<dyn Error>> {
-        let parent = if pattern.contains('\\') {
-            Path::new(pattern)
-                .parent()
-                .expect("Should have a parent")
-                .to_str()
-                .unwrap()
-                .to_string()
-        } else {
-            ".\\".to_string()
-        };
-        let mut results = Vec::new();
-        let wide_pattern: Vec<u16> = OsString::from(pattern)
-            .encode_wide()
-            .chain(Some(0))
-            .collect();
-        let mut find_data: Win32FindDataw = unsafe { std::mem::zeroed() };
+/// The entries of `dir` whose names match the glob `component`, sorted.
+///
+/// Directories that cannot be read have no entries.
+fn read_matching(
+    dir: &Path,
+    component: &str,
+    ignore_case: bool,
+) -> Vec<PathBuf> {
+    let read_from = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    let Ok(entries) = fs::read_dir(read_from) else {
+        return vec![];
+    };
+
+    let mut matched = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        // Hidden names must be matched explicitly
+        .filter(|name| !name.starts_with('.') || component.starts_with('.'))
+        .filter(|name| {
+            wildmatch_with(component.as_bytes(), name.as_bytes(), ignore_case)
+        })
+        .map(|name| dir.join(name))
+        .collect::<Vec<_>>();
+    matched.sort();
+    matched
+}
 
-        unsafe {
-            let handle = FindFirstFileW(wide_pattern.as_ptr(), &mut find_data);
-            if handle == (usize::MAX as *mut c_void) {
-                return Err(
-                    "Either no files were found, or pattern was invalid!"
-                        .into(),
-                );
-            } else if handle != ptr::null_mut() {
-                loop {
-                    let file_name = OsString::from_wide(
-                        &find_data.c_file_name[..find_data
-                            .c_file_name
-                            .iter()
-                            .position(|&x| x == 0)
-                            .unwrap_or(260)],
-                    );
-                    results.push(format!(
-                        "{parent}\\{}",
-                        PathBuf::from(&file_name)
-                            .to_str()
-                            .expect("Should be able to convert path to string")
-                    ));
+/// Whether `component` of a pattern has characters special to globs.
+fn is_glob(component: &str) -> bool {
+    component.contains(['*', '?', '[']) || (ESCAPES && component.contains('\\'))
+}
 
-                    if FindNextFileW(handle, &mut find_data) == 0 {
-                        break;
-                    }
-                }
+fn is_separator(c: char) -> bool {
+    c == '/' || (!ESCAPES && c == '\\')
+}
 
-                FindClose(handle);
-            }
-        }
+/// Whether the first component of a pattern makes it absolute, as the empty
+/// component before a leading separator, or a drive like `C:` on Windows.
+fn is_root(component: &str) -> bool {
+    component.is_empty()
+        || (!ESCAPES
+            && component.len() == 2
+            && component.ends_with(':')
+            && component.starts_with(|c: char| c.is_ascii_alphabetic()))
+}
 
-        Ok(results)
-    }
+/// The path to report for a match.
+#[cfg(target_family = "unix")]
+fn display(path: &Path) -> Option<String> {
+    Some(fs::canonicalize(path).ok()?.to_str()?.to_owned())
 }
 
-pub use glob::*;
+/// The path to report for a match.
+#[cfg(not(target_family = "unix"))]
+fn display(path: &Path) -> Option<String> {
+    path.to_str().map(str::to_owned)
+}
@@ -0,0 +1,190 @@
+//! Gitattributes parsing and lookup.
+//!
+//! This module implements the attribute rules described in
+//! `gitattributes(5)`. Each line of a `.gitattributes` file is a pattern
+//! followed by the attributes it assigns. Rules are collected into a
+//! [`GitAttributes`], each scoped to the directory whose `.gitattributes`
+//! defined it, and paths are queried relative to the worktree root using `/`
+//! as the separator.
+//!
+//! # Examples
+//!
+//! ```
+//! use mini_git::utils::gitattributes::{AttrValue, GitAttributes};
+//!
+//! let mut attributes = GitAttributes::new();
+//! attributes.add_patterns("", "*.pdf diff=pdf\n*.png binary\n");
+//!
+//! assert_eq!(
+//!     attributes.get("docs/manual.pdf", "diff"),
+//!     Some(AttrValue::Value("pdf".to_owned()))
+//! );
+//! assert_eq!(attributes.get("logo.png", "diff"), Some(AttrValue::Unset));
+//! assert_eq!(attributes.get("main.rs", "diff"), None);
+//! ```
+
+use std::fs;
+use std::path::Path;
+
+use crate::utils::gitignore::wildmatch;
+
+/// The state of an attribute for a path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttrValue {
+    /// The attribute is set, as in `attr`
+    Set,
+    /// The attribute is unset, as in `-attr`
+    Unset,
+    /// The attribute is set to a value, as in `attr=value`
+    Value(String),
+}
+
+/// A single line of a gitattributes file.
+#[derive(Debug, Clone)]
+struct Rule {
+    /// The glob, without anchoring.
+    glob: String,
+    /// The directory the rule was defined in, relative to the worktree.
+    base: String,
+    /// Whether the glob is matched against the full path relative to
+    /// `base`, rather than just the file name.
+    anchored: bool,
+    /// The attributes assigned, where `None` reverts an attribute to
+    /// unspecified, as in `!attr`.
+    attrs: Vec<(String, Option<AttrValue>)>,
+}
+
+/// An ordered collection of gitattributes rules.
+///
+/// Later rules take precedence over earlier ones, so sources should be added
+/// from lowest to highest precedence.
+#[derive(Debug, Clone, Default)]
+pub struct GitAttributes {
+    rules: Vec<Rule>,
+}
+
+impl Rule {
+    fn parse(line: &str, base: &str) -> Option<Self> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let mut parts = line.split_whitespace();
+        let pattern = parts.next()?;
+        // Negative patterns are forbidden, and patterns for directories never
+        // match the files inside them
+        if pattern.starts_with('!') || pattern.ends_with('/') {
+            return None;
+        }
+
+        let attrs = parts
+            .flat_map(|attr| {
+                // `binary` is a built-in macro attribute
+                if attr == "binary" {
+                    return ["diff", "merge", "text"]
+                        .map(|name| (name.to_owned(), Some(AttrValue::Unset)))
+                        .to_vec();
+                }
+                let parsed = if let Some(name) = attr.strip_prefix('-') {
+                    (name.to_owned(), Some(AttrValue::Unset))
+                } else if let Some(name) = attr.strip_prefix('!') {
+                    (name.to_owned(), None)
+                } else if let Some((name, value)) = attr.split_once('=') {
+                    (name.to_owned(), Some(AttrValue::Value(value.to_owned())))
+                } else {
+                    (attr.to_owned(), Some(AttrValue::Set))
+                };
+                vec![parsed]
+            })
+            .collect();
+
+        Some(Self {
+            glob: pattern.trim_start_matches('/').to_owned(),
+            base: base.trim_matches('/').to_owned(),
+            anchored: pattern.contains('/'),
+            attrs,
+        })
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        let relative = if self.base.is_empty() {
+            path
+        } else {
+            match path
+                .strip_prefix(&self.base)
+                .and_then(|rest| rest.strip_prefix('/'))
+            {
+                Some(rest) => rest,
+                None => return false,
+            }
+        };
+
+        if self.anchored {
+            wildmatch(self.glob.as_bytes(), relative.as_bytes())
+        } else {
+            let name = relative.rsplit('/').next().unwrap_or(relative);
+            wildmatch(self.glob.as_bytes(), name.as_bytes())
+        }
+    }
+}
+
+impl GitAttributes {
+    /// Create an empty collection, which assigns no attributes.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the rules in `text`, scoped to the directory `base`.
+    ///
+    /// `base` is relative to the worktree root; use `""` for rules that
+    /// apply to the whole worktree.
+    pub fn add_patterns(&mut self, base: &str, text: &str) -> &mut Self {
+        self.rules
+            .extend(text.lines().filter_map(|line| Rule::parse(line, base)));
+        self
+    }
+
+    /// Add the rules from the file at `path`, scoped to the directory
+    /// `base`. A missing file adds nothing.
+    ///
+    /// # Errors
+    ///
+    /// If the file exists but cannot be read.
+    pub fn add_file(
+        &mut self,
+        base: &str,
+        path: &Path,
+    ) -> Result<&mut Self, String> {
+        if !path.is_file() {
+            return Ok(self);
+        }
+        let text = fs::read_to_string(path).map_err(|e| {
+            format!("Failed to read attributes file {}: {e}", path.display())
+        })?;
+        Ok(self.add_patterns(base, &text))
+    }
+
+    /// Whether the collection has no rules.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Look up the attribute `name` for the file at `path`.
+    ///
+    /// Returns `None` if the attribute is unspecified.
+    #[must_use]
+    pub fn get(&self, path: &str, name: &str) -> Option<AttrValue> {
+        let path = path.trim_matches('/');
+        self.rules
+            .iter()
+            .rev()
+            .filter(|rule| rule.matches(path))
+            .find_map(|rule| {
+                rule.attrs.iter().rev().find(|(attr, _)| attr == name)
+            })
+            .and_then(|(_, value)| value.clone())
+    }
+}
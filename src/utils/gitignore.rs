@@ -0,0 +1,535 @@
+//! Gitignore pattern matching.
+//!
+//! This module implements the pattern rules described in `gitignore(5)`.
+//! Patterns are collected into a [`GitignoreSet`], each scoped to the
+//! directory whose `.gitignore` defined it, and paths are queried relative to
+//! the worktree root using `/` as the separator.
+//!
+//! # Examples
+//!
+//! ```
+//! use mini_git::utils::gitignore::GitignoreSet;
+//!
+//! let mut set = GitignoreSet::new();
+//! set.add_patterns("", "*.o\n!keep.o\nbuild/\n");
+//! set.add_patterns("docs", "/draft.md\n");
+//!
+//! assert!(set.is_ignored("src/main.o", false));
+//! assert!(!set.is_ignored("keep.o", false));
+//! assert!(set.is_ignored("build/out.txt", false));
+//! assert!(set.is_ignored("docs/draft.md", false));
+//! assert!(!set.is_ignored("draft.md", false));
+//! ```
+//!
+//! Walks of the worktree use a [`GitignoreStack`] instead, which reads the
+//! `.gitignore` of each directory as the walk enters it, and only matches
+//! the rules of the directories above the paths it is asked about.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// The name of the file of patterns in each directory
+pub const IGNORE_FILE: &str = ".gitignore";
+
+/// A single gitignore pattern.
+#[derive(Debug, Clone)]
+struct Pattern {
+    /// The glob, without negation, anchoring or trailing slash.
+    glob: String,
+    /// The directory the pattern was defined in, relative to the worktree.
+    base: String,
+    /// Whether a match re-includes the path instead of ignoring it.
+    negated: bool,
+    /// Whether the pattern only matches directories.
+    dir_only: bool,
+    /// Whether the pattern is matched against the full path relative to
+    /// `base`, rather than just the file name.
+    anchored: bool,
+}
+
+/// An ordered collection of gitignore patterns.
+///
+/// Later patterns take precedence over earlier ones, so sources should be
+/// added from lowest to highest precedence.
+#[derive(Debug, Clone, Default)]
+pub struct GitignoreSet {
+    patterns: Vec<Pattern>,
+}
+
+/// Gitignore rules for a walk of the worktree, stacking the rules of each
+/// directory as the walk enters it.
+///
+/// The rules of a directory's `.gitignore` are read the first time the walk
+/// enters it, and kept for when it enters it again. Entering a directory
+/// leaves those that do not contain it, dropping their rules from the stack.
+///
+/// A walk asks about the entries of the directory it entered last, and does
+/// not descend into ignored directories, so only the entries themselves are
+/// matched, against the rules of their directory and of the ones above it.
+///
+/// # Examples
+///
+/// ```
+/// use mini_git::utils::gitignore::{GitignoreSet, GitignoreStack};
+///
+/// let root = std::env::temp_dir().join("gitignore_stack_example");
+/// std::fs::create_dir_all(root.join("src")).unwrap();
+/// std::fs::write(root.join(".gitignore"), "*.o\n").unwrap();
+/// std::fs::write(root.join("src").join(".gitignore"), "!main.o\n").unwrap();
+///
+/// let mut rules = GitignoreStack::new(&root, GitignoreSet::new());
+/// rules.enter("src").unwrap();
+/// assert!(rules.is_ignored("src/lib.o", false));
+/// assert!(!rules.is_ignored("src/main.o", false));
+///
+/// rules.enter("").unwrap();
+/// assert!(rules.is_ignored("main.o", false));
+/// # std::fs::remove_dir_all(&root).unwrap();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct GitignoreStack {
+    /// The worktree root, or `None` if `.gitignore` files are not read.
+    root: Option<PathBuf>,
+    /// The rules that apply to the whole worktree, below the stacked ones.
+    base: GitignoreSet,
+    /// The directories entered, outermost first, with their rules.
+    frames: Vec<(String, Arc<[Pattern]>)>,
+    /// The rules of every directory entered so far.
+    cache: HashMap<String, Arc<[Pattern]>>,
+}
+
+impl Pattern {
+    fn parse(line: &str, base: &str) -> Option<Self> {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let line = trim_trailing_spaces(line);
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (
+                false,
+                line.strip_prefix('\\')
+                    .filter(|rest| {
+                        rest.starts_with('#') || rest.starts_with('!')
+                    })
+                    .unwrap_or(line),
+            ),
+        };
+
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+
+        // A slash anywhere but the end anchors the pattern to its directory
+        let anchored = line.contains('/');
+        let glob = line.strip_prefix('/').unwrap_or(line);
+        if glob.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            glob: glob.to_owned(),
+            base: base.trim_matches('/').to_owned(),
+            negated,
+            dir_only,
+            anchored,
+        })
+    }
+
+    fn matches(&self, path: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+
+        let relative = if self.base.is_empty() {
+            path
+        } else {
+            match path
+                .strip_prefix(&self.base)
+                .and_then(|rest| rest.strip_prefix('/'))
+            {
+                Some(rest) => rest,
+                None => return false,
+            }
+        };
+
+        if self.anchored {
+            wildmatch(self.glob.as_bytes(), relative.as_bytes())
+        } else {
+            let name = relative.rsplit('/').next().unwrap_or(relative);
+            wildmatch(self.glob.as_bytes(), name.as_bytes())
+        }
+    }
+}
+
+impl GitignoreSet {
+    /// Create an empty set, which ignores nothing.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the patterns in `text`, scoped to the directory `base`.
+    ///
+    /// `base` is relative to the worktree root; use `""` for patterns that
+    /// apply to the whole worktree.
+    pub fn add_patterns(&mut self, base: &str, text: &str) -> &mut Self {
+        self.patterns
+            .extend(text.lines().filter_map(|line| Pattern::parse(line, base)));
+        self
+    }
+
+    /// Add the patterns from the file at `path`, scoped to the directory
+    /// `base`. A missing file adds nothing.
+    ///
+    /// # Errors
+    ///
+    /// If the file exists but cannot be read.
+    pub fn add_file(
+        &mut self,
+        base: &str,
+        path: &Path,
+    ) -> Result<&mut Self, String> {
+        let patterns = read_patterns(base, path)?;
+        self.patterns.extend(patterns.iter().cloned());
+        Ok(self)
+    }
+
+    /// Whether the set has no patterns.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Check whether `path` is ignored.
+    ///
+    /// A path inside an ignored directory is ignored, even if a later
+    /// pattern would re-include it, as git never looks inside ignored
+    /// directories.
+    #[must_use]
+    pub fn is_ignored(&self, path: &str, is_dir: bool) -> bool {
+        let path = path.trim_matches('/');
+        let ancestor_ignored = path
+            .match_indices('/')
+            .any(|(i, _)| self.matched(&path[..i], true) == Some(true));
+        ancestor_ignored || self.matched(path, is_dir) == Some(true)
+    }
+
+    /// Check whether everything in the directory `path` is ignored.
+    ///
+    /// Once a directory is ignored, no pattern can re-include the paths
+    /// inside it, so a walker can skip it without testing its contents.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mini_git::utils::gitignore::GitignoreSet;
+    ///
+    /// let mut set = GitignoreSet::new();
+    /// set.add_patterns("", "target/\nlogs/*\n!logs/keep.log\n");
+    ///
+    /// assert!(set.is_dir_fully_ignored("target"));
+    /// assert!(set.is_dir_fully_ignored("target/debug"));
+    /// // Files in `logs` may be re-included, so it must be searched
+    /// assert!(!set.is_dir_fully_ignored("logs"));
+    /// ```
+    #[must_use]
+    pub fn is_dir_fully_ignored(&self, path: &str) -> bool {
+        self.is_ignored(path, true)
+    }
+
+    /// Check whether a walker should descend into the directory `path`,
+    /// given that it descended into the directory containing it.
+    ///
+    /// This is [`is_dir_fully_ignored`](Self::is_dir_fully_ignored) without
+    /// testing the parent directories again, which a walker going down from
+    /// the worktree root has already done.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mini_git::utils::gitignore::GitignoreSet;
+    ///
+    /// let mut set = GitignoreSet::new();
+    /// set.add_patterns("", "node_modules/\n");
+    ///
+    /// assert!(set.should_descend("src"));
+    /// assert!(!set.should_descend("web/node_modules"));
+    /// ```
+    #[must_use]
+    pub fn should_descend(&self, path: &str) -> bool {
+        self.matched(path.trim_matches('/'), true) != Some(true)
+    }
+
+    /// The verdict of the last pattern matching `path`, if any.
+    fn matched(&self, path: &str, is_dir: bool) -> Option<bool> {
+        self.patterns
+            .iter()
+            .rev()
+            .find(|pattern| pattern.matches(path, is_dir))
+            .map(|pattern| !pattern.negated)
+    }
+}
+
+impl GitignoreStack {
+    /// Create a stack for a walk of the worktree at `root`, whose rules are
+    /// stacked above those of `base`, like `.git/info/exclude`.
+    #[must_use]
+    pub fn new(root: &Path, base: GitignoreSet) -> Self {
+        Self {
+            root: Some(root.to_path_buf()),
+            base,
+            ..Self::default()
+        }
+    }
+
+    /// Enter the directory `dir`, relative to the worktree root, reading the
+    /// `.gitignore` of it and of the directories above it if they have not
+    /// been read yet, and leaving the directories that do not contain it.
+    ///
+    /// # Errors
+    ///
+    /// If a `.gitignore` exists but cannot be read.
+    pub fn enter(&mut self, dir: &str) -> Result<(), String> {
+        let Some(root) = &self.root else {
+            return Ok(());
+        };
+        let dir = dir.trim_matches('/');
+        while self
+            .frames
+            .last()
+            .is_some_and(|(entered, _)| !contains(entered, dir))
+        {
+            self.frames.pop();
+        }
+
+        // The frames are the ancestors of `dir`, outermost first
+        let ancestors = std::iter::once("")
+            .chain(dir.match_indices('/').map(|(i, _)| &dir[..i]))
+            .chain((!dir.is_empty()).then_some(dir));
+        for ancestor in ancestors.skip(self.frames.len()) {
+            let patterns = if let Some(patterns) = self.cache.get(ancestor) {
+                Arc::clone(patterns)
+            } else {
+                let path = root.join(ancestor).join(IGNORE_FILE);
+                let patterns = read_patterns(ancestor, &path)?;
+                self.cache
+                    .insert(ancestor.to_owned(), Arc::clone(&patterns));
+                patterns
+            };
+            self.frames.push((ancestor.to_owned(), patterns));
+        }
+        Ok(())
+    }
+
+    /// Check whether `path`, an entry of the directory entered last, is
+    /// ignored.
+    ///
+    /// The directories above it are assumed not to be ignored, as the walk
+    /// would not have entered them otherwise.
+    #[must_use]
+    pub fn is_ignored(&self, path: &str, is_dir: bool) -> bool {
+        let path = path.trim_matches('/');
+        let stacked = self
+            .frames
+            .iter()
+            .rev()
+            .flat_map(|(_, patterns)| patterns.iter().rev())
+            .find(|pattern| pattern.matches(path, is_dir))
+            .map(|pattern| !pattern.negated);
+        stacked.or_else(|| self.base.matched(path, is_dir)) == Some(true)
+    }
+}
+
+/// Whether the directory `dir` contains `path`, or is `path`.
+fn contains(dir: &str, path: &str) -> bool {
+    dir.is_empty()
+        || path
+            .strip_prefix(dir)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// The patterns of the file at `path`, scoped to the directory `base`. A
+/// missing file has none.
+fn read_patterns(base: &str, path: &Path) -> Result<Arc<[Pattern]>, String> {
+    if !path.is_file() {
+        return Ok(Arc::from([]));
+    }
+    let text = fs::read_to_string(path).map_err(|e| {
+        format!("Failed to read ignore file {}: {e}", path.display())
+    })?;
+    Ok(text
+        .lines()
+        .filter_map(|line| Pattern::parse(line, base))
+        .collect())
+}
+
+/// Trailing spaces are ignored unless escaped with a backslash.
+fn trim_trailing_spaces(line: &str) -> &str {
+    let trimmed = line.trim_end_matches(' ');
+    if trimmed.ends_with('\\') && trimmed.len() < line.len() {
+        &line[..=trimmed.len()]
+    } else {
+        trimmed
+    }
+}
+
+/// Match `text` against the glob `pattern`.
+///
+/// `*` and `?` do not match `/`, while `**` matches across directories.
+/// A leading `**/` or an inner `/**/` also matches zero directories.
+#[must_use]
+pub fn wildmatch(pattern: &[u8], text: &[u8]) -> bool {
+    wildmatch_with(pattern, text, false)
+}
+
+/// Match `text` against the glob `pattern` like [`wildmatch`], ignoring the
+/// case of ASCII letters if `ignore_case` is set.
+///
+/// # Examples
+///
+/// ```
+/// use mini_git::utils::gitignore::wildmatch_with;
+///
+/// assert!(wildmatch_with(b"*.TXT", b"notes.txt", true));
+/// assert!(wildmatch_with(b"[a-c]*", b"Build", true));
+/// assert!(!wildmatch_with(b"*.TXT", b"notes.txt", false));
+/// ```
+#[must_use]
+pub fn wildmatch_with(pattern: &[u8], text: &[u8], ignore_case: bool) -> bool {
+    dowild(pattern, text, ignore_case) == Wild::Match
+}
+
+/// The result of matching part of a glob, like git's `dowild`.
+///
+/// The aborts let stars stop trying later positions that cannot match,
+/// which keeps patterns with many stars from taking exponential time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Wild {
+    Match,
+    NoMatch,
+    /// The text ran out, so no shorter text can match either
+    AbortAll,
+    /// A `*` would have to match a `/`, which only a `**` can
+    AbortToStarStar,
+}
+
+fn dowild(pattern: &[u8], text: &[u8], ignore_case: bool) -> Wild {
+    let matches = |pattern, text| dowild(pattern, text, ignore_case);
+    match pattern {
+        [] if text.is_empty() => Wild::Match,
+        [] => Wild::NoMatch,
+        [b'*', b'*', rest @ ..] => {
+            let (starts, rest) = match rest {
+                // A leading or inner `**/` also matches zero directories
+                [b'/', after @ ..] => {
+                    let slashes = text.iter().enumerate();
+                    let after_slash = slashes
+                        .filter(|&(_, &c)| c == b'/')
+                        .map(|(i, _)| i + 1);
+                    (std::iter::once(0).chain(after_slash).collect(), after)
+                }
+                [] => return Wild::Match,
+                _ => ((0..=text.len()).collect::<Vec<_>>(), rest),
+            };
+            for i in starts {
+                match matches(rest, &text[i..]) {
+                    Wild::NoMatch | Wild::AbortToStarStar => {}
+                    result => return result,
+                }
+            }
+            Wild::AbortAll
+        }
+        [b'*', rest @ ..] => {
+            for i in 0..=text.len() {
+                match matches(rest, &text[i..]) {
+                    Wild::NoMatch => {}
+                    result => return result,
+                }
+                if text.get(i) == Some(&b'/') {
+                    return Wild::AbortToStarStar;
+                }
+            }
+            Wild::AbortAll
+        }
+        _ if text.is_empty() => Wild::AbortAll,
+        [b'?', rest @ ..] => match text {
+            [c, text @ ..] if *c != b'/' => matches(rest, text),
+            _ => Wild::NoMatch,
+        },
+        [b'[', class @ ..] => {
+            match (match_class(class, text.first(), ignore_case), text) {
+                (Some((true, len)), [_, text @ ..]) => {
+                    matches(&class[len..], text)
+                }
+                // An unterminated class is a literal bracket
+                (None, [b'[', text @ ..]) => matches(class, text),
+                _ => Wild::NoMatch,
+            }
+        }
+        // A backslash escapes the character after it
+        [b'\\', c, rest @ ..] | [c, rest @ ..] => match text {
+            [t, text @ ..]
+                if t == c || (ignore_case && t.eq_ignore_ascii_case(c)) =>
+            {
+                matches(rest, text)
+            }
+            _ => Wild::NoMatch,
+        },
+    }
+}
+
+/// Match a character against the class following a `[`.
+///
+/// Returns whether it matched and the length of the class including the
+/// closing `]`, or `None` if the class is unterminated.
+fn match_class(
+    class: &[u8],
+    c: Option<&u8>,
+    ignore_case: bool,
+) -> Option<(bool, usize)> {
+    let (negated, start) = match class.first() {
+        Some(b'!' | b'^') => (true, 1),
+        _ => (false, 0),
+    };
+
+    let mut matched = false;
+    let mut i = start;
+    loop {
+        let first = *class.get(i)?;
+        // A `]` right at the start is part of the class
+        if first == b']' && i > start {
+            break;
+        }
+        let (first, len) = if first == b'\\' {
+            (*class.get(i + 1)?, 2)
+        } else {
+            (first, 1)
+        };
+        i += len;
+
+        let last = match (class.get(i), class.get(i + 1)) {
+            (Some(b'-'), Some(&last)) if last != b']' => {
+                i += 2;
+                last
+            }
+            _ => first,
+        };
+        let in_range = |c: u8| c != b'/' && (first..=last).contains(&c);
+        if c.is_some_and(|&c| {
+            in_range(c)
+                || (ignore_case
+                    && (in_range(c.to_ascii_lowercase())
+                        || in_range(c.to_ascii_uppercase())))
+        }) {
+            matched = true;
+        }
+    }
+
+    Some((matched != negated && c.is_some_and(|&c| c != b'/'), i + 1))
+}
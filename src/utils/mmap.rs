@@ -0,0 +1,188 @@
+//! Read-only memory-mapped files
+//!
+//! A [`Mmap`] maps a whole file into memory and dereferences to its bytes, so
+//! that random reads do not need a system call each. On platforms where
+//! mapping is not supported, the file is read into memory instead.
+//!
+//! The mapping is private and read-only. Like git does for packfiles, the
+//! file is assumed not to be truncated while it is mapped, which is true for
+//! files that are only ever replaced, never modified in place.
+
+#![allow(unsafe_code)]
+
+use std::fs;
+use std::ops::Deref;
+use std::path::Path;
+
+#[cfg(all(target_family = "unix", target_pointer_width = "64"))]
+mod sys {
+    use std::ffi::{c_int, c_void};
+
+    pub const PROT_READ: c_int = 0x1;
+    pub const MAP_PRIVATE: c_int = 0x2;
+    pub const MAP_FAILED: *mut c_void = !0 as *mut c_void;
+
+    #[link(name = "c")]
+    extern "C" {
+        pub fn mmap(
+            addr: *mut c_void,
+            len: usize,
+            prot: c_int,
+            flags: c_int,
+            fd: c_int,
+            offset: i64,
+        ) -> *mut c_void;
+        pub fn munmap(addr: *mut c_void, len: usize) -> c_int;
+    }
+}
+
+/// The contents of a file, mapped into memory.
+///
+/// # Examples
+///
+/// ```
+/// use mini_git::utils::mmap::Mmap;
+///
+/// let map = Mmap::open(std::path::Path::new("Cargo.toml"))?;
+/// assert!(map.starts_with(b"[package]"));
+/// # Ok::<(), String>(())
+/// ```
+#[derive(Debug)]
+pub struct Mmap {
+    inner: Inner,
+}
+
+#[cfg(all(target_family = "unix", target_pointer_width = "64"))]
+#[derive(Debug)]
+enum Inner {
+    Mapped {
+        ptr: *mut std::ffi::c_void,
+        len: usize,
+    },
+    Empty,
+}
+
+#[cfg(not(all(target_family = "unix", target_pointer_width = "64")))]
+#[derive(Debug)]
+enum Inner {
+    Read(Vec<u8>),
+}
+
+// SAFETY: The mapping is read-only and owned by the `Mmap`, so sharing or
+// moving it between threads is like sharing or moving a `Vec<u8>`.
+unsafe impl Send for Mmap {}
+unsafe impl Sync for Mmap {}
+
+impl Mmap {
+    /// Maps the file at `path` into memory.
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be opened or mapped.
+    #[cfg(all(target_family = "unix", target_pointer_width = "64"))]
+    pub fn open(path: &Path) -> Result<Self, String> {
+        use std::os::fd::AsRawFd;
+
+        let file = fs::File::open(path).map_err(|e| e.to_string())?;
+        let len = file.metadata().map_err(|e| e.to_string())?.len();
+        let len = usize::try_from(len)
+            .map_err(|_| format!("{} is too large to map", path.display()))?;
+        if len == 0 {
+            // Empty mappings are not allowed
+            return Ok(Self {
+                inner: Inner::Empty,
+            });
+        }
+
+        // SAFETY: A fresh private read-only mapping of the whole open file,
+        // the file descriptor may be closed once it is mapped.
+        let ptr = unsafe {
+            sys::mmap(
+                std::ptr::null_mut(),
+                len,
+                sys::PROT_READ,
+                sys::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == sys::MAP_FAILED {
+            return Err(format!(
+                "Failed to map {}: {}",
+                path.display(),
+                std::io::Error::last_os_error()
+            ));
+        }
+        Ok(Self {
+            inner: Inner::Mapped { ptr, len },
+        })
+    }
+
+    /// Reads the file at `path` into memory.
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be read.
+    #[cfg(not(all(target_family = "unix", target_pointer_width = "64")))]
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let data = fs::read(path).map_err(|e| e.to_string())?;
+        Ok(Self {
+            inner: Inner::Read(data),
+        })
+    }
+}
+
+impl Deref for Mmap {
+    type Target = [u8];
+
+    #[cfg(all(target_family = "unix", target_pointer_width = "64"))]
+    fn deref(&self) -> &[u8] {
+        match self.inner {
+            // SAFETY: The mapping is `len` readable bytes that live as long
+            // as `self`.
+            Inner::Mapped { ptr, len } => unsafe {
+                std::slice::from_raw_parts(ptr.cast::<u8>(), len)
+            },
+            Inner::Empty => &[],
+        }
+    }
+
+    #[cfg(not(all(target_family = "unix", target_pointer_width = "64")))]
+    fn deref(&self) -> &[u8] {
+        let Inner::Read(data) = &self.inner;
+        data
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        #[cfg(all(target_family = "unix", target_pointer_width = "64"))]
+        if let Inner::Mapped { ptr, len } = self.inner {
+            // SAFETY: The mapping was made by `open` and is unmapped once.
+            unsafe {
+                sys::munmap(ptr, len);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test::TempDir;
+
+    #[test]
+    fn test_open() {
+        let tmp_dir = TempDir::<()>::create("test_mmap_open");
+        let path = tmp_dir.tmp_dir().join("file");
+
+        fs::write(&path, b"mapped contents").unwrap();
+        let map = Mmap::open(&path).unwrap();
+        assert_eq!(&*map, b"mapped contents");
+
+        fs::write(&path, b"").unwrap();
+        assert!(Mmap::open(&path).unwrap().is_empty());
+
+        assert!(Mmap::open(&tmp_dir.tmp_dir().join("missing")).is_err());
+    }
+}
@@ -1,10 +1,21 @@
 pub mod argparse;
 pub mod collections;
+pub mod color;
 pub mod configparser;
 pub mod datetime;
+pub mod ewah;
 pub mod fnmatch;
+pub mod gitattributes;
+pub mod gitignore;
 pub mod hex;
+pub mod json;
+pub mod mmap;
 pub mod path;
+pub mod progress;
+pub mod regex;
 pub mod sha1;
+pub mod sha256;
 pub mod test;
+pub mod threadpool;
+pub mod trace;
 pub mod zlib;
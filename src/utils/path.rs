@@ -9,6 +9,7 @@
 //! - [`repo_path`]: Joins paths to a base directory without creating any files or directories.
 //! - [`repo_file`]: Returns a file path and optionally creates intermediate directories.
 //! - [`repo_dir`]: Returns a directory path and optionally creates the directory structure.
+//! - [`resolve_gitdir`]: Finds the git directory of a working tree, following `.git` files.
 //!
 //! ## Usage
 //!
@@ -25,6 +26,8 @@
 use std::fs;
 use std::path::{Path, PathBuf};
 
+use crate::utils::configparser::ConfigParser;
+
 const POSIX_PATH_SEPARATOR: char = '/';
 const CURRENT_DIR_STR: &str = ".";
 const PARENT_DIR_STR: &str = "..";
@@ -127,11 +130,11 @@ pub fn to_posix_path(path: &Path) -> Result<String, String> {
                     }
                     Prefix::Verbatim(_) => {
                         // Ignore the "\\?\" prefix for extended-length paths
-                        continue;
                     }
                     Prefix::DeviceNS(_) => {
                         return Err(format!(
-                            "Unsupported prefix in path {path:?}"
+                            "Unsupported prefix in path {}",
+                            path.display()
                         ));
                     }
                 }
@@ -285,8 +288,86 @@ where
     }
 }
 
+/// Whether `dir` has the layout of a git directory, with a `HEAD` file and
+/// `objects` and `refs` directories in its [common directory](common_dir).
+#[must_use]
+pub fn is_gitdir(dir: &Path) -> bool {
+    let common = common_dir(dir);
+    dir.join("HEAD").is_file()
+        && common.join("objects").is_dir()
+        && common.join("refs").is_dir()
+}
+
+/// The common directory of the git directory `gitdir`, which holds what the
+/// worktrees of a repository share, such as objects, refs and the config.
+///
+/// A worktree added to a repository has a git directory of its own, with
+/// its `HEAD` and index, and a `commondir` file naming the common directory
+/// relative to it. Any other git directory is its own common directory.
+#[must_use]
+pub fn common_dir(gitdir: &Path) -> PathBuf {
+    let Ok(contents) = fs::read_to_string(gitdir.join("commondir")) else {
+        return gitdir.to_path_buf();
+    };
+    match contents.trim() {
+        "" => gitdir.to_path_buf(),
+        dir => {
+            let dir = gitdir.join(dir);
+            dir.canonicalize().unwrap_or(dir)
+        }
+    }
+}
+
+/// Whether `dir` is the git directory of a bare repository, which has no
+/// working tree.
+///
+/// The directory must have the layout of a git directory. Its config decides
+/// with `core.bare`, and if that is not set, like git, any git directory
+/// other than the `.git` directory of a working tree is bare.
+#[must_use]
+pub fn is_bare_repo(dir: &Path) -> bool {
+    let config = dir.join("config");
+    if !is_gitdir(dir) || !config.is_file() {
+        return false;
+    }
+    ConfigParser::from(config.as_path())
+        .get("core")
+        .and_then(|core| core.get_bool("bare"))
+        .unwrap_or_else(|| {
+            dir.canonicalize().is_ok_and(|dir| {
+                dir.file_name().is_some_and(|name| name != ".git")
+            })
+        })
+}
+
+/// The git directory that the `.git` file at `file` points to, if it has a
+/// `gitdir: <path>` line. A relative path is relative to the directory
+/// containing the file.
+#[must_use]
+pub fn read_gitfile(file: &Path) -> Option<PathBuf> {
+    let contents = fs::read_to_string(file).ok()?;
+    let gitdir = contents.strip_prefix("gitdir:")?.trim();
+    if gitdir.is_empty() {
+        return None;
+    }
+    Some(file.parent()?.join(gitdir))
+}
+
+/// The git directory of the repository with its working tree at `worktree`.
+/// This is either a `.git` directory, or the directory a `.git` file points
+/// to, as created by `init --separate-git-dir`.
+#[must_use]
+pub fn resolve_gitdir(worktree: &Path) -> Option<PathBuf> {
+    let dotgit = worktree.join(".git");
+    if dotgit.is_dir() {
+        Some(dotgit)
+    } else {
+        read_gitfile(&dotgit).filter(|gitdir| gitdir.is_dir())
+    }
+}
+
 /// Returns the path to the root of the repository, traversing from `top` to
-/// the root.
+/// the root. The root of a bare repository is its git directory.
 ///
 /// # Errors
 ///
@@ -310,26 +391,98 @@ pub fn repo_find<P>(top: P) -> Result<PathBuf, String>
 where
     P: AsRef<Path>,
 {
-    const GITDIR: &str = ".git";
+    repo_find_within(top, &[])
+}
 
+/// Returns the path to the root of the repository, traversing from `top`
+/// towards the root like [`repo_find`], but without going up into any of the
+/// `ceilings`. `top` itself is always searched.
+///
+/// # Errors
+///
+/// If an I/O error occurs while resolving paths, or if no directory searched
+/// is a repository root.
+pub fn repo_find_within<P>(
+    top: P,
+    ceilings: &[PathBuf],
+) -> Result<PathBuf, String>
+where
+    P: AsRef<Path>,
+{
     let top = top.as_ref();
     let path = Path::new(top);
     let Ok(path) = path.canonicalize() else {
         return Err(format!("Could not resolve path {:?}", path.as_os_str()));
     };
+    // Ceilings that do not exist cannot be reached
+    let ceilings = ceilings
+        .iter()
+        .filter_map(|dir| dir.canonicalize().ok())
+        .collect::<Vec<_>>();
 
     for dir in path.ancestors() {
-        if dir.join(GITDIR).is_dir() {
+        if dir != path && ceilings.iter().any(|ceiling| ceiling == dir) {
+            break;
+        }
+        if resolve_gitdir(dir).is_some() || is_bare_repo(dir) {
             return Ok(dir.to_path_buf());
         }
     }
 
     Err(format!(
-        "neither {top:?} nor any of it's parent directories \
-                 is a repository."
+        "neither {} nor any of it's parent directories \
+                 is a repository.",
+        top.display()
     ))
 }
 
+/// Whether `path` is a file that can be executed. Outside of unix, every
+/// file is considered executable.
+#[cfg(target_family = "unix")]
+#[must_use]
+pub fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    path.metadata().is_ok_and(|metadata| {
+        metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+    })
+}
+
+/// Whether `path` is a file that can be executed. Outside of unix, every
+/// file is considered executable.
+#[cfg(not(target_family = "unix"))]
+#[must_use]
+pub fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// Finds the executable `name` in the directories of `search_path`, which
+/// is separated like `PATH`. The first directory that has it wins.
+///
+/// Outside of unix, `name` with an `.exe` extension is looked for too.
+#[must_use]
+pub fn find_executable(
+    name: &str,
+    search_path: &std::ffi::OsStr,
+) -> Option<PathBuf> {
+    let names: &[String] = if cfg!(target_family = "unix") {
+        &[name.to_owned()]
+    } else {
+        &[name.to_owned(), format!("{name}.exe")]
+    };
+    std::env::split_paths(search_path)
+        // Like the shell, an empty entry is the current directory
+        .map(|dir| {
+            if dir.as_os_str().is_empty() {
+                PathBuf::from(CURRENT_DIR_STR)
+            } else {
+                dir
+            }
+        })
+        .flat_map(|dir| names.iter().map(move |name| dir.join(name)))
+        .find(|path| is_executable(path))
+}
+
 #[cfg(test)]
 mod tests {
     use std::fs;
@@ -338,6 +491,31 @@ mod tests {
     use super::*;
     use crate::utils::test::*;
 
+    #[test]
+    fn test_find_executable() {
+        let tmp_dir = TempDir::<()>::create("test_find_executable");
+        let first = tmp_dir.tmp_dir().join("first");
+        let second = tmp_dir.tmp_dir().join("second");
+        fs::create_dir(&first).unwrap();
+        fs::create_dir(&second).unwrap();
+
+        // Not executable, so skipped on unix
+        fs::write(first.join("tool"), "").unwrap();
+        let tool = second.join("tool");
+        fs::write(&tool, "#!/bin/sh\n").unwrap();
+        #[cfg(target_family = "unix")]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let executable = fs::Permissions::from_mode(0o755);
+            fs::set_permissions(&tool, executable).unwrap();
+        }
+
+        let search_path = std::env::join_paths([&first, &second]).unwrap();
+        #[cfg(target_family = "unix")]
+        assert_eq!(find_executable("tool", &search_path), Some(tool));
+        assert_eq!(find_executable("missing", &search_path), None);
+    }
+
     #[test]
     fn test_repo_path() {
         let base = Path::new(".git");
@@ -490,6 +668,25 @@ mod tests {
         assert_eq!(repo_root, expected);
     }
 
+    #[test]
+    fn test_resolve_gitdir_from_gitfile() {
+        let tmp_dir = TempDir::<()>::create("test_resolve_gitdir_from_gitfile");
+        let worktree = tmp_dir.tmp_dir().join("work");
+        let gitdir = tmp_dir.tmp_dir().join("store.git");
+        fs::create_dir_all(&worktree).unwrap();
+        fs::create_dir_all(&gitdir).unwrap();
+
+        // A relative path is relative to the directory of the .git file
+        fs::write(worktree.join(".git"), "gitdir: ../store.git\n").unwrap();
+        assert_eq!(
+            resolve_gitdir(&worktree),
+            Some(worktree.join("../store.git"))
+        );
+
+        fs::write(worktree.join(".git"), "not a gitfile\n").unwrap();
+        assert_eq!(resolve_gitdir(&worktree), None);
+    }
+
     #[test]
     fn test_repo_find_bad_dir() {
         let manifest = env!("CARGO_MANIFEST_DIR");
@@ -0,0 +1,238 @@
+//! Progress of long operations
+//!
+//! Operations that can take a while, like indexing or writing a packfile,
+//! report what they are doing through a [`Progress`], in phases such as
+//! "Resolving deltas" that count steps towards a total, and optionally the
+//! bytes processed.
+//!
+//! [`NoProgress`] ignores the reports, and [`StderrProgress`] draws them on
+//! a single line the way git does, like
+//! `Writing objects:  40% (2/5), 1.50 KiB | 3.00 MiB/s`. [`default`] picks
+//! between them, showing progress only when stderr is a terminal and
+//! `--no-progress` was not given.
+
+use std::fmt::Write as _;
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Set by `--no-progress`
+static DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// How often a phase without a total is redrawn
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Frames of the spinner shown for phases without a total
+const SPINNER: &[char] = &['|', '/', '-', '\\'];
+
+/// Receives the progress of an operation.
+///
+/// A phase is started, updated any number of times, and finished before the
+/// next one starts.
+pub trait Progress {
+    /// Starts the phase `title`, of `total` steps if it is known.
+    fn start(&mut self, title: &str, total: Option<u64>);
+
+    /// `done` steps of the phase are done, after `bytes` bytes were
+    /// processed, if they are counted.
+    fn update(&mut self, done: u64, bytes: Option<u64>);
+
+    /// Ends the phase.
+    fn finish(&mut self);
+}
+
+/// Ignores all progress.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoProgress;
+
+impl Progress for NoProgress {
+    fn start(&mut self, _title: &str, _total: Option<u64>) {}
+
+    fn update(&mut self, _done: u64, _bytes: Option<u64>) {}
+
+    fn finish(&mut self) {}
+}
+
+/// Draws progress on a line of stderr, or another writer, which is redrawn
+/// in place.
+///
+/// Phases with a total show a percentage, and phases without one a spinner.
+/// When bytes are counted, their amount and throughput are shown too. The
+/// line of a finished phase ends with `, done.`.
+///
+/// # Examples
+///
+/// ```
+/// use mini_git::utils::progress::{Progress, StderrProgress};
+///
+/// let mut progress = StderrProgress::with_writer(Vec::new());
+/// progress.start("Resolving deltas", Some(4));
+/// progress.update(1, None);
+/// progress.update(4, None);
+/// progress.finish();
+///
+/// let drawn = String::from_utf8(progress.into_inner()).unwrap();
+/// assert!(drawn.ends_with("Resolving deltas: 100% (4/4), done.\n"));
+/// ```
+pub struct StderrProgress<W: Write = io::Stderr> {
+    out: W,
+    phase: Option<Phase>,
+}
+
+/// The state of the phase being drawn
+struct Phase {
+    title: String,
+    total: Option<u64>,
+    done: u64,
+    bytes: Option<u64>,
+    started: Instant,
+    // The percentage or time the line was last drawn at
+    last_percent: Option<u64>,
+    last_draw: Option<Instant>,
+    frame: usize,
+}
+
+impl StderrProgress {
+    /// Draws progress on stderr.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_writer(io::stderr())
+    }
+}
+
+impl Default for StderrProgress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<W: Write> StderrProgress<W> {
+    /// Draws progress on `out`.
+    pub fn with_writer(out: W) -> Self {
+        Self { out, phase: None }
+    }
+
+    /// The writer progress is drawn on.
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn draw(&mut self, end: &str) {
+        let Some(phase) = &mut self.phase else {
+            return;
+        };
+        let mut line = format!("{}: ", phase.title);
+        match phase.total {
+            Some(total) => {
+                let percent = percent(phase.done, total);
+                let _ = write!(line, "{percent:3}% ({}/{total})", phase.done);
+            }
+            None if end.is_empty() => {
+                let spinner = SPINNER[phase.frame % SPINNER.len()];
+                phase.frame += 1;
+                let _ = write!(line, "{spinner} {}", phase.done);
+            }
+            None => line.push_str(&phase.done.to_string()),
+        }
+        if let Some(bytes) = phase.bytes {
+            let secs = phase.started.elapsed().as_secs_f64();
+            let _ = write!(line, ", {}", human_bytes(bytes as f64));
+            if secs > 0.0 {
+                let _ =
+                    write!(line, " | {}/s", human_bytes(bytes as f64 / secs));
+            }
+        }
+        // Write errors are not worth failing an operation over
+        let _ = write!(self.out, "\r{line}{end}");
+        let _ = self.out.flush();
+    }
+}
+
+impl<W: Write> Progress for StderrProgress<W> {
+    fn start(&mut self, title: &str, total: Option<u64>) {
+        self.phase = Some(Phase {
+            title: title.to_owned(),
+            total,
+            done: 0,
+            bytes: None,
+            started: Instant::now(),
+            last_percent: None,
+            last_draw: None,
+            frame: 0,
+        });
+    }
+
+    fn update(&mut self, done: u64, bytes: Option<u64>) {
+        let Some(phase) = &mut self.phase else {
+            return;
+        };
+        phase.done = done;
+        phase.bytes = bytes.or(phase.bytes);
+
+        // Only redraw when there is something new to show
+        let redraw = match phase.total {
+            Some(total) => {
+                let percent = percent(done, total);
+                let redraw = phase.last_percent != Some(percent);
+                phase.last_percent = Some(percent);
+                redraw
+            }
+            None => phase
+                .last_draw
+                .is_none_or(|last| last.elapsed() >= REDRAW_INTERVAL),
+        };
+        if redraw {
+            phase.last_draw = Some(Instant::now());
+            self.draw("");
+        }
+    }
+
+    fn finish(&mut self) {
+        self.draw(", done.\n");
+        self.phase = None;
+    }
+}
+
+fn percent(done: u64, total: u64) -> u64 {
+    (done.min(total) * 100).checked_div(total).unwrap_or(100)
+}
+
+/// Formats an amount of bytes like git, as in `1.50 KiB`.
+fn human_bytes(bytes: f64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB"];
+    if bytes < 1024.0 {
+        return format!("{bytes:.0} bytes");
+    }
+    let mut amount = bytes / 1024.0;
+    let mut unit = 0;
+    while amount >= 1024.0 && unit + 1 < UNITS.len() {
+        amount /= 1024.0;
+        unit += 1;
+    }
+    format!("{amount:.2} {}", UNITS[unit])
+}
+
+/// Stops [`default`] from showing progress, as `--no-progress` does.
+pub fn disable() {
+    DISABLED.store(true, Ordering::Relaxed);
+}
+
+/// Whether to show progress, given whether it was disabled and whether
+/// stderr is a terminal.
+#[must_use]
+pub fn resolve(disabled: bool, is_terminal: bool) -> bool {
+    !disabled && is_terminal
+}
+
+/// The progress commands report to: drawn on stderr if it is a terminal,
+/// unless `--no-progress` was given.
+#[must_use]
+pub fn default() -> Box<dyn Progress> {
+    let disabled = DISABLED.load(Ordering::Relaxed);
+    if resolve(disabled, io::stderr().is_terminal()) {
+        Box::new(StderrProgress::new())
+    } else {
+        Box::new(NoProgress)
+    }
+}
@@ -0,0 +1,557 @@
+//! A small regular expression engine
+//!
+//! This module implements the subset of POSIX extended regular expressions
+//! needed for searching through file contents, such as `log -G`.
+//!
+//! Patterns are compiled to a program for a Thompson NFA, which is run over
+//! the text once, following every possible match at the same time. Matching
+//! takes time proportional to the length of the text times the length of the
+//! program, without the backtracking or deep recursion that long lines would
+//! otherwise cause.
+//!
+//! Supported syntax:
+//! - Literals, and `.` to match any character
+//! - Anchors `^` and `$`
+//! - Character classes `[abc]`, `[a-z]`, and negated classes `[^abc]`
+//! - Escapes `\d`, `\w`, `\s`, their negations `\D`, `\W`, `\S`, and escaped
+//!   metacharacters such as `\.`
+//! - Groups `(...)` and alternation `|`
+//! - Quantifiers `*`, `+`, `?`, `{n}`, `{n,}` and `{n,m}`
+//!
+//! # Examples
+//!
+//! ```
+//! use mini_git::utils::regex::Regex;
+//!
+//! let re = Regex::new(r"fn \w+\(").expect("Valid regex");
+//! assert!(re.is_match("pub fn main() {"));
+//! assert!(!re.is_match("let x = 5;"));
+//! ```
+
+use std::iter::Peekable;
+use std::str::Chars;
+
+type Alternatives = Vec<Vec<Node>>;
+
+#[derive(Debug, Clone)]
+enum Node {
+    Char(char),
+    Any,
+    Class {
+        ranges: Vec<(char, char)>,
+        negated: bool,
+    },
+    Start,
+    End,
+    Group(Alternatives),
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+    },
+}
+
+/// The largest count allowed in a `{n,m}` quantifier
+const MAX_REPEAT: usize = 1000;
+
+/// The most instructions a compiled regex can have, as counted quantifiers
+/// copy what they repeat
+const MAX_PROGRAM: usize = 100_000;
+
+/// An instruction of a compiled regex.
+#[derive(Debug, Clone)]
+enum Inst {
+    /// Consumes a character matching the node, which is a
+    /// [`Node::Char`], [`Node::Any`] or [`Node::Class`]
+    Consume(Node),
+    Start,
+    End,
+    /// Continues at both instructions
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+/// A compiled regular expression.
+#[derive(Debug)]
+pub struct Regex {
+    program: Vec<Inst>,
+}
+
+impl Regex {
+    /// Compiles a regular expression.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`String`] describing the problem if the pattern is not a
+    /// valid regular expression, for example if it has unbalanced
+    /// parentheses or a quantifier with nothing to repeat.
+    pub fn new(pattern: &str) -> Result<Self, String> {
+        let mut chars = pattern.chars().peekable();
+        let alternatives = parse_alternatives(&mut chars)?;
+        if let Some(c) = chars.next() {
+            return Err(format!("Unmatched '{c}' in regex {pattern:?}"));
+        }
+
+        let mut program = Vec::new();
+        compile_alternatives(&alternatives, &mut program)?;
+        program.push(Inst::Match);
+        Ok(Self { program })
+    }
+
+    /// Returns `true` if the regex matches anywhere in `text`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mini_git::utils::regex::Regex;
+    ///
+    /// let re = Regex::new("^(foo|bar)+$").unwrap();
+    /// assert!(re.is_match("foobarfoo"));
+    /// assert!(!re.is_match("foobaz"));
+    /// ```
+    #[must_use]
+    pub fn is_match(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        let mut current = Threads::new(self.program.len());
+        let mut next = Threads::new(self.program.len());
+
+        for pos in 0..=text.len() {
+            // A match can start anywhere
+            current.add(&self.program, 0, pos, &text);
+            for &pc in &current.list {
+                match &self.program[pc] {
+                    Inst::Match => return true,
+                    Inst::Consume(node)
+                        if text
+                            .get(pos)
+                            .is_some_and(|&c| consumes(node, c)) =>
+                    {
+                        next.add(&self.program, pc + 1, pos + 1, &text);
+                    }
+                    _ => {}
+                }
+            }
+            std::mem::swap(&mut current, &mut next);
+            next.clear();
+        }
+        false
+    }
+}
+
+/// The instructions the NFA is at, for one position of the text.
+struct Threads {
+    list: Vec<usize>,
+    /// The generation each instruction was last visited in
+    seen: Vec<usize>,
+    generation: usize,
+    stack: Vec<usize>,
+}
+
+impl Threads {
+    fn new(len: usize) -> Self {
+        Self {
+            list: Vec::new(),
+            seen: vec![0; len],
+            generation: 1,
+            stack: Vec::new(),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.list.clear();
+        self.generation += 1;
+    }
+
+    /// Adds the thread at `pc`, following the instructions that do not
+    /// consume a character, at position `pos` of `text`.
+    fn add(&mut self, program: &[Inst], pc: usize, pos: usize, text: &[char]) {
+        self.stack.push(pc);
+        while let Some(pc) = self.stack.pop() {
+            if self.seen[pc] == self.generation {
+                continue;
+            }
+            self.seen[pc] = self.generation;
+            match &program[pc] {
+                Inst::Jump(to) => self.stack.push(*to),
+                Inst::Split(first, second) => {
+                    self.stack.push(*second);
+                    self.stack.push(*first);
+                }
+                Inst::Start if pos == 0 => self.stack.push(pc + 1),
+                Inst::End if pos == text.len() => self.stack.push(pc + 1),
+                Inst::Start | Inst::End => {}
+                // These wait on the next character
+                Inst::Consume(_) | Inst::Match => self.list.push(pc),
+            }
+        }
+    }
+}
+
+fn parse_alternatives(
+    chars: &mut Peekable<Chars>,
+) -> Result<Alternatives, String> {
+    let mut alternatives = vec![parse_sequence(chars)?];
+    while chars.next_if_eq(&'|').is_some() {
+        alternatives.push(parse_sequence(chars)?);
+    }
+    Ok(alternatives)
+}
+
+fn parse_sequence(chars: &mut Peekable<Chars>) -> Result<Vec<Node>, String> {
+    let mut sequence = Vec::new();
+
+    while let Some(&c) = chars.peek() {
+        if c == '|' || c == ')' {
+            break;
+        }
+        chars.next();
+
+        let (min, max) = match c {
+            '*' => (0, None),
+            '+' => (1, None),
+            '?' => (0, Some(1)),
+            '{' => parse_counts(chars)?,
+            _ => {
+                sequence.push(parse_atom(c, chars)?);
+                continue;
+            }
+        };
+
+        let Some(node) = sequence.pop() else {
+            return Err(format!("Nothing to repeat before '{c}'"));
+        };
+        if matches!(node, Node::Start | Node::End) {
+            return Err(format!("Cannot repeat an anchor with '{c}'"));
+        }
+        sequence.push(Node::Repeat {
+            node: Box::new(node),
+            min,
+            max,
+        });
+    }
+
+    Ok(sequence)
+}
+
+fn parse_atom(c: char, chars: &mut Peekable<Chars>) -> Result<Node, String> {
+    Ok(match c {
+        '.' => Node::Any,
+        '^' => Node::Start,
+        '$' => Node::End,
+        '(' => {
+            let alternatives = parse_alternatives(chars)?;
+            if chars.next() != Some(')') {
+                return Err("Missing ')' in regex".to_owned());
+            }
+            Node::Group(alternatives)
+        }
+        '[' => parse_class(chars)?,
+        '\\' => {
+            let Some(escaped) = chars.next() else {
+                return Err("Trailing '\\' in regex".to_owned());
+            };
+            match escape_class(escaped) {
+                Some((ranges, negated)) => Node::Class { ranges, negated },
+                None => Node::Char(escaped),
+            }
+        }
+        c => Node::Char(c),
+    })
+}
+
+// Parses the body of a `{n}`, `{n,}` or `{n,m}` quantifier
+fn parse_counts(
+    chars: &mut Peekable<Chars>,
+) -> Result<(usize, Option<usize>), String> {
+    let mut body = String::new();
+    loop {
+        match chars.next() {
+            Some('}') => break,
+            Some(c) => body.push(c),
+            None => return Err("Missing '}' in regex".to_owned()),
+        }
+    }
+
+    let parse = |n: &str| {
+        n.trim()
+            .parse::<usize>()
+            .map_err(|_| format!("Invalid repetition count {{{body}}}"))
+    };
+
+    let (min, max) = match body.split_once(',') {
+        None => {
+            let n = parse(&body)?;
+            (n, Some(n))
+        }
+        Some((min, max)) if max.trim().is_empty() => (parse(min)?, None),
+        Some((min, max)) => (parse(min)?, Some(parse(max)?)),
+    };
+
+    if max.is_some_and(|max| max < min) || max.unwrap_or(min) > MAX_REPEAT {
+        return Err(format!("Invalid repetition count {{{body}}}"));
+    }
+    Ok((min, max))
+}
+
+fn parse_class(chars: &mut Peekable<Chars>) -> Result<Node, String> {
+    let negated = chars.next_if_eq(&'^').is_some();
+    let mut ranges = Vec::new();
+    let mut first = true;
+
+    loop {
+        let Some(c) = chars.next() else {
+            return Err("Missing ']' in regex".to_owned());
+        };
+
+        // A ']' right after the opening bracket is a literal
+        if c == ']' && !first {
+            break;
+        }
+        first = false;
+
+        let start = if c == '\\' {
+            let Some(escaped) = chars.next() else {
+                return Err("Missing ']' in regex".to_owned());
+            };
+            match escape_class(escaped) {
+                Some((class, false)) => {
+                    ranges.extend(class);
+                    continue;
+                }
+                Some((_, true)) => {
+                    return Err(format!(
+                        "Negated class '\\{escaped}' is not supported in \
+                        a bracket expression"
+                    ));
+                }
+                None => escaped,
+            }
+        } else {
+            c
+        };
+
+        // A '-' right before the closing bracket is a literal
+        if chars.peek() == Some(&'-') {
+            let mut lookahead = chars.clone();
+            lookahead.next();
+            if let Some(end) = lookahead.next().filter(|&end| end != ']') {
+                if end < start {
+                    return Err(format!("Invalid range {start}-{end}"));
+                }
+                *chars = lookahead;
+                ranges.push((start, end));
+                continue;
+            }
+        }
+        ranges.push((start, start));
+    }
+
+    Ok(Node::Class { ranges, negated })
+}
+
+// Ranges for the shorthand classes `\d`, `\w` and `\s`, and their negations
+fn escape_class(c: char) -> Option<(Vec<(char, char)>, bool)> {
+    let ranges = match c.to_ascii_lowercase() {
+        'd' => vec![('0', '9')],
+        'w' => vec![('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')],
+        's' => vec![(' ', ' '), ('\t', '\r')],
+        _ => return None,
+    };
+    Some((ranges, c.is_ascii_uppercase()))
+}
+
+fn compile_alternatives(
+    alternatives: &Alternatives,
+    program: &mut Vec<Inst>,
+) -> Result<(), String> {
+    // Each alternative but the last is tried through a split, and jumps to
+    // the end once matched
+    let mut jumps = Vec::new();
+    for (i, sequence) in alternatives.iter().enumerate() {
+        if i + 1 < alternatives.len() {
+            let split = program.len();
+            program.push(Inst::Split(split + 1, 0));
+            compile_sequence(sequence, program)?;
+            jumps.push(program.len());
+            program.push(Inst::Jump(0));
+            program[split] = Inst::Split(split + 1, program.len());
+        } else {
+            compile_sequence(sequence, program)?;
+        }
+    }
+    let end = program.len();
+    for jump in jumps {
+        program[jump] = Inst::Jump(end);
+    }
+    Ok(())
+}
+
+fn compile_sequence(
+    sequence: &[Node],
+    program: &mut Vec<Inst>,
+) -> Result<(), String> {
+    for node in sequence {
+        compile_node(node, program)?;
+        if program.len() > MAX_PROGRAM {
+            return Err("Regex is too large".to_owned());
+        }
+    }
+    Ok(())
+}
+
+fn compile_node(node: &Node, program: &mut Vec<Inst>) -> Result<(), String> {
+    match node {
+        Node::Start => program.push(Inst::Start),
+        Node::End => program.push(Inst::End),
+        Node::Group(alternatives) => {
+            compile_alternatives(alternatives, program)?;
+        }
+        Node::Repeat { node, min, max } => {
+            for _ in 0..*min {
+                compile_sequence(std::slice::from_ref(node), program)?;
+            }
+            match max {
+                // Loop back to a split between another iteration and the
+                // rest of the pattern
+                None => {
+                    let split = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    compile_sequence(std::slice::from_ref(node), program)?;
+                    program.push(Inst::Jump(split));
+                    program[split] = Inst::Split(split + 1, program.len());
+                }
+                // Each optional iteration can skip to the end
+                Some(max) => {
+                    let mut splits = Vec::new();
+                    for _ in *min..*max {
+                        splits.push(program.len());
+                        program.push(Inst::Split(program.len() + 1, 0));
+                        compile_sequence(std::slice::from_ref(node), program)?;
+                    }
+                    let end = program.len();
+                    for split in splits {
+                        program[split] = Inst::Split(split + 1, end);
+                    }
+                }
+            }
+        }
+        node => program.push(Inst::Consume(node.clone())),
+    }
+    Ok(())
+}
+
+fn consumes(node: &Node, c: char) -> bool {
+    match node {
+        Node::Char(expected) => c == *expected,
+        Node::Any => c != '\n',
+        Node::Class { ranges, negated } => {
+            ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != *negated
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, text: &str) -> bool {
+        Regex::new(pattern).expect("Valid regex").is_match(text)
+    }
+
+    #[test]
+    fn test_literals_and_any() {
+        assert!(matches("abc", "xxabcxx"));
+        assert!(!matches("abd", "xxabcxx"));
+        assert!(matches("a.c", "abc"));
+        assert!(!matches("a.c", "ac"));
+        assert!(matches("", "anything"));
+    }
+
+    #[test]
+    fn test_anchors() {
+        assert!(matches("^abc", "abcdef"));
+        assert!(!matches("^abc", "xabc"));
+        assert!(matches("def$", "abcdef"));
+        assert!(!matches("def$", "defx"));
+        assert!(matches("^$", ""));
+    }
+
+    #[test]
+    fn test_quantifiers() {
+        assert!(matches("^ab*c$", "ac"));
+        assert!(matches("^ab*c$", "abbbc"));
+        assert!(!matches("^ab+c$", "ac"));
+        assert!(matches("^ab?c$", "abc"));
+        assert!(!matches("^ab?c$", "abbc"));
+        assert!(matches("^a{2}$", "aa"));
+        assert!(!matches("^a{2}$", "aaa"));
+        assert!(matches("^a{2,}$", "aaaa"));
+        assert!(matches("^a{1,2}b$", "aab"));
+        assert!(!matches("^a{1,2}b$", "aaab"));
+    }
+
+    #[test]
+    fn test_backtracking() {
+        assert!(matches("^.*b.*c$", "aabaac"));
+        assert!(matches("^(a|ab)c$", "abc"));
+        assert!(matches("^(a*)*b$", "aaab"));
+        assert!(!matches("^(a*)*b$", "aaaa"));
+    }
+
+    #[test]
+    fn test_classes() {
+        assert!(matches("^[a-c]+$", "abcabc"));
+        assert!(!matches("^[a-c]+$", "abcd"));
+        assert!(matches("^[^0-9]+$", "abc"));
+        assert!(!matches("^[^0-9]+$", "ab1"));
+        assert!(matches("^[]a]+$", "]a]"));
+        assert!(matches("^[a-]+$", "a-a"));
+        assert!(matches(r"^[\d.]+$", "1.25"));
+    }
+
+    #[test]
+    fn test_escapes() {
+        assert!(matches(r"^\d+$", "12345"));
+        assert!(!matches(r"^\d+$", "12a45"));
+        assert!(matches(r"^\w+\s\w+$", "hello world"));
+        assert!(matches(r"^\S+$", "no-spaces"));
+        assert!(matches(r"a\.b", "a.b"));
+        assert!(!matches(r"a\.b", "axb"));
+        assert!(matches(r"\(\)", "fn()"));
+    }
+
+    #[test]
+    fn test_alternation_and_groups() {
+        assert!(matches("cat|dog", "hotdog"));
+        assert!(matches("^(foo|bar)baz$", "barbaz"));
+        assert!(!matches("^(foo|bar)baz$", "bazbaz"));
+        assert!(matches("^(ab)+$", "ababab"));
+        assert!(!matches("^(ab)+$", "ababa"));
+    }
+
+    #[test]
+    fn test_invalid_patterns() {
+        assert!(Regex::new("(abc").is_err());
+        assert!(Regex::new("abc)").is_err());
+        assert!(Regex::new("[abc").is_err());
+        assert!(Regex::new("*abc").is_err());
+        assert!(Regex::new("a{3,1}").is_err());
+        assert!(Regex::new("a{x}").is_err());
+        assert!(Regex::new("[z-a]").is_err());
+        assert!(Regex::new("abc\\").is_err());
+        assert!(Regex::new("a{1001}").is_err());
+        assert!(Regex::new("((a{1000}){1000}){1000}").is_err());
+    }
+
+    #[test]
+    fn test_long_lines() {
+        // Each character used to add frames to the stack of the matcher
+        let line = "a".repeat(300_000);
+        assert!(!matches("a*b", &line));
+        assert!(matches("a*b", &format!("{line}b")));
+        assert!(matches("^(a|b)*$", &line));
+        assert!(!matches("^(a*)*b$", &line));
+    }
+}
@@ -20,15 +20,32 @@
 //! One-shot hash calculation:
 //!
 //! ```
-//! use mini_git::utils::sha1::hash;
+//! use mini_git::utils::sha1::sha1;
 //!
-//! let result = hash(b"hello world");
+//! let result = sha1(b"hello world");
 //! assert_eq!(result, [42, 174, 108, 53, 201, 79, 207, 180, 21, 219, 233, 95, 64, 139, 156, 233, 30, 232, 70, 237]);
 //! ```
+//!
+//! Collision detection:
+//!
+//! SHA-1 is broken, colliding messages can be crafted. With collision
+//! detection, each block is checked against the disturbance vectors of the
+//! known attacks, like the collision detection git ships, so that crafted
+//! messages can be rejected.
+//!
+//! ```
+//! use mini_git::utils::sha1::SHA1;
+//!
+//! let mut hasher = SHA1::new().with_collision_detection();
+//! let _ = hasher.update(b"hello world");
+//! assert!(!hasher.found_collision());
+//! ```
 
 #![forbid(unsafe_code)]
 #![allow(clippy::missing_panics_doc)]
 
+use std::ops::Range;
+
 use crate::utils::hex;
 
 /// Initial state constants for the SHA-1 algorithm.
@@ -40,11 +57,24 @@ const INITIAL_STATE: [u32; 5] = [
     0xC3D2_E1F0,
 ];
 
+/// Size in bytes of the blocks SHA-1 processes.
+const BLOCK_LEN: usize = 64;
+
 /// SHA-1 hasher structure.
+///
+/// Whole blocks of the data are processed as they are given, only the bytes
+/// of an incomplete block are buffered until more data is given.
+///
+/// With [`SHA1::with_collision_detection`], every block is also checked for
+/// being part of a known collision attack, see [`SHA1::found_collision`].
+#[derive(Clone)]
 pub struct SHA1 {
     state: [u32; 5],
-    buffer: Vec<u8>,
+    buffer: [u8; BLOCK_LEN],
+    buffer_len: usize,
     total_len: u64,
+    detect_collisions: bool,
+    collision: bool,
 }
 
 impl Default for SHA1 {
@@ -74,11 +104,48 @@ impl SHA1 {
     pub fn new() -> Self {
         SHA1 {
             state: INITIAL_STATE,
-            buffer: Vec::new(),
+            buffer: [0; BLOCK_LEN],
+            buffer_len: 0,
             total_len: 0,
+            detect_collisions: false,
+            collision: false,
         }
     }
 
+    /// Checks every block hashed for being part of a collision attack, like
+    /// the collision detection of git.
+    ///
+    /// A block is only hashed again for the disturbance vectors whose
+    /// unavoidable bit conditions it meets, which few blocks do, so hashing
+    /// is some two to three times slower with it.
+    ///
+    /// The hash of a message with a block of an attack is not its SHA-1 hash,
+    /// like the safe hash of git, so the messages of a collision differ.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mini_git::utils::sha1::SHA1;
+    /// let mut hasher = SHA1::new().with_collision_detection();
+    /// let _ = hasher.update(b"hello world");
+    /// assert_eq!(hasher.hex_digest(), "2aae6c35c94fcfb415dbe95f408b9ce91ee846ed");
+    /// assert!(!hasher.found_collision());
+    /// ```
+    #[must_use]
+    pub fn with_collision_detection(mut self) -> Self {
+        self.detect_collisions = true;
+        self
+    }
+
+    /// Returns whether a block hashed so far, including the padding of the
+    /// last finalized hash, is part of a collision attack.
+    ///
+    /// Always false unless [`SHA1::with_collision_detection`] is set.
+    #[must_use]
+    pub fn found_collision(&self) -> bool {
+        self.collision
+    }
+
     /// Updates the hasher with the provided data.
     ///
     /// This method can be called multiple times with different chunks of data.
@@ -92,30 +159,46 @@ impl SHA1 {
     /// hasher.update(b" world");
     /// ```
     #[must_use]
-    pub fn update(&mut self, data: &[u8]) -> &mut Self {
-        self.total_len += data.len() as u64;
-        self.buffer.extend_from_slice(data);
-
-        let (new_buffer, new_state) = self.buffer.chunks(64).fold(
-            (Vec::new(), self.state),
-            |(mut buffer, state), chunk| {
-                if chunk.len() == 64 {
-                    (buffer, process_chunk(chunk, state))
-                } else {
-                    buffer.extend_from_slice(chunk);
-                    (buffer, state)
-                }
-            },
-        );
+    pub fn update(&mut self, mut data: &[u8]) -> &mut Self {
+        self.total_len = self.total_len.wrapping_add(data.len() as u64);
+
+        // Complete the buffered block first
+        if self.buffer_len > 0 {
+            let len = data.len().min(BLOCK_LEN - self.buffer_len);
+            self.buffer[self.buffer_len..self.buffer_len + len]
+                .copy_from_slice(&data[..len]);
+            self.buffer_len += len;
+            data = &data[len..];
+
+            if self.buffer_len < BLOCK_LEN {
+                return self;
+            }
+            self.collision |=
+                compress(&mut self.state, &self.buffer, self.detect_collisions);
+            self.buffer_len = 0;
+        }
+
+        let mut blocks = data.chunks_exact(BLOCK_LEN);
+        for block in &mut blocks {
+            self.collision |= compress(
+                &mut self.state,
+                block.try_into().unwrap(),
+                self.detect_collisions,
+            );
+        }
 
-        self.state = new_state;
-        self.buffer = new_buffer;
+        let rest = blocks.remainder();
+        self.buffer[..rest.len()].copy_from_slice(rest);
+        self.buffer_len = rest.len();
 
         self
     }
 
     /// Finalizes the hasher and returns the SHA-1 hash value.
     ///
+    /// The hasher is left as it was, so more data may be given to it
+    /// afterwards.
+    ///
     /// # Examples
     ///
     /// ```
@@ -126,17 +209,27 @@ impl SHA1 {
     /// ```
     #[allow(missing_docs)]
     pub fn finalize(&mut self) -> [u8; 20] {
-        let mod_len = (self.total_len % 64) as usize;
-        let padding = create_padding(mod_len, self.total_len);
+        let mut state = self.state;
+        let mut block = [0; BLOCK_LEN];
+        block[..self.buffer_len]
+            .copy_from_slice(&self.buffer[..self.buffer_len]);
+        block[self.buffer_len] = 0x80;
 
-        let final_state = self.update(&padding).state;
+        // The length does not fit after the padding byte, pad a whole block
+        if self.buffer_len >= BLOCK_LEN - 8 {
+            self.collision |=
+                compress(&mut state, &block, self.detect_collisions);
+            block = [0; BLOCK_LEN];
+        }
+        block[BLOCK_LEN - 8..]
+            .copy_from_slice(&self.total_len.wrapping_mul(8).to_be_bytes());
+        self.collision |= compress(&mut state, &block, self.detect_collisions);
 
-        final_state
-            .iter()
-            .flat_map(|&word| word.to_be_bytes())
-            .collect::<Vec<_>>()
-            .try_into()
-            .unwrap()
+        let mut digest = [0; 20];
+        for (bytes, word) in digest.chunks_exact_mut(4).zip(state) {
+            bytes.copy_from_slice(&word.to_be_bytes());
+        }
+        digest
     }
 
     /// Returns the SHA-1 hash value as a hexadecimal string.
@@ -155,84 +248,631 @@ impl SHA1 {
     }
 }
 
-/// Creates padding for the message to ensure it is a multiple of 512 bits.
-fn create_padding(mod_len: usize, total_len: u64) -> Vec<u8> {
-    let padding_len = if mod_len < 56 {
-        56 - mod_len
+/// Processes a 512-bit block and updates the state, checking it for being
+/// part of a collision attack if `detect` is set.
+///
+/// Returns whether the block is part of a collision attack.
+fn compress(
+    state: &mut [u32; 5],
+    block: &[u8; BLOCK_LEN],
+    detect: bool,
+) -> bool {
+    if detect {
+        check_block(state, block)
+    } else {
+        process_block(state, block);
+        false
+    }
+}
+
+/// The steps at which the disturbance vectors are checked.
+const CHECKPOINTS: [usize; 2] = [58, 65];
+
+/// A disturbance vector, the pattern of local collisions a collision attack
+/// on SHA-1 is built from.
+struct DisturbanceVector {
+    /// A step at which both blocks of a collision have the same state.
+    checkpoint: usize,
+    /// The differences between the expanded messages of both blocks.
+    differences: [u32; 80],
+}
+
+/// The disturbance vectors of the known attacks on SHA-1, those checked by
+/// the collision detection of git.
+///
+/// The order is that of the bits of [`possible_vectors`].
+const DISTURBANCE_VECTORS: [DisturbanceVector; 32] = [
+    disturbance_vector(1, 43, 0),
+    disturbance_vector(1, 44, 0),
+    disturbance_vector(1, 45, 0),
+    disturbance_vector(1, 46, 0),
+    disturbance_vector(1, 46, 2),
+    disturbance_vector(1, 47, 0),
+    disturbance_vector(1, 47, 2),
+    disturbance_vector(1, 48, 0),
+    disturbance_vector(1, 48, 2),
+    disturbance_vector(1, 49, 0),
+    disturbance_vector(1, 49, 2),
+    disturbance_vector(1, 50, 0),
+    disturbance_vector(1, 50, 2),
+    disturbance_vector(1, 51, 0),
+    disturbance_vector(1, 51, 2),
+    disturbance_vector(1, 52, 0),
+    disturbance_vector(2, 45, 0),
+    disturbance_vector(2, 46, 0),
+    disturbance_vector(2, 46, 2),
+    disturbance_vector(2, 47, 0),
+    disturbance_vector(2, 48, 0),
+    disturbance_vector(2, 49, 0),
+    disturbance_vector(2, 49, 2),
+    disturbance_vector(2, 50, 0),
+    disturbance_vector(2, 50, 2),
+    disturbance_vector(2, 51, 0),
+    disturbance_vector(2, 51, 2),
+    disturbance_vector(2, 52, 0),
+    disturbance_vector(2, 53, 0),
+    disturbance_vector(2, 54, 0),
+    disturbance_vector(2, 55, 0),
+    disturbance_vector(2, 56, 0),
+];
+
+/// Builds the disturbance vector `I(k, bit)` if `kind` is 1, or `II(k, bit)`
+/// otherwise, in the classification of Manuel.
+///
+/// The vector follows the message expansion of SHA-1, and is given by its
+/// 16 words from step `k`: only step `k + 15` is disturbed, in `bit`, and for
+/// type II so are steps `k + 1` and `k + 3`, in the bit before.
+const fn disturbance_vector(kind: u8, k: usize, bit: u32) -> DisturbanceVector {
+    // The vector from step -5, for the corrections of the first steps
+    let mut vector = [0u32; 85];
+    vector[k + 20] = 1 << bit;
+    if kind == 2 {
+        vector[k + 6] = (1u32 << bit).rotate_left(31);
+        vector[k + 8] = (1u32 << bit).rotate_left(31);
+    }
+
+    let mut i = k + 21;
+    while i < 85 {
+        vector[i] =
+            (vector[i - 3] ^ vector[i - 8] ^ vector[i - 14] ^ vector[i - 16])
+                .rotate_left(1);
+        i += 1;
+    }
+    let mut i = k + 20;
+    while i >= 16 {
+        vector[i - 16] = vector[i].rotate_right(1)
+            ^ vector[i - 3]
+            ^ vector[i - 8]
+            ^ vector[i - 14];
+        i -= 1;
+    }
+
+    // Each disturbance is corrected over the next five steps
+    let mut differences = [0u32; 80];
+    let mut t = 0;
+    while t < 80 {
+        differences[t] = vector[t + 5]
+            ^ vector[t + 4].rotate_left(5)
+            ^ vector[t + 3]
+            ^ vector[t + 2].rotate_left(30)
+            ^ vector[t + 1].rotate_left(30)
+            ^ vector[t].rotate_left(30);
+        t += 1;
+    }
+
+    // No disturbance is left unfinished in the five steps before
+    let checkpoint = if k < 50 {
+        CHECKPOINTS[0]
     } else {
-        120 - mod_len
+        CHECKPOINTS[1]
     };
-    let mut padding = vec![0u8; padding_len + 8];
-    padding[0] = 0x80;
-    padding[padding_len..].copy_from_slice(&(total_len * 8).to_be_bytes());
-    padding
+    DisturbanceVector {
+        checkpoint,
+        differences,
+    }
 }
 
-/// Processes a 512-bit chunk and updates the state.
-#[allow(clippy::many_single_char_names)]
-fn process_chunk(chunk: &[u8], initial_state: [u32; 5]) -> [u32; 5] {
-    let words = expand_chunk(chunk);
-    let [a, b, c, d, e] = initial_state;
-
-    let final_state = (0..80).fold((a, b, c, d, e), |(a, b, c, d, e), i| {
-        let (f, k) = match i {
-            0..=19 => ((b & c) | ((!b) & d), 0x5A82_7999),
-            20..=39 => (b ^ c ^ d, 0x6ED9_EBA1),
-            40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1B_BCDC),
-            60..=79 => (b ^ c ^ d, 0xCA62_C1D6),
-            _ => unreachable!(),
-        };
+/// A condition on two bits of the expanded message of a block, met by every
+/// block of a collision attack following some disturbance vectors.
+struct BitCondition {
+    /// The word and bit of the first bit.
+    first: (usize, u32),
+    /// The word and bit of the second bit.
+    second: (usize, u32),
+    /// Whether both bits are the same, or differ.
+    equal: bool,
+    /// The disturbance vectors for which the bits must be so, as the bits of
+    /// their indices in [`DISTURBANCE_VECTORS`].
+    vectors: u32,
+}
+
+/// Builds the condition that bit `first_bit` of word `first` and bit
+/// `second_bit` of word `second` are `equal`, for the `vectors`.
+const fn condition(
+    first: usize,
+    first_bit: u32,
+    second: usize,
+    second_bit: u32,
+    equal: bool,
+    vectors: u32,
+) -> BitCondition {
+    BitCondition {
+        first: (first, first_bit),
+        second: (second, second_bit),
+        equal,
+        vectors,
+    }
+}
+
+/// The unavoidable bit conditions of the disturbance vectors, those of the
+/// `ubc_check` of the collision detection of git, by Marc Stevens and Dan
+/// Shumow (MIT License).
+///
+/// A block for which a condition does not hold cannot be part of an attack
+/// following its vectors, so these need not be checked for it.
+const UNAVOIDABLE_CONDITIONS: [BitCondition; 156] = [
+    condition(44, 29, 45, 29, true, 0x0283_A080),
+    condition(49, 29, 50, 29, true, 0xC281_0008),
+    condition(48, 29, 49, 29, true, 0x60A0_8004),
+    condition(47, 4, 50, 29, true, 0x8201_2220),
+    condition(47, 29, 48, 29, true, 0x3030_2002),
+    condition(46, 4, 49, 29, true, 0x4080_8888),
+    condition(46, 29, 47, 29, true, 0x1818_0801),
+    condition(45, 4, 48, 29, true, 0x2020_2224),
+    condition(45, 29, 46, 29, true, 0x0A0A_8200),
+    condition(44, 4, 47, 29, true, 0x1010_088A),
+    condition(43, 4, 46, 29, true, 0x0808_0225),
+    condition(43, 29, 44, 29, true, 0x00A1_2820),
+    condition(42, 4, 45, 29, true, 0x0202_808A),
+    condition(41, 4, 44, 29, true, 0x0081_2025),
+    condition(40, 29, 41, 29, true, 0x800A_00A2),
+    condition(54, 29, 55, 29, true, 0xC088_2000),
+    condition(53, 29, 54, 29, true, 0x6022_0800),
+    condition(52, 29, 53, 29, true, 0x3011_0200),
+    condition(50, 4, 53, 29, true, 0x2012_8800),
+    condition(50, 29, 51, 29, true, 0x8A02_0020),
+    condition(49, 4, 52, 29, true, 0x1009_2200),
+    condition(48, 4, 51, 29, true, 0x0802_8880),
+    condition(42, 29, 43, 29, true, 0x0030_0A08),
+    condition(41, 29, 42, 29, true, 0x0018_0284),
+    condition(40, 4, 43, 29, true, 0x8020_080A),
+    condition(39, 4, 42, 29, true, 0x4010_0205),
+    condition(38, 4, 41, 29, true, 0xA008_0082),
+    condition(37, 4, 40, 29, true, 0x5002_0021),
+    condition(55, 29, 56, 29, true, 0x8210_8000),
+    condition(52, 4, 55, 29, true, 0x8090_8000),
+    condition(51, 4, 54, 29, true, 0x4028_2000),
+    condition(51, 29, 52, 29, true, 0x1808_0080),
+    condition(36, 4, 40, 29, true, 0x0011_0208),
+    condition(53, 29, 56, 29, false, 0x0030_8000),
+    condition(51, 29, 54, 29, false, 0x000A_0800),
+    condition(50, 29, 52, 29, false, 0x0001_2200),
+    condition(49, 29, 51, 29, false, 0x0000_8880),
+    condition(48, 29, 50, 29, false, 0x0000_2220),
+    condition(47, 29, 49, 29, false, 0x0000_0888),
+    condition(46, 29, 48, 29, false, 0x0000_0224),
+    condition(45, 6, 47, 6, true, 0x0000_4440),
+    condition(45, 29, 47, 29, false, 0x0000_008A),
+    condition(44, 6, 46, 6, true, 0x0000_1110),
+    condition(44, 29, 46, 29, false, 0x0000_0025),
+    condition(41, 1, 42, 6, false, 0x0404_0100),
+    condition(40, 1, 41, 6, false, 0x0100_4040),
+    condition(40, 4, 42, 4, false, 0x8000_000A),
+    condition(39, 1, 40, 6, false, 0x0040_1010),
+    condition(39, 4, 41, 4, false, 0x4000_0005),
+    condition(38, 4, 40, 4, false, 0xA000_0002),
+    condition(37, 4, 39, 4, false, 0x5000_0001),
+    condition(36, 1, 37, 6, false, 0x0004_1040),
+    condition(35, 4, 39, 29, true, 0x0008_0084),
+    condition(63, 0, 64, 5, false, 0x0010_0080),
+    condition(63, 1, 64, 6, false, 0x0001_0004),
+    condition(62, 0, 63, 5, false, 0x0008_0020),
+    condition(61, 0, 62, 5, false, 0x0002_0008),
+    condition(61, 2, 62, 7, false, 0x0004_0010),
+    condition(60, 0, 61, 5, false, 0x0001_0004),
+    condition(58, 29, 59, 29, true, 0x2200_0000),
+    condition(57, 29, 58, 29, true, 0x1080_0000),
+    condition(56, 4, 59, 29, true, 0x2800_0000),
+    condition(56, 29, 59, 29, false, 0x0A00_0000),
+    condition(56, 29, 57, 29, true, 0x0820_0000),
+    condition(55, 4, 58, 29, true, 0x1200_0000),
+    condition(54, 4, 57, 29, true, 0x0880_0000),
+    condition(53, 4, 56, 29, true, 0x0220_0000),
+    condition(50, 6, 51, 1, true, 0x0004_1000),
+    condition(48, 6, 50, 6, true, 0x0004_1000),
+    condition(48, 29, 55, 29, false, 0x0000_A000),
+    condition(47, 6, 49, 6, true, 0x0000_4400),
+    condition(47, 6, 48, 1, true, 0x0400_0040),
+    condition(46, 6, 48, 6, true, 0x0000_1100),
+    condition(46, 6, 47, 1, true, 0x0100_0010),
+    condition(44, 1, 45, 6, false, 0x0040_4000),
+    condition(43, 6, 45, 6, true, 0x0000_0440),
+    condition(42, 6, 44, 6, true, 0x0000_0110),
+    condition(42, 6, 43, 1, true, 0x0404_0000),
+    condition(41, 6, 42, 1, true, 0x0100_4000),
+    condition(40, 6, 41, 1, true, 0x0040_1000),
+    condition(39, 4, 43, 29, true, 0x0200_8000),
+    condition(38, 4, 42, 29, true, 0x0080_2000),
+    condition(37, 1, 38, 6, false, 0x0000_4100),
+    condition(37, 4, 41, 29, true, 0x0020_0800),
+    condition(36, 4, 38, 4, false, 0x2800_0000),
+    condition(35, 1, 36, 6, false, 0x0000_0410),
+    condition(35, 3, 39, 28, true, 0x0008_2000),
+    condition(40, 6, 42, 6, true, 0x0000_0010),
+    condition(36, 4, 37, 4, false, 0x0000_0800),
+    condition(43, 1, 51, 1, false, 0x0000_1000),
+    condition(37, 4, 38, 4, false, 0x0000_2000),
+    condition(38, 4, 39, 4, false, 0x0000_8000),
+    condition(47, 1, 51, 1, false, 0x0004_0000),
+    condition(61, 1, 62, 6, false, 0x0000_0001),
+    condition(59, 5, 63, 30, true, 0x0000_0001),
+    condition(58, 0, 63, 30, false, 0x0000_0001),
+    condition(62, 1, 63, 6, false, 0x0000_0002),
+    condition(60, 5, 64, 30, true, 0x0000_0002),
+    condition(59, 0, 64, 30, false, 0x0000_0002),
+    condition(62, 2, 63, 7, false, 0x0000_0040),
+    condition(41, 6, 43, 6, true, 0x0000_0040),
+    condition(63, 2, 64, 7, false, 0x0000_0100),
+    condition(48, 6, 49, 1, true, 0x0000_0100),
+    condition(49, 6, 50, 1, true, 0x0000_0400),
+    condition(42, 1, 50, 1, false, 0x0000_0400),
+    condition(39, 6, 40, 1, true, 0x0000_0400),
+    condition(38, 1, 40, 1, false, 0x0000_0400),
+    condition(51, 6, 52, 1, true, 0x0000_4000),
+    condition(49, 6, 51, 6, true, 0x0000_4000),
+    condition(37, 1, 37, 6, true, 0x0000_4000),
+    condition(35, 5, 39, 30, true, 0x0000_4000),
+    condition(36, 3, 40, 28, true, 0x0010_0000),
+    condition(35, 30, 40, 28, false, 0x0010_0000),
+    condition(37, 3, 41, 28, true, 0x0020_0000),
+    condition(36, 30, 41, 28, false, 0x0020_0000),
+    condition(53, 6, 54, 1, true, 0x0040_0000),
+    condition(51, 6, 53, 6, true, 0x0040_0000),
+    condition(50, 1, 54, 1, false, 0x0040_0000),
+    condition(45, 6, 46, 1, true, 0x0040_0000),
+    condition(37, 5, 41, 30, true, 0x0040_0000),
+    condition(36, 0, 41, 30, false, 0x0040_0000),
+    condition(55, 29, 58, 29, false, 0x0080_0000),
+    condition(38, 3, 42, 28, true, 0x0080_0000),
+    condition(37, 30, 42, 28, false, 0x0080_0000),
+    condition(54, 6, 55, 1, true, 0x0100_0000),
+    condition(52, 6, 54, 6, true, 0x0100_0000),
+    condition(51, 1, 55, 1, false, 0x0100_0000),
+    condition(45, 1, 47, 1, false, 0x0100_0000),
+    condition(38, 5, 42, 30, true, 0x0100_0000),
+    condition(37, 0, 42, 30, false, 0x0100_0000),
+    condition(39, 3, 43, 28, true, 0x0200_0000),
+    condition(38, 30, 43, 28, false, 0x0200_0000),
+    condition(55, 6, 56, 1, true, 0x0400_0000),
+    condition(53, 6, 55, 6, true, 0x0400_0000),
+    condition(52, 1, 56, 1, false, 0x0400_0000),
+    condition(46, 1, 48, 1, false, 0x0400_0000),
+    condition(39, 5, 43, 30, true, 0x0400_0000),
+    condition(38, 0, 43, 30, false, 0x0400_0000),
+    condition(59, 29, 60, 29, true, 0x0800_0000),
+    condition(40, 3, 44, 28, true, 0x0800_0000),
+    condition(40, 4, 44, 29, true, 0x0800_0000),
+    condition(39, 30, 44, 28, false, 0x0800_0000),
+    condition(58, 29, 61, 29, false, 0x1000_0000),
+    condition(57, 4, 61, 29, true, 0x1000_0000),
+    condition(41, 3, 45, 28, true, 0x1000_0000),
+    condition(41, 4, 45, 29, true, 0x1000_0000),
+    condition(58, 4, 62, 29, true, 0x2000_0000),
+    condition(42, 3, 46, 28, true, 0x2000_0000),
+    condition(42, 4, 46, 29, true, 0x2000_0000),
+    condition(59, 4, 63, 29, true, 0x4000_0000),
+    condition(57, 4, 59, 29, true, 0x4000_0000),
+    condition(43, 3, 47, 28, true, 0x4000_0000),
+    condition(43, 4, 47, 29, true, 0x4000_0000),
+    condition(60, 4, 64, 29, true, 0x8000_0000),
+    condition(44, 3, 48, 28, true, 0x8000_0000),
+    condition(44, 4, 48, 29, true, 0x8000_0000),
+];
+
+/// Returns the disturbance vectors a block with the expanded message `words`
+/// may be part of an attack following, as the bits of their indices in
+/// [`DISTURBANCE_VECTORS`].
+fn possible_vectors(words: &[u32; 80]) -> u32 {
+    let mut vectors = u32::MAX;
+    for condition in &UNAVOIDABLE_CONDITIONS {
+        let (first, first_bit) = condition.first;
+        let (second, second_bit) = condition.second;
+        let failed = (words[first] >> first_bit ^ words[second] >> second_bit)
+            & 1
+            ^ u32::from(!condition.equal);
+        // Without branching, as the bits are random
+        vectors &= !(condition.vectors & failed.wrapping_neg());
+        if vectors == 0 {
+            break;
+        }
+    }
+    vectors
+}
+
+/// Processes a 512-bit block and updates the state.
+fn process_block(state: &mut [u32; 5], block: &[u8; BLOCK_LEN]) {
+    let words = expand_block(block);
+    let mut vars = *state;
+    run_steps(&mut vars, &words, 0..80);
+    *state = add_state(*state, vars);
+}
+
+/// The constants of the four groups of 20 steps.
+const ROUND_CONSTANTS: [u32; 4] =
+    [0x5A82_7999, 0x6ED9_EBA1, 0x8F1B_BCDC, 0xCA62_C1D6];
+
+/// The round function of the steps of the first group.
+fn choice(b: u32, c: u32, d: u32) -> u32 {
+    (b & c) | (!b & d)
+}
+
+/// The round function of the steps of the second and last groups.
+fn parity(b: u32, c: u32, d: u32) -> u32 {
+    b ^ c ^ d
+}
+
+/// The round function of the steps of the third group.
+fn majority(b: u32, c: u32, d: u32) -> u32 {
+    (b & c) | (b & d) | (c & d)
+}
+
+/// Runs `steps` over the working variables `vars`, with the schedule
+/// `words`.
+///
+/// Each group of 20 steps runs in its own loop, so that the steps need not
+/// branch on which group they are in.
+fn run_steps(vars: &mut [u32; 5], words: &[u32; 80], steps: Range<usize>) {
+    for (group, k) in ROUND_CONSTANTS.into_iter().enumerate() {
+        let start = steps.start.max(group * 20);
+        let end = steps.end.min(group * 20 + 20);
+        let words = words.get(start..end).unwrap_or_default();
+        match group {
+            0 => run_group(vars, words, k, choice),
+            2 => run_group(vars, words, k, majority),
+            _ => run_group(vars, words, k, parity),
+        }
+    }
+}
 
+/// Runs steps with the round function `f` and the constant `k` over the
+/// working variables `vars`, one for each of the schedule `words`.
+#[inline]
+#[allow(clippy::many_single_char_names)]
+fn run_group(
+    vars: &mut [u32; 5],
+    words: &[u32],
+    k: u32,
+    f: impl Fn(u32, u32, u32) -> u32,
+) {
+    for &word in words {
+        let [a, b, c, d, e] = *vars;
         let temp = a
             .rotate_left(5)
-            .wrapping_add(f)
+            .wrapping_add(f(b, c, d))
             .wrapping_add(e)
             .wrapping_add(k)
-            .wrapping_add(words[i]);
+            .wrapping_add(word);
+        *vars = [temp, a, b.rotate_left(30), c, d];
+    }
+}
 
-        (temp, a, b.rotate_left(30), c, d)
-    });
+/// Runs `steps` backwards over the working variables `vars`, with the
+/// schedule `words`, like [`run_steps`].
+fn unrun_steps(vars: &mut [u32; 5], words: &[u32; 80], steps: Range<usize>) {
+    for (group, k) in ROUND_CONSTANTS.into_iter().enumerate().rev() {
+        let start = steps.start.max(group * 20);
+        let end = steps.end.min(group * 20 + 20);
+        let words = words.get(start..end).unwrap_or_default();
+        match group {
+            0 => unrun_group(vars, words, k, choice),
+            2 => unrun_group(vars, words, k, majority),
+            _ => unrun_group(vars, words, k, parity),
+        }
+    }
+}
 
-    [
-        initial_state[0].wrapping_add(final_state.0),
-        initial_state[1].wrapping_add(final_state.1),
-        initial_state[2].wrapping_add(final_state.2),
-        initial_state[3].wrapping_add(final_state.3),
-        initial_state[4].wrapping_add(final_state.4),
-    ]
+/// Runs steps backwards with the round function `f` and the constant `k`
+/// over the working variables `vars`, one for each of the schedule `words`
+/// from the last.
+#[inline]
+#[allow(clippy::many_single_char_names)]
+fn unrun_group(
+    vars: &mut [u32; 5],
+    words: &[u32],
+    k: u32,
+    f: impl Fn(u32, u32, u32) -> u32,
+) {
+    for &word in words.iter().rev() {
+        let [temp, a, rotated_b, c, d] = *vars;
+        let b = rotated_b.rotate_right(30);
+        let e = temp
+            .wrapping_sub(a.rotate_left(5))
+            .wrapping_sub(f(b, c, d))
+            .wrapping_sub(k)
+            .wrapping_sub(word);
+        *vars = [a, b, c, d, e];
+    }
 }
 
-/// Expands a 512-bit chunk into an 80-word array.
-fn expand_chunk(chunk: &[u8]) -> [u32; 80] {
+/// Expands a 512-bit block into the 80 words of its message schedule.
+fn expand_block(block: &[u8; BLOCK_LEN]) -> [u32; 80] {
     let mut words = [0u32; 80];
-    words[..16].iter_mut().enumerate().for_each(|(i, word)| {
-        *word = u32::from_be_bytes([
-            chunk[i * 4],
-            chunk[i * 4 + 1],
-            chunk[i * 4 + 2],
-            chunk[i * 4 + 3],
-        ]);
-    });
-
-    (16..80).for_each(|i| {
+    for (word, bytes) in words.iter_mut().zip(block.chunks_exact(4)) {
+        *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+    for i in 16..80 {
         words[i] =
             (words[i - 3] ^ words[i - 8] ^ words[i - 14] ^ words[i - 16])
                 .rotate_left(1);
-    });
-
+    }
     words
 }
 
+/// Processes a 512-bit block and updates the state, like [`process_block`],
+/// and checks whether the block is part of a collision attack.
+///
+/// For each disturbance vector whose unavoidable bit conditions the block
+/// meets, the other block of a collision following it is rebuilt from the
+/// state both blocks share at the checkpoint of the vector, by running its
+/// steps backwards and forwards from there. The block is part of an attack
+/// if the other block hashes to the same state.
+///
+/// Like the safe hash of git, the block of an attack is processed twice more,
+/// so that both messages of the collision hash differently.
+fn check_block(state: &mut [u32; 5], block: &[u8; BLOCK_LEN]) -> bool {
+    let words = expand_block(block);
+
+    let mut checkpoints = [[0u32; 5]; 2];
+    let mut vars = *state;
+    run_steps(&mut vars, &words, 0..CHECKPOINTS[0]);
+    checkpoints[0] = vars;
+    run_steps(&mut vars, &words, CHECKPOINTS[0]..CHECKPOINTS[1]);
+    checkpoints[1] = vars;
+    run_steps(&mut vars, &words, CHECKPOINTS[1]..80);
+    let output = add_state(*state, vars);
+    *state = output;
+
+    let vectors = possible_vectors(&words);
+    if vectors == 0 {
+        return false;
+    }
+    let collision =
+        DISTURBANCE_VECTORS.iter().enumerate().any(|(i, vector)| {
+            let vars =
+                checkpoints[usize::from(vector.checkpoint != CHECKPOINTS[0])];
+            vectors >> i & 1 == 1
+                && rebuilds_collision(&words, vars, output, vector)
+        });
+    if collision {
+        process_block(state, block);
+        process_block(state, block);
+    }
+    collision
+}
+
+/// Returns whether the other block of a collision following `vector`, given
+/// the schedule `words` of a block with the working variables `vars` at the
+/// checkpoint of the vector and the resulting state `output`, also results in
+/// `output`.
+fn rebuilds_collision(
+    words: &[u32; 80],
+    vars: [u32; 5],
+    output: [u32; 5],
+    vector: &DisturbanceVector,
+) -> bool {
+    let t = vector.checkpoint;
+    let mut other = *words;
+    for (word, difference) in other.iter_mut().zip(vector.differences) {
+        *word ^= difference;
+    }
+
+    let mut input = vars;
+    unrun_steps(&mut input, &other, 0..t);
+    let mut vars = vars;
+    run_steps(&mut vars, &other, t..80);
+    add_state(input, vars) == output
+}
+
+/// Adds the working variables `vars` to the state `state`, word by word.
+fn add_state(mut state: [u32; 5], vars: [u32; 5]) -> [u32; 5] {
+    for (word, value) in state.iter_mut().zip(vars) {
+        *word = word.wrapping_add(value);
+    }
+    state
+}
+
 /// Calculates the SHA-1 hash of a message in one step.
 ///
 /// # Examples
 ///
 /// ```
+/// # use mini_git::utils::sha1::sha1;
+/// let result = sha1(b"hello world");
+/// assert_eq!(result, [42, 174, 108, 53, 201, 79, 207, 180, 21, 219, 233, 95, 64, 139, 156, 233, 30, 232, 70, 237]);
+/// ```
+#[must_use]
+pub fn sha1(message: &[u8]) -> [u8; 20] {
+    SHA1::new().update(message).finalize()
+}
+
+/// Calculates the SHA-1 hash of a message in one step, like [`sha1`].
+///
+/// # Examples
+///
+/// ```
 /// # use mini_git::utils::sha1::hash;
 /// let result = hash(b"hello world");
 /// assert_eq!(result, [42, 174, 108, 53, 201, 79, 207, 180, 21, 219, 233, 95, 64, 139, 156, 233, 30, 232, 70, 237]);
 /// ```
 #[must_use]
 pub fn hash(message: &[u8]) -> [u8; 20] {
-    SHA1::new().update(message).finalize()
+    sha1(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disturbance_vectors() {
+        // The first message differences of I(43, 0), as in the detection of git
+        assert_eq!(
+            DISTURBANCE_VECTORS[0].differences[..8],
+            [
+                0x0800_0000,
+                0x9800_000C,
+                0xD800_0010,
+                0x0800_0010,
+                0xB800_0010,
+                0x9800_0000,
+                0x6000_0000,
+                0x0000_0008,
+            ]
+        );
+
+        // The differences follow the message expansion
+        for vector in &DISTURBANCE_VECTORS {
+            let dm = vector.differences;
+            for i in 16..80 {
+                assert_eq!(
+                    dm[i],
+                    (dm[i - 3] ^ dm[i - 8] ^ dm[i - 14] ^ dm[i - 16])
+                        .rotate_left(1)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_rebuilds_collision() {
+        let block: [u8; BLOCK_LEN] =
+            std::array::from_fn(|i| u8::try_from(i * 7 % 251).unwrap());
+        let words = expand_block(&block);
+        let mut vars = INITIAL_STATE;
+        run_steps(&mut vars, &words, 0..58);
+        let checkpoint = vars;
+        run_steps(&mut vars, &words, 58..80);
+        let output = add_state(INITIAL_STATE, vars);
+
+        // Steps run backwards give back the input
+        unrun_steps(&mut vars, &words, 0..80);
+        assert_eq!(vars, INITIAL_STATE);
+
+        // A block without differences is rebuilt as itself
+        let same = DisturbanceVector {
+            checkpoint: 58,
+            differences: [0; 80],
+        };
+        assert!(rebuilds_collision(&words, checkpoint, output, &same));
+        assert!(!DISTURBANCE_VECTORS
+            .iter()
+            .filter(|vector| vector.checkpoint == 58)
+            .any(|vector| {
+                rebuilds_collision(&words, checkpoint, output, vector)
+            }));
+
+        let mut state = INITIAL_STATE;
+        assert!(!check_block(&mut state, &block));
+        assert_eq!(state, output);
+    }
 }
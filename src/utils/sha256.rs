@@ -0,0 +1,323 @@
+//! SHA-256 Hash Implementation
+//!
+//! This module provides an implementation of the SHA-256 hashing algorithm,
+//! from the SHA-2 family, which names the objects of repositories with the
+//! `sha256` object format. It produces a 256-bit hash value, typically
+//! rendered as a 64-digit hexadecimal number.
+//!
+//! # Examples
+//!
+//! Basic usage:
+//!
+//! ```
+//! use mini_git::utils::sha256::SHA256;
+//!
+//! let mut hasher = SHA256::new();
+//! let _ = hasher.update(b"hello world");
+//! let result = hasher.hex_digest();
+//! assert_eq!(
+//!     result,
+//!     "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9"
+//! );
+//! ```
+//!
+//! One-shot hash calculation:
+//!
+//! ```
+//! use mini_git::utils::sha256::sha256;
+//!
+//! let result = sha256(b"hello world");
+//! assert_eq!(result[..4], [0xb9, 0x4d, 0x27, 0xb9]);
+//! ```
+
+#![forbid(unsafe_code)]
+#![allow(clippy::missing_panics_doc)]
+
+use crate::utils::hex;
+
+/// Initial state constants for the SHA-256 algorithm.
+const INITIAL_STATE: [u32; 8] = [
+    0x6A09_E667,
+    0xBB67_AE85,
+    0x3C6E_F372,
+    0xA54F_F53A,
+    0x510E_527F,
+    0x9B05_688C,
+    0x1F83_D9AB,
+    0x5BE0_CD19,
+];
+
+/// The constants of the 64 rounds.
+const ROUND_CONSTANTS: [u32; 64] = [
+    0x428A_2F98,
+    0x7137_4491,
+    0xB5C0_FBCF,
+    0xE9B5_DBA5,
+    0x3956_C25B,
+    0x59F1_11F1,
+    0x923F_82A4,
+    0xAB1C_5ED5,
+    0xD807_AA98,
+    0x1283_5B01,
+    0x2431_85BE,
+    0x550C_7DC3,
+    0x72BE_5D74,
+    0x80DE_B1FE,
+    0x9BDC_06A7,
+    0xC19B_F174,
+    0xE49B_69C1,
+    0xEFBE_4786,
+    0x0FC1_9DC6,
+    0x240C_A1CC,
+    0x2DE9_2C6F,
+    0x4A74_84AA,
+    0x5CB0_A9DC,
+    0x76F9_88DA,
+    0x983E_5152,
+    0xA831_C66D,
+    0xB003_27C8,
+    0xBF59_7FC7,
+    0xC6E0_0BF3,
+    0xD5A7_9147,
+    0x06CA_6351,
+    0x1429_2967,
+    0x27B7_0A85,
+    0x2E1B_2138,
+    0x4D2C_6DFC,
+    0x5338_0D13,
+    0x650A_7354,
+    0x766A_0ABB,
+    0x81C2_C92E,
+    0x9272_2C85,
+    0xA2BF_E8A1,
+    0xA81A_664B,
+    0xC24B_8B70,
+    0xC76C_51A3,
+    0xD192_E819,
+    0xD699_0624,
+    0xF40E_3585,
+    0x106A_A070,
+    0x19A4_C116,
+    0x1E37_6C08,
+    0x2748_774C,
+    0x34B0_BCB5,
+    0x391C_0CB3,
+    0x4ED8_AA4A,
+    0x5B9C_CA4F,
+    0x682E_6FF3,
+    0x748F_82EE,
+    0x78A5_636F,
+    0x84C8_7814,
+    0x8CC7_0208,
+    0x90BE_FFFA,
+    0xA450_6CEB,
+    0xBEF9_A3F7,
+    0xC671_78F2,
+];
+
+/// Size in bytes of the blocks SHA-256 processes.
+const BLOCK_LEN: usize = 64;
+
+/// SHA-256 hasher structure.
+///
+/// Whole blocks of the data are processed as they are given, only the bytes
+/// of an incomplete block are buffered until more data is given.
+#[derive(Clone)]
+pub struct SHA256 {
+    state: [u32; 8],
+    buffer: [u8; BLOCK_LEN],
+    buffer_len: usize,
+    total_len: u64,
+}
+
+impl Default for SHA256 {
+    /// Creates a new SHA-256 hasher with the default initial state.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mini_git::utils::sha256::SHA256;
+    /// let hasher = SHA256::default();
+    /// ```
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SHA256 {
+    /// Creates a new SHA-256 hasher with the default initial state.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mini_git::utils::sha256::SHA256;
+    /// let hasher = SHA256::new();
+    /// ```
+    #[must_use]
+    pub fn new() -> Self {
+        SHA256 {
+            state: INITIAL_STATE,
+            buffer: [0; BLOCK_LEN],
+            buffer_len: 0,
+            total_len: 0,
+        }
+    }
+
+    /// Updates the hasher with the provided data.
+    ///
+    /// This method can be called multiple times with different chunks of data.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mini_git::utils::sha256::SHA256;
+    /// let mut hasher = SHA256::new();
+    /// let _ = hasher.update(b"hello");
+    /// let _ = hasher.update(b" world");
+    /// ```
+    #[must_use]
+    pub fn update(&mut self, mut data: &[u8]) -> &mut Self {
+        self.total_len = self.total_len.wrapping_add(data.len() as u64);
+
+        // Complete the buffered block first
+        if self.buffer_len > 0 {
+            let len = data.len().min(BLOCK_LEN - self.buffer_len);
+            self.buffer[self.buffer_len..self.buffer_len + len]
+                .copy_from_slice(&data[..len]);
+            self.buffer_len += len;
+            data = &data[len..];
+
+            if self.buffer_len < BLOCK_LEN {
+                return self;
+            }
+            process_block(&mut self.state, &self.buffer);
+            self.buffer_len = 0;
+        }
+
+        let mut blocks = data.chunks_exact(BLOCK_LEN);
+        for block in &mut blocks {
+            process_block(&mut self.state, block.try_into().unwrap());
+        }
+
+        let rest = blocks.remainder();
+        self.buffer[..rest.len()].copy_from_slice(rest);
+        self.buffer_len = rest.len();
+
+        self
+    }
+
+    /// Finalizes the hasher and returns the SHA-256 hash value.
+    ///
+    /// The hasher is left as it was, so more data may be given to it
+    /// afterwards.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mini_git::utils::sha256::SHA256;
+    /// let mut hasher = SHA256::new();
+    /// let _ = hasher.update(b"hello world");
+    /// let result = hasher.finalize();
+    /// ```
+    pub fn finalize(&mut self) -> [u8; 32] {
+        let mut state = self.state;
+        let mut block = [0; BLOCK_LEN];
+        block[..self.buffer_len]
+            .copy_from_slice(&self.buffer[..self.buffer_len]);
+        block[self.buffer_len] = 0x80;
+
+        // The length does not fit after the padding byte, pad a whole block
+        if self.buffer_len >= BLOCK_LEN - 8 {
+            process_block(&mut state, &block);
+            block = [0; BLOCK_LEN];
+        }
+        block[BLOCK_LEN - 8..]
+            .copy_from_slice(&self.total_len.wrapping_mul(8).to_be_bytes());
+        process_block(&mut state, &block);
+
+        let mut digest = [0; 32];
+        for (bytes, word) in digest.chunks_exact_mut(4).zip(state) {
+            bytes.copy_from_slice(&word.to_be_bytes());
+        }
+        digest
+    }
+
+    /// Returns the SHA-256 hash value as a hexadecimal string.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mini_git::utils::sha256::SHA256;
+    /// let mut hasher = SHA256::new();
+    /// let _ = hasher.update(b"abc");
+    /// assert_eq!(
+    ///     hasher.hex_digest(),
+    ///     "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    /// );
+    /// ```
+    pub fn hex_digest(&mut self) -> String {
+        hex::encode(&self.finalize())
+    }
+}
+
+/// Processes a 512-bit block and updates the state.
+#[allow(clippy::many_single_char_names)]
+fn process_block(state: &mut [u32; 8], block: &[u8; BLOCK_LEN]) {
+    let mut words = [0u32; 64];
+    for (word, bytes) in words.iter_mut().zip(block.chunks_exact(4)) {
+        *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+    for i in 16..64 {
+        let s0 = words[i - 15].rotate_right(7)
+            ^ words[i - 15].rotate_right(18)
+            ^ (words[i - 15] >> 3);
+        let s1 = words[i - 2].rotate_right(17)
+            ^ words[i - 2].rotate_right(19)
+            ^ (words[i - 2] >> 10);
+        words[i] = words[i - 16]
+            .wrapping_add(s0)
+            .wrapping_add(words[i - 7])
+            .wrapping_add(s1);
+    }
+
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+    for (&word, &k) in words.iter().zip(&ROUND_CONSTANTS) {
+        let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let choice = (e & f) ^ (!e & g);
+        let temp1 = h
+            .wrapping_add(s1)
+            .wrapping_add(choice)
+            .wrapping_add(k)
+            .wrapping_add(word);
+        let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let majority = (a & b) ^ (a & c) ^ (b & c);
+        let temp2 = s0.wrapping_add(majority);
+
+        h = g;
+        g = f;
+        f = e;
+        e = d.wrapping_add(temp1);
+        d = c;
+        c = b;
+        b = a;
+        a = temp1.wrapping_add(temp2);
+    }
+
+    for (word, value) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+        *word = word.wrapping_add(value);
+    }
+}
+
+/// Calculates the SHA-256 hash of a message in one step.
+///
+/// # Examples
+///
+/// ```
+/// # use mini_git::utils::sha256::sha256;
+/// let result = sha256(b"");
+/// assert_eq!(result[..4], [0xe3, 0xb0, 0xc4, 0x42]);
+/// ```
+#[must_use]
+pub fn sha256(message: &[u8]) -> [u8; 32] {
+    SHA256::new().update(message).finalize()
+}
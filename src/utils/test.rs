@@ -254,7 +254,7 @@ impl<'a, T> TempDir<'a, T> {
     }
 }
 
-impl<'a, T> Drop for TempDir<'a, T> {
+impl<T> Drop for TempDir<'_, T> {
     fn drop(&mut self) {
         self.revert();
     }
@@ -294,7 +294,11 @@ impl<'a, T> Drop for TempDir<'a, T> {
 /// - It fails to read the contents of the directory or any of its subdirectories.
 #[must_use]
 pub fn walkdir(top: &Path) -> Vec<PathBuf> {
-    assert!(top.is_dir(), "Top is not a directory (top = {top:?})");
+    assert!(
+        top.is_dir(),
+        "Top is not a directory (top = {})",
+        top.display()
+    );
     top.read_dir()
         .expect("Should read the dir")
         .flatten()
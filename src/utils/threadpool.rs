@@ -0,0 +1,191 @@
+//! A pool of worker threads
+//!
+//! Jobs queued on a [`ThreadPool`] are run by a fixed number of worker
+//! threads. The workers are started when the first job is queued, and are
+//! then kept until the pool is dropped, so a single pool can serve many
+//! parallel operations without spawning threads for each of them.
+//!
+//! # Examples
+//!
+//! ```
+//! use mini_git::utils::threadpool::ThreadPool;
+//!
+//! let pool = ThreadPool::new(4);
+//! let squares = pool
+//!     .map(1..=5, |n: u32| n * n)
+//!     .collect::<Result<Vec<_>, _>>()
+//!     .unwrap();
+//!
+//! assert_eq!(squares, [1, 4, 9, 16, 25]);
+//! ```
+
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex, OnceLock, PoisonError};
+use std::thread;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// A fixed number of worker threads that run queued jobs.
+///
+/// Dropping the pool does not wait for queued jobs. The workers finish the
+/// jobs already queued, and then exit.
+#[derive(Debug)]
+pub struct ThreadPool {
+    size: usize,
+    /// The sending end of the job queue, once the workers are started
+    sender: Mutex<Option<mpsc::Sender<Job>>>,
+}
+
+impl ThreadPool {
+    /// Create a pool of `size` worker threads. A `size` of 0 uses one thread
+    /// per available CPU, like [`ThreadPool::default`].
+    #[must_use]
+    pub fn new(size: usize) -> Self {
+        if size == 0 {
+            return Self::default();
+        }
+        Self {
+            size,
+            sender: Mutex::new(None),
+        }
+    }
+
+    /// The pool shared by the operations that do not keep one of their own,
+    /// with one worker thread per available CPU.
+    #[must_use]
+    pub fn shared() -> &'static Self {
+        static SHARED: OnceLock<ThreadPool> = OnceLock::new();
+        SHARED.get_or_init(Self::default)
+    }
+
+    /// The number of worker threads in the pool.
+    #[must_use]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Queue `job` to be run by the next free worker.
+    ///
+    /// A job that panics does not take its worker down with it.
+    pub fn execute<F>(&self, job: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let mut sender =
+            self.sender.lock().unwrap_or_else(PoisonError::into_inner);
+        let sender = sender.get_or_insert_with(|| self.start_workers());
+        // The workers only stop once the sender is dropped, so this cannot
+        // fail
+        let _ = sender.send(Box::new(job));
+    }
+
+    /// Apply `f` to each of `items` on the workers, yielding the results in
+    /// the order of `items`, each as soon as it and those before it are
+    /// ready.
+    ///
+    /// A result is an error if `f` panicked. Dropping the returned iterator
+    /// skips the items that have not been started yet.
+    pub fn map<I, T, F>(&self, items: I, f: F) -> Ordered<T>
+    where
+        I: IntoIterator,
+        I::Item: Send + 'static,
+        T: Send + 'static,
+        F: Fn(I::Item) -> T + Send + Sync + 'static,
+    {
+        let f = Arc::new(f);
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = mpsc::channel();
+
+        let mut len = 0;
+        for (i, item) in items.into_iter().enumerate() {
+            let (f, cancelled) = (Arc::clone(&f), Arc::clone(&cancelled));
+            let sender = sender.clone();
+            self.execute(move || {
+                if cancelled.load(Ordering::Relaxed) {
+                    return;
+                }
+                let result = panic::catch_unwind(AssertUnwindSafe(|| f(item)))
+                    .map_err(|_| "A thread panicked during execution".into());
+                // The receiver is gone if the results are no longer wanted
+                let _ = sender.send((i, result));
+            });
+            len += 1;
+        }
+
+        Ordered {
+            receiver,
+            pending: HashMap::new(),
+            next: 0,
+            len,
+            cancelled,
+        }
+    }
+
+    fn start_workers(&self) -> mpsc::Sender<Job> {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for _ in 0..self.size {
+            let receiver = Arc::clone(&receiver);
+            thread::spawn(move || loop {
+                // The lock is released as soon as a job is received
+                let job = receiver
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .recv();
+                let Ok(job) = job else {
+                    break;
+                };
+                let _ = panic::catch_unwind(AssertUnwindSafe(job));
+            });
+        }
+        sender
+    }
+}
+
+impl Default for ThreadPool {
+    /// A pool with one worker thread per available CPU.
+    fn default() -> Self {
+        Self::new(thread::available_parallelism().map_or(1, NonZeroUsize::get))
+    }
+}
+
+/// The results of [`ThreadPool::map`], in the order of its items.
+#[derive(Debug)]
+pub struct Ordered<T> {
+    receiver: mpsc::Receiver<(usize, Result<T, String>)>,
+    /// Results received ahead of their turn
+    pending: HashMap<usize, Result<T, String>>,
+    next: usize,
+    len: usize,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl<T> Iterator for Ordered<T> {
+    type Item = Result<T, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next == self.len {
+            return None;
+        }
+        loop {
+            if let Some(result) = self.pending.remove(&self.next) {
+                self.next += 1;
+                return Some(result);
+            }
+            let Ok((index, result)) = self.receiver.recv() else {
+                self.next = self.len;
+                return Some(Err("A worker exited without a result".into()));
+            };
+            self.pending.insert(index, result);
+        }
+    }
+}
+
+impl<T> Drop for Ordered<T> {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,152 @@
+//! Verbosity, and tracing of what commands do
+//!
+//! `--quiet` and `--verbose` set the [`Verbosity`] of a run. Tracing logs
+//! the objects read, the refs updated and the commands run, to make it
+//! possible to follow what a command does to a repository. Like
+//! `GIT_TRACE`, it is turned on by the [`TRACE_ENVIRONMENT`] variable:
+//!
+//! - unset, empty, `0` or `false` turn it off, unless `--verbose` is given,
+//!   in which case traces are written to stderr
+//! - `1`, `2` or `true` write traces to stderr
+//! - an absolute path appends traces to that file
+//!
+//! Traces are lines like `trace: ref: update refs/heads/main to 4b825dc...`.
+
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::OnceLock;
+
+/// The environment variable that turns tracing on
+pub const TRACE_ENVIRONMENT: &str = "MINI_GIT_TRACE";
+
+/// Set by `--quiet` and `--verbose`
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+/// Where traces go, decided on the first trace
+static TARGET: OnceLock<Option<Target>> = OnceLock::new();
+
+/// How much a run reports, as given by `--quiet` or `--verbose`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Report only errors, as `--quiet`
+    Quiet,
+    /// Report progress and warnings
+    #[default]
+    Normal,
+    /// Report everything, with traces on stderr, as `--verbose`
+    Verbose,
+}
+
+/// Sets the verbosity of the run.
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+/// The verbosity of the run.
+#[must_use]
+pub fn verbosity() -> Verbosity {
+    match VERBOSITY.load(Ordering::Relaxed) {
+        0 => Verbosity::Quiet,
+        1 => Verbosity::Normal,
+        _ => Verbosity::Verbose,
+    }
+}
+
+/// Where traces are written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// Traces are written to stderr
+    Stderr,
+    /// Traces are appended to the file
+    File(PathBuf),
+}
+
+/// Where to write traces, given the value of [`TRACE_ENVIRONMENT`], if it
+/// is set, and the verbosity, or `None` if tracing is off.
+///
+/// # Examples
+///
+/// ```
+/// use mini_git::utils::trace::{resolve_target, Target, Verbosity};
+///
+/// let stderr = Some(Target::Stderr);
+/// assert_eq!(resolve_target(Some("1"), Verbosity::Normal), stderr);
+/// assert_eq!(resolve_target(Some("0"), Verbosity::Normal), None);
+/// assert_eq!(resolve_target(None, Verbosity::Verbose), stderr);
+/// ```
+#[must_use]
+pub fn resolve_target(
+    value: Option<&str>,
+    verbosity: Verbosity,
+) -> Option<Target> {
+    match value.unwrap_or_default() {
+        "" | "0" | "false" => {
+            (verbosity == Verbosity::Verbose).then_some(Target::Stderr)
+        }
+        "1" | "2" | "true" => Some(Target::Stderr),
+        path if Path::new(path).is_absolute() => {
+            Some(Target::File(PathBuf::from(path)))
+        }
+        // Like git, values that are not paths are ignored
+        _ => None,
+    }
+}
+
+/// Where traces go for this run.
+fn target() -> Option<&'static Target> {
+    TARGET
+        .get_or_init(|| {
+            let value = std::env::var(TRACE_ENVIRONMENT).ok();
+            resolve_target(value.as_deref(), verbosity())
+        })
+        .as_ref()
+}
+
+/// Whether tracing is on.
+#[must_use]
+pub fn enabled() -> bool {
+    target().is_some()
+}
+
+/// Traces `message` under `category`, if tracing is on.
+///
+/// # Examples
+///
+/// ```
+/// use mini_git::utils::trace;
+///
+/// let sha = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
+/// trace::log("object", format_args!("read {sha}"));
+/// ```
+pub fn log(category: &str, message: fmt::Arguments) {
+    let Some(target) = target() else {
+        return;
+    };
+    let line = format!("trace: {category}: {message}\n");
+    // Tracing is not worth failing an operation over
+    let _ = match target {
+        Target::Stderr => io::stderr().lock().write_all(line.as_bytes()),
+        Target::File(path) => OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(line.as_bytes())),
+    };
+}
+
+/// Traces running `program` with `args`.
+pub fn command<S: AsRef<str>>(program: &str, args: &[S]) {
+    if !enabled() {
+        return;
+    }
+    let mut line = format!("'{program}'");
+    for arg in args {
+        line.push_str(" '");
+        line.push_str(arg.as_ref());
+        line.push('\'');
+    }
+    log("run_command", format_args!("{line}"));
+}
@@ -1,13 +1,129 @@
 const ADLER_MODULO: u32 = 65521;
 
+/// The most bytes that can be added to the sums, starting below
+/// [`ADLER_MODULO`], before `b` could overflow a `u32`.
+const NMAX: usize = 5552;
+
+/// The number of bytes summed at once.
+const BLOCK: usize = 16;
+#[allow(clippy::cast_possible_truncation)]
+const BLOCK_U32: u32 = BLOCK as u32;
+
 #[must_use]
 #[allow(clippy::module_name_repetitions)]
 pub fn adler32(data: &[u8]) -> u32 {
-    let (a, b) = data.iter().fold((1u32, 0u32), |(mut a, mut b), &byte| {
-        a = (a + u32::from(byte)) % ADLER_MODULO;
-        b = (b + a) % ADLER_MODULO;
-        (a, b)
-    });
+    let mut adler = Adler32::new();
+    adler.update(data);
+    adler.finish()
+}
+
+/// An Adler-32 checksum computed incrementally, over data given in pieces.
+#[derive(Debug, Clone, Copy)]
+#[allow(clippy::module_name_repetitions)]
+pub struct Adler32 {
+    a: u32,
+    b: u32,
+}
+
+impl Default for Adler32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Adler32 {
+    /// The checksum of no data.
+    #[must_use]
+    pub fn new() -> Self {
+        Self { a: 1, b: 0 }
+    }
+
+    /// Adds `data` to the checksum.
+    ///
+    /// The sums are only reduced modulo [`ADLER_MODULO`] every [`NMAX`]
+    /// bytes, and within that, blocks of [`BLOCK`] bytes are summed
+    /// independently of each other, which compilers turn into vector
+    /// instructions.
+    pub fn update(&mut self, data: &[u8]) {
+        let (mut a, mut b) = (self.a, self.b);
+        for chunk in data.chunks(NMAX) {
+            let mut blocks = chunk.chunks_exact(BLOCK);
+            for block in &mut blocks {
+                // Summing a block byte by byte adds each byte to `a` once,
+                // and to `b` once for each byte from it to the end
+                let (sum, weighted) = block
+                    .iter()
+                    .zip((1..=BLOCK_U32).rev())
+                    .fold((0u32, 0u32), |(sum, weighted), (&byte, weight)| {
+                        let byte = u32::from(byte);
+                        (sum + byte, weighted + weight * byte)
+                    });
+                b += BLOCK_U32 * a + weighted;
+                a += sum;
+            }
+            for &byte in blocks.remainder() {
+                a += u32::from(byte);
+                b += a;
+            }
+            a %= ADLER_MODULO;
+            b %= ADLER_MODULO;
+        }
+        self.a = a;
+        self.b = b;
+    }
+
+    /// The checksum of the data added so far.
+    #[must_use]
+    pub fn finish(&self) -> u32 {
+        self.b << 16 | self.a
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The checksum computed a byte at a time, as the definition gives it
+    fn reference(data: &[u8]) -> u32 {
+        let (mut a, mut b) = (1u32, 0u32);
+        for &byte in data {
+            a = (a + u32::from(byte)) % ADLER_MODULO;
+            b = (b + a) % ADLER_MODULO;
+        }
+        (b << 16) | a
+    }
+
+    #[test]
+    fn test_known_values() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"a"), 0x0062_0062);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+
+    #[test]
+    fn test_matches_reference() {
+        let data: Vec<u8> = (0..20_000u32)
+            .map(|i| i.wrapping_mul(2_654_435_761).to_be_bytes()[1])
+            .collect();
+        for len in [0, 1, 15, 16, 17, 255, NMAX - 1, NMAX, NMAX + 1, 20_000] {
+            assert_eq!(adler32(&data[..len]), reference(&data[..len]));
+        }
+    }
+
+    #[test]
+    fn test_all_ones_does_not_overflow() {
+        // The largest bytes grow the sums the fastest
+        let data = vec![0xFF; 3 * NMAX + 7];
+        assert_eq!(adler32(&data), reference(&data));
+    }
 
-    b << 16 | a
+    #[test]
+    fn test_update_in_pieces() {
+        let data = vec![0xFF; 2 * NMAX + 100];
+        let mut adler = Adler32::new();
+        for piece in data.chunks(7) {
+            adler.update(piece);
+        }
+        assert_eq!(adler.finish(), reference(&data));
+    }
 }
@@ -1,5 +1,8 @@
 //! This module provides a `BitReader` struct for reading bits from a byte slice.
 //! It also includes utility functions for encoding codes into bytes.
+//!
+//! Bits are read from a 64 bit accumulator, which is refilled from the slice
+//! a word at a time, rather than indexing the slice for every bit.
 
 /// A struct for reading individual bits from a byte slice.
 ///
@@ -17,12 +20,20 @@
 #[derive(Debug)]
 pub struct BitReader<'a> {
     mem: &'a [u8],
+    /// The position of the next byte to load into the accumulator
     pos: usize,
-    byte: u8,
-    numbits: isize,
+    /// Bits loaded but not read yet, least significant first. The bits
+    /// above `count` are zero.
+    bits: u64,
+    count: u32,
+    overrun: bool,
 }
 
 impl<'a> BitReader<'a> {
+    /// The most bits read at once by [`BitReader::read_bits`], which leaves
+    /// room in the accumulator to load whole bytes.
+    const MAX_READ: u32 = 56;
+
     /// Creates a new `BitReader` from a byte slice.
     ///
     /// # Examples
@@ -38,13 +49,60 @@ impl<'a> BitReader<'a> {
         Self {
             mem,
             pos: 0,
-            byte: 0,
-            numbits: 0,
+            bits: 0,
+            count: 0,
+            overrun: false,
         }
     }
 
+    /// Loads as many whole bytes into the accumulator as fit.
+    fn refill(&mut self) {
+        if let Some(word) = self.mem.get(self.pos..self.pos + 8) {
+            let word = u64::from_le_bytes(word.try_into().expect("8 bytes"));
+            self.bits |= word << self.count;
+            let loaded = (63 - self.count) / 8;
+            self.pos += loaded as usize;
+            self.count += loaded * 8;
+            // Clear what was shifted in past the bytes loaded
+            self.bits &= u64::MAX >> (64 - self.count);
+        } else {
+            while self.count <= Self::MAX_READ {
+                let Some(&byte) = self.mem.get(self.pos) else {
+                    break;
+                };
+                self.bits |= u64::from(byte) << self.count;
+                self.pos += 1;
+                self.count += 8;
+            }
+        }
+    }
+
+    /// Discards the bits up to the next byte boundary.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mini_git::utils::zlib::bitreader::BitReader;
+    ///
+    /// let data = vec![0xFF, 0x3C];
+    /// let mut reader = BitReader::new(&data);
+    ///
+    /// assert_eq!(reader.read_bits(3), 0b111);
+    /// reader.align_to_byte();
+    /// assert_eq!(reader.read_bits(8), 0x3C);
+    /// ```
+    pub fn align_to_byte(&mut self) {
+        let skip = self.count % 8;
+        self.bits >>= skip;
+        self.count -= skip;
+    }
+
     /// Reads a single byte from the input.
     ///
+    /// The bits left of a partly read byte are skipped, see
+    /// [`BitReader::align_to_byte`]. Reading past the end of the input gives
+    /// zeros, see [`BitReader::is_overrun`].
+    ///
     /// # Examples
     ///
     /// ```
@@ -56,11 +114,48 @@ impl<'a> BitReader<'a> {
     /// assert_eq!(reader.read_byte(), 0xA5);
     /// assert_eq!(reader.read_byte(), 0x3C);
     /// ```
+    #[allow(clippy::cast_possible_truncation)]
     pub fn read_byte(&mut self) -> u8 {
-        self.numbits = 0;
-        let b = self.mem[self.pos];
-        self.pos += 1;
-        b
+        self.align_to_byte();
+        self.read_bits(8) as u8
+    }
+
+    /// Returns whether more was read than the input has.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mini_git::utils::zlib::bitreader::BitReader;
+    ///
+    /// let mut reader = BitReader::new(&[0xA5]);
+    ///
+    /// assert_eq!(reader.read_byte(), 0xA5);
+    /// assert!(!reader.is_overrun());
+    /// assert_eq!(reader.read_byte(), 0);
+    /// assert!(reader.is_overrun());
+    /// ```
+    #[must_use]
+    pub fn is_overrun(&self) -> bool {
+        self.overrun
+    }
+
+    /// Returns the number of bits read so far.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mini_git::utils::zlib::bitreader::BitReader;
+    ///
+    /// let mut reader = BitReader::new(&[0xA5, 0x3C]);
+    ///
+    /// reader.read_bits(3);
+    /// assert_eq!(reader.bits_read(), 3);
+    /// reader.read_byte();
+    /// assert_eq!(reader.bits_read(), 16);
+    /// ```
+    #[must_use]
+    pub fn bits_read(&self) -> usize {
+        self.pos * 8 - self.count as usize
     }
 
     /// Reads a single bit from the input.
@@ -76,19 +171,9 @@ impl<'a> BitReader<'a> {
     /// assert_eq!(reader.read_bit(), 1);
     /// assert_eq!(reader.read_bit(), 0);
     /// ```
+    #[allow(clippy::cast_possible_truncation)]
     pub fn read_bit(&mut self) -> u8 {
-        if self.numbits <= 0 {
-            self.byte = self.read_byte();
-            self.numbits = 8;
-        }
-
-        self.numbits -= 1;
-
-        // shift bit out of byte
-        let bit = self.byte & 1;
-        self.byte >>= 1;
-
-        bit
+        self.read_bits(1) as u8
     }
 
     /// Reads multiple bits from the input and returns them as a usize.
@@ -104,14 +189,56 @@ impl<'a> BitReader<'a> {
     /// assert_eq!(reader.read_bits(3), 0b001);
     /// assert_eq!(reader.read_bits(5), 0b10110);
     /// ```
+    #[allow(clippy::cast_possible_truncation)]
     pub fn read_bits(&mut self, n: usize) -> usize {
-        let mut out = 0usize;
+        let n = u32::try_from(n).map_or(usize::BITS, |n| n.min(usize::BITS));
+        if n > Self::MAX_READ {
+            let low = self.read_bits(Self::MAX_READ as usize);
+            let high = self.read_bits((n - Self::MAX_READ) as usize);
+            return low | high << Self::MAX_READ;
+        }
 
-        for i in 0..n {
-            out |= (self.read_bit() as usize) << i;
+        if self.count < n {
+            self.refill();
+            if self.count < n {
+                // The bits past the end of the input are zeros
+                self.overrun = true;
+                self.count = n;
+            }
         }
+        let out = self.bits & ((1 << n) - 1);
+        self.bits >>= n;
+        self.count -= n;
+        out as usize
+    }
 
-        out
+    /// Reads `len` bytes from the next byte boundary, as a slice of the
+    /// input, without copying them.
+    ///
+    /// Returns `None`, and reads nothing, if the input has fewer bytes left.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mini_git::utils::zlib::bitreader::BitReader;
+    ///
+    /// let data = vec![0x01, b'a', b'b', b'c'];
+    /// let mut reader = BitReader::new(&data);
+    ///
+    /// assert_eq!(reader.read_bit(), 1);
+    /// assert_eq!(reader.read_aligned(2), Some(&b"ab"[..]));
+    /// assert_eq!(reader.read_aligned(2), None);
+    /// assert_eq!(reader.read_byte(), b'c');
+    /// ```
+    pub fn read_aligned(&mut self, len: usize) -> Option<&'a [u8]> {
+        self.align_to_byte();
+        // The whole bytes in the accumulator are the ones just before `pos`
+        let start = self.pos - (self.count / 8) as usize;
+        let bytes = self.mem.get(start..start.checked_add(len)?)?;
+        self.pos = start + len;
+        self.bits = 0;
+        self.count = 0;
+        Some(bytes)
     }
 
     /// Reads multiple bytes from the input and returns them as a little-endian usize.
@@ -201,4 +328,48 @@ mod tests {
         let mut reader = BitReader::new(b"\x66\x36");
         assert_eq!(reader.read_bytes(2), 13926);
     }
+
+    #[test]
+    fn test_read_bits_across_words() {
+        let data: Vec<u8> = (0..=255u8).rev().cycle().take(100).collect();
+        let bit = |i: usize| (data[i / 8] >> (i % 8)) & 1;
+
+        // Reads of every width, so that they straddle the refills
+        let mut reader = BitReader::new(&data);
+        let mut pos = 0;
+        for n in (0..=33).cycle().take(40) {
+            if pos + n > data.len() * 8 {
+                break;
+            }
+            let expected =
+                (0..n).fold(0, |acc, i| acc | (usize::from(bit(pos + i)) << i));
+            assert_eq!(reader.read_bits(n), expected);
+            pos += n;
+        }
+        assert!(!reader.is_overrun());
+    }
+
+    #[test]
+    fn test_read_past_end() {
+        let mut reader = BitReader::new(b"\xff\xff");
+        assert_eq!(reader.read_bits(12), 0xfff);
+        assert!(!reader.is_overrun());
+        assert_eq!(reader.read_bits(8), 0xf);
+        assert!(reader.is_overrun());
+        assert_eq!(reader.read_bits(16), 0);
+    }
+
+    #[test]
+    fn test_read_aligned() {
+        let data: Vec<u8> = (0..20).collect();
+        let mut reader = BitReader::new(&data);
+
+        // The accumulator holds bytes past the one partly read
+        assert_eq!(reader.read_bits(3), 0);
+        assert_eq!(reader.read_aligned(4), Some(&data[1..5]));
+        assert_eq!(reader.read_byte(), 5);
+        assert_eq!(reader.read_aligned(15), None);
+        assert_eq!(reader.read_aligned(14), Some(&data[6..]));
+        assert!(!reader.is_overrun());
+    }
 }
@@ -1,11 +1,16 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
 
 use crate::utils::zlib::adler::adler32;
 use crate::utils::zlib::bitwriter::BitWriter;
+use crate::utils::zlib::container::{
+    Checksum, Container, ZLIB_DICTIONARY_HEADER,
+};
 use crate::utils::zlib::huffman::{
     get_distance_code, get_length_code, HuffmanTree, CODE_LENGTH_CODES_ORDER,
     DISTANCE_BASE, DISTANCE_EXTRA_BITS, LENGTH_BASE, LENGTH_EXTRA_BITS,
-    ZLIB_MAX_STRING_LENGTH, ZLIB_MIN_STRING_LENGTH, ZLIB_WINDOW_SIZE,
+    MAX_CODE_LENGTH, MAX_CODE_LENGTH_CODE_LENGTH, ZLIB_MAX_STRING_LENGTH,
+    ZLIB_MIN_STRING_LENGTH, ZLIB_WINDOW_SIZE,
 };
 use crate::utils::zlib::lz77::{LZ77Compressor, LZ77Unit};
 use LZ77Unit::{Literal, Marker};
@@ -23,14 +28,33 @@ const SHORT_ZERO_MAX: usize = 10;
 const LONG_ZERO_MIN: usize = 11;
 const LONG_ZERO_MAX: usize = 138;
 
-#[derive(Debug)]
+/// How data is compressed into DEFLATE blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Strategy {
+    /// Each block of [`AUTO_BLOCK_SIZE`] bytes is written as whichever of
+    /// the other strategies makes it smallest
     Auto,
+    /// Huffman codes made for the data, written before it
     Dynamic,
+    /// The fixed Huffman codes of DEFLATE
     Fixed,
+    /// Uncompressed, stored blocks
     Raw,
 }
 
+/// The size of the blocks [`Strategy::Auto`] chooses a block type for.
+pub const AUTO_BLOCK_SIZE: usize = 1 << 16;
+
+/// How many bytes of a block are in each sample of its entropy.
+const ENTROPY_SAMPLE: usize = 4 * ONE_KB;
+
+/// How many samples of a block are taken for its entropy.
+const ENTROPY_SAMPLES: usize = 3;
+
+/// The entropy, in bits per byte, above which a block is taken to be
+/// incompressible, like already compressed data, and is stored.
+const INCOMPRESSIBLE_ENTROPY: f64 = 7.5;
+
 #[cfg_attr(test, derive(PartialEq, Eq))]
 #[derive(Debug)]
 enum RunLengthEncoding {
@@ -47,7 +71,6 @@ use RunLengthEncoding::{Once, Repeat};
 )]
 #[must_use]
 pub fn compress(data: &[u8], strategy: &Strategy) -> Vec<u8> {
-    use Strategy::{Auto, Dynamic, Fixed, Raw};
     const COMPRESSION_METHOD: u8 = 0b0000_1000;
     const COMPRESSION_INFO: u8 = 0b0111_0000;
     const FDICT_MASK: u8 = 0b00_1_00000;
@@ -69,12 +92,7 @@ pub fn compress(data: &[u8], strategy: &Strategy) -> Vec<u8> {
     let flg = (fcheck as u8) & NO_FDICT_OR_FLEVEL;
     bitwriter.write_byte(flg);
 
-    match strategy {
-        Dynamic => compress_dynamic(&mut bitwriter, data),
-        Fixed => compress_fixed(&mut bitwriter, data),
-        Raw => compress_raw(&mut bitwriter, data),
-        Auto => auto_compress(&mut bitwriter, data),
-    };
+    deflate(&mut bitwriter, data, *strategy, true);
 
     // Checksum
     let checksum = adler32(data).to_be_bytes();
@@ -83,102 +101,277 @@ pub fn compress(data: &[u8], strategy: &Strategy) -> Vec<u8> {
     bitwriter.finish()
 }
 
-#[allow(clippy::cast_precision_loss, clippy::cast_lossless)]
-fn auto_compress(writer: &mut BitWriter, data: &[u8]) {
-    // For data lesser 256 bytes the overhead is just not worth it
-    if data.len() < 256 {
-        return compress_raw(writer, data);
+/// Compresses `data` with `strategy` into a zlib stream with the preset
+/// `dictionary`, which the compressed data may refer back into.
+///
+/// The stream names the dictionary by its Adler-32, and can only be
+/// decompressed with the same dictionary, see
+/// [`decompress_with_dictionary`](super::decompress_with_dictionary).
+///
+/// # Examples
+///
+/// ```
+/// use mini_git::utils::zlib::{
+///     compress_with_dictionary, decompress_with_dictionary, Strategy,
+/// };
+///
+/// let dictionary = b"tree parent author committer ";
+/// let data = b"tree 4b825dc\nparent 0000000\nauthor A U Thor";
+/// let compressed =
+///     compress_with_dictionary(data, &Strategy::Fixed, dictionary);
+/// assert_eq!(
+///     decompress_with_dictionary(&compressed, dictionary).unwrap(),
+///     data
+/// );
+/// ```
+#[must_use]
+pub fn compress_with_dictionary(
+    data: &[u8],
+    strategy: &Strategy,
+    dictionary: &[u8],
+) -> Vec<u8> {
+    let mut bitwriter = BitWriter::new();
+    bitwriter.write_bytes(&ZLIB_DICTIONARY_HEADER);
+    bitwriter.write_bytes(&adler32(dictionary).to_be_bytes());
+    deflate_with_dictionary(&mut bitwriter, dictionary, data, *strategy, true);
+    bitwriter.write_bytes(&adler32(data).to_be_bytes());
+    bitwriter.finish()
+}
+
+/// Compresses `data` with `strategy` into a stream framed in `container`.
+///
+/// [`compress`] is the same as a [`Container::Zlib`] stream.
+///
+/// # Examples
+///
+/// ```
+/// use mini_git::utils::zlib::container::Container;
+/// use mini_git::utils::zlib::{compress_as, decompress_as, Strategy};
+///
+/// let gzip = compress_as(b"gzipped", &Strategy::Auto, Container::Gzip);
+/// assert_eq!(gzip[..2], [0x1F, 0x8B]);
+/// assert_eq!(decompress_as(&gzip, Container::Gzip).unwrap(), b"gzipped");
+/// ```
+#[must_use]
+pub fn compress_as(
+    data: &[u8],
+    strategy: &Strategy,
+    container: Container,
+) -> Vec<u8> {
+    if container == Container::Zlib {
+        return compress(data, strategy);
     }
+    let mut bitwriter = BitWriter::new();
+    bitwriter.write_bytes(container.header());
+    deflate(&mut bitwriter, data, *strategy, true);
 
-    // For data lesser than 1 KB, the overhead of storing dynamic codes is not
-    // worth it
-    if data.len() < ONE_KB {
-        return compress_fixed(writer, data);
+    let mut checksum = Checksum::new(container);
+    checksum.update(data);
+    bitwriter.write_bytes(&checksum.trailer());
+
+    bitwriter.finish()
+}
+
+/// Compresses `data` into DEFLATE blocks written to `writer`, ending the
+/// stream with them if `last` is set.
+///
+/// The blocks start where `writer` is, which need not be a byte boundary,
+/// and there is no zlib header or checksum.
+pub(crate) fn deflate(
+    writer: &mut BitWriter,
+    data: &[u8],
+    strategy: Strategy,
+    last: bool,
+) {
+    deflate_with_dictionary(writer, &[], data, strategy, last);
+}
+
+/// Like [`deflate`], with back references into the end of `dictionary`,
+/// as if `data` came after it.
+pub(crate) fn deflate_with_dictionary(
+    writer: &mut BitWriter,
+    dictionary: &[u8],
+    data: &[u8],
+    strategy: Strategy,
+    last: bool,
+) {
+    match strategy {
+        Strategy::Dynamic => compress_dynamic(writer, dictionary, data, last),
+        Strategy::Fixed => compress_fixed(writer, dictionary, data, last),
+        Strategy::Raw => compress_raw(writer, data, last),
+        Strategy::Auto => auto_compress(writer, dictionary, data, last),
     }
+}
 
-    // For data larger than 1 KB,
-    // We sample the first 1KB and and choose based on the following heuristic,
-    // Create a frequency map of the first kb, using this map,
-    // 1. Calculate entropy of the sample, if the entropy is within 25% of
-    //    log2(alphabet_size), prefer fixed encoding. (weight: 0.5 * proximity)
-    // 2. If the top 5 symbols in the alphabet account for > 50% of the data,
-    //    prefer dynamic encoding. (weight: -0.5 * %of the alphabet the top 5 account for)
-    // 3. If the sum of the above two heuristics is within -0.1 < 0.1,
-    //    Estimated compression ratio
-    //      = unique_chars * log2(data_size) / data_size
-    //    If ratio < 0.5, we use dynamic encoding
-    //
-    // Note: These heuristics were chosed arbitrarily, I do not have any
-    //       evidence that they are optimal.
-
-    let mut preference: f64 = 0.;
-
-    let freq = data[..ONE_KB]
-        .iter()
-        .fold(HashMap::new(), |mut map, &byte| {
-            *map.entry(byte).or_insert(0) += 1u32;
-            map
+/// Writes an empty stored block, which ends the blocks before it on a byte
+/// boundary, so that more blocks can be appended byte by byte.
+pub(crate) fn align_to_byte(writer: &mut BitWriter) {
+    // BFINAL = 0, BTYPE = 00, then the length and its complement
+    writer.write_bits(0b000, 3);
+    writer.write_bytes(&[0x00, 0x00, 0xFF, 0xFF]);
+}
+
+/// Compresses `data` a block of [`AUTO_BLOCK_SIZE`] at a time, each as
+/// the block type that makes it smallest.
+fn auto_compress(
+    writer: &mut BitWriter,
+    dictionary: &[u8],
+    data: &[u8],
+    last: bool,
+) {
+    // Empty data still needs a block, to end the stream
+    let n_blocks = data.len().div_ceil(AUTO_BLOCK_SIZE).max(1);
+    for i in 0..n_blocks {
+        let start = i * AUTO_BLOCK_SIZE;
+        let block = &data[start..data.len().min(start + AUTO_BLOCK_SIZE)];
+
+        // Back references may reach into the blocks before, and past them
+        // into the dictionary
+        let history: Cow<[u8]> = if start >= ZLIB_WINDOW_SIZE {
+            Cow::Borrowed(&data[start - ZLIB_WINDOW_SIZE..start])
+        } else if start == 0 {
+            Cow::Borrowed(dictionary)
+        } else {
+            Cow::Owned([dictionary, &data[..start]].concat())
+        };
+
+        auto_compress_block(writer, &history, block, last && i + 1 == n_blocks);
+    }
+}
+
+/// The type of a DEFLATE block, with its codes.
+enum BlockType {
+    Stored,
+    Fixed,
+    Dynamic(HuffmanTree, HuffmanTree),
+}
+
+/// Writes `block` as a stored, fixed or dynamic block, whichever is the
+/// smallest.
+fn auto_compress_block(
+    writer: &mut BitWriter,
+    history: &[u8],
+    block: &[u8],
+    last: bool,
+) {
+    // Finding matches through all of a block that has none is wasted work,
+    // so a block whose bytes look random is stored, if samples from its
+    // start, middle and end do not compress either
+    if block.len() > ENTROPY_SAMPLES * ENTROPY_SAMPLE {
+        let stride = (block.len() - ENTROPY_SAMPLE) / (ENTROPY_SAMPLES - 1);
+        let incompressible = (0..ENTROPY_SAMPLES).all(|i| {
+            let sample = &block[i * stride..i * stride + ENTROPY_SAMPLE];
+            entropy(sample) > INCOMPRESSIBLE_ENTROPY
+                && matches!(
+                    smallest_block(history, sample).1,
+                    BlockType::Stored
+                )
         });
+        if incompressible {
+            return compress_raw(writer, block, last);
+        }
+    }
 
-    // Heuristic 1
-    let entropy = -freq
-        .values()
-        .map(|&x| (x as f64) / (ONE_KB as f64))
-        .map(|p| p * p.log2())
-        .sum::<f64>();
-    let log2_alphabet = (freq.len() as f64).log2();
+    match smallest_block(history, block) {
+        (_, BlockType::Stored) => compress_raw(writer, block, last),
+        (compressed, BlockType::Fixed) => {
+            write_fixed_block(writer, &compressed, last);
+        }
+        (compressed, BlockType::Dynamic(ltree, dtree)) => {
+            write_dynamic_block(writer, &compressed, &ltree, &dtree, last);
+        }
+    }
+}
 
-    let proximity = 1f64 - (((entropy / log2_alphabet) - 1f64).abs());
-    preference += if proximity > 0.75 {
-        proximity
-    } else {
-        0.5 * proximity
-    };
+/// Finds the matches of `block`, and the type of block that makes it the
+/// smallest.
+fn smallest_block(history: &[u8], block: &[u8]) -> (Vec<LZ77Unit>, BlockType) {
+    let compressor = get_zlib_compressor();
+    let compressed = compressor.compress_with_dictionary(history, block);
+    let (fixed_lengths, fixed_distances) = HuffmanTree::zlib_fixed();
+    let (ltree, dtree) = create_dynamic_trees(&compressed, &compressor);
 
-    // Heuristic 2
-    let mut sorted = freq.values().copied().collect::<Vec<_>>();
-    sorted.sort_unstable();
-    let max_idx = 5.min(sorted.len());
-    let proportion =
-        (sorted[..max_idx].iter().sum::<u32>() as f64) / (ONE_KB as f64);
-    preference -= if proportion > 0.5 {
-        proportion
+    // The sizes of the block as each type, in bits, leaving out the
+    // three bits of the block header they all have
+    let stored = block.len().div_ceil(SIXTEEN_KB).max(1) * 40 + 8 * block.len();
+    let fixed = data_bits(&compressed, fixed_lengths, fixed_distances);
+    let mut header = BitWriter::new();
+    write_dynamic_header(&mut header, &ltree, &dtree);
+    let dynamic =
+        header.finish().len() * 8 + data_bits(&compressed, &ltree, &dtree);
+
+    let block_type = if stored <= fixed.min(dynamic) {
+        BlockType::Stored
+    } else if fixed <= dynamic {
+        BlockType::Fixed
     } else {
-        0.5 * proportion
+        BlockType::Dynamic(ltree, dtree)
     };
+    (compressed, block_type)
+}
 
-    if preference > 0.1 {
-        compress_fixed(writer, data);
-    } else if preference < -0.1 {
-        compress_dynamic(writer, data);
-    } else {
-        // Heuristic 3
-        let unique_chars = freq.len() as f64;
-        let data_len = data.len() as f64;
-        let log2_data_len = data_len.log2();
-        let estimated_ratio = unique_chars * log2_data_len / data_len;
-
-        if estimated_ratio < 0.5 {
-            compress_dynamic(writer, data);
-        } else {
-            compress_fixed(writer, data);
-        }
+/// The Shannon entropy of `data`, in bits per byte.
+#[allow(clippy::cast_precision_loss)]
+fn entropy(data: &[u8]) -> f64 {
+    let mut counts = [0usize; 256];
+    for &byte in data {
+        counts[usize::from(byte)] += 1;
     }
+    let len = data.len() as f64;
+    -counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| count as f64 / len)
+        .map(|p| p * p.log2())
+        .sum::<f64>()
 }
 
+/// The number of bits `compressed` takes with the codes of `ltree` and
+/// `dtree`, with the end of block.
 #[allow(clippy::cast_possible_truncation)]
-fn compress_raw(writer: &mut BitWriter, data: &[u8]) {
-    let n_blocks = data.len().div_ceil(SIXTEEN_KB) - 1;
+fn data_bits(
+    compressed: &[LZ77Unit],
+    ltree: &HuffmanTree,
+    dtree: &HuffmanTree,
+) -> usize {
+    let code_len = |tree: &HuffmanTree, symbol: usize| {
+        let symbol = char::from_u32(symbol as u32).expect("Should be in range");
+        tree.encode(symbol).map_or(0, |(_, len)| len)
+    };
+    let units = compressed
+        .iter()
+        .map(|unit| match *unit {
+            Literal(byte) => code_len(ltree, usize::from(byte)),
+            Marker(length, distance) => {
+                let length_code = get_length_code(length);
+                let distance_code = get_distance_code(distance);
+                code_len(ltree, length_code)
+                    + LENGTH_EXTRA_BITS[length_code - 257]
+                    + code_len(dtree, distance_code)
+                    + DISTANCE_EXTRA_BITS[distance_code]
+            }
+        })
+        .sum::<usize>();
+    units + code_len(ltree, 256)
+}
 
-    for (curr_block, chunk) in data.chunks(SIXTEEN_KB).enumerate() {
+#[allow(clippy::cast_possible_truncation)]
+fn compress_raw(writer: &mut BitWriter, data: &[u8], last: bool) {
+    // Empty data still needs a block, to end the stream
+    let n_blocks = data.len().div_ceil(SIXTEEN_KB).max(1);
+    let chunks = data
+        .chunks(SIXTEEN_KB)
+        .chain(data.is_empty().then_some(data));
+
+    for (curr_block, chunk) in chunks.enumerate() {
         // BFINAL
-        writer.write_bit(u8::from(curr_block == n_blocks));
+        writer.write_bit(u8::from(last && curr_block + 1 == n_blocks));
 
         // BTYPE
         writer.write_bits(0b00, 2);
 
         // Write length of block
-        let len = data.len() as u16;
+        let len = chunk.len() as u16;
         let bytes = [(len & 0xff) as u8, (len >> 8) as u8];
         writer.write_bytes(&bytes);
 
@@ -192,37 +385,63 @@ fn compress_raw(writer: &mut BitWriter, data: &[u8]) {
     }
 }
 
-fn compress_fixed(writer: &mut BitWriter, data: &[u8]) {
-    // BFINAL = 1, we only write one massive block
-    writer.write_bit(0b1);
+fn compress_fixed(
+    writer: &mut BitWriter,
+    dictionary: &[u8],
+    data: &[u8],
+    last: bool,
+) {
+    // We only write one massive block
+    let compressor = get_zlib_compressor();
+    let compressed = compressor.compress_with_dictionary(dictionary, data);
+    write_fixed_block(writer, &compressed, last);
+}
+
+fn write_fixed_block(
+    writer: &mut BitWriter,
+    compressed: &[LZ77Unit],
+    last: bool,
+) {
+    // BFINAL
+    writer.write_bit(u8::from(last));
     // BTYPE = 01, Fixed Huffman Codes
     writer.write_bits(0b01, 2);
 
-    let compressor = get_zlib_compressor();
-    let (mut length_tree, mut distance_tree) = HuffmanTree::get_zlib_fixed();
-    length_tree.assign();
-    distance_tree.assign();
-
-    let compressed = compressor.compress(data);
-    write_compressed_data(writer, &compressed, &length_tree, &distance_tree);
+    let (length_tree, distance_tree) = HuffmanTree::zlib_fixed();
+    write_compressed_data(writer, compressed, length_tree, distance_tree);
 }
 
 #[allow(clippy::cast_possible_truncation)]
-fn compress_dynamic(writer: &mut BitWriter, data: &[u8]) {
-    // BFINAL = 1, we only write one massive block
-    writer.write_bit(0b1);
-    // BTYPE = 10, Dynamic Huffman Codes
-    writer.write_bits(0b10, 2);
-
+fn compress_dynamic(
+    writer: &mut BitWriter,
+    dictionary: &[u8],
+    data: &[u8],
+    last: bool,
+) {
+    // We only write one massive block
     let compressor = get_zlib_compressor();
-    let compressed = compressor.compress(data);
+    let compressed = compressor.compress_with_dictionary(dictionary, data);
 
     let (ltree, dtree) = create_dynamic_trees(&compressed, &compressor);
+    write_dynamic_block(writer, &compressed, &ltree, &dtree, last);
+}
 
-    write_dynamic_header(writer, &ltree, &dtree);
+fn write_dynamic_block(
+    writer: &mut BitWriter,
+    compressed: &[LZ77Unit],
+    ltree: &HuffmanTree,
+    dtree: &HuffmanTree,
+    last: bool,
+) {
+    // BFINAL
+    writer.write_bit(u8::from(last));
+    // BTYPE = 10, Dynamic Huffman Codes
+    writer.write_bits(0b10, 2);
+
+    write_dynamic_header(writer, ltree, dtree);
 
     // the easy part, write out the data
-    write_compressed_data(writer, &compressed, &ltree, &dtree);
+    write_compressed_data(writer, compressed, ltree, dtree);
 }
 
 #[allow(clippy::cast_possible_truncation)]
@@ -299,11 +518,11 @@ fn create_dynamic_trees(
 
     let (lmax, dmax) = (ltree.max_code_len(), dtree.max_code_len());
     assert!(
-        lmax <= 15,
+        lmax <= MAX_CODE_LENGTH,
         "The Literal/Length code lengths are too long, found {lmax}"
     );
     assert!(
-        dmax <= 15,
+        dmax <= MAX_CODE_LENGTH,
         "The Distance code lengths are too long, found {dmax}"
     );
     ltree.assign();
@@ -406,20 +625,22 @@ fn get_codelengths(
 
 #[allow(clippy::cast_possible_truncation)]
 fn get_code_tree(combined: &[(usize, Option<usize>)]) -> HuffmanTree {
-    let codes_only = combined
-        .iter()
-        .map(|&(code, _)| code as u8)
-        .collect::<Vec<u8>>();
-
     // This combined codes is our data, make a tree from these codes.
-    let mut code_tree = HuffmanTree::from_data(&codes_only);
-    code_tree.assign();
-    let code_tree = code_tree.to_canonical();
+    let frequencies =
+        combined.iter().fold(HashMap::new(), |mut map, &(code, _)| {
+            *map.entry(char::from(code as u8)).or_insert(0) += 1;
+            map
+        });
+    let code_tree = HuffmanTree::from_freq_limited(
+        frequencies,
+        MAX_CODE_LENGTH_CODE_LENGTH,
+    )
+    .to_canonical();
 
     // Sanity check
     let cmax = code_tree.max_code_len();
     assert!(
-        cmax <= 7,
+        cmax <= MAX_CODE_LENGTH_CODE_LENGTH,
         "The Code code lengths are too long, found {cmax}"
     );
 
@@ -504,7 +725,7 @@ fn rle_to_zlib_codes(rle: &[RunLengthEncoding]) -> Vec<(usize, Option<usize>)> {
                     zlib_rle_encode_nonzero(&mut acc, *num, *repetitions);
                 }
             }
-        };
+        }
         acc
     })
 }
@@ -0,0 +1,109 @@
+//! The containers a DEFLATE stream is framed in
+//!
+//! git stores objects in zlib streams, while gzip files and HTTP content
+//! encodings use gzip framing, and some formats hold raw DEFLATE data with
+//! no framing at all. The framing adds a header before the compressed data
+//! and a checksum of the uncompressed data after it.
+
+use crate::utils::zlib::adler::Adler32;
+use crate::utils::zlib::crc32::Crc32;
+
+/// `CMF` and `FLG` of a zlib stream with a 32KiB window and no compression
+/// level.
+pub(crate) const ZLIB_HEADER: [u8; 2] = [0x78, 0x01];
+
+/// `CMF` and `FLG` of a zlib stream like [`ZLIB_HEADER`], with the `FDICT`
+/// flag of a preset dictionary set.
+pub(crate) const ZLIB_DICTIONARY_HEADER: [u8; 2] = [0x78, 0x20];
+
+/// The `FDICT` flag of `FLG`, set when the `DICTID` of a preset dictionary
+/// follows the header.
+pub(crate) const FDICT: u8 = 0x20;
+
+/// The magic bytes that start a gzip member.
+pub(crate) const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+
+/// A gzip header with no flags, modification time or extra fields, written
+/// on an unknown operating system.
+const GZIP_HEADER: [u8; 10] = [0x1F, 0x8B, 8, 0, 0, 0, 0, 0, 0, 0xFF];
+
+/// The framing of a DEFLATE stream.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Container {
+    /// A two byte header, and the Adler-32 of the data, as git uses.
+    #[default]
+    Zlib,
+    /// A gzip member, with a header of at least ten bytes, and the CRC-32
+    /// and length of the data.
+    Gzip,
+    /// DEFLATE blocks alone.
+    Raw,
+}
+
+impl Container {
+    /// The header written before the compressed data.
+    #[must_use]
+    pub(crate) fn header(self) -> &'static [u8] {
+        match self {
+            Self::Zlib => &ZLIB_HEADER,
+            Self::Gzip => &GZIP_HEADER,
+            Self::Raw => &[],
+        }
+    }
+}
+
+/// The checksum a [`Container`] ends with, computed over the uncompressed
+/// data.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Checksum {
+    Adler(Adler32),
+    Gzip {
+        crc: Crc32,
+        /// The length of the data, modulo 2^32
+        size: u32,
+    },
+    None,
+}
+
+impl Checksum {
+    /// The checksum of no data, for `container`.
+    pub(crate) fn new(container: Container) -> Self {
+        match container {
+            Container::Zlib => Self::Adler(Adler32::new()),
+            Container::Gzip => Self::Gzip {
+                crc: Crc32::new(),
+                size: 0,
+            },
+            Container::Raw => Self::None,
+        }
+    }
+
+    /// Adds `data` to the checksum.
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Adler(adler) => adler.update(data),
+            Self::Gzip { crc, size } => {
+                crc.update(data);
+                // gzip only keeps the length modulo 2^32
+                #[allow(clippy::cast_possible_truncation)]
+                {
+                    *size = size.wrapping_add(data.len() as u32);
+                }
+            }
+            Self::None => {}
+        }
+    }
+
+    /// The trailer that ends the stream, holding the checksum.
+    pub(crate) fn trailer(&self) -> Vec<u8> {
+        match self {
+            Self::Adler(adler) => adler.finish().to_be_bytes().to_vec(),
+            Self::Gzip { crc, size } => {
+                let mut trailer = crc.finish().to_le_bytes().to_vec();
+                trailer.extend_from_slice(&size.to_le_bytes());
+                trailer
+            }
+            Self::None => vec![],
+        }
+    }
+}
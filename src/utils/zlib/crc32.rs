@@ -0,0 +1,78 @@
+/// The CRC-32 polynomial, in reversed bit order.
+const CRC_POLYNOMIAL: u32 = 0xEDB8_8320;
+
+/// The CRC of each byte value, for table driven updates.
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut byte = 0;
+    while byte < 256 {
+        #[allow(clippy::cast_possible_truncation)]
+        let mut crc = byte as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ CRC_POLYNOMIAL
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[byte] = crc;
+        byte += 1;
+    }
+    table
+};
+
+/// Computes the CRC-32 checksum of `data`, as used by gzip and pack indexes.
+///
+/// # Examples
+///
+/// ```
+/// use mini_git::utils::zlib::crc32::crc32;
+///
+/// assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+/// assert_eq!(crc32(b""), 0);
+/// ```
+#[must_use]
+#[allow(clippy::module_name_repetitions)]
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(data);
+    crc.finish()
+}
+
+/// A CRC-32 checksum computed incrementally, over data given in pieces.
+#[derive(Debug, Clone, Copy)]
+#[allow(clippy::module_name_repetitions)]
+pub struct Crc32 {
+    crc: u32,
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Crc32 {
+    /// The checksum of no data.
+    #[must_use]
+    pub fn new() -> Self {
+        Self { crc: !0 }
+    }
+
+    /// Adds `data` to the checksum.
+    pub fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            #[allow(clippy::cast_possible_truncation)]
+            let index = usize::from((self.crc as u8) ^ byte);
+            self.crc = (self.crc >> 8) ^ CRC_TABLE[index];
+        }
+    }
+
+    /// The checksum of the data added so far.
+    #[must_use]
+    pub fn finish(&self) -> u32 {
+        !self.crc
+    }
+}
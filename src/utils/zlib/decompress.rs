@@ -1,12 +1,16 @@
 //! This module provides functionality for decompressing DEFLATE-compressed data.
 //! Inspired from: [this article](https://pyokagan.name/blog/2019-10-18-zlibinflate/)
 
+use std::io::Read;
+
 use crate::utils::zlib::adler::adler32;
 use crate::utils::zlib::bitreader::BitReader;
+use crate::utils::zlib::container::Container;
 use crate::utils::zlib::huffman::{
     HuffmanTree, DISTANCE_BASE, DISTANCE_EXTRA_BITS, LENGTH_BASE,
     LENGTH_EXTRA_BITS,
 };
+use crate::utils::zlib::stream::Inflater;
 
 /// Decompresses DEFLATE-compressed data.
 ///
@@ -29,9 +33,37 @@ use crate::utils::zlib::huffman::{
 /// - The compression method is not DEFLATE (CM != 8)
 /// - The compression info is invalid (CINFO > 7)
 /// - The CMF and FLAGS checksum is invalid
-/// - A preset dictionary is used (not supported)
+/// - A preset dictionary is used, see [`decompress_with_dictionary`]
 /// - The block type is invalid
+/// - The data is truncated or otherwise corrupt
 pub fn decompress(input: &[u8]) -> Result<Vec<u8>, String> {
+    decompress_with_limit(input, usize::MAX)
+}
+
+/// Decompresses DEFLATE-compressed data, like [`decompress`], but fails
+/// instead of decompressing to more than `max_out` bytes.
+///
+/// This bounds the memory used on data whose size is known beforehand, so
+/// that corrupt data can not make it grow without end.
+///
+/// # Examples
+///
+/// ```
+/// use mini_git::utils::zlib::{compress, decompress_with_limit, Strategy};
+///
+/// let compressed = compress(&[0; 1000], &Strategy::Fixed);
+/// assert!(decompress_with_limit(&compressed, 1000).is_ok());
+/// assert!(decompress_with_limit(&compressed, 999).is_err());
+/// ```
+///
+/// # Errors
+///
+/// Like [`decompress`], and if the data decompresses to more than
+/// `max_out` bytes.
+pub fn decompress_with_limit(
+    input: &[u8],
+    max_out: usize,
+) -> Result<Vec<u8>, String> {
     let mut reader = BitReader::new(input);
 
     // CMF is Compression Method and information Field
@@ -69,7 +101,7 @@ pub fn decompress(input: &[u8]) -> Result<Vec<u8>, String> {
     }
 
     // Inflate the data
-    let inflated = inflate(&mut reader)?;
+    let inflated = inflate(&mut reader, max_out)?;
 
     // Need to interpret the value as Big-Endian, because zlib uses Big-Endian.
     let adler32 = adler32(&inflated);
@@ -80,21 +112,64 @@ pub fn decompress(input: &[u8]) -> Result<Vec<u8>, String> {
         acc
     });
     let checksum = u32::from_be_bytes(checksum_bytes);
-    if adler32 == checksum {
+    if reader.is_overrun() {
+        Err(TRUNCATED.to_owned())
+    } else if adler32 == checksum {
         Ok(inflated)
     } else {
         Err("Checksum is invalid".to_owned())
     }
 }
 
+/// Decompresses a zlib stream compressed with the preset `dictionary`, see
+/// [`compress_with_dictionary`](super::compress_with_dictionary).
+///
+/// # Errors
+///
+/// If the stream is malformed or truncated, its checksum does not match, or
+/// it names another dictionary.
+pub fn decompress_with_dictionary(
+    input: &[u8],
+    dictionary: &[u8],
+) -> Result<Vec<u8>, String> {
+    let mut output = vec![];
+    Inflater::with_dictionary(input, dictionary)
+        .read_to_end(&mut output)
+        .map_err(|e| e.to_string())?;
+    Ok(output)
+}
+
+/// Decompresses a stream framed in `container`.
+///
+/// [`decompress`] is the same as a [`Container::Zlib`] stream.
+///
+/// # Errors
+///
+/// If the stream is malformed or truncated, or its checksum does not match.
+pub fn decompress_as(
+    input: &[u8],
+    container: Container,
+) -> Result<Vec<u8>, String> {
+    if container == Container::Zlib {
+        return decompress(input);
+    }
+    let mut output = vec![];
+    Inflater::with_container(input, container)
+        .read_to_end(&mut output)
+        .map_err(|e| e.to_string())?;
+    Ok(output)
+}
+
 /// Inflates DEFLATE-compressed data.
 ///
 /// This function is called by `decompress` to handle the actual inflation process.
 ///
 /// # Errors
 ///
-/// This function will return an error if an invalid block type is encountered.
-fn inflate(reader: &mut BitReader) -> Result<Vec<u8>, String> {
+/// This function will return an error if an invalid block type is
+/// encountered, a block is corrupt, or the data inflates to more than
+/// `max_out` bytes.
+fn inflate(reader: &mut BitReader, max_out: usize) -> Result<Vec<u8>, String> {
     let mut buffer: Vec<u8> = vec![];
 
     let mut final_block = false;
@@ -107,45 +182,88 @@ fn inflate(reader: &mut BitReader) -> Result<Vec<u8>, String> {
         };
 
         match reader.read_bits(2) {
-            0 => inflate_block_no_compression(reader, &mut buffer),
-            1 => inflate_block_fixed(reader, &mut buffer),
-            2 => inflate_block_dynamic(reader, &mut buffer),
+            0 => inflate_block_no_compression(reader, &mut buffer, max_out)?,
+            1 => inflate_block_fixed(reader, &mut buffer, max_out)?,
+            2 => inflate_block_dynamic(reader, &mut buffer, max_out)?,
             _ => return Err("Invalid block type".to_owned()),
-        };
+        }
+
+        if reader.is_overrun() {
+            return Err(TRUNCATED.to_owned());
+        }
     }
 
     Ok(buffer)
 }
 
+/// The error for input that ends before the stream does
+const TRUNCATED: &str = "Truncated zlib stream";
+
+/// The error for output that would grow past `max_out` bytes
+fn too_large(max_out: usize) -> String {
+    format!("Decompressed data exceeds the limit of {max_out} bytes")
+}
+
 /// Inflates an uncompressed block.
 ///
 /// This function is called by `inflate` when an uncompressed block is encountered.
-#[allow(unused_variables)]
-fn inflate_block_no_compression(reader: &mut BitReader, buffer: &mut Vec<u8>) {
+fn inflate_block_no_compression(
+    reader: &mut BitReader,
+    buffer: &mut Vec<u8>,
+    max_out: usize,
+) -> Result<(), String> {
     // Length of the data
     let len = reader.read_bytes(2);
 
     // One's complement of the length of the data
     let nlen = reader.read_bytes(2);
 
-    buffer.extend((0..len).map(|_| reader.read_byte()));
+    if reader.is_overrun() {
+        return Err(TRUNCATED.to_owned());
+    }
+    if len != !nlen & 0xFFFF {
+        return Err("Invalid stored block length".to_owned());
+    }
+    if len > max_out - buffer.len() {
+        return Err(too_large(max_out));
+    }
+
+    let Some(bytes) = reader.read_aligned(len) else {
+        return Err(TRUNCATED.to_owned());
+    };
+    buffer.extend_from_slice(bytes);
+    Ok(())
 }
 
 /// Inflates a block compressed with fixed Huffman codes.
 ///
 /// This function is called by `inflate` when a block with fixed Huffman codes is encountered.
-fn inflate_block_fixed(reader: &mut BitReader, buffer: &mut Vec<u8>) {
-    let (literal_tree, distance_tree) = HuffmanTree::get_zlib_fixed();
-    inflate_block_data(reader, &literal_tree, &distance_tree, buffer);
+fn inflate_block_fixed(
+    reader: &mut BitReader,
+    buffer: &mut Vec<u8>,
+    max_out: usize,
+) -> Result<(), String> {
+    let (literal_tree, distance_tree) = HuffmanTree::zlib_fixed();
+    inflate_block_data(reader, literal_tree, distance_tree, buffer, max_out)
 }
 
 /// Inflates a block compressed with dynamic Huffman codes.
 ///
 /// This function is called by `inflate` when a block with dynamic Huffman codes is encountered.
-fn inflate_block_dynamic(reader: &mut BitReader, buffer: &mut Vec<u8>) {
+fn inflate_block_dynamic(
+    reader: &mut BitReader,
+    buffer: &mut Vec<u8>,
+    max_out: usize,
+) -> Result<(), String> {
     let (literal_length_tree, distance_tree) =
-        HuffmanTree::decode_trees(reader);
-    inflate_block_data(reader, &literal_length_tree, &distance_tree, buffer);
+        HuffmanTree::try_decode_trees(reader)?;
+    inflate_block_data(
+        reader,
+        &literal_length_tree,
+        &distance_tree,
+        buffer,
+        max_out,
+    )
 }
 
 fn inflate_block_data(
@@ -153,17 +271,28 @@ fn inflate_block_data(
     literal_tree: &HuffmanTree,
     distance_tree: &HuffmanTree,
     buffer: &mut Vec<u8>,
-) {
+    max_out: usize,
+) -> Result<(), String> {
     loop {
+        // Past the end, the reader gives zeros, which may decode forever
+        if reader.is_overrun() {
+            return Err(TRUNCATED.to_owned());
+        }
+
         let Some(sym) = literal_tree.decode(reader) else {
-            return;
+            return Err("Invalid literal/length code".to_owned());
         };
 
         let sym_as_int = sym as usize;
 
         match sym_as_int {
-            0..=255 => buffer.push(sym as u8),
-            256 => return,
+            0..=255 => {
+                if buffer.len() == max_out {
+                    return Err(too_large(max_out));
+                }
+                buffer.push(sym as u8);
+            }
+            256 => return Ok(()),
             257..=285 => {
                 let idx = sym_as_int - 257;
 
@@ -171,19 +300,29 @@ fn inflate_block_data(
                     reader.read_bits(LENGTH_EXTRA_BITS[idx]) + LENGTH_BASE[idx];
 
                 let Some(distance) = distance_tree.decode(reader) else {
-                    panic!("Failed to read backwards distance!");
+                    return Err("Invalid distance code".to_owned());
                 };
 
                 let idx = distance as usize;
+                if idx >= DISTANCE_BASE.len() {
+                    return Err("Invalid distance code".to_owned());
+                }
 
                 let dist = reader.read_bits(DISTANCE_EXTRA_BITS[idx])
                     + DISTANCE_BASE[idx];
 
+                if dist > buffer.len() {
+                    return Err("Invalid distance too far back".to_owned());
+                }
+                if length > max_out - buffer.len() {
+                    return Err(too_large(max_out));
+                }
+
                 for _ in 0..length {
                     buffer.push(buffer[buffer.len() - dist]);
                 }
             }
-            _ => panic!("Invalid decoded value"),
+            _ => return Err("Invalid literal/length code".to_owned()),
         }
     }
 }
@@ -318,7 +457,8 @@ mod tests {
             let mut reader = BitReader::new(compressed);
             let mut buffer: Vec<u8> = vec![];
 
-            inflate_block_no_compression(&mut reader, &mut buffer);
+            inflate_block_no_compression(&mut reader, &mut buffer, usize::MAX)
+                .unwrap();
 
             let s = match std::str::from_utf8(&buffer) {
                 Ok(v) => v,
@@ -364,7 +504,9 @@ mod tests {
             &literal_tree,
             &distance_tree,
             &mut buffer,
-        );
+            usize::MAX,
+        )
+        .unwrap();
 
         assert_eq!(buffer.len(), 0);
     }
@@ -392,7 +534,9 @@ mod tests {
             &literal_tree,
             &distance_tree,
             &mut buffer,
-        );
+            usize::MAX,
+        )
+        .unwrap();
 
         assert_eq!(buffer.len(), 4);
         assert_eq!(buffer, b"ABAC");
@@ -443,10 +587,45 @@ mod tests {
                 &literal_tree,
                 &distance_tree,
                 &mut buffer,
-            );
+                usize::MAX,
+            )
+            .unwrap();
 
             assert_eq!(buffer.len(), exp_len);
             assert_eq!(buffer, exp_seq);
         }
     }
+
+    #[test]
+    fn test_decompress_with_limit() {
+        let compressed = crate::utils::zlib::compress(
+            &[b'a'; 100],
+            &crate::utils::zlib::Strategy::Fixed,
+        );
+        assert_eq!(decompress_with_limit(&compressed, 100).unwrap().len(), 100);
+        assert_eq!(
+            decompress_with_limit(&compressed, 10).unwrap_err(),
+            too_large(10)
+        );
+    }
+
+    #[test]
+    fn test_decompress_corrupt() {
+        // A fixed block that starts with a back reference
+        let too_far_back = b"\x78\x01\x03\x02\x00\x00\x00\x00\x01";
+        assert_eq!(
+            decompress(too_far_back).unwrap_err(),
+            "Invalid distance too far back"
+        );
+
+        // A stored block whose length does not match its complement
+        let bad_length = b"\x78\x01\x01\x05\x00\xfb\xffhello";
+        assert_eq!(
+            decompress(bad_length).unwrap_err(),
+            "Invalid stored block length"
+        );
+
+        // The header alone
+        assert_eq!(decompress(b"\x78\x01").unwrap_err(), TRUNCATED);
+    }
 }
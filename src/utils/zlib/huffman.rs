@@ -17,6 +17,7 @@
 
 use core::cmp::Ordering;
 use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::sync::OnceLock;
 
 use crate::utils::zlib::bitreader::BitReader;
 
@@ -45,6 +46,10 @@ pub static DISTANCE_BASE: [usize; 30] = [
 ];
 
 pub const ZLIB_WINDOW_SIZE: usize = 1 << 15;
+/// The longest code of a literal/length or distance code in DEFLATE.
+pub const MAX_CODE_LENGTH: usize = 15;
+/// The longest code of the code length code in DEFLATE.
+pub const MAX_CODE_LENGTH_CODE_LENGTH: usize = 7;
 pub const ZLIB_MIN_STRING_LENGTH: usize = 3;
 pub const ZLIB_MAX_STRING_LENGTH: usize = 258;
 
@@ -215,6 +220,46 @@ impl HuffmanTree {
         }
     }
 
+    /// Creates a `HuffmanTree` from a frequency map, like
+    /// [`HuffmanTree::from_freq`], with no code longer than `max_len` bits.
+    ///
+    /// Skewed frequencies make long codes, so the frequencies are halved
+    /// until the codes fit. The codes of the tree are assigned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use mini_git::utils::zlib::huffman::HuffmanTree;
+    ///
+    /// // Fibonacci frequencies make the longest codes
+    /// let frequencies = ('a'..='h')
+    ///     .zip([1, 1, 2, 3, 5, 8, 13, 21])
+    ///     .collect::<HashMap<_, _>>();
+    /// let tree = HuffmanTree::from_freq_limited(frequencies.clone(), 7);
+    /// assert_eq!(tree.max_code_len(), 7);
+    /// let tree = HuffmanTree::from_freq_limited(frequencies, 4);
+    /// assert_eq!(tree.max_code_len(), 4);
+    /// ```
+    #[must_use]
+    pub fn from_freq_limited(
+        mut frequencies: HashMap<char, usize>,
+        max_len: usize,
+    ) -> Self {
+        loop {
+            let mut tree = Self::from_freq(frequencies.clone());
+            tree.assign();
+            if tree.max_code_len() <= max_len {
+                return tree;
+            }
+            // Halving evens out the frequencies, while keeping them above
+            // zero, until at worst they are all the same
+            for count in frequencies.values_mut() {
+                *count = count.div_ceil(2);
+            }
+        }
+    }
+
     /// Creates two Huffman trees (literal/length and distance) from LZ77-compressed data.
     ///
     /// # Examples
@@ -284,7 +329,10 @@ impl HuffmanTree {
             },
         );
 
-        (Self::from_freq(sym_freq), Self::from_freq(dist_freq))
+        (
+            Self::from_freq_limited(sym_freq, MAX_CODE_LENGTH),
+            Self::from_freq_limited(dist_freq, MAX_CODE_LENGTH),
+        )
     }
 
     /// Constructs a Huffman tree from a list of bit lengths and an alphabet.
@@ -366,6 +414,28 @@ impl HuffmanTree {
         (literal_tree, distance_tree)
     }
 
+    /// The trees of [`HuffmanTree::get_zlib_fixed`], built once and shared,
+    /// with codes assigned so that they can be used for encoding too.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mini_git::utils::zlib::huffman::HuffmanTree;
+    ///
+    /// let (lit_tree, _) = HuffmanTree::zlib_fixed();
+    /// assert_eq!(lit_tree.encode('\u{100}'), Some((0, 7)));
+    /// ```
+    #[must_use]
+    pub fn zlib_fixed() -> &'static (Self, Self) {
+        static FIXED: OnceLock<(HuffmanTree, HuffmanTree)> = OnceLock::new();
+        FIXED.get_or_init(|| {
+            let (mut literal_tree, mut distance_tree) = Self::get_zlib_fixed();
+            literal_tree.assign();
+            distance_tree.assign();
+            (literal_tree, distance_tree)
+        })
+    }
+
     /// Returns the number of codes in the Huffman tree.
     ///
     /// # Examples
@@ -504,7 +574,7 @@ impl HuffmanTree {
     ///
     /// # Panics
     ///
-    /// If data stream is invalid
+    /// If data stream is invalid, see [`HuffmanTree::try_decode_trees`]
     ///
     /// # Examples
     ///
@@ -517,7 +587,36 @@ impl HuffmanTree {
     /// let mut reader = BitReader::new(&bytes);
     /// let (lit_tree, dist_tree) = HuffmanTree::decode_trees(&mut reader);
     /// ```
+    #[must_use]
     pub fn decode_trees(reader: &mut BitReader) -> (Self, Self) {
+        match Self::try_decode_trees(reader) {
+            Ok(trees) => trees,
+            Err(e) => panic!("{e}"),
+        }
+    }
+
+    /// Decodes two Huffman trees (literal/length and distance) from a
+    /// `BitReader`, like [`HuffmanTree::decode_trees`].
+    ///
+    /// # Errors
+    ///
+    /// If the data stream is invalid, as when it has more codes than the
+    /// alphabets do, or its code lengths run past the number given.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mini_git::utils::zlib::huffman::HuffmanTree;
+    /// use mini_git::utils::zlib::bitreader::BitReader;
+    ///
+    /// // 31 literal/length codes and 32 distance codes is too many
+    /// let bytes = [0xFF, 0x03];
+    /// let mut reader = BitReader::new(&bytes);
+    /// assert!(HuffmanTree::try_decode_trees(&mut reader).is_err());
+    /// ```
+    pub fn try_decode_trees(
+        reader: &mut BitReader,
+    ) -> Result<(Self, Self), String> {
         // The number of Huffman LITeral/length codes
         let hlit = reader.read_bits(5) + 257;
 
@@ -527,6 +626,10 @@ impl HuffmanTree {
         // The number of Huffman Code LENgth codes
         let hclen = reader.read_bits(4) + 4;
 
+        if hlit > 286 || hdist > 30 {
+            return Err("Too many length or distance codes".to_owned());
+        }
+
         // Read code lengths for the code length alphabet
         let code_length_tree_bl = (0..hclen).fold([0; 19], |mut acc, i| {
             acc[CODE_LENGTH_CODES_ORDER[i]] = reader.read_bits(3);
@@ -545,38 +648,35 @@ impl HuffmanTree {
         let maxlen = hlit + hdist;
 
         while bitlen.len() < maxlen {
+            if reader.is_overrun() {
+                return Err("Truncated zlib stream".to_owned());
+            }
+
             let Some(sym) = code_length_tree.decode(reader) else {
-                panic!("Expected sym, found nothing!");
+                return Err("Invalid code length code".to_owned());
             };
 
-            let sym = sym as usize;
-
-            match sym {
-                0..=15 => bitlen.push(sym),
+            let (length, repeat) = match sym as usize {
+                sym @ 0..=15 => (sym, 1),
                 16 => {
                     // Copy the previous code length 3-6 times.
                     // The next 2 bits indicate repeat length
                     // ( 0 -> 3, ..., 3 -> 6 )
-                    let prev_code_length = *bitlen
-                        .last()
-                        .expect("Should have at least one element to repeat");
-                    let repeat_length = reader.read_bits(2) + 3;
-                    bitlen.extend_from_slice(
-                        &[prev_code_length].repeat(repeat_length),
-                    );
-                }
-                17 => {
-                    // Repeat code length 0 for 3-10 times. (3 bits of length)
-                    let repeat_length = reader.read_bits(3) + 3;
-                    bitlen.extend_from_slice(&[0].repeat(repeat_length));
+                    let Some(&prev_code_length) = bitlen.last() else {
+                        return Err("Repeat with no previous length".to_owned());
+                    };
+                    (prev_code_length, reader.read_bits(2) + 3)
                 }
-                18 => {
-                    // Repeat code length 0 for 11-138 times. (7 bits of length)
-                    let repeat_length = reader.read_bits(7) + 11;
-                    bitlen.extend_from_slice(&[0].repeat(repeat_length));
-                }
-                _ => panic!("Invalid Symbol!"),
+                // Repeat code length 0 for 3-10 times. (3 bits of length)
+                17 => (0, reader.read_bits(3) + 3),
+                // Repeat code length 0 for 11-138 times. (7 bits of length)
+                _ => (0, reader.read_bits(7) + 11),
+            };
+
+            if bitlen.len() + repeat > maxlen {
+                return Err("Too many code lengths".to_owned());
             }
+            bitlen.resize(bitlen.len() + repeat, length);
         }
 
         // Construct trees
@@ -588,7 +688,7 @@ impl HuffmanTree {
         let dist_tree =
             Self::from_bitlen_list(&bitlen[hlit..], &distance_tree_alphabet());
 
-        (lit_tree, dist_tree)
+        Ok((lit_tree, dist_tree))
     }
 
     /// Assigns codes to symbols in the Huffman tree.
@@ -629,6 +729,8 @@ impl HuffmanTree {
 
         while let Some((node, code, length)) = queue.pop_front() {
             if let Some(sym) = node.symbol {
+                // A tree of a single symbol still needs a bit to code it
+                let length = length.max(1);
                 let code =
                     ((code as u32).reverse_bits() >> (32 - length)) as usize;
                 map.insert(sym, (code, length));
@@ -1090,20 +1192,18 @@ mod tests {
         for TestData(code, length, n_good_iters) in data {
             let bytes = code_to_bytes(code, length);
 
-            let res = std::panic::catch_unwind(|| {
-                let mut reader = BitReader::new(&bytes);
-
-                // Run through the good iterations
-                for _ in 0..n_good_iters {
-                    let x = tree.decode(&mut reader);
-                    assert!(x.is_some());
-                }
+            let mut reader = BitReader::new(&bytes);
 
-                // This should cause a panic
-                tree.decode(&mut reader);
-            });
+            // Run through the good iterations
+            for _ in 0..n_good_iters {
+                let x = tree.decode(&mut reader);
+                assert!(x.is_some());
+                assert!(!reader.is_overrun());
+            }
 
-            assert!(res.is_err());
+            // This reads past the end
+            tree.decode(&mut reader);
+            assert!(reader.is_overrun());
         }
     }
 
@@ -1169,4 +1269,21 @@ mod tests {
         lz77.max_match_length = 259;
         check_lz77(&lz77);
     }
+
+    #[test]
+    fn test_from_lz77_limits_code_lengths() {
+        let mut lz77 = LZ77Compressor::with_window_size(32768);
+        lz77.min_match_length = 3;
+        lz77.max_match_length = 258;
+
+        // Fibonacci frequencies would make codes as long as the alphabet
+        let (mut a, mut b) = (1, 1);
+        let mut data = vec![];
+        for byte in 0..25 {
+            data.extend((0..a).map(|_| LZ77Unit::Literal(byte)));
+            (a, b) = (b, a + b);
+        }
+        let (ltree, _) = HuffmanTree::from_lz77(&data, &lz77);
+        assert!(ltree.max_code_len() <= MAX_CODE_LENGTH);
+    }
 }
@@ -14,12 +14,32 @@
 //! let compressed = compressor.compress(data);
 //! assert!(compressed.len() < data.len());
 //! ```
+//!
+//! Matches are found through hash chains, which link every position of the
+//! window to the previous position that starts with the same
+//! [`HASH_LENGTH`] bytes, as zlib does. Matching is lazy: a match is held
+//! back while the next position starts a longer one.
 
 const DEFAULT_MIN_STRING_LENGTH: usize = 5;
 const DEFAULT_MAX_STRING_LENGTH: usize = 100;
 const MAX_WINDOW_SIZE: usize = 1 << 15; // 32KB
 const DEFAULT_WINDOW_SIZE: usize = 144;
 
+/// The number of bytes positions are hashed by, so that shorter matches
+/// are not found.
+pub const HASH_LENGTH: usize = 3;
+/// The most bits of a hash, and so the most hash chains.
+const MAX_HASH_BITS: u32 = 15;
+/// The most positions of a hash chain that are tried for a match.
+const MAX_CHAIN_LENGTH: usize = 128;
+/// A match this long is taken without looking for a longer one.
+const NICE_MATCH_LENGTH: usize = 128;
+/// A match this long is taken without trying for a longer match at the
+/// next position.
+const LAZY_MATCH_LENGTH: usize = 16;
+/// The end of a hash chain.
+const NIL: u32 = u32::MAX;
+
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub enum LZ77Unit {
     Literal(u8),
@@ -141,69 +161,208 @@ impl LZ77Compressor {
     /// ```
     #[must_use]
     pub fn compress(&self, data: &[u8]) -> Vec<LZ77Unit> {
+        self.compress_from(data, 0)
+    }
+
+    /// Compresses the input data using the LZ77 algorithm, as if it came
+    /// after `dictionary`, so that it can refer back into the end of the
+    /// dictionary.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mini_git::utils::zlib::lz77::{LZ77Compressor, LZ77Unit};
+    ///
+    /// let compressor = LZ77Compressor::new();
+    /// let compressed =
+    ///     compressor.compress_with_dictionary(b"hello world, ", b"world!");
+    /// assert_eq!(compressed[0], LZ77Unit::Marker(5, 7));
+    /// ```
+    #[must_use]
+    pub fn compress_with_dictionary(
+        &self,
+        dictionary: &[u8],
+        data: &[u8],
+    ) -> Vec<LZ77Unit> {
+        let start = dictionary.len().saturating_sub(self.window_size);
+        let dictionary = &dictionary[start..];
+        let joined = [dictionary, data].concat();
+        self.compress_from(&joined, dictionary.len())
+    }
+
+    /// Compresses `data[start..]`, with matches found from the start of
+    /// `data`.
+    fn compress_from(&self, data: &[u8], start: usize) -> Vec<LZ77Unit> {
         use LZ77Unit::{Literal, Marker};
 
         let mut compressed: Vec<LZ77Unit> = vec![];
-        let window_size = self.window_size;
-
-        let mut pos = 0;
-        let last_pos = if data.len() > self.min_match_length {
-            data.len() - self.min_match_length
-        } else {
-            return data.iter().map(|&byte| Literal(byte)).collect();
-        };
-
-        while pos < last_pos {
-            let mut search_start = if pos > window_size {
-                pos - window_size
-            } else {
-                0
-            };
+        let mut chains = HashChains::new(data.len());
+        for pos in 0..start {
+            chains.insert(data, pos);
+        }
 
-            let mut match_length = self.min_match_length;
+        // The match at the previous position, held back in case this
+        // position starts a longer one
+        let mut pending: Option<(usize, usize)> = None;
+        let mut pos = start;
 
-            let mut found_match = false;
-            let mut best_match_distance = 0;
-            let mut best_match_length = 0;
+        while pos < data.len() {
+            let current = match pending {
+                Some((length, _)) if length >= LAZY_MATCH_LENGTH => None,
+                _ => self.longest_match(data, pos, &chains),
+            };
+            chains.insert(data, pos);
+
+            match (pending, current) {
+                (Some((length, distance)), current)
+                    if current.is_none_or(|(next, _)| next <= length) =>
+                {
+                    // The match at the previous position is at least as
+                    // long, so take it, and skip to its end
+                    compressed.push(Marker(length, distance));
+                    let end = pos - 1 + length;
+                    for skipped in pos + 1..end {
+                        chains.insert(data, skipped);
+                    }
+                    pos = end;
+                    pending = None;
+                }
+                (Some(_), current) => {
+                    compressed.push(Literal(data[pos - 1]));
+                    pending = current;
+                    pos += 1;
+                }
+                (None, current) => {
+                    if current.is_none() {
+                        compressed.push(Literal(data[pos]));
+                    }
+                    pending = current;
+                    pos += 1;
+                }
+            }
+        }
 
-            while search_start + match_length < pos {
-                let end = data.len().min(search_start + match_length);
-                let m1 = &data[search_start..end];
-                let end = data.len().min(pos + match_length);
-                let m2 = &data[pos..end];
+        if let Some((length, distance)) = pending {
+            compressed.push(Marker(length, distance));
+        }
 
-                if m1 == m2 && match_length < self.max_match_length {
-                    match_length += 1;
-                    found_match = true;
-                } else {
-                    let true_match_length = match_length - 1;
+        compressed
+    }
 
-                    if found_match && true_match_length > best_match_length {
-                        best_match_distance = pos - search_start;
-                        best_match_length = true_match_length;
+    /// Finds the longest match for the data at `pos`, as its length and
+    /// distance, if there is one of at least the minimum length.
+    fn longest_match(
+        &self,
+        data: &[u8],
+        pos: usize,
+        chains: &HashChains,
+    ) -> Option<(usize, usize)> {
+        let max_length = self.max_match_length.min(data.len() - pos);
+        if max_length < self.min_match_length.max(HASH_LENGTH) {
+            return None;
+        }
+        let target = &data[pos..pos + max_length];
+        let limit = pos.saturating_sub(self.window_size);
+
+        let (mut best_length, mut best_distance) = (0, 0);
+        let mut candidate = chains.head(data, pos);
+        for _ in 0..MAX_CHAIN_LENGTH {
+            let Some(from) = candidate.filter(|&from| from >= limit) else {
+                break;
+            };
+            // Only a match that agrees at the byte past the best can beat
+            // it, which rules out most candidates cheaply
+            if data[from + best_length] == target[best_length] {
+                // A match may run into the data it matches, when it repeats
+                let length = data[from..]
+                    .iter()
+                    .zip(target)
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if length > best_length {
+                    (best_length, best_distance) = (length, pos - from);
+                    if length >= NICE_MATCH_LENGTH.min(max_length) {
+                        break;
                     }
-
-                    match_length = self.min_match_length;
-                    search_start += 1;
-                    found_match = false;
                 }
             }
+            candidate = chains.previous(from);
+        }
 
-            pos += if best_match_length > 0 {
-                compressed.push(Marker(best_match_length, best_match_distance));
-                best_match_length
-            } else {
-                compressed.push(Literal(data[pos]));
-                1
-            };
+        (best_length >= self.min_match_length)
+            .then_some((best_length, best_distance))
+    }
+}
+
+/// Chains of the positions in the data that start with the same
+/// [`HASH_LENGTH`] bytes, latest first.
+struct HashChains {
+    /// The latest position of each hash
+    head: Vec<u32>,
+    /// The position before each position in the window, by its position
+    /// modulo the window size
+    previous: Vec<u32>,
+    hash_bits: u32,
+}
+
+impl HashChains {
+    /// Creates chains sized for `len` bytes of data.
+    fn new(len: usize) -> Self {
+        let size = len.next_power_of_two().clamp(1 << 8, MAX_WINDOW_SIZE);
+        Self {
+            head: vec![NIL; size],
+            previous: vec![NIL; size],
+            hash_bits: size.trailing_zeros().min(MAX_HASH_BITS),
         }
+    }
 
-        compressed.extend(data[pos..].iter().fold(vec![], |mut acc, &x| {
-            acc.push(Literal(x));
-            acc
-        }));
+    fn hash(&self, data: &[u8], pos: usize) -> usize {
+        let bytes = [data[pos], data[pos + 1], data[pos + 2], 0];
+        let hash = u32::from_le_bytes(bytes).wrapping_mul(0x9E37_79B1);
+        (hash >> (32 - self.hash_bits)) as usize
+    }
 
-        compressed
+    /// Adds `pos` to the chain of its hash.
+    fn insert(&mut self, data: &[u8], pos: usize) {
+        if pos + HASH_LENGTH > data.len() {
+            return;
+        }
+        let hash = self.hash(data, pos);
+        let mask = self.previous.len() - 1;
+        // Positions past 4GiB wrap, but are never followed that far back,
+        // as they are only followed within the window
+        #[allow(clippy::cast_possible_truncation)]
+        let position = pos as u32;
+        self.previous[pos & mask] = self.head[hash];
+        self.head[hash] = position;
+    }
+
+    /// The latest position with the same hash as `pos`, before it.
+    fn head(&self, data: &[u8], pos: usize) -> Option<usize> {
+        if pos + HASH_LENGTH > data.len() {
+            return None;
+        }
+        Self::position(self.head[self.hash(data, pos)], pos)
+    }
+
+    /// The position before `pos` in its chain.
+    fn previous(&self, pos: usize) -> Option<usize> {
+        let mask = self.previous.len() - 1;
+        Self::position(self.previous[pos & mask], pos)
+    }
+
+    /// The position `entry` stands for, if it comes before `pos`.
+    ///
+    /// An entry may have been overwritten by a later position that falls
+    /// on the same slot, which ends the chain.
+    fn position(entry: u32, pos: usize) -> Option<usize> {
+        if entry == NIL {
+            return None;
+        }
+        // Restore the bits lost when the position was stored
+        #[allow(clippy::cast_possible_truncation)]
+        let distance = (pos as u32).wrapping_sub(entry) as usize;
+        (distance > 0 && distance <= pos).then(|| pos - distance)
     }
 }
 
@@ -318,7 +477,8 @@ mod tests {
 
     #[test]
     fn test_compress_with_different_window_sizes() {
-        let input = b"abcdefghijklmnopqrstuvwxyz".repeat(100);
+        // Repeats further apart than the small window
+        let input = (0..=255).collect::<Vec<u8>>().repeat(10);
         let small_window = LZ77Compressor::with_window_size(64);
         let large_window = LZ77Compressor::with_window_size(1024);
 
@@ -343,4 +503,57 @@ mod tests {
         let compressed = compressor.compress(&input);
         assert!(compressed.len() < input.len() / 5);
     }
+
+    /// The data `compressed` stands for.
+    fn expand(compressed: &[LZ77Unit]) -> Vec<u8> {
+        let mut data = vec![];
+        for unit in compressed {
+            match *unit {
+                LZ77Unit::Literal(byte) => data.push(byte),
+                LZ77Unit::Marker(length, distance) => {
+                    for _ in 0..length {
+                        data.push(data[data.len() - distance]);
+                    }
+                }
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn test_compress_round_trip() {
+        let mut compressor = LZ77Compressor::with_window_size(MAX_WINDOW_SIZE);
+        compressor.min_match_length = 3;
+        compressor.max_match_length = 258;
+
+        let text = include_bytes!("lz77.rs");
+        let noise: Vec<u8> = (0..100_000u32)
+            .map(|i| i.wrapping_mul(2_654_435_761).to_be_bytes()[0])
+            .collect();
+        let run = vec![b'a'; 70_000];
+        for input in [&text[..], &noise, &text.repeat(5), &run] {
+            let compressed = compressor.compress(input);
+            assert_eq!(expand(&compressed), input);
+            for unit in &compressed {
+                if let LZ77Unit::Marker(length, distance) = *unit {
+                    assert!((3..=258).contains(&length));
+                    assert!((1..=MAX_WINDOW_SIZE).contains(&distance));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_compress_lazy_match() {
+        let mut compressor = LZ77Compressor::new();
+        compressor.min_match_length = 3;
+
+        // The last "abc" matches, but "bcdefg" after its "a" is longer
+        let compressed = compressor.compress(b"abc_bcdefg_abcdefg");
+        assert_eq!(expand(&compressed), b"abc_bcdefg_abcdefg");
+        assert_eq!(
+            compressed[compressed.len() - 2..],
+            [LZ77Unit::Literal(b'a'), LZ77Unit::Marker(6, 8)]
+        );
+    }
 }
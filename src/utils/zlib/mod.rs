@@ -4,9 +4,15 @@ pub mod adler;
 pub mod bitreader;
 pub mod bitwriter;
 pub mod compress;
+pub mod container;
+pub mod crc32;
 pub mod decompress;
 pub mod huffman;
 pub mod lz77;
+pub mod parallel;
+pub mod stream;
 
 pub use compress::*;
 pub use decompress::*;
+pub use parallel::compress_parallel;
+pub use stream::{Deflater, Inflater, ZlibDecoder, ZlibEncoder};
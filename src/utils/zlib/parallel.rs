@@ -0,0 +1,142 @@
+//! Compression of large inputs on a thread pool
+//!
+//! A large input is split into blocks that are compressed on the workers of
+//! a [`ThreadPool`], each with the 32KiB of input before it as a preset
+//! dictionary, like pigz does, so that the result is about as small as
+//! compressing the input at once. Every block but the last ends on a byte
+//! boundary, so the blocks are joined into a single zlib stream by
+//! concatenating them.
+
+use crate::utils::threadpool::ThreadPool;
+use crate::utils::zlib::adler::adler32;
+use crate::utils::zlib::bitwriter::BitWriter;
+use crate::utils::zlib::compress::{
+    align_to_byte, compress, deflate_with_dictionary, Strategy,
+};
+use crate::utils::zlib::container::ZLIB_HEADER;
+use crate::utils::zlib::huffman::ZLIB_WINDOW_SIZE;
+
+use std::sync::Arc;
+
+/// How much input each worker compresses. Inputs of at most one block are
+/// compressed at once, without the pool.
+pub const PARALLEL_BLOCK_SIZE: usize = 1 << 17;
+
+/// Compresses `data` with `strategy` into a zlib stream, compressing blocks
+/// of it in parallel on `pool`.
+///
+/// # Examples
+///
+/// ```
+/// use mini_git::utils::threadpool::ThreadPool;
+/// use mini_git::utils::zlib::{compress_parallel, decompress, Strategy};
+///
+/// let data = b"a line of a large file\n".repeat(100);
+/// let compressed =
+///     compress_parallel(&data, &Strategy::Fixed, ThreadPool::shared());
+/// assert_eq!(decompress(&compressed).unwrap(), data);
+/// ```
+#[must_use]
+pub fn compress_parallel(
+    data: &[u8],
+    strategy: &Strategy,
+    pool: &ThreadPool,
+) -> Vec<u8> {
+    compress_in_blocks(data, *strategy, pool, PARALLEL_BLOCK_SIZE)
+}
+
+fn compress_in_blocks(
+    data: &[u8],
+    strategy: Strategy,
+    pool: &ThreadPool,
+    block_size: usize,
+) -> Vec<u8> {
+    if data.len() <= block_size || pool.size() < 2 {
+        return compress(data, &strategy);
+    }
+
+    let shared: Arc<[u8]> = Arc::from(data);
+    let blocks = (0..data.len()).step_by(block_size).collect::<Vec<_>>();
+    let input = Arc::clone(&shared);
+    let compressed = pool.map(blocks.clone(), move |start| {
+        compress_block(&input, start, block_size, strategy)
+    });
+
+    let mut out = ZLIB_HEADER.to_vec();
+    for (block, &start) in compressed.zip(&blocks) {
+        // A block whose worker panicked is compressed here instead
+        let block = block.unwrap_or_else(|_| {
+            compress_block(&shared, start, block_size, strategy)
+        });
+        out.extend(block);
+    }
+    out.extend(adler32(data).to_be_bytes());
+    out
+}
+
+/// Compresses the block of `data` at `start`, ending the stream if it is
+/// the last block.
+fn compress_block(
+    data: &[u8],
+    start: usize,
+    block_size: usize,
+    strategy: Strategy,
+) -> Vec<u8> {
+    let end = data.len().min(start + block_size);
+    let dictionary = &data[start.saturating_sub(ZLIB_WINDOW_SIZE)..start];
+    let last = end == data.len();
+
+    let mut writer = BitWriter::new();
+    deflate_with_dictionary(
+        &mut writer,
+        dictionary,
+        &data[start..end],
+        strategy,
+        last,
+    );
+    if !last {
+        align_to_byte(&mut writer);
+    }
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::zlib::decompress;
+
+    fn sample(len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|i| b"pack objects in parallel, "[(i * 5) % 26])
+            .collect()
+    }
+
+    #[test]
+    fn test_compress_in_blocks() {
+        let pool = ThreadPool::new(4);
+        let strategies = [
+            Strategy::Auto,
+            Strategy::Fixed,
+            Strategy::Dynamic,
+            Strategy::Raw,
+        ];
+        for strategy in strategies {
+            for len in [0, 999, 1000, 1001, 4500] {
+                let data = sample(len);
+                let compressed =
+                    compress_in_blocks(&data, strategy, &pool, 1000);
+                assert_eq!(decompress(&compressed).unwrap(), data);
+            }
+        }
+    }
+
+    #[test]
+    fn test_blocks_refer_back() {
+        // Repeated data compresses across the block boundaries
+        let data = b"0123456789abcdef".repeat(500);
+        let pool = ThreadPool::new(4);
+        let parallel = compress_in_blocks(&data, Strategy::Fixed, &pool, 2000);
+        let single = compress(&data, &Strategy::Fixed);
+        assert!(parallel.len() < single.len() * 2);
+    }
+}
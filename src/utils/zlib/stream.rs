@@ -0,0 +1,1039 @@
+//! This module provides streaming zlib compression and decompression, for
+//! data too large to hold in memory at once.
+//!
+//! An [`Inflater`] decompresses any zlib stream read from a reader, keeping
+//! only the last 32KiB of output that DEFLATE may refer back to. A
+//! [`ZlibEncoder`] compresses data written to it into a zlib stream, a chunk
+//! at a time, and a [`Deflater`] writes a zlib stream of uncompressed
+//! (stored) blocks, like [`Strategy::Raw`] does, for when speed matters more
+//! than size.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::io::{Read, Write};
+//! use mini_git::utils::zlib::{Deflater, Inflater};
+//!
+//! let mut deflater = Deflater::new(Vec::new());
+//! deflater.write_all(b"streamed")?;
+//! let compressed = deflater.finish()?;
+//!
+//! let mut inflated = String::new();
+//! Inflater::new(compressed.as_slice()).read_to_string(&mut inflated)?;
+//! assert_eq!(inflated, "streamed");
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::io::{self, Read, Write};
+
+use crate::utils::zlib::adler::{adler32, Adler32};
+use crate::utils::zlib::bitreader::BitReader;
+use crate::utils::zlib::bitwriter::BitWriter;
+use crate::utils::zlib::compress::{align_to_byte, deflate, Strategy};
+use crate::utils::zlib::container::{
+    Checksum, Container, FDICT, GZIP_MAGIC, ZLIB_HEADER,
+};
+use crate::utils::zlib::huffman::{
+    HuffmanTree, DISTANCE_BASE, DISTANCE_EXTRA_BITS, LENGTH_BASE,
+    LENGTH_EXTRA_BITS, ZLIB_WINDOW_SIZE,
+};
+
+/// The largest length of a stored block.
+const MAX_STORED_LEN: usize = u16::MAX as usize;
+/// The most output decoded ahead of what is read.
+const OUTPUT_CHUNK: usize = 1 << 15;
+/// How much input an [`Inflater`] reads ahead at once.
+const INPUT_CHUNK: usize = 1 << 13;
+/// The least input buffered before each step of an [`Inflater`], unless the
+/// input ends first. This is more than the largest dynamic block header, so
+/// that a step never runs out of input part way through.
+const LOOKAHEAD: usize = 1 << 10;
+/// The most bits a symbol and its extra bits take, a length code and a
+/// distance code of 15 bits, with 5 and 13 extra bits.
+const MAX_SYMBOL_BITS: usize = 48;
+/// How much a [`ZlibEncoder`] compresses at once.
+const ENCODER_CHUNK: usize = 1 << 16;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn truncated() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "Truncated zlib stream")
+}
+
+/// Where an [`Inflater`] is in the stream.
+#[derive(Debug)]
+enum State {
+    Header,
+    BlockHeader,
+    Stored {
+        remaining: usize,
+    },
+    Fixed,
+    Codes {
+        literals: HuffmanTree,
+        distances: HuffmanTree,
+    },
+    Trailer,
+    Done,
+}
+
+/// The output of an [`Inflater`], and the window of the last 32KiB of it
+/// that back references are copied from.
+#[derive(Debug)]
+struct Window {
+    data: Box<[u8]>,
+    pos: usize,
+    /// The number of bytes in the window that back references may reach.
+    history: usize,
+    /// Output that has not been read yet.
+    out: Vec<u8>,
+}
+
+impl Window {
+    fn new() -> Self {
+        Self {
+            data: vec![0; ZLIB_WINDOW_SIZE].into_boxed_slice(),
+            pos: 0,
+            history: 0,
+            out: Vec::with_capacity(OUTPUT_CHUNK + 258),
+        }
+    }
+
+    fn push(&mut self, byte: u8) {
+        self.out.push(byte);
+        self.data[self.pos] = byte;
+        self.pos = (self.pos + 1) % ZLIB_WINDOW_SIZE;
+        self.history = (self.history + 1).min(ZLIB_WINDOW_SIZE);
+    }
+
+    fn extend(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.push(byte);
+        }
+    }
+
+    /// Copies `length` bytes from `distance` bytes back.
+    fn copy(&mut self, distance: usize, length: usize) -> io::Result<()> {
+        if distance > self.history {
+            return Err(invalid("Invalid distance too far back"));
+        }
+        for _ in 0..length {
+            let from =
+                (self.pos + ZLIB_WINDOW_SIZE - distance) % ZLIB_WINDOW_SIZE;
+            self.push(self.data[from]);
+        }
+        Ok(())
+    }
+}
+
+/// Decompresses a zlib stream read from a reader, or a stream in another
+/// [`Container`].
+///
+/// The stream is decoded with the same [`BitReader`] and [`HuffmanTree`]s
+/// as [`decompress`](super::decompress), over a buffer of the input read
+/// ahead. The reader may be read past the end of the stream.
+#[derive(Debug)]
+pub struct Inflater<R: Read> {
+    input: R,
+    /// Input read but not consumed yet, after the bits already read.
+    in_buf: Vec<u8>,
+    /// The position in `in_buf` of the next bit to read.
+    in_bit: usize,
+    /// Whether the input has ended.
+    in_eof: bool,
+    /// The number of bytes consumed and dropped from `in_buf`.
+    in_dropped: u64,
+    window: Window,
+    out_pos: usize,
+    last_block: bool,
+    state: State,
+    container: Container,
+    checksum: Checksum,
+    /// The Adler-32 of the preset dictionary given, if any
+    dictionary_id: Option<u32>,
+    total_out: u64,
+    limit: Option<u64>,
+}
+
+impl<R: Read> Inflater<R> {
+    /// Creates an inflater reading the zlib stream from `input`.
+    pub fn new(input: R) -> Self {
+        Self::with_container(input, Container::Zlib)
+    }
+
+    /// Creates an inflater reading a stream framed in `container` from
+    /// `input`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::Read;
+    /// use mini_git::utils::zlib::container::Container;
+    /// use mini_git::utils::zlib::{compress_as, Inflater, Strategy};
+    ///
+    /// let gzip = compress_as(b"gzipped", &Strategy::Fixed, Container::Gzip);
+    /// let mut inflated = String::new();
+    /// Inflater::with_container(gzip.as_slice(), Container::Gzip)
+    ///     .read_to_string(&mut inflated)?;
+    /// assert_eq!(inflated, "gzipped");
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn with_container(input: R, container: Container) -> Self {
+        Self {
+            input,
+            in_buf: Vec::with_capacity(INPUT_CHUNK),
+            in_bit: 0,
+            in_eof: false,
+            in_dropped: 0,
+            window: Window::new(),
+            out_pos: 0,
+            last_block: false,
+            state: State::Header,
+            container,
+            checksum: Checksum::new(container),
+            dictionary_id: None,
+            total_out: 0,
+            limit: None,
+        }
+    }
+
+    /// Creates an inflater reading a zlib stream compressed with the preset
+    /// `dictionary` from `input`.
+    ///
+    /// Streams without a preset dictionary are read too, while a stream
+    /// that names another dictionary is an error.
+    pub fn with_dictionary(input: R, dictionary: &[u8]) -> Self {
+        let mut inflater = Self::new(input);
+        let tail =
+            &dictionary[dictionary.len().saturating_sub(ZLIB_WINDOW_SIZE)..];
+        let window = &mut inflater.window;
+        window.data[..tail.len()].copy_from_slice(tail);
+        window.pos = tail.len() % ZLIB_WINDOW_SIZE;
+        window.history = tail.len();
+        inflater.dictionary_id = Some(adler32(dictionary));
+        inflater
+    }
+
+    /// Makes reading fail instead of inflating to more than `max_out`
+    /// bytes.
+    ///
+    /// This bounds the memory used on data whose size is known beforehand,
+    /// so that corrupt data can not make it grow without end.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::Read;
+    /// use mini_git::utils::zlib::{compress, Inflater, Strategy};
+    ///
+    /// let compressed = compress(&[0; 1000], &Strategy::Fixed);
+    /// let mut inflated = vec![];
+    /// let result = Inflater::new(compressed.as_slice())
+    ///     .with_limit(999)
+    ///     .read_to_end(&mut inflated);
+    /// assert!(result.is_err());
+    /// ```
+    #[must_use]
+    pub fn with_limit(mut self, max_out: u64) -> Self {
+        self.set_limit(Some(max_out));
+        self
+    }
+
+    /// Sets or clears the limit of [`Inflater::with_limit`].
+    pub fn set_limit(&mut self, max_out: Option<u64>) {
+        self.limit = max_out;
+    }
+
+    /// Starts inflating a new stream, in the same container, from `input`,
+    /// and returns the previous input.
+    ///
+    /// The buffers of the inflater are kept, which saves allocating them
+    /// for each of many small streams, like the objects of a packfile. The
+    /// limit and preset dictionary are cleared.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::Read;
+    /// use mini_git::utils::zlib::{compress, Inflater, Strategy};
+    ///
+    /// let first = compress(b"first", &Strategy::Fixed);
+    /// let second = compress(b"second", &Strategy::Fixed);
+    ///
+    /// let mut inflater = Inflater::new(first.as_slice());
+    /// let mut inflated = String::new();
+    /// inflater.read_to_string(&mut inflated)?;
+    ///
+    /// inflater.reset(second.as_slice());
+    /// inflater.read_to_string(&mut inflated)?;
+    /// assert_eq!(inflated, "firstsecond");
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn reset(&mut self, input: R) -> R {
+        self.in_buf.clear();
+        self.in_bit = 0;
+        self.in_eof = false;
+        self.in_dropped = 0;
+        self.window.pos = 0;
+        self.window.history = 0;
+        self.window.out.clear();
+        self.out_pos = 0;
+        self.last_block = false;
+        self.state = State::Header;
+        self.checksum = Checksum::new(self.container);
+        self.dictionary_id = None;
+        self.total_out = 0;
+        self.limit = None;
+        std::mem::replace(&mut self.input, input)
+    }
+
+    /// Returns the number of bytes of the zlib stream consumed so far.
+    ///
+    /// Once the stream has been read to its end, this is the length of the
+    /// stream, even if the input was read past it.
+    #[must_use]
+    pub fn total_in(&self) -> u64 {
+        self.in_dropped + self.in_bit.div_ceil(8) as u64
+    }
+
+    /// Reads ahead from the input, up to [`INPUT_CHUNK`] bytes, unless
+    /// [`LOOKAHEAD`] bytes are buffered already, dropping the bytes consumed
+    /// before.
+    fn fill_input(&mut self) -> io::Result<()> {
+        let consumed = self.in_bit / 8;
+        if self.in_eof || self.in_buf.len() - consumed >= LOOKAHEAD {
+            return Ok(());
+        }
+
+        self.in_buf.drain(..consumed);
+        self.in_dropped += consumed as u64;
+        self.in_bit %= 8;
+        let mut len = self.in_buf.len();
+        self.in_buf.resize(INPUT_CHUNK.max(len), 0);
+        while !self.in_eof && len < LOOKAHEAD {
+            match self.input.read(&mut self.in_buf[len..]) {
+                Ok(0) => self.in_eof = true,
+                Ok(read) => len += read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    self.in_buf.truncate(len);
+                    return Err(e);
+                }
+            }
+        }
+        self.in_buf.truncate(len);
+        Ok(())
+    }
+
+    /// Reads the next byte, where the input is at a byte boundary.
+    fn next_byte(&mut self) -> io::Result<u8> {
+        self.fill_input()?;
+        let byte = *self.in_buf.get(self.in_bit / 8).ok_or_else(truncated)?;
+        self.in_bit += 8;
+        Ok(byte)
+    }
+
+    fn read_header(&mut self) -> io::Result<()> {
+        match self.container {
+            Container::Zlib => self.read_zlib_header(),
+            Container::Gzip => self.read_gzip_header(),
+            Container::Raw => Ok(()),
+        }
+    }
+
+    fn read_zlib_header(&mut self) -> io::Result<()> {
+        let cmf = self.next_byte()?;
+        let flags = self.next_byte()?;
+        if cmf & 0x0F != 8 {
+            return Err(invalid("Unsupported compression method"));
+        }
+        if cmf >> 4 > 7 {
+            return Err(invalid("Invalid compression info"));
+        }
+        if (u16::from(cmf) * 256 + u16::from(flags)) % 31 != 0 {
+            return Err(invalid("CMF + FLAGS checksum failed!"));
+        }
+        if flags & FDICT != 0 {
+            let mut id = [0u8; 4];
+            for byte in &mut id {
+                *byte = self.next_byte()?;
+            }
+            match self.dictionary_id {
+                None => return Err(invalid("A preset dictionary is needed")),
+                Some(dictionary_id)
+                    if dictionary_id != u32::from_be_bytes(id) =>
+                {
+                    return Err(invalid("Wrong preset dictionary"));
+                }
+                Some(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a gzip member header, skipping the optional fields it has.
+    fn read_gzip_header(&mut self) -> io::Result<()> {
+        const FHCRC: u8 = 0x02;
+        const FEXTRA: u8 = 0x04;
+        const FNAME: u8 = 0x08;
+        const FCOMMENT: u8 = 0x10;
+        const RESERVED: u8 = 0xE0;
+
+        if [self.next_byte()?, self.next_byte()?] != GZIP_MAGIC {
+            return Err(invalid("Not a gzip stream"));
+        }
+        if self.next_byte()? != 8 {
+            return Err(invalid("Unsupported compression method"));
+        }
+        let flags = self.next_byte()?;
+        if flags & RESERVED != 0 {
+            return Err(invalid("Reserved gzip flags are set"));
+        }
+        // The modification time, extra flags and operating system
+        for _ in 0..6 {
+            self.next_byte()?;
+        }
+        if flags & FEXTRA != 0 {
+            let len =
+                u16::from_le_bytes([self.next_byte()?, self.next_byte()?]);
+            for _ in 0..len {
+                self.next_byte()?;
+            }
+        }
+        // The file name and comment end with a NUL
+        for flag in [FNAME, FCOMMENT] {
+            if flags & flag != 0 {
+                while self.next_byte()? != 0 {}
+            }
+        }
+        if flags & FHCRC != 0 {
+            self.next_byte()?;
+            self.next_byte()?;
+        }
+        Ok(())
+    }
+
+    /// Decodes what the buffered input holds of a block, with a
+    /// [`BitReader`] over it, and returns the state to go on from.
+    fn inflate_block(&mut self, state: State) -> io::Result<State> {
+        let start = self.in_bit / 8;
+        let available = (self.in_buf.len() - start) * 8;
+        let mut reader = BitReader::new(&self.in_buf[start..]);
+        reader.read_bits(self.in_bit % 8);
+
+        let next = match state {
+            State::BlockHeader => {
+                self.last_block = reader.read_bit() == 1;
+                match reader.read_bits(2) {
+                    0 => {
+                        let len = reader.read_bytes(2);
+                        let nlen = reader.read_bytes(2);
+                        if !reader.is_overrun() && len != !nlen & 0xFFFF {
+                            return Err(invalid("Invalid stored block length"));
+                        }
+                        Ok(State::Stored { remaining: len })
+                    }
+                    1 => Ok(State::Fixed),
+                    2 => match HuffmanTree::try_decode_trees(&mut reader) {
+                        Ok((literals, distances)) => Ok(State::Codes {
+                            literals,
+                            distances,
+                        }),
+                        Err(_) if reader.is_overrun() => Err(truncated()),
+                        Err(e) => Err(invalid(&e)),
+                    },
+                    _ => Err(invalid("Invalid block type")),
+                }
+            }
+            State::Stored { remaining } => {
+                reader.align_to_byte();
+                let len = remaining
+                    .min((available - reader.bits_read()) / 8)
+                    .min(OUTPUT_CHUNK - self.window.out.len());
+                if len == 0 && remaining > 0 {
+                    return Err(truncated());
+                }
+                let bytes = reader.read_aligned(len).ok_or_else(truncated)?;
+                self.window.extend(bytes);
+                Ok(if len < remaining {
+                    State::Stored {
+                        remaining: remaining - len,
+                    }
+                } else {
+                    self.end_of_block()
+                })
+            }
+            State::Fixed => {
+                let (literals, distances) = HuffmanTree::zlib_fixed();
+                let ended = inflate_codes(
+                    &mut reader,
+                    literals,
+                    distances,
+                    &mut self.window,
+                    (!self.in_eof).then_some(available),
+                )?;
+                Ok(if ended {
+                    self.end_of_block()
+                } else {
+                    State::Fixed
+                })
+            }
+            State::Codes {
+                literals,
+                distances,
+            } => {
+                let ended = inflate_codes(
+                    &mut reader,
+                    &literals,
+                    &distances,
+                    &mut self.window,
+                    (!self.in_eof).then_some(available),
+                )?;
+                Ok(if ended {
+                    self.end_of_block()
+                } else {
+                    State::Codes {
+                        literals,
+                        distances,
+                    }
+                })
+            }
+            state => Ok(state),
+        };
+
+        if reader.is_overrun() {
+            return Err(truncated());
+        }
+        self.in_bit = start * 8 + reader.bits_read();
+        next
+    }
+
+    /// Decodes the next chunk of output.
+    fn fill(&mut self) -> io::Result<()> {
+        self.window.out.clear();
+        self.out_pos = 0;
+
+        while self.window.out.len() < OUTPUT_CHUNK {
+            match std::mem::replace(&mut self.state, State::Done) {
+                State::Header => {
+                    self.read_header()?;
+                    self.state = State::BlockHeader;
+                }
+                State::Trailer => {
+                    self.checksum.update(&self.window.out);
+                    self.in_bit = self.in_bit.div_ceil(8) * 8;
+                    let expected = self.checksum.trailer();
+                    let mut trailer = vec![0u8; expected.len()];
+                    for byte in &mut trailer {
+                        *byte = self.next_byte()?;
+                    }
+                    if trailer != expected {
+                        return Err(invalid("Checksum is invalid"));
+                    }
+                    return Ok(());
+                }
+                State::Done => return Ok(()),
+                state => {
+                    self.fill_input()?;
+                    self.state = self.inflate_block(state)?;
+                }
+            }
+        }
+
+        self.checksum.update(&self.window.out);
+        Ok(())
+    }
+
+    fn end_of_block(&self) -> State {
+        if self.last_block {
+            State::Trailer
+        } else {
+            State::BlockHeader
+        }
+    }
+}
+
+/// Decodes symbols until the output is full or the block ends, or, if the
+/// input goes on past the `available` bits buffered, until the next symbol
+/// may not be buffered whole.
+///
+/// Returns whether the block ended.
+fn inflate_codes(
+    reader: &mut BitReader,
+    literals: &HuffmanTree,
+    distances: &HuffmanTree,
+    window: &mut Window,
+    available: Option<usize>,
+) -> io::Result<bool> {
+    while window.out.len() < OUTPUT_CHUNK
+        && available
+            .is_none_or(|bits| reader.bits_read() + MAX_SYMBOL_BITS <= bits)
+    {
+        // Past the end, the reader gives zeros, which may decode forever
+        if reader.is_overrun() {
+            return Err(truncated());
+        }
+
+        let symbol = literals
+            .decode(reader)
+            .ok_or_else(|| invalid("Invalid literal/length code"))?
+            as usize;
+        match symbol {
+            #[allow(clippy::cast_possible_truncation)]
+            0..=255 => window.push(symbol as u8),
+            256 => return Ok(true),
+            257..=285 => {
+                let idx = symbol - 257;
+                let length =
+                    reader.read_bits(LENGTH_EXTRA_BITS[idx]) + LENGTH_BASE[idx];
+
+                let idx = distances
+                    .decode(reader)
+                    .map(|code| code as usize)
+                    .filter(|&idx| idx < DISTANCE_BASE.len())
+                    .ok_or_else(|| invalid("Invalid distance code"))?;
+                let distance = reader.read_bits(DISTANCE_EXTRA_BITS[idx])
+                    + DISTANCE_BASE[idx];
+                window.copy(distance, length)?;
+            }
+            _ => return Err(invalid("Invalid literal/length code")),
+        }
+    }
+    Ok(false)
+}
+
+impl<R: Read> Read for Inflater<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.out_pos == self.window.out.len() {
+            if matches!(self.state, State::Done) {
+                return Ok(0);
+            }
+            self.fill()?;
+            self.total_out += self.window.out.len() as u64;
+            if let Some(limit) = self.limit.filter(|&l| self.total_out > l) {
+                return Err(invalid(&format!(
+                    "Decompressed data exceeds the limit of {limit} bytes"
+                )));
+            }
+        }
+        let out = &self.window.out;
+        let len = buf.len().min(out.len() - self.out_pos);
+        buf[..len].copy_from_slice(&out[self.out_pos..self.out_pos + len]);
+        self.out_pos += len;
+        Ok(len)
+    }
+}
+
+/// Compresses data written to it into a zlib stream written to a writer.
+///
+/// The data is stored in uncompressed blocks, so that it can be written
+/// without holding more than a block in memory. [`Deflater::finish`] must
+/// be called to end the stream.
+#[derive(Debug)]
+pub struct Deflater<W: Write> {
+    output: W,
+    block: Vec<u8>,
+    started: bool,
+    adler: Adler32,
+}
+
+impl<W: Write> Deflater<W> {
+    /// Creates a deflater writing the zlib stream to `output`.
+    pub fn new(output: W) -> Self {
+        Self {
+            output,
+            block: Vec::with_capacity(MAX_STORED_LEN),
+            started: false,
+            adler: Adler32::new(),
+        }
+    }
+
+    fn write_block(&mut self, last: bool) -> io::Result<()> {
+        if !self.started {
+            self.output.write_all(&ZLIB_HEADER)?;
+            self.started = true;
+        }
+        // Stored blocks are byte aligned, so the block header is a byte
+        #[allow(clippy::cast_possible_truncation)]
+        let len = self.block.len() as u16;
+        self.output.write_all(&[u8::from(last)])?;
+        self.output.write_all(&len.to_le_bytes())?;
+        self.output.write_all(&(!len).to_le_bytes())?;
+        self.output.write_all(&self.block)?;
+        self.adler.update(&self.block);
+        self.block.clear();
+        Ok(())
+    }
+
+    /// Ends the stream, and returns the writer.
+    ///
+    /// # Errors
+    ///
+    /// If writing to the writer fails.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_block(true)?;
+        self.output.write_all(&self.adler.finish().to_be_bytes())?;
+        self.output.flush()?;
+        Ok(self.output)
+    }
+}
+
+impl<W: Write> Write for Deflater<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.block.len() == MAX_STORED_LEN {
+            self.write_block(false)?;
+        }
+        let len = buf.len().min(MAX_STORED_LEN - self.block.len());
+        self.block.extend_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+/// Decompresses a zlib stream read from a reader, the counterpart of
+/// [`ZlibEncoder`].
+pub type ZlibDecoder<R> = Inflater<R>;
+
+/// Compresses data written to it into a zlib stream written to a writer, or
+/// a stream in another [`Container`].
+///
+/// The data is compressed with the [`Strategy`] given a chunk at a time, so
+/// that no more than a chunk is held in memory. Each chunk is compressed on
+/// its own, and ends with an empty stored block that aligns the output to a
+/// byte. [`ZlibEncoder::finish`] must be called to end the stream.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::{Read, Write};
+/// use mini_git::utils::zlib::{Strategy, ZlibDecoder, ZlibEncoder};
+///
+/// let data = b"a line that repeats\n".repeat(100);
+/// let mut encoder = ZlibEncoder::new(Vec::new(), Strategy::Auto);
+/// encoder.write_all(&data)?;
+/// let compressed = encoder.finish()?;
+/// assert!(compressed.len() < data.len());
+///
+/// let mut decoded = vec![];
+/// ZlibDecoder::new(compressed.as_slice()).read_to_end(&mut decoded)?;
+/// assert_eq!(decoded, data);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct ZlibEncoder<W: Write> {
+    output: W,
+    strategy: Strategy,
+    container: Container,
+    chunk: Vec<u8>,
+    started: bool,
+    checksum: Checksum,
+}
+
+impl<W: Write> ZlibEncoder<W> {
+    /// Creates an encoder writing the zlib stream to `output`, compressed
+    /// with `strategy`.
+    pub fn new(output: W, strategy: Strategy) -> Self {
+        Self::with_container(output, strategy, Container::Zlib)
+    }
+
+    /// Creates an encoder writing a stream framed in `container` to
+    /// `output`, compressed with `strategy`.
+    pub fn with_container(
+        output: W,
+        strategy: Strategy,
+        container: Container,
+    ) -> Self {
+        Self {
+            output,
+            strategy,
+            container,
+            chunk: Vec::with_capacity(ENCODER_CHUNK),
+            started: false,
+            checksum: Checksum::new(container),
+        }
+    }
+
+    /// Compresses the chunk and writes it out, ending the stream if `last`.
+    fn write_chunk(&mut self, last: bool) -> io::Result<()> {
+        let mut writer = BitWriter::new();
+        if !self.started {
+            writer.write_bytes(self.container.header());
+            self.started = true;
+        }
+        deflate(&mut writer, &self.chunk, self.strategy, last);
+        if !last {
+            align_to_byte(&mut writer);
+        }
+        self.output.write_all(&writer.finish())?;
+        self.checksum.update(&self.chunk);
+        self.chunk.clear();
+        Ok(())
+    }
+
+    /// Ends the stream, and returns the writer.
+    ///
+    /// # Errors
+    ///
+    /// If writing to the writer fails.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.end()?;
+        Ok(self.output)
+    }
+
+    /// Ends the stream, and starts a new one, with the same strategy and
+    /// container, written to `output`. Returns the previous writer.
+    ///
+    /// The buffers of the encoder are kept, which saves allocating them for
+    /// each of many small streams.
+    ///
+    /// # Errors
+    ///
+    /// If writing to the previous writer fails.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::Write;
+    /// use mini_git::utils::zlib::{decompress, Strategy, ZlibEncoder};
+    ///
+    /// let mut encoder = ZlibEncoder::new(Vec::new(), Strategy::Fixed);
+    /// encoder.write_all(b"first")?;
+    /// let first = encoder.reset(Vec::new())?;
+    /// encoder.write_all(b"second")?;
+    /// let second = encoder.finish()?;
+    ///
+    /// assert_eq!(decompress(&first).unwrap(), b"first");
+    /// assert_eq!(decompress(&second).unwrap(), b"second");
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn reset(&mut self, output: W) -> io::Result<W> {
+        self.end()?;
+        self.started = false;
+        self.checksum = Checksum::new(self.container);
+        Ok(std::mem::replace(&mut self.output, output))
+    }
+
+    /// Writes the last chunk and the trailer.
+    fn end(&mut self) -> io::Result<()> {
+        self.write_chunk(true)?;
+        self.output.write_all(&self.checksum.trailer())?;
+        self.output.flush()
+    }
+}
+
+impl<W: Write> Write for ZlibEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.chunk.len() == ENCODER_CHUNK {
+            self.write_chunk(false)?;
+        }
+        let len = buf.len().min(ENCODER_CHUNK - self.chunk.len());
+        self.chunk.extend_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::zlib::{compress, decompress, Strategy};
+
+    fn inflate(data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut out = vec![];
+        Inflater::new(data).read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    fn sample(len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|i| {
+                b"the quick brown fox jumps over the lazy dog"[(i * 7) % 43]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_inflate_matches_decompress() {
+        let cases = [
+            (Strategy::Auto, [1, 100, 1000, 70_000].as_slice()),
+            (Strategy::Fixed, &[1, 100, 1000, 70_000]),
+            (Strategy::Dynamic, &[1000, 70_000]),
+            (Strategy::Raw, &[1, 100]),
+        ];
+        for (strategy, lens) in cases {
+            for &len in lens {
+                let data = sample(len);
+                let compressed = compress(&data, &strategy);
+                assert_eq!(inflate(&compressed).unwrap(), data);
+            }
+        }
+    }
+
+    #[test]
+    fn test_inflate_small_reads() {
+        /// A reader that gives one byte at a time.
+        struct Trickle<'a>(&'a [u8]);
+
+        impl Read for Trickle<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                let len = buf.len().min(self.0.len()).min(1);
+                buf[..len].copy_from_slice(&self.0[..len]);
+                self.0 = &self.0[len..];
+                Ok(len)
+            }
+        }
+
+        for strategy in [Strategy::Dynamic, Strategy::Fixed, Strategy::Raw] {
+            let data = sample(70_000);
+            let compressed = compress(&data, &strategy);
+            let mut output = vec![];
+            let mut inflater = Inflater::new(Trickle(&compressed));
+            inflater.read_to_end(&mut output).unwrap();
+            assert_eq!(output, data);
+            assert_eq!(inflater.total_in(), compressed.len() as u64);
+        }
+    }
+
+    #[test]
+    fn test_deflate_round_trip() {
+        for len in [0, 10, MAX_STORED_LEN, MAX_STORED_LEN + 1, 200_000] {
+            let data = sample(len);
+            let mut deflater = Deflater::new(vec![]);
+            deflater.write_all(&data).unwrap();
+            let compressed = deflater.finish().unwrap();
+
+            assert_eq!(decompress(&compressed).unwrap(), data);
+            assert_eq!(inflate(&compressed).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_encoder_round_trip() {
+        let strategies = [
+            Strategy::Auto,
+            Strategy::Fixed,
+            Strategy::Dynamic,
+            Strategy::Raw,
+        ];
+        for strategy in strategies {
+            for len in [0, 10, 300, ENCODER_CHUNK + 7] {
+                let data = sample(len);
+                let mut encoder = ZlibEncoder::new(vec![], strategy);
+                encoder.write_all(&data).unwrap();
+                let compressed = encoder.finish().unwrap();
+
+                assert_eq!(decompress(&compressed).unwrap(), data);
+                assert_eq!(inflate(&compressed).unwrap(), data);
+            }
+        }
+    }
+
+    #[test]
+    fn test_containers_round_trip() {
+        for container in [Container::Zlib, Container::Gzip, Container::Raw] {
+            for len in [0, 300, ENCODER_CHUNK + 7] {
+                let data = sample(len);
+                let mut encoder = ZlibEncoder::with_container(
+                    vec![],
+                    Strategy::Auto,
+                    container,
+                );
+                encoder.write_all(&data).unwrap();
+                let compressed = encoder.finish().unwrap();
+
+                let mut inflated = vec![];
+                Inflater::with_container(compressed.as_slice(), container)
+                    .read_to_end(&mut inflated)
+                    .unwrap();
+                assert_eq!(inflated, data);
+            }
+        }
+    }
+
+    #[test]
+    fn test_total_in() {
+        for (len, strategy) in [(100, Strategy::Fixed), (70000, Strategy::Auto)]
+        {
+            let compressed = compress(&sample(len), &strategy);
+            let mut input = compressed.clone();
+            input.extend_from_slice(b"trailing data");
+
+            let mut inflater = Inflater::new(input.as_slice());
+            io::copy(&mut inflater, &mut io::sink()).unwrap();
+            assert_eq!(inflater.total_in(), compressed.len() as u64);
+        }
+    }
+
+    #[test]
+    fn test_inflate_errors() {
+        let mut compressed = compress(b"some data", &Strategy::Auto);
+        let len = compressed.len();
+
+        // Truncated stream
+        let err = inflate(&compressed[..len - 2]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        // Bad checksum
+        compressed[len - 1] ^= 1;
+        let err = inflate(&compressed).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // Bad header
+        let err = inflate(&[0x77, 0x01]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_inflate_corrupt() {
+        // A fixed block that starts with a back reference
+        let err = inflate(b"\x78\x01\x03\x02\x00\x00\x00\x00\x01").unwrap_err();
+        assert_eq!(err.to_string(), "Invalid distance too far back");
+
+        let compressed = compress(&sample(OUTPUT_CHUNK * 3), &Strategy::Auto);
+        let mut inflated = vec![];
+        let err = Inflater::new(compressed.as_slice())
+            .with_limit(OUTPUT_CHUNK as u64)
+            .read_to_end(&mut inflated)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(inflated.len() <= OUTPUT_CHUNK);
+    }
+
+    #[test]
+    fn test_reset() {
+        let first = sample(ENCODER_CHUNK + 7);
+        let second = sample(300);
+
+        let mut encoder = ZlibEncoder::with_container(
+            vec![],
+            Strategy::Auto,
+            Container::Gzip,
+        );
+        encoder.write_all(&first).unwrap();
+        let first_compressed = encoder.reset(vec![]).unwrap();
+        encoder.write_all(&second).unwrap();
+        let second_compressed = encoder.finish().unwrap();
+
+        // A stream left half read, or in error, is forgotten on reset
+        let mut inflater =
+            Inflater::with_container(&first_compressed[..100], Container::Gzip);
+        assert!(inflater.read_to_end(&mut vec![]).is_err());
+        for (compressed, data) in
+            [(&first_compressed, &first), (&second_compressed, &second)]
+        {
+            inflater.reset(compressed.as_slice());
+            let mut output = vec![];
+            inflater.read_to_end(&mut output).unwrap();
+            assert_eq!(&output, data);
+            assert_eq!(inflater.total_in(), compressed.len() as u64);
+        }
+    }
+}
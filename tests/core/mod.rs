@@ -1,10 +1,28 @@
+pub mod test_advice;
 pub mod test_cat_file;
+pub mod test_commands;
+pub mod test_config;
+pub mod test_diff;
+pub mod test_diff_files;
+pub mod test_diff_tree;
+pub mod test_facade;
+pub mod test_generate_docs;
 pub mod test_hash_object;
+pub mod test_hooks;
 pub mod test_init;
 pub mod test_log;
+pub mod test_ls_files;
 pub mod test_ls_tree;
+pub mod test_object_store;
+pub mod test_repository;
 pub mod test_rev_parse;
+pub mod test_revwalk;
 pub mod test_show_ref;
+pub mod test_status;
+pub mod test_tree_walk;
+pub mod test_version;
+pub mod test_worktree;
+pub mod test_write_tree;
 
 #[macro_export]
 macro_rules! make_namespaces_from {
@@ -0,0 +1,27 @@
+#[cfg(test)]
+mod tests {
+    use mini_git::core::advice::{enabled, format, Advice};
+    use mini_git::utils::configparser::ConfigParser;
+
+    #[test]
+    fn test_format() {
+        let hint = format(Advice::DefaultBranchName, "First\n\nSecond");
+        assert_eq!(
+            hint,
+            "hint: First\nhint:\nhint: Second\nhint: Disable this message by \
+            setting advice.defaultBranchName to false\n"
+        );
+    }
+
+    #[test]
+    fn test_enabled_from_config() {
+        let advice = Advice::DefaultBranchName;
+        let mut config = ConfigParser::new();
+
+        config.add_config("advice", advice.key(), "false");
+        assert!(!enabled(advice, &config));
+
+        config.add_config("advice", advice.key(), "true");
+        assert!(enabled(advice, &config));
+    }
+}
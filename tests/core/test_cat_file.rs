@@ -69,6 +69,7 @@ mod tests {
                             "hashes did not match in setup"
                         );
 
+                        let hash = hash.to_string();
                         let file =
                             OBJECT_DIR().join(&hash[..2]).join(&hash[2..]);
                         assert!(file.is_file());
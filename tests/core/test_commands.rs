@@ -0,0 +1,54 @@
+#[cfg(test)]
+mod tests {
+    use mini_git::core::commands::{self, COMMANDS};
+    use mini_git::core::{Error, GitRepository};
+
+    use mini_git::utils::test::TempDir;
+
+    #[test]
+    fn test_run() {
+        let tmp = TempDir::create("cmd_run").with_mutex(&crate::TEST_MUTEX);
+        GitRepository::create(tmp.tmp_dir()).expect("Create repo");
+
+        std::fs::write(tmp.tmp_dir().join("file"), "hello\n").unwrap();
+        let output = tmp
+            .run(|| {
+                commands::run("hash-object", &["file"]).map_err(String::from)
+            })
+            .unwrap();
+        assert_eq!(output.exit_code, 0);
+        assert_eq!(output.text(), "ce013625030ba8dba906f756967f9e9ca394464a\n");
+    }
+
+    #[test]
+    fn test_run_usage() {
+        let err = commands::run("no-such-command", &[]).unwrap_err();
+        assert!(matches!(err, Error::Usage(_)));
+
+        let err = commands::run("version", &["--unknown"]).unwrap_err();
+        assert!(matches!(err, Error::Usage(_)));
+        assert_eq!(err.exit_code(), 129);
+    }
+
+    #[test]
+    fn test_run_help() {
+        let output = commands::run("version", &["--help"]).unwrap();
+        assert_eq!(output.exit_code, 0);
+        assert!(output.text().contains("--build-options"));
+
+        let output = commands::run("help", &["-a"]).unwrap();
+        for command in COMMANDS {
+            let hidden = command.make_parser().is_hidden();
+            assert_eq!(output.text().contains(command.name()), !hidden);
+        }
+    }
+
+    #[test]
+    fn test_commands_are_found() {
+        for command in COMMANDS {
+            let found = commands::find(command.name()).expect("Find command");
+            assert_eq!(found.name(), command.name());
+        }
+        assert!(commands::find("no-such-command").is_none());
+    }
+}
@@ -0,0 +1,123 @@
+#[cfg(test)]
+mod tests {
+    use mini_git::core::config;
+    use mini_git::core::GitRepository;
+
+    use mini_git::utils::test::TempDir;
+
+    use std::fs;
+    use std::path::Path;
+
+    fn append_config(gitdir: &Path, text: &str) {
+        let config = gitdir.join("config");
+        let mut contents = fs::read_to_string(&config).unwrap();
+        contents.push_str(text);
+        fs::write(&config, contents).unwrap();
+    }
+
+    fn user_name(root: &Path) -> Option<String> {
+        let repo = GitRepository::new(root).expect("Open repo");
+        let user = repo.config().get("user")?;
+        user.get("name").map(String::from)
+    }
+
+    #[test]
+    fn test_include() {
+        let tmp =
+            TempDir::create("config_include").with_mutex(&crate::TEST_MUTEX);
+        let root = tmp.tmp_dir().to_path_buf();
+        let gitdir = root.join(".git");
+        GitRepository::create(&root).expect("Create repo");
+
+        // Relative paths are relative to the including file
+        fs::write(
+            gitdir.join("extra"),
+            "[user]\n\tname = included\n\temail = a@b.c\n",
+        )
+        .unwrap();
+        append_config(&gitdir, "[include]\n\tpath = extra\n");
+        assert_eq!(user_name(&root).as_deref(), Some("included"));
+
+        // Settings after the include override it
+        append_config(&gitdir, "[user]\n\tname = after\n");
+        let repo = GitRepository::new(&root).expect("Open repo");
+        let user = repo.config().get("user").unwrap();
+        assert_eq!(user.get("name"), Some("after"));
+        assert_eq!(user.get("email"), Some("a@b.c"));
+
+        // Files that do not exist are skipped
+        append_config(&gitdir, "[include]\n\tpath = missing\n");
+        assert_eq!(user_name(&root).as_deref(), Some("after"));
+    }
+
+    #[test]
+    fn test_include_cycle() {
+        let tmp = TempDir::create("config_include_cycle")
+            .with_mutex(&crate::TEST_MUTEX);
+        let root = tmp.tmp_dir().to_path_buf();
+        let gitdir = root.join(".git");
+        GitRepository::create(&root).expect("Create repo");
+
+        fs::write(gitdir.join("a"), "[include]\n\tpath = b\n").unwrap();
+        fs::write(gitdir.join("b"), "[include]\n\tpath = a\n").unwrap();
+        append_config(&gitdir, "[include]\n\tpath = a\n");
+
+        let res = config::load(&gitdir.join("config"), Some(&gitdir));
+        assert!(res.unwrap_err().to_string().contains("includes itself"));
+        assert!(GitRepository::new(&root).is_err());
+    }
+
+    #[test]
+    fn test_include_if() {
+        let tmp =
+            TempDir::create("config_include_if").with_mutex(&crate::TEST_MUTEX);
+        let root = tmp.tmp_dir().to_path_buf();
+        let gitdir = root.join(".git");
+        GitRepository::create(&root).expect("Create repo");
+
+        fs::write(gitdir.join("yes"), "[user]\n\tname = yes\n").unwrap();
+        fs::write(gitdir.join("no"), "[user]\n\tname = no\n").unwrap();
+        let dir_name = root.file_name().unwrap().to_string_lossy();
+        append_config(
+            &gitdir,
+            &format!(
+                "[includeIf \"gitdir:{dir_name}/\"]\n\tpath = yes\n\
+                [includeIf \"gitdir:elsewhere/\"]\n\tpath = no\n"
+            ),
+        );
+        assert_eq!(user_name(&root).as_deref(), Some("yes"));
+
+        // Without a git directory, no conditions hold
+        let parser = config::load(&gitdir.join("config"), None).unwrap();
+        assert!(parser.get("user").is_none());
+
+        append_config(
+            &gitdir,
+            &format!(
+                "[includeIf \"gitdir/i:{}/\"]\n\tpath = no\n",
+                dir_name.to_uppercase()
+            ),
+        );
+        assert_eq!(user_name(&root).as_deref(), Some("no"));
+    }
+
+    #[test]
+    fn test_include_if_onbranch() {
+        let tmp = TempDir::create("config_include_if_onbranch")
+            .with_mutex(&crate::TEST_MUTEX);
+        let root = tmp.tmp_dir().to_path_buf();
+        let gitdir = root.join(".git");
+        GitRepository::create(&root).expect("Create repo");
+
+        fs::write(gitdir.join("feature"), "[user]\n\tname = feature\n")
+            .unwrap();
+        append_config(
+            &gitdir,
+            "[includeIf \"onbranch:feature/\"]\n\tpath = feature\n",
+        );
+        assert_eq!(user_name(&root), None);
+
+        fs::write(gitdir.join("HEAD"), "ref: refs/heads/feature/x\n").unwrap();
+        assert_eq!(user_name(&root).as_deref(), Some("feature"));
+    }
+}
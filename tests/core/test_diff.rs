@@ -0,0 +1,448 @@
+#[cfg(test)]
+mod tests {
+    use crate::make_namespaces_from;
+    use crate::utils::fixtures::{
+        set_head, temp_repo, write_blob, write_commit, write_tree,
+    };
+
+    use mini_git::core::commands::diff::*;
+    use mini_git::core::index::{GitIndex, IndexEntry};
+    use mini_git::core::objects::oid::Oid;
+    use mini_git::core::GitRepository;
+
+    use mini_git::utils::test::TempDir;
+
+    make_namespaces_from!(make_parser);
+
+    fn write_index(repo: &GitRepository, files: &[(&str, &str)]) {
+        let mut index = GitIndex::new();
+        for (path, content) in files {
+            let sha = write_blob(repo, content).to_string();
+            index.add(IndexEntry::new(0o100_644, &sha, path));
+        }
+        index.write(repo).expect("Write index");
+    }
+
+    fn write_worktree(tmp: &TempDir<()>, files: &[(&str, &str)]) {
+        for (path, content) in files {
+            let path = tmp.tmp_dir().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).expect("Mkdir");
+            std::fs::write(path, content).expect("Write file");
+        }
+    }
+
+    /// Writes a commit with the given files and parents.
+    fn commit_files(
+        repo: &GitRepository,
+        files: &[(&str, &str)],
+        parents: &[Oid],
+    ) -> Oid {
+        let tree = write_tree(repo, files);
+        write_commit(repo, &tree, parents, 1_627_890_123, "Commit")
+    }
+
+    /// Creates a repository whose `HEAD` commit has `a.txt` and `b.txt`.
+    fn create_temp_repo<'a>() -> (TempDir<'a, ()>, GitRepository) {
+        let (tmp, repo) = temp_repo("cmd_diff");
+
+        let commit = commit_files(
+            &repo,
+            &[("a.txt", "alpha\n"), ("b.txt", "beta\n")],
+            &[],
+        );
+        set_head(&repo, &commit.to_string());
+
+        (tmp, repo)
+    }
+
+    fn run_diff(tmp: &TempDir<()>, args: &[&str]) -> Result<String, String> {
+        let args: [&[&str]; 1] = [args];
+        tmp.run(|| {
+            let namespace = make_namespaces(&args).next().unwrap();
+            diff(&namespace).map_err(String::from)
+        })
+    }
+
+    #[test]
+    fn test_worktree_against_index() {
+        let (tmp, repo) = create_temp_repo();
+        // a.txt is staged, c.txt is newly staged and b.txt is deleted
+        write_index(
+            &repo,
+            &[
+                ("a.txt", "alpha 2\n"),
+                ("b.txt", "beta\n"),
+                ("c.txt", "c\n"),
+            ],
+        );
+        write_worktree(
+            &tmp,
+            &[
+                ("a.txt", "alpha 2\n"),
+                ("c.txt", "c 2\n"),
+                ("new.txt", "?\n"),
+            ],
+        );
+
+        let res = run_diff(&tmp, &["--name-status"]).unwrap();
+        // Untracked files are not shown
+        assert_eq!(res, "D\tb.txt\nM\tc.txt");
+    }
+
+    #[test]
+    fn test_cached_against_head() {
+        let (tmp, repo) = create_temp_repo();
+        write_index(
+            &repo,
+            &[
+                ("a.txt", "alpha 2\n"),
+                ("b.txt", "beta\n"),
+                ("c.txt", "c\n"),
+            ],
+        );
+        write_worktree(&tmp, &[("a.txt", "alpha 3\n")]);
+
+        let res = run_diff(&tmp, &["--cached", "--name-status"]).unwrap();
+        assert_eq!(res, "M\ta.txt\nA\tc.txt");
+
+        let res = run_diff(&tmp, &["--cached", "--name-status", "--json"]);
+        assert_eq!(
+            res.unwrap(),
+            r#"[{"status":"M","path":"a.txt"},{"status":"A","path":"c.txt"}]"#
+        );
+        assert!(run_diff(&tmp, &["--cached", "--json"]).is_err());
+
+        let res = run_diff(&tmp, &["--cached"]).unwrap();
+        assert!(res.contains("-alpha"));
+        assert!(res.contains("+alpha 2"));
+        assert!(!res.contains("alpha 3"));
+    }
+
+    #[test]
+    fn test_numstat_and_shortstat() {
+        let (tmp, _) = create_temp_repo();
+        write_worktree(
+            &tmp,
+            &[("a.txt", "alpha 2\nalpha 3\n"), ("b.txt", "beta\n")],
+        );
+
+        let res = run_diff(&tmp, &["--numstat"]).unwrap();
+        assert_eq!(res, "2\t1\ta.txt");
+
+        let res = run_diff(&tmp, &["--shortstat"]).unwrap();
+        assert_eq!(res, " 1 file changed, 2 insertions(+), 1 deletion(-)");
+
+        let res = run_diff(&tmp, &["--numstat", "--shortstat"]).unwrap();
+        assert_eq!(
+            res,
+            "2\t1\ta.txt\n 1 file changed, 2 insertions(+), 1 deletion(-)"
+        );
+
+        std::fs::remove_file(tmp.tmp_dir().join("b.txt")).unwrap();
+        let res = run_diff(&tmp, &["--shortstat"]).unwrap();
+        assert_eq!(res, " 2 files changed, 2 insertions(+), 2 deletions(-)");
+    }
+
+    #[test]
+    fn test_dirstat() {
+        let (tmp, repo) = create_temp_repo();
+        let lines = |n: usize| (0..n).map(|i| format!("{i}\n")).collect();
+        let (ten, twenty): (String, String) = (lines(10), lines(20));
+        write_index(
+            &repo,
+            &[
+                ("a.txt", "alpha\n"),
+                ("src/x/1.rs", &ten),
+                ("src/y/1.rs", &ten),
+                ("lib/deep/1.rs", &ten),
+                ("doc/1.md", &twenty),
+            ],
+        );
+        write_worktree(
+            &tmp,
+            &[
+                ("a.txt", "alpha\n"),
+                ("src/x/1.rs", ""),
+                ("src/y/1.rs", "0\n"),
+                ("lib/deep/1.rs", &twenty),
+                ("doc/1.md", "0\n"),
+            ],
+        );
+
+        // 10 + 9 + 10 + 19 changed lines. lib/ is left out, since all of its
+        // changes come from lib/deep/
+        let res = run_diff(&tmp, &["--dirstat"]).unwrap();
+        assert_eq!(
+            res,
+            "  39.5% doc/\n  20.8% lib/deep/\n  20.8% src/x/\n  18.7% src/y/"
+        );
+    }
+
+    #[test]
+    fn test_shortstat_without_changes() {
+        let (tmp, _) = create_temp_repo();
+        write_worktree(&tmp, &[("a.txt", "alpha\n"), ("b.txt", "beta\n")]);
+        assert_eq!(run_diff(&tmp, &["--shortstat"]).unwrap(), "");
+    }
+
+    #[test]
+    fn test_exit_code_and_quiet() {
+        let (tmp, _) = create_temp_repo();
+        write_worktree(&tmp, &[("a.txt", "alpha\n"), ("b.txt", "beta\n")]);
+        let args: [&[&str]; 3] = [
+            &["--name-only"],
+            &["--exit-code", "--name-only"],
+            &["--quiet"],
+        ];
+        let run = || {
+            tmp.run(|| {
+                make_namespaces(&args)
+                    .map(|namespace| diff_with_status(&namespace))
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .unwrap()
+        };
+
+        // No changes
+        let res = run();
+        assert_eq!(res[0], (String::new(), 0));
+        assert_eq!(res[1], (String::new(), 0));
+        assert_eq!(res[2], (String::new(), 0));
+
+        write_worktree(&tmp, &[("a.txt", "alpha 2\n")]);
+        let res = run();
+        assert_eq!(res[0], ("a.txt".to_owned(), 0));
+        assert_eq!(res[1], ("a.txt".to_owned(), 1));
+        assert_eq!(res[2], (String::new(), 1));
+    }
+
+    #[test]
+    fn test_write_diff_streams_in_path_order() {
+        let (tmp, _) = create_temp_repo();
+        let files = (0..20)
+            .map(|i| (format!("f{i:02}.txt"), format!("{i}\n")))
+            .collect::<Vec<_>>();
+        let files = files
+            .iter()
+            .map(|(path, content)| (path.as_str(), content.as_str()))
+            .collect::<Vec<_>>();
+        write_worktree(&tmp, &files);
+
+        let args: [&[&str]; 1] = [&["--name-status"]];
+        let (streamed, status, buffered) = tmp.run(|| {
+            let namespace = make_namespaces(&args).next().unwrap();
+            let mut out = vec![];
+            let status = write_diff(&namespace, &mut out).unwrap();
+            (out, status, diff(&namespace).unwrap())
+        });
+
+        let mut expected = files
+            .iter()
+            .map(|(path, _)| format!("A\t{path}\n"))
+            .collect::<String>();
+        expected.insert_str(0, "D\ta.txt\nD\tb.txt\n");
+        // The streamed output ends with a newline, as it is printed
+        assert_eq!(String::from_utf8(streamed).unwrap(), expected);
+        assert_eq!(buffered, expected.trim_end());
+        assert_eq!(status, 0);
+    }
+
+    #[test]
+    fn test_submodule_changes() {
+        let (tmp, repo) = create_temp_repo();
+        let (old, new) = ("1".repeat(40), "2".repeat(40));
+
+        let mut index = GitIndex::new();
+        index.add(IndexEntry::new(
+            0o100_644,
+            &write_blob(&repo, "alpha\n").to_string(),
+            "a.txt",
+        ));
+        index.add(IndexEntry::new(
+            0o100_644,
+            &write_blob(&repo, "beta\n").to_string(),
+            "b.txt",
+        ));
+        index.add(IndexEntry::new(0o160_000, &old, "sub"));
+        index.write(&repo).expect("Write index");
+
+        // The submodule is checked out at a newer commit
+        let sub = GitRepository::create(&tmp.tmp_dir().join("sub"))
+            .expect("Create submodule");
+        set_head(&sub, &new);
+        write_worktree(&tmp, &[("a.txt", "alpha\n"), ("b.txt", "beta\n")]);
+
+        let res = run_diff(&tmp, &[]).unwrap();
+        assert_eq!(res, "Submodule sub 1111111..2222222");
+        let res = run_diff(&tmp, &["--name-status"]).unwrap();
+        assert_eq!(res, "M\tsub");
+
+        let res = run_diff(&tmp, &["--cached"]).unwrap();
+        assert_eq!(res, "Submodule sub 0000000...1111111 (new submodule)");
+
+        // A submodule that is not checked out is unchanged
+        std::fs::remove_dir_all(tmp.tmp_dir().join("sub")).unwrap();
+        std::fs::create_dir(tmp.tmp_dir().join("sub")).unwrap();
+        assert_eq!(run_diff(&tmp, &[]).unwrap(), "");
+    }
+
+    #[test]
+    fn test_combined_diff_of_merge() {
+        let (tmp, repo) = create_temp_repo();
+        let base = repo.gitdir().join("refs").join("heads").join("main");
+        let base = std::fs::read_to_string(base).unwrap();
+        let base: Oid = base.trim().parse().unwrap();
+
+        let ours = commit_files(
+            &repo,
+            &[("a.txt", "ours\n"), ("b.txt", "beta\n")],
+            &[base],
+        );
+        let theirs = commit_files(
+            &repo,
+            &[("a.txt", "theirs\n"), ("b.txt", "beta 2\n")],
+            &[base],
+        );
+        // b.txt is taken from one side, so is not shown
+        let merge = commit_files(
+            &repo,
+            &[("a.txt", "merged\n"), ("b.txt", "beta 2\n")],
+            &[ours, theirs],
+        );
+        set_head(&repo, &merge.to_string());
+
+        let res = run_diff(&tmp, &["--cc"]).unwrap();
+        assert!(res.contains("diff --cc a.txt"));
+        assert!(res.contains("@@@ -1,1 -1,1 +1,1 @@@"));
+        assert!(res.contains("- ours"));
+        assert!(res.contains(" -theirs"));
+        assert!(res.contains("++merged"));
+        assert!(!res.contains("b.txt"));
+
+        // Other commits are compared against their parent
+        let res =
+            run_diff(&tmp, &["--cc", "--name-status", &theirs.to_string()]);
+        assert_eq!(res.unwrap(), "M\ta.txt\nM\tb.txt");
+
+        assert!(run_diff(&tmp, &["--cc", "--cached"]).is_err());
+    }
+
+    #[test]
+    fn test_color() {
+        let (tmp, _) = create_temp_repo();
+        write_worktree(&tmp, &[("a.txt", "alpha 2\n"), ("b.txt", "beta\n")]);
+
+        let res = run_diff(&tmp, &["--color", "always"]).unwrap();
+        assert!(res.contains("\x1b[31m-alpha\x1b[0m\n"));
+        assert!(res.contains("\x1b[32m+alpha 2\x1b[0m\n"));
+
+        let res = run_diff(&tmp, &["--color", "never"]).unwrap();
+        assert!(res.contains("\n-alpha\n+alpha 2\n"));
+        assert!(!res.contains('\x1b'));
+    }
+
+    #[test]
+    fn test_diff_drivers() {
+        let (tmp, repo) = create_temp_repo();
+        write_worktree(&tmp, &[("a.txt", "alpha 2\n"), ("b.txt", "beta 2\n")]);
+        let info = repo.gitdir().join("info");
+        std::fs::create_dir_all(&info).unwrap();
+        std::fs::write(
+            info.join("attributes"),
+            "a.txt diff=upper\nb.txt -diff\n",
+        )
+        .unwrap();
+        let config = repo.gitdir().join("config");
+        let mut text = std::fs::read_to_string(&config).unwrap();
+        text.push_str("[diff \"upper\"]\n\ttextconv = tr a-z A-Z <\n");
+        std::fs::write(&config, text).unwrap();
+
+        let res = run_diff(&tmp, &[]).unwrap();
+        assert!(res.contains("-ALPHA"));
+        assert!(res.contains("+ALPHA 2"));
+        assert!(res.contains("diff --mini-git a/b.txt b/b.txt\nBinary"));
+        assert!(!res.contains("beta"));
+
+        let res = run_diff(&tmp, &["--numstat"]).unwrap();
+        assert_eq!(res, "1\t1\ta.txt\n-\t-\tb.txt");
+
+        // An external command replaces the whole patch
+        let mut text = std::fs::read_to_string(&config).unwrap();
+        text.push_str("\tcommand = echo external\n");
+        std::fs::write(&config, text).unwrap();
+        let res = run_diff(&tmp, &["--files", "a.txt"]).unwrap();
+        assert!(res.starts_with("external a.txt "));
+        assert!(res.ends_with(" 100644\n"));
+    }
+
+    #[test]
+    fn test_files() {
+        let (tmp, _repo) = create_temp_repo();
+        write_worktree(&tmp, &[("a.txt", "alpha 2\n"), ("b.txt", "beta 2\n")]);
+
+        let res = run_diff(&tmp, &["--numstat", "--files", "b.txt"]).unwrap();
+        assert_eq!(res, "1\t1\tb.txt");
+
+        let res = run_diff(&tmp, &["--files", "a.txt", "b.txt", "--numstat"])
+            .unwrap();
+        assert_eq!(res, "1\t1\ta.txt\n1\t1\tb.txt");
+
+        assert!(run_diff(&tmp, &["--files", "a.txt", "missing.txt"]).is_err());
+    }
+
+    #[test]
+    fn test_jobs() {
+        let (tmp, repo) = create_temp_repo();
+        write_worktree(&tmp, &[("a.txt", "alpha 2\n"), ("b.txt", "beta 2\n")]);
+        let expected = "1\t1\ta.txt\n1\t1\tb.txt";
+
+        for jobs in ["0", "1", "4"] {
+            let res = run_diff(&tmp, &["--numstat", "--jobs", jobs]).unwrap();
+            assert_eq!(res, expected);
+        }
+        assert!(run_diff(&tmp, &["--jobs", "-1"]).is_err());
+
+        let config = repo.gitdir().join("config");
+        let mut text = std::fs::read_to_string(&config).unwrap();
+        text.push_str("[diff]\n\tjobs = 2\n");
+        std::fs::write(&config, &text).unwrap();
+        assert_eq!(run_diff(&tmp, &["--numstat"]).unwrap(), expected);
+
+        text.push_str("[diff]\n\tjobs = many\n");
+        std::fs::write(&config, &text).unwrap();
+        assert!(run_diff(&tmp, &["--numstat"]).is_err());
+        // --jobs takes precedence over diff.jobs
+        let res = run_diff(&tmp, &["--numstat", "--jobs", "1"]).unwrap();
+        assert_eq!(res, expected);
+    }
+
+    #[test]
+    fn test_cached_against_tree() {
+        let (tmp, repo) = create_temp_repo();
+        write_index(&repo, &[("a.txt", "alpha\n")]);
+
+        let res = run_diff(&tmp, &["--cached", "--name-status", "HEAD"]);
+        assert_eq!(res.unwrap(), "D\tb.txt");
+
+        let res = run_diff(&tmp, &["--cached", "HEAD", "HEAD"]);
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_cached_without_commits() {
+        let (tmp, repo) = temp_repo("cmd_diff");
+        write_index(&repo, &[("a.txt", "alpha\n")]);
+
+        let res = run_diff(&tmp, &["--cached", "--name-status"]);
+        assert_eq!(res.unwrap(), "A\ta.txt");
+    }
+
+    #[test]
+    fn test_worktree_against_head_without_index() {
+        let (tmp, _) = create_temp_repo();
+        write_worktree(&tmp, &[("a.txt", "alpha 2\n"), ("b.txt", "beta\n")]);
+
+        let res = run_diff(&tmp, &["--name-status"]).unwrap();
+        assert_eq!(res, "M\ta.txt");
+    }
+}
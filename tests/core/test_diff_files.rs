@@ -0,0 +1,139 @@
+#[cfg(test)]
+mod tests {
+    use crate::make_namespaces_from;
+
+    use mini_git::core::commands::diff_files::*;
+    use mini_git::core::index::{GitIndex, IndexEntry};
+    use mini_git::core::objects::{blob::Blob, write_object, GitObject};
+    use mini_git::core::GitRepository;
+
+    use mini_git::utils::test::TempDir;
+
+    make_namespaces_from!(make_parser);
+
+    const NULL_SHA: &str = "0000000000000000000000000000000000000000";
+
+    fn run_diff_files(
+        tmp: &TempDir<()>,
+        args: &[&str],
+    ) -> Result<String, String> {
+        let args: [&[&str]; 1] = [args];
+        tmp.run(|| {
+            let namespace = make_namespaces(&args).next().unwrap();
+            diff_files(&namespace).map_err(String::from)
+        })
+    }
+
+    #[test]
+    fn test_diff_files() {
+        let tmp =
+            TempDir::create("cmd_diff_files").with_mutex(&crate::TEST_MUTEX);
+        let repo = GitRepository::create(tmp.tmp_dir()).expect("Create repo");
+
+        let mut index = GitIndex::new();
+        let mut shas = vec![];
+        for path in ["a.txt", "b.txt", "c.txt"] {
+            let blob =
+                GitObject::Blob(Blob::from(format!("{path}\n").as_bytes()));
+            let sha =
+                write_object(&blob, &repo).expect("Write blob").to_string();
+            index.add(IndexEntry::new(0o100_644, &sha, path));
+            shas.push(sha);
+        }
+        let mut conflicted = IndexEntry::new(0o100_644, &shas[0], "d.txt");
+        conflicted.flags |= 1 << 12;
+        index.add(conflicted.clone());
+        conflicted.flags += 1 << 12;
+        index.add(conflicted);
+        index.write(&repo).expect("Write index");
+
+        // a.txt is unchanged, b.txt is modified and c.txt is deleted
+        std::fs::write(tmp.tmp_dir().join("a.txt"), "a.txt\n").unwrap();
+        std::fs::write(tmp.tmp_dir().join("b.txt"), "changed\n").unwrap();
+        std::fs::write(tmp.tmp_dir().join("untracked.txt"), "?\n").unwrap();
+
+        let res = run_diff_files(&tmp, &[]).unwrap();
+        assert_eq!(
+            res,
+            format!(
+                ":100644 100644 {} {NULL_SHA} M\tb.txt\n\
+                :100644 000000 {} {NULL_SHA} D\tc.txt\n\
+                :000000 000000 {NULL_SHA} {NULL_SHA} U\td.txt",
+                shas[1], shas[2]
+            )
+        );
+
+        let res = run_diff_files(&tmp, &["--name-status"]).unwrap();
+        assert_eq!(res, "M\tb.txt\nD\tc.txt\nU\td.txt");
+
+        let res = run_diff_files(&tmp, &["--name-only", "-z"]).unwrap();
+        assert_eq!(res, "b.txt\0c.txt\0d.txt\0");
+    }
+
+    #[test]
+    fn test_fsmonitor() {
+        let tmp = TempDir::create("cmd_diff_files_fsmonitor")
+            .with_mutex(&crate::TEST_MUTEX);
+        let root = tmp.tmp_dir().to_path_buf();
+        let gitdir = root.join(".git");
+        GitRepository::create(&root).expect("Create repo");
+
+        // The hook reports the paths listed in .git/changed, and records
+        // the token it was given
+        std::fs::write(
+            gitdir.join("fsmonitor"),
+            "echo \"$1 $2\" >> .git/queries\n\
+            printf 'next\\0'\n\
+            [ -f .git/changed ] && tr '\\n' '\\0' < .git/changed\n\
+            exit 0\n",
+        )
+        .unwrap();
+        let config = gitdir.join("config");
+        let mut text = std::fs::read_to_string(&config).unwrap();
+        text.push_str("[core]\n\tfsmonitor = sh .git/fsmonitor\n");
+        std::fs::write(&config, text).unwrap();
+        let repo = GitRepository::new(&root).expect("Open repo");
+
+        let mut index = GitIndex::new();
+        for path in ["a.txt", "b.txt"] {
+            let blob =
+                GitObject::Blob(Blob::from(format!("{path}\n").as_bytes()));
+            let sha =
+                write_object(&blob, &repo).expect("Write blob").to_string();
+            index.add(IndexEntry::new(0o100_644, &sha, path));
+            std::fs::write(root.join(path), format!("{path}\n")).unwrap();
+        }
+        std::fs::write(root.join("b.txt"), "changed\n").unwrap();
+
+        // Without a token, every entry is checked
+        index.refresh_fsmonitor(&repo).expect("Refresh");
+        let monitor = index.fsmonitor().expect("File system monitor");
+        assert_eq!(monitor.token(), "next");
+        assert!(monitor.is_valid("a.txt"));
+        assert!(!monitor.is_valid("b.txt"));
+        index.write(&repo).expect("Write index");
+
+        let index = GitIndex::read(&repo).expect("Read index");
+        assert!(index.fsmonitor().unwrap().is_valid("a.txt"));
+        assert_eq!(run_diff_files(&tmp, &["--name-only"]).unwrap(), "b.txt");
+
+        // A change the hook does not report is not noticed
+        std::fs::write(root.join("a.txt"), "unreported\n").unwrap();
+        assert_eq!(run_diff_files(&tmp, &["--name-only"]).unwrap(), "b.txt");
+
+        std::fs::write(gitdir.join("changed"), "a.txt\n").unwrap();
+        let res = run_diff_files(&tmp, &["--name-only"]).unwrap();
+        assert_eq!(res, "a.txt\nb.txt");
+
+        // `/` means that everything may have changed
+        std::fs::write(gitdir.join("changed"), "/\n").unwrap();
+        let res = run_diff_files(&tmp, &["--name-only"]).unwrap();
+        assert_eq!(res, "a.txt\nb.txt");
+
+        let queries = std::fs::read_to_string(gitdir.join("queries")).unwrap();
+        assert_eq!(
+            queries.lines().collect::<Vec<_>>(),
+            ["2 builtin:fake", "2 next", "2 next", "2 next", "2 next"]
+        );
+    }
+}
@@ -0,0 +1,123 @@
+#[cfg(test)]
+mod tests {
+    use crate::make_namespaces_from;
+    use crate::utils::fixtures::{
+        temp_repo, write_blob, write_commit, write_tree, write_tree_with_modes,
+    };
+
+    use mini_git::core::commands::diff_tree::*;
+
+    use mini_git::utils::test::TempDir;
+
+    make_namespaces_from!(make_parser);
+
+    fn run_diff_tree(
+        tmp: &TempDir<()>,
+        args: &[&str],
+    ) -> Result<String, String> {
+        let args: [&[&str]; 1] = [args];
+        tmp.run(|| {
+            let namespace = make_namespaces(&args).next().unwrap();
+            diff_tree(&namespace).map_err(String::from)
+        })
+    }
+
+    /// Creates two commits, where the second modifies `src/lib.rs`, deletes
+    /// `old.txt`, adds `new.txt` and turns `run.sh` into a symlink.
+    fn create_temp_repo<'a>() -> (TempDir<'a, ()>, [String; 2], [String; 4]) {
+        let (tmp, repo) = temp_repo("cmd_diff_tree");
+
+        let tree1 = write_tree_with_modes(
+            &repo,
+            &[
+                (0o100_644, "old.txt", "text\n"),
+                (0o100_755, "run.sh", "echo\n"),
+                (0o100_644, "src/lib.rs", "lib 1\n"),
+            ],
+        );
+        let tree2 = write_tree_with_modes(
+            &repo,
+            &[
+                (0o100_644, "new.txt", "text\n"),
+                (0o120_000, "run.sh", "echo\n"),
+                (0o100_644, "src/lib.rs", "lib 2\n"),
+            ],
+        );
+        let commit1 = write_commit(&repo, &tree1, &[], 1_627_890_123, "Commit");
+        let commit2 =
+            write_commit(&repo, &tree2, &[commit1], 1_627_890_123, "Commit");
+
+        // The same subtrees and blobs as the commits have
+        let src1 = write_tree(&repo, &[("lib.rs", "lib 1\n")]);
+        let src2 = write_tree(&repo, &[("lib.rs", "lib 2\n")]);
+        let lib1 = write_blob(&repo, "lib 1\n");
+        let lib2 = write_blob(&repo, "lib 2\n");
+
+        (
+            tmp,
+            [commit1, commit2].map(|oid| oid.to_string()),
+            [src1, src2, lib1, lib2].map(|oid| oid.to_string()),
+        )
+    }
+
+    #[test]
+    fn test_diff_tree_recursive() {
+        let (tmp, [commit1, commit2], [_, _, lib1, lib2]) = create_temp_repo();
+
+        let res = run_diff_tree(&tmp, &["-r", &commit1, &commit2]).unwrap();
+        let lines = res.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with(
+            ":000000 100644 0000000000000000000000000000000000000000 "
+        ));
+        assert!(lines[0].ends_with(" A\tnew.txt"));
+        assert!(lines[1].starts_with(":100644 000000 "));
+        assert!(lines[1]
+            .ends_with(" 0000000000000000000000000000000000000000 D\told.txt"));
+        assert!(lines[2].starts_with(":100755 120000 "));
+        assert!(lines[2].ends_with(" T\trun.sh"));
+        assert_eq!(
+            lines[3],
+            format!(":100644 100644 {lib1} {lib2} M\tsrc/lib.rs")
+        );
+
+        let res =
+            run_diff_tree(&tmp, &["-r", "--name-status", &commit1, &commit2])
+                .unwrap();
+        assert_eq!(res, "A\tnew.txt\nD\told.txt\nT\trun.sh\nM\tsrc/lib.rs");
+
+        let res = run_diff_tree(
+            &tmp,
+            &["-r", "--name-only", "-z", &commit1, &commit2],
+        )
+        .unwrap();
+        assert_eq!(res, "new.txt\0old.txt\0run.sh\0src/lib.rs\0");
+
+        // Nothing differs between a tree and itself
+        assert_eq!(run_diff_tree(&tmp, &[&commit2, &commit2]).unwrap(), "");
+    }
+
+    #[test]
+    fn test_diff_tree_top_level() {
+        let (tmp, [commit1, commit2], [src1, src2, ..]) = create_temp_repo();
+
+        let res = run_diff_tree(&tmp, &[&commit1, &commit2]).unwrap();
+        let last = res.lines().last().unwrap();
+        assert_eq!(last, format!(":040000 040000 {src1} {src2} M\tsrc"));
+    }
+
+    #[test]
+    fn test_diff_tree_single_commit() {
+        let (tmp, [commit1, commit2], _) = create_temp_repo();
+
+        let res =
+            run_diff_tree(&tmp, &["-r", "--name-only", &commit2]).unwrap();
+        assert_eq!(
+            res,
+            format!("{commit2}\nnew.txt\nold.txt\nrun.sh\nsrc/lib.rs")
+        );
+
+        // A root commit has no parent to compare with
+        assert_eq!(run_diff_tree(&tmp, &[&commit1]).unwrap(), "");
+    }
+}
@@ -0,0 +1,145 @@
+#[cfg(test)]
+mod tests {
+    use crate::utils::fixtures::{temp_repo, write_tree};
+
+    use mini_git::core::commands::diff::LineOrigin;
+    use mini_git::core::objects::commit::Signature;
+    use mini_git::core::objects::tree::Delta;
+    use mini_git::core::objects::update_ref;
+    use mini_git::core::Error;
+
+    const AUTHOR: &str = "A U Thor <author@example.com> 1112911993 -0700";
+
+    #[test]
+    fn test_create_commit() {
+        let (_tmp, repo) = temp_repo("facade_commit");
+        assert_eq!(repo.head().expect("Read HEAD"), None);
+        assert_eq!(repo.head_branch().as_deref(), Some("main"));
+
+        let sig: Signature = AUTHOR.parse().expect("Parse signature");
+        let tree = write_tree(&repo, &[("README", "hello\n")]);
+        let first = repo
+            .create_commit(Some("HEAD"), &sig, &sig, "First\n", &tree, &[])
+            .expect("Create commit");
+        assert_eq!(repo.head().expect("Read HEAD"), Some(first));
+
+        let commit = repo.find_commit(&first).expect("Read commit");
+        assert_eq!(commit.tree().expect("Tree"), tree);
+        assert!(commit.parents().expect("Parents").is_empty());
+        assert_eq!(commit.author().expect("Author"), sig);
+        assert_eq!(commit.committer().expect("Committer"), sig);
+        assert_eq!(commit.message(), "First\n");
+
+        let tree = write_tree(&repo, &[("README", "hello\nworld\n")]);
+        let second = repo
+            .create_commit(
+                Some("HEAD"),
+                &sig,
+                &sig,
+                "Second\n",
+                &tree,
+                &[first],
+            )
+            .expect("Create commit");
+        let commit = repo.find_commit(&second).expect("Read commit");
+        assert_eq!(commit.parents().expect("Parents"), vec![first]);
+        assert_eq!(repo.resolve("main").expect("Resolve main"), second);
+
+        // Branches are listed with what they point to, HEAD moved main
+        update_ref(&repo, "refs/heads/topic/x", &first).expect("Update ref");
+        assert_eq!(
+            repo.branches().expect("List branches"),
+            vec![("main".to_owned(), second), ("topic/x".to_owned(), first)]
+        );
+
+        // Without a ref to update, only the commit is written
+        let third = repo
+            .create_commit(None, &sig, &sig, "Third\n", &tree, &[second])
+            .expect("Create commit");
+        assert_eq!(repo.head().expect("Read HEAD"), Some(second));
+        assert!(repo.find_commit(&third).is_ok());
+    }
+
+    #[test]
+    fn test_diff_tree_to_tree() {
+        let (_tmp, repo) = temp_repo("facade_diff");
+        let old = write_tree(&repo, &[("a", "one\ntwo\n"), ("b", "gone\n")]);
+        let new = write_tree(&repo, &[("a", "one\n2\n"), ("c", "new\n")]);
+
+        let diffs = repo
+            .diff_tree_to_tree(Some(&old), Some(&new))
+            .expect("Diff trees");
+        let summary = diffs
+            .iter()
+            .map(|diff| {
+                (
+                    diff.status,
+                    diff.old_path.as_deref(),
+                    diff.new_path.as_deref(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                (Delta::Modified, Some("a"), Some("a")),
+                (Delta::Deleted, Some("b"), None),
+                (Delta::Added, None, Some("c")),
+            ]
+        );
+
+        let lines = diffs[0].hunks[0]
+            .lines
+            .iter()
+            .map(|line| format!("{}{}", line.prefix(), line.content))
+            .collect::<Vec<_>>();
+        assert_eq!(lines, [" one", "-two", "+2"]);
+        assert_eq!(diffs[1].hunks[0].lines[0].origin, LineOrigin::Deletion);
+        assert!(repo
+            .diff_tree_to_tree(Some(&new), Some(&new))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_object_types() {
+        let (_tmp, repo) = temp_repo("facade_types");
+
+        let blob = repo.write_blob(b"hello\n").expect("Write blob");
+        assert_eq!(
+            blob.to_string(),
+            "ce013625030ba8dba906f756967f9e9ca394464a"
+        );
+        assert_eq!(repo.blob(&blob).expect("Read blob").data(), b"hello\n");
+
+        let err = repo.find_commit(&blob).unwrap_err();
+        assert!(err.to_string().contains("is a blob, not a commit"));
+        assert!(repo.find_tree(&blob).is_err());
+
+        // A commit needs a tree, and commits as parents
+        let sig: Signature = AUTHOR.parse().expect("Parse signature");
+        let err = repo.create_commit(None, &sig, &sig, "Msg\n", &blob, &[]);
+        assert!(err.is_err());
+        let tree = write_tree(&repo, &[("a", "a\n")]);
+        let err = repo.create_commit(None, &sig, &sig, "Msg\n", &tree, &[blob]);
+        assert!(err.is_err());
+    }
+
+    #[test]
+    fn test_update_ref_locked() {
+        let (_tmp, repo) = temp_repo("facade_locked");
+        let sig: Signature = AUTHOR.parse().expect("Parse signature");
+        let tree = write_tree(&repo, &[("a", "a\n")]);
+
+        let lock = repo.gitdir().join("refs/heads/main.lock");
+        std::fs::write(&lock, "").unwrap();
+        let err = repo
+            .create_commit(Some("HEAD"), &sig, &sig, "Msg\n", &tree, &[])
+            .unwrap_err();
+        assert!(matches!(err, Error::Locked(ref path) if *path == lock));
+        assert_eq!(repo.head().expect("Read HEAD"), None);
+
+        std::fs::remove_file(&lock).unwrap();
+        assert!(update_ref(&repo, "refs/heads/bad..name", &tree).is_err());
+    }
+}
@@ -0,0 +1,47 @@
+#[cfg(test)]
+mod tests {
+    use crate::make_namespaces_from;
+
+    use mini_git::core::commands::generate_docs::*;
+    use mini_git::core::commands::COMMANDS;
+
+    use mini_git::utils::test::TempDir;
+
+    make_namespaces_from!(make_parser);
+
+    fn run_generate_docs(args: &[&str]) -> Result<String, String> {
+        let args = [args];
+        let namespace = make_namespaces(&args).next().unwrap();
+        generate_docs(&namespace).map_err(String::from)
+    }
+
+    #[test]
+    fn test_generate_docs() {
+        let tmp = TempDir::<()>::create("cmd_generate_docs");
+        let dir = tmp.tmp_dir().join("docs");
+        let dir_arg = dir.to_str().unwrap();
+
+        let res = run_generate_docs(&[dir_arg]).unwrap();
+        assert_eq!(res.lines().count(), COMMANDS.len());
+        let page = std::fs::read_to_string(dir.join("mini_git.md")).unwrap();
+        assert!(page.starts_with("# mini_git\n"));
+        for command in COMMANDS {
+            let file_name = format!("mini_git-{}.md", command.name());
+            let hidden = command.make_parser().is_hidden();
+            assert_eq!(dir.join(&file_name).exists(), !hidden);
+            assert_eq!(page.contains(&file_name), !hidden);
+        }
+        assert!(!dir.join("mini_git-generate-docs.md").exists());
+
+        let diff =
+            std::fs::read_to_string(dir.join("mini_git-diff.md")).unwrap();
+        assert!(diff.contains("\n```\nmini_git diff [options]"));
+        assert!(diff.contains("- `-f, --files FILES...`: Files to diff\n"));
+
+        run_generate_docs(&["--format", "man", dir_arg]).unwrap();
+        let man = std::fs::read_to_string(dir.join("mini_git-diff.1")).unwrap();
+        assert!(man.starts_with(".TH \"MINI_GIT-DIFF\" \"1\"\n.SH NAME\n"));
+        assert!(man.contains(".TP\n.B \\-f, \\-\\-files FILES...\n"));
+        assert!(man.ends_with(".SH SEE ALSO\nmini_git(1)\n"));
+    }
+}
@@ -0,0 +1,130 @@
+#[cfg(test)]
+#[cfg(target_family = "unix")]
+mod tests {
+    use mini_git::core::hooks::{self, Hook, PushUpdate};
+    use mini_git::core::GitRepository;
+
+    use mini_git::utils::test::TempDir;
+
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+
+    fn write_hook(dir: &Path, hook: Hook, script: &str, mode: u32) {
+        fs::create_dir_all(dir).unwrap();
+        let path = dir.join(hook.name());
+        fs::write(&path, format!("#!/bin/sh\n{script}")).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    #[test]
+    fn test_missing_hooks_are_skipped() {
+        let tmp =
+            TempDir::create("hooks_missing").with_mutex(&crate::TEST_MUTEX);
+        let root = tmp.tmp_dir().to_path_buf();
+        let repo = GitRepository::create(&root).expect("Create repo");
+        let hooks_dir = repo.gitdir().join("hooks");
+
+        assert!(!hooks::run(&repo, Hook::PreCommit, &[], None).unwrap());
+
+        // Hooks that are not executable are skipped too
+        write_hook(&hooks_dir, Hook::PreCommit, "exit 1\n", 0o644);
+        assert!(hooks::find(&repo, Hook::PreCommit).is_none());
+        assert!(hooks::pre_commit(&repo).is_ok());
+    }
+
+    #[test]
+    fn test_exit_status() {
+        let tmp =
+            TempDir::create("hooks_exit_status").with_mutex(&crate::TEST_MUTEX);
+        let root = tmp.tmp_dir().to_path_buf();
+        let repo = GitRepository::create(&root).expect("Create repo");
+        let hooks_dir = repo.gitdir().join("hooks");
+
+        write_hook(&hooks_dir, Hook::PreCommit, "exit 1\n", 0o755);
+        let res = hooks::pre_commit(&repo);
+        assert!(res.unwrap_err().to_string().contains("pre-commit"));
+
+        // The hooks run after an operation cannot abort it
+        write_hook(&hooks_dir, Hook::PostCommit, "exit 1\n", 0o755);
+        assert!(hooks::run(&repo, Hook::PostCommit, &[], None).unwrap());
+    }
+
+    #[test]
+    fn test_arguments() {
+        let tmp =
+            TempDir::create("hooks_arguments").with_mutex(&crate::TEST_MUTEX);
+        let root = tmp.tmp_dir().to_path_buf();
+        let repo = GitRepository::create(&root).expect("Create repo");
+        let hooks_dir = repo.gitdir().join("hooks");
+
+        // Hooks run from the worktree root, and may edit the message
+        write_hook(
+            &hooks_dir,
+            Hook::CommitMsg,
+            "pwd > cwd\necho edited > \"$1\"\n",
+            0o755,
+        );
+        let msg_file = repo.gitdir().join("COMMIT_EDITMSG");
+        fs::write(&msg_file, "message\n").unwrap();
+        hooks::commit_msg(&repo, &msg_file).expect("Run commit-msg");
+        assert_eq!(fs::read_to_string(&msg_file).unwrap(), "edited\n");
+        let cwd = fs::read_to_string(root.join("cwd")).unwrap();
+        assert_eq!(Path::new(cwd.trim()), root.canonicalize().unwrap());
+
+        write_hook(
+            &hooks_dir,
+            Hook::PrePush,
+            "echo \"$1 $2\" > push\ncat >> push\n",
+            0o755,
+        );
+        let update = PushUpdate {
+            local_ref: "refs/heads/main".to_owned(),
+            local_sha: "1".repeat(40),
+            remote_ref: "refs/heads/main".to_owned(),
+            remote_sha: "0".repeat(40),
+        };
+        hooks::pre_push(&repo, "origin", "/srv/repo.git", &[update])
+            .expect("Run pre-push");
+        assert_eq!(
+            fs::read_to_string(root.join("push")).unwrap(),
+            format!(
+                "origin /srv/repo.git\nrefs/heads/main {} refs/heads/main {}\n",
+                "1".repeat(40),
+                "0".repeat(40)
+            )
+        );
+
+        write_hook(
+            &hooks_dir,
+            Hook::PostCheckout,
+            "echo \"$@\" > checkout\n",
+            0o755,
+        );
+        hooks::post_checkout(&repo, "a", "b", true).expect("Run post-checkout");
+        assert_eq!(
+            fs::read_to_string(root.join("checkout")).unwrap(),
+            "a b 1\n"
+        );
+    }
+
+    #[test]
+    fn test_hooks_path() {
+        let tmp =
+            TempDir::create("hooks_hooks_path").with_mutex(&crate::TEST_MUTEX);
+        let root = tmp.tmp_dir().to_path_buf();
+        GitRepository::create(&root).expect("Create repo");
+        let config = root.join(".git").join("config");
+        let mut text = fs::read_to_string(&config).unwrap();
+        text.push_str("[core]\n\thooksPath = my-hooks\n");
+        fs::write(&config, text).unwrap();
+        let repo = GitRepository::new(&root).expect("Open repo");
+
+        write_hook(&root.join("my-hooks"), Hook::PreCommit, "exit 1\n", 0o755);
+        assert_eq!(
+            hooks::find(&repo, Hook::PreCommit),
+            Some(root.join("my-hooks").join("pre-commit"))
+        );
+        assert!(hooks::pre_commit(&repo).is_err());
+    }
+}
@@ -75,6 +75,281 @@ mod tests {
         check_expected_path(&tmp_dir.tmp_dir().join(args[0][0]));
     }
 
+    #[test]
+    fn test_cmd_init_bare() {
+        use mini_git::core::{resolve_repository_context, GitRepository};
+
+        let args: [&[&str]; 1] = [&["--bare", "repo.git"]];
+        let namespaces = make_namespaces(&args).next().unwrap();
+
+        let tmp_dir = TempDir::<()>::create("cmd_init_bare")
+            .with_mutex(&crate::TEST_MUTEX);
+
+        let res = switch_dir!(tmp_dir, { init(&namespaces) });
+        assert!(res.is_ok(), "{res:?}");
+
+        // The git directory is the repository itself
+        let root = tmp_dir.tmp_dir().join("repo.git");
+        assert!(!root.join(".git").exists());
+        for dir in ["objects", "refs/heads", "refs/tags"] {
+            assert!(root.join(dir).is_dir(), "{dir} expected");
+        }
+        assert!(root.join("HEAD").is_file());
+
+        let repo = GitRepository::new(&root).expect("Should open bare repo");
+        assert!(repo.is_bare());
+        assert_eq!(repo.gitdir(), root);
+        assert!(repo.require_worktree().is_err());
+        assert_eq!(
+            repo.config().get("core").and_then(|c| c.get_bool("bare")),
+            Some(true)
+        );
+
+        // The repository is found from within its git directory
+        let objects = root.join("objects");
+        let repo = switch_dir!(tmp_dir, {
+            std::env::set_current_dir(&objects).unwrap();
+            resolve_repository_context()
+        })
+        .expect("Should find bare repo")
+        .repo;
+        assert!(repo.is_bare());
+    }
+
+    #[test]
+    fn test_cmd_init_initial_branch() {
+        let args: [&[&str]; 3] = [
+            &["-b", "trunk", "explicit"],
+            &["configured"],
+            &["--initial-branch", "bad..name", "invalid"],
+        ];
+        let mut namespaces = make_namespaces(&args);
+        let explicit = namespaces.next().unwrap();
+        let configured = namespaces.next().unwrap();
+        let invalid = namespaces.next().unwrap();
+
+        let tmp_dir = TempDir::<()>::create("cmd_init_initial_branch")
+            .with_mutex(&crate::TEST_MUTEX);
+        let root = tmp_dir.tmp_dir().to_path_buf();
+        let head = |repo: &str| {
+            std::fs::read_to_string(root.join(repo).join(".git").join("HEAD"))
+                .unwrap()
+        };
+
+        let res = switch_dir!(tmp_dir, { init(&explicit) });
+        assert!(res.is_ok(), "{res:?}");
+        assert_eq!(head("explicit"), "ref: refs/heads/trunk\n");
+
+        // init.defaultBranch is read from the user's global config
+        std::fs::write(
+            root.join(".gitconfig"),
+            "[init]\n\tdefaultBranch = develop\n",
+        )
+        .unwrap();
+        let res = switch_dir!(tmp_dir, {
+            let home = std::env::var_os("HOME");
+            std::env::set_var("HOME", &root);
+            let res = init(&configured);
+            match home {
+                Some(home) => std::env::set_var("HOME", home),
+                None => std::env::remove_var("HOME"),
+            }
+            res
+        });
+        assert!(res.is_ok(), "{res:?}");
+        assert_eq!(head("configured"), "ref: refs/heads/develop\n");
+
+        let res = switch_dir!(tmp_dir, { init(&invalid) });
+        assert!(res.is_err());
+        assert!(!root.join("invalid").exists());
+    }
+
+    #[test]
+    fn test_cmd_init_separate_git_dir() {
+        use mini_git::core::{resolve_repository_context, GitRepository};
+
+        let args: [&[&str]; 2] = [
+            &["--separate-git-dir", "store.git", "work"],
+            &["--bare", "--separate-git-dir", "other.git", "bare"],
+        ];
+        let mut namespaces = make_namespaces(&args);
+        let separate = namespaces.next().unwrap();
+        let bare = namespaces.next().unwrap();
+
+        let tmp_dir = TempDir::<()>::create("cmd_init_separate_git_dir")
+            .with_mutex(&crate::TEST_MUTEX);
+        let root = tmp_dir.tmp_dir().canonicalize().unwrap();
+
+        let res = switch_dir!(tmp_dir, { init(&separate) });
+        assert!(res.is_ok(), "{res:?}");
+
+        // The working tree only has a .git file pointing to the git directory
+        let worktree = root.join("work");
+        let gitdir = root.join("store.git");
+        let gitfile = std::fs::read_to_string(worktree.join(".git")).unwrap();
+        assert_eq!(gitfile, format!("gitdir: {}\n", gitdir.display()));
+        for dir in ["objects", "refs/heads", "refs/tags"] {
+            assert!(gitdir.join(dir).is_dir(), "{dir} expected");
+        }
+
+        let repo = GitRepository::new(&worktree).expect("Should open repo");
+        assert!(!repo.is_bare());
+        assert_eq!(repo.worktree(), worktree);
+        assert_eq!(repo.gitdir(), gitdir);
+
+        let context = switch_dir!(tmp_dir, {
+            std::env::set_current_dir(&worktree).unwrap();
+            resolve_repository_context()
+        })
+        .expect("Should find repo");
+        assert_eq!(context.repo_path, worktree);
+        assert_eq!(context.repo.gitdir(), gitdir);
+
+        let res = switch_dir!(tmp_dir, { init(&bare) });
+        assert!(res.is_err());
+        assert!(!root.join("other.git").exists());
+    }
+
+    #[test]
+    fn test_cmd_init_template() {
+        use mini_git::core::GitRepository;
+
+        let args: [&[&str]; 2] = [
+            &["--template", "template", "repo"],
+            &["--template", "missing", "other"],
+        ];
+        let mut namespaces = make_namespaces(&args);
+        let with_template = namespaces.next().unwrap();
+        let missing = namespaces.next().unwrap();
+
+        let tmp_dir = TempDir::<()>::create("cmd_init_template")
+            .with_mutex(&crate::TEST_MUTEX);
+        let root = tmp_dir.tmp_dir().to_path_buf();
+
+        let template = root.join("template");
+        std::fs::create_dir_all(template.join("hooks")).unwrap();
+        std::fs::create_dir_all(template.join("info")).unwrap();
+        std::fs::write(template.join("hooks/pre-commit"), "exit 0\n").unwrap();
+        std::fs::write(template.join("info/exclude"), "*.log\n").unwrap();
+        std::fs::write(template.join("description"), "templated\n").unwrap();
+        std::fs::write(template.join("config"), "[user]\n\tname = me\n")
+            .unwrap();
+
+        let res = switch_dir!(tmp_dir, { init(&with_template) });
+        assert!(res.is_ok(), "{res:?}");
+
+        let gitdir = root.join("repo").join(".git");
+        let read = |file: &str| std::fs::read_to_string(gitdir.join(file));
+        assert_eq!(read("hooks/pre-commit").unwrap(), "exit 0\n");
+        assert_eq!(read("info/exclude").unwrap(), "*.log\n");
+        assert_eq!(read("description").unwrap(), "templated\n");
+        assert_eq!(read("HEAD").unwrap(), "ref: refs/heads/main\n");
+
+        // The template config is kept alongside the defaults
+        let repo = GitRepository::new(&root.join("repo")).unwrap();
+        let config = repo.config();
+        assert_eq!(config.get("user").and_then(|u| u.get("name")), Some("me"));
+        assert_eq!(
+            config.get("core").and_then(|c| c.get_bool("bare")),
+            Some(false)
+        );
+
+        let res = switch_dir!(tmp_dir, { init(&missing) });
+        assert!(res.is_ok(), "{res:?}");
+        assert!(res.unwrap().starts_with("warning: templates not found"));
+        check_expected_path(&root.join("other"));
+    }
+
+    #[test]
+    fn test_cmd_init_object_format() {
+        use mini_git::utils::configparser::ConfigParser;
+
+        let args: [&[&str]; 2] = [
+            &["--object-format", "sha256", "sha256"],
+            &["--object-format", "sha1", "sha1"],
+        ];
+        let mut namespaces = make_namespaces(&args);
+        let sha256 = namespaces.next().unwrap();
+        let sha1 = namespaces.next().unwrap();
+        assert!(make_namespaces(&[&["--object-format", "md5"]])
+            .next()
+            .is_none());
+
+        let tmp_dir = TempDir::<()>::create("cmd_init_object_format")
+            .with_mutex(&crate::TEST_MUTEX);
+        let root = tmp_dir.tmp_dir().to_path_buf();
+        let config = |repo: &str| {
+            ConfigParser::from(root.join(repo).join(".git/config").as_path())
+        };
+
+        let res = switch_dir!(tmp_dir, { init(&sha256) });
+        assert!(res.is_ok(), "{res:?}");
+        let sha256 = config("sha256");
+        assert_eq!(sha256["core"].get_int("repositoryformatversion"), Some(1));
+        assert_eq!(
+            sha256.get("extensions").and_then(|e| e.get("objectFormat")),
+            Some("sha256")
+        );
+
+        let res = switch_dir!(tmp_dir, { init(&sha1) });
+        assert!(res.is_ok(), "{res:?}");
+        let sha1 = config("sha1");
+        assert_eq!(sha1["core"].get_int("repositoryformatversion"), Some(0));
+        assert!(sha1.get("extensions").is_none());
+    }
+
+    #[test]
+    fn test_cmd_init_reinit() {
+        let args: [&[&str]; 4] = [
+            &["repo"],
+            &["-b", "trunk", "repo"],
+            &["--quiet", "repo"],
+            &["--object-format", "sha256", "repo"],
+        ];
+        let mut namespaces = make_namespaces(&args);
+        let first = namespaces.next().unwrap();
+        let again = namespaces.next().unwrap();
+        let quiet = namespaces.next().unwrap();
+        let other_format = namespaces.next().unwrap();
+
+        let tmp_dir = TempDir::<()>::create("cmd_init_reinit")
+            .with_mutex(&crate::TEST_MUTEX);
+        let gitdir = tmp_dir.tmp_dir().join("repo").join(".git");
+
+        let res = switch_dir!(tmp_dir, { init(&first) });
+        assert!(res.is_ok(), "{res:?}");
+
+        let config = "[core]\n\trepositoryformatversion = 0\n\tbare = false\n\
+                      [user]\n\tname = me\n";
+        std::fs::write(gitdir.join("config"), config).unwrap();
+        std::fs::write(gitdir.join("HEAD"), "ref: refs/heads/dev\n").unwrap();
+        std::fs::remove_dir_all(gitdir.join("refs").join("tags")).unwrap();
+
+        // HEAD and the config are kept, and missing directories are restored
+        let res = switch_dir!(tmp_dir, { init(&again) });
+        assert!(res.is_ok(), "{res:?}");
+        assert_eq!(
+            res.unwrap().lines().collect::<Vec<_>>()[..],
+            [
+                "warning: re-init: ignored --initial-branch=trunk",
+                &format!(
+                    "Reinitialized existing Git repository in {:?}",
+                    tmp_dir.tmp_dir().join("repo").as_os_str()
+                )
+            ]
+        );
+        let read = |file: &str| std::fs::read_to_string(gitdir.join(file));
+        assert_eq!(read("HEAD").unwrap(), "ref: refs/heads/dev\n");
+        assert_eq!(read("config").unwrap(), config);
+        check_expected_path(&tmp_dir.tmp_dir().join("repo"));
+
+        let res = switch_dir!(tmp_dir, { init(&quiet) });
+        assert_eq!(res.unwrap(), "");
+
+        let res = switch_dir!(tmp_dir, { init(&other_format) });
+        assert!(res.is_err());
+    }
+
     #[test]
     fn test_cmd_init_extra_args() {
         let args: [&[&str]; 1] = [&["new_repo", "arg1", "arg2"]];
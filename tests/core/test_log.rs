@@ -1,6 +1,9 @@
 #[cfg(test)]
 mod tests {
     use crate::make_namespaces_from;
+    use crate::utils::fixtures::{
+        set_head, temp_repo, write_commit, write_tree,
+    };
 
     use mini_git::core::commands::log::*;
     use mini_git::core::objects::commit::Commit;
@@ -139,6 +142,41 @@ Second commit"
         assert!(index_second < index_initial);
     }
 
+    #[test]
+    fn test_log_json() {
+        setup();
+
+        let args: [&[&str]; 1] = [&["--json", "--oneline"]];
+
+        let res = switch_dir!({
+            let namespace = make_namespaces(&args).next().unwrap();
+            log(&namespace)
+        });
+
+        let (a, b) = ("a".repeat(40), "b".repeat(40));
+        assert_eq!(
+            res.unwrap(),
+            format!(
+                "[{{\"commit\":\"{b}\",\"parents\":[\"{a}\"],\
+                 \"author\":{{\"name\":\"Jane Smith\",\
+                 \"email\":\"jane@example.com\",\
+                 \"date\":\"2009-02-14T01:31:30+02:00\"}},\
+                 \"committer\":{{\"name\":\"Jane Smith\",\
+                 \"email\":\"jane@example.com\",\
+                 \"date\":\"2009-02-14T01:31:30+02:00\"}},\
+                 \"message\":\"Second commit\"}},\
+                 {{\"commit\":\"{a}\",\"parents\":[],\
+                 \"author\":{{\"name\":\"John Doe\",\
+                 \"email\":\"john@example.com\",\
+                 \"date\":\"2021-08-02T09:42:03+02:00\"}},\
+                 \"committer\":{{\"name\":\"John Doe\",\
+                 \"email\":\"john@example.com\",\
+                 \"date\":\"2021-08-02T09:42:03+02:00\"}},\
+                 \"message\":\"Initial commit\"}}]"
+            )
+        );
+    }
+
     #[test]
     fn test_log_max_commits() {
         setup();
@@ -185,6 +223,38 @@ Second commit"
         }
     }
 
+    #[test]
+    fn test_log_abbrev_commit() {
+        setup();
+
+        let args: [&[&str]; 1] = [&["--abbrev-commit", "--color", "always"]];
+
+        let res = switch_dir!({
+            let namespace = make_namespaces(&args).next().unwrap();
+            log(&namespace)
+        });
+
+        let output = res.expect("Log should succeed");
+        assert!(output.contains(&format!("commit {YELLOW}bbbbbbb{RESET}\n")));
+        assert!(output.contains(&format!("commit {YELLOW}aaaaaaa{RESET}\n")));
+    }
+
+    #[test]
+    fn test_log_color_never() {
+        setup();
+
+        let args: [&[&str]; 1] = [&["--abbrev-commit", "--color", "never"]];
+
+        let res = switch_dir!({
+            let namespace = make_namespaces(&args).next().unwrap();
+            log(&namespace)
+        });
+
+        let output = res.expect("Log should succeed");
+        assert!(output.contains("commit bbbbbbb\n"));
+        assert!(!output.contains('\x1b'));
+    }
+
     #[test]
     fn test_log_no_author() {
         setup();
@@ -219,4 +289,221 @@ Second commit"
         assert!(output.contains("Initial commit"));
         assert!(!output.contains("Second commit"));
     }
+
+    fn create_history_repo<'a>() -> TempDir<'a, ()> {
+        let (tmp, repo) = temp_repo("cmd_log_history");
+
+        let tree1 = write_tree(&repo, &[("a.txt", "one\n")]);
+        let first = write_commit(&repo, &tree1, &[], 1_627_890_123, "Add a");
+        let tree2 =
+            write_tree(&repo, &[("a.txt", "one\ntwo\n"), ("b.txt", "bee\n")]);
+        let second =
+            write_commit(&repo, &tree2, &[first], 1_627_890_124, "Add b");
+
+        set_head(&repo, &second.to_string());
+
+        tmp
+    }
+
+    #[test]
+    fn test_log_stat() {
+        let tmp = create_history_repo();
+        let args: [&[&str]; 1] = [&["--stat"]];
+
+        let output = tmp
+            .run(|| {
+                let namespace = make_namespaces(&args).next().unwrap();
+                log(&namespace)
+            })
+            .expect("Log should succeed");
+
+        let second = output.find("Add b").unwrap();
+        let first = output.find("Add a").unwrap();
+        let stat_b = output.find("b.txt | 1").unwrap();
+        assert!(second < stat_b && stat_b < first);
+        // The root commit is diffed against the empty tree
+        assert!(output[first..].contains("a.txt | 1"));
+        assert!(!output.contains("diff --mini-git"));
+    }
+
+    #[test]
+    fn test_log_patch() {
+        let tmp = create_history_repo();
+        let args: [&[&str]; 1] = [&["-p", "-n", "1"]];
+
+        let output = tmp
+            .run(|| {
+                let namespace = make_namespaces(&args).next().unwrap();
+                log(&namespace)
+            })
+            .expect("Log should succeed");
+
+        assert!(output.contains("diff --mini-git a/a.txt b/a.txt"));
+        assert!(output.contains("+two"));
+        assert!(output.contains("new file mode 100644"));
+        assert!(!output.contains("Add a"));
+    }
+
+    #[test]
+    fn test_log_oneline_unique_prefix() {
+        let tmp = create_history_repo();
+
+        // Plant an object that shares the first 9 characters of HEAD
+        let head = tmp
+            .run(|| {
+                let args: [&[&str]; 1] = [&["--color", "always"]];
+                let namespace = make_namespaces(&args).next().unwrap();
+                log(&namespace)
+            })
+            .expect("Log should succeed")
+            .lines()
+            .next()
+            .and_then(|line| line.split(YELLOW).nth(1))
+            .and_then(|rest| rest.split(RESET).next())
+            .expect("Should have a commit line")
+            .to_owned();
+        let mut lookalike = head[..9].to_owned();
+        lookalike.push(if head.as_bytes()[9] == b'0' { '1' } else { '0' });
+        lookalike.push_str(&"0".repeat(30));
+        let objects = tmp.tmp_dir().join(".git").join("objects");
+        std::fs::write(objects.join(&head[..2]).join(&lookalike[2..]), b"")
+            .expect("Should write");
+
+        let output = tmp
+            .run(|| {
+                let args: [&[&str]; 1] =
+                    [&["--oneline", "-n", "1", "--color", "always"]];
+                let namespace = make_namespaces(&args).next().unwrap();
+                log(&namespace)
+            })
+            .expect("Log should succeed");
+
+        assert!(output
+            .starts_with(&format!("{YELLOW}{}{RESET} Add b", &head[..10])));
+    }
+
+    fn create_merge_repo<'a>() -> TempDir<'a, ()> {
+        let (tmp, repo) = temp_repo("cmd_log_merges");
+
+        let tree = write_tree(&repo, &[("a.txt", "one\n")]);
+        let base = write_commit(&repo, &tree, &[], 100, "Base");
+        let topic = write_commit(&repo, &tree, &[base], 200, "Topic work");
+        let main = write_commit(&repo, &tree, &[base], 300, "Main work");
+        let merge =
+            write_commit(&repo, &tree, &[main, topic], 400, "Merge topic");
+
+        set_head(&repo, &merge.to_string());
+
+        tmp
+    }
+
+    fn run_merge_log(args: &[&str]) -> Vec<String> {
+        let tmp = create_merge_repo();
+        let args: [&[&str]; 1] = [args];
+        tmp.run(|| {
+            let namespace = make_namespaces(&args).next().unwrap();
+            log(&namespace)
+        })
+        .expect("Log should succeed")
+        .lines()
+        .filter_map(|line| line.strip_prefix("    "))
+        .map(String::from)
+        .collect()
+    }
+
+    #[test]
+    fn test_log_follows_all_parents() {
+        assert_eq!(
+            run_merge_log(&[]),
+            ["Merge topic", "Main work", "Topic work", "Base"]
+        );
+    }
+
+    #[test]
+    fn test_log_first_parent() {
+        assert_eq!(
+            run_merge_log(&["--first-parent"]),
+            ["Merge topic", "Main work", "Base"]
+        );
+    }
+
+    #[test]
+    fn test_log_merges() {
+        assert_eq!(run_merge_log(&["--merges"]), ["Merge topic"]);
+        assert_eq!(
+            run_merge_log(&["--first-parent", "--merges"]),
+            ["Merge topic"]
+        );
+    }
+
+    #[test]
+    fn test_log_no_merges() {
+        assert_eq!(
+            run_merge_log(&["--no-merges"]),
+            ["Main work", "Topic work", "Base"]
+        );
+    }
+
+    fn run_history_log(args: &[&str]) -> Result<Vec<String>, String> {
+        let tmp = create_history_repo();
+        let args: [&[&str]; 1] = [args];
+        let output = tmp.run(|| {
+            let namespace = make_namespaces(&args).next().unwrap();
+            log(&namespace)
+        })?;
+        Ok(output
+            .lines()
+            .filter_map(|line| line.strip_prefix("    "))
+            .map(String::from)
+            .collect())
+    }
+
+    #[test]
+    fn test_log_pickaxe_string() {
+        assert_eq!(run_history_log(&["-S", "two"]).unwrap(), ["Add b"]);
+        assert_eq!(run_history_log(&["-S", "one"]).unwrap(), ["Add a"]);
+        assert!(run_history_log(&["-S", "three"]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_log_pickaxe_regex() {
+        assert_eq!(run_history_log(&["-G", "^t.o$"]).unwrap(), ["Add b"]);
+        assert_eq!(run_history_log(&["-G", "e{2}"]).unwrap(), ["Add b"]);
+        assert_eq!(
+            run_history_log(&["-G", "^(one|bee)$"]).unwrap(),
+            ["Add b", "Add a"]
+        );
+    }
+
+    #[test]
+    fn test_log_pickaxe_errors() {
+        assert!(run_history_log(&["-S", "one", "-G", "one"]).is_err());
+        assert!(run_history_log(&["-G", "(unbalanced"]).is_err());
+    }
+
+    #[test]
+    fn test_log_date_formats() {
+        let tmp = create_history_repo();
+        let run = |format: &str| {
+            let args: [&[&str]; 1] = [&["--date", format, "-n", "1"]];
+            tmp.run(|| {
+                let namespace = make_namespaces(&args).next()?;
+                log(&namespace).ok()
+            })
+        };
+
+        let output = run("iso").expect("Log should succeed");
+        assert!(output.contains("Date:   2021-08-02 09:42:04 +0200\n"));
+        let output = run("iso-strict").expect("Log should succeed");
+        assert!(output.contains("Date:   2021-08-02T09:42:04+02:00\n"));
+        let output = run("rfc").expect("Log should succeed");
+        assert!(output.contains("Date:   Mon, 2 Aug 2021 09:42:04 +0200\n"));
+        let output = run("short").expect("Log should succeed");
+        assert!(output.contains("Date:   2021-08-02\n"));
+        let output = run("unix").expect("Log should succeed");
+        assert!(output.contains("Date:   1627890124\n"));
+        let output = run("relative").expect("Log should succeed");
+        assert!(output.contains(" ago\n"));
+        assert!(run("yesterday").is_none());
+    }
 }
@@ -0,0 +1,397 @@
+#[cfg(test)]
+mod tests {
+    use crate::make_namespaces_from;
+
+    use mini_git::core::commands::ls_files::*;
+    use mini_git::core::index::{GitIndex, IndexEntry};
+    use mini_git::core::objects::{blob::Blob, hash_object, GitObject};
+    use mini_git::core::{GitRepository, ObjectFormat};
+
+    use mini_git::utils::test::TempDir;
+
+    make_namespaces_from!(make_parser);
+
+    const TRACKED: &[&str] = &[
+        ".gitignore",
+        "conflict.txt",
+        "src/lib.rs",
+        "src/nested/mod.rs",
+    ];
+
+    const FILES: &[(&str, &str)] = &[
+        (".gitignore", "*.log\n!keep.log\ntarget/\n"),
+        ("src/lib.rs", "lib"),
+        ("src/nested/mod.rs", "mod"),
+    ];
+
+    fn blob_sha(content: &str) -> String {
+        let blob = GitObject::Blob(Blob::from(content.as_bytes()));
+        hash_object(&blob, ObjectFormat::Sha1).1.hex_digest()
+    }
+
+    fn create_temp_repo<'a>() -> TempDir<'a, ()> {
+        let tmp =
+            TempDir::create("cmd_ls_files").with_mutex(&crate::TEST_MUTEX);
+        let repo = GitRepository::create(tmp.tmp_dir()).expect("Create repo");
+
+        let untracked = [
+            ("src/notes.txt", "notes"),
+            ("src/nested/scratch.tmp", "scratch"),
+            ("debug.log", "debug"),
+            ("keep.log", "keep"),
+            ("target/out", "out"),
+            ("docs/guide/intro.md", "intro"),
+            ("docs/readme.md", "readme"),
+        ];
+        for (path, content) in FILES.iter().chain(&untracked) {
+            let path = tmp.tmp_dir().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).expect("Mkdir");
+            std::fs::write(path, content).expect("Write file");
+        }
+
+        std::fs::create_dir_all(repo.gitdir().join("info")).expect("Mkdir");
+        std::fs::write(repo.gitdir().join("info").join("exclude"), "*.tmp\n")
+            .expect("Write exclude");
+
+        let mut index = GitIndex::new();
+        for (path, content) in FILES {
+            index.add(IndexEntry::new(0o100_644, &blob_sha(content), path));
+        }
+        // A merge conflict leaves the base, ours and theirs versions
+        for (stage, sha) in [(1u16, "b"), (2, "c"), (3, "d")] {
+            let mut entry =
+                IndexEntry::new(0o100_644, &sha.repeat(40), "conflict.txt");
+            entry.flags |= stage << 12;
+            index.add(entry);
+        }
+        index.write(&repo).expect("Write index");
+
+        tmp
+    }
+
+    fn run_ls_files(args: &[&str]) -> Vec<String> {
+        let tmp = create_temp_repo();
+        let args: [&[&str]; 1] = [args];
+        let res = tmp.run(|| {
+            let namespace = make_namespaces(&args).next().unwrap();
+            ls_files(&namespace).map_err(String::from)
+        });
+        res.expect("ls-files").lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_cached_by_default() {
+        assert_eq!(run_ls_files(&[]), TRACKED);
+        assert_eq!(run_ls_files(&["--cached"]), TRACKED);
+    }
+
+    #[test]
+    fn test_others() {
+        assert_eq!(
+            run_ls_files(&["--others"]),
+            [
+                "debug.log",
+                "docs/guide/intro.md",
+                "docs/readme.md",
+                "keep.log",
+                "src/nested/scratch.tmp",
+                "src/notes.txt",
+                "target/out",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_others_exclude_standard() {
+        assert_eq!(
+            run_ls_files(&["-o", "--exclude-standard"]),
+            [
+                "docs/guide/intro.md",
+                "docs/readme.md",
+                "keep.log",
+                "src/notes.txt",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_others_global_excludes_file() {
+        let tmp = create_temp_repo();
+        let home = tmp.tmp_dir().join("home");
+        std::fs::create_dir_all(&home).unwrap();
+        std::fs::write(
+            home.join(".gitconfig"),
+            "[core]\n\texcludesFile = ~/ignore\n",
+        )
+        .unwrap();
+        std::fs::write(home.join("ignore"), "*.md\nhome/\n").unwrap();
+
+        // core.excludesFile is read from the user's global config
+        let args: [&[&str]; 1] = [&["-o", "--exclude-standard"]];
+        let res = tmp.run(|| {
+            let namespace = make_namespaces(&args).next().unwrap();
+            let old_home = std::env::var_os("HOME");
+            std::env::set_var("HOME", &home);
+            let res = ls_files(&namespace);
+            match old_home {
+                Some(old_home) => std::env::set_var("HOME", old_home),
+                None => std::env::remove_var("HOME"),
+            }
+            res
+        });
+        let res = res.expect("ls-files");
+        assert_eq!(
+            res.lines().collect::<Vec<_>>(),
+            ["keep.log", "src/notes.txt"]
+        );
+    }
+
+    #[test]
+    fn test_others_directory() {
+        assert_eq!(
+            run_ls_files(&["-o", "--exclude-standard", "--directory"]),
+            ["docs/", "keep.log", "src/notes.txt"]
+        );
+    }
+
+    #[test]
+    fn test_cached_and_others() {
+        // Untracked files come first, like git
+        let mut expected = vec!["docs/", "keep.log", "src/notes.txt"];
+        expected.extend(TRACKED);
+        assert_eq!(
+            run_ls_files(&["-c", "-o", "--exclude-standard", "--directory"]),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_stage() {
+        let staged = |i: usize| {
+            let (path, content) = FILES[i];
+            format!("100644 {} 0\t{path}", blob_sha(content))
+        };
+        assert_eq!(
+            run_ls_files(&["--stage"]),
+            [
+                staged(0),
+                format!("100644 {} 1\tconflict.txt", "b".repeat(40)),
+                format!("100644 {} 2\tconflict.txt", "c".repeat(40)),
+                format!("100644 {} 3\tconflict.txt", "d".repeat(40)),
+                staged(1),
+                staged(2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_nul_terminated() {
+        let tmp = create_temp_repo();
+        let args: [&[&str]; 2] =
+            [&["-z"], &["-z", "-o", "--exclude-standard", "--directory"]];
+        let res = tmp
+            .run(|| {
+                make_namespaces(&args)
+                    .map(|namespace| ls_files(&namespace).map_err(String::from))
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .expect("ls-files");
+
+        assert_eq!(
+            res[0],
+            ".gitignore\0conflict.txt\0src/lib.rs\0src/nested/mod.rs\0"
+        );
+        assert_eq!(res[1], "docs/\0keep.log\0src/notes.txt\0");
+    }
+
+    #[test]
+    fn test_modified_and_deleted() {
+        let tmp = create_temp_repo();
+        std::fs::write(tmp.tmp_dir().join("src/lib.rs"), "changed")
+            .expect("Modify file");
+        std::fs::remove_file(tmp.tmp_dir().join("src/nested/mod.rs"))
+            .expect("Delete file");
+
+        let args: [&[&str]; 3] = [&["-m"], &["-d"], &["-c", "-d", "src"]];
+        let res = tmp
+            .run(|| {
+                make_namespaces(&args)
+                    .map(|namespace| ls_files(&namespace).map_err(String::from))
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .expect("ls-files");
+
+        // The conflicted path has no file in the worktree, so is deleted
+        assert_eq!(res[0], "conflict.txt\nsrc/lib.rs\nsrc/nested/mod.rs\n");
+        assert_eq!(res[1], "conflict.txt\nsrc/nested/mod.rs\n");
+        assert_eq!(
+            res[2],
+            "src/lib.rs\nsrc/nested/mod.rs\nsrc/nested/mod.rs\n"
+        );
+    }
+
+    #[test]
+    fn test_unchanged_files_not_modified() {
+        assert_eq!(run_ls_files(&["-m", "src"]), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_error_unmatch() {
+        let tmp = create_temp_repo();
+        let args: [&[&str]; 3] = [
+            &["--error-unmatch", "src/lib.rs", "src/nested"],
+            &["--error-unmatch", "src/lib.rs", "src/notes.txt"],
+            &["-o", "--error-unmatch", "src/notes.txt"],
+        ];
+        let res = tmp.run(|| {
+            make_namespaces(&args)
+                .map(|namespace| ls_files(&namespace).map_err(String::from))
+                .collect::<Vec<_>>()
+        });
+
+        assert_eq!(res[0], Ok("src/lib.rs\nsrc/nested/mod.rs\n".to_owned()));
+        assert!(res[1]
+            .as_ref()
+            .is_err_and(|e| e.contains("pathspec 'src/notes.txt'")));
+        assert_eq!(res[2], Ok("src/notes.txt\n".to_owned()));
+    }
+
+    #[test]
+    fn test_split_index() {
+        let tmp = create_temp_repo();
+        let gitdir = tmp.tmp_dir().join(".git");
+        let config = gitdir.join("config");
+        let mut text = std::fs::read_to_string(&config).unwrap();
+        text.push_str("[core]\n\tsplitIndex = true\n");
+        std::fs::write(&config, text).unwrap();
+
+        let shared_files = || {
+            std::fs::read_dir(&gitdir)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name())
+                .filter(|name| {
+                    name.to_string_lossy().starts_with("sharedindex.")
+                })
+                .count()
+        };
+
+        // The first write moves every entry to a new shared index
+        let repo = GitRepository::new(tmp.tmp_dir()).expect("Open repo");
+        let whole = std::fs::read(gitdir.join("index")).unwrap();
+        let index = GitIndex::read(&repo).expect("Read index");
+        index.write(&repo).expect("Write index");
+        assert_eq!(shared_files(), 1);
+        let raw = std::fs::read(gitdir.join("index")).unwrap();
+        assert!(GitIndex::parse(&raw).is_err());
+        let mut read = GitIndex::read(&repo).expect("Read index");
+        assert_eq!(read.entries(), index.entries());
+
+        // Changing an entry only writes that entry to the split index
+        read.add(IndexEntry::new(0o100_644, &"e".repeat(40), "src/lib.rs"));
+        read.write(&repo).expect("Write index");
+        assert_eq!(shared_files(), 1);
+        let split = std::fs::read(gitdir.join("index")).unwrap();
+        assert!(split.len() < whole.len(), "{}", split.len());
+        let index = read;
+        let read = GitIndex::read(&repo).expect("Read index");
+        assert_eq!(read.entries(), index.entries());
+
+        let args: [&[&str]; 1] = [&["--stage", "src/lib.rs"]];
+        let res = tmp.run(|| {
+            let namespace = make_namespaces(&args).next().unwrap();
+            ls_files(&namespace).map_err(String::from)
+        });
+        assert_eq!(
+            res,
+            Ok(format!("100644 {} 0\tsrc/lib.rs\n", "e".repeat(40)))
+        );
+
+        // Too many changes write a new shared index, replacing the old one
+        let mut read = read;
+        for path in ["x", "y", "z"] {
+            read.add(IndexEntry::new(0o100_644, &"f".repeat(40), path));
+        }
+        read.write(&repo).expect("Write index");
+        assert_eq!(shared_files(), 1);
+        assert_eq!(GitIndex::read(&repo).unwrap().entries(), read.entries());
+    }
+
+    #[test]
+    fn test_untracked_cache() {
+        let tmp = create_temp_repo();
+        let root = tmp.tmp_dir().to_path_buf();
+        let gitdir = root.join(".git");
+        let config = gitdir.join("config");
+        let mut text = std::fs::read_to_string(&config).unwrap();
+        text.push_str("[core]\n\tuntrackedCache = true\n");
+        std::fs::write(&config, text).unwrap();
+
+        let args: [&[&str]; 1] = [&["-o", "--exclude-standard"]];
+        let run = || {
+            let res = tmp.run(|| {
+                let namespace = make_namespaces(&args).next().unwrap();
+                ls_files(&namespace)
+            });
+            res.expect("ls-files").lines().map(String::from).collect()
+        };
+        let untracked: Vec<String> = run();
+        assert_eq!(
+            untracked,
+            [
+                "docs/guide/intro.md",
+                "docs/readme.md",
+                "keep.log",
+                "src/notes.txt"
+            ]
+        );
+
+        // The listings are saved in the index, and survive a round trip
+        let repo = GitRepository::new(&root).expect("Open repo");
+        let raw = std::fs::read(gitdir.join("index")).unwrap();
+        let index = GitIndex::parse(&raw).expect("Parse index");
+        assert_eq!(index.serialize(), raw);
+        let cache = index.untracked_cache().expect("Untracked cache");
+        assert_eq!(cache.untracked("src"), Some(&["notes.txt".to_owned()][..]));
+        assert!(cache.untracked("docs/guide").is_some());
+        assert!(cache.untracked("missing").is_none());
+
+        // A directory whose modification time is unchanged is not read again
+        let mtime = std::fs::metadata(&root).unwrap().modified().unwrap();
+        std::fs::write(root.join("hidden.txt"), "hidden").unwrap();
+        std::fs::File::open(&root)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+        assert_eq!(run(), untracked);
+
+        // Adding a file changes the modification time of its directory
+        std::fs::write(root.join("src/new.rs"), "new").unwrap();
+        assert!(run().contains(&"src/new.rs".to_owned()));
+
+        // Staging a file invalidates the listings of its directories
+        let mut index = GitIndex::read(&repo).expect("Read index");
+        index.add(IndexEntry::new(
+            0o100_644,
+            &"e".repeat(40),
+            "docs/readme.md",
+        ));
+        assert!(index.untracked_cache().unwrap().untracked("docs").is_none());
+        assert!(index.untracked_cache().unwrap().untracked("src").is_some());
+        index.write(&repo).expect("Write index");
+        assert!(!run().contains(&"docs/readme.md".to_owned()));
+
+        // Changing the ignore rules drops the listings
+        std::fs::write(gitdir.join("info").join("exclude"), "*.tmp\n*.txt\n")
+            .unwrap();
+        assert_eq!(run(), ["docs/guide/intro.md", "keep.log", "src/new.rs"]);
+        std::fs::write(root.join("src/.gitignore"), "*.rs\n").unwrap();
+        std::fs::File::open(root.join("src"))
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+        assert_eq!(
+            run(),
+            ["docs/guide/intro.md", "keep.log", "src/.gitignore"]
+        );
+    }
+}
@@ -5,6 +5,7 @@ mod tests {
     use mini_git::core::commands::ls_tree::*;
     use mini_git::core::objects::traits::Serialize;
     use mini_git::core::objects::tree::{Leaf, Tree};
+    use mini_git::core::objects::{blob::Blob, write_object, GitObject};
     use mini_git::core::GitRepository;
 
     use mini_git::utils::test::TempDir;
@@ -368,4 +369,174 @@ mod tests {
         ];
         check_output(&expected, &res);
     }
+
+    #[test]
+    fn test_path_filter() {
+        setup();
+
+        let root = "f".repeat(40);
+        let args: [&[&str]; 3] = [
+            &[&root, "dir1", "readme.md"],
+            &[&root, "dir1/"],
+            &["-r", &root, "dir2/subdir2"],
+        ];
+
+        let res = switch_dir!({
+            make_namespaces(&args)
+                .map(|namespace| ls_tree(&namespace))
+                .collect::<Result<Vec<_>, _>>()
+        });
+
+        assert!(res.is_ok());
+        let res = res.unwrap();
+        check_output(
+            &[exp_tree!("0", "dir1"), exp_blob!("3", "readme.md")],
+            &res[0],
+        );
+        check_output(
+            &[
+                exp_blob!("6", join_path("dir1", "file1")),
+                exp_blob!("7", join_path("dir1", "file2")),
+                exp_tree!("5", join_path("dir1", "subdir1")),
+            ],
+            &res[1],
+        );
+        let subdir2 = join_path("dir2", "subdir2");
+        check_output(
+            &[
+                exp_blob!("c", join_path(&subdir2, "subfile1")),
+                exp_blob!("d", join_path(&subdir2, "subfile2")),
+            ],
+            &res[2],
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        setup();
+
+        let root = "f".repeat(40);
+        let args: [&[&str]; 1] = [&["--json", &root, "dir1", "readme.md"]];
+
+        let res = switch_dir!({
+            let namespace = make_namespaces(&args).next().unwrap();
+            ls_tree(&namespace)
+        });
+
+        assert_eq!(
+            res.unwrap(),
+            format!(
+                "[{{\"mode\":\"040000\",\"type\":\"tree\",\"oid\":\"{}\",\
+                 \"path\":\"dir1\"}},\
+                 {{\"mode\":\"100644\",\"type\":\"blob\",\"oid\":\"{}\",\
+                 \"path\":\"readme.md\"}}]",
+                "0".repeat(40),
+                "3".repeat(40)
+            )
+        );
+    }
+
+    #[test]
+    fn test_name_only() {
+        setup();
+
+        let root = "f".repeat(40);
+        let args: [&[&str]; 3] = [
+            &["--name-only", &root],
+            &["--name-status", "-r", &root, "dir1/subdir1"],
+            &["--name-only", "-z", &root],
+        ];
+
+        let res = switch_dir!({
+            make_namespaces(&args)
+                .map(|namespace| ls_tree(&namespace))
+                .collect::<Result<Vec<_>, _>>()
+        });
+
+        assert!(res.is_ok());
+        let res = res.unwrap();
+        assert_eq!(res[0], "dir1\ndir2\nreadme.md\ntest.file\n");
+        let subdir1 = join_path("dir1", "subdir1");
+        assert_eq!(
+            res[1],
+            format!(
+                "{}\n{}\n",
+                join_path(&subdir1, "subfile1"),
+                join_path(&subdir1, "subfile2")
+            )
+        );
+        assert_eq!(res[2], "dir1\0dir2\0readme.md\0test.file\0");
+    }
+
+    #[test]
+    fn test_nul_terminated() {
+        setup();
+
+        let args: [&[&str]; 1] = [&["-z", &"f".repeat(40), "readme.md"]];
+
+        let res = switch_dir!({
+            let namespace = make_namespaces(&args).next().unwrap();
+            ls_tree(&namespace)
+        });
+
+        assert!(res.is_ok());
+        assert_eq!(
+            res.unwrap(),
+            format!("100644 blob {}\treadme.md\0", "3".repeat(40))
+        );
+    }
+
+    #[test]
+    fn test_long_shows_blob_sizes() {
+        let tmp = TempDir::<()>::create("cmd_ls_tree_long")
+            .with_mutex(&crate::TEST_MUTEX);
+        let repo = GitRepository::create(tmp.tmp_dir()).expect("Create repo");
+
+        let write_blob = |content: &[u8]| {
+            let blob = GitObject::Blob(Blob::from(content));
+            write_object(&blob, &repo).expect("Write blob").to_string()
+        };
+        let small = write_blob(b"hello\n");
+        let large = write_blob(&[b'x'; 12345]);
+
+        let mut subtree = Tree::new();
+        subtree.set_leaves(vec![Leaf::new(b"100644", b"large", &large)]);
+        let subtree = write_object(&GitObject::Tree(subtree), &repo)
+            .expect("Write tree")
+            .to_string();
+
+        let mut root = Tree::new();
+        root.set_leaves(vec![
+            Leaf::new(b"040000", b"dir", &subtree),
+            Leaf::new(b"100644", b"small", &small),
+        ]);
+        let root = write_object(&GitObject::Tree(root), &repo)
+            .expect("Write tree")
+            .to_string();
+
+        let args: [&[&str]; 2] = [&["-l", &root], &["-r", "--long", &root]];
+        let res = tmp
+            .run(|| {
+                make_namespaces(&args)
+                    .map(|namespace| ls_tree(&namespace))
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .expect("ls-tree");
+
+        assert_eq!(
+            res[0],
+            format!(
+                "040000 tree {subtree}       -\tdir\n\
+                 100644 blob {small}       6\tsmall\n"
+            )
+        );
+        assert_eq!(
+            res[1],
+            format!(
+                "100644 blob {large}   12345\t{}\n\
+                 100644 blob {small}       6\tsmall\n",
+                join_path("dir", "large")
+            )
+        );
+    }
 }
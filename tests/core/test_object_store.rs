@@ -0,0 +1,435 @@
+#[cfg(test)]
+mod tests {
+    use mini_git::core::objects::blob::Blob;
+    use mini_git::core::objects::oid::Oid;
+    use mini_git::core::objects::packwriter::{store_pack, PackWriter};
+    use mini_git::core::objects::store::{
+        LooseStore, ObjectCache, ObjectDatabase, ObjectStore,
+    };
+    use mini_git::core::objects::traits::Deserialize;
+    use mini_git::core::objects::{
+        abbreviate_object, big_file_threshold, find_object, hash_object_stream,
+        object_size, read_object, read_object_stream, write_object,
+        write_object_stream, GitObject, DEFAULT_BIG_FILE_THRESHOLD,
+    };
+    use mini_git::core::{GitRepository, ObjectFormat};
+
+    use mini_git::utils::test::TempDir;
+
+    use std::io::Read;
+
+    fn blob(data: &[u8]) -> GitObject {
+        GitObject::Blob(Blob::deserialize(data).unwrap())
+    }
+
+    #[test]
+    fn test_database_order_and_writes() {
+        let tmp = TempDir::create("object_store_database")
+            .with_mutex(&crate::TEST_MUTEX);
+        let root = tmp.tmp_dir().canonicalize().unwrap();
+        let first = root.join("first");
+        let second = root.join("second");
+
+        let mut db = ObjectDatabase::new()
+            .with_store(LooseStore::new(&first, false))
+            .with_store(LooseStore::new(&second, true));
+
+        let readme = blob(b"readme.md\n");
+        let sha = db.write(&readme).expect("Write object");
+        assert_eq!(sha, "cdb5f04f10c21998fd7406f7e8ceafd2035d83e2");
+
+        // Only the writable store is written to
+        assert!(!first.join("cd").exists());
+        assert!(second.join("cd").join(&sha[2..]).is_file());
+
+        assert!(db.contains(&sha));
+        assert_eq!(db.read(&sha).unwrap().serialize(), readme.serialize());
+        assert_eq!(db.size(&sha).unwrap(), 10);
+        assert_eq!(db.ids_with_prefix("cdb5").unwrap(), [sha.as_str()]);
+        assert!(db.ids_with_prefix("cdb6").unwrap().is_empty());
+
+        // An object some store has is not written again
+        let mut store = LooseStore::new(&second, true);
+        assert!(store.contains(&sha));
+        let mut db = ObjectDatabase::new()
+            .with_store(LooseStore::new(&second, false))
+            .with_store(LooseStore::new(&first, true));
+        assert_eq!(db.write(&readme).unwrap(), sha);
+        assert!(!first.join("cd").exists());
+
+        // Read-only stores refuse writes
+        assert!(LooseStore::new(&first, false)
+            .write(&sha, b"blob 0\0")
+            .is_err());
+        let mut db = ObjectDatabase::new();
+        assert!(db
+            .write(&readme)
+            .unwrap_err()
+            .to_string()
+            .contains("no store"));
+        assert!(db.read(&sha).unwrap_err().to_string().contains("not found"));
+        assert_eq!(
+            store.read(&sha).unwrap().map(|obj| obj.serialize()),
+            Some(readme.serialize())
+        );
+    }
+
+    #[test]
+    fn test_alternates() {
+        let tmp = TempDir::create("object_store_alternates")
+            .with_mutex(&crate::TEST_MUTEX);
+        let root = tmp.tmp_dir().canonicalize().unwrap();
+        let shared =
+            GitRepository::create(&root.join("shared")).expect("Create repo");
+        let repo =
+            GitRepository::create(&root.join("repo")).expect("Create repo");
+
+        let readme = blob(b"readme.md\n");
+        let sha = write_object(&readme, &shared).unwrap();
+        assert!(read_object(&repo, &sha).is_err());
+
+        // Relative alternates are relative to the object directory, and
+        // cycles back to the repository are ignored
+        for name in ["shared", "repo"] {
+            std::fs::create_dir_all(root.join(name).join(".git/objects/info"))
+                .unwrap();
+        }
+        std::fs::write(
+            root.join("shared/.git/objects/info/alternates"),
+            "../../../repo/.git/objects\n",
+        )
+        .unwrap();
+        std::fs::write(
+            root.join("repo/.git/objects/info/alternates"),
+            "# shared objects\n../../../shared/.git/objects\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            read_object(&repo, &sha).unwrap().serialize(),
+            readme.serialize()
+        );
+        assert_eq!(object_size(&repo, &sha).unwrap(), 10);
+        assert_eq!(
+            abbreviate_object(&repo, &sha.to_string()).unwrap(),
+            sha.to_string()[..7]
+        );
+        assert_eq!(find_object(&repo, "cdb5f0", None, false).unwrap(), sha);
+
+        // Objects are never written to an alternate
+        let testfile = blob(b"testfile\n");
+        let sha = write_object(&testfile, &repo).unwrap();
+        assert!(root
+            .join("repo/.git/objects/26")
+            .join(&sha.to_string()[2..])
+            .is_file());
+        assert!(!root.join("shared/.git/objects/26").exists());
+        assert!(write_object(&readme, &repo).is_ok());
+        assert!(!root.join("repo/.git/objects/cd").exists());
+    }
+
+    #[test]
+    fn test_object_cache() {
+        let tmp = TempDir::create("object_store_cache")
+            .with_mutex(&crate::TEST_MUTEX);
+        let root = tmp.tmp_dir().canonicalize().unwrap();
+        let repo =
+            GitRepository::create(&root.join("repo")).expect("Create repo");
+
+        let readme = blob(b"readme.md\n");
+        let sha = write_object(&readme, &repo).unwrap();
+        assert!(repo.object_cache().is_empty());
+
+        // Reads fill the cache of the repository, shared by its clones
+        assert_eq!(object_size(&repo, &sha).unwrap(), 10);
+        assert!(repo.object_cache().is_empty());
+        read_object(&repo, &sha).unwrap();
+        let clone = repo.clone();
+        assert_eq!(clone.object_cache().len(), 1);
+
+        // Cached objects are not read from the stores again
+        let path = root
+            .join("repo/.git/objects/cd")
+            .join(&sha.to_string()[2..]);
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(
+            read_object(&clone, &sha).unwrap().serialize(),
+            readme.serialize()
+        );
+        assert_eq!(object_size(&clone, &sha).unwrap(), 10);
+        let reopened = GitRepository::new(&root.join("repo")).unwrap();
+        assert!(read_object(&reopened, &sha).is_err());
+
+        // Objects that do not fit are not cached
+        let cache = ObjectCache::new(4);
+        let mut db = ObjectDatabase::new()
+            .with_store(LooseStore::new(&root.join("small"), true))
+            .with_cache(cache.clone());
+        let sha = db.write(&readme).unwrap();
+        assert!(db.read_raw(&sha).unwrap().starts_with(b"blob 10\0"));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_object_id_index() {
+        let tmp = TempDir::create("object_store_id_index")
+            .with_mutex(&crate::TEST_MUTEX);
+        let root = tmp.tmp_dir().canonicalize().unwrap();
+        let repo =
+            GitRepository::create(&root.join("repo")).expect("Create repo");
+
+        let readme = write_object(&blob(b"readme.md\n"), &repo).unwrap();
+        assert!(repo.object_ids().is_empty());
+
+        // Lookups by prefix list the objects once, for all clones
+        let clone = repo.clone();
+        assert_eq!(
+            find_object(&repo, &readme.to_string()[..6], None, false).unwrap(),
+            readme
+        );
+        assert_eq!(clone.object_ids().len(), 1);
+        assert_eq!(
+            abbreviate_object(&clone, &readme.to_string()).unwrap(),
+            readme.to_string()[..7]
+        );
+
+        // Objects written through a database are added to the index
+        let license = write_object(&blob(b"license\n"), &clone).unwrap();
+        assert_eq!(repo.object_ids().len(), 2);
+        assert_eq!(
+            find_object(&repo, &license.to_string()[..4], None, false).unwrap(),
+            license
+        );
+
+        // Stored packs are seen once the index is listed again
+        let (raw, mut hash) = mini_git::core::objects::hash_object(
+            &blob(b"packed\n"),
+            ObjectFormat::Sha1,
+        );
+        let mut writer = PackWriter::new();
+        writer.add(&raw).unwrap();
+        let mut pack = Vec::new();
+        writer.write(&mut pack).unwrap();
+        store_pack(&repo, pack, false).unwrap();
+        let packed = hash.hex_digest();
+        assert_eq!(
+            find_object(&clone, &packed[..4], None, false).unwrap(),
+            packed
+        );
+        assert_eq!(repo.object_ids().len(), 3);
+
+        // Other repositories have their own index
+        let reopened = GitRepository::new(&root.join("repo")).unwrap();
+        assert!(reopened.object_ids().is_empty());
+        let db = ObjectDatabase::open(&reopened);
+        assert_eq!(db.ids_with_prefix("").unwrap().len(), 3);
+        assert!(db.longest_shared_prefix(&packed).unwrap() < 7);
+    }
+
+    #[test]
+    fn test_object_streams() {
+        let tmp = TempDir::create("object_store_streams")
+            .with_mutex(&crate::TEST_MUTEX);
+        let root = tmp.tmp_dir().canonicalize().unwrap();
+        let repo =
+            GitRepository::create(&root.join("repo")).expect("Create repo");
+
+        // Large enough to span several chunks and stored blocks
+        let data: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        let size = data.len() as u64;
+        let expected = write_object(&blob(&data), &repo).unwrap();
+        assert_eq!(
+            hash_object_stream(
+                "blob",
+                size,
+                &mut data.as_slice(),
+                ObjectFormat::Sha1
+            )
+            .unwrap(),
+            expected
+        );
+
+        // Objects written by the regular compressor stream back
+        let mut reader = read_object_stream(&repo, &expected).unwrap();
+        assert_eq!(reader.format(), "blob");
+        assert_eq!(reader.size(), size);
+        let mut contents = Vec::new();
+        reader.read_to_end(&mut contents).unwrap();
+        assert_eq!(contents, data);
+
+        // Streamed writes read back whole
+        let path = root
+            .join("repo/.git/objects")
+            .join(&expected.to_string()[..2]);
+        std::fs::remove_dir_all(path).unwrap();
+        let sha =
+            write_object_stream(&repo, "blob", size, &mut data.as_slice())
+                .unwrap();
+        assert_eq!(sha, expected);
+        assert_eq!(
+            read_object(&repo, &sha).unwrap().serialize(),
+            blob(&data).serialize()
+        );
+        assert!(std::fs::read_dir(root.join("repo/.git/objects"))
+            .unwrap()
+            .all(|entry| !entry
+                .unwrap()
+                .file_name()
+                .to_string_lossy()
+                .starts_with("tmp_obj")));
+
+        // Data must match the size it is written with
+        let err = write_object_stream(&repo, "blob", size + 1, &mut &data[..])
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("does not match its size"), "{err}");
+        assert!(hash_object_stream(
+            "blob",
+            1,
+            &mut &b"ab"[..],
+            ObjectFormat::Sha1
+        )
+        .is_err());
+        assert!(
+            read_object_stream(&repo, &Oid::null(ObjectFormat::Sha1)).is_err()
+        );
+
+        assert_eq!(big_file_threshold(&repo), DEFAULT_BIG_FILE_THRESHOLD);
+        let config = root.join("repo/.git/config");
+        let contents = std::fs::read_to_string(&config).unwrap();
+        let contents = contents.replacen(
+            "[core]\n",
+            "[core]\n\tbigFileThreshold = 2k\n",
+            1,
+        );
+        std::fs::write(config, contents).unwrap();
+        let repo = GitRepository::new(&root.join("repo")).unwrap();
+        assert_eq!(big_file_threshold(&repo), 2048);
+    }
+
+    #[test]
+    fn test_store_thin_pack() {
+        let tmp = TempDir::create("object_store_thin_pack")
+            .with_mutex(&crate::TEST_MUTEX);
+        let root = tmp.tmp_dir().canonicalize().unwrap();
+        let repo =
+            GitRepository::create(&root.join("repo")).expect("Create repo");
+
+        let text: Vec<u8> = (0..1000u32)
+            .flat_map(|i| format!("line {i}\n").into_bytes())
+            .collect();
+        let mut changed = text.clone();
+        changed.extend_from_slice(b"one more line\n");
+        let base_sha = write_object(&blob(&text), &repo).unwrap();
+
+        // The receiving repository has the base of the delta
+        let mut db = ObjectDatabase::open(&repo);
+        let mut writer = PackWriter::new();
+        writer
+            .add_base(&db.read_raw(&base_sha.to_string()).unwrap())
+            .unwrap();
+        let (raw, mut hash) = mini_git::core::objects::hash_object(
+            &blob(&changed),
+            ObjectFormat::Sha1,
+        );
+        let sha = writer.add(&raw).unwrap();
+        assert_eq!(sha, hash.hex_digest());
+
+        let mut pack = Vec::new();
+        writer.write(&mut pack).unwrap();
+        assert!(store_pack(&repo, pack.clone(), false).is_err());
+        let checksum = store_pack(&repo, pack, true).unwrap();
+
+        let pack_dir = root.join("repo/.git/objects/pack");
+        assert!(pack_dir.join(format!("pack-{checksum}.idx")).is_file());
+        let reopened = GitRepository::new(&root.join("repo")).unwrap();
+        assert_eq!(
+            read_object(&reopened, &sha.parse().unwrap())
+                .unwrap()
+                .serialize(),
+            blob(&changed).serialize()
+        );
+
+        // The stored pack is complete without the loose base
+        std::fs::remove_dir_all(
+            root.join("repo/.git/objects")
+                .join(&base_sha.to_string()[..2]),
+        )
+        .unwrap();
+        let reopened = GitRepository::new(&root.join("repo")).unwrap();
+        assert_eq!(object_size(&reopened, &base_sha).unwrap(), text.len());
+    }
+
+    #[test]
+    fn test_sha256_repository() {
+        use mini_git::core::index::{GitIndex, IndexEntry};
+        use mini_git::core::objects::tree::{Leaf, Tree};
+        use mini_git::core::InitOptions;
+
+        let tmp = TempDir::create("object_store_sha256")
+            .with_mutex(&crate::TEST_MUTEX);
+        let root = tmp.tmp_dir().canonicalize().unwrap();
+        let opts = InitOptions {
+            object_format: Some(ObjectFormat::Sha256),
+            ..InitOptions::default()
+        };
+        let repo = GitRepository::create_with(&root.join("repo"), &opts)
+            .expect("Create repo");
+        assert_eq!(repo.object_format(), ObjectFormat::Sha256);
+
+        // Loose objects are named by their SHA-256 hash
+        let readme = blob(b"hi\n");
+        let sha = write_object(&readme, &repo).unwrap();
+        assert_eq!(
+            sha,
+            "96c18f0297e38d01f4b2dacddea4259aea6b2961eb0822bd2c0c3f6029030045"
+        );
+        let mut tree = Tree::new();
+        tree.set_leaves(vec![Leaf::new(
+            b"100644",
+            b"README",
+            &sha.to_string(),
+        )]);
+        let tree_sha = write_object(&GitObject::Tree(tree), &repo).unwrap();
+        let GitObject::Tree(tree) = read_object(&repo, &tree_sha).unwrap()
+        else {
+            panic!("Not a tree");
+        };
+        assert_eq!(tree.leaves()[0].sha(), sha);
+        assert_eq!(
+            abbreviate_object(&repo, &sha.to_string()).unwrap(),
+            sha.to_string()[..7]
+        );
+
+        // The index stores the longer object names
+        let mut index = GitIndex::new();
+        index.add(IndexEntry::new(0o100_644, &sha.to_string(), "README"));
+        index.write(&repo).unwrap();
+        let index = GitIndex::read(&repo).unwrap();
+        assert_eq!(index.entries()[0].sha, sha);
+
+        // So do packs
+        let text: Vec<u8> = (0..1000u32)
+            .flat_map(|i| format!("line {i}\n").into_bytes())
+            .collect();
+        let mut writer =
+            PackWriter::new().with_object_format(ObjectFormat::Sha256);
+        let (raw, _) = mini_git::core::objects::hash_object(
+            &blob(&text),
+            ObjectFormat::Sha256,
+        );
+        let packed_sha = writer.add(&raw).unwrap();
+        let mut pack = Vec::new();
+        writer.write(&mut pack).unwrap();
+        store_pack(&repo, pack, false).unwrap();
+        let reopened = GitRepository::new(&root.join("repo")).unwrap();
+        assert_eq!(
+            object_size(&reopened, &packed_sha.parse().unwrap()).unwrap(),
+            text.len()
+        );
+        assert_eq!(
+            find_object(&reopened, &packed_sha[..8], None, false).unwrap(),
+            packed_sha
+        );
+    }
+}
@@ -0,0 +1,331 @@
+#[cfg(test)]
+mod tests {
+    use mini_git::core::objects::{blob::Blob, read_object, write_object};
+    use mini_git::core::objects::{resolve_ref, GitObject};
+    use mini_git::core::{
+        resolve_repository_context, GitRepository, RepositoryContext,
+        CEILING_DIRECTORIES_ENVIRONMENT, GIT_DIR_ENVIRONMENT,
+        OBJECT_DIRECTORY_ENVIRONMENT, WORK_TREE_ENVIRONMENT,
+    };
+
+    use mini_git::utils::test::TempDir;
+
+    use std::ffi::OsStr;
+    use std::path::Path;
+
+    /// Resolve the repository context with `var` set to `value`.
+    fn resolve_with_env(
+        var: &str,
+        value: &OsStr,
+    ) -> Result<RepositoryContext, String> {
+        let old = std::env::var_os(var);
+        std::env::set_var(var, value);
+        let res = resolve_repository_context().map_err(String::from);
+        match old {
+            Some(old) => std::env::set_var(var, old),
+            None => std::env::remove_var(var),
+        }
+        res
+    }
+
+    /// Remove `core.bare` from the config of the git directory `gitdir`.
+    fn unset_core_bare(gitdir: &Path) {
+        let config = gitdir.join("config");
+        let text = std::fs::read_to_string(&config).unwrap();
+        let text = text
+            .lines()
+            .filter(|line| !line.trim_start().starts_with("bare"))
+            .fold(String::new(), |text, line| text + line + "\n");
+        std::fs::write(&config, text).unwrap();
+    }
+
+    #[test]
+    fn test_with_gitdir() {
+        let tmp = TempDir::create("repository_with_gitdir")
+            .with_mutex(&crate::TEST_MUTEX);
+        let root = tmp.tmp_dir().canonicalize().unwrap();
+        GitRepository::create(&root.join("repo")).expect("Create repo");
+        let gitdir = root.join("repo").join(".git");
+        std::fs::create_dir(root.join("elsewhere")).unwrap();
+
+        let repo = GitRepository::with_gitdir(&gitdir, &root.join("elsewhere"))
+            .expect("Open repo");
+        assert_eq!(repo.gitdir(), gitdir);
+        assert_eq!(repo.worktree(), root.join("elsewhere"));
+        assert!(!repo.is_bare());
+
+        let res = GitRepository::with_gitdir(&root.join("elsewhere"), &root);
+        assert!(res
+            .unwrap_err()
+            .to_string()
+            .contains("not a git repository"));
+
+        // A bare repository has no worktree to use
+        let bare = root.join("bare.git");
+        GitRepository::create_bare(&bare).expect("Create bare repo");
+        let repo = GitRepository::with_gitdir(&bare, &root).expect("Open repo");
+        assert!(repo.is_bare());
+        assert_eq!(repo.worktree(), bare);
+    }
+
+    #[test]
+    fn test_git_dir_environment() {
+        let tmp = TempDir::create("repository_git_dir_environment")
+            .with_mutex(&crate::TEST_MUTEX);
+        let root = tmp.tmp_dir().canonicalize().unwrap();
+        GitRepository::create(&root.join("repo")).expect("Create repo");
+        std::fs::create_dir(root.join("elsewhere")).unwrap();
+
+        // The current directory is the worktree of the named git directory
+        let context = tmp.run(|| {
+            std::env::set_current_dir(root.join("elsewhere")).unwrap();
+            resolve_with_env(GIT_DIR_ENVIRONMENT, "../repo/.git".as_ref())
+        });
+        let context = context.expect("Resolve context");
+        assert_eq!(context.repo_path, root.join("elsewhere"));
+        assert_eq!(context.repo.gitdir(), root.join("repo").join(".git"));
+
+        // An empty value is ignored
+        let context = tmp.run(|| {
+            std::env::set_current_dir(root.join("repo")).unwrap();
+            resolve_with_env(GIT_DIR_ENVIRONMENT, "".as_ref())
+        });
+        assert_eq!(
+            context.expect("Resolve context").repo_path,
+            root.join("repo")
+        );
+
+        let res = tmp
+            .run(|| resolve_with_env(GIT_DIR_ENVIRONMENT, "missing".as_ref()));
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_work_tree_environment() {
+        let tmp = TempDir::create("repository_work_tree_environment")
+            .with_mutex(&crate::TEST_MUTEX);
+        let root = tmp.tmp_dir().canonicalize().unwrap();
+        GitRepository::create(&root.join("repo")).expect("Create repo");
+        std::fs::create_dir(root.join("elsewhere")).unwrap();
+
+        // The git directory is still found from the current directory
+        let context = tmp.run(|| {
+            std::env::set_current_dir(root.join("repo")).unwrap();
+            resolve_with_env(WORK_TREE_ENVIRONMENT, "../elsewhere".as_ref())
+        });
+        let context = context.expect("Resolve context");
+        assert_eq!(context.repo_path, root.join("elsewhere"));
+        assert_eq!(context.repo.gitdir(), root.join("repo").join(".git"));
+        assert!(!context.repo.is_bare());
+    }
+
+    #[test]
+    fn test_object_directory_environment() {
+        let tmp = TempDir::create("repository_object_directory_environment")
+            .with_mutex(&crate::TEST_MUTEX);
+        let root = tmp.tmp_dir().canonicalize().unwrap();
+        GitRepository::create(&root.join("repo")).expect("Create repo");
+        let store = root.join("store");
+
+        let blob = GitObject::Blob(Blob::from(b"stored\n".as_slice()));
+        let sha = tmp.run(|| {
+            std::env::set_current_dir(root.join("repo")).unwrap();
+            let context =
+                resolve_with_env(OBJECT_DIRECTORY_ENVIRONMENT, store.as_ref())
+                    .expect("Resolve context");
+            assert_eq!(context.repo.objects_dir(), store);
+            write_object(&blob, &context.repo).expect("Write object")
+        });
+        let sha = sha.to_string();
+        assert!(store.join(&sha[..2]).join(&sha[2..]).is_file());
+
+        // Without it, the object is not found
+        let repo = GitRepository::new(&root.join("repo")).expect("Open repo");
+        assert!(read_object(&repo, &sha.parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_ceiling_directories_environment() {
+        let tmp = TempDir::create("repository_ceiling_directories")
+            .with_mutex(&crate::TEST_MUTEX);
+        let root = tmp.tmp_dir().canonicalize().unwrap();
+        GitRepository::create(&root).expect("Create repo");
+        let nested = root.join("a").join("b");
+        std::fs::create_dir_all(&nested).unwrap();
+
+        let resolve = |ceilings: &str| {
+            tmp.run(|| {
+                std::env::set_current_dir(&nested).unwrap();
+                resolve_with_env(
+                    CEILING_DIRECTORIES_ENVIRONMENT,
+                    ceilings.as_ref(),
+                )
+            })
+        };
+
+        // The search does not go up into a ceiling
+        let ceilings = std::env::join_paths(
+            ["/nonexistent", "a"].map(|dir| root.join(dir)),
+        )
+        .unwrap();
+        assert!(resolve(ceilings.to_str().unwrap()).is_err());
+
+        // A ceiling at the current directory does not stop the search
+        let ceiling = nested.to_string_lossy();
+        assert_eq!(resolve(&ceiling).unwrap().repo_path, root);
+    }
+
+    #[test]
+    fn test_bare_without_core_bare() {
+        let tmp = TempDir::create("repository_bare_without_core_bare")
+            .with_mutex(&crate::TEST_MUTEX);
+        let root = tmp.tmp_dir().canonicalize().unwrap();
+        let bare = root.join("repo.git");
+        GitRepository::create_bare(&bare).expect("Create bare repo");
+        unset_core_bare(&bare);
+
+        // A git directory not named .git is bare, even from inside it
+        let context = tmp.run(|| {
+            std::env::set_current_dir(bare.join("refs").join("heads")).unwrap();
+            resolve_repository_context()
+        });
+        let repo = context.expect("Resolve context").repo;
+        assert!(repo.is_bare());
+        assert_eq!(repo.gitdir(), bare);
+        assert!(repo
+            .require_worktree()
+            .unwrap_err()
+            .to_string()
+            .contains("must be run in a work tree"));
+
+        // The .git directory of a worktree is not, so the worktree is found
+        GitRepository::create(&root.join("work")).expect("Create repo");
+        let gitdir = root.join("work").join(".git");
+        unset_core_bare(&gitdir);
+        let context = tmp.run(|| {
+            std::env::set_current_dir(gitdir.join("refs")).unwrap();
+            resolve_repository_context()
+        });
+        let repo = context.expect("Resolve context").repo;
+        assert!(!repo.is_bare());
+        assert_eq!(repo.worktree(), root.join("work"));
+    }
+
+    #[test]
+    fn test_added_worktree() {
+        let tmp = TempDir::create("repository_added_worktree")
+            .with_mutex(&crate::TEST_MUTEX);
+        let root = tmp.tmp_dir().canonicalize().unwrap();
+        let main = GitRepository::create(&root.join("main")).expect("Create");
+        let common = main.gitdir().to_path_buf();
+
+        // An added worktree has its own HEAD, and shares the rest
+        let gitdir = common.join("worktrees").join("wt");
+        std::fs::create_dir_all(&gitdir).unwrap();
+        std::fs::write(gitdir.join("HEAD"), "ref: refs/heads/side\n").unwrap();
+        std::fs::write(gitdir.join("commondir"), "../..\n").unwrap();
+        std::fs::create_dir(root.join("wt")).unwrap();
+        std::fs::write(
+            root.join("wt").join(".git"),
+            "gitdir: ../main/.git/worktrees/wt\n",
+        )
+        .unwrap();
+        let sha = "1".repeat(40);
+        std::fs::write(
+            common.join("refs").join("heads").join("side"),
+            format!("{sha}\n"),
+        )
+        .unwrap();
+
+        let context = tmp.run(|| {
+            std::env::set_current_dir(root.join("wt")).unwrap();
+            resolve_repository_context()
+        });
+        let repo = context.expect("Resolve context").repo;
+        assert_eq!(repo.worktree(), root.join("wt"));
+        assert_eq!(repo.gitdir().canonicalize().unwrap(), gitdir);
+        assert_eq!(repo.common_dir(), common);
+        assert_eq!(repo.objects_dir(), common.join("objects"));
+        assert_eq!(repo.ref_dir("HEAD"), repo.gitdir());
+        assert_eq!(repo.ref_dir("refs/heads/side"), common);
+        assert_eq!(
+            resolve_ref(&repo, "HEAD").unwrap(),
+            Some(sha.parse().unwrap())
+        );
+        assert!(repo.config().get("core").is_some());
+    }
+
+    #[test]
+    fn test_format_version_and_extensions() {
+        let tmp = TempDir::create("repository_format_version")
+            .with_mutex(&crate::TEST_MUTEX);
+        let root = tmp.tmp_dir().canonicalize().unwrap();
+        GitRepository::create(&root).expect("Create repo");
+        let open = |config: &str| {
+            std::fs::write(root.join(".git").join("config"), config).unwrap();
+            GitRepository::new(&root)
+        };
+
+        // Version 0 ignores extensions, but not those only version 1 allows
+        assert!(open(
+            "[core]\nrepositoryformatversion=0\n[extensions]\nfoo=bar\n"
+        )
+        .is_ok());
+        assert!(open(
+            "[core]\nrepositoryformatversion=0\n[extensions]\nobjectFormat=sha1\n"
+        )
+        .is_err());
+
+        // Version 1 refuses the extensions it does not know
+        assert!(open(
+            "[core]\nrepositoryformatversion=1\n[extensions]\n\
+            objectFormat=sha1\nworktreeConfig=false\n"
+        )
+        .is_ok());
+        let res =
+            open("[core]\nrepositoryformatversion=1\n[extensions]\nfoo=bar\n");
+        assert!(res
+            .unwrap_err()
+            .to_string()
+            .contains("unknown repository extension"));
+        let res = open(
+            "[core]\nrepositoryformatversion=1\n[extensions]\nobjectFormat=md5\n",
+        );
+        assert!(res.unwrap_err().to_string().contains("md5"));
+
+        let res = open("[core]\nrepositoryformatversion=2\n");
+        assert!(res
+            .unwrap_err()
+            .to_string()
+            .contains("repositoryformatversion 2"));
+        assert!(open("[core]\nrepositoryformatversion=one\n").is_err());
+        assert!(open("[core]\nbare=false\n").is_err());
+    }
+
+    #[test]
+    fn test_worktree_config() {
+        let tmp = TempDir::create("repository_worktree_config")
+            .with_mutex(&crate::TEST_MUTEX);
+        let root = tmp.tmp_dir().canonicalize().unwrap();
+        GitRepository::create(&root).expect("Create repo");
+        let gitdir = root.join(".git");
+        std::fs::write(
+            gitdir.join("config"),
+            "[core]\nrepositoryformatversion=1\n[user]\nname=shared\n",
+        )
+        .unwrap();
+        std::fs::write(gitdir.join("config.worktree"), "[user]\nname=own\n")
+            .unwrap();
+        let user_name = || {
+            let repo = GitRepository::new(&root).expect("Open repo");
+            repo.config().get("user")?.get("name").map(String::from)
+        };
+
+        // config.worktree is only read with the extension
+        assert_eq!(user_name().as_deref(), Some("shared"));
+        let mut text = std::fs::read_to_string(gitdir.join("config")).unwrap();
+        text.push_str("[extensions]\nworktreeConfig=true\n");
+        std::fs::write(gitdir.join("config"), text).unwrap();
+        assert_eq!(user_name().as_deref(), Some("own"));
+    }
+}
@@ -100,7 +100,9 @@ mod tests {
         let repo_path = path::repo_find(".").expect("Should find repo");
         let repo = GitRepository::new(&repo_path).expect("Should find repo");
 
-        let hash = write_object(&obj, &repo).expect("Should write to database");
+        let hash = write_object(&obj, &repo)
+            .expect("Should write to database")
+            .to_string();
         create_dir_if_not_exists(&OBJECT_DIR().join("b0"));
         fs::rename(
             OBJECT_DIR().join(&hash[..2]).join(&hash[2..]),
@@ -123,7 +125,9 @@ mod tests {
         let repo_path = path::repo_find(".").expect("Should find repo");
         let repo = GitRepository::new(&repo_path).expect("Should find repo");
 
-        let hash = write_object(&obj, &repo).expect("Should write to database");
+        let hash = write_object(&obj, &repo)
+            .expect("Should write to database")
+            .to_string();
         create_dir_if_not_exists(&OBJECT_DIR().join("a0"));
         fs::rename(
             OBJECT_DIR().join(&hash[..2]).join(&hash[2..]),
@@ -0,0 +1,196 @@
+#[cfg(test)]
+mod tests {
+    use mini_git::core::index::{GitIndex, IndexEntry};
+    use mini_git::core::objects::commit::Signature;
+    use mini_git::core::objects::oid::Oid;
+    use mini_git::core::objects::revwalk::Sort;
+    use mini_git::core::GitRepository;
+
+    use mini_git::utils::test::TempDir;
+
+    // The commits of a history, by message
+    struct History {
+        repo: GitRepository,
+        names: Vec<(Oid, String)>,
+    }
+
+    impl History {
+        fn commit(
+            &mut self,
+            message: &str,
+            time: u64,
+            files: &[(&str, &str)],
+            parents: &[&str],
+        ) {
+            let mut index = GitIndex::new();
+            for (path, content) in files {
+                let blob = self.repo.write_blob(content.as_bytes()).unwrap();
+                index.add(IndexEntry::new(0o100_644, &blob.to_string(), path));
+            }
+            let tree = index.write_tree(&self.repo).unwrap().parse().unwrap();
+            let parents =
+                parents.iter().map(|p| self.oid(p)).collect::<Vec<_>>();
+            let sig: Signature =
+                format!("A U Thor <author@example.com> {time} +0000")
+                    .parse()
+                    .unwrap();
+            let oid = self
+                .repo
+                .create_commit(None, &sig, &sig, message, &tree, &parents)
+                .unwrap();
+            self.names.push((oid, message.to_owned()));
+        }
+
+        fn oid(&self, message: &str) -> Oid {
+            self.names.iter().find(|(_, m)| m == message).unwrap().0
+        }
+
+        fn name(&self, oid: &Oid) -> &str {
+            &self.names.iter().find(|(o, _)| o == oid).unwrap().1
+        }
+
+        fn walk(
+            &self,
+            push: &[&str],
+            hide: &[&str],
+            setup: impl FnOnce(&mut mini_git::core::objects::revwalk::Revwalk),
+        ) -> Vec<&str> {
+            let mut walk = self.repo.revwalk();
+            for message in push {
+                walk.push(&self.oid(message)).unwrap();
+            }
+            for message in hide {
+                walk.hide(&self.oid(message)).unwrap();
+            }
+            setup(&mut walk);
+            walk.map(|oid| self.name(&oid.unwrap())).collect()
+        }
+    }
+
+    // A <- B1 <- B2 <--- M
+    //  \                /
+    //   <- C1 <- C2 <---
+    //
+    // where the C line was committed between the commits of the B line, and
+    // only the B line changes `b`.
+    fn make_history(tmp: &TempDir<()>) -> History {
+        let repo = GitRepository::create(tmp.tmp_dir()).expect("Create repo");
+        let mut history = History {
+            repo,
+            names: Vec::new(),
+        };
+        history.commit("A", 1, &[("a", "1"), ("b", "1")], &[]);
+        history.commit("B1", 3, &[("a", "1"), ("b", "2")], &["A"]);
+        history.commit("C1", 4, &[("a", "2"), ("b", "1")], &["A"]);
+        history.commit("B2", 5, &[("a", "1"), ("b", "3")], &["B1"]);
+        history.commit("C2", 6, &[("a", "3"), ("b", "1")], &["C1"]);
+        history.commit("M", 7, &[("a", "3"), ("b", "3")], &["B2", "C2"]);
+        history
+    }
+
+    #[test]
+    fn test_revwalk_sorting() {
+        let tmp =
+            TempDir::create("revwalk_sorting").with_mutex(&crate::TEST_MUTEX);
+        let history = make_history(&tmp);
+
+        assert_eq!(
+            history.walk(&["M"], &[], |_| {}),
+            ["M", "C2", "B2", "C1", "B1", "A"]
+        );
+        assert_eq!(
+            history.walk(&["M"], &[], |walk| {
+                walk.sorting(Sort::Topological);
+            }),
+            ["M", "B2", "B1", "C2", "C1", "A"]
+        );
+        assert_eq!(
+            history.walk(&["M"], &[], |walk| {
+                walk.reverse(true);
+            }),
+            ["A", "B1", "C1", "B2", "C2", "M"]
+        );
+        assert_eq!(
+            history.walk(&["M"], &[], |walk| {
+                walk.sorting(Sort::Topological).reverse(true);
+            }),
+            ["A", "C1", "C2", "B1", "B2", "M"]
+        );
+        assert_eq!(
+            history.walk(&["M"], &[], |walk| {
+                walk.first_parent(true);
+            }),
+            ["M", "B2", "B1", "A"]
+        );
+    }
+
+    #[test]
+    fn test_revwalk_hide() {
+        let tmp =
+            TempDir::create("revwalk_hide").with_mutex(&crate::TEST_MUTEX);
+        let history = make_history(&tmp);
+
+        assert_eq!(history.walk(&["M"], &["B2"], |_| {}), ["M", "C2", "C1"]);
+        assert_eq!(
+            history.walk(&["C2", "B1"], &["A"], |_| {}),
+            ["C2", "C1", "B1"]
+        );
+        assert!(history.walk(&["B1"], &["M"], |_| {}).is_empty());
+        assert!(history.walk(&[], &[], |_| {}).is_empty());
+    }
+
+    #[test]
+    fn test_revwalk_paths() {
+        let tmp =
+            TempDir::create("revwalk_paths").with_mutex(&crate::TEST_MUTEX);
+        let history = make_history(&tmp);
+
+        // The merge has the `b` of B2, so only the B line is walked
+        assert_eq!(
+            history.walk(&["M"], &[], |walk| {
+                walk.limit_path("b");
+            }),
+            ["B2", "B1", "A"]
+        );
+        assert_eq!(
+            history.walk(&["M"], &[], |walk| {
+                walk.limit_path("a");
+            }),
+            ["C2", "C1", "A"]
+        );
+        // Changes to either path are listed, and the merge changes both
+        assert_eq!(
+            history.walk(&["M"], &[], |walk| {
+                walk.limit_path("a").limit_path("./b");
+            }),
+            ["M", "C2", "B2", "C1", "B1", "A"]
+        );
+        assert!(history
+            .walk(&["M"], &[], |walk| {
+                walk.limit_path("missing");
+            })
+            .is_empty());
+    }
+
+    #[test]
+    fn test_revwalk_head() {
+        let tmp =
+            TempDir::create("revwalk_head").with_mutex(&crate::TEST_MUTEX);
+        let history = make_history(&tmp);
+
+        // HEAD is on a branch with no commits yet
+        let mut walk = history.repo.revwalk();
+        walk.push_head().expect("Push HEAD");
+        assert_eq!(walk.count(), 0);
+
+        // Only commits can be walked
+        let tree = history
+            .repo
+            .find_commit(&history.oid("A"))
+            .unwrap()
+            .tree()
+            .unwrap();
+        let err = history.repo.revwalk().push(&tree).unwrap_err();
+        assert!(err.to_string().contains("Cannot show history for a tree"));
+    }
+}
@@ -5,7 +5,7 @@ mod tests {
     use mini_git::core::commands::show_ref::*;
     use mini_git::core::objects::tag::Tag;
     use mini_git::core::objects::traits::KVLM;
-    use mini_git::core::GitRepository;
+    use mini_git::core::{Error, GitRepository};
 
     use mini_git::utils::collections::kvlm;
     use mini_git::utils::test::TempDir;
@@ -193,6 +193,28 @@ mod tests {
         assert!(!output.contains("refs/remotes/develop/feature1"));
     }
 
+    #[test]
+    fn test_show_ref_json() {
+        setup();
+        let args: [&[&str]; 2] =
+            [&["--json", "--tags"], &["--json", "nothing"]];
+        let result = switch_dir!({
+            make_namespaces(&args)
+                .map(|namespace| show_ref(&namespace))
+                .collect::<Result<Vec<_>, _>>()
+        });
+
+        let output = result.unwrap();
+        assert_eq!(
+            output[0],
+            format!(
+                "[{{\"oid\":\"{}\",\"ref\":\"refs/tags/v1\"}}]",
+                "ab".repeat(20)
+            )
+        );
+        assert_eq!(output[1], "[]");
+    }
+
     #[test]
     fn test_show_ref_remotes() {
         setup();
@@ -237,4 +259,17 @@ mod tests {
         });
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_show_ref_exists_usage() {
+        setup();
+        let args: [&[&str]; 1] = [&["--exists"]];
+        let result = switch_dir!({
+            let namespace = make_namespaces(&args).next().unwrap();
+            show_ref(&namespace)
+        });
+        let err = result.unwrap_err();
+        assert!(matches!(err, Error::Usage(_)));
+        assert_eq!(err.exit_code(), 129);
+    }
 }
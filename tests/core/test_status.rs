@@ -0,0 +1,230 @@
+#[cfg(test)]
+mod tests {
+    use crate::make_namespaces_from;
+
+    use mini_git::core::commands::status::*;
+    use mini_git::core::index::{GitIndex, IndexEntry};
+    use mini_git::core::objects::commit::Signature;
+    use mini_git::core::GitRepository;
+
+    use mini_git::utils::test::TempDir;
+
+    make_namespaces_from!(make_parser);
+
+    const AUTHOR: &str = "A U Thor <author@example.com> 1112911993 -0700";
+    const NULL_SHA: &str = "0000000000000000000000000000000000000000";
+
+    fn run_status(tmp: &TempDir<()>, args: &[&str]) -> Result<String, String> {
+        let args: [&[&str]; 1] = [args];
+        tmp.run(|| {
+            let namespace = make_namespaces(&args).next().unwrap();
+            status(&namespace).map_err(String::from)
+        })
+    }
+
+    /// Stages `files`, writing them to the working tree too, and returns
+    /// the object name of each.
+    fn stage(
+        tmp: &TempDir<()>,
+        repo: &GitRepository,
+        index: &mut GitIndex,
+        files: &[(&str, &str)],
+    ) -> Vec<String> {
+        let mut shas = vec![];
+        for (path, content) in files {
+            let blob = repo.write_blob(content.as_bytes()).expect("Write blob");
+            index.add(IndexEntry::new(0o100_644, &blob.to_string(), path));
+            std::fs::write(tmp.tmp_dir().join(path), content).unwrap();
+            shas.push(blob.to_string());
+        }
+        shas
+    }
+
+    #[test]
+    fn test_status() {
+        let tmp = TempDir::create("cmd_status").with_mutex(&crate::TEST_MUTEX);
+        let repo = GitRepository::create(tmp.tmp_dir()).expect("Create repo");
+
+        let mut index = GitIndex::new();
+        let files = [
+            ("del.txt", "del\n"),
+            ("keep.txt", "keep\n"),
+            ("mod.txt", "old\n"),
+            ("old.txt", "moved\n"),
+        ];
+        let old = stage(&tmp, &repo, &mut index, &files);
+        let tree = index.write_tree(&repo).expect("Write tree");
+        let sig: Signature = AUTHOR.parse().expect("Parse signature");
+        let head = repo
+            .create_commit(
+                Some("HEAD"),
+                &sig,
+                &sig,
+                "First\n",
+                &tree.parse().unwrap(),
+                &[],
+            )
+            .expect("Create commit");
+        index.write(&repo).expect("Write index");
+        assert_eq!(
+            run_status(&tmp, &[]).unwrap(),
+            "On branch main\n\nnothing to commit, working tree clean\n"
+        );
+
+        let mut index = GitIndex::new();
+        let files = [
+            ("keep.txt", "keep\n"),
+            ("mod.txt", "new\n"),
+            ("new.txt", "new file\n"),
+            ("renamed.txt", "moved\n"),
+        ];
+        let new = stage(&tmp, &repo, &mut index, &files);
+        index.write(&repo).expect("Write index");
+        std::fs::remove_file(tmp.tmp_dir().join("del.txt")).unwrap();
+        std::fs::remove_file(tmp.tmp_dir().join("old.txt")).unwrap();
+        std::fs::write(tmp.tmp_dir().join("keep.txt"), "changed\n").unwrap();
+        std::fs::write(tmp.tmp_dir().join("untracked.txt"), "?\n").unwrap();
+        std::fs::create_dir(tmp.tmp_dir().join("dir")).unwrap();
+        std::fs::write(tmp.tmp_dir().join("dir/a.txt"), "a\n").unwrap();
+
+        let res = run_status(&tmp, &["--porcelain"]).unwrap();
+        assert_eq!(
+            res,
+            "D  del.txt\n \
+            M keep.txt\n\
+            M  mod.txt\n\
+            A  new.txt\n\
+            R  old.txt -> renamed.txt\n\
+            ?? dir/\n\
+            ?? untracked.txt\n"
+        );
+
+        let res = run_status(&tmp, &["-z", "-b", "-u"]).unwrap();
+        assert_eq!(
+            res,
+            "## main\0D  del.txt\0 M keep.txt\0M  mod.txt\0A  new.txt\0\
+            R  renamed.txt\0old.txt\0?? dir/a.txt\0?? untracked.txt\0"
+        );
+
+        let res =
+            run_status(&tmp, &["--porcelain=v2", "-b", "--untracked-files=no"])
+                .unwrap();
+        assert_eq!(
+            res,
+            format!(
+                "# branch.oid {head}\n\
+                # branch.head main\n\
+                1 D. N... 100644 000000 000000 {} {NULL_SHA} del.txt\n\
+                1 .M N... 100644 100644 100644 {} {} keep.txt\n\
+                1 M. N... 100644 100644 100644 {} {} mod.txt\n\
+                1 A. N... 000000 100644 100644 {NULL_SHA} {} new.txt\n\
+                2 R. N... 100644 100644 100644 {} {} R100 renamed.txt\told.txt\n",
+                old[0], old[1], new[0], old[2], new[1], new[2], old[3], new[3],
+            )
+        );
+
+        let res = run_status(&tmp, &["--json"]).unwrap();
+        assert_eq!(
+            res,
+            format!(
+                "{{\"branch\":\"main\",\"oid\":\"{head}\",\"changes\":[\
+                {{\"path\":\"del.txt\",\"orig\":null,\"index\":\"D\",\
+                \"worktree\":\".\",\"unmerged\":false}},\
+                {{\"path\":\"keep.txt\",\"orig\":null,\"index\":\".\",\
+                \"worktree\":\"M\",\"unmerged\":false}},\
+                {{\"path\":\"mod.txt\",\"orig\":null,\"index\":\"M\",\
+                \"worktree\":\".\",\"unmerged\":false}},\
+                {{\"path\":\"new.txt\",\"orig\":null,\"index\":\"A\",\
+                \"worktree\":\".\",\"unmerged\":false}},\
+                {{\"path\":\"renamed.txt\",\"orig\":\"old.txt\",\
+                \"index\":\"R\",\"worktree\":\".\",\"unmerged\":false}}],\
+                \"untracked\":[\"dir/\",\"untracked.txt\"]}}"
+            )
+        );
+
+        let res = run_status(&tmp, &["--untracked-files=no"]).unwrap();
+        assert_eq!(
+            res,
+            "On branch main\n\
+            \n\
+            Changes to be committed:\n\
+            \tdeleted:    del.txt\n\
+            \tmodified:   mod.txt\n\
+            \tnew file:   new.txt\n\
+            \trenamed:    old.txt -> renamed.txt\n\
+            \n\
+            Changes not staged for commit:\n\
+            \tmodified:   keep.txt\n"
+        );
+    }
+
+    #[test]
+    fn test_status_unmerged() {
+        let tmp = TempDir::create("cmd_status_unmerged")
+            .with_mutex(&crate::TEST_MUTEX);
+        let repo = GitRepository::create(tmp.tmp_dir()).expect("Create repo");
+
+        let mut index = GitIndex::new();
+        let shas = stage(&tmp, &repo, &mut index, &[("a.txt", "a\n")]);
+        let mut conflicted = IndexEntry::new(0o100_644, &shas[0], "both.txt");
+        for stage in 1..=3 {
+            conflicted.flags = (conflicted.flags & !(3 << 12)) | stage << 12;
+            index.add(conflicted.clone());
+        }
+        let mut ours = IndexEntry::new(0o100_644, &shas[0], "ours.txt");
+        ours.flags |= 2 << 12;
+        index.add(ours);
+        index.write(&repo).expect("Write index");
+
+        let res = run_status(&tmp, &["--porcelain", "--branch"]).unwrap();
+        assert_eq!(
+            res,
+            "## No commits yet on main\n\
+            A  a.txt\n\
+            UU both.txt\n\
+            AU ours.txt\n"
+        );
+
+        let res = run_status(&tmp, &["--porcelain=v2", "-b"]).unwrap();
+        let a = &shas[0];
+        assert_eq!(
+            res,
+            format!(
+                "# branch.oid (initial)\n\
+                # branch.head main\n\
+                1 A. N... 000000 100644 100644 {NULL_SHA} {a} a.txt\n\
+                u UU N... 100644 100644 100644 000000 {a} {a} {a} both.txt\n\
+                u AU N... 000000 100644 000000 000000 {NULL_SHA} {a} \
+                {NULL_SHA} ours.txt\n"
+            )
+        );
+
+        let res =
+            run_status(&tmp, &["--json", "--untracked-files=no"]).unwrap();
+        assert_eq!(
+            res,
+            "{\"branch\":\"main\",\"oid\":null,\"changes\":[\
+            {\"path\":\"a.txt\",\"orig\":null,\"index\":\"A\",\
+            \"worktree\":\".\",\"unmerged\":false},\
+            {\"path\":\"both.txt\",\"orig\":null,\"index\":\"U\",\
+            \"worktree\":\"U\",\"unmerged\":true},\
+            {\"path\":\"ours.txt\",\"orig\":null,\"index\":\"A\",\
+            \"worktree\":\"U\",\"unmerged\":true}],\"untracked\":[]}"
+        );
+
+        let res = run_status(&tmp, &[]).unwrap();
+        assert_eq!(
+            res,
+            "On branch main\n\
+            \n\
+            No commits yet\n\
+            \n\
+            Changes to be committed:\n\
+            \tnew file:   a.txt\n\
+            \n\
+            Unmerged paths:\n\
+            \tboth modified:   both.txt\n\
+            \tadded by us:     ours.txt\n"
+        );
+    }
+}
@@ -0,0 +1,152 @@
+#[cfg(test)]
+mod tests {
+    use crate::utils::fixtures::{temp_repo, write_tree_with_modes};
+
+    use mini_git::core::objects::tree::{
+        diff_trees, diff_trees_shallow, Delta, TreeWalkResult, TreeWalker,
+    };
+
+    // Some commit a submodule points to
+    const GITLINK: &str = "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391";
+
+    fn paths(walker: &mut TreeWalker) -> Vec<String> {
+        walker.map(|entry| entry.unwrap().path).collect()
+    }
+
+    #[test]
+    fn test_tree_walker() {
+        let (_tmp, repo) = temp_repo("tree_walker");
+        let tree = write_tree_with_modes(
+            &repo,
+            &[
+                (0o100_644, "a.txt", "a"),
+                (0o100_644, "a/b/c", "c"),
+                (0o100_755, "a/x", "x"),
+                (0o160_000, "sub", GITLINK),
+            ],
+        );
+
+        let mut walker = TreeWalker::new(&repo, &tree);
+        assert_eq!(paths(&mut walker), ["a.txt", "a/b/c", "a/x", "sub"]);
+        assert_eq!(
+            paths(TreeWalker::new(&repo, &tree).recursive(false)),
+            ["a.txt", "a", "sub"]
+        );
+        assert_eq!(
+            paths(TreeWalker::new(&repo, &tree).include_trees(true)),
+            ["a.txt", "a", "a/b", "a/b/c", "a/x", "sub"]
+        );
+
+        let entries = TreeWalker::new(&repo, &tree)
+            .collect::<Result<Vec<_>, _>>()
+            .expect("Walk tree");
+        assert_eq!(entries[2].mode, 0o100_755);
+        assert_eq!(entries[2].obj_type(), "blob");
+        assert!(entries[3].is_gitlink());
+        assert_eq!(entries[3].oid.to_string(), GITLINK);
+
+        // Trees can be skipped, and the walk stopped
+        let mut seen = vec![];
+        TreeWalker::new(&repo, &tree)
+            .walk(|entry| {
+                seen.push(entry.path.clone());
+                match entry.path.as_str() {
+                    "a/b" => TreeWalkResult::Skip,
+                    "a/x" => TreeWalkResult::Abort,
+                    _ => TreeWalkResult::Continue,
+                }
+            })
+            .expect("Walk tree");
+        assert_eq!(seen, ["a.txt", "a", "a/b", "a/x"]);
+
+        // Only trees can be walked
+        let blob = repo.write_blob(b"a").unwrap();
+        let mut walker = TreeWalker::new(&repo, &blob);
+        let err = walker.next().unwrap().unwrap_err();
+        assert!(err.to_string().contains("is not a tree"));
+        assert!(walker.next().is_none());
+    }
+
+    #[test]
+    fn test_diff_trees() {
+        let (_tmp, repo) = temp_repo("tree_diff");
+        let old = write_tree_with_modes(
+            &repo,
+            &[
+                (0o100_644, "deleted", "d"),
+                (0o100_644, "dir/file", "f"),
+                (0o100_644, "mode", "m"),
+                (0o100_644, "same/file", "s"),
+                (0o100_644, "src/lib.rs", "1"),
+                (0o100_644, "src/main.rs", "m"),
+                (0o100_644, "link", "target"),
+            ],
+        );
+        let new = write_tree_with_modes(
+            &repo,
+            &[
+                (0o100_644, "added/file", "a"),
+                (0o100_644, "dir", "now a file"),
+                (0o100_755, "mode", "m"),
+                (0o100_644, "same/file", "s"),
+                (0o100_644, "src/lib.rs", "2"),
+                (0o100_644, "src/main.rs", "m"),
+                (0o120_000, "link", "target"),
+            ],
+        );
+
+        let changes =
+            |deltas: Vec<mini_git::core::objects::tree::TreeDelta>| {
+                deltas
+                    .into_iter()
+                    .map(|delta| {
+                        format!("{} {}", delta.delta().status(), delta.path)
+                    })
+                    .collect::<Vec<_>>()
+            };
+        let deltas = diff_trees(&repo, Some(&old), Some(&new)).expect("Diff");
+        assert_eq!(
+            changes(deltas.clone()),
+            [
+                "A added/file",
+                "D deleted",
+                "A dir",
+                "D dir/file",
+                "T link",
+                "M mode",
+                "M src/lib.rs",
+            ]
+        );
+        let mode = deltas.iter().find(|delta| delta.path == "mode").unwrap();
+        assert_eq!(mode.old.as_ref().unwrap().mode, 0o100_644);
+        assert_eq!(mode.new.as_ref().unwrap().mode, 0o100_755);
+        assert_eq!(
+            mode.old.as_ref().unwrap().oid,
+            mode.new.as_ref().unwrap().oid
+        );
+
+        // Subtrees are single entries when not walked into
+        let deltas =
+            diff_trees_shallow(&repo, Some(&old), Some(&new)).expect("Diff");
+        assert_eq!(
+            changes(deltas),
+            [
+                "A added",
+                "D deleted",
+                "A dir",
+                "D dir",
+                "T link",
+                "M mode",
+                "M src",
+            ]
+        );
+
+        // A missing tree is the empty tree
+        let deltas = diff_trees(&repo, None, Some(&old)).expect("Diff");
+        assert_eq!(deltas.len(), 7);
+        assert!(deltas.iter().all(|delta| delta.delta() == Delta::Added));
+        assert!(diff_trees(&repo, Some(&old), Some(&old))
+            .unwrap()
+            .is_empty());
+    }
+}
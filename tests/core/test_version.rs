@@ -0,0 +1,26 @@
+#[cfg(test)]
+mod tests {
+    use crate::make_namespaces_from;
+
+    use mini_git::core::commands::version::*;
+
+    make_namespaces_from!(make_parser);
+
+    #[test]
+    fn test_version() {
+        let args: [&[&str]; 2] = [&[], &["--build-options"]];
+        let mut namespaces = make_namespaces(&args);
+
+        let info = version(&namespaces.next().unwrap()).unwrap();
+        let expected = format!(
+            "mini_git version {}\nobject formats: sha1 sha256\nfeatures: none\n",
+            env!("CARGO_PKG_VERSION")
+        );
+        assert_eq!(info, expected);
+
+        let info = version(&namespaces.next().unwrap()).unwrap();
+        assert!(info.starts_with(&expected));
+        assert!(info.contains(&format!("os: {}\n", std::env::consts::OS)));
+        assert!(info.contains(&format!("cpu: {}\n", std::env::consts::ARCH)));
+    }
+}
@@ -0,0 +1,54 @@
+#[cfg(test)]
+mod tests {
+    use mini_git::core::objects::worktree::get_worktree_files;
+    use mini_git::core::objects::FileSource;
+    use mini_git::core::GitRepository;
+
+    use mini_git::utils::test::TempDir;
+
+    fn paths(files: &[FileSource]) -> Vec<String> {
+        files.iter().map(FileSource::path).collect()
+    }
+
+    #[test]
+    fn test_get_worktree_files() {
+        let tmp =
+            TempDir::create("worktree_files").with_mutex(&crate::TEST_MUTEX);
+        let root = tmp.tmp_dir().canonicalize().unwrap();
+        let repo = GitRepository::create(&root).expect("Create repo");
+
+        // Enough directories for the walk to spread over the workers
+        let mut expected = vec!["top.txt".to_owned()];
+        for i in 0..20 {
+            for j in 0..5 {
+                let dir = root.join(format!("d{i:02}")).join(format!("e{j}"));
+                std::fs::create_dir_all(&dir).unwrap();
+                std::fs::write(dir.join("f"), "f\n").unwrap();
+                expected.push(format!("d{i:02}/e{j}/f"));
+            }
+        }
+        std::fs::write(root.join("top.txt"), "top\n").unwrap();
+        std::fs::create_dir(root.join("empty")).unwrap();
+        expected.sort();
+
+        let files = get_worktree_files(&repo, None).expect("Walk worktree");
+        assert_eq!(paths(&files), expected);
+        assert!(files
+            .iter()
+            .all(|file| matches!(file, FileSource::Worktree { .. })));
+
+        let files = get_worktree_files(&repo, Some(&root.join("d07")))
+            .expect("Walk directory");
+        let expected = (0..5).map(|j| format!("e{j}/f")).collect::<Vec<_>>();
+        assert_eq!(paths(&files), expected);
+    }
+
+    #[test]
+    fn test_get_worktree_files_missing_top() {
+        let tmp = TempDir::create("worktree_files_missing")
+            .with_mutex(&crate::TEST_MUTEX);
+        let repo = GitRepository::create(tmp.tmp_dir()).expect("Create repo");
+        let missing = tmp.tmp_dir().join("missing");
+        assert!(get_worktree_files(&repo, Some(&missing)).is_err());
+    }
+}
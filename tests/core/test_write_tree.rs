@@ -0,0 +1,99 @@
+#[cfg(test)]
+mod tests {
+    use crate::make_namespaces_from;
+
+    use mini_git::core::commands::write_tree::*;
+    use mini_git::core::index::{GitIndex, IndexEntry};
+    use mini_git::core::objects::{blob::Blob, write_object, GitObject};
+    use mini_git::core::{Error, GitRepository};
+
+    use mini_git::utils::test::TempDir;
+
+    make_namespaces_from!(make_parser);
+
+    // Each file contains its path and a newline
+    const FILES: &[&str] = &["a.txt", "a/b/z", "a/y", "ab/q", "c/w", "x"];
+
+    // The tree git writes for FILES
+    const TREE: &str = "d0feab2ebca0b5c90a57247aeee076e99a548b63";
+
+    fn run_write_tree(tmp: &TempDir<()>) -> Result<String, String> {
+        let args: [&[&str]; 1] = [&[]];
+        tmp.run(|| {
+            let namespace = make_namespaces(&args).next().unwrap();
+            write_tree(&namespace).map_err(String::from)
+        })
+    }
+
+    fn add_file(repo: &GitRepository, index: &mut GitIndex, path: &str) {
+        let blob = GitObject::Blob(Blob::from(format!("{path}\n").as_bytes()));
+        let sha = write_object(&blob, repo).expect("Write blob").to_string();
+        index.add(IndexEntry::new(0o100_644, &sha, path));
+    }
+
+    #[test]
+    fn test_write_tree() {
+        let tmp =
+            TempDir::create("cmd_write_tree").with_mutex(&crate::TEST_MUTEX);
+        let repo = GitRepository::create(tmp.tmp_dir()).expect("Create repo");
+
+        let mut index = GitIndex::new();
+        for path in FILES {
+            add_file(&repo, &mut index, path);
+        }
+        index.write(&repo).expect("Write index");
+
+        // The index is not written while another process holds its lock
+        let lock = repo.gitdir().join("index.lock");
+        std::fs::write(&lock, "").unwrap();
+        let err = index.write(&repo).unwrap_err();
+        assert!(matches!(err, Error::Locked(ref path) if *path == lock));
+        std::fs::remove_file(&lock).unwrap();
+
+        assert_eq!(run_write_tree(&tmp), Ok(TREE.to_owned()));
+        assert!(!lock.exists());
+
+        // The written trees are cached in the index
+        let index = GitIndex::read(&repo).expect("Read index");
+        let cache = index.cache_tree().expect("Cached trees");
+        assert_eq!(cache.sha(), Some(TREE));
+        assert_eq!(cache.entry_count(), Some(FILES.len()));
+        assert_eq!(cache.find("a").and_then(|a| a.entry_count()), Some(2));
+        assert!(cache.find("a/b").and_then(|b| b.sha()).is_some());
+        assert!(cache.find("missing").is_none());
+    }
+
+    #[test]
+    fn test_write_tree_invalidation() {
+        let tmp = TempDir::create("cmd_write_tree_invalidation")
+            .with_mutex(&crate::TEST_MUTEX);
+        let repo = GitRepository::create(tmp.tmp_dir()).expect("Create repo");
+
+        let mut index = GitIndex::new();
+        for path in FILES {
+            add_file(&repo, &mut index, path);
+        }
+        assert_eq!(index.write_tree(&repo).unwrap(), TREE);
+        let unchanged = index.cache_tree().unwrap().find("c").cloned();
+
+        // Only the directories containing the changed file are invalidated
+        add_file(&repo, &mut index, "a/b/new");
+        let cache = index.cache_tree().unwrap();
+        for dir in ["", "a", "a/b"] {
+            assert!(cache.find(dir).unwrap().sha().is_none(), "{dir}");
+        }
+        assert!(cache.find("a/b").is_some());
+        assert_eq!(cache.find("c").cloned(), unchanged);
+
+        let tree = index.write_tree(&repo).expect("Write tree");
+        assert_ne!(tree, TREE);
+        assert_eq!(index.cache_tree().unwrap().sha(), Some(tree.as_str()));
+        assert_eq!(index.cache_tree().unwrap().find("c").cloned(), unchanged);
+
+        // Conflicted entries cannot be written as a tree
+        let mut conflicted = index.entries()[0].clone();
+        conflicted.flags |= 1 << 12;
+        index.add(conflicted);
+        assert!(index.write_tree(&repo).is_err());
+    }
+}
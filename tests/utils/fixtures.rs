@@ -0,0 +1,72 @@
+//! Repositories and objects shared by the integration tests, written through
+//! the repository facade.
+
+use mini_git::core::index::{GitIndex, IndexEntry};
+use mini_git::core::objects::commit::Signature;
+use mini_git::core::objects::oid::Oid;
+use mini_git::core::GitRepository;
+
+use mini_git::utils::test::TempDir;
+
+/// Creates an empty repository in a new temporary directory, which holds the
+/// test mutex.
+pub fn temp_repo(name: &str) -> (TempDir<'static, ()>, GitRepository) {
+    let tmp = TempDir::create(name).with_mutex(&crate::TEST_MUTEX);
+    let repo = GitRepository::create(tmp.tmp_dir()).expect("Create repo");
+    (tmp, repo)
+}
+
+pub fn write_blob(repo: &GitRepository, content: &str) -> Oid {
+    repo.write_blob(content.as_bytes()).expect("Write blob")
+}
+
+/// Writes a tree of files, given by mode, path and content. The content of a
+/// gitlink is the commit it points to.
+pub fn write_tree_with_modes(
+    repo: &GitRepository,
+    files: &[(u32, &str, &str)],
+) -> Oid {
+    let mut index = GitIndex::new();
+    for &(mode, path, content) in files {
+        let sha = if mode == 0o160_000 {
+            content.to_owned()
+        } else {
+            write_blob(repo, content).to_string()
+        };
+        index.add(IndexEntry::new(mode, &sha, path));
+    }
+    let tree = index.write_tree(repo).expect("Write tree");
+    tree.parse().expect("Valid tree name")
+}
+
+/// Writes a tree of regular files, given by path and content.
+pub fn write_tree(repo: &GitRepository, files: &[(&str, &str)]) -> Oid {
+    let files = files
+        .iter()
+        .map(|&(path, content)| (0o100_644, path, content))
+        .collect::<Vec<_>>();
+    write_tree_with_modes(repo, &files)
+}
+
+/// Writes a commit of `tree` by John Doe, made at `time`.
+pub fn write_commit(
+    repo: &GitRepository,
+    tree: &Oid,
+    parents: &[Oid],
+    time: u64,
+    message: &str,
+) -> Oid {
+    let sig: Signature = format!("John Doe <john@example.com> {time} +0200")
+        .parse()
+        .expect("Parse signature");
+    repo.create_commit(None, &sig, &sig, message, tree, parents)
+        .expect("Write commit")
+}
+
+/// Points `main`, the branch `HEAD` is on, at `commit`.
+pub fn set_head(repo: &GitRepository, commit: &str) {
+    let refs_dir = repo.gitdir().join("refs").join("heads");
+    std::fs::create_dir_all(&refs_dir).expect("Create refs/heads");
+    std::fs::write(refs_dir.join("main"), format!("{commit}\n"))
+        .expect("Write main ref");
+}
@@ -1,5 +1,15 @@
+pub mod fixtures;
+pub mod test_color;
 pub mod test_configparser;
 pub mod test_debug;
+pub mod test_ewah;
 pub mod test_fnmatch;
+pub mod test_gitattributes;
+pub mod test_gitignore;
+pub mod test_json;
+pub mod test_progress;
 pub mod test_sha1;
+pub mod test_sha256;
+pub mod test_threadpool;
+pub mod test_trace;
 pub mod test_zlib;
@@ -0,0 +1,116 @@
+#[cfg(test)]
+mod tests {
+    use mini_git::utils::color::{parse_color, ColorMode, Palette};
+    use mini_git::utils::configparser::ConfigParser;
+
+    #[test]
+    fn test_parse_color_mode() {
+        assert_eq!("auto".parse(), Ok(ColorMode::Auto));
+        assert_eq!("always".parse(), Ok(ColorMode::Always));
+        assert_eq!("never".parse(), Ok(ColorMode::Never));
+        assert!("sometimes".parse::<ColorMode>().is_err());
+    }
+
+    #[test]
+    fn test_resolve_color_mode() {
+        // (no_color, is_terminal)
+        let cases =
+            [(false, false), (false, true), (true, false), (true, true)];
+        for (no_color, is_terminal) in cases {
+            assert!(ColorMode::Always.resolve(no_color, is_terminal));
+            assert!(!ColorMode::Never.resolve(no_color, is_terminal));
+            assert_eq!(
+                ColorMode::Auto.resolve(no_color, is_terminal),
+                !no_color && is_terminal
+            );
+        }
+    }
+
+    #[test]
+    fn test_palette() {
+        assert_eq!(Palette::new(ColorMode::Always), Palette::COLOR);
+        assert_eq!(Palette::new(ColorMode::Never), Palette::PLAIN);
+        assert_eq!(Palette::PLAIN.old, "");
+        assert_eq!(Palette::COLOR.old, "\x1b[31m");
+    }
+
+    #[test]
+    fn test_color_mode_from_config() {
+        let mut config = ConfigParser::new();
+        assert_eq!(
+            ColorMode::from_config(&config, "diff"),
+            Ok(ColorMode::Auto)
+        );
+
+        config.add_config("color", "ui", "false");
+        assert_eq!(
+            ColorMode::from_config(&config, "diff"),
+            Ok(ColorMode::Never)
+        );
+        config.add_config("color", "ui", "true");
+        assert_eq!(
+            ColorMode::from_config(&config, "diff"),
+            Ok(ColorMode::Auto)
+        );
+
+        // The setting for the command wins over color.ui
+        config.add_config("color", "log", "always");
+        assert_eq!(
+            ColorMode::from_config(&config, "diff"),
+            Ok(ColorMode::Auto)
+        );
+        assert_eq!(
+            ColorMode::from_config(&config, "log"),
+            Ok(ColorMode::Always)
+        );
+
+        config.add_config("color", "ui", "sometimes");
+        assert!(ColorMode::from_config(&config, "diff").is_err());
+    }
+
+    #[test]
+    fn test_parse_color() {
+        let cases = [
+            ("", ""),
+            ("normal", ""),
+            ("red", "\x1b[31m"),
+            ("BrightGreen", "\x1b[92m"),
+            ("normal blue", "\x1b[44m"),
+            ("default default", "\x1b[39;49m"),
+            ("ul reverse white", "\x1b[4;7;37m"),
+            ("no-bold noul", "\x1b[22;24m"),
+            ("0 255", "\x1b[38;5;0;48;5;255m"),
+            ("#00ff7f bold", "\x1b[1;38;2;0;255;127m"),
+        ];
+        for (value, code) in cases {
+            assert_eq!(parse_color(value).as_deref(), Ok(code), "{value}");
+        }
+
+        for value in ["purple", "256", "#fff", "#gg0000", "red green blue"] {
+            assert!(parse_color(value).is_err(), "{value}");
+        }
+    }
+
+    #[test]
+    fn test_palette_with_config() {
+        let mut config = ConfigParser::new();
+        config
+            .add_config("color \"diff\"", "frag", "magenta")
+            .add_config("color \"diff\"", "new", "normal")
+            .add_config("color \"log\"", "author", "bold");
+
+        let palette = Palette::COLOR.with_config(&config).unwrap();
+        assert_eq!(palette.frag, "\x1b[35m");
+        assert_eq!(palette.new, "");
+        assert_eq!(palette.author, "\x1b[1m");
+        assert_eq!(palette.meta, Palette::COLOR.meta);
+
+        // Configured colors are not used when color is off
+        let palette = Palette::from_config(ColorMode::Never, &config).unwrap();
+        assert_eq!(palette, Palette::PLAIN);
+
+        config.add_config("color \"diff\"", "old", "reddish");
+        let err = Palette::COLOR.with_config(&config).unwrap_err();
+        assert!(err.contains("color.diff.old"), "{err}");
+    }
+}
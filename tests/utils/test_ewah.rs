@@ -0,0 +1,78 @@
+use mini_git::utils::ewah::EwahBitmap;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(data: &[u8]) -> Vec<u64> {
+        data[8..data.len() - 4]
+            .chunks_exact(8)
+            .map(|b| u64::from_be_bytes(b.try_into().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_empty() {
+        let bitmap = EwahBitmap::default();
+        let data = bitmap.serialize();
+        // Git writes an empty bitmap as a single empty RLW
+        assert_eq!(
+            data,
+            [[0u8; 4].as_slice(), &1u32.to_be_bytes(), &[0; 12]].concat()
+        );
+        assert_eq!(EwahBitmap::parse(&data), Ok((bitmap, data.len())));
+    }
+
+    #[test]
+    fn test_runs_and_literals() {
+        let bitmap = EwahBitmap::from_positions([0, 64 * 100]);
+        assert_eq!(bitmap.bit_size(), 64 * 100 + 1);
+        assert_eq!(bitmap.count(), 2);
+
+        let data = bitmap.serialize();
+        assert_eq!(&data[..4], &6401u32.to_be_bytes());
+        assert_eq!(words(&data), [1 << 33, 1, (1 << 33) | (99 << 1), 1]);
+        // The position of the last RLW
+        assert_eq!(&data[data.len() - 4..], &2u32.to_be_bytes());
+
+        let (parsed, len) = EwahBitmap::parse(&data).unwrap();
+        assert_eq!(len, data.len());
+        assert_eq!(parsed.positions().collect::<Vec<_>>(), [0, 6400]);
+    }
+
+    #[test]
+    fn test_ones() {
+        let bitmap = EwahBitmap::from_positions(0..200);
+        let data = bitmap.serialize();
+        // Three words of ones, and the remaining 8 bits as a literal
+        assert_eq!(words(&data), [(1 << 33) | (3 << 1) | 1, 0xff]);
+
+        let (parsed, _) = EwahBitmap::parse(&data).unwrap();
+        assert_eq!(parsed, bitmap);
+        assert!(parsed.contains(199));
+        assert!(!parsed.contains(200));
+    }
+
+    #[test]
+    fn test_trailing_data() {
+        let mut data = EwahBitmap::from_positions([3]).serialize();
+        let len = data.len();
+        data.extend_from_slice(b"rest");
+        let (parsed, parsed_len) = EwahBitmap::parse(&data).unwrap();
+        assert_eq!(parsed_len, len);
+        assert!(parsed.contains(3));
+    }
+
+    #[test]
+    fn test_truncated() {
+        let data = EwahBitmap::from_positions([1, 100]).serialize();
+        for len in [0, 6, data.len() - 1] {
+            assert!(EwahBitmap::parse(&data[..len]).is_err(), "{len}");
+        }
+
+        // The words must cover the size of the bitmap
+        let mut data = data;
+        data[..4].copy_from_slice(&1000u32.to_be_bytes());
+        assert!(EwahBitmap::parse(&data).is_err());
+    }
+}
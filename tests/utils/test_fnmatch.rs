@@ -1,6 +1,6 @@
 #[cfg(test)]
 mod tests {
-    use mini_git::utils::fnmatch::fnmatch;
+    use mini_git::utils::fnmatch::{fnmatch, fnmatch_with};
     #[cfg(test)]
     use mini_git::utils::test::TempDir;
     use std::fs::File;
@@ -13,6 +13,69 @@ mod tests {
         file.write_all(b"test content").unwrap();
     }
 
+    /// The file names of the matches of `pattern` in `dir`.
+    fn names(dir: &Path, pattern: &str, ignore_case: bool) -> Vec<String> {
+        let pattern = format!("{}/{pattern}", dir.to_str().unwrap());
+        fnmatch_with(&pattern, ignore_case)
+            .unwrap_or_default()
+            .iter()
+            .map(|path| {
+                let path = Path::new(path);
+                let parent = path.parent().unwrap().file_name().unwrap();
+                let name = path.file_name().unwrap();
+                format!(
+                    "{}/{}",
+                    parent.to_str().unwrap(),
+                    name.to_str().unwrap()
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_fnmatch_components() {
+        let mut tmp_dir = TempDir::<()>::create("fnmatch_components")
+            .with_mutex(&crate::TEST_MUTEX);
+        tmp_dir.auto_revert(false);
+        let tmp_dir = tmp_dir.tmp_dir();
+        for dir in ["src", "tests", "docs"] {
+            std::fs::create_dir(tmp_dir.join(dir)).unwrap();
+        }
+        create_test_file(&tmp_dir.join("src"), "main.rs");
+        create_test_file(&tmp_dir.join("src"), "lib.rs");
+        create_test_file(&tmp_dir.join("tests"), "fnmatch.rs");
+        create_test_file(&tmp_dir.join("docs"), "README.md");
+
+        assert_eq!(
+            names(tmp_dir, "*/*.rs", false),
+            ["src/lib.rs", "src/main.rs", "tests/fnmatch.rs"]
+        );
+        assert_eq!(names(tmp_dir, "[st]*/m*.rs", false), ["src/main.rs"]);
+        assert_eq!(names(tmp_dir, "src/lib.rs", false), ["src/lib.rs"]);
+        assert!(names(tmp_dir, "src/missing.rs", false).is_empty());
+        // `*` does not match the separator
+        assert!(names(tmp_dir, "*.rs", false).is_empty());
+    }
+
+    #[test]
+    fn test_fnmatch_hidden_and_case() {
+        let mut tmp_dir = TempDir::<()>::create("fnmatch_hidden_and_case")
+            .with_mutex(&crate::TEST_MUTEX);
+        tmp_dir.auto_revert(false);
+        let tmp_dir = tmp_dir.tmp_dir();
+        let dir = tmp_dir.file_name().unwrap().to_str().unwrap();
+        create_test_file(tmp_dir, ".hidden.txt");
+        create_test_file(tmp_dir, "Notes.TXT");
+
+        assert!(names(tmp_dir, "*.txt", false).is_empty());
+        assert_eq!(names(tmp_dir, "*.txt", true), [format!("{dir}/Notes.TXT")]);
+        assert_eq!(names(tmp_dir, "n*", true), [format!("{dir}/Notes.TXT")]);
+        assert_eq!(
+            names(tmp_dir, ".*.txt", false),
+            [format!("{dir}/.hidden.txt")]
+        );
+    }
+
     #[cfg(target_family = "unix")]
     mod unix_tests {
         use super::*;
@@ -0,0 +1,75 @@
+#[cfg(test)]
+mod tests {
+    use mini_git::utils::gitattributes::{AttrValue, GitAttributes};
+
+    fn attributes(text: &str) -> GitAttributes {
+        let mut attributes = GitAttributes::new();
+        attributes.add_patterns("", text);
+        attributes
+    }
+
+    fn value(value: &str) -> Option<AttrValue> {
+        Some(AttrValue::Value(value.to_owned()))
+    }
+
+    #[test]
+    fn test_attribute_states() {
+        let attrs = attributes("*.txt text -diff eol=lf !merge\n");
+        assert_eq!(attrs.get("a.txt", "text"), Some(AttrValue::Set));
+        assert_eq!(attrs.get("a.txt", "diff"), Some(AttrValue::Unset));
+        assert_eq!(attrs.get("a.txt", "eol"), value("lf"));
+        assert_eq!(attrs.get("a.txt", "merge"), None);
+        assert_eq!(attrs.get("a.txt", "other"), None);
+        assert_eq!(attrs.get("a.rs", "text"), None);
+    }
+
+    #[test]
+    fn test_comments_and_invalid_patterns() {
+        let attrs = attributes("# *.txt diff\n\n!*.txt diff\nbuild/ diff\n");
+        assert!(attrs.is_empty());
+    }
+
+    #[test]
+    fn test_binary_macro() {
+        let attrs = attributes("*.png binary\n");
+        for name in ["diff", "merge", "text"] {
+            assert_eq!(attrs.get("img/a.png", name), Some(AttrValue::Unset));
+        }
+    }
+
+    #[test]
+    fn test_last_match_wins() {
+        let attrs = attributes("* diff=a\n*.pdf diff=pdf\nold.pdf !diff\n");
+        assert_eq!(attrs.get("a.rs", "diff"), value("a"));
+        assert_eq!(attrs.get("doc/a.pdf", "diff"), value("pdf"));
+        assert_eq!(attrs.get("doc/old.pdf", "diff"), None);
+
+        // Within a line, the last assignment wins too
+        let attrs = attributes("*.c diff -diff\n");
+        assert_eq!(attrs.get("a.c", "diff"), Some(AttrValue::Unset));
+    }
+
+    #[test]
+    fn test_anchored() {
+        let attrs = attributes("/root.txt diff\ndoc/*.md diff\n");
+        assert_eq!(attrs.get("root.txt", "diff"), Some(AttrValue::Set));
+        assert_eq!(attrs.get("sub/root.txt", "diff"), None);
+        assert_eq!(attrs.get("doc/a.md", "diff"), Some(AttrValue::Set));
+        assert_eq!(attrs.get("doc/sub/a.md", "diff"), None);
+        assert_eq!(attrs.get("sub/doc/a.md", "diff"), None);
+    }
+
+    #[test]
+    fn test_scoped_patterns() {
+        let mut attrs = GitAttributes::new();
+        attrs
+            .add_patterns("", "*.bin diff=hex\n")
+            .add_patterns("sub", "*.bin -diff\n/local diff\n");
+        assert_eq!(attrs.get("a.bin", "diff"), value("hex"));
+        assert_eq!(attrs.get("sub/a.bin", "diff"), Some(AttrValue::Unset));
+        assert_eq!(attrs.get("subway/a.bin", "diff"), value("hex"));
+        assert_eq!(attrs.get("sub/local", "diff"), Some(AttrValue::Set));
+        assert_eq!(attrs.get("local", "diff"), None);
+        assert_eq!(attrs.get("sub/deeper/local", "diff"), None);
+    }
+}
@@ -0,0 +1,199 @@
+#[cfg(test)]
+mod tests {
+    use mini_git::utils::gitignore::{
+        wildmatch, GitignoreSet, GitignoreStack, IGNORE_FILE,
+    };
+    use mini_git::utils::test::TempDir;
+    use std::fs;
+
+    fn set(text: &str) -> GitignoreSet {
+        let mut set = GitignoreSet::new();
+        set.add_patterns("", text);
+        set
+    }
+
+    #[test]
+    fn test_wildmatch() {
+        let cases: &[(&str, &str, bool)] = &[
+            ("*.rs", "main.rs", true),
+            ("*.rs", "src/main.rs", false),
+            ("?.c", "a.c", true),
+            ("?.c", "ab.c", false),
+            ("[abc].txt", "b.txt", true),
+            ("[!abc].txt", "b.txt", false),
+            ("[a-z]1", "q1", true),
+            ("[a-z]1", "Q1", false),
+            ("**/foo", "foo", true),
+            ("**/foo", "a/b/foo", true),
+            ("a/**/b", "a/b", true),
+            ("a/**/b", "a/x/y/b", true),
+            ("a/**", "a/x/y", true),
+            ("a/**", "a", false),
+            ("\\*", "*", true),
+            ("\\*", "a", false),
+            ("[unterminated", "[unterminated", true),
+            ("*a*b", "xaxb", true),
+            ("*a*b", "xaxbx", false),
+            ("a*/b", "ax/b", true),
+            ("a*/b", "a/x/b", false),
+            ("**/a*b", "x/y/aab", true),
+        ];
+        for &(pattern, text, expected) in cases {
+            assert_eq!(
+                wildmatch(pattern.as_bytes(), text.as_bytes()),
+                expected,
+                "{pattern} vs {text}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_wildmatch_many_stars() {
+        // Each star used to retry every position of the ones after it
+        let pattern = b"*a*a*a*a*a*a*a*a*a*a*a*b";
+        let text = [b'a'; 60];
+        assert!(!wildmatch(pattern, &text));
+        assert!(!set("*a*a*a*a*a*a*a*a*a*a*a*b")
+            .is_ignored("a".repeat(60).as_str(), false));
+
+        let mut text = text.to_vec();
+        text.push(b'b');
+        assert!(wildmatch(pattern, &text));
+        assert!(!wildmatch(b"**/*a*a*a*a*a*a*a*a*b", &[b'a'; 60]));
+    }
+
+    #[test]
+    fn test_comments_and_blank_lines() {
+        let rules = set("# comment\n\n\\#literal\n   \n");
+        assert!(!rules.is_ignored("# comment", false));
+        assert!(rules.is_ignored("#literal", false));
+    }
+
+    #[test]
+    fn test_trailing_spaces() {
+        let rules = set("trailing   \nescaped\\ \n");
+        assert!(rules.is_ignored("trailing", false));
+        assert!(rules.is_ignored("escaped ", false));
+        assert!(!rules.is_ignored("escaped", false));
+    }
+
+    #[test]
+    fn test_negation_last_match_wins() {
+        let rules = set("*.log\n!important.log\n");
+        assert!(rules.is_ignored("debug.log", false));
+        assert!(!rules.is_ignored("important.log", false));
+        assert!(!rules.is_ignored("a/important.log", false));
+
+        let rules = set("!important.log\n*.log\n");
+        assert!(rules.is_ignored("important.log", false));
+    }
+
+    #[test]
+    fn test_directory_only() {
+        let rules = set("build/\n");
+        assert!(rules.is_ignored("build", true));
+        assert!(!rules.is_ignored("build", false));
+        assert!(rules.is_ignored("src/build", true));
+        assert!(rules.is_ignored("build/output.o", false));
+    }
+
+    #[test]
+    fn test_anchored() {
+        let rules = set("/root.txt\ndoc/*.md\n");
+        assert!(rules.is_ignored("root.txt", false));
+        assert!(!rules.is_ignored("sub/root.txt", false));
+        assert!(rules.is_ignored("doc/a.md", false));
+        assert!(!rules.is_ignored("doc/sub/a.md", false));
+        assert!(!rules.is_ignored("sub/doc/a.md", false));
+    }
+
+    #[test]
+    fn test_ignored_directory_cannot_be_reincluded() {
+        let rules = set("vendor/\n!vendor/keep.rs\n");
+        assert!(rules.is_ignored("vendor/keep.rs", false));
+
+        let rules = set("vendor/*\n!vendor/keep.rs\n");
+        assert!(!rules.is_ignored("vendor/keep.rs", false));
+        assert!(rules.is_ignored("vendor/drop.rs", false));
+    }
+
+    #[test]
+    fn test_scoped_patterns() {
+        let mut rules = GitignoreSet::new();
+        rules
+            .add_patterns("", "*.tmp\n")
+            .add_patterns("sub", "!keep.tmp\n/local\n");
+        assert!(rules.is_ignored("keep.tmp", false));
+        assert!(!rules.is_ignored("sub/keep.tmp", false));
+        assert!(rules.is_ignored("subway/keep.tmp", false));
+        assert!(rules.is_ignored("sub/local", false));
+        assert!(!rules.is_ignored("local", false));
+        assert!(!rules.is_ignored("sub/deeper/local", false));
+    }
+
+    #[test]
+    fn test_directory_pruning() {
+        let rules = set("target/\nnode_modules\nvendor/*\n!vendor/keep.rs\n");
+        assert!(rules.is_dir_fully_ignored("target"));
+        assert!(rules.is_dir_fully_ignored("target/debug/deps"));
+        assert!(rules.is_dir_fully_ignored("web/node_modules"));
+        assert!(!rules.is_dir_fully_ignored("src"));
+        // Some of its contents are re-included
+        assert!(!rules.is_dir_fully_ignored("vendor"));
+
+        assert!(!rules.should_descend("target"));
+        assert!(!rules.should_descend("web/node_modules/"));
+        assert!(rules.should_descend("vendor"));
+        // Only the directory itself is tested, not its parents
+        assert!(rules.should_descend("target/debug"));
+    }
+
+    #[test]
+    fn test_stack_per_directory_rules() {
+        let mut tmp_dir = TempDir::<()>::create("gitignore_stack")
+            .with_mutex(&crate::TEST_MUTEX);
+        tmp_dir.auto_revert(false);
+        let root = tmp_dir.tmp_dir();
+        for dir in ["a/deep", "b"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        fs::write(root.join(IGNORE_FILE), "*.log\n").unwrap();
+        fs::write(root.join("a").join(IGNORE_FILE), "!keep.log\n*.tmp\n")
+            .unwrap();
+        fs::write(root.join("b").join(IGNORE_FILE), "/local\n").unwrap();
+
+        let mut base = GitignoreSet::new();
+        base.add_patterns("", "*.bak\n*.log\n");
+        let mut rules = GitignoreStack::new(root, base);
+
+        // The directories above are entered too
+        rules.enter("a/deep").unwrap();
+        assert!(rules.is_ignored("a/deep/x.tmp", false));
+        assert!(rules.is_ignored("a/deep/x.log", false));
+        assert!(!rules.is_ignored("a/deep/keep.log", false));
+        assert!(rules.is_ignored("a/deep/x.bak", false));
+
+        // Entering a sibling leaves `a` and its rules
+        rules.enter("b").unwrap();
+        assert!(!rules.is_ignored("b/x.tmp", false));
+        assert!(rules.is_ignored("b/keep.log", false));
+        assert!(rules.is_ignored("b/local", true));
+
+        // Rules are read once, when first entering their directory
+        fs::write(root.join("a").join(IGNORE_FILE), "").unwrap();
+        rules.enter("a").unwrap();
+        assert!(rules.is_ignored("a/x.tmp", false));
+
+        rules.enter("").unwrap();
+        assert!(!rules.is_ignored("local", false));
+        assert!(!rules.is_ignored("x.tmp", false));
+        assert!(rules.is_ignored("x.log", false));
+    }
+
+    #[test]
+    fn test_stack_without_root_ignores_nothing() {
+        let mut rules = GitignoreStack::default();
+        rules.enter("a/b").unwrap();
+        assert!(!rules.is_ignored("a/b/c.o", false));
+    }
+}
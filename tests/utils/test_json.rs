@@ -0,0 +1,40 @@
+#[cfg(test)]
+mod tests {
+    use mini_git::utils::json::Json;
+
+    #[test]
+    fn test_scalars() {
+        assert_eq!(Json::Null.to_string(), "null");
+        assert_eq!(Json::from(true).to_string(), "true");
+        assert_eq!(Json::from(-42i64).to_string(), "-42");
+        assert_eq!(Json::from(u64::MAX).to_string(), "18446744073709551615");
+        assert_eq!(Json::from(None::<&str>).to_string(), "null");
+        assert_eq!(Json::from(Some("x")).to_string(), "\"x\"");
+    }
+
+    #[test]
+    fn test_string_escapes() {
+        let json = Json::from("a \"quoted\" \\ path\n\twith\r\u{1}\u{7f} é");
+        assert_eq!(
+            json.to_string(),
+            r#""a \"quoted\" \\ path\n\twith\r\u0001\u007f é""#
+        );
+    }
+
+    #[test]
+    fn test_nested() {
+        let json = Json::object([
+            ("empty", Json::Array(vec![])),
+            ("list", vec![1usize, 2, 3].into()),
+            ("object", Json::object([("key", Json::from("value"))])),
+            ("none", Json::object::<String, _>([])),
+        ]);
+        assert_eq!(
+            json.to_string(),
+            r#"{"empty":[],"list":[1,2,3],"object":{"key":"value"},"none":{}}"#
+        );
+
+        let collected: Json = ["a", "b"].into_iter().collect();
+        assert_eq!(collected, Json::from(vec!["a", "b"]));
+    }
+}
@@ -0,0 +1,146 @@
+#[cfg(test)]
+mod tests {
+    use mini_git::core::objects::packwriter::{
+        index_pack_with_progress, PackWriter,
+    };
+    use mini_git::core::ObjectFormat;
+    use mini_git::utils::progress::{
+        resolve, NoProgress, Progress, StderrProgress,
+    };
+
+    /// Records the phases reported, with their total and last count.
+    #[derive(Default)]
+    struct Recorder {
+        phases: Vec<(String, Option<u64>, u64)>,
+        finished: usize,
+    }
+
+    impl Progress for Recorder {
+        fn start(&mut self, title: &str, total: Option<u64>) {
+            self.phases.push((title.to_owned(), total, 0));
+        }
+
+        fn update(&mut self, done: u64, _bytes: Option<u64>) {
+            self.phases.last_mut().unwrap().2 = done;
+        }
+
+        fn finish(&mut self) {
+            self.finished += 1;
+        }
+    }
+
+    fn raw_blob(data: &[u8]) -> Vec<u8> {
+        [format!("blob {}\0", data.len()).as_bytes(), data].concat()
+    }
+
+    fn drawn(progress: StderrProgress<Vec<u8>>) -> String {
+        String::from_utf8(progress.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn test_percentage() {
+        let mut progress = StderrProgress::with_writer(Vec::new());
+        progress.start("Counting", Some(3));
+        for done in 1..=3 {
+            progress.update(done, None);
+        }
+        progress.finish();
+
+        let drawn = drawn(progress);
+        let lines: Vec<_> = drawn.split('\r').skip(1).collect();
+        assert_eq!(
+            lines,
+            [
+                "Counting:  33% (1/3)",
+                "Counting:  66% (2/3)",
+                "Counting: 100% (3/3)",
+                "Counting: 100% (3/3), done.\n",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_redraw_on_percent_change() {
+        let mut progress = StderrProgress::with_writer(Vec::new());
+        progress.start("Counting", Some(1000));
+        for done in 1..=1000 {
+            progress.update(done, None);
+        }
+        // Once per percent, from 0% to 100%
+        assert_eq!(drawn(progress).matches('\r').count(), 101);
+    }
+
+    #[test]
+    fn test_unknown_total() {
+        let mut progress = StderrProgress::with_writer(Vec::new());
+        progress.start("Enumerating", None);
+        progress.update(7, None);
+        progress.finish();
+        assert_eq!(
+            drawn(progress),
+            "\rEnumerating: | 7\rEnumerating: 7, done.\n"
+        );
+    }
+
+    #[test]
+    fn test_bytes() {
+        let mut progress = StderrProgress::with_writer(Vec::new());
+        progress.start("Receiving", Some(2));
+        progress.update(1, Some(512));
+        progress.update(2, Some(3 * 1024 + 512));
+        let drawn = drawn(progress);
+        assert!(drawn.contains("Receiving:  50% (1/2), 512 bytes"));
+        assert!(drawn.contains("Receiving: 100% (2/2), 3.50 KiB"));
+    }
+
+    #[test]
+    fn test_resolve() {
+        // (disabled, is_terminal)
+        assert!(resolve(false, true));
+        assert!(!resolve(false, false));
+        assert!(!resolve(true, true));
+        assert!(!resolve(true, false));
+    }
+
+    #[test]
+    fn test_pack_phases() {
+        let base: Vec<u8> = (0..500u32)
+            .flat_map(|i| format!("line {i}\n").into_bytes())
+            .collect();
+        let mut changed = base.clone();
+        changed.extend_from_slice(b"one more line\n");
+
+        let mut writer = PackWriter::new();
+        writer.add(&raw_blob(&base)).unwrap();
+        writer.add(&raw_blob(&changed)).unwrap();
+        writer.add(&raw_blob(b"other")).unwrap();
+
+        let mut recorder = Recorder::default();
+        let mut pack = Vec::new();
+        writer
+            .write_with_progress(&mut pack, &mut recorder)
+            .unwrap();
+        assert_eq!(recorder.phases, [("Writing objects".into(), Some(3), 3)]);
+
+        let mut recorder = Recorder::default();
+        index_pack_with_progress(pack, None, ObjectFormat::Sha1, &mut recorder)
+            .unwrap();
+        assert_eq!(
+            recorder.phases,
+            [
+                ("Indexing objects".into(), Some(3), 3),
+                ("Resolving deltas".into(), Some(1), 1),
+            ]
+        );
+        assert_eq!(recorder.finished, 2);
+
+        // Progress does not change what is written
+        let mut quiet = Vec::new();
+        writer
+            .write_with_progress(&mut quiet, &mut NoProgress)
+            .unwrap();
+        let mut plain = Vec::new();
+        writer.write(&mut plain).unwrap();
+        assert_eq!(quiet, plain);
+    }
+}
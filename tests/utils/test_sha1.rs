@@ -1,9 +1,32 @@
-use mini_git::utils::sha1::SHA1;
+use mini_git::utils::hex;
+use mini_git::utils::sha1::{sha1, SHA1};
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    // The header and the near-collision blocks of the SHAttered PDFs
+    const SHATTERED_PREFIX: &str = concat!(
+        "255044462d312e330a25e2e3cfd30a0a0a312030206f626a0a3c3c2f57696474",
+        "682032203020522f4865696768742033203020522f547970652034203020522f",
+        "537562747970652035203020522f46696c7465722036203020522f436f6c6f72",
+        "53706163652037203020522f4c656e6774682038203020522f42697473506572",
+        "436f6d706f6e656e7420383e3e0a73747265616d0affd8fffe00245348412d31",
+        "20697320646561642121212121852fec092339759c39b1a1c63c4c97e1fffe01",
+    );
+    const SHATTERED_1: &str = concat!(
+        "7346dc9166b67e118f029ab621b2560ff9ca67cca8c7f85ba84c79030c2b3de2",
+        "18f86db3a90901d5df45c14f26fedfb3dc38e96ac22fe7bd728f0e45bce046d2",
+        "3c570feb141398bb552ef5a0a82be331fea48037b8b5d71f0e332edf93ac3500",
+        "eb4ddc0decc1a864790c782c76215660dd309791d06bd0af3f98cda4bc4629b1",
+    );
+    const SHATTERED_2: &str = concat!(
+        "7f46dc93a6b67e013b029aaa1db2560b45ca67d688c7f84b8c4c791fe02b3df6",
+        "14f86db1690901c56b45c1530afedfb76038e972722fe7ad728f0e4904e046c2",
+        "30570fe9d41398abe12ef5bc942be33542a4802d98b5d70f2a332ec37fac3514",
+        "e74ddc0f2cc1a874cd0c78305a21566461309789606bd0bf3f98cda8044629a1",
+    );
+
     #[test]
     fn test_empty_string() {
         let mut sha1 = SHA1::new();
@@ -105,4 +128,92 @@ mod tests {
             assert_eq!(sha1.hex_digest(), x.1);
         }
     }
+
+    #[test]
+    fn test_million_a() {
+        let data = vec![b'a'; 1_000_000];
+        let mut hasher = SHA1::new();
+        for chunk in data.chunks(1000) {
+            let _ = hasher.update(chunk);
+        }
+        assert_eq!(
+            hasher.hex_digest(),
+            "34aa973cd4c4daa4f61eeb2bdbad27316534016f"
+        );
+    }
+
+    #[test]
+    fn test_block_boundaries() {
+        let data: Vec<u8> = (0..200u8).collect();
+        for len in 0..data.len() {
+            let expected = sha1(&data[..len]);
+            for split in [1, 7, 55, 56, 63, 64, 65, 128] {
+                let mut hasher = SHA1::new();
+                for chunk in data[..len].chunks(split) {
+                    let _ = hasher.update(chunk);
+                }
+                assert_eq!(hasher.finalize(), expected, "len {len}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_finalize_twice() {
+        let mut hasher = SHA1::new();
+        let _ = hasher.update(b"The quick brown fox ");
+        assert_eq!(hasher.finalize(), hasher.finalize());
+
+        let _ = hasher.update(b"jumps over the lazy dog");
+        assert_eq!(
+            hasher.hex_digest(),
+            "2fd4e1c67a2d28fced849ee1bb76e7391b93eb12"
+        );
+    }
+
+    #[test]
+    fn test_collision_detection() {
+        let data: Vec<u8> =
+            (0..2000u32).map(|i| (i * 31 % 256) as u8).collect();
+        for len in [0, 55, 64, 119, 1000, 2000] {
+            let mut hasher = SHA1::new().with_collision_detection();
+            let _ = hasher.update(&data[..len]);
+            assert_eq!(hasher.finalize(), sha1(&data[..len]));
+            assert!(!hasher.found_collision());
+        }
+        assert!(!SHA1::new().found_collision());
+    }
+
+    #[test]
+    fn test_shattered() {
+        let decode = |blocks| {
+            hex::decode(&[SHATTERED_PREFIX, blocks].concat()).expect("Decode")
+        };
+        let first = decode(SHATTERED_1);
+        let second = decode(SHATTERED_2);
+
+        // Different messages with the same SHA-1 hash
+        assert_ne!(first, second);
+        assert_eq!(sha1(&first), sha1(&second));
+        assert_eq!(
+            hex::encode(&sha1(&first)),
+            "f92d74e3874587aaf443d1db961d4e26dde13e9c"
+        );
+
+        // The collision is detected, and the safe hashes differ
+        for (message, digest) in [
+            (&first, "7117b3cb9225aaf0d8ef1a40e493957b0bf8693d"),
+            (&second, "29f38ae9fd98e2931120fa0bf213e024250d3f6a"),
+        ] {
+            let mut hasher = SHA1::new().with_collision_detection();
+            let _ = hasher.update(message);
+            assert_eq!(hasher.hex_digest(), digest);
+            assert!(hasher.found_collision());
+
+            // Not before the blocks of the collision
+            let mut hasher = SHA1::new().with_collision_detection();
+            let _ = hasher.update(&message[..192]);
+            let _ = hasher.finalize();
+            assert!(!hasher.found_collision());
+        }
+    }
 }
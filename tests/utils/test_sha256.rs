@@ -0,0 +1,67 @@
+use mini_git::utils::sha256::{sha256, SHA256};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_string() {
+        let mut sha256 = SHA256::new();
+        let sha256 = sha256.update(b"");
+        assert_eq!(
+            sha256.hex_digest(),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn test_fox() {
+        let mut sha256 = SHA256::new();
+        let sha256 =
+            sha256.update(b"The quick brown fox jumps over the lazy dog");
+        assert_eq!(
+            sha256.hex_digest(),
+            "d7a8fbb307d7809469ca9abcb0082e4f8d5651e46d3cdb762d02d0bf37c9e592"
+        );
+    }
+
+    #[test]
+    fn test_two_blocks() {
+        let mut sha256 = SHA256::new();
+        let sha256 = sha256.update(
+            b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq",
+        );
+        assert_eq!(
+            sha256.hex_digest(),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+    }
+
+    #[test]
+    fn test_million_a() {
+        let data = vec![b'a'; 1_000_000];
+        let mut hasher = SHA256::new();
+        for chunk in data.chunks(1000) {
+            let _ = hasher.update(chunk);
+        }
+        assert_eq!(
+            hasher.hex_digest(),
+            "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0"
+        );
+    }
+
+    #[test]
+    fn test_block_boundaries() {
+        let data: Vec<u8> = (0..200u8).collect();
+        for len in 0..data.len() {
+            let expected = sha256(&data[..len]);
+            for split in [1, 7, 55, 56, 63, 64, 65, 128] {
+                let mut hasher = SHA256::new();
+                for chunk in data[..len].chunks(split) {
+                    let _ = hasher.update(chunk);
+                }
+                assert_eq!(hasher.finalize(), expected, "len {len}");
+            }
+        }
+    }
+}
@@ -0,0 +1,96 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+    use std::time::Duration;
+
+    use mini_git::utils::threadpool::ThreadPool;
+
+    #[test]
+    fn test_size() {
+        assert_eq!(ThreadPool::new(3).size(), 3);
+        assert!(ThreadPool::new(0).size() >= 1);
+        assert_eq!(ThreadPool::new(0).size(), ThreadPool::default().size());
+    }
+
+    #[test]
+    fn test_execute() {
+        let pool = ThreadPool::new(2);
+        let (sender, receiver) = mpsc::channel();
+        for i in 0..10 {
+            let sender = sender.clone();
+            pool.execute(move || sender.send(i).unwrap());
+        }
+        drop(sender);
+
+        let mut values = receiver.iter().collect::<Vec<_>>();
+        values.sort_unstable();
+        assert_eq!(values, (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_map_keeps_order() {
+        let pool = ThreadPool::new(4);
+        // Earlier items finish last
+        let values = pool
+            .map(0..8_u64, |i| {
+                thread::sleep(Duration::from_millis(5 * (8 - i)));
+                i * 10
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(values, [0, 10, 20, 30, 40, 50, 60, 70]);
+    }
+
+    #[test]
+    fn test_map_panic() {
+        let pool = ThreadPool::new(1);
+        let results = pool
+            .map(0..3, |i| {
+                assert_ne!(i, 1, "Boom");
+                i
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(results[0], Ok(0));
+        assert!(results[1].is_err());
+        // The worker survives the panic
+        assert_eq!(results[2], Ok(2));
+    }
+
+    #[test]
+    fn test_pool_is_reused() {
+        let pool = ThreadPool::new(2);
+        let threads = Arc::new(std::sync::Mutex::new(Vec::new()));
+        for _ in 0..5 {
+            let threads = Arc::clone(&threads);
+            pool.map(0..4, move |_| {
+                threads.lock().unwrap().push(thread::current().id());
+            })
+            .for_each(drop);
+        }
+
+        let mut ids = threads.lock().unwrap().clone();
+        ids.sort_by_key(|id| format!("{id:?}"));
+        ids.dedup();
+        assert!(ids.len() <= 2);
+    }
+
+    #[test]
+    fn test_dropped_map_skips_pending_items() {
+        let pool = ThreadPool::new(1);
+        let started = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&started);
+        let mut results = pool.map(0..100, move |i| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(1));
+            i
+        });
+        assert_eq!(results.next(), Some(Ok(0)));
+        drop(results);
+
+        // Wait for the queue to drain
+        pool.map(0..1, |i| i).for_each(drop);
+        assert!(started.load(Ordering::SeqCst) < 100);
+    }
+}
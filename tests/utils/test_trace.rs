@@ -0,0 +1,55 @@
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use mini_git::utils::trace::{
+        resolve_target, set_verbosity, verbosity, Target, Verbosity,
+    };
+
+    #[test]
+    fn test_resolve_target() {
+        for verbosity in [Verbosity::Quiet, Verbosity::Normal] {
+            for value in [None, Some(""), Some("0"), Some("false")] {
+                assert_eq!(resolve_target(value, verbosity), None);
+            }
+        }
+        for value in [None, Some(""), Some("0"), Some("false")] {
+            assert_eq!(
+                resolve_target(value, Verbosity::Verbose),
+                Some(Target::Stderr)
+            );
+        }
+
+        for value in ["1", "2", "true"] {
+            assert_eq!(
+                resolve_target(Some(value), Verbosity::Quiet),
+                Some(Target::Stderr)
+            );
+        }
+    }
+
+    #[test]
+    fn test_resolve_target_file() {
+        let path = std::env::temp_dir().join("trace.log");
+        assert_eq!(
+            resolve_target(path.to_str(), Verbosity::Normal),
+            Some(Target::File(PathBuf::from(&path)))
+        );
+        // Relative paths are ignored
+        assert_eq!(resolve_target(Some("trace.log"), Verbosity::Normal), None);
+        assert_eq!(resolve_target(Some("trace.log"), Verbosity::Verbose), None);
+    }
+
+    #[test]
+    fn test_verbosity() {
+        assert!(Verbosity::Quiet < Verbosity::Normal);
+        assert!(Verbosity::Normal < Verbosity::Verbose);
+        assert_eq!(Verbosity::default(), Verbosity::Normal);
+
+        // Only set levels that leave tracing off for the other tests
+        set_verbosity(Verbosity::Quiet);
+        assert_eq!(verbosity(), Verbosity::Quiet);
+        set_verbosity(Verbosity::Normal);
+        assert_eq!(verbosity(), Verbosity::Normal);
+    }
+}
@@ -1,8 +1,14 @@
 #[cfg(test)]
 mod tests {
     use mini_git::utils::test::walkdir;
-    use mini_git::utils::zlib::{compress, compress::Strategy, decompress};
+    use mini_git::utils::zlib::container::Container;
+    use mini_git::utils::zlib::{
+        compress, compress::Strategy, compress_as, compress_with_dictionary,
+        decompress, decompress_as, decompress_with_dictionary,
+        decompress_with_limit, Inflater,
+    };
     use std::fs;
+    use std::io::Read;
     use std::path::Path;
 
     struct Rng {
@@ -131,4 +137,170 @@ mod tests {
             assert_eq!(bytes, decompressed);
         }
     }
+
+    #[test]
+    fn test_gzip_from_python() {
+        // gzip of "hello, gzip\n" three times, with the name "hello.txt"
+        let gzip = [
+            31, 139, 8, 8, 0, 0, 0, 0, 2, 255, 104, 101, 108, 108, 111, 46,
+            116, 120, 116, 0, 203, 72, 205, 201, 201, 215, 81, 72, 175, 202,
+            44, 224, 202, 192, 193, 6, 0, 38, 33, 7, 17, 36, 0, 0, 0,
+        ];
+        let decompressed =
+            decompress_as(&gzip, Container::Gzip).expect("Decompress gzip");
+        assert_eq!(decompressed, b"hello, gzip\n".repeat(3));
+
+        // A corrupt length is caught
+        let mut corrupt = gzip;
+        corrupt[gzip.len() - 4] ^= 1;
+        assert!(decompress_as(&corrupt, Container::Gzip).is_err());
+    }
+
+    #[test]
+    fn test_raw_deflate_from_python() {
+        let raw = [43, 74, 44, 87, 72, 73, 77, 203, 73, 44, 73, 229, 2, 0];
+        let decompressed =
+            decompress_as(&raw, Container::Raw).expect("Decompress raw");
+        assert_eq!(decompressed, b"raw deflate\n");
+    }
+
+    #[test]
+    fn test_containers_on_license() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let bytes = fs::read(root.join("LICENSE")).expect("Read file!");
+
+        for container in [Container::Zlib, Container::Gzip, Container::Raw] {
+            let compressed = compress_as(&bytes, &Strategy::Auto, container);
+            let decompressed = decompress_as(&compressed, container)
+                .expect("Correct decompression");
+            assert_eq!(bytes, decompressed);
+        }
+    }
+
+    #[test]
+    fn test_dictionary_from_python() {
+        let dictionary = b"the quick brown fox jumps over the lazy dog";
+        let compressed = [
+            120, 187, 97, 60, 15, 250, 67, 102, 163, 171, 65, 51, 2, 0, 93,
+            102, 15, 250,
+        ];
+        let decompressed = decompress_with_dictionary(&compressed, dictionary)
+            .expect("Decompress with dictionary");
+        assert_eq!(
+            decompressed,
+            b"the lazy dog jumps over the quick brown fox"
+        );
+
+        // Without the dictionary, or with another one
+        assert!(decompress(&compressed).is_err());
+        assert!(decompress_with_dictionary(&compressed, b"other").is_err());
+    }
+
+    #[test]
+    fn test_dictionary_round_trip() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let bytes = fs::read(root.join("LICENSE")).expect("Read file!");
+        let (dictionary, data) = bytes.split_at(bytes.len() / 2);
+
+        for strategy in [Strategy::Auto, Strategy::Fixed, Strategy::Dynamic] {
+            let with_dictionary =
+                compress_with_dictionary(data, &strategy, dictionary);
+            let decompressed =
+                decompress_with_dictionary(&with_dictionary, dictionary)
+                    .expect("Correct decompression");
+            assert_eq!(data, decompressed);
+        }
+    }
+
+    #[test]
+    fn test_limit_on_license() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let bytes = fs::read(root.join("LICENSE")).expect("Read file!");
+        let compressed = compress(&bytes, &Strategy::Dynamic);
+
+        let decompressed = decompress_with_limit(&compressed, bytes.len())
+            .expect("Correct decompression");
+        assert_eq!(bytes, decompressed);
+        assert!(decompress_with_limit(&compressed, bytes.len() - 1).is_err());
+
+        let mut inflated = vec![];
+        let result = Inflater::new(compressed.as_slice())
+            .with_limit(bytes.len() as u64 - 1)
+            .read_to_end(&mut inflated);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_corrupt_data_is_an_error() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let bytes = fs::read(root.join("LICENSE")).expect("Read file!");
+        let limit = bytes.len() * 2;
+        let mut rng = Rng::new();
+
+        for strategy in [Strategy::Fixed, Strategy::Dynamic, Strategy::Raw] {
+            let compressed = compress(&bytes, &strategy);
+
+            for len in 0..compressed.len() {
+                let truncated = &compressed[..len];
+                assert!(decompress(truncated).is_err());
+                assert!(Inflater::new(truncated)
+                    .read_to_end(&mut vec![])
+                    .is_err());
+            }
+
+            // Corrupt data need not be an error, if its checksum happens to
+            // match, but it must not panic or grow past the limit
+            for _ in 0..200 {
+                let mut corrupt = compressed.clone();
+                #[allow(clippy::cast_possible_truncation)]
+                let pos = rng.randbelow(corrupt.len() as u64) as usize;
+                corrupt[pos] ^= 1 << rng.randbelow(8);
+
+                if let Ok(data) = decompress_with_limit(&corrupt, limit) {
+                    assert!(data.len() <= limit);
+                }
+                let mut inflated = vec![];
+                let _ = Inflater::new(corrupt.as_slice())
+                    .with_limit(limit as u64)
+                    .read_to_end(&mut inflated);
+            }
+        }
+    }
+
+    /// Bytes that do not compress, from a xorshift generator.
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state.to_le_bytes()[0]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_auto_stores_incompressible_data() {
+        let data = noise(200_000);
+        let compressed = compress(&data, &Strategy::Auto);
+        assert_eq!(decompress(&compressed).unwrap(), data);
+
+        // No more than the headers of stored blocks
+        assert!(compressed.len() <= data.len() + 100);
+        assert!(compressed.len() < compress(&data, &Strategy::Fixed).len());
+    }
+
+    #[test]
+    fn test_auto_chooses_per_block() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let text = fs::read(root.join("LICENSE")).expect("Read file!");
+        let data = [text.repeat(70), noise(150_000), text.repeat(70)].concat();
+
+        let auto = compress(&data, &Strategy::Auto);
+        assert_eq!(decompress(&auto).unwrap(), data);
+        for strategy in [Strategy::Fixed, Strategy::Dynamic, Strategy::Raw] {
+            assert!(auto.len() < compress(&data, &strategy).len());
+        }
+    }
 }